@@ -0,0 +1,23 @@
+//! `cargo bench` runs the benchmark suite with each evaluator, the same programs as
+//! `sanguinello bench`, printing how each evaluator compares with the interpreter
+
+use sanguinello::sgir::bench::{measure_with, suite, Evaluator};
+
+const ITERATIONS: usize = 10;
+
+fn main() {
+    for (name, program) in suite() {
+        let mut baseline = None;
+        for evaluator in Evaluator::ALL {
+            let measurement = match measure_with(evaluator, &format!("{} ({})", name, evaluator.name()), &program, ITERATIONS) {
+                Ok(measurement) => measurement,
+                Err(error) => panic!("{} failed with the {}: {}", name, evaluator.name(), error),
+            };
+            match &baseline {
+                Some(baseline) => println!("{}, {:.2}x the interpreter", measurement, measurement.relative_to(baseline)),
+                None => println!("{}", measurement),
+            }
+            baseline.get_or_insert(measurement);
+        }
+    }
+}
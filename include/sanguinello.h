@@ -0,0 +1,88 @@
+/* the C API for embedding sanguinello, which `cargo rustc --lib --release --features capi
+ * --crate-type cdylib` builds. see `src/capi/mod.rs` for who owns what:
+ *
+ * - `sg_*_new` and `sg_eval` give the host something it owns, to free with `sg_engine_free` or
+ *   `sg_value_free`, or to give back to a function that takes it.
+ * - a function taking a `SgValue *` (rather than a `const SgValue *`) takes ownership of it.
+ * - what accessors return is borrowed from the value it came from.
+ * - errors are reported by returning `NULL` or `false`, and storing a message in `*error` unless
+ *   `error` is `NULL`, which the host frees with `sg_string_free`.
+ */
+
+#ifndef SANGUINELLO_H
+#define SANGUINELLO_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SgEngine SgEngine;
+typedef struct SgValue SgValue;
+
+typedef enum SgKind {
+    SG_BOOLEAN,
+    SG_NUMBER,
+    SG_STRING,
+    SG_CHAR,
+    SG_BYTES,
+    SG_TUPLE,
+    SG_RECORD,
+    SG_VARIANT,
+    SG_FUNCTION,
+    SG_FLOAT,
+} SgKind;
+
+/* a native function: `arguments` are borrowed for the call, and it returns its result, or `NULL`
+ * with a message in `*error` that stays valid until it returns. it may be called from any thread
+ * running a program on the engine. */
+typedef SgValue *(*SgNative)(void *data, const SgValue *const *arguments, size_t count, const char **error);
+
+SgEngine *sg_engine_new(void);
+void sg_engine_free(SgEngine *engine);
+void sg_engine_grant(SgEngine *engine, const char *capability);
+void sg_engine_seed(const SgEngine *engine, uint64_t seed);
+void sg_engine_set_arguments(const SgEngine *engine, const char *const *arguments, size_t count);
+void sg_engine_set_root(const SgEngine *engine, const char *root);
+bool sg_engine_define(SgEngine *engine, const char *name, const char *type, SgValue *value, char **error);
+bool sg_engine_register_native(SgEngine *engine, const char *module, const char *name, const char *type,
+                               SgNative function, void *data, void (*free_data)(void *), char **error);
+
+SgValue *sg_eval(const SgEngine *engine, const char *source, char **error);
+void sg_string_free(char *string);
+
+void sg_value_free(SgValue *value);
+SgValue *sg_value_clone(const SgValue *value);
+SgKind sg_value_kind(const SgValue *value);
+
+bool sg_value_boolean(const SgValue *value);
+int64_t sg_value_number(const SgValue *value);
+double sg_value_float(const SgValue *value);
+uint32_t sg_value_char(const SgValue *value);
+/* strings, field names, and tags are UTF-8 that isn't NUL-terminated */
+const char *sg_value_string(const SgValue *value, size_t *length);
+const uint8_t *sg_value_bytes(const SgValue *value, size_t *length);
+size_t sg_value_length(const SgValue *value);
+const SgValue *sg_value_element(const SgValue *value, size_t i);
+const char *sg_value_field(const SgValue *value, size_t i, size_t *length);
+const char *sg_value_tag(const SgValue *value, size_t *length);
+const SgValue *sg_value_payload(const SgValue *value);
+
+SgValue *sg_value_new_boolean(bool value);
+SgValue *sg_value_new_number(int64_t value);
+SgValue *sg_value_new_float(double value);
+SgValue *sg_value_new_char(uint32_t value);
+SgValue *sg_value_new_string(const char *string, size_t length);
+SgValue *sg_value_new_bytes(const uint8_t *bytes, size_t length);
+SgValue *sg_value_new_tuple(SgValue *const *elements, size_t count);
+SgValue *sg_value_new_record(const char *const *fields, SgValue *const *values, size_t count);
+SgValue *sg_value_new_variant(const char *tag, SgValue *payload);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,204 @@
+//! python bindings for the embedding API. a script makes an `Engine`, defines globals and
+//! registers python functions as natives in it, and evaluates sanguinello source, getting back
+//! python values:
+//!
+//! ```python
+//! from sanguinello_py import Engine
+//!
+//! engine = Engine()
+//! engine.register_native("io", "double", "(Number) -> Number", lambda n: n * 2)
+//! engine.grant("io")
+//! assert engine.eval("io.double(21)") == 42
+//! ```
+//!
+//! types are written as they would be in a program, e.g. `{x: Number, name: String}`. build with
+//! the `extension-module` feature, e.g. by maturin, for python to import.
+
+use std::sync::Arc;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
+use pyo3::types::{PyBool, PyBytes, PyDict, PyFloat, PyList, PyString, PyTuple};
+
+use sanguinello::engine;
+use sanguinello::sgir::operators::Operators;
+use sanguinello::sgir::{is_subtype, EvalError, Type, Value};
+use sanguinello::syntax::{lower, parser};
+
+#[cfg(test)]
+mod tests;
+
+create_exception!(sanguinello_py, SanguinelloError, PyException, "a program that failed to parse, check, or run");
+
+fn error(error: impl ToString) -> PyErr {
+    SanguinelloError::new_err(error.to_string())
+}
+
+fn parse_type(typ: &str) -> PyResult<Type> {
+    parser::parse_type(typ).map_err(error)
+}
+
+/// a sanguinello function, which python can hold on to and pass back to an engine, but not call
+#[pyclass(frozen, module = "sanguinello_py")]
+struct Function(Value);
+
+/// a value of a variant type, `tag(payload)`
+#[pyclass(frozen, get_all, module = "sanguinello_py")]
+struct Variant {
+    tag: String,
+    payload: PyObject,
+}
+
+#[pymethods]
+impl Variant {
+    #[new]
+    fn new(tag: String, payload: PyObject) -> Variant {
+        Variant { tag, payload }
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("Variant({:?}, {})", self.tag, self.payload.bind(py).repr()?))
+    }
+}
+
+/// `value` as a python value: booleans, numbers, floats, strings, and bytes as themselves, characters as
+/// strings, tuples as tuples, records as dicts, and variants and functions as the classes for
+/// them
+fn to_python(py: Python<'_>, value: Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Boolean(value) => value.into_py_any(py)?,
+        Value::Number(value) => value.into_py_any(py)?,
+        Value::Float(value) => value.into_py_any(py)?,
+        Value::String(value) => value.into_py_any(py)?,
+        Value::Char(value) => value.to_string().into_py_any(py)?,
+        Value::Bytes(value) => PyBytes::new(py, &value).into_py_any(py)?,
+        Value::Tuple(values) => {
+            let values = values.into_iter().map(|value| to_python(py, value)).collect::<PyResult<Vec<_>>>()?;
+            PyTuple::new(py, values)?.into_py_any(py)?
+        }
+        Value::Record(fields) => {
+            let record = PyDict::new(py);
+            for (field, value) in Arc::unwrap_or_clone(fields) {
+                record.set_item(field, to_python(py, value)?)?;
+            }
+            record.into_py_any(py)?
+        }
+        Value::Variant { tag, payload } => Variant { tag, payload: to_python(py, *payload)? }.into_py_any(py)?,
+        value @ (Value::Function { .. } | Value::Native(_)) => Function(value).into_py_any(py)?,
+    })
+}
+
+/// the sanguinello value of `value`, the other way around from `to_python`. lists are tuples too.
+fn from_python(value: &Bound<'_, PyAny>) -> PyResult<Value> {
+    // python's booleans are integers too
+    if let Ok(value) = value.downcast::<PyBool>() {
+        Ok(Value::Boolean(value.is_true()))
+    } else if let Ok(value) = value.downcast::<PyString>() {
+        Ok(Value::String(value.to_str()?.to_owned()))
+    } else if let Ok(value) = value.downcast::<PyBytes>() {
+        Ok(Value::Bytes(value.as_bytes().to_vec()))
+    } else if let Ok(values) = value.downcast::<PyTuple>() {
+        Ok(Value::Tuple(values.iter().map(|value| from_python(&value)).collect::<PyResult<_>>()?))
+    } else if let Ok(values) = value.downcast::<PyList>() {
+        Ok(Value::Tuple(values.iter().map(|value| from_python(&value)).collect::<PyResult<_>>()?))
+    } else if let Ok(fields) = value.downcast::<PyDict>() {
+        let fields = fields.iter()
+                           .map(|(field, value)| Ok((field.extract::<String>()?, from_python(&value)?)))
+                           .collect::<PyResult<_>>()?;
+        Ok(Value::Record(Arc::new(fields)))
+    } else if let Ok(function) = value.downcast::<Function>() {
+        Ok(function.get().0.clone())
+    } else if let Ok(variant) = value.downcast::<Variant>() {
+        let Variant { tag, payload } = variant.get();
+        Ok(Value::Variant { tag: tag.clone(), payload: Box::new(from_python(payload.bind(value.py()))?) })
+    } else if let Ok(float) = value.downcast::<PyFloat>() {
+        match float.value() {
+            float if float.is_finite() => Ok(Value::Float(float)),
+            _ => Err(PyValueError::new_err(format!("{} has no sanguinello value", value.repr()?))),
+        }
+    } else if let Ok(value) = value.extract::<i64>() {
+        Ok(Value::Number(value))
+    } else {
+        Err(PyTypeError::new_err(format!("{} has no sanguinello value", value.get_type().name()?)))
+    }
+}
+
+/// an engine that runs sanguinello programs, with the globals and natives python gives it
+#[pyclass(module = "sanguinello_py")]
+struct Engine(engine::Engine);
+
+#[pymethods]
+impl Engine {
+    /// an engine with the prelude, like `Engine::new`
+    #[new]
+    fn new() -> Engine {
+        Engine(engine::Engine::new())
+    }
+
+    /// bind `name` to `value`, a value of the type written `typ`, for every program. a value
+    /// whose type can be told and isn't that one, like a float for a number, is a type error.
+    fn define(&mut self, name: &str, typ: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let (typ, value) = (parse_type(typ)?, from_python(value)?);
+        if let Some(found) = value.type_of().filter(|found| !is_subtype(found, &typ)) {
+            return Err(PyTypeError::new_err(format!("{} is a {}, not a {}", value, found, typ)))
+        }
+        self.0.define(name, typ, value);
+        Ok(())
+    }
+
+    /// register the python callable `function` as `module.name`, of the type written `typ`. an
+    /// exception it raises fails the program that called it.
+    fn register_native(&mut self, module: &str, name: &str, typ: &str, function: PyObject) -> PyResult<()> {
+        let qualified = format!("{}.{}", module, name);
+        self.0.register_native(module, name, parse_type(typ)?, move |arguments| {
+            Python::with_gil(|py| {
+                let arguments = arguments.into_iter().map(|argument| to_python(py, argument)).collect::<PyResult<Vec<_>>>()?;
+                from_python(function.call1(py, PyTuple::new(py, arguments)?)?.bind(py))
+            }).map_err(|error| EvalError::NativeFailure { name: qualified.clone(), message: error.to_string() })
+        });
+        Ok(())
+    }
+
+    /// allow programs to use the natives in `capability`
+    fn grant(&mut self, capability: &str) {
+        self.0.grant(capability);
+    }
+
+    /// reseed the generator behind `math.random`
+    fn seed(&self, seed: u64) {
+        self.0.seed(seed);
+    }
+
+    /// set the arguments `os.args` produces
+    fn set_arguments(&self, arguments: Vec<String>) {
+        self.0.set_arguments(arguments);
+    }
+
+    /// confine the `fs` module to the files under the directory `root`
+    fn set_root(&self, root: &str) {
+        self.0.set_root(root);
+    }
+
+    /// parse, check, and run the program `source`, producing its value. python's other threads
+    /// carry on while it runs.
+    fn eval(&self, py: Python<'_>, source: &str) -> PyResult<PyObject> {
+        let parsed = parser::parse(source);
+        if let Some(syntax) = parsed.errors.first() {
+            return Err(error(syntax))
+        }
+        let program = lower::lower_block(&parsed.program, &Operators::default()).map_err(error)?;
+        let value = py.allow_threads(|| self.0.run(program)).map_err(error)?;
+        to_python(py, value)
+    }
+}
+
+#[pymodule]
+fn sanguinello_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Engine>()?;
+    module.add_class::<Function>()?;
+    module.add_class::<Variant>()?;
+    module.add("SanguinelloError", module.py().get_type::<SanguinelloError>())?;
+    Ok(())
+}
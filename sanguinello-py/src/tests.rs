@@ -0,0 +1,76 @@
+use std::ffi::CStr;
+
+use super::*;
+
+/// run the python `script`, which can import the module as `sanguinello_py`
+fn run(script: &CStr) {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let module = PyModule::new(py, "sanguinello_py").unwrap();
+        sanguinello_py(&module).unwrap();
+        py.import("sys").unwrap().getattr("modules").unwrap().set_item("sanguinello_py", module).unwrap();
+        if let Err(error) = py.run(script, None, None) {
+            panic!("{}", error.value(py).repr().unwrap());
+        }
+    });
+}
+
+#[test]
+fn test_python_engine_runs_programs_with_natives() {
+    run(c"
+from sanguinello_py import Engine, Function, SanguinelloError
+
+engine = Engine()
+engine.register_native('io', 'double', '(Number) -> Number', lambda n: n * 2)
+engine.register_native('io', 'fail', '(String) -> Number', lambda message: 1 / 0)
+try:
+    engine.eval('io.double(21)')
+    assert False, 'io was used before it was granted'
+except SanguinelloError as error:
+    assert 'io' in str(error)
+engine.grant('io')
+assert engine.eval('io.double(21)') == 42
+
+try:
+    engine.eval('io.fail(\"now\")')
+    assert False, 'the native raised'
+except SanguinelloError as error:
+    assert 'io.fail' in str(error) and 'division by zero' in str(error)
+for source in ['1 + true', 'local x = ', 'y']:
+    try:
+        engine.eval(source)
+        assert False, source
+    except SanguinelloError:
+        pass
+
+point = {'x': 3, 'label': 'origin', 'parts': (True, b'\\x00\\x01', [1, 2])}
+engine.define('point', '{x: Number, label: String, parts: (Boolean, Bytes, (Number, Number))}', point)
+assert engine.eval('point') == {'x': 3, 'label': 'origin', 'parts': (True, b'\\x00\\x01', (1, 2))}
+
+increment = engine.eval('function(n: Number): Number return n + 1 end')
+assert isinstance(increment, Function)
+engine.define('increment', '(Number) -> Number', increment)
+assert engine.eval('increment(io.double(2))') == 5
+
+try:
+    engine.define('half', 'Number', 0.5)
+    assert False, 'numbers are integers'
+except TypeError:
+    pass
+");
+}
+
+#[test]
+fn test_python_values_round_trip() {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let values = [Value::Variant { tag: "some".to_owned(), payload: Box::new(Value::Number(1)) },
+                      Value::Tuple(vec![Value::String("a".to_owned()), Value::Boolean(false)]),
+                      Value::Record(Arc::new(vec![("b".to_owned(), Value::Bytes(vec![7]))]))];
+        for value in values {
+            let converted = to_python(py, value.clone()).unwrap();
+            assert_eq!(from_python(converted.bind(py)).unwrap(), value);
+        }
+        assert_eq!(from_python(to_python(py, Value::Char('c')).unwrap().bind(py)).unwrap(), Value::String("c".to_owned()));
+    });
+}
@@ -0,0 +1,434 @@
+//! the embedding API for hosts that aren't written in rust, declared for C in
+//! `include/sanguinello.h`. build it with `cargo rustc --lib --release --features capi --crate-type
+//! cdylib` (or `staticlib`).
+//!
+//! engines and values are opaque, and are only ever handled through pointers:
+//!
+//! - a function named `sg_*_new` or `sg_eval` gives the host a value it owns, which it frees with
+//!   `sg_engine_free` or `sg_value_free`, or gives back to a function that takes it.
+//! - a function that takes a `SgValue *` (rather than a `const SgValue *`) takes ownership of it,
+//!   so the host mustn't use or free it afterwards.
+//! - pointers and strings that accessors return are borrowed from the value they came from, and
+//!   are only valid until it's freed.
+//! - an error is reported by returning `NULL` or `false` and, if the `error` pointer passed in
+//!   isn't `NULL`, storing a message there, which the host frees with `sg_string_free`.
+//!
+//! every pointer passed in must be valid, and every string must be NUL-terminated UTF-8 unless
+//! it's passed along with its length.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::sync::Arc;
+use std::{ptr, slice};
+
+use crate::engine::Engine;
+use crate::sgir::operators::Operators;
+use crate::sgir::{EvalError, Value};
+use crate::syntax::lower::lower_block;
+use crate::syntax::parser::{parse, parse_type};
+
+#[cfg(test)]
+mod tests;
+
+/// an engine, as `Engine`
+pub struct SgEngine(Engine);
+
+/// a value, as `Value`
+#[repr(transparent)]
+pub struct SgValue(Value);
+
+/// what kind of value a `SgValue` is, which says which accessors apply to it
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SgKind {
+    Boolean,
+    Number,
+    String,
+    Char,
+    Bytes,
+    Tuple,
+    Record,
+    Variant,
+    Function,
+    Float,
+}
+
+/// a native function implemented by the host. it's called with the `data` it was registered
+/// with and its arguments, which are borrowed for the duration of the call, and returns its
+/// result, a value of the native's result type. to fail, it returns `NULL`, storing a message in
+/// `*error` that must stay valid until the callback has returned, like a string literal. it may be
+/// called from any thread running a program on the engine.
+pub type SgNative = unsafe extern "C" fn(data: *mut c_void, arguments: *const *const SgValue, count: usize,
+                                         error: *mut *const c_char) -> *mut SgValue;
+
+/// a native registered by the host, which frees its data once the engine is done with it
+struct Callback {
+    function: SgNative,
+    data: *mut c_void,
+    free_data: Option<unsafe extern "C" fn(*mut c_void)>,
+}
+
+// the host promises that the callback can be called from any thread, with its data
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+impl Drop for Callback {
+    fn drop(&mut self) {
+        if let Some(free_data) = self.free_data {
+            unsafe { free_data(self.data) }
+        }
+    }
+}
+
+impl Callback {
+    fn call(&self, name: &str, arguments: Vec<Value>) -> Result<Value, EvalError> {
+        let arguments = arguments.into_iter().map(SgValue).collect::<Vec<_>>();
+        let pointers = arguments.iter().map(|argument| argument as *const SgValue).collect::<Vec<_>>();
+        let mut message = ptr::null();
+        let result = unsafe { (self.function)(self.data, pointers.as_ptr(), pointers.len(), &mut message) };
+        if result.is_null() {
+            let message = if message.is_null() {
+                "the native failed".to_owned()
+            } else {
+                unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned()
+            };
+            return Err(EvalError::NativeFailure { name: name.to_owned(), message })
+        }
+        Ok(unsafe { Box::from_raw(result) }.0)
+    }
+}
+
+/// store `message` in `error`, if the host asked for it, and produce `failed`
+unsafe fn fail<T>(error: *mut *mut c_char, message: impl ToString, failed: T) -> T {
+    if !error.is_null() {
+        let message = message.to_string().replace('\0', "\u{fffd}");
+        *error = CString::new(message).unwrap().into_raw();
+    }
+    failed
+}
+
+unsafe fn string<'a>(string: *const c_char) -> Result<&'a str, String> {
+    CStr::from_ptr(string).to_str().map_err(|_| "a string isn't valid UTF-8".to_owned())
+}
+
+fn owned(value: Value) -> *mut SgValue {
+    Box::into_raw(Box::new(SgValue(value)))
+}
+
+fn borrowed(value: &Value) -> *const SgValue {
+    value as *const Value as *const SgValue
+}
+
+/// the start of `text`, storing its length in `length`, since it isn't NUL-terminated
+unsafe fn text(text: &str, length: *mut usize) -> *const c_char {
+    if !length.is_null() {
+        *length = text.len();
+    }
+    text.as_ptr() as *const c_char
+}
+
+/// a new engine with the prelude, like `Engine::new`
+#[no_mangle]
+pub extern "C" fn sg_engine_new() -> *mut SgEngine {
+    Box::into_raw(Box::new(SgEngine(Engine::new())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sg_engine_free(engine: *mut SgEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// allow programs to use the natives in `capability`
+#[no_mangle]
+pub unsafe extern "C" fn sg_engine_grant(engine: *mut SgEngine, capability: *const c_char) {
+    (*engine).0.grant(&CStr::from_ptr(capability).to_string_lossy());
+}
+
+/// reseed the generator behind `math.random`
+#[no_mangle]
+pub unsafe extern "C" fn sg_engine_seed(engine: *const SgEngine, seed: u64) {
+    (*engine).0.seed(seed);
+}
+
+/// set the `count` arguments `os.args` produces
+#[no_mangle]
+pub unsafe extern "C" fn sg_engine_set_arguments(engine: *const SgEngine, arguments: *const *const c_char, count: usize) {
+    let arguments = slice::from_raw_parts(arguments, count).iter().map(|argument| CStr::from_ptr(*argument).to_string_lossy().into_owned());
+    (*engine).0.set_arguments(arguments.collect());
+}
+
+/// confine the `fs` module to the files under the directory `root`
+#[no_mangle]
+pub unsafe extern "C" fn sg_engine_set_root(engine: *const SgEngine, root: *const c_char) {
+    (*engine).0.set_root(&*CStr::from_ptr(root).to_string_lossy());
+}
+
+/// bind `name` to `value`, which the engine takes, as a value of the type written `typ`
+#[no_mangle]
+pub unsafe extern "C" fn sg_engine_define(engine: *mut SgEngine, name: *const c_char, typ: *const c_char,
+                                          value: *mut SgValue, error: *mut *mut c_char) -> bool {
+    let value = Box::from_raw(value).0;
+    let define = || -> Result<(), String> {
+        let typ = parse_type(string(typ)?).map_err(|error| error.to_string())?;
+        (*engine).0.define(string(name)?, typ, value);
+        Ok(())
+    };
+    match define() {
+        Ok(()) => true,
+        Err(message) => fail(error, message, false),
+    }
+}
+
+/// register `function` as `module.name`, of the type written `typ`, to be called with `data`.
+/// `free_data`, unless it's `NULL`, is called with `data` once the engine no longer needs it,
+/// which can be as late as when the engine is freed, or right away if the native can't be
+/// registered.
+#[no_mangle]
+pub unsafe extern "C" fn sg_engine_register_native(engine: *mut SgEngine, module: *const c_char,
+                                                   name: *const c_char, typ: *const c_char, function: SgNative,
+                                                   data: *mut c_void, free_data: Option<unsafe extern "C" fn(*mut c_void)>,
+                                                   error: *mut *mut c_char) -> bool {
+    let callback = Callback { function, data, free_data };
+    let register = || -> Result<(), String> {
+        let (module, name) = (string(module)?, string(name)?);
+        let typ = parse_type(string(typ)?).map_err(|error| error.to_string())?;
+        let qualified = format!("{}.{}", module, name);
+        (*engine).0.register_native(module, name, typ, move |arguments| callback.call(&qualified, arguments));
+        Ok(())
+    };
+    match register() {
+        Ok(()) => true,
+        Err(message) => fail(error, message, false),
+    }
+}
+
+/// parse, check, and run the program `source` on `engine`, producing its value
+#[no_mangle]
+pub unsafe extern "C" fn sg_eval(engine: *const SgEngine, source: *const c_char, error: *mut *mut c_char) -> *mut SgValue {
+    let eval = || -> Result<Value, String> {
+        let parsed = parse(string(source)?);
+        if let Some(error) = parsed.errors.first() {
+            return Err(error.to_string())
+        }
+        let program = lower_block(&parsed.program, &Operators::default()).map_err(|error| error.to_string())?;
+        (*engine).0.run(program).map_err(|error| error.to_string())
+    };
+    match eval() {
+        Ok(value) => owned(value),
+        Err(message) => fail(error, message, ptr::null_mut()),
+    }
+}
+
+/// free an error message
+#[no_mangle]
+pub unsafe extern "C" fn sg_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_free(value: *mut SgValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// a copy of `value`, which the host owns separately
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_clone(value: *const SgValue) -> *mut SgValue {
+    owned((*value).0.clone())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_kind(value: *const SgValue) -> SgKind {
+    match &(*value).0 {
+        Value::Boolean(_) => SgKind::Boolean,
+        Value::Number(_) => SgKind::Number,
+        Value::Float(_) => SgKind::Float,
+        Value::String(_) => SgKind::String,
+        Value::Char(_) => SgKind::Char,
+        Value::Bytes(_) => SgKind::Bytes,
+        Value::Tuple(_) => SgKind::Tuple,
+        Value::Record(_) => SgKind::Record,
+        Value::Variant { .. } => SgKind::Variant,
+        Value::Function { .. } | Value::Native(_) => SgKind::Function,
+    }
+}
+
+/// the boolean `value` is, or false if it isn't one
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_boolean(value: *const SgValue) -> bool {
+    matches!((*value).0, Value::Boolean(true))
+}
+
+/// the number `value` is, or 0 if it isn't one
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_number(value: *const SgValue) -> i64 {
+    match (*value).0 {
+        Value::Number(number) => number,
+        _ => 0,
+    }
+}
+
+/// the float `value` is, or 0 if it isn't one
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_float(value: *const SgValue) -> f64 {
+    match (*value).0 {
+        Value::Float(float) => float,
+        _ => 0.0,
+    }
+}
+
+/// the code point of the character `value` is, or 0 if it isn't one
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_char(value: *const SgValue) -> u32 {
+    match (*value).0 {
+        Value::Char(c) => c as u32,
+        _ => 0,
+    }
+}
+
+/// the UTF-8 of the string `value` is, with its length stored in `length`, or `NULL` if it
+/// isn't one. it isn't NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_string(value: *const SgValue, length: *mut usize) -> *const c_char {
+    match &(*value).0 {
+        Value::String(string) => text(string, length),
+        _ => ptr::null(),
+    }
+}
+
+/// the contents of the bytes `value` is, with its length stored in `length`, or `NULL` if it
+/// isn't bytes
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_bytes(value: *const SgValue, length: *mut usize) -> *const u8 {
+    match &(*value).0 {
+        Value::Bytes(bytes) => {
+            if !length.is_null() {
+                *length = bytes.len();
+            }
+            bytes.as_ptr()
+        }
+        _ => ptr::null(),
+    }
+}
+
+/// the number of elements of a tuple or fields of a record, or 0 for any other value
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_length(value: *const SgValue) -> usize {
+    match &(*value).0 {
+        Value::Tuple(elements) => elements.len(),
+        Value::Record(fields) => fields.len(),
+        _ => 0,
+    }
+}
+
+/// the `i`th element of a tuple, or the value of the `i`th field of a record, or `NULL` if there
+/// isn't one
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_element(value: *const SgValue, i: usize) -> *const SgValue {
+    let element = match &(*value).0 {
+        Value::Tuple(elements) => elements.get(i),
+        Value::Record(fields) => fields.get(i).map(|(_, value)| value),
+        _ => None,
+    };
+    element.map_or(ptr::null(), borrowed)
+}
+
+/// the name of the `i`th field of a record, with its length stored in `length`, or `NULL` if
+/// there isn't one
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_field(value: *const SgValue, i: usize, length: *mut usize) -> *const c_char {
+    match &(*value).0 {
+        Value::Record(fields) if i < fields.len() => text(&fields[i].0, length),
+        _ => ptr::null(),
+    }
+}
+
+/// the tag of a variant, with its length stored in `length`, or `NULL` if `value` isn't one
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_tag(value: *const SgValue, length: *mut usize) -> *const c_char {
+    match &(*value).0 {
+        Value::Variant { tag, .. } => text(tag, length),
+        _ => ptr::null(),
+    }
+}
+
+/// the payload of a variant, or `NULL` if `value` isn't one
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_payload(value: *const SgValue) -> *const SgValue {
+    match &(*value).0 {
+        Value::Variant { payload, .. } => borrowed(payload),
+        _ => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn sg_value_new_boolean(value: bool) -> *mut SgValue {
+    owned(Value::Boolean(value))
+}
+
+#[no_mangle]
+pub extern "C" fn sg_value_new_number(value: i64) -> *mut SgValue {
+    owned(Value::Number(value))
+}
+
+/// the float `value`, or `NULL` if it's NaN or infinite
+#[no_mangle]
+pub extern "C" fn sg_value_new_float(value: f64) -> *mut SgValue {
+    if value.is_finite() { owned(Value::Float(value)) } else { ptr::null_mut() }
+}
+
+/// the character with the code point `value`, or `NULL` if there isn't one
+#[no_mangle]
+pub extern "C" fn sg_value_new_char(value: u32) -> *mut SgValue {
+    char::from_u32(value).map_or(ptr::null_mut(), |c| owned(Value::Char(c)))
+}
+
+/// the string of the `length` bytes of UTF-8 at `string`, or `NULL` if they aren't UTF-8
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_new_string(string: *const c_char, length: usize) -> *mut SgValue {
+    match std::str::from_utf8(slice::from_raw_parts(string as *const u8, length)) {
+        Ok(string) => owned(Value::String(string.to_owned())),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_new_bytes(bytes: *const u8, length: usize) -> *mut SgValue {
+    owned(Value::Bytes(slice::from_raw_parts(bytes, length).to_vec()))
+}
+
+/// a tuple of the `count` `elements`, which it takes
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_new_tuple(elements: *const *mut SgValue, count: usize) -> *mut SgValue {
+    let elements = slice::from_raw_parts(elements, count).iter().map(|element| Box::from_raw(*element).0).collect();
+    owned(Value::Tuple(elements))
+}
+
+/// a record of the `count` `fields` with their `values`, which it takes, or `NULL` if a field
+/// isn't UTF-8
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_new_record(fields: *const *const c_char, values: *const *mut SgValue,
+                                             count: usize) -> *mut SgValue {
+    let values = slice::from_raw_parts(values, count).iter().map(|value| Box::from_raw(*value).0).collect::<Vec<_>>();
+    let fields = slice::from_raw_parts(fields, count).iter().map(|field| string(*field).map(str::to_owned));
+    match fields.zip(values).map(|(field, value)| Ok((field?, value))).collect::<Result<_, String>>() {
+        Ok(fields) => owned(Value::Record(Arc::new(fields))),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// the variant `tag(payload)`, which takes `payload`, or `NULL` if `tag` isn't UTF-8
+#[no_mangle]
+pub unsafe extern "C" fn sg_value_new_variant(tag: *const c_char, payload: *mut SgValue) -> *mut SgValue {
+    let payload = Box::from_raw(payload).0;
+    match string(tag) {
+        Ok(tag) => owned(Value::Variant { tag: tag.to_owned(), payload: Box::new(payload) }),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::*;
+
+unsafe extern "C" fn double(data: *mut c_void, arguments: *const *const SgValue, count: usize,
+                            error: *mut *const c_char) -> *mut SgValue {
+    (*(data as *const AtomicUsize)).fetch_add(1, Ordering::SeqCst);
+    let arguments = slice::from_raw_parts(arguments, count);
+    match sg_value_number(arguments[0]) {
+        n if n < 0 => {
+            *error = c"a negative number".as_ptr();
+            ptr::null_mut()
+        }
+        n => sg_value_new_number(n * 2),
+    }
+}
+
+unsafe extern "C" fn release(data: *mut c_void) {
+    drop(Box::from_raw(data as *mut AtomicUsize));
+}
+
+unsafe fn eval(engine: *const SgEngine, source: &CStr) -> Result<*mut SgValue, String> {
+    let mut error = ptr::null_mut();
+    let value = sg_eval(engine, source.as_ptr(), &mut error);
+    if value.is_null() {
+        let message = CStr::from_ptr(error).to_str().unwrap().to_owned();
+        sg_string_free(error);
+        return Err(message)
+    }
+    Ok(value)
+}
+
+#[test]
+fn test_capi_evaluates_with_callbacks() {
+    unsafe {
+        let engine = sg_engine_new();
+        let calls = Box::into_raw(Box::new(AtomicUsize::new(0)));
+        let registered = sg_engine_register_native(engine, c"io".as_ptr(), c"double".as_ptr(), c"(Number) -> Number".as_ptr(),
+                                                   double, calls as *mut c_void, Some(release), ptr::null_mut());
+        assert!(registered);
+        assert!(eval(engine, c"io.double(21)").unwrap_err().contains("io capability"));
+        sg_engine_grant(engine, c"io".as_ptr());
+
+        let value = eval(engine, c"io.double(21)").unwrap();
+        assert_eq!((sg_value_kind(value), sg_value_number(value)), (SgKind::Number, 42));
+        sg_value_free(value);
+        assert_eq!(eval(engine, c"io.double(0 - 1)").unwrap_err(), "native function io.double failed: a negative number");
+        assert_eq!((*calls).load(Ordering::SeqCst), 2);
+        assert!(eval(engine, c"1 +").is_err());
+
+        let mut error = ptr::null_mut();
+        assert!(!sg_engine_define(engine, c"x".as_ptr(), c"Number ->".as_ptr(), sg_value_new_number(1), &mut error));
+        sg_string_free(error);
+
+        let fields = [c"name".as_ptr(), c"pair".as_ptr()];
+        let pair = [sg_value_new_boolean(true), sg_value_new_variant(c"some".as_ptr(), sg_value_new_char('c' as u32))];
+        let values = [sg_value_new_string("ok".as_ptr() as *const c_char, 2), sg_value_new_tuple(pair.as_ptr(), 2)];
+        let record = sg_value_new_record(fields.as_ptr(), values.as_ptr(), 2);
+        let typ = c"{name: String, pair: (Boolean, Char)}";
+        let copy = sg_value_clone(record);
+        assert!(sg_engine_define(engine, c"point".as_ptr(), typ.as_ptr(), record, ptr::null_mut()));
+
+        let value = eval(engine, c"point").unwrap();
+        assert_eq!((sg_value_kind(value), sg_value_length(value)), (SgKind::Record, 2));
+        assert_eq!((*value).0, (*copy).0);
+        let mut length = 0;
+        let field = sg_value_field(value, 1, &mut length);
+        assert_eq!(slice::from_raw_parts(field as *const u8, length), b"pair");
+        let pair = sg_value_element(value, 1);
+        let variant = sg_value_element(pair, 1);
+        let tag = sg_value_tag(variant, &mut length);
+        assert_eq!(slice::from_raw_parts(tag as *const u8, length), b"some");
+        assert_eq!(sg_value_char(sg_value_payload(variant)), 'c' as u32);
+        assert!(sg_value_element(pair, 2).is_null());
+        assert!(sg_value_string(pair, &mut length).is_null());
+        sg_value_free(value);
+        sg_value_free(copy);
+
+        sg_engine_free(engine);
+    }
+}
@@ -0,0 +1,9 @@
+//! the hash maps and sets `sgir` uses: the standard library's, or hashbrown's without it
+
+pub use alloc::collections::{BTreeMap, BTreeSet};
+
+#[cfg(feature = "std")]
+pub use std::collections::{HashMap, HashSet};
+
+#[cfg(not(feature = "std"))]
+pub use hashbrown::{HashMap, HashSet};
@@ -0,0 +1,85 @@
+//! the `fs` module, which lets a script read and write files: `fs.read_file(path)`,
+//! `fs.write_file(path, contents)`, and `fs.list_dir(path)`. a script only ever sees the files
+//! under the root directory the host gives it, which is the directory the host runs in unless
+//! it sets another, so a path must be relative, and can't climb out of the root with `..` or
+//! through a symbolic link. each produces `Ok` of what it does, or `Error` with why it couldn't,
+//! which the script can match on rather than failing outright.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::sgir::{EvalError, Type, Value};
+
+/// `variant { Ok(typ), Error(String) }`, what a native of the module produces
+pub(super) fn outcome(typ: Type) -> Type {
+    Type::Variant(vec![("Ok".to_owned(), typ), ("Error".to_owned(), Type::String)])
+}
+
+fn ok(value: Value) -> Value {
+    Value::Variant { tag: "Ok".to_owned(), payload: Box::new(value) }
+}
+
+fn error(message: String) -> Value {
+    Value::Variant { tag: "Error".to_owned(), payload: Box::new(Value::String(message)) }
+}
+
+/// `path` under `root`, if it's relative and stays under it once the links along it are followed
+fn resolve(root: &Path, path: &str) -> Result<PathBuf, String> {
+    if !Path::new(path).components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+        return Err(format!("{} isn't a relative path without `..` in it", path))
+    }
+    let root = root.canonicalize().map_err(|error| format!("{}: {}", root.display(), error))?;
+    let joined = root.join(path);
+    // a file that's about to be written may not exist yet, but the directory it's written to must.
+    // a link to nothing is found too, but writing through it would create whatever it points to,
+    // which could be anywhere, so it's refused.
+    let existing = match joined.canonicalize() {
+        Ok(canonical) => canonical,
+        Err(_) if joined.symlink_metadata().is_ok() => {
+            return Err(format!("{} is a symbolic link to something that doesn't exist", path))
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => match joined.parent().map(Path::canonicalize) {
+            Some(Ok(parent)) => parent,
+            _ => return Err(format!("{}: {}", path, error)),
+        },
+        Err(error) => return Err(format!("{}: {}", path, error)),
+    };
+    if existing.starts_with(&root) {
+        Ok(joined)
+    } else {
+        Err(format!("{} is outside of the directory scripts can use", path))
+    }
+}
+
+/// a native of the module, `name`, that does `f` with the path it's called with under `root`
+/// and its other arguments
+pub(super) fn native(root: Arc<Mutex<PathBuf>>, name: &'static str, f: fn(&Path, Vec<Value>) -> io::Result<Value>)
+                     -> impl Fn(Vec<Value>) -> Result<Value, EvalError> + Send + Sync + 'static {
+    move |mut arguments| {
+        let Value::String(path) = arguments.remove(0) else { unreachable!("checked by the type of {}", name) };
+        let resolved = resolve(&root.lock().unwrap(), &path);
+        let result = resolved.and_then(|resolved| f(&resolved, arguments).map_err(|error| format!("{}: {}", path, error)));
+        Ok(result.map_or_else(error, ok))
+    }
+}
+
+/// `fs.read_file(path)`, the contents of the file at `path`, which must be utf-8
+pub(super) fn read_file(path: &Path, _: Vec<Value>) -> io::Result<Value> {
+    std::fs::read_to_string(path).map(Value::String)
+}
+
+/// `fs.write_file(path, contents)`, replacing whatever is at `path`
+pub(super) fn write_file(path: &Path, arguments: Vec<Value>) -> io::Result<Value> {
+    let [Value::String(contents)] = &arguments[..] else { unreachable!("checked by the type of fs.write_file") };
+    std::fs::write(path, contents).map(|()| Value::Tuple(vec![]))
+}
+
+/// `fs.list_dir(path)`, the names of what's in the directory at `path`, in order
+pub(super) fn list_dir(path: &Path, _: Vec<Value>) -> io::Result<Value> {
+    let mut names = std::fs::read_dir(path)?
+                              .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                              .collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(Value::Tuple(names.into_iter().map(Value::String).collect()))
+}
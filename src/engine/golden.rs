@@ -0,0 +1,99 @@
+//! golden traces, for testing what programs do rather than only what they produce. a program is
+//! traced by running it deterministically and writing out, a line each, the calls of natives it
+//! made, with their arguments and what they produced, and then its value, or the failure it
+//! stopped with. `io.print` prints nothing when a program is traced, since its calls are already
+//! in the trace, so it marks the steps of a program worth seeing.
+//!
+//! the programs in `tests/golden` are traced by the tests and compared against the traces
+//! recorded next to them, so that a change to the evaluator that changes what a program does,
+//! e.g. the order arguments are evaluated in, fails until its trace is recorded again on purpose,
+//! by running the tests with `SANGUINELLO_BLESS` set.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::sgir::operators::Operators;
+use crate::sgir::{Expression, Type, Value};
+use crate::syntax::literal::Literal;
+use crate::syntax::lower::lower_block;
+use crate::syntax::parser::parse;
+
+use super::{Deterministic, Engine};
+
+/// the extension of a golden trace, which is recorded next to the program it's the trace of
+pub const EXTENSION: &str = "trace";
+
+/// what every program is traced with, so that its trace is the same on every run
+const SETTINGS: Deterministic = Deterministic { seed: 0, fuel: 1_000_000 };
+
+/// an engine for tracing programs: the prelude, and an `io.print` that takes a value of any type
+pub fn engine() -> Engine {
+    let mut engine = Engine::new();
+    let typ = Type::Function { arguments: vec![Type::Intersection(vec![])], result: Box::new(Type::Tuple(vec![])) };
+    engine.register_native("io", "print", typ, |_| Ok(Value::Tuple(vec![])));
+    engine.grant("io");
+    engine
+}
+
+/// the trace of running `expr` on `engine`
+pub fn trace(engine: &Engine, expr: Expression) -> String {
+    let recording = engine.run_deterministic(expr, SETTINGS);
+    let mut trace = String::new();
+    for effect in &recording.trace {
+        let arguments = effect.arguments.iter().map(|argument| Literal(argument).to_string()).collect::<Vec<_>>();
+        write!(trace, "{}({})", effect.native, arguments.join(", ")).unwrap();
+        match &effect.result {
+            Ok(Value::Tuple(elements)) if elements.is_empty() => writeln!(trace),
+            Ok(value) => writeln!(trace, " = {}", Literal(value)),
+            Err(error) => writeln!(trace, " ! {}", error),
+        }.unwrap();
+    }
+    match &recording.result {
+        Ok(value) => writeln!(trace, "= {}", Literal(value)),
+        Err(error) => writeln!(trace, "! {}", error),
+    }.unwrap();
+    trace
+}
+
+/// trace each program in `directory` and compare it against its golden trace, or record it as
+/// its golden trace if `bless` is set, producing a message for each program whose trace differs
+pub fn compare(directory: &Path, bless: bool) -> Vec<String> {
+    let mut programs = std::fs::read_dir(directory).map_or(vec![], |entries| {
+        entries.filter_map(|entry| Some(entry.ok()?.path()))
+               .filter(|path| path.extension().is_some_and(|extension| extension == "sg"))
+               .collect::<Vec<_>>()
+    });
+    programs.sort();
+
+    let engine = engine();
+    let mut failures = vec![];
+    for program in programs {
+        let source = std::fs::read_to_string(&program).unwrap_or_default();
+        let parsed = parse(&source);
+        let lowered = match parsed.errors.first() {
+            Some(error) => Err(error.to_string()),
+            None => lower_block(&parsed.program, &Operators::default()).map_err(|error| error.to_string()),
+        };
+        let expr = match lowered {
+            Ok(expr) => expr,
+            Err(error) => {
+                failures.push(format!("{}: {}", program.display(), error));
+                continue
+            }
+        };
+        let found = trace(&engine, expr);
+        let golden = program.with_extension(EXTENSION);
+        if bless {
+            if let Err(error) = std::fs::write(&golden, &found) {
+                failures.push(format!("{}: {}", golden.display(), error));
+            }
+            continue
+        }
+        match std::fs::read_to_string(&golden) {
+            Ok(expected) if expected == found => {}
+            Ok(expected) => failures.push(format!("{}: expected the trace\n{}found\n{}", program.display(), expected, found)),
+            Err(error) => failures.push(format!("{}: {}", golden.display(), error)),
+        }
+    }
+    failures
+}
@@ -0,0 +1,88 @@
+//! the `json` module, which converts values to JSON text and back: `json.encode(value)` and
+//! `json.decode(text)`. records are objects, tuples are arrays, including the `...T` lists of
+//! values, and numbers, floats, strings, and booleans are themselves. `()`, which stands for
+//! nothing, is `null`, and a character is a string of one. functions, bytes, and variants have no
+//! JSON, so encoding one fails. the text a program decodes could be anything, so what it produces
+//! is of the top type, for the program to test the type of.
+
+use std::sync::Arc;
+
+use crate::sgir::{EvalError, Type, Value};
+use crate::syntax::json::{parse_json, Json, JsonError};
+
+/// the top type, of any value
+fn any() -> Type {
+    Type::Intersection(vec![])
+}
+
+/// `variant { Ok(typ), Error(error) }`
+fn outcome(typ: Type, error: Type) -> Type {
+    Type::Variant(vec![("Ok".to_owned(), typ), ("Error".to_owned(), error)])
+}
+
+/// `(Any) -> variant { Ok(String), Error(String) }`, the type of `json.encode`
+pub(super) fn encode_type() -> Type {
+    Type::Function { arguments: vec![any()], result: Box::new(outcome(Type::String, Type::String)) }
+}
+
+/// `(String) -> variant { Ok(Any), Error({message: String, offset: Number}) }`, the type of
+/// `json.decode`, which fails with the offset of the byte where the text went wrong
+pub(super) fn decode_type() -> Type {
+    let error = Type::Record(vec![("message".to_owned(), Type::String), ("offset".to_owned(), Type::Number)]);
+    Type::Function { arguments: vec![Type::String], result: Box::new(outcome(any(), error)) }
+}
+
+fn variant(tag: &str, payload: Value) -> Value {
+    Value::Variant { tag: tag.to_owned(), payload: Box::new(payload) }
+}
+
+/// `value` as JSON, or what in it has none
+fn to_json(value: &Value) -> Result<Json, String> {
+    Ok(match value {
+        Value::Boolean(value) => Json::Boolean(*value),
+        Value::Number(value) => Json::Number(*value),
+        Value::Float(value) => Json::Float(*value),
+        Value::String(value) => Json::String(value.clone()),
+        Value::Char(value) => Json::String(value.to_string()),
+        Value::Tuple(elements) if elements.is_empty() => Json::Null,
+        Value::Tuple(elements) => Json::Array(elements.iter().map(to_json).collect::<Result<_, _>>()?),
+        Value::Record(fields) => Json::Object(fields.iter().map(|(field, value)| Ok((field.clone(), to_json(value)?))).collect::<Result<_, String>>()?),
+        Value::Bytes(_) => return Err("bytes can't be encoded as JSON".to_owned()),
+        Value::Variant { tag, .. } => return Err(format!("the variant {} can't be encoded as JSON", tag)),
+        Value::Function { .. } | Value::Native(_) => return Err("a function can't be encoded as JSON".to_owned()),
+    })
+}
+
+/// `json` as a value. an empty array is `()`, like `null`, since it's an empty tuple.
+fn from_json(json: Json) -> Value {
+    match json {
+        Json::Null => Value::Tuple(vec![]),
+        Json::Boolean(value) => Value::Boolean(value),
+        Json::Number(value) => Value::Number(value),
+        Json::Float(value) => Value::Float(value),
+        Json::String(value) => Value::String(value),
+        Json::Array(elements) => Value::Tuple(elements.into_iter().map(from_json).collect()),
+        Json::Object(fields) => Value::Record(Arc::new(fields.into_iter().map(|(field, value)| (field, from_json(value))).collect())),
+    }
+}
+
+/// `json.encode(value)`, the compact JSON text of `value`
+pub(super) fn encode(arguments: Vec<Value>) -> Result<Value, EvalError> {
+    let [value] = &arguments[..] else { unreachable!("checked by the type of json.encode") };
+    Ok(match to_json(value) {
+        Ok(json) => variant("Ok", Value::String(json.to_string())),
+        Err(message) => variant("Error", Value::String(message)),
+    })
+}
+
+/// `json.decode(text)`, the value `text` writes in JSON
+pub(super) fn decode(arguments: Vec<Value>) -> Result<Value, EvalError> {
+    let [Value::String(text)] = &arguments[..] else { unreachable!("checked by the type of json.decode") };
+    Ok(match parse_json(text) {
+        Ok(json) => variant("Ok", from_json(json)),
+        Err(JsonError { message, offset }) => {
+            let offset = Value::Number(offset.try_into().unwrap_or(i64::MAX));
+            variant("Error", Value::Record(Arc::new(vec![("message".to_owned(), Value::String(message)), ("offset".to_owned(), offset)])))
+        }
+    })
+}
@@ -0,0 +1,510 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use thiserror::Error;
+
+use crate::sgir::binary::{DecodeError, Decoder, Encoder, Format, Version};
+use crate::sgir::coverage::{self, CoverageReport};
+use crate::sgir::primitives::Primitive;
+use crate::sgir::target;
+use crate::sgir::{self, Binding, Declarations, Effect, Environment, EvalError, Expression, Interpreter, Interrupt, Kind, Native, Type, TypeError, Value};
+use random::Random;
+
+mod fs;
+pub mod golden;
+mod json;
+mod os;
+pub mod random;
+pub mod session;
+mod tasks;
+pub mod time;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum EngineError {
+    #[error("{name} requires the {capability} capability, which was not granted")]
+    CapabilityDenied {
+        name: String,
+        capability: String,
+    },
+
+    #[error(transparent)]
+    Type(#[from] TypeError),
+
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+
+    #[error("{0} isn't a global the host defined")]
+    Undefined(String),
+
+    #[error("{name} mentions the abstract type {typ}, which only the natives of its module can")]
+    AbstractTypeLeak {
+        name: String,
+        typ: String,
+    },
+
+    #[error("{value} isn't a value of {name}'s type, {expected}")]
+    Mistyped {
+        name: String,
+        expected: Type,
+        value: Value,
+    },
+}
+
+/// the most characters of a value `bindings` summarizes it with
+const SUMMARY_LENGTH: usize = 60;
+
+/// the format of the snapshots of an engine's globals. 1.1 added record updates to SGIR, 1.2
+/// floats, 1.3 the `error` and `check` primitives, 1.4 return points with inferred result
+/// types, and 1.5 the `/` (Quotient) primitive.
+pub const SNAPSHOT: Format = Format { magic: b"SGSN", version: Version { major: 1, minor: 5 }, migrations: &[] };
+
+/// a global binding along with what a program needs to use it
+struct Global {
+    typ: Type,
+    /// the native module the binding belongs to, which must be granted for a program to use it
+    capability: Option<String>,
+    value: Value,
+}
+
+/// `math.random`, drawing from `random`
+fn math_random(random: Arc<Mutex<Random>>) -> impl Fn(Vec<Value>) -> Result<Value, EvalError> + Send + Sync + 'static {
+    move |arguments| match &arguments[..] {
+        [Value::Number(low), Value::Number(high)] if low <= high => Ok(Value::Number(random.lock().unwrap().range(*low, *high))),
+        [Value::Number(low), Value::Number(high)] => Err(EvalError::NativeFailure {
+            name: "math.random".to_owned(),
+            message: format!("empty range {}..{}", low, high),
+        }),
+        _ => unreachable!("checked by the type of math.random"),
+    }
+}
+
+/// the settings of a deterministic run, which behaves the same way on every machine given the
+/// same program, globals, and natives
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deterministic {
+    /// the seed of the run's own generator behind `math.random`, which other runs don't share
+    pub seed: u64,
+    /// the evaluation steps the run may take before it fails with `OutOfFuel`
+    pub fuel: usize,
+}
+
+/// what a deterministic run did
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    pub result: Result<Value, EngineError>,
+    /// the evaluation steps it took
+    pub steps: usize,
+    /// the calls of natives it made, in order, which `replay` can run it again from
+    pub trace: Vec<Effect>,
+}
+
+/// the embedding API: a host defines globals and registers native functions in modules, grants
+/// a script access to some of those modules, and runs it. globals are read-only to scripts, so
+/// one engine can run many scripts at once from different threads.
+#[derive(Default)]
+pub struct Engine {
+    globals: HashMap<String, Global>,
+    granted: HashSet<String>,
+    /// the abstract types registered by modules, by name, along with their modules and kinds
+    abstract_types: HashMap<String, (String, Kind)>,
+    /// the generator behind `math.random`, shared by every program the engine runs
+    random: Arc<Mutex<Random>>,
+    /// the arguments behind `os.args`
+    arguments: Arc<Mutex<Vec<String>>>,
+    /// the directory the `fs` module is confined to
+    root: Arc<Mutex<PathBuf>>,
+    /// the clock behind the `time` module
+    clock: time::Shared,
+    /// the target whose branches of target conditionals are run, if it isn't the native one
+    target: Option<String>,
+}
+
+impl Engine {
+    /// an engine with the prelude: `assert` and `error`, the `math` and `json` modules, which are
+    /// granted from the start, the `task` module, which isn't, since its tasks run on threads of
+    /// their own, and the `os`, `fs`, and `time` modules, which aren't either, since they tell a
+    /// program about the machine it runs on
+    pub fn new() -> Engine {
+        let mut engine = Engine::default();
+        engine.install_failures();
+        engine.install_math();
+        engine.install_json();
+        engine.install_tasks();
+        engine.install_os();
+        engine.install_fs();
+        engine.install_time();
+        engine
+    }
+
+    /// `assert(condition, message?)` and `error(value)`, whose failures are located at their calls
+    fn install_failures(&mut self) {
+        let variable = |id: &str| Expression::Variable(id.to_owned());
+        let binding = |id: &str, typ| Binding { id: id.to_owned(), typ };
+        let function = |parameters: Vec<Binding>, operator| {
+            let arguments = parameters.iter().map(|Binding { id, .. }| variable(id)).collect();
+            let body = Box::new(Expression::Primitive { operator, arguments });
+            Value::Function { parameters, body, environment: Environment::global(HashMap::new()) }
+        };
+
+        let parameters = vec![binding("condition", Type::Boolean), binding("message", Type::Rest(Box::new(Type::String)))];
+        let typ = Type::Function { arguments: parameters.iter().map(|Binding { typ, .. }| typ.clone()).collect(),
+                                   result: Box::new(Type::Tuple(vec![])) };
+        self.define("assert", typ, function(parameters, Primitive::Check));
+
+        let parameters = vec![binding("value", Type::Intersection(vec![]))];
+        let typ = Type::Function { arguments: vec![Type::Intersection(vec![])], result: Box::new(Type::Union(vec![])) };
+        self.define("error", typ, function(parameters, Primitive::Error));
+    }
+
+    fn install_math(&mut self) {
+        let typ = Type::Function { arguments: vec![Type::Number, Type::Number], result: Box::new(Type::Number) };
+        self.register_native("math", "random", typ, math_random(self.random.clone()));
+        self.grant("math");
+    }
+
+    fn install_json(&mut self) {
+        self.register_native("json", "encode", json::encode_type(), json::encode);
+        self.register_native("json", "decode", json::decode_type(), json::decode);
+        self.grant("json");
+    }
+
+    fn install_tasks(&mut self) {
+        use tasks::{element, of, polymorphic};
+        let function = |arguments, result| polymorphic(Type::Function { arguments, result: Box::new(result) });
+        self.register_abstract_type_constructor("task", "Task", 1);
+        self.register_abstract_type_constructor("task", "Channel", 1);
+        let body = Type::Function { arguments: vec![], result: Box::new(element()) };
+        self.register_native("task", "spawn", function(vec![body], of("Task", element())), tasks::spawn);
+        self.register_native("task", "join", function(vec![of("Task", element())], element()), tasks::join);
+        self.register_native("task", "channel", function(vec![], of("Channel", element())), tasks::channel);
+        self.register_native("task", "send", function(vec![of("Channel", element()), element()], Type::Tuple(vec![])), tasks::send);
+        self.register_native("task", "receive", function(vec![of("Channel", element())], element()), tasks::receive);
+    }
+
+    fn install_os(&mut self) {
+        let args = Type::Function { arguments: vec![], result: Box::new(Type::Rest(Box::new(Type::String))) };
+        self.register_native("os", "args", args, os::args(self.arguments.clone()));
+        let env = Type::Function { arguments: vec![Type::String], result: Box::new(os::optional_string()) };
+        self.register_native("os", "env", env, os::env);
+    }
+
+    fn install_fs(&mut self) {
+        let function = |arguments, result| Type::Function { arguments, result: Box::new(fs::outcome(result)) };
+        *self.root.lock().unwrap() = PathBuf::from(".");
+        let root = self.root.clone();
+        let native = |name, f| fs::native(root.clone(), name, f);
+        self.register_native("fs", "read_file", function(vec![Type::String], Type::String), native("fs.read_file", fs::read_file));
+        self.register_native("fs", "write_file", function(vec![Type::String, Type::String], Type::Tuple(vec![])),
+                             native("fs.write_file", fs::write_file));
+        self.register_native("fs", "list_dir", function(vec![Type::String], Type::Rest(Box::new(Type::String))),
+                             native("fs.list_dir", fs::list_dir));
+    }
+
+    /// confine the `fs` module to the files under `root`, which is the directory the host runs in
+    /// until this is called
+    pub fn set_root(&self, root: impl Into<PathBuf>) {
+        *self.root.lock().unwrap() = root.into();
+    }
+
+    fn install_time(&mut self) {
+        let typ = Type::Function { arguments: vec![], result: Box::new(time::duration()) };
+        self.register_native("time", "monotonic", typ.clone(), time::native(self.clock.clone(), |clock| clock.monotonic()));
+        self.register_native("time", "wall_clock", typ, time::native(self.clock.clone(), |clock| clock.wall_clock()));
+    }
+
+    /// read the time for the `time` module from `clock` rather than from the system, e.g. to test
+    /// a program that measures how long things take
+    pub fn set_clock(&self, clock: impl time::Clock + 'static) {
+        *self.clock.0.lock().unwrap() = Arc::new(clock);
+    }
+
+    /// set the arguments `os.args` produces, e.g. those a script was run with. there are none
+    /// until this is called.
+    pub fn set_arguments(&self, arguments: Vec<String>) {
+        *self.arguments.lock().unwrap() = arguments;
+    }
+
+    /// run the branches of target conditionals for `target`, e.g. `"wasm"` in the playground,
+    /// rather than those for the native target
+    pub fn set_target(&mut self, target: &str) {
+        self.target = Some(target.to_owned());
+    }
+
+    /// the target whose branches of target conditionals are run
+    pub fn target(&self) -> &str {
+        self.target.as_deref().unwrap_or(target::NATIVE)
+    }
+
+    /// reseed the generator behind `math.random`, so that programs using it behave the same
+    /// way on every run. engines are seeded unpredictably until this is called.
+    pub fn seed(&self, seed: u64) {
+        *self.random.lock().unwrap() = Random::new(seed);
+    }
+
+    /// register `function` as `module.name`, available to programs granted `module`
+    pub fn register_native(&mut self, module: &str, name: &str, typ: Type,
+                           function: impl Fn(Vec<Value>) -> Result<Value, EvalError> + Send + Sync + 'static) {
+        let native = Native::new(&format!("{}.{}", module, name), function);
+        self.register(module, typ, native);
+    }
+
+    /// register an async `function` as `module.name`, which only `run_async` can call
+    pub fn register_async_native<F>(&mut self, module: &str, name: &str, typ: Type,
+                                    function: impl Fn(Vec<Value>) -> F + Send + Sync + 'static)
+    where F: Future<Output = Result<Value, EvalError>> + 'static {
+        let native = Native::new_async(&format!("{}.{}", module, name), function);
+        self.register(module, typ, native);
+    }
+
+    fn register(&mut self, module: &str, typ: Type, native: Native) {
+        let global = Global { typ, capability: Some(module.to_owned()), value: Value::Native(native.clone()) };
+        self.globals.insert(native.name, global);
+    }
+
+    /// register `module.name` as an abstract type, which the signatures of the natives in `module`
+    /// can mention as `Type::Variable("module.name")`. its values are whatever those natives make
+    /// them, but a program only sees them as of `module.name`, so it can pass them back to
+    /// `module` but never make one itself or look into one, e.g. to break the invariants of a data
+    /// structure the module keeps.
+    pub fn register_abstract_type(&mut self, module: &str, name: &str) {
+        self.register_abstract_type_constructor(module, name, 0);
+    }
+
+    /// register `module.name` as an abstract type constructor taking `parameters` types, like
+    /// `task.Task`, whose `task.Task<T>` is a task producing a `T`. it's an abstract type if it
+    /// takes none.
+    pub fn register_abstract_type_constructor(&mut self, module: &str, name: &str, parameters: usize) {
+        let kind = match parameters {
+            0 => Kind::Star,
+            parameters => Kind::Arrow { from: vec![Kind::Star; parameters], to: Box::new(Kind::Star) },
+        };
+        self.abstract_types.insert(format!("{}.{}", module, name), (module.to_owned(), kind));
+    }
+
+    /// bind `name` to `value` for every program, which needs no capability to use it
+    pub fn define(&mut self, name: &str, typ: Type, value: Value) {
+        self.globals.insert(name.to_owned(), Global { typ, capability: None, value });
+    }
+
+    /// every global, natives included, in order of name: its name, its type, and a summary of its
+    /// value, which is cut short if it's long
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, &Type, String)> {
+        let mut globals: Vec<_> = self.globals.iter().collect();
+        globals.sort_by_key(|(name, _)| *name);
+        globals.into_iter().map(|(name, Global { typ, value, .. })| {
+            let mut summary = value.to_string();
+            if let Some((end, _)) = summary.char_indices().nth(SUMMARY_LENGTH) {
+                summary.truncate(end);
+                summary.push('…');
+            }
+            (name.as_str(), typ, summary)
+        })
+    }
+
+    /// the value of the global `name`, along with its type
+    pub fn get(&self, name: &str) -> Option<(&Type, &Value)> {
+        self.globals.get(name).map(|Global { typ, value, .. }| (typ, value))
+    }
+
+    /// replace the value of `name`, which must be a global the host defined, with `value`, which
+    /// must be of its type. the programs run afterwards see the new value.
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), EngineError> {
+        let Some(global) = self.globals.get_mut(name).filter(|global| global.capability.is_none()) else {
+            return Err(EngineError::Undefined(name.to_owned()))
+        };
+        if !value.type_of().is_some_and(|typ| sgir::is_subtype(&typ, &global.typ)) {
+            return Err(EngineError::Mistyped { name: name.to_owned(), expected: global.typ.clone(), value })
+        }
+        global.value = value;
+        Ok(())
+    }
+
+    /// allow programs to use the natives in `capability`
+    pub fn grant(&mut self, capability: &str) {
+        self.granted.insert(capability.to_owned());
+    }
+
+    /// resolve the globals `expr` refers to, refusing natives whose module hasn't been granted
+    fn link(&self, expr: &Expression) -> Result<Vec<(String, &Global)>, EngineError> {
+        let mut free: Vec<_> = expr.free_variables().into_iter().collect();
+        free.sort();
+
+        let mut linked = vec![];
+        for name in free {
+            if let Some(global) = self.globals.get(&name) {
+                if let Some(capability) = &global.capability {
+                    if !self.granted.contains(capability) {
+                        return Err(EngineError::CapabilityDenied { name, capability: capability.clone() })
+                    }
+                }
+                // an abstract type in another signature would let a program forge its values
+                for typ in global.typ.free_variables() {
+                    if self.abstract_types.get(&typ).is_some_and(|(module, _)| global.capability.as_ref() != Some(module)) {
+                        return Err(EngineError::AbstractTypeLeak { name, typ })
+                    }
+                }
+                linked.push((name, global));
+            }
+        }
+        Ok(linked)
+    }
+
+    /// link and check `expr` without running it, producing its type
+    pub fn check(&self, expr: &Expression) -> Result<Type, EngineError> {
+        Ok(self.prepare(&mut expr.clone(), None)?.0)
+    }
+
+    /// link, check, and evaluate `expr`
+    pub fn run(&self, expr: Expression) -> Result<Value, EngineError> {
+        Ok(self.run_typed(expr)?.0)
+    }
+
+    /// link, check, and evaluate `expr`, along with the type it was checked at
+    pub fn run_typed(&self, mut expr: Expression) -> Result<(Value, Type), EngineError> {
+        let (typ, globals) = self.prepare(&mut expr, None)?;
+        Ok((Interpreter::default().run_in(&globals, expr)?, typ))
+    }
+
+    /// link, check, and evaluate `expr` with `fuel`, if it's bounded, recording which of its spans
+    /// are executed
+    pub fn run_with_coverage(&self, expr: Expression, fuel: Option<usize>) -> (Result<Value, EngineError>, CoverageReport) {
+        let mut interpreter = Interpreter::default();
+        interpreter.fuel = fuel;
+        coverage::covering(interpreter, expr, |interpreter, mut expr| {
+            let (_, globals) = self.prepare(&mut expr, None)?;
+            Ok(interpreter.run_in(&globals, expr)?)
+        })
+    }
+
+    /// link, check, and evaluate `expr`, stopping with `Interrupted` if `interrupt` asks it to,
+    /// e.g. because another thread set its token or its deadline passed
+    pub fn run_interruptible(&self, mut expr: Expression, interrupt: Interrupt) -> Result<Value, EngineError> {
+        let (_, globals) = self.prepare(&mut expr, None)?;
+        let mut interpreter = Interpreter::default();
+        interpreter.interrupt = Some(interrupt);
+        Ok(interpreter.run_in(&globals, expr)?)
+    }
+
+    /// link, check, and evaluate `expr`, suspending while async natives are pending
+    pub async fn run_async(&self, mut expr: Expression) -> Result<Value, EngineError> {
+        let (_, globals) = self.prepare(&mut expr, None)?;
+        Ok(Interpreter::default().run_async(&globals, expr).await?)
+    }
+
+    /// run each of `programs` on its own thread, returning their results in order
+    pub fn run_parallel(&self, programs: Vec<Expression>) -> Vec<Result<Value, EngineError>> {
+        thread::scope(|scope| {
+            let handles: Vec<_> = programs.into_iter()
+                                          .map(|expr| scope.spawn(move || self.run(expr)))
+                                          .collect();
+            handles.into_iter()
+                   .map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                   .collect()
+        })
+    }
+
+    /// run `expr` deterministically, recording the calls of natives it makes. the natives are
+    /// called as usual, so the run is only as deterministic as they are, but the trace of them
+    /// makes it replayable regardless.
+    pub fn run_deterministic(&self, mut expr: Expression, settings: Deterministic) -> Recording {
+        let mut interpreter = Interpreter::with_fuel(settings.fuel);
+        interpreter.trace = Some(vec![]);
+        let result = self.prepare(&mut expr, Some(settings.seed))
+                         .and_then(|(_, globals)| Ok(interpreter.run_in(&globals, expr)?));
+        Recording { result, steps: interpreter.steps, trace: interpreter.trace.unwrap_or_default() }
+    }
+
+    /// run `expr` again from the `trace` a deterministic run of it recorded, answering its calls
+    /// of natives with what they produced then rather than calling them. a run that makes other
+    /// calls than the trace recorded fails with `Diverged`.
+    pub fn replay(&self, mut expr: Expression, settings: Deterministic, trace: Vec<Effect>) -> Result<Value, EngineError> {
+        let (_, globals) = self.prepare(&mut expr, Some(settings.seed))?;
+        let mut interpreter = Interpreter::with_fuel(settings.fuel);
+        interpreter.replay = Some(trace.into());
+        let value = interpreter.run_in(&globals, expr)?;
+        match interpreter.replay.and_then(|mut replay| replay.pop_front()) {
+            Some(effect) => Err(EvalError::Diverged {
+                expected: format!("a call of {}", effect.native),
+                found: "the end of the program".to_owned(),
+            }.into()),
+            None => Ok(value),
+        }
+    }
+
+    /// serialize the globals defined by `define`. natives aren't included, but references to
+    /// them from within values are kept by name.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut defined: Vec<_> = self.globals.iter()
+                                              .filter(|(_, global)| global.capability.is_none())
+                                              .collect();
+        defined.sort_by_key(|(name, _)| *name);
+
+        let mut encoder = Encoder::new();
+        encoder.header(&SNAPSHOT);
+        encoder.encode(&defined.len());
+        for (name, Global { typ, value, .. }) in defined {
+            encoder.encode(name);
+            encoder.encode(typ);
+            encoder.encode(value);
+        }
+        encoder.finish()
+    }
+
+    /// define the globals saved in `snapshot`, linking the natives they refer to against those
+    /// registered with this engine
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), DecodeError> {
+        let natives = |name: &str| match self.globals.get(name) {
+            Some(Global { capability: Some(_), value: Value::Native(native), .. }) => Some(native.clone()),
+            _ => None,
+        };
+        let mut decoder = Decoder::new(snapshot, &natives);
+        decoder.header(&SNAPSHOT)?;
+
+        let length: usize = decoder.decode()?;
+        let mut restored = vec![];
+        for _ in 0..length {
+            let name: String = decoder.decode()?;
+            let typ: Type = decoder.decode()?;
+            let value: Value = decoder.decode()?;
+            restored.push((name, typ, value));
+        }
+
+        for (name, typ, value) in restored {
+            self.define(&name, typ, value);
+        }
+        Ok(())
+    }
+
+    /// resolve the target conditionals in `expr` for the engine's target, then link and check it,
+    /// producing its type and the global environment to run it in. with a `seed`, `math.random`
+    /// draws from a generator of the run's own, seeded with it.
+    fn prepare(&self, expr: &mut Expression, seed: Option<u64>) -> Result<(Type, Arc<Environment>), EngineError> {
+        *expr = target::resolve_targets(expr.take(), self.target());
+        let linked = self.link(expr)?;
+
+        let declarations: Declarations = linked.iter()
+                                               .map(|(name, global)| (name.clone(), global.typ.clone()))
+                                               .collect();
+        let abstract_types: Vec<_> = self.abstract_types.iter()
+                                                        .filter(|(_, (module, _))| self.granted.contains(module))
+                                                        .map(|(typ, (_, kind))| (typ.clone(), kind.clone()))
+                                                        .collect();
+        let typ = sgir::check_with_abstract_types(&abstract_types, &declarations, expr.clone())?;
+
+        let globals = linked.into_iter()
+                            .map(|(name, global)| match seed {
+                                Some(seed) if name == "math.random" => {
+                                    let random = Arc::new(Mutex::new(Random::new(seed)));
+                                    (name, Value::Native(Native::new("math.random", math_random(random))))
+                                }
+                                _ => (name, global.value.clone()),
+                            })
+                            .collect();
+        Ok((typ, Environment::global(globals)))
+    }
+}
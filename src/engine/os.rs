@@ -0,0 +1,29 @@
+//! the `os` module, which gives a script what it was run with: `os.args()`, the arguments the host
+//! passed it, e.g. those after `--` on the command line, and `os.env(name)`, the environment
+//! variable `name`, if it's set. a script can learn things about the machine from these that the
+//! host may not want it to, so the module has to be granted.
+
+use std::sync::{Arc, Mutex};
+
+use crate::sgir::{EvalError, Type, Value};
+
+/// `variant { Some(String), None }`, the result of `os.env`
+pub(super) fn optional_string() -> Type {
+    Type::Variant(vec![("Some".to_owned(), Type::String), ("None".to_owned(), Type::Tuple(vec![]))])
+}
+
+/// `os.args()`, the arguments in `arguments` as they are when it's called
+pub(super) fn args(arguments: Arc<Mutex<Vec<String>>>) -> impl Fn(Vec<Value>) -> Result<Value, EvalError> + Send + Sync + 'static {
+    move |_| Ok(Value::Tuple(arguments.lock().unwrap().iter().cloned().map(Value::String).collect()))
+}
+
+/// `os.env(name)`, `Some` of the value of the environment variable `name`, or `None` if it isn't
+/// set or isn't unicode. a name no variable can have, e.g. one with an `=` in it, isn't set.
+pub(super) fn env(arguments: Vec<Value>) -> Result<Value, EvalError> {
+    let [Value::String(name)] = &arguments[..] else { unreachable!("checked by the type of os.env") };
+    let valid = !name.is_empty() && !name.contains(['=', '\0']);
+    Ok(match valid.then(|| std::env::var(name).ok()).flatten() {
+        Some(value) => Value::Variant { tag: "Some".to_owned(), payload: Box::new(Value::String(value)) },
+        None => Value::Variant { tag: "None".to_owned(), payload: Box::new(Value::Tuple(vec![])) },
+    })
+}
@@ -0,0 +1,51 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// a small, fast, seedable pseudorandom number generator (splitmix64). it isn't suitable for
+/// cryptography, but the same seed always produces the same sequence.
+#[derive(Clone, Debug)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    /// a generator seeded from the entropy the standard library uses for hash maps
+    pub fn from_entropy() -> Random {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u8(0);
+        Random::new(hasher.finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// a uniformly distributed number between `low` and `high`, inclusive. `low` must not be
+    /// greater than `high`.
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        debug_assert!(low <= high);
+        let span = (high as i128 - low as i128 + 1) as u128;
+        // reject draws from the incomplete block at the top so that every number is equally likely
+        let limit = (1u128 << 64) - (1u128 << 64) % span;
+        loop {
+            let draw = self.next_u64() as u128;
+            if draw < limit {
+                return (low as i128 + (draw % span) as i128) as i64
+            }
+        }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Random {
+        Random::from_entropy()
+    }
+}
@@ -0,0 +1,162 @@
+use thiserror::Error;
+
+use crate::sgir::blocks;
+use crate::sgir::operators::Operators;
+use crate::sgir::patterns::Arm;
+use crate::sgir::{Expression, Span, Type, Value};
+use crate::syntax::ast::{Ast, Node};
+use crate::syntax::lexer::{offset, LexError};
+use crate::syntax::lower::{lower_block_for, LowerError};
+use crate::syntax::parser::{parse, SyntaxError};
+
+use super::{Engine, EngineError};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum SessionError {
+    #[error(transparent)]
+    Syntax(#[from] SyntaxError),
+
+    #[error(transparent)]
+    Lower(#[from] LowerError),
+
+    #[error(transparent)]
+    Engine(#[from] EngineError),
+
+    #[error("the type alias {name} at {}:{} can't be declared in a session yet", span.start.line, span.start.column)]
+    TypeAlias {
+        name: String,
+        span: Span,
+    },
+
+    #[error("the macro {name} at {}:{} can't be declared in a session yet", span.start.line, span.start.column)]
+    Macro {
+        name: String,
+        span: Span,
+    },
+}
+
+impl SessionError {
+    /// whether the input ended before what it started was finished, so more of it should be read
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            SessionError::Syntax(SyntaxError::Expected { found, .. }) => found == "the end of the input",
+            SessionError::Syntax(SyntaxError::Lex(LexError::UnterminatedComment(_) | LexError::UnterminatedString(_))) => true,
+            _ => false,
+        }
+    }
+}
+
+/// a result the session has evaluated, along with the input it came from
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub input: String,
+    pub value: Value,
+    pub typ: Type,
+}
+
+/// the state of an interactive session: each input is run against the definitions of the ones
+/// before it, which are globals of its engine. the value of the `n`th expression evaluated is
+/// bound to `_n`, and the latest to `_` as well.
+pub struct Session {
+    engine: Engine,
+    operators: Operators,
+    /// the source of every definition made so far, in order
+    definitions: Vec<String>,
+    history: Vec<Entry>,
+}
+
+impl Session {
+    pub fn new(engine: Engine) -> Session {
+        Session { engine, operators: Operators::default(), definitions: vec![], history: vec![] }
+    }
+
+    /// every result evaluated so far, the first of them bound to `_1`
+    pub fn history(&self) -> &[Entry] {
+        &self.history
+    }
+
+    /// the source of every definition made so far, in order
+    pub fn definitions(&self) -> &[String] {
+        &self.definitions
+    }
+
+    /// evaluate each statement of `input` in turn, producing the values of those that are
+    /// expressions. a statement that fails leaves the session as the ones before it left it.
+    pub fn eval(&mut self, input: &str) -> Result<Vec<Entry>, SessionError> {
+        let mut parsed = parse(input);
+        if let Some(error) = parsed.errors.into_iter().next() {
+            return Err(error.into())
+        }
+        parsed.program.iter_mut().for_each(last_result);
+        let mut entries = vec![];
+        for node in &parsed.program {
+            let source = slice(input, node.span);
+            match &node.ast {
+                Ast::TypeAlias { name, .. } => {
+                    return Err(SessionError::TypeAlias { name: name.clone(), span: node.span })
+                }
+                Ast::MacroDeclaration { name, .. } => {
+                    return Err(SessionError::Macro { name: name.clone(), span: node.span })
+                }
+                Ast::Local { .. } | Ast::FunctionDeclaration { .. } => {
+                    self.define(node)?;
+                    if let Ast::FunctionDeclaration { operator: Some(fixity), name, .. } = &node.ast {
+                        self.operators.declare(name, *fixity);
+                    }
+                    self.definitions.push(source.to_owned());
+                }
+                _ => {
+                    let (value, typ) = self.engine.run_typed(lower_block_for(std::slice::from_ref(node), &self.operators, self.engine.target())?)?;
+                    let entry = Entry { input: source.to_owned(), value, typ };
+                    self.history.push(entry.clone());
+                    self.engine.define(&format!("_{}", self.history.len()), entry.typ.clone(), entry.value.clone());
+                    self.engine.define("_", entry.typ.clone(), entry.value.clone());
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// run a declaration, defining each of the names it binds as a global. it's lowered to a
+    /// `let` whose body is replaced with the tuple of the values of those names.
+    fn define(&mut self, node: &Node) -> Result<(), SessionError> {
+        let mut lowered = lower_block_for(std::slice::from_ref(node), &self.operators, self.engine.target())?;
+        let Expression::Match { scrutinee, arms } = &mut lowered else {
+            unreachable!("a declaration is lowered to a let")
+        };
+        let Arm { pattern, .. } = arms.remove(0);
+        let names = pattern.variables();
+        let body = Expression::Tuple(names.iter().cloned().map(Expression::Variable).collect());
+        let bound = blocks::let_in(pattern, scrutinee.take(), body);
+        let (value, mut typ) = self.engine.run_typed(bound)?;
+        match (value, &mut typ) {
+            (Value::Tuple(values), Type::Tuple(types)) => {
+                for ((name, value), typ) in names.iter().zip(values).zip(std::mem::take(types)) {
+                    self.engine.define(name, typ, value);
+                }
+            }
+            _ => unreachable!("a tuple is checked as a tuple type"),
+        }
+        Ok(())
+    }
+
+    /// write every definition made so far to the file at `path` as a program
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.definitions.iter().map(|definition| format!("{}\n", definition)).collect::<String>())
+    }
+}
+
+/// `node` with each placeholder `_` in it replaced by the name `_`, which is the last result in a
+/// session rather than a placeholder
+fn last_result(node: &mut Node) {
+    if let Ast::Placeholder = node.ast {
+        node.ast = Ast::Name("_".to_owned());
+    }
+    node.children_mut().into_iter().for_each(last_result);
+}
+
+/// the text of `source` that `span` covers
+fn slice(source: &str, span: Span) -> &str {
+    &source[offset(source, span.start)..offset(source, span.end)]
+}
@@ -0,0 +1,151 @@
+//! structured concurrency for the programs an engine runs: the `task` module. `task.spawn` runs a
+//! closure as a task on a thread of its own, `task.join` waits for its result, and channels carry
+//! values from one task to another. a task nothing joins is waited for when the last reference to
+//! it goes away, so no task outlives the program that spawned it.
+//!
+//! a closure belongs to the task that made it, so none can be passed to another task, whether as
+//! a task's result or through a channel. natives belong to the host, so they can, tasks and
+//! channels included.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+
+use crate::sgir::{Environment, EvalError, Expression, Interpreter, Kind, Native, NativeFunction, Type, TypeBinding, Value};
+
+/// `forall<T>. typ`, the type of a native that works on values of any type `T`
+pub(super) fn polymorphic(typ: Type) -> Type {
+    Type::ForAll { parameters: vec![TypeBinding { id: "T".to_owned(), kind: Kind::Star }], typ: Box::new(typ) }
+}
+
+/// the `T` of a polymorphic native's type
+pub(super) fn element() -> Type {
+    Type::Variable("T".to_owned())
+}
+
+/// `task.Task<T>` or `task.Channel<T>`, the abstract type `name` of the `task` module of `T`s
+pub(super) fn of(name: &str, typ: Type) -> Type {
+    Type::Instantiate { typ: Box::new(Type::Variable(format!("task.{}", name))), arguments: vec![typ] }
+}
+
+/// whether `value` can be passed from one task to another: anything but a closure, or a value
+/// holding one
+fn transferable(value: &Value) -> bool {
+    match value {
+        Value::Function { .. } => false,
+        Value::Tuple(elements) => elements.iter().all(transferable),
+        Value::Record(fields) => fields.iter().all(|(_, value)| transferable(value)),
+        Value::Variant { payload, .. } => transferable(payload),
+        _ => true,
+    }
+}
+
+/// `value`, passed to another task by the native `name`, if it can be
+fn transfer(name: &str, value: Value) -> Result<Value, EvalError> {
+    if transferable(&value) {
+        Ok(value)
+    } else {
+        Err(EvalError::NativeFailure { name: name.to_owned(), message: "a closure can't be passed from one task to another".to_owned() })
+    }
+}
+
+/// call `native`, a task or a channel, which is always synchronous
+fn call(native: &Native, arguments: Vec<Value>) -> Result<Value, EvalError> {
+    match &native.function {
+        NativeFunction::Sync(function) => function(arguments),
+        NativeFunction::Async(_) => unreachable!("tasks and channels are synchronous natives"),
+    }
+}
+
+/// a task running on a thread of its own, and its result once it's been joined
+struct Task {
+    thread: Mutex<Option<JoinHandle<Result<Value, EvalError>>>>,
+    result: OnceLock<Result<Value, EvalError>>,
+}
+
+impl Task {
+    fn join(&self) -> Result<Value, EvalError> {
+        self.result.get_or_init(|| {
+            let thread = self.thread.lock().unwrap().take().expect("a task is only joined once");
+            thread.join().unwrap_or_else(|_| Err(EvalError::NativeFailure { name: "task.join".to_owned(), message: "the task panicked".to_owned() }))
+        }).clone()
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.get_mut().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// a queue of values that tasks send to, and receive from in the order they were sent
+#[derive(Default)]
+struct Channel {
+    queue: Mutex<VecDeque<Value>>,
+    sent: Condvar,
+}
+
+impl Channel {
+    fn send(&self, value: Value) {
+        self.queue.lock().unwrap().push_back(value);
+        self.sent.notify_one();
+    }
+
+    /// the next value sent, waiting for one if there isn't one yet
+    fn receive(&self) -> Value {
+        let mut queue = self.sent.wait_while(self.queue.lock().unwrap(), |queue| queue.is_empty()).unwrap();
+        queue.pop_front().unwrap()
+    }
+}
+
+/// `task.spawn(f)`, a task running `f()` on a new thread, which is a native that joins it
+pub(super) fn spawn(arguments: Vec<Value>) -> Result<Value, EvalError> {
+    let [function] = <[Value; 1]>::try_from(arguments).expect("checked by the type of task.spawn");
+    let thread = thread::spawn(move || {
+        let env = Environment::global(HashMap::from([("task".to_owned(), function)]));
+        let body = Expression::Application { function: Box::new(Expression::Variable("task".to_owned())), arguments: vec![] };
+        Interpreter::default().run_in(&env, body).and_then(|value| transfer("task.join", value))
+    });
+    let task = Task { thread: Mutex::new(Some(thread)), result: OnceLock::new() };
+    Ok(Value::Native(Native::new("task.Task", move |_| task.join())))
+}
+
+/// `task.join(t)`, the result of `t`, once it's finished. a task that failed fails the same way
+/// wherever it's joined.
+pub(super) fn join(arguments: Vec<Value>) -> Result<Value, EvalError> {
+    match &arguments[..] {
+        [Value::Native(task)] => call(task, vec![]),
+        _ => unreachable!("checked by the type of task.join"),
+    }
+}
+
+/// `task.channel()`, a new channel, which is a native that sends what it's called with, or
+/// receives if it's called with nothing
+pub(super) fn channel(_: Vec<Value>) -> Result<Value, EvalError> {
+    let channel = Arc::new(Channel::default());
+    Ok(Value::Native(Native::new("task.Channel", move |mut arguments| match arguments.pop() {
+        Some(value) => {
+            channel.send(value);
+            Ok(Value::Tuple(vec![]))
+        }
+        None => Ok(channel.receive()),
+    })))
+}
+
+/// `task.send(c, value)`
+pub(super) fn send(arguments: Vec<Value>) -> Result<Value, EvalError> {
+    match <[Value; 2]>::try_from(arguments) {
+        Ok([Value::Native(channel), value]) => call(&channel, vec![transfer("task.send", value)?]),
+        _ => unreachable!("checked by the type of task.send"),
+    }
+}
+
+/// `task.receive(c)`, the next value sent to `c`, waiting until there is one
+pub(super) fn receive(arguments: Vec<Value>) -> Result<Value, EvalError> {
+    match &arguments[..] {
+        [Value::Native(channel)] => call(channel, vec![]),
+        _ => unreachable!("checked by the type of task.receive"),
+    }
+}
@@ -0,0 +1,581 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::task::{Context, Poll, Waker};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::*;
+
+fn number_to_number() -> Type {
+    Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) }
+}
+
+fn call(name: &str, argument: Expression) -> Expression {
+    Expression::Application { function: Box::new(Expression::Variable(name.to_owned())), arguments: vec![argument] }
+}
+
+fn engine_with_io() -> (Engine, Arc<Mutex<Vec<i64>>>) {
+    let printed = Arc::new(Mutex::new(vec![]));
+    let mut engine = Engine::new();
+    let output = printed.clone();
+    engine.register_native("io", "print", number_to_number(), move |arguments| match &arguments[..] {
+        [Value::Number(n)] => {
+            output.lock().unwrap().push(*n);
+            Ok(Value::Number(*n))
+        }
+        _ => unreachable!("checked by the type of io.print"),
+    });
+    (engine, printed)
+}
+
+#[test]
+fn test_engine_calls_granted_native() {
+    let (mut engine, printed) = engine_with_io();
+    engine.grant("io");
+    assert_eq!(engine.run(call("io.print", Expression::Number(5))), Ok(Value::Number(5)));
+    assert_eq!(*printed.lock().unwrap(), vec![5]);
+}
+
+#[test]
+fn test_engine_refuses_ungranted_capability_before_running() {
+    let (engine, printed) = engine_with_io();
+    assert_eq!(engine.run(call("io.print", Expression::Number(5))),
+               Err(EngineError::CapabilityDenied { name: "io.print".to_owned(), capability: "io".to_owned() }));
+    assert!(printed.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_engine_checks_calls_against_native_types() {
+    let (mut engine, printed) = engine_with_io();
+    engine.grant("io");
+    assert_eq!(engine.run(call("io.print", Expression::Boolean(true))),
+               Err(EngineError::Type(TypeError::TypeMismatch { expected: Type::Number, found: Type::Boolean })));
+    assert!(printed.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_engine_resolves_target_conditionals() {
+    let conditional = Expression::IfTarget { target: "wasm".to_owned(), consequent: Box::new(Expression::Number(1)),
+                                             alternative: Box::new(Expression::Boolean(false)) };
+    let mut engine = Engine::new();
+    assert_eq!(engine.target(), "native");
+    assert_eq!(engine.check(&conditional), Ok(Type::Boolean));
+    assert_eq!(engine.run(conditional.clone()), Ok(Value::Boolean(false)));
+    engine.set_target("wasm");
+    assert_eq!(engine.check(&conditional), Ok(Type::Number));
+    assert_eq!(engine.run(conditional), Ok(Value::Number(1)));
+}
+
+#[test]
+fn test_engine_unregistered_native_is_unbound() {
+    let engine = Engine::new();
+    assert_eq!(engine.run(call("fs.read", Expression::Number(5))),
+               Err(EngineError::Type(TypeError::UnboundIdentifier("fs.read".to_owned()))));
+}
+
+/// an engine with a `stack` module, whose stacks are tuples of numbers that programs can't see
+fn engine_with_stacks() -> Engine {
+    let stack = || Type::Variable("stack.Stack".to_owned());
+    let mut engine = Engine::new();
+    engine.register_abstract_type("stack", "Stack");
+    engine.register_native("stack", "empty", Type::Function { arguments: vec![], result: Box::new(stack()) },
+                           |_| Ok(Value::Tuple(vec![])));
+    engine.register_native("stack", "push", Type::Function { arguments: vec![stack(), Type::Number], result: Box::new(stack()) },
+                           |arguments| match &arguments[..] {
+                               [Value::Tuple(elements), value] => Ok(Value::Tuple(elements.iter().chain([value]).cloned().collect())),
+                               _ => unreachable!("checked by the type of stack.push"),
+                           });
+    engine.register_native("stack", "size", Type::Function { arguments: vec![stack()], result: Box::new(Type::Number) },
+                           |arguments| match &arguments[..] {
+                               [Value::Tuple(elements)] => Ok(Value::Number(elements.len() as i64)),
+                               _ => unreachable!("checked by the type of stack.size"),
+                           });
+    engine.grant("stack");
+    engine
+}
+
+#[test]
+fn test_engine_abstract_types_hide_their_representation() {
+    let mut engine = engine_with_stacks();
+    let empty = Expression::Application { function: Box::new(Expression::Variable("stack.empty".to_owned())), arguments: vec![] };
+    let pushed = Expression::Application { function: Box::new(Expression::Variable("stack.push".to_owned())),
+                                           arguments: vec![empty, Expression::Number(1)] };
+    assert_eq!(engine.run(call("stack.size", pushed)), Ok(Value::Number(1)));
+    // a tuple is what a stack is, but a program can't pass one off as a stack
+    assert_eq!(engine.run(call("stack.size", Expression::Tuple(vec![]))),
+               Err(EngineError::Type(TypeError::TypeMismatch { expected: Type::Variable("stack.Stack".to_owned()),
+                                                               found: Type::Tuple(vec![]) })));
+
+    // nor can another module's natives make one for it
+    let forged = Type::Function { arguments: vec![], result: Box::new(Type::Variable("stack.Stack".to_owned())) };
+    engine.register_native("forge", "stack", forged, |_| Ok(Value::Tuple(vec![Value::Boolean(true)])));
+    engine.grant("forge");
+    let forge = Expression::Application { function: Box::new(Expression::Variable("forge.stack".to_owned())), arguments: vec![] };
+    assert_eq!(engine.run(call("stack.size", forge)),
+               Err(EngineError::AbstractTypeLeak { name: "forge.stack".to_owned(), typ: "stack.Stack".to_owned() }));
+}
+
+/// a future that is pending on its first poll, like a host operation waiting on IO
+#[derive(Default)]
+struct Later {
+    polled: bool,
+}
+
+impl Future for Later {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if self.polled {
+            Poll::Ready(())
+        } else {
+            self.polled = true;
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// drive `future` to completion, counting how many times it suspended
+fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+    let mut future = std::pin::pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    let mut suspensions = 0;
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return (output, suspensions),
+            Poll::Pending => suspensions += 1,
+        }
+    }
+}
+
+fn engine_with_async_double() -> Engine {
+    let mut engine = Engine::new();
+    engine.register_async_native("net", "double", number_to_number(), |arguments| async move {
+        Later::default().await;
+        match &arguments[..] {
+            [Value::Number(n)] => Ok(Value::Number(n * 2)),
+            _ => unreachable!("checked by the type of net.double"),
+        }
+    });
+    engine.grant("net");
+    engine
+}
+
+#[test]
+fn test_engine_run_async_suspends_at_async_native() {
+    let engine = engine_with_async_double();
+    let expr = call("net.double", call("net.double", Expression::Number(5)));
+    let (result, suspensions) = block_on(engine.run_async(expr));
+    assert_eq!(result, Ok(Value::Number(20)));
+    assert_eq!(suspensions, 2);
+}
+
+#[test]
+fn test_engine_run_refuses_async_native() {
+    let engine = engine_with_async_double();
+    assert_eq!(engine.run(call("net.double", Expression::Number(5))),
+               Err(EngineError::Eval(EvalError::AsyncNative("net.double".to_owned()))));
+}
+
+#[test]
+fn test_engine_and_values_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Engine>();
+    assert_send_sync::<Value>();
+    assert_send_sync::<Expression>();
+}
+
+#[test]
+fn test_engine_run_parallel_shares_globals() {
+    let (mut engine, printed) = engine_with_io();
+    engine.grant("io");
+    engine.define("answer", Type::Number, Value::Number(42));
+    let programs = (0..8).map(|n| call("io.print", Expression::If { condition: Box::new(Expression::Boolean(n % 2 == 0)),
+                                                                     consequent: Box::new(Expression::Variable("answer".to_owned())),
+                                                                     alternative: Box::new(Expression::Number(n)) }))
+                         .collect();
+    let results = engine.run_parallel(programs);
+    assert_eq!(results, vec![Ok(Value::Number(42)), Ok(Value::Number(1)), Ok(Value::Number(42)), Ok(Value::Number(3)),
+                             Ok(Value::Number(42)), Ok(Value::Number(5)), Ok(Value::Number(42)), Ok(Value::Number(7))]);
+
+    let mut printed = printed.lock().unwrap().clone();
+    printed.sort();
+    assert_eq!(printed, vec![1, 3, 5, 7, 42, 42, 42, 42]);
+}
+
+/// a closure that captured io.print from the environment it was created in
+fn print_closure(engine: &Engine) -> Value {
+    let expr = Expression::Application { function: Box::new(Expression::Function { parameters: vec![sgir::Binding { id: "p".to_owned(), typ: number_to_number() }],
+                                                                                     body: Box::new(Expression::Function { parameters: vec![sgir::Binding { id: "n".to_owned(), typ: Type::Number }],
+                                                                                                                           body: Box::new(call("p", Expression::Variable("n".to_owned()))) }) }),
+                                         arguments: vec![Expression::Variable("io.print".to_owned())] };
+    engine.run(expr).unwrap()
+}
+
+#[test]
+fn test_engine_bindings_get_and_set() {
+    let (mut engine, _) = engine_with_io();
+    engine.define("answer", Type::Number, Value::Number(42));
+    engine.define("motto", Type::String, Value::String("x".repeat(100)));
+    let bindings: Vec<_> = engine.bindings().map(|(name, typ, summary)| (name.to_owned(), typ.clone(), summary)).collect();
+    // the bindings are listed in order of name
+    assert!(bindings.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    let binding = |name: &str| bindings.iter().find(|(found, ..)| found == name).unwrap_or_else(|| panic!("{} isn't listed", name));
+    assert_eq!(binding("answer"), &("answer".to_owned(), Type::Number, "42".to_owned()));
+    assert_eq!(binding("io.print").1, number_to_number());
+    let (_, _, motto) = binding("motto");
+    assert_eq!(motto.chars().count(), SUMMARY_LENGTH + 1);
+    assert!(motto.ends_with('…'));
+
+    assert_eq!(engine.set("answer", Value::Number(43)), Ok(()));
+    assert_eq!(engine.get("answer"), Some((&Type::Number, &Value::Number(43))));
+    assert_eq!(engine.run(Expression::Variable("answer".to_owned())), Ok(Value::Number(43)));
+    assert_eq!(
+        engine.set("answer", Value::Boolean(true)),
+        Err(EngineError::Mistyped { name: "answer".to_owned(), expected: Type::Number, value: Value::Boolean(true) }),
+    );
+    assert_eq!(engine.set("io.print", Value::Number(0)), Err(EngineError::Undefined("io.print".to_owned())));
+    assert_eq!(engine.set("missing", Value::Number(0)), Err(EngineError::Undefined("missing".to_owned())));
+    assert_eq!(engine.get("missing"), None);
+}
+
+#[test]
+fn test_engine_run_interruptible_from_another_thread_or_by_deadline() {
+    let engine = Engine::new();
+    let forever = Expression::Loop { variables: vec![], body: Box::new(Expression::Continue(vec![])) };
+    let token = Arc::new(AtomicBool::new(false));
+    let interrupted = thread::scope(|scope| {
+        let run = scope.spawn(|| engine.run_interruptible(forever.clone(), Interrupt::new(token.clone(), 1000)));
+        thread::sleep(Duration::from_millis(10));
+        token.store(true, Ordering::Relaxed);
+        run.join().unwrap()
+    });
+    assert_eq!(interrupted, Err(EngineError::Eval(EvalError::Interrupted { stack: vec![] })));
+
+    let interrupt = Interrupt { deadline: Some(Instant::now() + Duration::from_millis(10)), ..Interrupt::new(Arc::default(), 1000) };
+    assert_eq!(engine.run_interruptible(forever, interrupt), Err(EngineError::Eval(EvalError::Interrupted { stack: vec![] })));
+}
+
+#[test]
+fn test_engine_snapshot_and_restore() {
+    let (mut engine, _) = engine_with_io();
+    engine.grant("io");
+    engine.define("answer", Type::Number, Value::Number(42));
+    engine.define("print", number_to_number(), print_closure(&engine));
+    let snapshot = engine.snapshot();
+
+    let (mut restored, printed) = engine_with_io();
+    restored.restore(&snapshot).unwrap();
+    assert_eq!(restored.run(call("print", Expression::Variable("answer".to_owned()))), Ok(Value::Number(42)));
+    assert_eq!(*printed.lock().unwrap(), vec![42]);
+}
+
+#[test]
+fn test_engine_restore_without_native() {
+    let (mut engine, _) = engine_with_io();
+    engine.grant("io");
+    engine.define("print", number_to_number(), print_closure(&engine));
+    let snapshot = engine.snapshot();
+
+    assert_eq!(Engine::new().restore(&snapshot), Err(DecodeError::UnknownNative("io.print".to_owned())));
+}
+
+#[test]
+fn test_engine_restore_rejects_other_formats() {
+    let mut engine = Engine::new();
+    assert_eq!(engine.restore(b"nope"), Err(DecodeError::BadMagic { expected: "SGSN".to_owned() }));
+
+    let mut snapshot = Engine::new().snapshot();
+    snapshot[4] = 99;
+    let supported = Version { major: 1, minor: 5 };
+    assert_eq!(engine.restore(&snapshot), Err(DecodeError::UnsupportedVersion { found: Version { major: 99, minor: 5 }, supported }));
+    snapshot[4] = 1;
+    snapshot[6] = 6;
+    assert_eq!(engine.restore(&snapshot), Err(DecodeError::UnsupportedVersion { found: Version { major: 1, minor: 6 }, supported }));
+
+    let snapshot = engine_with_io().0.snapshot();
+    assert_eq!(engine.restore(&snapshot[..snapshot.len() - 1]), Err(DecodeError::UnexpectedEnd(snapshot.len() - 1)));
+}
+
+fn random(low: i64, high: i64) -> Expression {
+    Expression::Application { function: Box::new(Expression::Variable("math.random".to_owned())),
+                              arguments: vec![Expression::Number(low), Expression::Number(high)] }
+}
+
+#[test]
+fn test_engine_random_is_reproducible_when_seeded() {
+    let draws = |engine: &Engine| -> Vec<_> { (0..8).map(|_| engine.run(random(1, 1000)).unwrap()).collect() };
+    let engine = Engine::new();
+    engine.seed(7);
+    let first = draws(&engine);
+    engine.seed(7);
+    assert_eq!(draws(&engine), first);
+
+    let other = Engine::new();
+    other.seed(7);
+    assert_eq!(draws(&other), first);
+    other.seed(8);
+    assert_ne!(draws(&other), first);
+}
+
+#[test]
+fn test_engine_random_stays_in_range() {
+    let engine = Engine::new();
+    for _ in 0..100 {
+        match engine.run(random(-2, 2)) {
+            Ok(Value::Number(n)) => assert!((-2..=2).contains(&n)),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+    assert!(matches!(engine.run(random(i64::MIN, i64::MAX)), Ok(Value::Number(_))));
+    assert_eq!(engine.run(random(3, 3)), Ok(Value::Number(3)));
+    assert!(matches!(engine.run(random(3, 2)), Err(EngineError::Eval(EvalError::NativeFailure { .. }))));
+}
+
+#[test]
+fn test_engine_replays_deterministic_runs() {
+    let (mut engine, printed) = engine_with_io();
+    engine.grant("io");
+    let program = call("io.print", call("io.print", random(1, 1000)));
+    let settings = Deterministic { seed: 7, fuel: 1_000 };
+    let recording = engine.run_deterministic(program.clone(), settings);
+    // the run has its own generator, which the engine's isn't shared with
+    engine.seed(8);
+    assert_eq!(engine.run_deterministic(program.clone(), settings), recording);
+    let Ok(Value::Number(n)) = recording.result else { panic!("unexpected result {:?}", recording.result) };
+    let natives: Vec<_> = recording.trace.iter().map(|effect| effect.native.as_str()).collect();
+    assert_eq!(natives, ["math.random", "io.print", "io.print"]);
+    assert_eq!(recording.trace[0].result, Ok(Value::Number(n)));
+
+    printed.lock().unwrap().clear();
+    assert_eq!(engine.replay(program.clone(), settings, recording.trace.clone()), Ok(Value::Number(n)));
+    assert!(printed.lock().unwrap().is_empty());
+    let diverged = |expected: &str, found: &str| {
+        Err(EngineError::Eval(EvalError::Diverged { expected: expected.to_owned(), found: found.to_owned() }))
+    };
+    assert_eq!(engine.replay(call("io.print", Expression::Number(1)), settings, recording.trace.clone()),
+               diverged("a call of math.random", "a call of io.print"));
+    assert_eq!(engine.replay(call("io.print", random(1, 1000)), settings, recording.trace),
+               diverged("a call of io.print", "the end of the program"));
+
+    let starved = engine.run_deterministic(program, Deterministic { fuel: 2, ..settings });
+    assert_eq!(starved.result, Err(EngineError::Eval(EvalError::OutOfFuel)));
+}
+
+#[test]
+fn test_session_binds_results_and_keeps_definitions() {
+    use session::{Session, SessionError};
+
+    let mut session = Session::new(Engine::new());
+    let values = |entries: Vec<session::Entry>| entries.into_iter().map(|entry| entry.value).collect::<Vec<_>>();
+    assert_eq!(session.eval("local x = 1 + 2").map(values), Ok(vec![]));
+    assert_eq!(session.eval("x * 2").map(values), Ok(vec![Value::Number(6)]));
+    assert_eq!(session.eval("fn f(n: Number): Number\n  return n + x\nend; f(_); _ + _1").map(values),
+               Ok(vec![Value::Number(9), Value::Number(15)]));
+    assert_eq!(session.history().iter().map(|entry| entry.input.as_str()).collect::<Vec<_>>(), ["x * 2", "f(_)", "_ + _1"]);
+    assert_eq!(session.history()[2].typ, Type::Number);
+    assert_eq!(session.definitions(), ["local x = 1 + 2", "fn f(n: Number): Number\n  return n + x\nend"]);
+    // an operator stays declared for the inputs after it
+    assert_eq!(session.eval("operator <-> left 9 (a: Number, b: Number): Number return b - a end").map(values), Ok(vec![]));
+    assert_eq!(session.eval("1 <-> 10 * 2").map(values), Ok(vec![Value::Number(19)]));
+
+    // a failed input defines nothing
+    assert!(matches!(session.eval("local y = x + true"), Err(SessionError::Engine(EngineError::Type(_)))));
+    assert!(matches!(session.eval("y"), Err(SessionError::Engine(_))));
+    assert_eq!(session.definitions().len(), 3);
+
+    assert!(session.eval("fn g(n: Number): Number").unwrap_err().is_incomplete());
+    assert!(!session.eval("1 +* 2").unwrap_err().is_incomplete());
+}
+
+#[test]
+fn test_engine_failures_are_located_at_their_calls() {
+    use session::{Session, SessionError};
+
+    let mut session = Session::new(Engine::new());
+    let failure = |result: Result<Vec<session::Entry>, SessionError>| match result {
+        Err(SessionError::Engine(EngineError::Eval(error))) => error.to_string(),
+        result => panic!("expected a failure, got {:?}", result.map(|entries| entries.len())),
+    };
+    assert_eq!(session.eval("assert(1 < 2, \"one is less\")").map(|entries| entries[0].value.clone()), Ok(Value::Tuple(vec![])));
+    assert_eq!(failure(session.eval("local x = 1\nassert(1 < x)")), "assertion failed at 2:1");
+    assert_eq!(failure(session.eval("fn f(n: Number): Number\n  assert(0 < n, \"a positive number\")\n  return n\nend\nf(2) + f(0)")),
+               "assertion failed: a positive number at 2:3");
+    assert_eq!(failure(session.eval("local y = 1 +\n  error(\"bad\")")), "error: \"bad\" at 2:3");
+    assert!(matches!(session.eval("assert(1)"), Err(SessionError::Engine(EngineError::Type(_)))));
+}
+
+#[test]
+fn test_engine_tasks_run_concurrently_and_pass_values() {
+    use session::{Session, SessionError};
+
+    // a session's globals can't have the abstract types of the `task` module, so its tasks and
+    // channels are local to a function
+    let program = "fn main(): Number\n\
+                   \x20 local c = task.channel<Number>()\n\
+                   \x20 fn produce(): Number\n    task.send<Number>(c, 1)\n    task.send<Number>(c, 2)\n    return 40\n  end\n\
+                   \x20 local t = task.spawn<Number>(produce)\n\
+                   \x20 return task.receive<Number>(c) + task.receive<Number>(c) + task.join<Number>(t)\n\
+                   end\n\
+                   main()";
+    assert!(matches!(Session::new(Engine::new()).eval(program),
+                     Err(SessionError::Engine(EngineError::CapabilityDenied { capability, .. })) if capability == "task"));
+
+    let mut engine = Engine::new();
+    engine.grant("task");
+    let mut session = Session::new(engine);
+    assert_eq!(session.eval(program).map(|entries| entries.last().unwrap().value.clone()), Ok(Value::Number(43)));
+
+    // a closure stays in the task that made it, and a task's result is typed by what it returns
+    let closure = "fn leak(): ()\n  return task.send<() -> Number>(task.channel<() -> Number>(), fn() 1 end)\nend\nleak()";
+    assert!(matches!(session.eval(closure),
+                     Err(SessionError::Engine(EngineError::Eval(EvalError::NativeFailure { name, .. }))) if name == "task.send"));
+    assert!(matches!(session.eval("task.join<Boolean>(task.spawn<Number>(fn() 1 end))"), Err(SessionError::Engine(EngineError::Type(_)))));
+}
+
+#[test]
+fn test_engine_os_gives_arguments_and_environment() {
+    let args = Expression::Application { function: Box::new(Expression::Variable("os.args".to_owned())), arguments: vec![] };
+    let env = |name: &str| call("os.env", Expression::String(name.to_owned()));
+    let mut engine = Engine::new();
+    assert_eq!(engine.run(args.clone()), Err(EngineError::CapabilityDenied { name: "os.args".to_owned(), capability: "os".to_owned() }));
+
+    engine.grant("os");
+    assert_eq!(engine.run(args.clone()), Ok(Value::Tuple(vec![])));
+    engine.set_arguments(vec!["one".to_owned(), "two three".to_owned()]);
+    assert_eq!(engine.run_typed(args), Ok((Value::Tuple(vec![Value::String("one".to_owned()), Value::String("two three".to_owned())]),
+                                           Type::Rest(Box::new(Type::String)))));
+
+    let variable = |value: Option<String>| match value {
+        Some(value) => Value::Variant { tag: "Some".to_owned(), payload: Box::new(Value::String(value)) },
+        None => Value::Variant { tag: "None".to_owned(), payload: Box::new(Value::Tuple(vec![])) },
+    };
+    assert_eq!(engine.run(env("CARGO_MANIFEST_DIR")), Ok(variable(std::env::var("CARGO_MANIFEST_DIR").ok())));
+    assert_eq!(engine.run(env("SANGUINELLO=UNSET")), Ok(variable(None)));
+    assert_eq!(engine.run(env("")), Ok(variable(None)));
+
+    // a program takes the variant apart with a match
+    let program = "match os.env(\"SANGUINELLO=UNSET\")\ncase Some(value) then\n  value\ncase None() then\n  \"unset\"\nend";
+    let mut session = session::Session::new(engine);
+    assert_eq!(session.eval(program).map(|entries| entries[0].value.clone()), Ok(Value::String("unset".to_owned())));
+    assert!(matches!(session.eval("match os.env(\"HOME\") case Some(value) then value end"),
+                     Err(session::SessionError::Engine(EngineError::Type(_)))));
+}
+
+#[test]
+fn test_engine_fs_is_confined_to_its_root() {
+    let root = std::env::temp_dir().join(format!("sanguinello-fs-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("notes")).unwrap();
+    let string = |value: &str| Expression::String(value.to_owned());
+    let outcome = |tag: &str, payload| Value::Variant { tag: tag.to_owned(), payload: Box::new(payload) };
+    let error = |result: Result<Value, EngineError>| match result {
+        Ok(Value::Variant { tag, payload }) if tag == "Error" => match *payload {
+            Value::String(message) => message,
+            payload => panic!("unexpected message {:?}", payload),
+        },
+        other => panic!("unexpected result {:?}", other),
+    };
+    let write = |path: &str, contents: &str| Expression::Application { function: Box::new(Expression::Variable("fs.write_file".to_owned())),
+                                                                       arguments: vec![string(path), string(contents)] };
+
+    let mut engine = Engine::new();
+    engine.set_root(&root);
+    assert_eq!(engine.run(call("fs.read_file", string("a.txt"))),
+               Err(EngineError::CapabilityDenied { name: "fs.read_file".to_owned(), capability: "fs".to_owned() }));
+
+    engine.grant("fs");
+    assert_eq!(engine.run(write("notes/a.txt", "hello")), Ok(outcome("Ok", Value::Tuple(vec![]))));
+    assert_eq!(engine.run(write("b.txt", "")), Ok(outcome("Ok", Value::Tuple(vec![]))));
+    assert_eq!(engine.run(call("fs.read_file", string("notes/a.txt"))), Ok(outcome("Ok", Value::String("hello".to_owned()))));
+    assert_eq!(engine.run(call("fs.list_dir", string("."))),
+               Ok(outcome("Ok", Value::Tuple(vec![Value::String("b.txt".to_owned()), Value::String("notes".to_owned())]))));
+
+    // what goes wrong is the program's to handle, and nothing outside of the root can be reached
+    assert!(error(engine.run(call("fs.read_file", string("missing.txt")))).starts_with("missing.txt: "));
+    assert!(error(engine.run(call("fs.list_dir", string("b.txt")))).starts_with("b.txt: "));
+    assert!(error(engine.run(call("fs.read_file", string("../outside.txt")))).contains("without `..`"));
+    assert!(error(engine.run(write("/tmp/outside.txt", ""))).contains("without `..`"));
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(std::env::temp_dir(), root.join("escape")).unwrap();
+        assert!(error(engine.run(call("fs.list_dir", string("escape")))).contains("outside"));
+        assert!(error(engine.run(write("escape/outside.txt", ""))).contains("outside"));
+        // writing through a link to nothing would create its target, wherever that is
+        let outside = std::env::temp_dir().join(format!("sanguinello-fs-{}-outside.txt", std::process::id()));
+        std::os::unix::fs::symlink(&outside, root.join("dangling")).unwrap();
+        assert!(error(engine.run(write("dangling", "escaped"))).contains("symbolic link"));
+        assert!(!outside.exists());
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// a clock that only moves when it's told to, a second for each tick it's given
+#[derive(Default)]
+struct ManualClock(Arc<AtomicU64>);
+
+impl time::Clock for ManualClock {
+    fn monotonic(&self) -> Duration {
+        Duration::from_secs(self.0.load(Ordering::SeqCst))
+    }
+
+    fn wall_clock(&self) -> Duration {
+        Duration::new(1_700_000_000 + self.0.load(Ordering::SeqCst), 500)
+    }
+}
+
+#[test]
+fn test_engine_time_reads_its_clock() {
+    let read = |name: &str| Expression::Application { function: Box::new(Expression::Variable(name.to_owned())), arguments: vec![] };
+    let duration = |seconds, nanoseconds| Value::Record(Arc::new(vec![("seconds".to_owned(), Value::Number(seconds)),
+                                                                      ("nanoseconds".to_owned(), Value::Number(nanoseconds))]));
+    let mut engine = Engine::new();
+    assert_eq!(engine.run(read("time.monotonic")),
+               Err(EngineError::CapabilityDenied { name: "time.monotonic".to_owned(), capability: "time".to_owned() }));
+
+    engine.grant("time");
+    assert!(matches!(engine.run_typed(read("time.wall_clock")), Ok((Value::Record(_), typ)) if typ == time::duration()));
+
+    let clock = ManualClock::default();
+    let ticks = clock.0.clone();
+    engine.set_clock(clock);
+    assert_eq!(engine.run(read("time.monotonic")), Ok(duration(0, 0)));
+    ticks.fetch_add(3, Ordering::SeqCst);
+    assert_eq!(engine.run(read("time.monotonic")), Ok(duration(3, 0)));
+    assert_eq!(engine.run(read("time.wall_clock")), Ok(duration(1_700_000_003, 500)));
+}
+
+#[test]
+fn test_engine_json_encodes_and_decodes_values() {
+    let outcome = |tag: &str, payload| Value::Variant { tag: tag.to_owned(), payload: Box::new(payload) };
+    let string = |value: &str| Value::String(value.to_owned());
+    let record = |fields: Vec<(&str, Value)>| Value::Record(Arc::new(fields.into_iter().map(|(field, value)| (field.to_owned(), value)).collect()));
+    let engine = Engine::new();
+    let decode = |text: &str| engine.run(call("json.decode", Expression::String(text.to_owned())));
+
+    let program = Expression::Record(vec![("name".to_owned(), Expression::String("a\"b".to_owned())),
+                                          ("scores".to_owned(), Expression::Tuple(vec![Expression::Number(1), Expression::Float(sgir::numbers::Float(2.5))])),
+                                          ("nothing".to_owned(), Expression::Tuple(vec![])),
+                                          ("done".to_owned(), Expression::Boolean(false))]);
+    let text = "{\"name\":\"a\\\"b\",\"scores\":[1,2.5],\"nothing\":null,\"done\":false}";
+    assert_eq!(engine.run(call("json.encode", program)), Ok(outcome("Ok", string(text))));
+    assert_eq!(decode(text), Ok(outcome("Ok", record(vec![("name", string("a\"b")), ("scores", Value::Tuple(vec![Value::Number(1), Value::Float(2.5)])),
+                                                        ("nothing", Value::Tuple(vec![])), ("done", Value::Boolean(false))]))));
+    let some = Expression::Variant { tag: "Some".to_owned(), payload: Box::new(Expression::Number(1)) };
+    assert_eq!(engine.run(call("json.encode", some)),
+               Ok(outcome("Error", string("the variant Some can't be encoded as JSON"))));
+
+    // what's decoded could be anything, and a failure is at the byte where the text went wrong
+    let typ = engine.check(&call("json.decode", Expression::String("1".to_owned()))).unwrap();
+    assert!(sgir::is_subtype(&Type::Variant(vec![("Ok".to_owned(), Type::Intersection(vec![]))]), &typ));
+    assert_eq!(decode("[1, 2,]"), Ok(outcome("Error", record(vec![("message", string("expected a value, found `]`")), ("offset", Value::Number(6))]))));
+}
+
+#[test]
+fn test_engine_golden_traces() {
+    let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let failures = golden::compare(&directory, std::env::var_os("SANGUINELLO_BLESS").is_some());
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
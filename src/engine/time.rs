@@ -0,0 +1,73 @@
+//! the `time` module, which lets a script read the time: `time.monotonic()`, for measuring how
+//! long something takes, and `time.wall_clock()`, the time of day. both produce a `Duration`,
+//! `{seconds: Number, nanoseconds: Number}`, the seconds since some starting point and the
+//! nanoseconds since the last of them. the engine reads them from its clock, which a host can
+//! replace, e.g. with one that only moves when a test says so.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::sgir::{EvalError, Type, Value};
+
+/// where an engine reads the time from
+pub trait Clock: Send + Sync {
+    /// the time since some fixed point, e.g. when the clock was made. it never goes backward.
+    fn monotonic(&self) -> Duration;
+
+    /// the time since the unix epoch, which can jump either way when the system's clock is set
+    fn wall_clock(&self) -> Duration;
+}
+
+/// the system's clocks, with monotonic readings since it was made
+#[derive(Clone, Copy, Debug)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn monotonic(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// a system clock set before the epoch reads as the epoch
+    fn wall_clock(&self) -> Duration {
+        SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default()
+    }
+}
+
+/// the clock an engine and the natives of its `time` module share, which is the system's until
+/// the host replaces it
+#[derive(Clone)]
+pub(super) struct Shared(pub(super) Arc<Mutex<Arc<dyn Clock>>>);
+
+impl Default for Shared {
+    fn default() -> Shared {
+        Shared(Arc::new(Mutex::new(Arc::new(SystemClock::default()))))
+    }
+}
+
+/// `{seconds: Number, nanoseconds: Number}`, the type of a duration
+pub(super) fn duration() -> Type {
+    Type::Record(vec![("seconds".to_owned(), Type::Number), ("nanoseconds".to_owned(), Type::Number)])
+}
+
+/// `duration` as a value of `duration()`. one too long for a number has as many seconds as one can.
+fn value(duration: Duration) -> Value {
+    let seconds = i64::try_from(duration.as_secs()).unwrap_or(i64::MAX);
+    Value::Record(Arc::new(vec![("seconds".to_owned(), Value::Number(seconds)),
+                                ("nanoseconds".to_owned(), Value::Number(duration.subsec_nanos().into()))]))
+}
+
+/// a native of the module that reads `clock` with `read`
+pub(super) fn native(clock: Shared, read: fn(&dyn Clock) -> Duration) -> impl Fn(Vec<Value>) -> Result<Value, EvalError> + Send + Sync + 'static {
+    move |_| {
+        let clock = clock.0.lock().unwrap().clone();
+        Ok(value(read(&*clock)))
+    }
+}
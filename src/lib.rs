@@ -0,0 +1,20 @@
+//! without the `std` feature, only `sgir` is built, which needs nothing but an allocator, so that
+//! programs can be checked and run inside wasm32-unknown-unknown or embedded hosts
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(not(any(feature = "std", feature = "hashbrown")))]
+compile_error!("sanguinello needs either the `std` feature or, without it, the `hashbrown` feature");
+
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod collections;
+#[cfg(feature = "std")]
+pub mod engine;
+#[cfg(feature = "playground")]
+pub mod playground;
+pub(crate) mod prelude;
+pub mod sgir;
+#[cfg(feature = "std")]
+pub mod syntax;
@@ -1,10 +1,746 @@
-use crate::sgir::Binding;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::process::{exit, Command, Stdio};
+use std::sync::Mutex;
 
-mod sgir;
+use sanguinello::engine::session::Session;
+use sanguinello::engine::{self, Engine};
+use sanguinello::sgir::{self, anf, constants, contracts, coverage, cse, diff, dump, escape, hash, interface, partial, program, resolve, step, target, typed, usage, Binding, Declarations, Expression, Type, Value};
+use sanguinello::sgir::bench::{self, Evaluator};
+use sanguinello::sgir::binary::{DecodeError, Format};
+use sanguinello::sgir::operators::Operators;
+use sanguinello::sgir::{Position, Span};
+use sanguinello::syntax::literal::Literal;
+use sanguinello::syntax::ast::{self, Block};
+use sanguinello::syntax::{completion, declarations, doc, json, lint, lower, manifest, parser, reduce, rename};
+
+/// the applications the partial evaluator may unfold in a whole program
+const PARTIAL_EVAL_BUDGET: usize = 1_000;
+
+/// the evaluation steps each test may take, so that one that diverges fails instead of hanging
+const TEST_FUEL: usize = 10_000_000;
+
+const USAGE: &str = "usage: sanguinello [-O<pass>...] [--emit-diff=<pass>] [--debug-escape] [--print-call-graph] [--no-contracts] [--emit=ast-json] [--emit=hashes] [--emit=sgir [--filter=<function>]] [--emit=trace] [--compat=luau] [--target=<target>] [--trace-steps] <file>\n       sanguinello run [--coverage[=<lcov file>]] <file> [-- <argument>...]\n       sanguinello build <file>\n       sanguinello doc <file>\n       sanguinello test [--coverage[=<lcov file>]] [<path>...]\n       sanguinello migrate <file>...\n       sanguinello bench [--engine=<engine>] [--iterations=<n>] [--save=<file>] [--baseline=<file>]\n       sanguinello --repl\n       sanguinello rename <old> <new> --at <file>:<line>:<column>\n       sanguinello reduce <file> <command> [<argument>...]";
+
+/// run the pass named `name` over `expr`
+fn pass(name: &str, expr: Expression) -> Result<Expression, String> {
+    match name {
+        "constants" => constants::evaluate_constants(expr, constants::FUEL).map_err(|error| error.to_string()),
+        "partial-eval" => Ok(partial::partial_evaluate(expr, HashMap::new(), PARTIAL_EVAL_BUDGET)),
+        "anf" => Ok(anf::normalize(expr)),
+        "cse" => Ok(cse::eliminate_common_subexpressions(expr)),
+        "lift" => Ok(program::lift_lambdas(expr).without_unreachable(&[]).into_expression()),
+        "dead-parameters" => Ok(usage::eliminate_dead_parameters(expr)),
+        "resolve" => resolve::resolve(expr, &Declarations::new()).map_err(|error| error.to_string()),
+        _ => Err(format!("unknown pass {}, expected constants, partial-eval, anf, cse, lift, dead-parameters or resolve", name)),
+    }
+}
+
+/// the manifest of the project the file at `path` is in, or the default settings if it isn't in
+/// one
+fn find_manifest(path: &str) -> Result<manifest::Manifest, String> {
+    // a bare file name's parent is empty, which is the directory it was run from
+    let directory = std::path::Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+    let absolute = std::path::absolute(directory).map_err(|error| format!("{}: {}", path, error))?;
+    for directory in absolute.ancestors() {
+        let candidate = directory.join(manifest::MANIFEST_NAME);
+        if candidate.is_file() {
+            let source = std::fs::read_to_string(&candidate).map_err(|error| format!("{}: {}", candidate.display(), error))?;
+            return manifest::parse(&source).map_err(|error| format!("{}: {}", candidate.display(), error))
+        }
+    }
+    Ok(manifest::Manifest::default())
+}
+
+/// where the last panic in a phase happened, and what it said, which the panic hook keeps in place
+/// of printing it
+static PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// the lines `expr` was lowered from, from the first line of any of it to the last
+fn extent(expr: &Expression) -> Option<Span> {
+    let mut found = match expr {
+        Expression::Located { span, .. } => Some(*span),
+        _ => None,
+    };
+    for span in expr.children().into_iter().filter_map(extent) {
+        found = Some(match found {
+            Some(Span { start, end }) => Span { start: start.min(span.start), end: end.max(span.end) },
+            None => span,
+        });
+    }
+    found
+}
+
+/// run the phase of the compiler `name` over the program in the file at `path`, which has been
+/// lowered to `input` unless the phase is given its source. a panic in the phase is a bug in the
+/// compiler, which is reported as an internal compiler error, with the SGIR it was given written
+/// to a file for reproducing it, rather than as a backtrace.
+fn phase<T>(name: &str, path: &str, input: Option<&Expression>, f: impl FnOnce() -> T) -> Result<T, String> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|info| *PANIC.lock().unwrap() = Some(info.to_string())));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(hook);
+    result.map_err(|payload| internal_error(name, path, input, payload))
+}
+
+fn internal_error(name: &str, path: &str, input: Option<&Expression>, payload: Box<dyn Any + Send>) -> String {
+    let message = PANIC.lock().unwrap().take().unwrap_or_else(|| {
+        payload.downcast_ref::<&str>()
+               .map(|message| message.to_string())
+               .or_else(|| payload.downcast_ref::<String>().cloned())
+               .unwrap_or_else(|| "panicked".to_owned())
+    });
+    let mut report = format!("{}: internal compiler error in {}", path, name);
+    let Some(expr) = input else {
+        return format!("{}, given the source of {}\n{}\nthis is a bug in sanguinello", report, path, message)
+    };
+    if let Some(Span { start, end }) = extent(expr) {
+        report.push_str(&format!(", processing lines {}-{}", start.line, end.line));
+    }
+    let dumped = std::env::temp_dir().join(format!("sanguinello-ice-{}.sgir", std::process::id()));
+    match std::fs::write(&dumped, dump::dump(expr, std::fs::read_to_string(path).ok().as_deref())) {
+        Ok(()) => report.push_str(&format!(", given the SGIR in {}", dumped.display())),
+        Err(error) => report.push_str(&format!(", given SGIR that couldn't be written to {}: {}", dumped.display(), error)),
+    }
+    format!("{}\n{}\nthis is a bug in sanguinello", report, message)
+}
+
+/// the engine programs are run on from the command line: the prelude, and an `io.print` that
+/// prints a value of any type on a line of its own, a string as it is and anything else as a literal
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    let typ = Type::Function { arguments: vec![Type::Intersection(vec![])], result: Box::new(Type::Tuple(vec![])) };
+    engine.register_native("io", "print", typ, |arguments| {
+        match &arguments[..] {
+            [Value::String(line)] => println!("{}", line),
+            [value] => println!("{}", Literal(value)),
+            _ => unreachable!("checked by the type of io.print"),
+        }
+        Ok(Value::Tuple(vec![]))
+    });
+    engine.grant("io");
+    engine
+}
+
+/// parse and lower the program in the file at `path`, which is written in the `compat` language
+/// if there is one, for `target`, producing it as parsed and as lowered. what the lints find is printed to
+/// stderr, at the levels its manifest and its attributes set, and it's an error if any of it is
+/// denied.
+fn load(path: &str, compat: Option<parser::Compat>, target: &str) -> Result<(Block, Expression), String> {
+    let source = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+    let parsed = phase("parsing", path, None, || match compat {
+        Some(compat) => parser::parse_compat(&source, compat),
+        None => parser::parse(&source),
+    })?;
+    if let Some(error) = parsed.errors.first() {
+        return Err(format!("{}: {}", path, error))
+    }
+    let levels = find_manifest(path)?.lints;
+    let warnings = phase("linting", path, None, || lint::lint(&source, &parsed.program, &levels))?.map_err(|error| format!("{}: {}", path, error))?;
+    for warning in &warnings {
+        eprintln!("{}: {}", path, warning);
+    }
+    if warnings.iter().any(|warning| warning.level == lint::Level::Deny) {
+        return Err(format!("{}: not compiled, because of the lint errors above", path))
+    }
+    let lowered = phase("lowering", path, None, || lower::lower_block_for(&parsed.program, &Operators::default(), target))?.map_err(|error| format!("{}: {}", path, error))?;
+    Ok((parsed.program, lowered))
+}
+
+/// `sanguinello -O<pass>... <file>` checks the program against the engine it's run on, and then
+/// runs it after the pipeline of passes, in order.
+/// with `--emit-diff=<pass>`, it prints what that pass changes in the program as a structural
+/// diff instead, running it last if it isn't in the pipeline. `--debug-escape` prints which
+/// allocations escape, after the pipeline, to stderr. `--print-call-graph` prints the call graph
+/// of the program, with its functions lifted, as DOT instead of running it. `--no-contracts`
+/// leaves out the checks of the `requires` and `ensures` clauses of functions, for a release build.
+/// `--emit=ast-json` prints the program as parsed, as JSON, instead of compiling it.
+/// `--emit=hashes` prints the structural hash of each function of the program, with its lambdas
+/// lifted, and of its main expression, after the pipeline, to check that builds are reproducible.
+/// `--emit=sgir` prints the program after the pipeline as SGIR, with comments about the lines of
+/// the source each part came from, and `--filter=<function>` narrows it to one definition.
+/// `--emit=trace` runs the program and prints its trace, the calls of natives it made and then
+/// its value, as the golden tests compare it, e.g. to record a new golden trace.
+/// `--compat=luau` reads the program as Luau, translating what it can, to help port it.
+/// `--target=<target>` compiles it for another target than the native one, keeping the branches
+/// of its `@if target == "..."` conditionals for that target instead.
+/// `--trace-steps` evaluates the program one reduction at a time, printing each term on the way
+/// with its redex highlighted, instead of just its value.
+fn compile(arguments: &[String]) -> Result<(), String> {
+    let (mut pipeline, mut emit_diff, mut emit_ast, mut path, mut compat, mut target) = (vec![], None, false, None, None, target::NATIVE);
+    let (mut debug_escape, mut print_call_graph, mut no_contracts, mut emit_hashes) = (false, false, false, false);
+    let (mut emit_sgir, mut filter, mut trace_steps, mut emit_trace) = (false, None, false, false);
+    for argument in arguments {
+        if argument == "--debug-escape" {
+            debug_escape = true;
+        } else if argument == "--print-call-graph" {
+            print_call_graph = true;
+        } else if argument == "--no-contracts" {
+            no_contracts = true;
+        } else if let Some(name) = argument.strip_prefix("-O") {
+            pipeline.push(name.to_owned());
+        } else if argument == "--emit=ast-json" {
+            emit_ast = true;
+        } else if argument == "--emit=hashes" {
+            emit_hashes = true;
+        } else if argument == "--emit=sgir" {
+            emit_sgir = true;
+        } else if argument == "--emit=trace" {
+            emit_trace = true;
+        } else if argument == "--trace-steps" {
+            trace_steps = true;
+        } else if let Some(name) = argument.strip_prefix("--filter=") {
+            filter = Some(name);
+        } else if let Some(name) = argument.strip_prefix("--emit-diff=") {
+            emit_diff = Some(name.to_owned());
+        } else if let Some(language) = argument.strip_prefix("--compat=") {
+            compat = match language {
+                "luau" => Some(parser::Compat::Luau),
+                _ => return Err(format!("unknown compat mode {}, expected luau", language)),
+            };
+        } else if let Some(name) = argument.strip_prefix("--target=") {
+            target = name;
+        } else if argument.starts_with('-') || path.is_some() {
+            return Err(USAGE.to_owned())
+        } else {
+            path = Some(argument);
+        }
+    }
+    let path = path.ok_or_else(|| USAGE.to_owned())?;
+    if filter.is_some() && !emit_sgir {
+        return Err(USAGE.to_owned())
+    }
+    if emit_ast {
+        let source = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        println!("{}", json::dump(&source));
+        return Ok(())
+    }
+    if let Some(name) = emit_diff.as_ref().filter(|name| !pipeline.contains(name)) {
+        pipeline.push(name.clone());
+    }
+
+    let (_, mut expr) = load(path, compat, target)?;
+    let mut engine = engine();
+    engine.set_target(target);
+    phase("checking", path, Some(&expr), || engine.check(&expr))?.map_err(|error| format!("{}: {}", path, error))?;
+    if no_contracts {
+        expr = contracts::erase(expr);
+    }
+    for name in &pipeline {
+        let after = phase(&format!("the {} pass", name), path, Some(&expr), || pass(name, expr.clone()))??;
+        if emit_diff.as_ref() == Some(name) {
+            println!("{}", diff::diff(&expr, &after));
+            return Ok(())
+        }
+        expr = after;
+    }
+    if debug_escape {
+        eprintln!("{}", escape::analyze(&anf::normalize(expr.clone())));
+    }
+    if print_call_graph {
+        println!("{}", program::lift_lambdas(expr).call_graph());
+        return Ok(())
+    }
+    if emit_sgir {
+        let source = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let dumped = match filter {
+            Some(name) => dump::definition(&expr, name).ok_or_else(|| format!("{}: no definition named {}", path, name))?,
+            None => &expr,
+        };
+        print!("{}", dump::dump(dumped, Some(&source)));
+        return Ok(())
+    }
+    if emit_hashes {
+        let program = program::lift_lambdas(expr);
+        for declaration in &program.declarations {
+            let function = Expression::Function { parameters: declaration.parameters.clone(), body: Box::new(declaration.body.clone()) };
+            println!("{} {}", hash::hash_expression(&function), declaration.name);
+        }
+        println!("{} main", hash::hash_expression(&program.main));
+        return Ok(())
+    }
+    if emit_trace {
+        print!("{}", engine::golden::trace(&engine::golden::engine(), expr));
+        return Ok(())
+    }
+    if trace_steps {
+        return trace(expr)
+    }
+    let value = phase("running", path, Some(&expr), || engine.run(expr.clone()))?;
+    println!("{}", value.map_err(|error| format!("{}: {}", path, error))?);
+    Ok(())
+}
+
+/// `sanguinello run file -- argument...` runs the program in the file on the same engine as
+/// `sanguinello file`, with the `os`, `fs`, and `time` modules granted too, so that it can read
+/// the arguments after `--` as `os.args()` and the environment it was run in as `os.env(name)`,
+/// the files under the directory it was run from, and the time. with `--coverage`, it reports
+/// which of the program's expressions it never executed, as `coverage` does.
+fn run(arguments: &[String]) -> Result<(), String> {
+    let (coverage, arguments) = match arguments {
+        [flag, rest @ ..] if coverage_flag(flag).is_some() => (coverage_flag(flag), rest),
+        _ => (None, arguments),
+    };
+    let (path, forwarded) = match arguments {
+        [path] => (path, &[][..]),
+        [path, separator, forwarded @ ..] if separator == "--" => (path, forwarded),
+        _ => return Err(USAGE.to_owned()),
+    };
+    let (_, expr) = load(path, None, target::NATIVE)?;
+    let mut engine = engine();
+    engine.set_arguments(forwarded.to_vec());
+    engine.grant("os");
+    engine.grant("fs");
+    engine.grant("time");
+    phase("checking", path, Some(&expr), || engine.check(&expr))?.map_err(|error| format!("{}: {}", path, error))?;
+    let value = match coverage {
+        Some(destination) => {
+            let (value, report) = phase("running", path, Some(&expr), || engine.run_with_coverage(expr.clone(), None))?;
+            write_coverage(destination.as_deref(), &BTreeMap::from([(path.clone(), report)]))?;
+            value
+        }
+        None => phase("running", path, Some(&expr), || engine.run(expr.clone()))?,
+    };
+    println!("{}", value.map_err(|error| format!("{}: {}", path, error))?);
+    Ok(())
+}
+
+/// what `--coverage` asks for, if `argument` is it: the lcov file to write the report to, or
+/// `None` to print it as text to stderr
+fn coverage_flag(argument: &str) -> Option<Option<String>> {
+    match argument.strip_prefix("--coverage") {
+        Some("") => Some(None),
+        Some(path) => path.strip_prefix('=').map(|path| Some(path.to_owned())),
+        None => None,
+    }
+}
+
+/// report the coverage of each file in `reports`, as lcov's tracefile format into the file at
+/// `destination` if there is one, or else as text to stderr
+fn write_coverage(destination: Option<&str>, reports: &BTreeMap<String, coverage::CoverageReport>) -> Result<(), String> {
+    match destination {
+        Some(destination) => {
+            let lcov = reports.iter().map(|(path, report)| report.to_lcov(path)).collect::<String>();
+            std::fs::write(destination, lcov).map_err(|error| format!("{}: {}", destination, error))
+        }
+        None => {
+            for (path, report) in reports {
+                eprintln!("{}", report.to_text(path));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// print each term evaluating `expr` steps through, with the redex its next step contracts
+/// between `⟦` and `⟧`, and then its value. the steps that only leave a location behind aren't
+/// printed, since the term looks the same after them.
+fn trace(mut expr: Expression) -> Result<(), String> {
+    let mut steps = 0;
+    while let Some(redex) = step::redex(&expr) {
+        if !matches!(redex, Expression::Located { .. } | Expression::Expanded { .. }) {
+            steps += 1;
+            println!("-- step {}", steps);
+            print!("{}", dump::dump_redex(&expr, redex));
+        }
+        expr = match step::step(expr).map_err(|error| error.to_string())? {
+            step::Step::Next(next) => next,
+            step::Step::Value(value) => value,
+        };
+    }
+    println!("-- value");
+    print!("{}", dump::dump(&expr, None));
+    Ok(())
+}
+
+/// `sanguinello build file` checks the module in the file and writes its interface next to it, as
+/// `file.sangi`. the modules it refers to, as `module.name`, are checked against the interfaces
+/// next to it rather than loaded, so they must have been built first, and it can only refer to
+/// what they export. a module without an interface, like one the host provides, can be declared
+/// by a declaration file next to it instead, `module.sangd`.
+fn build(arguments: &[String]) -> Result<(), String> {
+    let [path] = arguments else { return Err(USAGE.to_owned()) };
+    let (program, expr) = load(path, None, target::NATIVE)?;
+    let directory = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new(""));
+    let mut modules: Vec<_> = expr.free_variables()
+                                  .into_iter()
+                                  .filter_map(|name| name.split_once('.').map(|(module, _)| module.to_owned()))
+                                  .collect();
+    modules.sort();
+    modules.dedup();
+    let (mut declarations, mut private) = (Declarations::new(), HashSet::new());
+    for module in modules {
+        let candidate = directory.join(format!("{}.{}", module, interface::EXTENSION));
+        let Ok(bytes) = std::fs::read(&candidate) else {
+            // a module with neither is left for the resolver to report what it's missing
+            let declared = directory.join(format!("{}.{}", module, declarations::EXTENSION));
+            let Ok(source) = std::fs::read_to_string(&declared) else { continue };
+            let declared = declarations::declarations(&source).map_err(|error| format!("{}: {}", declared.display(), error))?;
+            declarations.extend(declared.into_iter().map(|(name, typ)| (format!("{}.{}", module, name), typ)));
+            continue
+        };
+        let interface = interface::Interface::decode(&bytes).map_err(|error| format!("{}: {}", candidate.display(), error))?;
+        declarations.extend(interface.declarations(&module));
+        private.extend(interface.private(&module));
+    }
+    resolve::resolve_module(expr.clone(), &declarations, &private).map_err(|error| format!("{}: {}", path, error))?;
+    let typed = phase("checking", path, Some(&expr), || typed::elaborate(&declarations, expr.clone()))?.map_err(|error| format!("{}: {}", path, error))?;
+    let output = std::path::Path::new(path).with_extension(interface::EXTENSION);
+    std::fs::write(&output, interface::Interface::of(&typed, &ast::exports(&program)).encode()).map_err(|error| format!("{}: {}", output.display(), error))
+}
+
+/// `sanguinello doc file` prints the API listing of the program in the file as Markdown: each of
+/// its top-level declarations, or each it exports if it's a module, with its type and its `---`
+/// doc comment
+fn document(arguments: &[String]) -> Result<(), String> {
+    let [path] = arguments else { return Err(USAGE.to_owned()) };
+    let source = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+    let parsed = parser::parse(&source);
+    if let Some(error) = parsed.errors.first() {
+        return Err(format!("{}: {}", path, error))
+    }
+    let globals = engine().bindings().map(|(name, typ, _)| (name.to_owned(), Some(typ.clone()))).collect::<Vec<_>>();
+    let title = std::path::Path::new(path).file_stem().map_or(path.clone(), |stem| stem.to_string_lossy().into_owned());
+    print!("{}", sgir::doc::render_markdown(&title, &doc::items(&parsed.program, &globals)));
+    Ok(())
+}
+
+/// `sanguinello test path...` runs the `test "name" ... end` blocks of each file under the paths
+/// given, or under the project the directory it was run from is in if there are none. each test
+/// is checked and run on its own, on the same engine as `sanguinello file`, after the statements of
+/// its file before it, and passes if its block runs to the end. a failed assertion is reported
+/// with where it is. with `--coverage`, it reports which expressions of the files with tests none
+/// of them executed, as `run` does.
+fn test(arguments: &[String]) -> Result<(), String> {
+    let (coverage, arguments) = match arguments {
+        [flag, rest @ ..] if coverage_flag(flag).is_some() => (coverage_flag(flag), rest),
+        _ => (None, arguments),
+    };
+    let roots = match arguments {
+        [] => vec![find_project(".")?],
+        paths => paths.iter().map(std::path::PathBuf::from).collect(),
+    };
+    let mut files = vec![];
+    for root in roots {
+        discover(&root, &mut files)?;
+    }
+    files.sort();
+    let engine = engine();
+    let mut cases = vec![];
+    for file in files {
+        let path = file.strip_prefix(".").unwrap_or(&file).to_string_lossy().into_owned();
+        let source = std::fs::read_to_string(&file).map_err(|error| format!("{}: {}", path, error))?;
+        // only the files with tests are loaded, so that a script without any isn't linted too
+        let parsed = parser::parse(&source);
+        if parsed.errors.is_empty() && !parsed.program.iter().any(|node| matches!(node.ast, ast::Ast::Test { .. })) {
+            continue
+        }
+        let (program, _) = load(&path, None, target::NATIVE)?;
+        let tests = lower::lower_tests(&program, &Operators::default()).map_err(|error| format!("{}: {}", path, error))?;
+        for (span, mut case) in tests {
+            engine.check(&case.body).map_err(|error| format!("{}: {}", path, error))?;
+            case.name = format!("{}:{}: {}", path, span.start.line, case.name);
+            cases.push((path.clone(), case));
+        }
+    }
+    let (files, cases): (Vec<_>, Vec<_>) = cases.into_iter().unzip();
+    let mut files = files.into_iter();
+    let mut reports: BTreeMap<_, coverage::CoverageReport> = BTreeMap::new();
+    let report = sgir::testing::run_tests_with(cases, |body| {
+        let file = files.next().expect("a file for each test");
+        let result = match coverage {
+            Some(_) => {
+                let (result, report) = engine.run_with_coverage(body, Some(TEST_FUEL));
+                reports.entry(file).or_default().merge(report);
+                result
+            }
+            None => engine.run_deterministic(body, engine::Deterministic { seed: 0, fuel: TEST_FUEL }).result,
+        };
+        match result {
+            Ok(value) => Ok(value),
+            Err(engine::EngineError::Eval(error)) => Err(error),
+            Err(error) => unreachable!("a test that was checked failed to link: {}", error),
+        }
+    });
+    println!("{}", report);
+    if let Some(destination) = &coverage {
+        write_coverage(destination.as_deref(), &reports)?;
+    }
+    if !report.passed() {
+        return Err(format!("{} of {} tests failed", report.results.iter().filter(|(_, outcome)| *outcome != sgir::testing::Outcome::Passed).count(), report.results.len()))
+    }
+    Ok(())
+}
+
+/// the root of the project the directory `directory` is in, the directory of its manifest, or
+/// `directory` itself if it isn't in one
+fn find_project(directory: &str) -> Result<std::path::PathBuf, String> {
+    let absolute = std::path::absolute(directory).map_err(|error| format!("{}: {}", directory, error))?;
+    Ok(absolute.ancestors()
+               .find(|ancestor| ancestor.join(manifest::MANIFEST_NAME).is_file())
+               .map_or_else(|| std::path::PathBuf::from(directory), std::path::Path::to_path_buf))
+}
+
+/// add the sanguinello files under `path`, or `path` itself if it's a file, to `files`, leaving out
+/// hidden directories and build output in `target`
+fn discover(path: &std::path::Path, files: &mut Vec<std::path::PathBuf>) -> Result<(), String> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(())
+    }
+    let entries = std::fs::read_dir(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    for entry in entries {
+        let entry = entry.map_err(|error| format!("{}: {}", path.display(), error))?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let child = entry.path();
+        if child.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                discover(&child, files)?;
+            }
+        } else if child.extension().is_some_and(|extension| extension == "sg") {
+            files.push(child);
+        }
+    }
+    Ok(())
+}
+
+/// `sanguinello bench` runs the benchmark suite with each engine, or just the one given with
+/// `--engine=interpreter` or `--engine=small-step`, `--iterations` times each, 10 by default, and
+/// prints how long each program took, and how that compares with the interpreter. the results can
+/// be saved as a baseline with `--save=<file>`, and a later run compared with one with
+/// `--baseline=<file>`, e.g. to see what a change to an engine did.
+fn benchmark(arguments: &[String]) -> Result<(), String> {
+    let (mut evaluators, mut iterations, mut save, mut baseline) = (Evaluator::ALL.to_vec(), 10, None, vec![]);
+    for argument in arguments {
+        if let Some(name) = argument.strip_prefix("--engine=") {
+            let evaluator = Evaluator::named(name).ok_or_else(|| format!("unknown engine {}, expected interpreter or small-step", name))?;
+            evaluators = vec![evaluator];
+        } else if let Some(count) = argument.strip_prefix("--iterations=") {
+            iterations = count.parse().ok().filter(|count| *count > 0).ok_or_else(|| USAGE.to_owned())?;
+        } else if let Some(path) = argument.strip_prefix("--save=") {
+            save = Some(path);
+        } else if let Some(path) = argument.strip_prefix("--baseline=") {
+            let saved = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+            baseline = bench::load(&saved).map_err(|error| format!("{}: {}", path, error))?;
+        } else {
+            return Err(USAGE.to_owned())
+        }
+    }
+    let mut measurements = vec![];
+    for (name, program) in bench::suite() {
+        let mut interpreter = None;
+        for &evaluator in &evaluators {
+            let label = format!("{} ({})", name, evaluator.name());
+            let measurement = bench::measure_with(evaluator, &label, &program, iterations).map_err(|error| format!("{}: {}", label, error))?;
+            let mut line = measurement.to_string();
+            if let Some(interpreter) = &interpreter {
+                line.push_str(&format!(", {:.2}x the interpreter", measurement.relative_to(interpreter)));
+            }
+            if let Some(before) = baseline.iter().find(|before| before.name == measurement.name) {
+                line.push_str(&format!(", {}", measurement.delta(before)));
+            }
+            println!("{}", line);
+            if evaluator == Evaluator::Interpreter {
+                interpreter = Some(measurement.clone());
+            }
+            measurements.push(measurement);
+        }
+    }
+    if let Some(path) = save {
+        std::fs::write(path, bench::save(&measurements)).map_err(|error| format!("{}: {}", path, error))?;
+    }
+    Ok(())
+}
+
+/// the formats of the binary files `migrate` upgrades
+const FORMATS: [(&str, Format); 2] = [("snapshot", engine::SNAPSHOT), ("interface", interface::FORMAT)];
+
+/// `sanguinello migrate file...` upgrades each file, a snapshot or an interface, to the current
+/// major version of its format, in place, and prints the version it was upgraded from
+fn migrate(paths: &[String]) -> Result<(), String> {
+    if paths.is_empty() {
+        return Err(USAGE.to_owned())
+    }
+    for path in paths {
+        let bytes = std::fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+        let Some((name, format, found)) = FORMATS.iter().find_map(|(name, format)| Some((name, format, format.version_of(&bytes).ok()?))) else {
+            return Err(format!("{}: {}", path, DecodeError::BadMagic { expected: "snapshot or interface".to_owned() }))
+        };
+        if found.major == format.version.major && found <= format.version {
+            println!("{}: version {} of the {} format, which is read as it is", path, found, name);
+            continue
+        }
+        let migrated = format.migrate(&bytes).map_err(|error| format!("{}: {}", path, error))?;
+        std::fs::write(path, migrated).map_err(|error| format!("{}: {}", path, error))?;
+        println!("{}: migrated from version {} to {} of the {} format", path, found, format.version, name);
+    }
+    Ok(())
+}
+
+/// `sanguinello --repl` reads statements from stdin and evaluates each as it's finished, printing
+/// each result as a literal that can be pasted back in. an input can be continued over several
+/// lines. `:history` lists the results so far, each bound to `_n`, `:save <path>` writes the
+/// definitions made so far to a file, and `:complete <input>` lists what could be written after
+/// the start of an input.
+fn repl() -> Result<(), String> {
+    let mut session = Session::new(Engine::new());
+    let mut input = String::new();
+    let prompt = |continued: bool| {
+        print!("{}", if continued { ".. " } else { "> " });
+        io::stdout().flush().map_err(|error| error.to_string())
+    };
+    prompt(false)?;
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|error| error.to_string())?;
+        if input.is_empty() {
+            let command = line.trim();
+            if command == ":quit" {
+                return Ok(())
+            } else if command == ":history" {
+                for (i, entry) in session.history().iter().enumerate() {
+                    println!("_{} = {} : {}    -- {}", i + 1, Literal(&entry.value), entry.typ, entry.input);
+                }
+                prompt(false)?;
+                continue
+            } else if let Some(partial) = command.strip_prefix(":complete") {
+                complete(&session, partial.trim_start());
+                prompt(false)?;
+                continue
+            } else if let Some(path) = command.strip_prefix(":save") {
+                if let Err(error) = session.save(path.trim()) {
+                    eprintln!("{}: {}", path.trim(), error);
+                }
+                prompt(false)?;
+                continue
+            }
+        }
+        input.push_str(&line);
+        input.push('\n');
+        match session.eval(&input) {
+            Err(error) if error.is_incomplete() => {
+                prompt(true)?;
+                continue
+            }
+            Err(error) => eprintln!("{}", error),
+            Ok(entries) => {
+                for entry in entries {
+                    println!("{} : {}", Literal(&entry.value), entry.typ);
+                }
+            }
+        }
+        input.clear();
+        prompt(false)?;
+    }
+    Ok(())
+}
+
+/// print what could be written after `partial`, the start of an input to `session`: the type
+/// expected there and what makes a value of it, when it's known, or else the names in scope
+fn complete(session: &Session, partial: &str) {
+    let definitions = session.definitions().iter().map(|definition| format!("{}\n", definition)).collect::<String>();
+    let source = format!("{}{}", definitions, partial);
+    let position = Position { line: source.matches('\n').count() + 1, column: partial.chars().count() + 1 };
+    let expected = completion::expect(&source, position);
+    let completions = match &expected.typ {
+        Some(typ) => {
+            println!("-- expected {}", typ);
+            expected.completions
+        }
+        None => completion::complete(&source, position),
+    };
+    for completion in completions {
+        match completion.typ {
+            Some(typ) => println!("{} : {}", completion.label, typ),
+            None => println!("{}", completion.label),
+        }
+    }
+}
+
+/// `sanguinello rename old new --at file:line:col` renames the binding of `old` at that position
+/// in the file, along with every use of it, to `new`, rewriting the file
+fn rename(arguments: &[String]) -> Result<(), String> {
+    let [old, new, at, location] = arguments else { return Err(USAGE.to_owned()) };
+    if at != "--at" {
+        return Err(USAGE.to_owned())
+    }
+    let mut parts = location.rsplitn(3, ':');
+    let (Some(column), Some(line), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(USAGE.to_owned())
+    };
+    let (Ok(line), Ok(column)) = (line.parse(), column.parse()) else { return Err(USAGE.to_owned()) };
+    let source = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+    let renamed = rename::rename(&source, Position { line, column }, new).map_err(|error| format!("{}: {}", path, error))?;
+    if &renamed.old != old {
+        return Err(format!("{}: the name at {}:{} is {}, not {}", path, line, column, renamed.old, old))
+    }
+    std::fs::write(path, renamed.source).map_err(|error| format!("{}: {}", path, error))
+}
+
+/// `sanguinello reduce file command arguments...` shrinks the program in the file to a smaller one
+/// the command still succeeds on, e.g. a script that checks that the checker crashes, and prints
+/// it. the command is run with the path of each candidate program after its arguments.
+fn reduce(arguments: &[String]) -> Result<(), String> {
+    let [path, command, arguments @ ..] = arguments else { return Err(USAGE.to_owned()) };
+    let source = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+    let extension = std::path::Path::new(path).extension().and_then(|extension| extension.to_str()).unwrap_or("sg");
+    let candidate = std::env::temp_dir().join(format!("sanguinello-reduce-{}.{}", std::process::id(), extension));
+    let mut failure = None;
+    let mut interesting = |source: &str| {
+        let run = std::fs::write(&candidate, source).and_then(|()| {
+            Command::new(command).args(arguments)
+                                 .arg(&candidate)
+                                 .stdout(Stdio::null())
+                                 .stderr(Stdio::null())
+                                 .status()
+        });
+        match run {
+            Ok(status) => status.success(),
+            Err(error) => {
+                failure.get_or_insert(format!("{}: {}", command, error));
+                false
+            }
+        }
+    };
+    if !interesting(&source) {
+        let _ = std::fs::remove_file(&candidate);
+        return Err(failure.unwrap_or_else(|| format!("{}: {} doesn't succeed on the program to begin with", path, command)))
+    }
+    let reduced = reduce::reduce(&source, interesting);
+    let _ = std::fs::remove_file(&candidate);
+    print!("{}", reduced);
+    Ok(())
+}
 
 fn main() {
+    let arguments = std::env::args().skip(1).collect::<Vec<_>>();
+    let result = match arguments.first().map(String::as_str) {
+        Some("rename") => rename(&arguments[1..]),
+        Some("run") => run(&arguments[1..]),
+        Some("build") => build(&arguments[1..]),
+        Some("doc") => document(&arguments[1..]),
+        Some("test") => test(&arguments[1..]),
+        Some("bench") => benchmark(&arguments[1..]),
+        Some("migrate") => migrate(&arguments[1..]),
+        Some("reduce") => reduce(&arguments[1..]),
+        Some("--repl") if arguments.len() == 1 => repl(),
+        Some(_) => compile(&arguments),
+        None => {
+            example();
+            Ok(())
+        }
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        exit(1);
+    }
+}
+
+/// run a small program built by hand, which is what `sanguinello` does without arguments
+fn example() {
     use sgir::Expression::*;
-    use sgir::Type;
 
     let prog = Application {
         function: Box::new(Function {
@@ -30,3 +766,31 @@ fn main() {
     let result = sgir::run(prog);
     println!("{:?}", result);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_reports_a_panic_as_an_internal_compiler_error() {
+        let path = std::env::temp_dir().join(format!("sanguinello-phase-{}.sg", std::process::id()));
+        std::fs::write(&path, "1\n2\n3\n").unwrap();
+        let path = path.to_str().unwrap();
+        let number = |line, n| Expression::Located { span: Span { start: Position { line, column: 1 }, end: Position { line, column: 2 } },
+                                                    expression: Box::new(Expression::Number(n)) };
+        let expr = Expression::Tuple(vec![number(2, 2), number(3, 3)]);
+
+        assert_eq!(phase("checking", path, Some(&expr), || 1), Ok(1));
+        let report = phase("checking", path, Some(&expr), || -> () { panic!("no rule for {}", "Tuple") }).unwrap_err();
+        let first = report.lines().next().unwrap();
+        assert!(first.starts_with(&format!("{}: internal compiler error in checking, processing lines 2-3, given the SGIR in ", path)), "{}", report);
+        assert!(report.contains("no rule for Tuple") && report.ends_with("\nthis is a bug in sanguinello"), "{}", report);
+        let dumped = first.rsplit("given the SGIR in ").next().unwrap();
+        assert!(std::fs::read_to_string(dumped).unwrap().contains('3'));
+
+        let report = phase("parsing", path, None, || -> () { panic!("unexpected token") }).unwrap_err();
+        assert!(report.starts_with(&format!("{}: internal compiler error in parsing, given the source of {}\n", path, path)), "{}", report);
+        std::fs::remove_file(dumped).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+//! the interface of a browser playground, which wasm-bindgen exports to javascript when the crate
+//! is built for wasm32-unknown-unknown with the `playground` feature:
+//!
+//! ```js
+//! import init, { check, run } from "./sanguinello.js";
+//!
+//! await init();
+//! const { diagnostics, type } = JSON.parse(check(editor.value));
+//! output.textContent = run(editor.value);
+//! ```
+//!
+//! programs can use `math`, and `io.print`, which writes a line to the output. build it with
+//! `cargo rustc --lib --release --target wasm32-unknown-unknown --features playground
+//! --crate-type cdylib`, then generate the javascript with `wasm-bindgen --target web`.
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use wasm_bindgen::prelude::*;
+
+use crate::engine::{Engine, EngineError};
+use crate::sgir::operators::Operators;
+use crate::sgir::{Expression, Span, Type, Value};
+use crate::syntax::json::{self, Json};
+use crate::syntax::lower::lower_block_for;
+use crate::syntax::parser::parse;
+
+#[cfg(test)]
+mod tests;
+
+/// the target whose branches of `@if target == "..."` conditionals the playground runs
+const TARGET: &str = "wasm";
+
+/// an engine for the playground, whose `io.print` writes to `output`
+fn engine(output: Arc<Mutex<String>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_target(TARGET);
+    let typ = Type::Function { arguments: vec![Type::String], result: Box::new(Type::Tuple(vec![])) };
+    engine.register_native("io", "print", typ, move |arguments| match &arguments[..] {
+        [Value::String(line)] => {
+            writeln!(output.lock().unwrap(), "{}", line).unwrap();
+            Ok(Value::Tuple(vec![]))
+        }
+        _ => unreachable!("checked by the type of io.print"),
+    });
+    engine.grant("io");
+    engine
+}
+
+fn diagnostic(message: String, span: Option<Span>) -> Json {
+    Json::Object(vec![("message".to_owned(), Json::String(message)), ("span".to_owned(), span.map_or(Json::Null, json::span))])
+}
+
+/// the program in `source`, lowered, or the errors that kept it from being, each with where it
+/// is, if that's known
+fn load(source: &str) -> Result<Expression, Vec<(String, Option<Span>)>> {
+    let parsed = parse(source);
+    if !parsed.errors.is_empty() {
+        return Err(parsed.errors.iter().map(|error| (error.to_string(), Some(error.span()))).collect())
+    }
+    lower_block_for(&parsed.program, &Operators::default(), TARGET).map_err(|error| vec![(error.to_string(), error.span())])
+}
+
+/// check the program in `source` without running it, producing a JSON object of its
+/// `diagnostics`, each with a `message` and the `span` it's about, if it's known, and its `type`,
+/// or null if it doesn't have one
+#[wasm_bindgen]
+pub fn check(source: &str) -> String {
+    let checked = load(source).and_then(|program| {
+        engine(Arc::default()).check(&program).map_err(|error| {
+            let span = match &error {
+                EngineError::Type(error) => error.provenance().map(|provenance| provenance.span),
+                _ => None,
+            };
+            vec![(error.to_string(), span)]
+        })
+    });
+    let (diagnostics, typ) = match checked {
+        Ok(typ) => (vec![], Json::String(typ.to_string())),
+        Err(errors) => (errors.into_iter().map(|(message, span)| diagnostic(message, span)).collect(), Json::Null),
+    };
+    Json::Object(vec![("diagnostics".to_owned(), Json::Array(diagnostics)), ("type".to_owned(), typ)]).to_string()
+}
+
+/// run the program in `source`, producing what it printed followed by its value, or by the
+/// errors that stopped it
+#[wasm_bindgen]
+pub fn run(source: &str) -> String {
+    let output = Arc::new(Mutex::new(String::new()));
+    let result = load(source).and_then(|program| {
+        engine(output.clone()).run(program).map_err(|error| vec![(error.to_string(), None)])
+    });
+    let mut output = output.lock().unwrap().clone();
+    match result {
+        Ok(value) => writeln!(output, "{}", value).unwrap(),
+        Err(errors) => errors.iter().for_each(|(message, _)| writeln!(output, "error: {}", message).unwrap()),
+    }
+    output
+}
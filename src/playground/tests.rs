@@ -0,0 +1,16 @@
+use super::*;
+
+#[test]
+fn test_playground_checks_and_runs() {
+    assert_eq!(check("local x = 1\nx + 2"), r#"{"diagnostics":[],"type":"Number"}"#);
+    assert_eq!(check("local x = \nlocal y = 2"),
+               concat!(r#"{"diagnostics":[{"message":"expected an expression, found local at 2:1","#,
+                       r#""span":{"start":{"line":2,"column":1},"end":{"line":2,"column":6}}}],"type":null}"#));
+    assert_eq!(check("1 + true"),
+               concat!(r#"{"diagnostics":[{"message":"type mismatch: expected Number, found Boolean at 1:1","#,
+                       r#""span":{"start":{"line":1,"column":1},"end":{"line":1,"column":9}}}],"type":null}"#));
+
+    assert_eq!(run("io.print(\"hello\")\nio.print(\"world\")\n1 + 2"), "hello\nworld\n3\n");
+    assert_eq!(run("io.print(\"before\")\n1 // 0"), "before\nerror: division by zero\n");
+    assert_eq!(run("@if target == \"wasm\" then 1 else 2 end"), "1\n");
+}
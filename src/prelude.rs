@@ -0,0 +1,8 @@
+//! what the standard prelude brings into scope from `alloc`, for the modules that are built
+//! without it
+
+pub use alloc::borrow::ToOwned;
+pub use alloc::boxed::Box;
+pub use alloc::string::{String, ToString};
+pub use alloc::vec::Vec;
+pub use alloc::{format, vec};
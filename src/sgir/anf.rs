@@ -0,0 +1,159 @@
+use core::mem;
+
+use crate::prelude::*;
+
+use super::blocks::let_in;
+use super::patterns::{Arm, Pattern};
+use super::{Expression, Identifier};
+
+/// convert `expr` to administrative normal form: the operands of every operation are atoms,
+/// which are variables, literals and functions, and every intermediate result is bound by a
+/// `let` of its own, in the order it was evaluated. the bodies of functions, branches and arms
+/// are each a chain of `let`s ending in one operation. a `let` written in operand position is
+/// kept whole rather than flattened, so that its variables stay in the scope they had.
+pub fn normalize(expr: Expression) -> Expression {
+    Normalizer::default().expression(expr)
+}
+
+#[derive(Default)]
+struct Normalizer {
+    /// the number of temporaries bound so far
+    temporaries: usize,
+}
+
+/// a `let` in the way `blocks::let_in` writes it: the pattern, the value and the body. the
+/// source annotations around a `let` are dropped, since a chain of them has nowhere to keep
+/// them: the parts of the `let` keep their own.
+fn as_let(mut expr: Expression) -> Result<(Pattern, Expression, Expression), Expression> {
+    match &mut expr {
+        Expression::Located { span, expression } => {
+            let span = *span;
+            as_let(expression.take()).map_err(|expression| Expression::Located { span, expression: Box::new(expression) })
+        }
+        Expression::Match { scrutinee, arms } if arms.len() == 1 && arms[0].guard.is_none() => {
+            let Arm { pattern, body, .. } = arms.pop().unwrap();
+            Ok((pattern, scrutinee.take(), body))
+        }
+        _ => Err(expr),
+    }
+}
+
+/// whether `expr` is an atom, which can be an operand as it is
+pub fn is_atom(expr: &Expression) -> bool {
+    match expr {
+        Expression::Variable(_) | Expression::Boolean(_) | Expression::Number(_) | Expression::Float(_)
+        | Expression::String(_) | Expression::Char(_) | Expression::Bytes(_) | Expression::Function { .. } => true,
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => is_atom(expression),
+        _ => false,
+    }
+}
+
+impl Normalizer {
+    /// `expr` as a chain of `let`s
+    fn expression(&mut self, mut expr: Expression) -> Expression {
+        let mut bindings = vec![];
+        loop {
+            expr = match as_let(expr) {
+                Ok((pattern, value, body)) => {
+                    let value = self.operation(value, &mut bindings);
+                    bindings.push((pattern, value));
+                    body
+                }
+                Err(expr) => {
+                    let tail = self.operation(expr, &mut bindings);
+                    return bindings.into_iter().rev().fold(tail, |body, (pattern, value)| let_in(pattern, value, body))
+                }
+            }
+        }
+    }
+
+    /// `expr` with atoms for operands, binding the operations they were in `bindings`
+    fn operation(&mut self, mut expr: Expression, bindings: &mut Vec<(Pattern, Expression)>) -> Expression {
+        match &mut expr {
+            Expression::Application { function, arguments } => {
+                let function = self.atom(function.take(), bindings);
+                Expression::Application { function: Box::new(function), arguments: self.atoms(mem::take(arguments), bindings) }
+            }
+            Expression::Primitive { operator, arguments } => Expression::Primitive { operator: *operator, arguments: self.atoms(mem::take(arguments), bindings) },
+            Expression::Tuple(elements) => Expression::Tuple(self.atoms(mem::take(elements), bindings)),
+            Expression::Record(fields) => {
+                let (names, values): (Vec<_>, Vec<_>) = mem::take(fields).into_iter().unzip();
+                Expression::Record(names.into_iter().zip(self.atoms(values, bindings)).collect())
+            }
+            Expression::Update { record, fields } => {
+                let record = self.atom(record.take(), bindings);
+                let (names, values): (Vec<_>, Vec<_>) = mem::take(fields).into_iter().unzip();
+                Expression::Update { record: Box::new(record), fields: names.into_iter().zip(self.atoms(values, bindings)).collect() }
+            }
+            Expression::Continue(arguments) => Expression::Continue(self.atoms(mem::take(arguments), bindings)),
+            Expression::Variant { tag, payload } => Expression::Variant { tag: mem::take(tag), payload: Box::new(self.atom(payload.take(), bindings)) },
+            Expression::TypeTest { expression, tag } => Expression::TypeTest { expression: Box::new(self.atom(expression.take(), bindings)), tag: tag.clone() },
+            Expression::TypeApplication { function, arguments } => {
+                Expression::TypeApplication { function: Box::new(self.atom(function.take(), bindings)), arguments: mem::take(arguments) }
+            }
+            Expression::Return(value) => Expression::Return(Box::new(self.atom(value.take(), bindings))),
+            Expression::If { condition, consequent, alternative } => Expression::If {
+                condition: Box::new(self.atom(condition.take(), bindings)),
+                consequent: Box::new(self.expression(consequent.take())),
+                alternative: Box::new(self.expression(alternative.take())),
+            },
+            Expression::IfTarget { target, consequent, alternative } => Expression::IfTarget {
+                target: mem::take(target),
+                consequent: Box::new(self.expression(consequent.take())),
+                alternative: Box::new(self.expression(alternative.take())),
+            },
+            Expression::Match { scrutinee, arms } => Expression::Match {
+                scrutinee: Box::new(self.atom(scrutinee.take(), bindings)),
+                arms: mem::take(arms).into_iter()
+                                    .map(|Arm { pattern, guard, body }| Arm {
+                                        pattern,
+                                        guard: guard.map(|guard| self.expression(guard)),
+                                        body: self.expression(body),
+                                    })
+                                    .collect(),
+            },
+            Expression::Loop { variables, body } => {
+                let variables = mem::take(variables).into_iter()
+                                                   .map(|(binding, init)| (binding, self.atom(init, bindings)))
+                                                   .collect();
+                Expression::Loop { variables, body: Box::new(self.expression(body.take())) }
+            }
+            Expression::Function { parameters, body } => Expression::Function { parameters: mem::take(parameters), body: Box::new(self.expression(body.take())) },
+            Expression::Returning { result, body } => Expression::Returning { result: result.take(), body: Box::new(self.expression(body.take())) },
+            Expression::TypeFunction { parameters, body } => {
+                Expression::TypeFunction { parameters: mem::take(parameters), body: Box::new(self.expression(body.take())) }
+            }
+            Expression::Constant(expression) => Expression::Constant(Box::new(self.expression(expression.take()))),
+            Expression::Total(expression) => Expression::Total(Box::new(self.expression(expression.take()))),
+            Expression::Located { span, expression } => {
+                Expression::Located { span: *span, expression: Box::new(self.operation(expression.take(), bindings)) }
+            }
+            Expression::Expanded { construct, span, expression } => {
+                Expression::Expanded { construct: mem::take(construct), span: *span, expression: Box::new(self.operation(expression.take(), bindings)) }
+            }
+            Expression::Variable(_) | Expression::Boolean(_) | Expression::Number(_) | Expression::Float(_)
+            | Expression::String(_) | Expression::Char(_) | Expression::Bytes(_) => expr,
+        }
+    }
+
+    fn atoms(&mut self, exprs: Vec<Expression>, bindings: &mut Vec<(Pattern, Expression)>) -> Vec<Expression> {
+        exprs.into_iter().map(|expr| self.atom(expr, bindings)).collect()
+    }
+
+    /// `expr` as an atom, binding it to a temporary if it isn't one
+    fn atom(&mut self, expr: Expression, bindings: &mut Vec<(Pattern, Expression)>) -> Expression {
+        let value = self.operation(expr, bindings);
+        if is_atom(&value) {
+            return value
+        }
+        let id = self.temporary();
+        bindings.push((Pattern::Variable(id.clone()), value));
+        Expression::Variable(id)
+    }
+
+    /// a temporary no program can write
+    fn temporary(&mut self) -> Identifier {
+        self.temporaries += 1;
+        format!("%anf{}", self.temporaries - 1)
+    }
+}
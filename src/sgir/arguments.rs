@@ -0,0 +1,121 @@
+use crate::collections::HashSet;
+use crate::prelude::*;
+
+use super::blocks::let_in;
+use super::patterns::Pattern;
+use super::{Binding, Expression, Identifier, Type, TypeError};
+
+// defaults and named arguments are elaborated away: a call to a function with a known signature
+// becomes an ordinary application, with its arguments in parameter order and defaults for any
+// that were left out.
+
+/// a parameter of a function declared in the surface language, which may have a default
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    pub binding: Binding,
+    pub default: Option<Expression>,
+}
+
+/// a function with `parameters`. defaults are inserted at call sites, so they can't refer to
+/// variables that might be shadowed there.
+pub fn function(parameters: &[Parameter], body: Expression) -> Result<Expression, TypeError> {
+    let mut seen = HashSet::new();
+    for Parameter { binding, default } in parameters {
+        if !seen.insert(&binding.id) {
+            return Err(TypeError::DuplicateBinding { id: binding.id.clone(), first: None, second: None })
+        }
+        if default.as_ref().is_some_and(|default| !default.free_variables().is_empty()) {
+            return Err(TypeError::NonConstantDefault(binding.id.clone()))
+        }
+    }
+    Ok(Expression::Function {
+        parameters: parameters.iter().map(|parameter| parameter.binding.clone()).collect(),
+        body: Box::new(body),
+    })
+}
+
+/// `function(positional, name = named...)`, for a function with `parameters`. the function and
+/// its arguments are still evaluated in the order they were written.
+pub fn call(function: Expression, parameters: &[Parameter], positional: Vec<Expression>,
+            named: Vec<(String, Expression)>) -> Result<Expression, TypeError> {
+    if positional.len() > parameters.len() {
+        return Err(TypeError::ArityMismatch { expected: parameters.len(), found: positional.len() })
+    }
+
+    // without names, nothing is reordered
+    if named.is_empty() {
+        let defaults = parameters[positional.len()..].iter().map(|Parameter { binding, default }| {
+            default.clone().ok_or_else(|| TypeError::MissingArgument(binding.id.clone()))
+        });
+        let arguments = positional.into_iter().map(Ok).chain(defaults).collect::<Result<_, _>>()?;
+        return Ok(Expression::Application { function: Box::new(function), arguments })
+    }
+
+    // otherwise, each argument is bound to a temporary in the order it was written, and the
+    // temporaries are passed in parameter order
+    let mut slots: Vec<Option<Expression>> = vec![None; parameters.len()];
+    let mut written = vec![function];
+    for (i, argument) in positional.into_iter().enumerate() {
+        slots[i] = Some(Expression::Variable(temporary(written.len())));
+        written.push(argument);
+    }
+    for (name, argument) in named {
+        let i = parameters.iter()
+                          .position(|parameter| parameter.binding.id == name)
+                          .ok_or_else(|| TypeError::UnknownArgument(name.clone()))?;
+        if slots[i].is_some() {
+            return Err(TypeError::DuplicateArgument(name))
+        }
+        slots[i] = Some(Expression::Variable(temporary(written.len())));
+        written.push(argument);
+    }
+    let arguments = slots.into_iter().zip(parameters).map(|(slot, Parameter { binding, default })| {
+        slot.or_else(|| default.clone()).ok_or_else(|| TypeError::MissingArgument(binding.id.clone()))
+    }).collect::<Result<_, _>>()?;
+
+    let body = Expression::Application { function: Box::new(Expression::Variable(temporary(0))), arguments };
+    Ok(bind_temporaries(written, body))
+}
+
+/// the name of the `i`th value bound by `bind_temporaries`, which no program can write
+fn temporary(i: usize) -> Identifier {
+    format!("%argument{}", i)
+}
+
+/// evaluate `values` in order, binding each to its temporary, and then `body`
+fn bind_temporaries(values: Vec<Expression>, body: Expression) -> Expression {
+    let pattern = Pattern::Tuple((0..values.len()).map(|i| Pattern::Variable(temporary(i))).collect());
+    let_in(pattern, Expression::Tuple(values), body)
+}
+
+/// `function(arguments)` where some arguments are the placeholder `_`, which is a function of
+/// the missing arguments. `function` has type `function_type`, which gives the placeholders
+/// their types. the function and the arguments that were given are evaluated once, when the
+/// partial application is.
+pub fn partial(function: Expression, function_type: &Type, arguments: Vec<Option<Expression>>) -> Result<Expression, TypeError> {
+    let Type::Function { arguments: parameter_types, .. } = function_type else {
+        return Err(TypeError::ExpectedFunction { found: function_type.clone() })
+    };
+    if arguments.len() != parameter_types.len() {
+        return Err(TypeError::ArityMismatch { expected: parameter_types.len(), found: arguments.len() })
+    }
+
+    let mut written = vec![function];
+    let mut parameters = vec![];
+    let mut passed = vec![];
+    for (argument, typ) in arguments.into_iter().zip(parameter_types) {
+        match argument {
+            Some(argument) => {
+                passed.push(Expression::Variable(temporary(written.len())));
+                written.push(argument);
+            }
+            None => {
+                let id = format!("%placeholder{}", parameters.len());
+                passed.push(Expression::Variable(id.clone()));
+                parameters.push(Binding { id, typ: typ.clone() });
+            }
+        }
+    }
+    let body = Expression::Application { function: Box::new(Expression::Variable(temporary(0))), arguments: passed };
+    Ok(bind_temporaries(written, Expression::Function { parameters, body: Box::new(body) }))
+}
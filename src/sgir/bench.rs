@@ -0,0 +1,281 @@
+//! measuring how long programs take to evaluate, and a suite of programs to measure, each
+//! exercising one thing an evaluator does a lot of, so that evaluators can be compared on them
+//! and changes to one can be compared with what it did before
+
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use super::patterns::{Arm, Pattern};
+use super::primitives::Primitive;
+use super::step::{self, Step};
+use super::{Binding, EvalError, Expression, Interpreter, Type};
+
+/// summary statistics over repeated measurements
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+}
+
+impl Statistics {
+    pub fn of(samples: &[f64]) -> Statistics {
+        let count = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / count;
+        let variance = samples.iter().map(|sample| (sample - mean).powi(2)).sum::<f64>() / count;
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let middle = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        } else {
+            sorted[middle]
+        };
+
+        Statistics { mean, median, stddev: variance.sqrt() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub name: String,
+    /// evaluation steps, in the interpreter's units
+    pub steps: Statistics,
+    /// wall time, in seconds
+    pub time: Statistics,
+}
+
+/// a way of evaluating programs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Evaluator {
+    /// the interpreter, `Interpreter::run`
+    Interpreter,
+    /// the small-step semantics, `step::step`, stepped until the program is a value
+    SmallStep,
+}
+
+impl Evaluator {
+    pub const ALL: [Evaluator; 2] = [Evaluator::Interpreter, Evaluator::SmallStep];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Evaluator::Interpreter => "interpreter",
+            Evaluator::SmallStep => "small-step",
+        }
+    }
+
+    /// the evaluator called `name`
+    pub fn named(name: &str) -> Option<Evaluator> {
+        Evaluator::ALL.into_iter().find(|evaluator| evaluator.name() == name)
+    }
+
+    /// evaluate `expr`, producing the steps it took, in the evaluator's own units
+    fn evaluate(self, expr: Expression) -> Result<usize, EvalError> {
+        match self {
+            Evaluator::Interpreter => {
+                let mut interpreter = Interpreter::default();
+                interpreter.run(expr)?;
+                Ok(interpreter.steps)
+            }
+            Evaluator::SmallStep => {
+                let (mut expr, mut steps) = (expr, 0);
+                while let Step::Next(next) = step::step(expr)? {
+                    expr = next;
+                    steps += 1;
+                }
+                Ok(steps)
+            }
+        }
+    }
+}
+
+/// evaluate `expr` `iterations` times, each in a fresh interpreter
+pub fn measure(name: &str, expr: &Expression, iterations: usize) -> Result<Measurement, EvalError> {
+    measure_with(Evaluator::Interpreter, name, expr, iterations)
+}
+
+/// evaluate `expr` `iterations` times with `evaluator`, each time afresh
+pub fn measure_with(evaluator: Evaluator, name: &str, expr: &Expression, iterations: usize) -> Result<Measurement, EvalError> {
+    assert!(iterations > 0, "a benchmark needs at least one iteration");
+
+    let mut steps = vec![];
+    let mut times = vec![];
+    for _ in 0..iterations {
+        let expr = expr.clone();
+        let start = Instant::now();
+        let taken = evaluator.evaluate(expr)?;
+        times.push(start.elapsed().as_secs_f64());
+        steps.push(taken as f64);
+    }
+
+    Ok(Measurement { name: name.to_owned(), steps: Statistics::of(&steps), time: Statistics::of(&times) })
+}
+
+impl Measurement {
+    /// how many times slower (> 1) or faster (< 1) this measurement is than `baseline`, by
+    /// median wall time
+    pub fn relative_to(&self, baseline: &Measurement) -> f64 {
+        self.time.median / baseline.time.median
+    }
+}
+
+/// how a measurement changed from a baseline, by its median steps and wall time, in percent of
+/// the baseline's: more than 0 when it got slower
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Delta {
+    pub steps: f64,
+    pub time: f64,
+}
+
+impl Measurement {
+    /// how this measurement changed from `baseline`
+    pub fn delta(&self, baseline: &Measurement) -> Delta {
+        let change = |now: f64, before: f64| if now == before { 0.0 } else { (now - before) / before * 100.0 };
+        Delta { steps: change(self.steps.median, baseline.steps.median), time: change(self.time.median, baseline.time.median) }
+    }
+}
+
+impl Display for Delta {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:+.1}% steps and {:+.1}% time against the baseline", self.steps, self.time)
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum BaselineError {
+    #[error("line {0} of the baseline isn't a measurement")]
+    Malformed(usize),
+}
+
+/// `measurements` as a baseline for later runs to be compared with, a line for each: its name,
+/// then the mean, median and standard deviation of its steps and then of its wall time, separated
+/// by tabs
+pub fn save(measurements: &[Measurement]) -> String {
+    measurements.iter()
+                .map(|Measurement { name, steps, time }| {
+                    format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\n", name, steps.mean, steps.median, steps.stddev, time.mean, time.median, time.stddev)
+                })
+                .collect()
+}
+
+/// the measurements in a baseline that `save` made
+pub fn load(baseline: &str) -> Result<Vec<Measurement>, BaselineError> {
+    baseline.lines()
+            .enumerate()
+            .map(|(i, line)| {
+                let mut fields = line.split('\t');
+                let name = fields.next().unwrap_or_default().to_owned();
+                let numbers = fields.map(|field| field.parse()).collect::<Result<Vec<f64>, _>>();
+                match numbers.as_deref() {
+                    Ok(&[mean, median, stddev, time_mean, time_median, time_stddev]) => {
+                        Ok(Measurement { name,
+                                         steps: Statistics { mean, median, stddev },
+                                         time: Statistics { mean: time_mean, median: time_median, stddev: time_stddev } })
+                    }
+                    _ => Err(BaselineError::Malformed(i + 1)),
+                }
+            })
+            .collect()
+}
+
+impl Display for Measurement {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {} steps (median), time mean {:?} median {:?} stddev {:?}",
+               self.name,
+               self.steps.median,
+               Duration::from_secs_f64(self.time.mean),
+               Duration::from_secs_f64(self.time.median),
+               Duration::from_secs_f64(self.time.stddev))
+    }
+}
+
+fn variable(id: &str) -> Expression {
+    Expression::Variable(id.to_owned())
+}
+
+fn number(id: &str) -> Binding {
+    Binding { id: id.to_owned(), typ: Type::Number }
+}
+
+fn apply(function: Expression, arguments: Vec<Expression>) -> Expression {
+    Expression::Application { function: Box::new(function), arguments }
+}
+
+fn primitive(operator: Primitive, arguments: Vec<Expression>) -> Expression {
+    Expression::Primitive { operator, arguments }
+}
+
+/// `fn(id, parameters...) -> if n < 1 then done else recur`, called with itself as `id` and then
+/// `arguments`. SGIR has no recursive bindings, so each program recurs by being passed itself,
+/// which isn't well-typed, and so the suite is evaluated without being checked.
+fn recursive(id: &str, parameters: &[&str], done: Expression, recur: Expression, arguments: Vec<Expression>) -> Expression {
+    let function = Expression::Function {
+        parameters: [id].iter().chain(parameters).map(|id| number(id)).collect(),
+        body: Box::new(Expression::If {
+            condition: Box::new(primitive(Primitive::Less, vec![variable("n"), Expression::Number(1)])),
+            consequent: Box::new(done),
+            alternative: Box::new(recur),
+        }),
+    };
+    apply(function.clone(), [function].into_iter().chain(arguments).collect())
+}
+
+/// `id(id, n - 1, arguments...)`
+fn recur(id: &str, arguments: Vec<Expression>) -> Expression {
+    let n = primitive(Primitive::Subtract, vec![variable("n"), Expression::Number(1)]);
+    apply(variable(id), [variable(id), n].into_iter().chain(arguments).collect())
+}
+
+/// the naive fibonacci function of `n`, a tree of calls
+fn recursion(n: i64) -> Expression {
+    let previous = |offset| {
+        let n = primitive(Primitive::Subtract, vec![variable("n"), Expression::Number(offset)]);
+        apply(variable("fib"), vec![variable("fib"), n])
+    };
+    let fib = Expression::Function {
+        parameters: vec![number("fib"), number("n")],
+        body: Box::new(Expression::If {
+            condition: Box::new(primitive(Primitive::Less, vec![variable("n"), Expression::Number(2)])),
+            consequent: Box::new(variable("n")),
+            alternative: Box::new(primitive(Primitive::Add, vec![previous(1), previous(2)])),
+        }),
+    };
+    apply(fib.clone(), vec![fib, Expression::Number(n)])
+}
+
+/// the sum of `n` numbers, each added by a closure made for it, `(fn (x) -> fn (y) -> x + y)(n)`
+fn closures(n: i64) -> Expression {
+    let adder = Expression::Function {
+        parameters: vec![number("x")],
+        body: Box::new(Expression::Function {
+            parameters: vec![number("y")],
+            body: Box::new(primitive(Primitive::Add, vec![variable("x"), variable("y")])),
+        }),
+    };
+    let added = apply(apply(adder, vec![variable("n")]), vec![variable("total")]);
+    recursive("sum", &["n", "total"], variable("total"), recur("sum", vec![added]), vec![Expression::Number(n), Expression::Number(0)])
+}
+
+/// a record of two fields, stepped `n` times, each step taking it apart and making another
+fn records(n: i64) -> Expression {
+    let pattern = Pattern::Record(vec![("x".to_owned(), Pattern::Variable("x".to_owned())),
+                                       ("y".to_owned(), Pattern::Variable("y".to_owned()))]);
+    let next = Expression::Record(vec![("x".to_owned(), primitive(Primitive::Add, vec![variable("y"), Expression::Number(1)])),
+                                       ("y".to_owned(), variable("x"))]);
+    let step = Expression::Match {
+        scrutinee: Box::new(variable("point")),
+        arms: vec![Arm { pattern, guard: None, body: recur("walk", vec![next]) }],
+    };
+    let origin = Expression::Record(vec![("x".to_owned(), Expression::Number(0)), ("y".to_owned(), Expression::Number(0))]);
+    recursive("walk", &["n", "point"], variable("point"), step, vec![Expression::Number(n), origin])
+}
+
+/// the programs to benchmark evaluators on, by name: one heavy on calls, one on making closures,
+/// and one on making and taking apart records. the interpreter recurses for each of their calls,
+/// which takes more stack in a debug build than a thread other than the main one has by default.
+pub fn suite() -> Vec<(&'static str, Expression)> {
+    vec![("recursion", recursion(15)), ("closures", closures(500)), ("records", records(500))]
+}
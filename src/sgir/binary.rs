@@ -0,0 +1,980 @@
+//! a compact binary encoding of SGIR, used for snapshots and other on-disk artifacts. natives
+//! are encoded by name and linked again when decoding.
+//!
+//! each kind of file starts with the magic bytes and the version of its `Format`. a reader reads
+//! files of its own major version and of earlier minor versions as they are. a file of an
+//! earlier major version has to be migrated first, one major version at a time, by the
+//! migrations of its format, e.g. with `sanguinello migrate`.
+
+use alloc::sync::Arc;
+use core::fmt::{self, Display, Formatter};
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use thiserror::Error;
+
+use super::numbers::Float;
+use super::patterns::{Arm, Pattern};
+use super::primitives::Primitive;
+use super::{Binding, Environment, Expression, Kind, Native, Position, Span, Type, TypeBinding, TypeTag, Value};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum DecodeError {
+    #[error("unexpected end of input at byte {0}")]
+    UnexpectedEnd(usize),
+
+    #[error("invalid tag {tag} at byte {position}")]
+    InvalidTag {
+        tag: u8,
+        position: usize,
+    },
+
+    #[error("invalid utf-8 in string at byte {0}")]
+    InvalidUtf8(usize),
+
+    #[error("a float that's NaN or infinite at byte {0}")]
+    InvalidFloat(usize),
+
+    #[error("not a {expected} file")]
+    BadMagic {
+        expected: String,
+    },
+
+    #[error("unsupported format version {found}, expected {supported} or an earlier version it can be migrated from")]
+    UnsupportedVersion {
+        found: Version,
+        supported: Version,
+    },
+
+    #[error("no native function named {0} to link")]
+    UnknownNative(String),
+}
+
+type DC<T> = Result<T, DecodeError>;
+
+/// the version of a format. a new minor version only adds to what files can hold, so a reader of
+/// it still reads the files of the versions before it, while a new major version changes what's
+/// there and needs a migration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// a change of the contents of a file, after its header, from one major version to the next
+pub type Migration = fn(&[u8]) -> DC<Vec<u8>>;
+
+/// a kind of file, e.g. a snapshot of an engine's globals
+#[derive(Clone, Copy, Debug)]
+pub struct Format {
+    /// the bytes files of this kind start with
+    pub magic: &'static [u8; 4],
+    /// the version written now
+    pub version: Version,
+    /// the migrations to each major version up to `version`'s from the one before it, in order,
+    /// so that files of the last `migrations.len()` major versions before it can be read
+    pub migrations: &'static [Migration],
+}
+
+impl Format {
+    /// the version `bytes` were written in, if they're a file of this format
+    pub fn version_of(&self, bytes: &[u8]) -> DC<Version> {
+        let mut decoder = Decoder::new(bytes, &|_| None);
+        if decoder.bytes(4).ok() != Some(self.magic) {
+            return Err(DecodeError::BadMagic { expected: String::from_utf8_lossy(self.magic).into_owned() })
+        }
+        Ok(Version { major: decoder.u16()?, minor: decoder.u16()? })
+    }
+
+    /// `bytes` in the current version, migrated from the major version they were written in if
+    /// it's an earlier one. they're left as they are if they can be read already.
+    pub fn migrate(&self, bytes: &[u8]) -> DC<Vec<u8>> {
+        let found = self.version_of(bytes)?;
+        let unsupported = DecodeError::UnsupportedVersion { found, supported: self.version };
+        if found.major == self.version.major {
+            return if found <= self.version { Ok(bytes.to_vec()) } else { Err(unsupported) }
+        }
+        let behind = self.version.major.checked_sub(found.major).ok_or(unsupported.clone())? as usize;
+        let Some(first) = self.migrations.len().checked_sub(behind) else { return Err(unsupported) };
+        let mut contents = bytes[8..].to_vec();
+        for migration in &self.migrations[first..] {
+            contents = migration(&contents)?;
+        }
+        let mut encoder = Encoder::new();
+        encoder.header(self);
+        encoder.bytes(&contents);
+        Ok(encoder.finish())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Encoder {
+        Encoder::default()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn encode<T: Encode + ?Sized>(&mut self, value: &T) {
+        value.encode(self);
+    }
+
+    /// start a file of `format`, in its current version
+    pub fn header(&mut self, format: &Format) {
+        self.bytes(format.magic);
+        self.u16(format.version.major);
+        self.u16(format.version.minor);
+    }
+}
+
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// finds the native function with a given name
+    natives: &'a dyn Fn(&str) -> Option<Native>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8], natives: &'a dyn Fn(&str) -> Option<Native>) -> Decoder<'a> {
+        Decoder { bytes, position: 0, natives }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    pub fn bytes(&mut self, length: usize) -> DC<&'a [u8]> {
+        let end = self.position.checked_add(length)
+                               .filter(|end| *end <= self.bytes.len())
+                               .ok_or(DecodeError::UnexpectedEnd(self.bytes.len()))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> DC<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> DC<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> DC<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> DC<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// the tag just read was not one `T` uses
+    fn invalid<T>(&self, tag: u8) -> DC<T> {
+        Err(DecodeError::InvalidTag { tag, position: self.position - 1 })
+    }
+
+    pub fn decode<T: Decode>(&mut self) -> DC<T> {
+        T::decode(self)
+    }
+
+    /// check that the input is a file of `format`, in a version that can be read without
+    /// migrating it, and produce that version
+    pub fn header(&mut self, format: &Format) -> DC<Version> {
+        let found = format.version_of(&self.bytes[self.position..])?;
+        if found.major != format.version.major || found > format.version {
+            return Err(DecodeError::UnsupportedVersion { found, supported: format.version })
+        }
+        self.position += 8;
+        Ok(found)
+    }
+}
+
+pub trait Encode {
+    fn encode(&self, encoder: &mut Encoder);
+}
+
+pub trait Decode: Sized {
+    fn decode(decoder: &mut Decoder) -> DC<Self>;
+}
+
+impl Encode for bool {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u8(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(decoder: &mut Decoder) -> DC<bool> {
+        match decoder.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => decoder.invalid(tag),
+        }
+    }
+}
+
+impl Encode for i64 {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.bytes(&self.to_le_bytes());
+    }
+}
+
+impl Decode for i64 {
+    fn decode(decoder: &mut Decoder) -> DC<i64> {
+        Ok(i64::from_le_bytes(decoder.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+impl Encode for f64 {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u64(self.to_bits());
+    }
+}
+
+impl Decode for f64 {
+    fn decode(decoder: &mut Decoder) -> DC<f64> {
+        let position = decoder.position;
+        Some(f64::from_bits(decoder.u64()?)).filter(|value| value.is_finite()).ok_or(DecodeError::InvalidFloat(position))
+    }
+}
+
+impl Encode for usize {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u64(*self as u64);
+    }
+}
+
+impl Decode for usize {
+    fn decode(decoder: &mut Decoder) -> DC<usize> {
+        let position = decoder.position;
+        usize::try_from(decoder.u64()?).map_err(|_| DecodeError::UnexpectedEnd(position))
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.encode(&self.len());
+        encoder.bytes(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.encode(self.as_str());
+    }
+}
+
+impl Decode for String {
+    fn decode(decoder: &mut Decoder) -> DC<String> {
+        let length = decoder.decode()?;
+        let position = decoder.position;
+        String::from_utf8(decoder.bytes(length)?.to_vec()).map_err(|_| DecodeError::InvalidUtf8(position))
+    }
+}
+
+impl Encode for [u8] {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.encode(&self.len());
+        encoder.bytes(self);
+    }
+}
+
+impl Decode for Vec<u8> {
+    fn decode(decoder: &mut Decoder) -> DC<Vec<u8>> {
+        let length = decoder.decode()?;
+        Ok(decoder.bytes(length)?.to_vec())
+    }
+}
+
+impl Encode for char {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u32(*self as u32);
+    }
+}
+
+impl Decode for char {
+    fn decode(decoder: &mut Decoder) -> DC<char> {
+        let position = decoder.position;
+        char::from_u32(decoder.u32()?).ok_or(DecodeError::InvalidUtf8(position))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.encode(&self.len());
+        for item in self {
+            encoder.encode(item);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(decoder: &mut Decoder) -> DC<Vec<T>> {
+        let length: usize = decoder.decode()?;
+        // don't trust the length to size the allocation, since the input may be corrupt
+        let mut items = vec![];
+        for _ in 0..length {
+            items.push(decoder.decode()?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            None => encoder.u8(0),
+            Some(value) => {
+                encoder.u8(1);
+                encoder.encode(value);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(decoder: &mut Decoder) -> DC<Option<T>> {
+        match decoder.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(decoder.decode()?)),
+            tag => decoder.invalid(tag),
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.encode(&self.0);
+        encoder.encode(&self.1);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(decoder: &mut Decoder) -> DC<(A, B)> {
+        Ok((decoder.decode()?, decoder.decode()?))
+    }
+}
+
+impl<T: Encode> Encode for Box<T> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.encode(&**self);
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn decode(decoder: &mut Decoder) -> DC<Box<T>> {
+        Ok(Box::new(decoder.decode()?))
+    }
+}
+
+impl Encode for Kind {
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            Kind::Star => encoder.u8(0),
+            Kind::Variable(id) => {
+                encoder.u8(1);
+                encoder.encode(id);
+            }
+            Kind::Arrow { from, to } => {
+                encoder.u8(2);
+                encoder.encode(from);
+                encoder.encode(to);
+            }
+        }
+    }
+}
+
+impl Decode for Kind {
+    fn decode(decoder: &mut Decoder) -> DC<Kind> {
+        match decoder.u8()? {
+            0 => Ok(Kind::Star),
+            1 => Ok(Kind::Variable(decoder.decode()?)),
+            2 => Ok(Kind::Arrow { from: decoder.decode()?, to: decoder.decode()? }),
+            tag => decoder.invalid(tag),
+        }
+    }
+}
+
+impl Encode for TypeBinding {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.encode(&self.id);
+        encoder.encode(&self.kind);
+    }
+}
+
+impl Decode for TypeBinding {
+    fn decode(decoder: &mut Decoder) -> DC<TypeBinding> {
+        Ok(TypeBinding { id: decoder.decode()?, kind: decoder.decode()? })
+    }
+}
+
+impl Encode for Type {
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            Type::Variable(id) => {
+                encoder.u8(0);
+                encoder.encode(id);
+            }
+            Type::ForAll { parameters, typ } => {
+                encoder.u8(1);
+                encoder.encode(parameters);
+                encoder.encode(typ);
+            }
+            Type::Instantiate { typ, arguments } => {
+                encoder.u8(2);
+                encoder.encode(typ);
+                encoder.encode(arguments);
+            }
+            Type::Function { arguments, result } => {
+                encoder.u8(3);
+                encoder.encode(arguments);
+                encoder.encode(result);
+            }
+            Type::Intersection(types) => {
+                encoder.u8(4);
+                encoder.encode(types);
+            }
+            Type::Union(types) => {
+                encoder.u8(5);
+                encoder.encode(types);
+            }
+            Type::Boolean => encoder.u8(6),
+            Type::Number => encoder.u8(7),
+            Type::String => encoder.u8(8),
+            Type::Char => encoder.u8(9),
+            Type::Bytes => encoder.u8(10),
+            Type::Tuple(types) => {
+                encoder.u8(11);
+                encoder.encode(types);
+            }
+            Type::Record(fields) => {
+                encoder.u8(12);
+                encoder.encode(fields);
+            }
+            Type::Variant(cases) => {
+                encoder.u8(13);
+                encoder.encode(cases);
+            }
+            Type::Rest(typ) => {
+                encoder.u8(14);
+                encoder.encode(typ);
+            }
+            Type::Float => encoder.u8(15),
+        }
+    }
+}
+
+impl Decode for Type {
+    fn decode(decoder: &mut Decoder) -> DC<Type> {
+        match decoder.u8()? {
+            0 => Ok(Type::Variable(decoder.decode()?)),
+            1 => Ok(Type::ForAll { parameters: decoder.decode()?, typ: decoder.decode()? }),
+            2 => Ok(Type::Instantiate { typ: decoder.decode()?, arguments: decoder.decode()? }),
+            3 => Ok(Type::Function { arguments: decoder.decode()?, result: decoder.decode()? }),
+            4 => Ok(Type::Intersection(decoder.decode()?)),
+            5 => Ok(Type::Union(decoder.decode()?)),
+            6 => Ok(Type::Boolean),
+            7 => Ok(Type::Number),
+            8 => Ok(Type::String),
+            9 => Ok(Type::Char),
+            10 => Ok(Type::Bytes),
+            11 => Ok(Type::Tuple(decoder.decode()?)),
+            12 => Ok(Type::Record(decoder.decode()?)),
+            13 => Ok(Type::Variant(decoder.decode()?)),
+            14 => Ok(Type::Rest(decoder.decode()?)),
+            15 => Ok(Type::Float),
+            tag => decoder.invalid(tag),
+        }
+    }
+}
+
+impl Encode for Binding {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.encode(&self.id);
+        encoder.encode(&self.typ);
+    }
+}
+
+impl Decode for Binding {
+    fn decode(decoder: &mut Decoder) -> DC<Binding> {
+        Ok(Binding { id: decoder.decode()?, typ: decoder.decode()? })
+    }
+}
+
+impl Encode for TypeTag {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u8(match self {
+            TypeTag::Boolean => 0,
+            TypeTag::Number => 1,
+            TypeTag::Function => 2,
+            TypeTag::String => 3,
+            TypeTag::Char => 4,
+            TypeTag::Bytes => 5,
+            TypeTag::Tuple => 6,
+            TypeTag::Record => 7,
+            TypeTag::Variant => 8,
+            TypeTag::Float => 9,
+        });
+    }
+}
+
+impl Decode for TypeTag {
+    fn decode(decoder: &mut Decoder) -> DC<TypeTag> {
+        match decoder.u8()? {
+            0 => Ok(TypeTag::Boolean),
+            1 => Ok(TypeTag::Number),
+            2 => Ok(TypeTag::Function),
+            3 => Ok(TypeTag::String),
+            4 => Ok(TypeTag::Char),
+            5 => Ok(TypeTag::Bytes),
+            6 => Ok(TypeTag::Tuple),
+            7 => Ok(TypeTag::Record),
+            8 => Ok(TypeTag::Variant),
+            9 => Ok(TypeTag::Float),
+            tag => decoder.invalid(tag),
+        }
+    }
+}
+
+impl Encode for Primitive {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u8(*self as u8);
+    }
+}
+
+impl Decode for Primitive {
+    fn decode(decoder: &mut Decoder) -> DC<Primitive> {
+        let tag = decoder.u8()?;
+        match Primitive::ALL.iter().find(|primitive| **primitive as u8 == tag) {
+            Some(primitive) => Ok(*primitive),
+            None => decoder.invalid(tag),
+        }
+    }
+}
+
+impl Encode for Pattern {
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            Pattern::Wildcard => encoder.u8(0),
+            Pattern::Variable(id) => {
+                encoder.u8(1);
+                encoder.encode(id);
+            }
+            Pattern::Boolean(value) => {
+                encoder.u8(2);
+                encoder.encode(value);
+            }
+            Pattern::Number(value) => {
+                encoder.u8(3);
+                encoder.encode(value);
+            }
+            Pattern::String(value) => {
+                encoder.u8(4);
+                encoder.encode(value);
+            }
+            Pattern::Char(value) => {
+                encoder.u8(5);
+                encoder.encode(value);
+            }
+            Pattern::Tuple(patterns) => {
+                encoder.u8(6);
+                encoder.encode(patterns);
+            }
+            Pattern::Record(fields) => {
+                encoder.u8(7);
+                encoder.encode(fields);
+            }
+            Pattern::Variant { tag, payload } => {
+                encoder.u8(8);
+                encoder.encode(tag);
+                encoder.encode(payload);
+            }
+            Pattern::Or(alternatives) => {
+                encoder.u8(9);
+                encoder.encode(alternatives);
+            }
+        }
+    }
+}
+
+impl Decode for Pattern {
+    fn decode(decoder: &mut Decoder) -> DC<Pattern> {
+        match decoder.u8()? {
+            0 => Ok(Pattern::Wildcard),
+            1 => Ok(Pattern::Variable(decoder.decode()?)),
+            2 => Ok(Pattern::Boolean(decoder.decode()?)),
+            3 => Ok(Pattern::Number(decoder.decode()?)),
+            4 => Ok(Pattern::String(decoder.decode()?)),
+            5 => Ok(Pattern::Char(decoder.decode()?)),
+            6 => Ok(Pattern::Tuple(decoder.decode()?)),
+            7 => Ok(Pattern::Record(decoder.decode()?)),
+            8 => Ok(Pattern::Variant { tag: decoder.decode()?, payload: decoder.decode()? }),
+            9 => Ok(Pattern::Or(decoder.decode()?)),
+            tag => decoder.invalid(tag),
+        }
+    }
+}
+
+impl Encode for Arm {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.encode(&self.pattern);
+        encoder.encode(&self.guard);
+        encoder.encode(&self.body);
+    }
+}
+
+impl Decode for Arm {
+    fn decode(decoder: &mut Decoder) -> DC<Arm> {
+        Ok(Arm { pattern: decoder.decode()?, guard: decoder.decode()?, body: decoder.decode()? })
+    }
+}
+
+impl Encode for Span {
+    fn encode(&self, encoder: &mut Encoder) {
+        for Position { line, column } in [self.start, self.end] {
+            encoder.encode(&line);
+            encoder.encode(&column);
+        }
+    }
+}
+
+impl Decode for Span {
+    fn decode(decoder: &mut Decoder) -> DC<Span> {
+        let start = Position { line: decoder.decode()?, column: decoder.decode()? };
+        let end = Position { line: decoder.decode()?, column: decoder.decode()? };
+        Ok(Span { start, end })
+    }
+}
+
+impl Encode for Expression {
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            Expression::Variable(id) => {
+                encoder.u8(0);
+                encoder.encode(id);
+            }
+            Expression::Boolean(value) => {
+                encoder.u8(1);
+                encoder.encode(value);
+            }
+            Expression::Number(value) => {
+                encoder.u8(2);
+                encoder.encode(value);
+            }
+            Expression::Function { parameters, body } => {
+                encoder.u8(3);
+                encoder.encode(parameters);
+                encoder.encode(body);
+            }
+            Expression::Application { function, arguments } => {
+                encoder.u8(4);
+                encoder.encode(function);
+                encoder.encode(arguments);
+            }
+            Expression::If { condition, consequent, alternative } => {
+                encoder.u8(5);
+                encoder.encode(condition);
+                encoder.encode(consequent);
+                encoder.encode(alternative);
+            }
+            Expression::TypeTest { expression, tag } => {
+                encoder.u8(6);
+                encoder.encode(expression);
+                encoder.encode(tag);
+            }
+            Expression::IfTarget { target, consequent, alternative } => {
+                encoder.u8(7);
+                encoder.encode(target);
+                encoder.encode(consequent);
+                encoder.encode(alternative);
+            }
+            Expression::Constant(expression) => {
+                encoder.u8(8);
+                encoder.encode(expression);
+            }
+            Expression::Located { span, expression } => {
+                encoder.u8(9);
+                encoder.encode(span);
+                encoder.encode(expression);
+            }
+            Expression::Primitive { operator, arguments } => {
+                encoder.u8(10);
+                encoder.encode(operator);
+                encoder.encode(arguments);
+            }
+            Expression::String(value) => {
+                encoder.u8(11);
+                encoder.encode(value);
+            }
+            Expression::Char(value) => {
+                encoder.u8(12);
+                encoder.encode(value);
+            }
+            Expression::Bytes(value) => {
+                encoder.u8(13);
+                encoder.encode(&value[..]);
+            }
+            Expression::Tuple(elements) => {
+                encoder.u8(14);
+                encoder.encode(elements);
+            }
+            Expression::Record(fields) => {
+                encoder.u8(15);
+                encoder.encode(fields);
+            }
+            Expression::Variant { tag, payload } => {
+                encoder.u8(16);
+                encoder.encode(tag);
+                encoder.encode(payload);
+            }
+            Expression::Match { scrutinee, arms } => {
+                encoder.u8(17);
+                encoder.encode(scrutinee);
+                encoder.encode(arms);
+            }
+            Expression::Loop { variables, body } => {
+                encoder.u8(18);
+                encoder.encode(variables);
+                encoder.encode(body);
+            }
+            Expression::Continue(arguments) => {
+                encoder.u8(19);
+                encoder.encode(arguments);
+            }
+            Expression::Returning { result: Some(result), body } => {
+                encoder.u8(20);
+                encoder.encode(result);
+                encoder.encode(body);
+            }
+            Expression::Returning { result: None, body } => {
+                encoder.u8(28);
+                encoder.encode(body);
+            }
+            Expression::Return(value) => {
+                encoder.u8(21);
+                encoder.encode(value);
+            }
+            Expression::TypeFunction { parameters, body } => {
+                encoder.u8(22);
+                encoder.encode(parameters);
+                encoder.encode(body);
+            }
+            Expression::TypeApplication { function, arguments } => {
+                encoder.u8(23);
+                encoder.encode(function);
+                encoder.encode(arguments);
+            }
+            Expression::Total(expression) => {
+                encoder.u8(24);
+                encoder.encode(expression);
+            }
+            Expression::Expanded { construct, span, expression } => {
+                encoder.u8(25);
+                encoder.encode(construct);
+                encoder.encode(span);
+                encoder.encode(expression);
+            }
+            Expression::Update { record, fields } => {
+                encoder.u8(26);
+                encoder.encode(record);
+                encoder.encode(fields);
+            }
+            Expression::Float(Float(value)) => {
+                encoder.u8(27);
+                encoder.encode(value);
+            }
+        }
+    }
+}
+
+impl Decode for Expression {
+    fn decode(decoder: &mut Decoder) -> DC<Expression> {
+        match decoder.u8()? {
+            0 => Ok(Expression::Variable(decoder.decode()?)),
+            1 => Ok(Expression::Boolean(decoder.decode()?)),
+            2 => Ok(Expression::Number(decoder.decode()?)),
+            3 => Ok(Expression::Function { parameters: decoder.decode()?, body: decoder.decode()? }),
+            4 => Ok(Expression::Application { function: decoder.decode()?, arguments: decoder.decode()? }),
+            5 => Ok(Expression::If { condition: decoder.decode()?,
+                                     consequent: decoder.decode()?,
+                                     alternative: decoder.decode()? }),
+            6 => Ok(Expression::TypeTest { expression: decoder.decode()?, tag: decoder.decode()? }),
+            7 => Ok(Expression::IfTarget { target: decoder.decode()?,
+                                           consequent: decoder.decode()?,
+                                           alternative: decoder.decode()? }),
+            8 => Ok(Expression::Constant(decoder.decode()?)),
+            9 => Ok(Expression::Located { span: decoder.decode()?, expression: decoder.decode()? }),
+            10 => Ok(Expression::Primitive { operator: decoder.decode()?, arguments: decoder.decode()? }),
+            11 => Ok(Expression::String(decoder.decode()?)),
+            12 => Ok(Expression::Char(decoder.decode()?)),
+            13 => Ok(Expression::Bytes(decoder.decode()?)),
+            14 => Ok(Expression::Tuple(decoder.decode()?)),
+            15 => Ok(Expression::Record(decoder.decode()?)),
+            16 => Ok(Expression::Variant { tag: decoder.decode()?, payload: decoder.decode()? }),
+            17 => Ok(Expression::Match { scrutinee: decoder.decode()?, arms: decoder.decode()? }),
+            18 => Ok(Expression::Loop { variables: decoder.decode()?, body: decoder.decode()? }),
+            19 => Ok(Expression::Continue(decoder.decode()?)),
+            20 => Ok(Expression::Returning { result: Some(decoder.decode()?), body: decoder.decode()? }),
+            21 => Ok(Expression::Return(decoder.decode()?)),
+            22 => Ok(Expression::TypeFunction { parameters: decoder.decode()?, body: decoder.decode()? }),
+            23 => Ok(Expression::TypeApplication { function: decoder.decode()?, arguments: decoder.decode()? }),
+            24 => Ok(Expression::Total(decoder.decode()?)),
+            25 => Ok(Expression::Expanded { construct: decoder.decode()?, span: decoder.decode()?, expression: decoder.decode()? }),
+            26 => Ok(Expression::Update { record: decoder.decode()?, fields: decoder.decode()? }),
+            27 => Ok(Expression::Float(Float(decoder.decode()?))),
+            28 => Ok(Expression::Returning { result: None, body: decoder.decode()? }),
+            tag => decoder.invalid(tag),
+        }
+    }
+}
+
+impl Encode for Value {
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            Value::Boolean(value) => {
+                encoder.u8(0);
+                encoder.encode(value);
+            }
+            Value::Number(value) => {
+                encoder.u8(1);
+                encoder.encode(value);
+            }
+            Value::Function { parameters, body, environment } => {
+                encoder.u8(2);
+                encoder.encode(parameters);
+                encoder.encode(body);
+                encoder.encode(&**environment);
+            }
+            Value::Native(native) => {
+                encoder.u8(3);
+                encoder.encode(&native.name);
+            }
+            Value::String(value) => {
+                encoder.u8(4);
+                encoder.encode(value);
+            }
+            Value::Char(value) => {
+                encoder.u8(5);
+                encoder.encode(value);
+            }
+            Value::Bytes(value) => {
+                encoder.u8(6);
+                encoder.encode(&value[..]);
+            }
+            Value::Tuple(values) => {
+                encoder.u8(7);
+                encoder.encode(values);
+            }
+            Value::Record(fields) => {
+                encoder.u8(8);
+                encoder.encode(&**fields);
+            }
+            Value::Variant { tag, payload } => {
+                encoder.u8(9);
+                encoder.encode(tag);
+                encoder.encode(payload);
+            }
+            Value::Float(value) => {
+                encoder.u8(10);
+                encoder.encode(value);
+            }
+        }
+    }
+}
+
+impl Decode for Value {
+    fn decode(decoder: &mut Decoder) -> DC<Value> {
+        match decoder.u8()? {
+            0 => Ok(Value::Boolean(decoder.decode()?)),
+            1 => Ok(Value::Number(decoder.decode()?)),
+            2 => Ok(Value::Function { parameters: decoder.decode()?,
+                                      body: decoder.decode()?,
+                                      environment: Arc::new(decoder.decode()?) }),
+            3 => {
+                let name: String = decoder.decode()?;
+                match (decoder.natives)(&name) {
+                    Some(native) => Ok(Value::Native(native)),
+                    None => Err(DecodeError::UnknownNative(name)),
+                }
+            }
+            4 => Ok(Value::String(decoder.decode()?)),
+            5 => Ok(Value::Char(decoder.decode()?)),
+            6 => Ok(Value::Bytes(decoder.decode()?)),
+            7 => Ok(Value::Tuple(decoder.decode()?)),
+            8 => Ok(Value::Record(Arc::new(decoder.decode()?))),
+            9 => Ok(Value::Variant { tag: decoder.decode()?, payload: decoder.decode()? }),
+            10 => Ok(Value::Float(decoder.decode()?)),
+            tag => decoder.invalid(tag),
+        }
+    }
+}
+
+/// environments shared between closures are written out once per closure
+impl Encode for Environment {
+    fn encode(&self, encoder: &mut Encoder) {
+        let mut bindings: Vec<_> = self.bindings.iter().collect();
+        bindings.sort_by_key(|(id, _)| *id);
+        encoder.encode(&bindings.len());
+        for (id, value) in bindings {
+            encoder.encode(id);
+            encoder.encode(value);
+        }
+        match &self.parent {
+            Some(parent) => {
+                encoder.u8(1);
+                encoder.encode(&**parent);
+            }
+            None => encoder.u8(0),
+        }
+    }
+}
+
+impl Decode for Environment {
+    fn decode(decoder: &mut Decoder) -> DC<Environment> {
+        let length: usize = decoder.decode()?;
+        let mut bindings = HashMap::new();
+        for _ in 0..length {
+            let id = decoder.decode()?;
+            bindings.insert(id, decoder.decode()?);
+        }
+        let parent = match decoder.u8()? {
+            0 => None,
+            1 => Some(Arc::new(decoder.decode()?)),
+            tag => return decoder.invalid(tag),
+        };
+        Ok(Environment { bindings, parent })
+    }
+}
@@ -0,0 +1,27 @@
+use crate::prelude::*;
+
+use super::patterns::{Arm, Pattern};
+use super::Expression;
+
+/// a statement in a `do ... end` block
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    /// `local pattern = value`, whose variables are in scope for the rest of the block
+    Local(Pattern, Expression),
+    /// an expression evaluated for its effects, whose value is discarded
+    Expression(Expression),
+}
+
+/// `let pattern = value in body`, which is a match with a single arm. the pattern has to be
+/// irrefutable for it to check.
+pub fn let_in(pattern: Pattern, value: Expression, body: Expression) -> Expression {
+    Expression::Match { scrutinee: Box::new(value), arms: vec![Arm { pattern, guard: None, body }] }
+}
+
+/// `do statements; result end`, whose value is the value of `result`
+pub fn block(statements: Vec<Statement>, result: Expression) -> Expression {
+    statements.into_iter().rev().fold(result, |body, statement| match statement {
+        Statement::Local(pattern, value) => let_in(pattern, value, body),
+        Statement::Expression(value) => let_in(Pattern::Wildcard, value, body),
+    })
+}
@@ -0,0 +1,82 @@
+use alloc::sync::Arc;
+
+use crate::prelude::*;
+
+use thiserror::Error;
+
+use super::numbers::Float;
+use super::macros::{substitute, FreshNames};
+use super::termination::terminates;
+use super::{check, EvalError, Expression, Identifier, Interpreter, TypeError, Value};
+
+/// the steps evaluating a constant may take, unless it's shown to terminate, before it's given up on
+pub const FUEL: usize = 10_000;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ConstantError {
+    #[error("constant expression refers to the runtime variable {0}")]
+    NotConstant(Identifier),
+
+    #[error(transparent)]
+    Type(#[from] TypeError),
+
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+}
+
+impl Value {
+    /// an expression that evaluates to this value
+    pub fn reify(self) -> Expression {
+        self.reified(&mut FreshNames::default())
+    }
+
+    /// reify this value, with the binders renamed in closing over environments drawn from `names`
+    fn reified(self, names: &mut FreshNames) -> Expression {
+        match self {
+            Value::Boolean(value) => Expression::Boolean(value),
+            Value::Number(value) => Expression::Number(value),
+            Value::Float(value) => Expression::Float(Float(value)),
+            Value::String(value) => Expression::String(value),
+            Value::Char(value) => Expression::Char(value),
+            Value::Bytes(value) => Expression::Bytes(value),
+            Value::Tuple(values) => Expression::Tuple(values.into_iter().map(|value| value.reified(names)).collect()),
+            Value::Record(fields) => Expression::Record(Arc::unwrap_or_clone(fields).into_iter()
+                                                                            .map(|(field, value)| (field, value.reified(names)))
+                                                                            .collect()),
+            Value::Variant { tag, payload } => Expression::Variant { tag, payload: Box::new(payload.reified(names)) },
+            // a closure's environment is closed over by substituting its values into the body
+            Value::Function { parameters, body, environment } => {
+                let replacements = environment.flatten()
+                                              .into_iter()
+                                              .map(|(id, value)| (id, value.reified(names)))
+                                              .collect();
+                let body = substitute(*body, &replacements, names);
+                Expression::Function { parameters, body: Box::new(body) }
+            }
+            // natives are linked by name
+            Value::Native(native) => Expression::Variable(native.name),
+        }
+    }
+}
+
+/// replace every `Constant` in `expr` with the value it evaluates to. constants may only refer
+/// to variables bound inside of them, and each is evaluated with at most `fuel` steps, so
+/// compilation is guaranteed to terminate. a constant that's shown to terminate, like one that
+/// calls a `total` function, is sure to finish anyway, so it's trusted to run as long as it takes.
+pub fn evaluate_constants(mut expr: Expression, fuel: usize) -> Result<Expression, ConstantError> {
+    match &mut expr {
+        Expression::Constant(expression) => Ok(evaluate_constant(expression.take(), fuel)?.reify()),
+        _ => expr.try_map_children(|child| evaluate_constants(child, fuel)),
+    }
+}
+
+/// the value of the body of a `Constant`, which is checked before it's evaluated
+pub fn evaluate_constant(expression: Expression, fuel: usize) -> Result<Value, ConstantError> {
+    if let Some(id) = expression.free_variables().into_iter().min() {
+        return Err(ConstantError::NotConstant(id))
+    }
+    let expression = evaluate_constants(expression, fuel)?;
+    check(expression.clone())?;
+    let mut interpreter = if terminates(&expression) { Interpreter::default() } else { Interpreter::with_fuel(fuel) };
+    Ok(interpreter.run(expression)?)
+}
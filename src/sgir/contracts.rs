@@ -0,0 +1,13 @@
+use crate::prelude::*;
+
+use super::primitives::Primitive;
+use super::Expression;
+
+/// `expr` without its contract checks, for a release build that trusts them to hold. each
+/// `assert` is replaced by the unit it evaluates to when it passes, so nothing else changes.
+pub fn erase(expr: Expression) -> Expression {
+    match expr.map_children(erase) {
+        Expression::Primitive { operator: Primitive::Assert, .. } => Expression::Tuple(vec![]),
+        expr => expr,
+    }
+}
@@ -0,0 +1,88 @@
+use core::fmt::Write;
+
+use crate::collections::{BTreeMap, HashMap};
+use crate::prelude::*;
+
+use super::{EvalError, Expression, Interpreter, Span, Value};
+
+impl Expression {
+    /// the spans of every located expression within this one
+    pub fn spans(&self) -> Vec<Span> {
+        let mut spans = match self {
+            Expression::Located { span, .. } => vec![*span],
+            _ => vec![],
+        };
+        for child in self.children() {
+            spans.extend(child.spans());
+        }
+        spans
+    }
+}
+
+/// how often each span of a program was evaluated, including those never evaluated
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CoverageReport {
+    pub hits: BTreeMap<Span, usize>,
+}
+
+/// evaluate `expr`, recording which of its spans are executed
+pub fn run_with_coverage(expr: Expression) -> (Result<Value, EvalError>, CoverageReport) {
+    covering(Interpreter::default(), expr, |interpreter, expr| interpreter.run(expr))
+}
+
+/// evaluate `expr` with `run` on `interpreter`, recording which of its spans are executed
+pub fn covering<T>(mut interpreter: Interpreter, expr: Expression, run: impl FnOnce(&mut Interpreter, Expression) -> T) -> (T, CoverageReport) {
+    let mut hits: BTreeMap<_, _> = expr.spans().into_iter().map(|span| (span, 0)).collect();
+
+    interpreter.coverage = Some(HashMap::new());
+    let result = run(&mut interpreter, expr);
+    for (span, count) in interpreter.coverage.unwrap_or_default() {
+        *hits.entry(span).or_default() += count;
+    }
+
+    (result, CoverageReport { hits })
+}
+
+impl CoverageReport {
+    /// add the hits of `other`, a report of another run of the same file, to these
+    pub fn merge(&mut self, other: CoverageReport) {
+        for (span, count) in other.hits {
+            *self.hits.entry(span).or_default() += count;
+        }
+    }
+
+    pub fn uncovered(&self) -> Vec<Span> {
+        self.hits.iter()
+                 .filter(|(_, count)| **count == 0)
+                 .map(|(span, _)| *span)
+                 .collect()
+    }
+
+    /// a human-readable listing of the expressions that were never evaluated
+    pub fn to_text(&self, file: &str) -> String {
+        let mut out = String::new();
+        for Span { start, end } in self.uncovered() {
+            writeln!(out, "{}:{}:{}-{}:{}: not executed", file, start.line, start.column, end.line, end.column).unwrap();
+        }
+        let covered = self.hits.len() - self.uncovered().len();
+        write!(out, "{} of {} expressions executed", covered, self.hits.len()).unwrap();
+        out
+    }
+
+    /// the report in lcov's tracefile format, counting a line by its most executed expression
+    pub fn to_lcov(&self, file: &str) -> String {
+        let mut lines = BTreeMap::new();
+        for (span, count) in &self.hits {
+            let line = lines.entry(span.start.line).or_insert(0);
+            *line = (*line).max(*count);
+        }
+
+        let mut out = format!("SF:{}\n", file);
+        for (line, count) in &lines {
+            writeln!(out, "DA:{},{}", line, count).unwrap();
+        }
+        let hit = lines.values().filter(|count| **count > 0).count();
+        write!(out, "LH:{}\nLF:{}\nend_of_record\n", hit, lines.len()).unwrap();
+        out
+    }
+}
@@ -0,0 +1,104 @@
+use core::mem;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use super::anf::{self, is_atom};
+use super::effects::is_pure;
+use super::patterns::{Arm, Pattern};
+use super::{Binding, Expression, Identifier};
+
+/// the values already bound in scope, each to the variable holding it, along with the
+/// variables that were found to hold the same value as another and renamed to it
+#[derive(Clone, Default)]
+struct Available {
+    values: HashMap<Expression, Identifier>,
+    renamed: HashMap<Identifier, Identifier>,
+}
+
+impl Available {
+    /// what's still available inside a scope that binds `bound`: nothing that refers to them,
+    /// or is held by a variable they shadow
+    fn shadowed(&self, bound: &[Identifier]) -> Available {
+        let is_bound = |id: &Identifier| bound.contains(id);
+        Available {
+            values: self.values.iter()
+                               .filter(|(value, id)| !is_bound(id) && !value.free_variables().iter().any(is_bound))
+                               .map(|(value, id)| (value.clone(), id.clone()))
+                               .collect(),
+            renamed: self.renamed.iter()
+                                 .filter(|(from, to)| !is_bound(from) && !is_bound(to))
+                                 .map(|(from, to)| (from.clone(), to.clone()))
+                                 .collect(),
+        }
+    }
+}
+
+/// eliminate common subexpressions from `expr`, which is put in administrative normal form
+/// first. a pure operation bound by a `let` when the same operation on the same variables is
+/// already bound in scope is replaced by the variable that holds it, and uses of the variable
+/// it was bound to are renamed to that one. the earlier binding was evaluated on every path to
+/// the later one, so nothing is evaluated where it wasn't before.
+pub fn eliminate_common_subexpressions(expr: Expression) -> Expression {
+    eliminate(anf::normalize(expr), &Available::default())
+}
+
+fn eliminate(mut expr: Expression, available: &Available) -> Expression {
+    match &mut expr {
+        Expression::Variable(id) => available.renamed.get(id).map_or(expr, |holder| Expression::Variable(holder.clone())),
+        Expression::Match { scrutinee, arms } if arms.len() == 1 && arms[0].guard.is_none() => {
+            let Arm { pattern, body, .. } = arms.pop().unwrap();
+            let mut value = eliminate(scrutinee.take(), available);
+            let key = unlocated(&value);
+            let shared = (is_pure(&key) && !is_atom(&key)).then(|| available.values.get(&key)).flatten();
+
+            let mut inner = available.shadowed(&pattern.variables());
+            if let Pattern::Variable(id) = &pattern {
+                match shared {
+                    Some(holder) => {
+                        value = Expression::Variable(holder.clone());
+                        inner.renamed.insert(id.clone(), holder.clone());
+                    }
+                    None if is_pure(&key) && !is_atom(&key) && !key.free_variables().contains(id) => {
+                        inner.values.insert(key, id.clone());
+                    }
+                    None => {}
+                }
+            } else if let Some(holder) = shared {
+                value = Expression::Variable(holder.clone());
+            }
+            let body = eliminate(body, &inner);
+            Expression::Match { scrutinee: Box::new(value), arms: vec![Arm { pattern, guard: None, body }] }
+        }
+        Expression::Match { scrutinee, arms } => Expression::Match {
+            scrutinee: Box::new(eliminate(scrutinee.take(), available)),
+            arms: mem::take(arms).into_iter()
+                                .map(|Arm { pattern, guard, body }| {
+                                    let inner = available.shadowed(&pattern.variables());
+                                    Arm { guard: guard.map(|guard| eliminate(guard, &inner)), body: eliminate(body, &inner), pattern }
+                                })
+                                .collect(),
+        },
+        Expression::Function { parameters, body } => {
+            let inner = available.shadowed(&parameters.iter().map(|Binding { id, .. }| id.clone()).collect::<Vec<_>>());
+            Expression::Function { body: Box::new(eliminate(body.take(), &inner)), parameters: mem::take(parameters) }
+        }
+        Expression::Loop { variables, body } => {
+            let variables = mem::take(variables).into_iter()
+                                               .map(|(binding, init)| (binding, eliminate(init, available)))
+                                               .collect::<Vec<_>>();
+            let inner = available.shadowed(&variables.iter().map(|(Binding { id, .. }, _)| id.clone()).collect::<Vec<_>>());
+            Expression::Loop { body: Box::new(eliminate(body.take(), &inner)), variables }
+        }
+        _ => expr.map_children(|child| eliminate(child, available)),
+    }
+}
+
+/// `expr` without any of its source annotations, so that the same operation written in two
+/// places is the same
+fn unlocated(expr: &Expression) -> Expression {
+    match expr {
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => unlocated(expression),
+        expr => expr.clone().map_children(|child| unlocated(&child)),
+    }
+}
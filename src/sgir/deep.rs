@@ -0,0 +1,96 @@
+//! dropping and cloning expressions and types without recursing once for each level of them, so
+//! that the deeply nested terms a program generator can make don't overflow the stack, e.g. a
+//! chain of a million `let`s.
+//!
+//! `Expression` and `Type` implement `Drop` with `drop_children`, which means they can't be
+//! moved out of by a pattern: a match that takes one apart takes its fields with `take`, which
+//! leaves the unit in their place.
+
+use core::mem;
+
+use crate::prelude::*;
+
+/// how deep `clone` recurses before it clones the rest of a term with a stack of its own
+const RECURSION_LIMIT: usize = 256;
+
+/// a term whose children are terms of the same kind
+pub(crate) trait Tree: Default + Sized {
+    /// call `f` on each child of this term, in order
+    fn each_child_mut(&mut self, f: impl FnMut(&mut Self));
+
+    /// a copy of this term, but with `f` of each of its children in their place, in order
+    fn clone_with<'a>(&'a self, f: impl FnMut(&'a Self) -> Self) -> Self;
+}
+
+fn has_children<T: Tree>(tree: &mut T) -> bool {
+    let mut found = false;
+    tree.each_child_mut(|_| found = true);
+    found
+}
+
+/// move the children of `tree` that have children of their own onto `stack`. the rest are left
+/// to be dropped with `tree`, so a term that's nearly a leaf doesn't allocate.
+fn take_children<T: Tree>(tree: &mut T, stack: &mut Vec<T>) {
+    tree.each_child_mut(|child| {
+        if has_children(child) {
+            stack.push(mem::take(child));
+        }
+    });
+}
+
+/// empty `tree` of its children and theirs, dropping each once it's been emptied in turn
+pub(crate) fn drop_children<T: Tree>(tree: &mut T) {
+    let mut stack = vec![];
+    take_children(tree, &mut stack);
+    while let Some(mut tree) = stack.pop() {
+        take_children(&mut tree, &mut stack);
+    }
+}
+
+pub(crate) fn clone<T: Tree>(tree: &T) -> T {
+    clone_within(tree, RECURSION_LIMIT)
+}
+
+fn clone_within<T: Tree>(tree: &T, depth: usize) -> T {
+    match depth {
+        0 => clone_iteratively(tree),
+        _ => tree.clone_with(|child| clone_within(child, depth - 1)),
+    }
+}
+
+/// a term being cloned: its copy, with the unit in place of its children, the children left to
+/// clone, and the clones of the ones before them
+struct Frame<'a, T> {
+    copy: T,
+    children: vec::IntoIter<&'a T>,
+    clones: Vec<T>,
+}
+
+impl<'a, T: Tree> Frame<'a, T> {
+    fn new(tree: &'a T) -> Self {
+        let mut children = vec![];
+        let copy = tree.clone_with(|child| {
+            children.push(child);
+            T::default()
+        });
+        Frame { copy, children: children.into_iter(), clones: vec![] }
+    }
+}
+
+fn clone_iteratively<T: Tree>(tree: &T) -> T {
+    let mut frames = vec![Frame::new(tree)];
+    loop {
+        let frame = frames.last_mut().unwrap();
+        if let Some(child) = frame.children.next() {
+            frames.push(Frame::new(child));
+            continue
+        }
+        let Frame { mut copy, clones, .. } = frames.pop().unwrap();
+        let mut clones = clones.into_iter();
+        copy.each_child_mut(|child| *child = clones.next().unwrap());
+        match frames.last_mut() {
+            Some(parent) => parent.clones.push(copy),
+            None => return copy,
+        }
+    }
+}
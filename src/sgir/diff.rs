@@ -0,0 +1,213 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::prelude::*;
+
+use super::dump::dump_fragment;
+use super::patterns::Arm;
+use super::Expression;
+
+/// one difference between two expression trees, at a path from the root like
+/// `$.body.arguments[1]`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// a subexpression only the second tree has, e.g. a new argument or record field
+    Added { path: String, expression: Expression },
+    /// a subexpression only the first tree has
+    Removed { path: String, expression: Expression },
+    /// a node that's a different kind of node, or the same kind with different contents, e.g.
+    /// another operator or literal. its children aren't compared.
+    Changed { path: String, before: Expression, after: Expression },
+}
+
+/// the changes that take one expression tree to another, in the order of a preorder walk
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// the paths that changed, for asserting what a pass touched without spelling out the trees
+    pub fn paths(&self) -> Vec<&str> {
+        self.changes.iter()
+                    .map(|change| match change {
+                        Change::Added { path, .. } | Change::Removed { path, .. } | Change::Changed { path, .. } => path.as_str(),
+                    })
+                    .collect()
+    }
+}
+
+/// the structural diff from `before` to `after`, e.g. before and after an optimization pass.
+/// nodes of the same kind are compared child by child, matching the children of records by
+/// name and everything else by position.
+pub fn diff(before: &Expression, after: &Expression) -> Diff {
+    let mut diff = Diff::default();
+    compare("$".to_owned(), before, after, &mut diff.changes);
+    diff
+}
+
+fn compare(path: String, before: &Expression, after: &Expression, changes: &mut Vec<Change>) {
+    if before == after {
+        return
+    }
+    if !same_head(before, after) {
+        changes.push(Change::Changed { path, before: before.clone(), after: after.clone() });
+        return
+    }
+    let (before, after) = (labelled_children(before), labelled_children(after));
+    for (label, child) in &before {
+        match after.iter().find(|(other, _)| other == label) {
+            Some((_, other)) => compare(format!("{}{}", path, label), child, other, changes),
+            None => changes.push(Change::Removed { path: format!("{}{}", path, label), expression: (*child).clone() }),
+        }
+    }
+    for (label, child) in &after {
+        if !before.iter().any(|(other, _)| other == label) {
+            changes.push(Change::Added { path: format!("{}{}", path, label), expression: (*child).clone() });
+        }
+    }
+}
+
+/// whether `before` and `after` are the same kind of node with the same contents, so that they
+/// differ only in their children. the lists whose children can be added or removed one at a
+/// time aren't compared, only what's in them.
+fn same_head(before: &Expression, after: &Expression) -> bool {
+    match (before, after) {
+        (Expression::Variable(before), Expression::Variable(after)) => before == after,
+        (Expression::Boolean(before), Expression::Boolean(after)) => before == after,
+        (Expression::Number(before), Expression::Number(after)) => before == after,
+        (Expression::Float(before), Expression::Float(after)) => before == after,
+        (Expression::String(before), Expression::String(after)) => before == after,
+        (Expression::Char(before), Expression::Char(after)) => before == after,
+        (Expression::Bytes(before), Expression::Bytes(after)) => before == after,
+        (Expression::Function { parameters: before, .. }, Expression::Function { parameters: after, .. }) => before == after,
+        (Expression::TypeTest { tag: before, .. }, Expression::TypeTest { tag: after, .. }) => before == after,
+        (Expression::IfTarget { target: before, .. }, Expression::IfTarget { target: after, .. }) => before == after,
+        (Expression::Located { span: before, .. }, Expression::Located { span: after, .. }) => before == after,
+        (Expression::Expanded { construct, span, .. }, Expression::Expanded { construct: other, span: at, .. }) => construct == other && span == at,
+        (Expression::Primitive { operator: before, .. }, Expression::Primitive { operator: after, .. }) => before == after,
+        (Expression::Variant { tag: before, .. }, Expression::Variant { tag: after, .. }) => before == after,
+        (Expression::Match { arms: before, .. }, Expression::Match { arms: after, .. }) => {
+            before.len() == after.len()
+                && before.iter().zip(after).all(|(before, after)| before.pattern == after.pattern && before.guard.is_some() == after.guard.is_some())
+        }
+        (Expression::Loop { variables: before, .. }, Expression::Loop { variables: after, .. }) => {
+            before.len() == after.len() && before.iter().zip(after).all(|((before, _), (after, _))| before == after)
+        }
+        (Expression::Returning { result: before, .. }, Expression::Returning { result: after, .. }) => before == after,
+        (Expression::TypeFunction { parameters: before, .. }, Expression::TypeFunction { parameters: after, .. }) => before == after,
+        (Expression::TypeApplication { arguments: before, .. }, Expression::TypeApplication { arguments: after, .. }) => before == after,
+        (Expression::Application { .. }, Expression::Application { .. }) | (Expression::If { .. }, Expression::If { .. })
+        | (Expression::Constant(_), Expression::Constant(_)) | (Expression::Total(_), Expression::Total(_))
+        | (Expression::Tuple(_), Expression::Tuple(_)) | (Expression::Record(_), Expression::Record(_))
+        | (Expression::Update { .. }, Expression::Update { .. }) | (Expression::Continue(_), Expression::Continue(_))
+        | (Expression::Return(_), Expression::Return(_)) => true,
+        _ => false,
+    }
+}
+
+/// the children of `expr`, each with the step in a path that leads to it. `Located` and
+/// `Expanded` only annotate their expressions, so they add no step.
+fn labelled_children(expr: &Expression) -> Vec<(String, &Expression)> {
+    fn field<'a>(name: &str, child: &'a Expression) -> (String, &'a Expression) {
+        (format!(".{}", name), child)
+    }
+    fn indexed<'a>(name: &str, children: &'a [Expression]) -> Vec<(String, &'a Expression)> {
+        children.iter()
+                .enumerate()
+                .map(|(i, child)| (format!(".{}[{}]", name, i), child))
+                .collect()
+    }
+    match expr {
+        Expression::Variable(_) | Expression::Boolean(_) | Expression::Number(_) | Expression::Float(_)
+        | Expression::String(_) | Expression::Char(_) | Expression::Bytes(_) => vec![],
+        Expression::Function { body, .. } | Expression::Returning { body, .. } | Expression::TypeFunction { body, .. } => {
+            vec![field("body", body)]
+        }
+        Expression::Application { function, arguments } => {
+            let mut children = vec![field("function", function)];
+            children.extend(indexed("arguments", arguments));
+            children
+        }
+        Expression::If { condition, consequent, alternative } => {
+            vec![field("condition", condition), field("consequent", consequent), field("alternative", alternative)]
+        }
+        Expression::TypeTest { expression, .. } => vec![field("expression", expression)],
+        Expression::IfTarget { consequent, alternative, .. } => {
+            vec![field("consequent", consequent), field("alternative", alternative)]
+        }
+        Expression::Constant(expression) => vec![field("constant", expression)],
+        Expression::Total(expression) => vec![field("total", expression)],
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => vec![(String::new(), expression)],
+        Expression::Primitive { arguments, .. } | Expression::Continue(arguments) => indexed("arguments", arguments),
+        Expression::Tuple(elements) => indexed("elements", elements),
+        Expression::Record(fields) => fields.iter().map(|(name, child)| field(name, child)).collect(),
+        Expression::Update { record, fields } => {
+            let mut children = vec![field("record", record)];
+            children.extend(fields.iter().map(|(name, child)| field(&format!("fields.{}", name), child)));
+            children
+        }
+        Expression::Variant { payload, .. } => vec![field("payload", payload)],
+        Expression::Match { scrutinee, arms } => {
+            let mut children = vec![field("scrutinee", scrutinee)];
+            for (i, Arm { guard, body, .. }) in arms.iter().enumerate() {
+                children.extend(guard.iter().map(|guard| (format!(".arms[{}].guard", i), guard)));
+                children.push((format!(".arms[{}].body", i), body));
+            }
+            children
+        }
+        Expression::Loop { variables, body } => {
+            let mut children = variables.iter()
+                                        .map(|(binding, init)| field(&format!("variables.{}", binding.id), init))
+                                        .collect::<Vec<_>>();
+            children.push(field("body", body));
+            children
+        }
+        Expression::Return(value) => vec![field("value", value)],
+        Expression::TypeApplication { function, .. } => vec![field("function", function)],
+    }
+}
+
+/// `expr` as SGIR, on the same line when it fits on one, or indented on the lines after it
+fn fragment(expr: &Expression) -> String {
+    let text = dump_fragment(expr);
+    let text = text.trim_end();
+    match text.contains('\n') {
+        true => text.lines().map(|line| format!("\n    {}", line)).collect(),
+        false => format!(" {}", text),
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Change::Added { path, expression } => write!(f, "+ {}:{}", path, fragment(expression)),
+            Change::Removed { path, expression } => write!(f, "- {}:{}", path, fragment(expression)),
+            Change::Changed { path, before, after } => {
+                let (before, after) = (fragment(before), fragment(after));
+                match before.contains('\n') || after.contains('\n') {
+                    true => write!(f, "~ {}:{}\n  =>{}", path, before, after),
+                    false => write!(f, "~ {}:{} =>{}", path, before, after),
+                }
+            }
+        }
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes")
+        }
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
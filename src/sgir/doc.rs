@@ -0,0 +1,30 @@
+use core::fmt::Write;
+
+use crate::prelude::*;
+
+use super::{Identifier, Type};
+
+/// a top-level declaration together with the doc comment attached to it, and its type if it's
+/// known
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocItem {
+    pub name: Identifier,
+    pub typ: Option<Type>,
+    pub doc: String,
+}
+
+/// render an API listing of `items` as Markdown, one section per declaration in the order given
+pub fn render_markdown(title: &str, items: &[DocItem]) -> String {
+    let mut out = format!("# {}\n", title);
+    for DocItem { name, typ, doc } in items {
+        match typ {
+            Some(typ) => write!(out, "\n## `{}`\n\n```\n{}: {}\n```\n", name, name, typ).unwrap(),
+            None => write!(out, "\n## `{}`\n", name).unwrap(),
+        }
+        let doc = doc.trim();
+        if !doc.is_empty() {
+            write!(out, "\n{}\n", doc).unwrap();
+        }
+    }
+    out
+}
@@ -0,0 +1,324 @@
+//! a textual dump of SGIR, for seeing what a program was lowered to. each line that starts a
+//! region of the source is preceded by a comment with the lines of that region, e.g.
+//! `-- 3-5: function f(x: number)`, so the dump can be read alongside the program.
+
+use core::fmt::Write;
+use core::ptr;
+
+use crate::prelude::*;
+
+use super::numbers::{self, Float};
+use super::patterns::{Arm, Pattern};
+use super::{Binding, Expression, Span};
+
+const INDENTATION: &str = "    ";
+
+struct Printer<'a> {
+    source: Vec<&'a str>,
+    output: String,
+    /// the line being written, which is only added to `output` once it's finished, after the
+    /// comment about it
+    line: String,
+    indent: usize,
+    /// the first region that starts on `line` in a source line that hasn't been noted yet
+    region: Option<Span>,
+    /// the source line of the last comment
+    noted: Option<usize>,
+    /// whether to write the comments about regions of the source
+    notes: bool,
+    /// the subterm to write between `⟦` and `⟧`
+    highlight: Option<&'a Expression>,
+}
+
+impl Printer<'_> {
+    fn write(&mut self, text: &str) {
+        self.line.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        if let Some(Span { start, end }) = self.region.take() {
+            let indentation = &self.line[..self.line.len() - self.line.trim_start().len()];
+            write!(self.output, "{}-- {}", indentation, start.line).unwrap();
+            if end.line > start.line {
+                write!(self.output, "-{}", end.line).unwrap();
+            }
+            if let Some(text) = start.line.checked_sub(1).and_then(|line| self.source.get(line)) {
+                write!(self.output, ": {}", text.trim()).unwrap();
+            }
+            self.output.push('\n');
+            self.noted = Some(start.line);
+        }
+        self.output.push_str(self.line.trim_end());
+        self.output.push('\n');
+        self.line = INDENTATION.repeat(self.indent);
+    }
+
+    /// write `body` on the lines after this one, indented one level further
+    fn indented(&mut self, body: &Expression) {
+        self.indent += 1;
+        self.newline();
+        self.expression(body);
+        self.indent -= 1;
+    }
+
+    fn separated<T>(&mut self, items: &[T], mut write: impl FnMut(&mut Self, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            write(self, item);
+        }
+    }
+
+    fn arguments(&mut self, arguments: &[Expression]) {
+        self.write("(");
+        self.separated(arguments, Self::expression);
+        self.write(")");
+    }
+
+    fn binding(&mut self, Binding { id, typ }: &Binding) {
+        write!(self.line, "{}: {}", id, typ).unwrap();
+    }
+
+    /// write the function of an application, parenthesized unless it's a name or a call
+    fn callee(&mut self, function: &Expression) {
+        let mut inner = function;
+        while let Expression::Located { expression, .. } | Expression::Expanded { expression, .. } = inner {
+            inner = expression;
+        }
+        if matches!(inner, Expression::Variable(_) | Expression::Application { .. } | Expression::TypeApplication { .. }) {
+            self.expression(function);
+        } else {
+            self.write("(");
+            self.expression(function);
+            self.write(")");
+        }
+    }
+
+    fn conditional(&mut self, consequent: &Expression, alternative: &Expression) {
+        self.write(" then");
+        self.indented(consequent);
+        self.newline();
+        self.write("else");
+        self.indented(alternative);
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        if self.highlight.is_some_and(|highlight| ptr::eq(highlight, expr)) {
+            self.write("⟦");
+            self.term(expr);
+            self.write("⟧");
+        } else {
+            self.term(expr);
+        }
+    }
+
+    fn term(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Variable(id) => self.write(id),
+            Expression::Boolean(value) => write!(self.line, "{}", value).unwrap(),
+            Expression::Number(value) => self.write(&numbers::format(*value, 10)),
+            Expression::Float(Float(value)) => self.write(&numbers::format_float(*value)),
+            Expression::String(value) => write!(self.line, "{:?}", value).unwrap(),
+            Expression::Char(value) => write!(self.line, "{:?}", value).unwrap(),
+            Expression::Bytes(bytes) => write!(self.line, "bytes{:?}", bytes).unwrap(),
+            Expression::Function { parameters, body } => {
+                self.write("fn (");
+                self.separated(parameters, Self::binding);
+                self.write(") ->");
+                self.indented(body);
+            }
+            Expression::Application { function, arguments } => {
+                self.callee(function);
+                self.arguments(arguments);
+            }
+            Expression::If { condition, consequent, alternative } => {
+                self.write("if ");
+                self.expression(condition);
+                self.conditional(consequent, alternative);
+            }
+            Expression::TypeTest { expression, tag } => {
+                self.write("typeof(");
+                self.expression(expression);
+                write!(self.line, ") == \"{}\"", format!("{:?}", tag).to_lowercase()).unwrap();
+            }
+            Expression::IfTarget { target, consequent, alternative } => {
+                write!(self.line, "@if target == {:?}", target).unwrap();
+                self.conditional(consequent, alternative);
+            }
+            Expression::Constant(expression) => {
+                self.write("const(");
+                self.expression(expression);
+                self.write(")");
+            }
+            Expression::Total(expression) => {
+                self.write("total ");
+                self.expression(expression);
+            }
+            Expression::Located { span, expression } => {
+                if self.notes && self.region.is_none() && self.noted != Some(span.start.line) {
+                    self.region = Some(*span);
+                }
+                self.expression(expression);
+            }
+            Expression::Expanded { expression, .. } => self.expression(expression),
+            Expression::Primitive { operator, arguments } => {
+                write!(self.line, "{}", operator).unwrap();
+                self.arguments(arguments);
+            }
+            Expression::Tuple(elements) => {
+                self.arguments(elements);
+                if elements.len() == 1 {
+                    self.line.insert(self.line.len() - 1, ',');
+                }
+            }
+            Expression::Record(fields) => {
+                self.write("{");
+                self.separated(fields, |printer, (field, value)| {
+                    write!(printer.line, "{} = ", field).unwrap();
+                    printer.expression(value);
+                });
+                self.write("}");
+            }
+            Expression::Update { record, fields } => {
+                self.write("{");
+                self.expression(record);
+                self.write(" with ");
+                self.separated(fields, |printer, (field, value)| {
+                    write!(printer.line, "{} = ", field).unwrap();
+                    printer.expression(value);
+                });
+                self.write("}");
+            }
+            Expression::Variant { tag, payload } => {
+                self.write(tag);
+                if **payload != Expression::Tuple(vec![]) {
+                    self.write("(");
+                    self.expression(payload);
+                    self.write(")");
+                }
+            }
+            // a match with one arm is how lowering binds locals
+            Expression::Match { scrutinee, arms } => match &arms[..] {
+                [Arm { pattern, guard: None, body }] => {
+                    write!(self.line, "let {} = ", pattern).unwrap();
+                    self.expression(scrutinee);
+                    self.newline();
+                    self.expression(body);
+                }
+                _ => {
+                    self.write("match ");
+                    self.expression(scrutinee);
+                    self.indent += 1;
+                    for Arm { pattern, guard, body } in arms {
+                        self.newline();
+                        write!(self.line, "case {}", pattern).unwrap();
+                        if let Some(guard) = guard {
+                            self.write(" if ");
+                            self.expression(guard);
+                        }
+                        self.write(" ->");
+                        self.indented(body);
+                    }
+                    self.indent -= 1;
+                }
+            },
+            Expression::Loop { variables, body } => {
+                self.write("loop (");
+                self.separated(variables, |printer, (binding, init)| {
+                    printer.binding(binding);
+                    printer.write(" = ");
+                    printer.expression(init);
+                });
+                self.write(")");
+                self.indented(body);
+            }
+            Expression::Continue(arguments) => {
+                self.write("continue");
+                self.arguments(arguments);
+            }
+            Expression::Returning { result, body } => {
+                self.write("returning");
+                if let Some(result) = result {
+                    write!(self.line, " {}", result).unwrap();
+                }
+                self.indented(body);
+            }
+            Expression::Return(value) => {
+                self.write("return ");
+                self.expression(value);
+            }
+            Expression::TypeFunction { parameters, body } => {
+                self.write("forall<");
+                self.separated(parameters, |printer, parameter| write!(printer.line, "{}", parameter).unwrap());
+                self.write(">.");
+                self.indented(body);
+            }
+            Expression::TypeApplication { function, arguments } => {
+                self.callee(function);
+                self.write("<");
+                self.separated(arguments, |printer, typ| write!(printer.line, "{}", typ).unwrap());
+                self.write(">");
+            }
+        }
+    }
+}
+
+/// `expr` as text, one construct with a body per line. with the `source` it was lowered from,
+/// the comments about regions of the source quote their first lines.
+pub fn dump(expr: &Expression, source: Option<&str>) -> String {
+    print(expr, source, true, None)
+}
+
+/// `expr` as text without the comments about regions of the source, e.g. for a fragment of a
+/// program that's been taken out of it
+pub fn dump_fragment(expr: &Expression) -> String {
+    print(expr, None, false, None)
+}
+
+/// `expr` as text, with `redex`, one of its subterms, between `⟦` and `⟧`, e.g. to show which
+/// part of a term its next step contracts. it's written without the comments about regions.
+pub fn dump_redex(expr: &Expression, redex: &Expression) -> String {
+    print(expr, None, false, Some(redex))
+}
+
+fn print<'a>(expr: &Expression, source: Option<&'a str>, notes: bool, highlight: Option<&'a Expression>) -> String {
+    let mut printer = Printer {
+        source: source.map(|source| source.lines().collect()).unwrap_or_default(),
+        output: String::new(),
+        line: String::new(),
+        indent: 0,
+        region: None,
+        noted: None,
+        notes,
+        highlight,
+    };
+    printer.expression(expr);
+    printer.newline();
+    printer.output
+}
+
+/// the value `pattern` binds `name` to when it matches `value`, if that's a part of `value`
+fn bound<'a>(pattern: &Pattern, value: &'a Expression, name: &str) -> Option<&'a Expression> {
+    match (pattern, value) {
+        (Pattern::Variable(id), value) if id == name => Some(value),
+        (pattern, Expression::Located { expression, .. }) => bound(pattern, expression, name),
+        (Pattern::Tuple(patterns), Expression::Tuple(values)) => {
+            patterns.iter().zip(values).find_map(|(pattern, value)| bound(pattern, value, name))
+        }
+        _ => None,
+    }
+}
+
+/// the definition of `name` in `expr`, e.g. the function a `function name(...)` declaration was
+/// lowered to, for dumping just that definition. the outermost one is found first.
+pub fn definition<'a>(expr: &'a Expression, name: &str) -> Option<&'a Expression> {
+    if let Expression::Match { scrutinee, arms } = expr {
+        if let [Arm { pattern, guard: None, .. }] = &arms[..] {
+            if let Some(value) = bound(pattern, scrutinee, name) {
+                return Some(value)
+            }
+        }
+    }
+    expr.children().into_iter().find_map(|child| definition(child, name))
+}
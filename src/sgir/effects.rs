@@ -0,0 +1,19 @@
+use super::primitives::Primitive;
+use super::Expression;
+
+/// whether evaluating `expr` is pure: it can't call a function, and so can't reach a native,
+/// and it can't transfer control with `return` or `continue`. all a pure expression can do is
+/// produce a value or fail, and which depends only on the values of its free variables, so
+/// evaluating it again where they're the same would do exactly the same.
+pub fn is_pure(expr: &Expression) -> bool {
+    match expr {
+        Expression::Application { .. } | Expression::Return(_) | Expression::Continue(_) => false,
+        // folding characters calls the function it's given
+        Expression::Primitive { operator: Primitive::FoldChars, .. } => false,
+        // a loop may run forever, which is an effect worth keeping in its place
+        Expression::Loop { .. } => false,
+        // making a closure doesn't run its body
+        Expression::Function { .. } => true,
+        expr => expr.children().into_iter().all(is_pure),
+    }
+}
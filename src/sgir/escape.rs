@@ -0,0 +1,135 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::prelude::*;
+
+use super::patterns::{Arm, Pattern};
+use super::{Binding, Expression, Identifier};
+
+/// what a `let` allocates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AllocationKind {
+    Closure,
+    Tuple,
+    Record,
+    Variant,
+}
+
+/// a value allocated by a `let`, and whether it may outlive the `let`. one that doesn't escape
+/// can be allocated in an arena for the frame it's in, and freed with it, rather than on the
+/// heap.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Allocation {
+    pub name: Identifier,
+    pub kind: AllocationKind,
+    pub escapes: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EscapeReport {
+    pub allocations: Vec<Allocation>,
+}
+
+/// find which of the values allocated by `let`s in `expr` escape them. in administrative normal
+/// form, every allocation is bound by a `let`, so it sees them all.
+///
+/// a value escapes when it's used anywhere it might be kept: as the value of its scope, as an
+/// argument, inside another value, captured by a closure, or bound to another name. calling it,
+/// taking it apart with a pattern, testing it, and passing it to a primitive don't keep it.
+pub fn analyze(expr: &Expression) -> EscapeReport {
+    let mut report = EscapeReport::default();
+    allocations(expr, &mut report.allocations);
+    report
+}
+
+fn allocations(expr: &Expression, found: &mut Vec<Allocation>) {
+    if let Expression::Match { scrutinee, arms } = expr {
+        if let [Arm { pattern: Pattern::Variable(name), guard: None, body }] = &arms[..] {
+            let kind = match stripped(scrutinee) {
+                Expression::Function { .. } => Some(AllocationKind::Closure),
+                Expression::Tuple(_) => Some(AllocationKind::Tuple),
+                Expression::Record(_) | Expression::Update { .. } => Some(AllocationKind::Record),
+                Expression::Variant { .. } => Some(AllocationKind::Variant),
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                found.push(Allocation { name: name.clone(), kind, escapes: escapes(name, body) });
+            }
+        }
+    }
+    for child in expr.children() {
+        allocations(child, found);
+    }
+}
+
+/// whether the value of `id` may be kept by `expr`, or be its value
+fn escapes(id: &str, expr: &Expression) -> bool {
+    let is_id = |expr: &Expression| matches!(stripped(expr), Expression::Variable(variable) if variable == id);
+    // a use that doesn't keep the value, or anything else that might
+    let used = |expr: &Expression| !is_id(expr) && escapes(id, expr);
+    match expr {
+        Expression::Variable(variable) => variable == id,
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => escapes(id, expression),
+        Expression::Function { parameters, body } => {
+            !parameters.iter().any(|Binding { id: parameter, .. }| parameter == id) && body.free_variables().contains(id)
+        }
+        Expression::Application { function, arguments } => used(function) || arguments.iter().any(|argument| escapes(id, argument)),
+        Expression::Primitive { arguments, .. } => arguments.iter().any(used),
+        Expression::TypeTest { expression, .. } => used(expression),
+        Expression::If { condition, consequent, alternative } => {
+            used(condition) || escapes(id, consequent) || escapes(id, alternative)
+        }
+        Expression::Match { scrutinee, arms } => {
+            let kept = if is_id(scrutinee) {
+                arms.iter().any(|Arm { pattern, .. }| binds_whole(pattern))
+            } else {
+                escapes(id, scrutinee)
+            };
+            kept || arms.iter().any(|Arm { pattern, guard, body }| {
+                !pattern.variables().iter().any(|variable| variable == id)
+                    && (guard.iter().any(|guard| escapes(id, guard)) || escapes(id, body))
+            })
+        }
+        Expression::Loop { variables, body } => {
+            variables.iter().any(|(_, init)| escapes(id, init))
+                || (!variables.iter().any(|(Binding { id: variable, .. }, _)| variable == id) && escapes(id, body))
+        }
+        expr => expr.children().into_iter().any(|child| escapes(id, child)),
+    }
+}
+
+/// whether matching `pattern` binds the whole value to a variable
+fn binds_whole(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Variable(_) => true,
+        Pattern::Or(alternatives) => alternatives.iter().any(binds_whole),
+        _ => false,
+    }
+}
+
+fn stripped(expr: &Expression) -> &Expression {
+    match expr {
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => stripped(expression),
+        expr => expr,
+    }
+}
+
+impl Display for AllocationKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AllocationKind::Closure => write!(f, "closure"),
+            AllocationKind::Tuple => write!(f, "tuple"),
+            AllocationKind::Record => write!(f, "record"),
+            AllocationKind::Variant => write!(f, "variant"),
+        }
+    }
+}
+
+impl Display for EscapeReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for Allocation { name, kind, escapes } in &self.allocations {
+            writeln!(f, "{} {}: {}", kind, name, if *escapes { "escapes" } else { "frame" })?;
+        }
+        let local = self.allocations.iter().filter(|allocation| !allocation.escapes).count();
+        write!(f, "{} of {} allocations can live in their frame", local, self.allocations.len())
+    }
+}
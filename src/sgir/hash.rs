@@ -0,0 +1,144 @@
+//! stable structural hashes of expressions and types, which don't depend on the names of bound
+//! variables or on where in the source the code came from, so that alpha-equivalent definitions
+//! share a hash across runs, platforms, and versions of the compiler that keep the binary format.
+
+use core::fmt::{self, Display, Formatter};
+use core::mem;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use super::binary::Encoder;
+use super::patterns::Arm;
+use super::{Binding, Expression, Identifier, Type, TypeBinding};
+
+/// the hash of an expression or type, written as 16 hex digits
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StructuralHash(pub u64);
+
+impl Display for StructuralHash {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// the 64-bit FNV-1a hash of `bytes`, which, unlike the hasher of `std`, is the same everywhere
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// the names bound by the enclosing binders, each to its canonical name
+type Scope = HashMap<Identifier, Identifier>;
+
+/// renames every binder to `%n` for the nth binder from the root, in order, and drops
+/// locations, so that alpha-equivalent terms come out the same
+#[derive(Default)]
+struct Canonicalizer {
+    binders: usize,
+}
+
+impl Canonicalizer {
+    fn bind(&mut self, id: Identifier, scope: &mut Scope) -> Identifier {
+        self.binders += 1;
+        let canonical = format!("%{}", self.binders);
+        scope.insert(id, canonical.clone());
+        canonical
+    }
+
+    fn typ(&mut self, mut typ: Type, types: &Scope) -> Type {
+        match &mut typ {
+            Type::Variable(id) => types.get(id).map_or(typ, |canonical| Type::Variable(canonical.clone())),
+            Type::ForAll { parameters, typ } => {
+                let mut inner = types.clone();
+                let parameters = mem::take(parameters);
+                let parameters = parameters.into_iter()
+                                           .map(|TypeBinding { id, kind }| TypeBinding { id: self.bind(id, &mut inner), kind })
+                                           .collect();
+                Type::ForAll { parameters, typ: Box::new(self.typ(typ.take(), &inner)) }
+            }
+            _ => typ.map_children(|typ| self.typ(typ, types)),
+        }
+    }
+
+    fn expression(&mut self, mut expr: Expression, values: &Scope, types: &Scope) -> Expression {
+        match &mut expr {
+            Expression::Variable(id) => values.get(id).map_or(expr, |canonical| Expression::Variable(canonical.clone())),
+            Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => {
+                self.expression(expression.take(), values, types)
+            }
+            Expression::Function { parameters, body } => {
+                let mut inner = values.clone();
+                let parameters = mem::take(parameters);
+                let parameters = parameters.into_iter()
+                                           .map(|Binding { id, typ }| Binding { typ: self.typ(typ, types), id: self.bind(id, &mut inner) })
+                                           .collect();
+                Expression::Function { parameters, body: Box::new(self.expression(body.take(), &inner, types)) }
+            }
+            Expression::Match { scrutinee, arms } => {
+                let scrutinee = Box::new(self.expression(scrutinee.take(), values, types));
+                let arms = mem::take(arms);
+                let arms = arms.into_iter()
+                               .map(|Arm { pattern, guard, body }| {
+                                   let mut inner = values.clone();
+                                   let renaming: Scope = pattern.variables()
+                                                                .into_iter()
+                                                                .map(|id| (id.clone(), self.bind(id, &mut inner)))
+                                                                .collect();
+                                   Arm {
+                                       pattern: pattern.rename(&renaming),
+                                       guard: guard.map(|guard| self.expression(guard, &inner, types)),
+                                       body: self.expression(body, &inner, types),
+                                   }
+                               })
+                               .collect();
+                Expression::Match { scrutinee, arms }
+            }
+            Expression::Loop { variables, body } => {
+                // the initial values are in the scope around the loop
+                let mut inner = values.clone();
+                let variables = mem::take(variables);
+                let variables = variables.into_iter()
+                                         .map(|(Binding { id, typ }, init)| {
+                                             let init = self.expression(init, values, types);
+                                             (Binding { typ: self.typ(typ, types), id: self.bind(id, &mut inner) }, init)
+                                         })
+                                         .collect();
+                Expression::Loop { variables, body: Box::new(self.expression(body.take(), &inner, types)) }
+            }
+            Expression::Returning { result, body } => Expression::Returning {
+                result: result.take().map(|result| self.typ(result, types)),
+                body: Box::new(self.expression(body.take(), values, types)),
+            },
+            Expression::TypeFunction { parameters, body } => {
+                let mut inner = types.clone();
+                let parameters = mem::take(parameters);
+                let parameters = parameters.into_iter()
+                                           .map(|TypeBinding { id, kind }| TypeBinding { id: self.bind(id, &mut inner), kind })
+                                           .collect();
+                Expression::TypeFunction { parameters, body: Box::new(self.expression(body.take(), values, &inner)) }
+            }
+            Expression::TypeApplication { function, arguments } => Expression::TypeApplication {
+                function: Box::new(self.expression(function.take(), values, types)),
+                arguments: mem::take(arguments).into_iter().map(|typ| self.typ(typ, types)).collect(),
+            },
+            _ => expr.map_children(|expr| self.expression(expr, values, types)),
+        }
+    }
+}
+
+/// the structural hash of `expr`. expressions that differ only in the names of the variables
+/// they bind, or in their locations, hash the same. free variables are hashed by name.
+pub fn hash_expression(expr: &Expression) -> StructuralHash {
+    let canonical = Canonicalizer::default().expression(expr.clone(), &Scope::new(), &Scope::new());
+    let mut encoder = Encoder::new();
+    encoder.encode(&canonical);
+    StructuralHash(fnv1a(&encoder.finish()))
+}
+
+/// the structural hash of `typ`, which doesn't depend on the names of its quantified variables
+pub fn hash_type(typ: &Type) -> StructuralHash {
+    let canonical = Canonicalizer::default().typ(typ.clone(), &Scope::new());
+    let mut encoder = Encoder::new();
+    encoder.encode(&canonical);
+    StructuralHash(fnv1a(&encoder.finish()))
+}
@@ -0,0 +1,89 @@
+//! interfaces, the names a module exports and their types without their definitions, so that
+//! the modules that depend on it can be checked against its interface rather than against the
+//! whole of it. a module is a program file, it exports the variables its top level binds, and
+//! another program refers to them as `module.name`, by the name of the file without its extension.
+//! only the variables its `pub` declarations bind are exported. the rest are private, and an
+//! interface lists them by name too, so that a dependent that refers to one can be told why it
+//! can't.
+
+use crate::prelude::*;
+
+use super::binary::{DecodeError, Decoder, Encoder, Format, Version};
+use super::typed::{Node, TypedArm, TypedExpression};
+use super::{Declarations, Identifier, Type};
+use crate::collections::HashSet;
+
+/// the extension of an interface file, which is written next to the module it describes
+pub const EXTENSION: &str = "sangi";
+
+/// the format of interface files. 1.1 added floats to their types, and 1.2 the names of the
+/// private variables.
+pub const FORMAT: Format = Format { magic: b"SGIF", version: Version { major: 1, minor: 2 }, migrations: &[] };
+
+/// the names a module exports, in the order its top level binds them, with their types, and the
+/// names of the variables it keeps private
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Interface {
+    pub exports: Vec<(Identifier, Type)>,
+    pub private: Vec<Identifier>,
+}
+
+impl Interface {
+    /// the interface of the checked module `module`, whose top level exports the names in
+    /// `public`: the variables bound at its top level, by the chain of `let`s lowering makes of
+    /// it. a variable bound again shadows what it was before.
+    pub fn of(module: &TypedExpression, public: &[Identifier]) -> Interface {
+        let mut bound = Interface::default();
+        let mut expr = module;
+        loop {
+            match &expr.node {
+                Node::Located { expression, .. } | Node::Expanded { expression, .. } => expr = expression,
+                Node::Match { arms, .. } => match &arms[..] {
+                    [TypedArm { bindings, guard: None, body, .. }] => {
+                        for (_, binding) in bindings {
+                            bound.exports.retain(|(id, _)| *id != binding.id);
+                            bound.exports.push((binding.id.clone(), binding.typ.clone()));
+                        }
+                        expr = body;
+                    }
+                    _ => break,
+                },
+                _ => break,
+            }
+        }
+        let (exports, private): (Vec<_>, Vec<_>) = bound.exports.into_iter().partition(|(id, _)| public.contains(id));
+        Interface { exports, private: private.into_iter().map(|(id, _)| id).collect() }
+    }
+
+    /// the declarations a program that depends on the module named `module` is checked with,
+    /// each export as `module.name`
+    pub fn declarations(&self, module: &str) -> Declarations {
+        self.exports.iter()
+                    .map(|(id, typ)| (format!("{}.{}", module, id), typ.clone()))
+                    .collect()
+    }
+
+    /// the names the module named `module` keeps private, each as `module.name`, which a program
+    /// that depends on it can't refer to
+    pub fn private(&self, module: &str) -> HashSet<Identifier> {
+        self.private.iter().map(|id| format!("{}.{}", module, id)).collect()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.header(&FORMAT);
+        encoder.encode(&self.exports);
+        encoder.encode(&self.private);
+        encoder.finish()
+    }
+
+    /// read an interface that `encode` wrote. it has no values, so there are no natives to link.
+    /// the interfaces written before 1.2 export every variable, and so have none that are private.
+    pub fn decode(bytes: &[u8]) -> Result<Interface, DecodeError> {
+        let mut decoder = Decoder::new(bytes, &|_| None);
+        let version = decoder.header(&FORMAT)?;
+        let exports = decoder.decode()?;
+        let private = if version.minor >= 2 { decoder.decode()? } else { vec![] };
+        Ok(Interface { exports, private })
+    }
+}
@@ -0,0 +1,222 @@
+use crate::prelude::*;
+
+use super::blocks::let_in;
+use super::patterns::{Arm, Pattern};
+use super::primitives::Primitive;
+use super::{Binding, Expression, Identifier, Type, TypeError};
+
+// the frontend's loops all lower to a single `Loop` whose variables are the state the loop
+// updates. `break` is a tail expression that doesn't continue, and `continue` is a `Continue`
+// with the current values of the state.
+
+/// `while condition do state = step end`, with the value of `result` once `condition` fails
+pub fn while_loop(state: Vec<(Binding, Expression)>, condition: Expression, step: Vec<Expression>,
+                  result: Expression) -> Expression {
+    Expression::Loop {
+        variables: state,
+        body: Box::new(Expression::If {
+            condition: Box::new(condition),
+            consequent: Box::new(Expression::Continue(step)),
+            alternative: Box::new(result),
+        }),
+    }
+}
+
+/// `repeat state = step until condition`, with the value of `result` once `condition` holds.
+/// the step runs before the condition is first tested, and the condition sees the state it
+/// produced.
+pub fn repeat_until(state: Vec<(Binding, Expression)>, step: Vec<Expression>, condition: Expression,
+                    result: Expression) -> Expression {
+    let pattern = Pattern::Tuple(state.iter().map(|(binding, _)| Pattern::Variable(binding.id.clone())).collect());
+    let next = state.iter().map(|(binding, _)| Expression::Variable(binding.id.clone())).collect();
+    Expression::Loop {
+        variables: state,
+        // rebind the state to the values the step produced
+        body: Box::new(let_in(pattern, Expression::Tuple(step), Expression::If {
+            condition: Box::new(condition),
+            consequent: Box::new(result),
+            alternative: Box::new(Expression::Continue(next)),
+        })),
+    }
+}
+
+/// `while condition do body end`, where `body` produces a tuple of whether to stop, having broken
+/// out of the loop, followed by the state after it. its value is `result` once the body stops it
+/// or `condition` fails.
+pub fn while_body(state: Vec<(Binding, Expression)>, condition: Expression, body: Expression, result: Expression) -> Expression {
+    let next = variables(&state);
+    let iteration = iterate(&state, body, result.clone(), next);
+    Expression::Loop {
+        variables: state,
+        body: Box::new(Expression::If { condition: Box::new(condition), consequent: Box::new(iteration), alternative: Box::new(result) }),
+    }
+}
+
+/// `repeat body until condition`, where `body` produces a tuple of whether to stop, having broken
+/// out of the loop or found the condition to hold at its end, followed by the state after it.
+/// testing the condition is left to the body so that it can see the body's locals.
+pub fn repeat_body(state: Vec<(Binding, Expression)>, body: Expression, result: Expression) -> Expression {
+    let next = variables(&state);
+    let iteration = iterate(&state, body, result, next);
+    Expression::Loop { variables: state, body: Box::new(iteration) }
+}
+
+/// the variables of a loop's state, as the values to continue it with
+fn variables(state: &[(Binding, Expression)]) -> Vec<Expression> {
+    state.iter().map(|(binding, _)| Expression::Variable(binding.id.clone())).collect()
+}
+
+/// an iteration that runs `body`, which produces whether to stop along with the state after it,
+/// then gives `result` if it stops, or continues with `next` if it doesn't
+fn iterate(state: &[(Binding, Expression)], body: Expression, result: Expression, next: Vec<Expression>) -> Expression {
+    let pattern = Pattern::Tuple(core::iter::once(STOPS.to_owned()).chain(state.iter().map(|(binding, _)| binding.id.clone()))
+                                                                  .map(Pattern::Variable)
+                                                                  .collect());
+    let_in(pattern, body, Expression::If {
+        condition: Box::new(Expression::Variable(STOPS.to_owned())),
+        consequent: Box::new(result),
+        alternative: Box::new(Expression::Continue(next)),
+    })
+}
+
+/// `for variable = start, stop do state = step end`, counting up by one to `stop` inclusive. `stop`
+/// is evaluated once, before the first iteration.
+pub fn for_range(variable: &str, start: Expression, stop: Expression, mut state: Vec<(Binding, Expression)>,
+                 mut step: Vec<Expression>, result: Expression) -> Expression {
+    let counter = Expression::Variable(variable.to_owned());
+    let stop_variable = Expression::Variable(STOP.to_owned());
+    state.splice(0..0, [(Binding { id: variable.to_owned(), typ: Type::Number }, start),
+                        (Binding { id: STOP.to_owned(), typ: Type::Number }, stop)]);
+    step.splice(0..0, [Expression::Primitive { operator: Primitive::Add, arguments: vec![counter.clone(), Expression::Number(1)] },
+                       stop_variable.clone()]);
+    Expression::Loop {
+        variables: state,
+        body: Box::new(Expression::If {
+            condition: Box::new(Expression::Primitive { operator: Primitive::Less, arguments: vec![stop_variable, counter] }),
+            consequent: Box::new(result),
+            alternative: Box::new(Expression::Continue(step)),
+        }),
+    }
+}
+
+/// `for variable = start, stop, step do body end` like `for_range`, where `body` produces a tuple
+/// of whether to stop, having broken out of the loop, followed by the state after it. without a
+/// `step`, it counts up by one. a `step` is evaluated once, like `stop`: a negative one counts
+/// down to `stop`, and a step of zero runs the body no times, where Luau would raise an error.
+pub fn for_range_body(variable: &str, start: Expression, stop: Expression, step: Option<Expression>,
+                      mut state: Vec<(Binding, Expression)>, body: Expression, result: Expression) -> Expression {
+    let counter = Expression::Variable(variable.to_owned());
+    let stop_variable = Expression::Variable(STOP.to_owned());
+    let less = |left, right| Expression::Primitive { operator: Primitive::Less, arguments: vec![left, right] };
+    let mut counters = vec![(Binding { id: variable.to_owned(), typ: Type::Number }, start),
+                            (Binding { id: STOP.to_owned(), typ: Type::Number }, stop)];
+    let (increment, done) = match step {
+        None => (Expression::Number(1), less(stop_variable.clone(), counter.clone())),
+        Some(step) => {
+            counters.push((Binding { id: STEP.to_owned(), typ: Type::Number }, step));
+            let step_variable = Expression::Variable(STEP.to_owned());
+            let done = Expression::If {
+                condition: Box::new(less(Expression::Number(0), step_variable.clone())),
+                consequent: Box::new(less(stop_variable.clone(), counter.clone())),
+                alternative: Box::new(Expression::If {
+                    condition: Box::new(less(step_variable.clone(), Expression::Number(0))),
+                    consequent: Box::new(less(counter.clone(), stop_variable.clone())),
+                    alternative: Box::new(Expression::Boolean(true)),
+                }),
+            };
+            (step_variable, done)
+        }
+    };
+    let next = core::iter::once(Expression::Primitive { operator: Primitive::Add, arguments: vec![counter.clone(), increment] })
+        .chain(counters[1..].iter().map(|(binding, _)| Expression::Variable(binding.id.clone())));
+    let iteration = iterate(&state, body, result.clone(), next.chain(variables(&state)).collect());
+    state.splice(0..0, counters);
+    Expression::Loop {
+        variables: state,
+        body: Box::new(Expression::If {
+            condition: Box::new(done),
+            consequent: Box::new(result),
+            alternative: Box::new(iteration),
+        }),
+    }
+}
+
+/// the bound and the step of a numeric for, which no program can refer to
+const STOP: &str = "%stop";
+const STEP: &str = "%step";
+/// whether an iteration stops its loop
+const STOPS: &str = "%stops";
+/// the parts of the iterator a generic for is running
+const NEXT: &str = "%next";
+const POSITION: &str = "%position";
+
+/// the type of an iterator over `item`s. an iterator is a `state` and a `next` function, which
+/// takes a state to the next item along with the state after it, or to `Done` at the end.
+pub fn iterator_type(item: Type, state: Type) -> Type {
+    let next = Type::Variant(vec![("Next".to_owned(), Type::Tuple(vec![item, state.clone()])),
+                                  ("Done".to_owned(), Type::Tuple(vec![]))]);
+    Type::Record(vec![("next".to_owned(), Type::Function { arguments: vec![state.clone()], result: Box::new(next) }),
+                      ("state".to_owned(), state)])
+}
+
+/// the type of the items of an iterator of type `typ`, and the type of its state
+pub fn iterator_item(typ: &Type) -> Option<(Type, Type)> {
+    fn field<'a>(fields: &'a [(Identifier, Type)], name: &str) -> Option<&'a Type> {
+        fields.iter().find(|(id, _)| id == name).map(|(_, typ)| typ)
+    }
+    let Type::Record(fields) = typ else { return None };
+    let state = field(fields, "state")?;
+    let Some(Type::Function { arguments, result }) = field(fields, "next") else { return None };
+    let Type::Variant(cases) = &**result else { return None };
+    match (&arguments[..], field(cases, "Next")?, field(cases, "Done")?) {
+        ([argument], Type::Tuple(next), Type::Tuple(done)) if argument == state && done.is_empty() => match &next[..] {
+            [item, after] if after == state => Some((item.clone(), state.clone())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `for variable in iterable do state = step end`, where `iterable` is an iterator of type
+/// `iterable_type`. the loop variable has the iterator's item type.
+pub fn for_in(variable: &str, iterable: Expression, iterable_type: &Type, state: Vec<(Binding, Expression)>,
+              mut step: Vec<Expression>, result: Expression) -> Result<Expression, TypeError> {
+    step.insert(0, Expression::Variable(POSITION.to_owned()));
+    iterating(variable, iterable, iterable_type, state, Expression::Continue(step), result)
+}
+
+/// `for variable in iterable do body end` like `for_in`, where `body` produces a tuple of whether
+/// to stop, having broken out of the loop, followed by the state after it
+pub fn for_in_body(variable: &str, iterable: Expression, iterable_type: &Type, state: Vec<(Binding, Expression)>,
+                   body: Expression, result: Expression) -> Result<Expression, TypeError> {
+    let next = core::iter::once(Expression::Variable(POSITION.to_owned())).chain(variables(&state)).collect();
+    let iteration = iterate(&state, body, result.clone(), next);
+    iterating(variable, iterable, iterable_type, state, iteration, result)
+}
+
+/// a loop over `iterable` whose iterations are `iteration`, with the loop variable and the position
+/// after it bound
+fn iterating(variable: &str, iterable: Expression, iterable_type: &Type, mut state: Vec<(Binding, Expression)>,
+             iteration: Expression, result: Expression) -> Result<Expression, TypeError> {
+    let (_, state_type) = iterator_item(iterable_type).ok_or_else(|| TypeError::NotIterable(iterable_type.clone()))?;
+    let position = || Expression::Variable(POSITION.to_owned());
+    state.insert(0, (Binding { id: POSITION.to_owned(), typ: state_type }, position()));
+
+    let iterator = Pattern::Record(vec![("next".to_owned(), Pattern::Variable(NEXT.to_owned())),
+                                        ("state".to_owned(), Pattern::Variable(POSITION.to_owned()))]);
+    let next = Pattern::Variant {
+        tag: "Next".to_owned(),
+        payload: Box::new(Pattern::Tuple(vec![Pattern::Variable(variable.to_owned()), Pattern::Variable(POSITION.to_owned())])),
+    };
+    let done = Pattern::Variant { tag: "Done".to_owned(), payload: Box::new(Pattern::Tuple(vec![])) };
+    let advance = Expression::Application { function: Box::new(Expression::Variable(NEXT.to_owned())), arguments: vec![position()] };
+    let body = Expression::Loop {
+        variables: state,
+        body: Box::new(Expression::Match {
+            scrutinee: Box::new(advance),
+            arms: vec![Arm { pattern: next, guard: None, body: iteration },
+                       Arm { pattern: done, guard: None, body: result }],
+        }),
+    };
+    Ok(let_in(iterator, iterable, body))
+}
@@ -0,0 +1,268 @@
+use core::mem;
+
+use crate::collections::{HashMap, HashSet};
+use crate::prelude::*;
+
+use thiserror::Error;
+
+use super::patterns::Arm;
+use super::{Binding, Expression, Identifier};
+
+/// a supply of names that can't clash with any written by the user. a pass keeps a single
+/// supply for all of its renaming, so that no two of the names it introduces are the same.
+#[derive(Debug, Default)]
+pub struct FreshNames {
+    counter: usize,
+}
+
+impl FreshNames {
+    pub fn fresh(&mut self, base: &str) -> Identifier {
+        self.counter += 1;
+        format!("{}%{}", base, self.counter)
+    }
+}
+
+/// a fresh name for the binder `id` that's none of the variables in `avoid`, which may have come
+/// from another supply
+fn fresh_avoiding(id: &str, avoid: &HashSet<Identifier>, names: &mut FreshNames) -> Identifier {
+    loop {
+        let fresh = names.fresh(id);
+        if !avoid.contains(&fresh) {
+            return fresh
+        }
+    }
+}
+
+/// replace the free occurrences of variables in `expr` according to `replacements`, renaming
+/// binders that would otherwise capture a free variable of a replacement
+pub fn substitute(mut expr: Expression,
+                  replacements: &HashMap<Identifier, Expression>,
+                  names: &mut FreshNames) -> Expression {
+    match &mut expr {
+        Expression::Variable(id) => match replacements.get(id) {
+            Some(replacement) => replacement.clone(),
+            None => expr,
+        }
+
+        Expression::Function { parameters, body } => {
+            let captured: HashSet<_> = replacements.values()
+                                                   .flat_map(Expression::free_variables)
+                                                   .collect();
+
+            let mut inner = replacements.clone();
+            let mut renamed = vec![];
+            for Binding { id, typ } in mem::take(parameters) {
+                // the parameter shadows any replacement for it
+                inner.remove(&id);
+                if captured.contains(&id) {
+                    let fresh = fresh_avoiding(&id, &captured, names);
+                    inner.insert(id, Expression::Variable(fresh.clone()));
+                    renamed.push(Binding { id: fresh, typ });
+                } else {
+                    renamed.push(Binding { id, typ });
+                }
+            }
+
+            Expression::Function { parameters: renamed, body: Box::new(substitute(body.take(), &inner, names)) }
+        }
+
+        Expression::Match { scrutinee, arms } => {
+            let captured: HashSet<_> = replacements.values()
+                                                   .flat_map(Expression::free_variables)
+                                                   .collect();
+
+            let scrutinee = Box::new(substitute(scrutinee.take(), replacements, names));
+            let arms = mem::take(arms).into_iter().map(|Arm { pattern, guard, body }| {
+                let mut inner = replacements.clone();
+                let mut renaming = HashMap::new();
+                for id in pattern.variables() {
+                    // the pattern's variables shadow any replacements for them
+                    inner.remove(&id);
+                    if captured.contains(&id) {
+                        let fresh = fresh_avoiding(&id, &captured, names);
+                        inner.insert(id.clone(), Expression::Variable(fresh.clone()));
+                        renaming.insert(id, fresh);
+                    }
+                }
+                Arm { pattern: pattern.rename(&renaming),
+                      guard: guard.map(|guard| substitute(guard, &inner, names)),
+                      body: substitute(body, &inner, names) }
+            }).collect();
+
+            Expression::Match { scrutinee, arms }
+        }
+
+        Expression::Loop { variables, body } => {
+            let captured: HashSet<_> = replacements.values()
+                                                   .flat_map(Expression::free_variables)
+                                                   .collect();
+
+            let mut inner = replacements.clone();
+            let mut renamed = vec![];
+            for (Binding { id, typ }, init) in mem::take(variables) {
+                let init = substitute(init, replacements, names);
+                // the loop variable shadows any replacement for it
+                inner.remove(&id);
+                if captured.contains(&id) {
+                    let fresh = fresh_avoiding(&id, &captured, names);
+                    inner.insert(id, Expression::Variable(fresh.clone()));
+                    renamed.push((Binding { id: fresh, typ }, init));
+                } else {
+                    renamed.push((Binding { id, typ }, init));
+                }
+            }
+
+            Expression::Loop { variables: renamed, body: Box::new(substitute(body.take(), &inner, names)) }
+        }
+
+        _ => expr.map_children(|child| substitute(child, replacements, names)),
+    }
+}
+
+/// how deeply the expansions of macros may nest before one is assumed to recur forever
+pub const MAX_EXPANSION_DEPTH: usize = 64;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ExpansionError {
+    #[error("macro {name} expects {expected} arguments, found {found}")]
+    ArityMismatch {
+        name: Identifier,
+        expected: usize,
+        found: usize,
+    },
+    #[error("the expansion of macro {name} is nested more than {limit} deep; does it expand to itself?")]
+    TooDeep {
+        name: Identifier,
+        limit: usize,
+    },
+}
+
+/// a syntactic abstraction: applying it substitutes the argument expressions for its
+/// parameters in the template, without evaluating them
+#[derive(Clone, Debug)]
+pub struct Macro {
+    pub parameters: Vec<Identifier>,
+    pub template: Expression,
+}
+
+impl Macro {
+    /// the variables of the template that aren't its parameters, which refer to the bindings in
+    /// scope where the macro is defined
+    pub fn free_variables(&self) -> HashSet<Identifier> {
+        let mut free = self.template.free_variables();
+        for parameter in &self.parameters {
+            free.remove(parameter);
+        }
+        free
+    }
+}
+
+pub type MacroEnv = HashMap<Identifier, Macro>;
+
+/// expand every application of a macro in `expr`. expansion is hygienic in both directions:
+/// binders introduced by a template never capture variables from the arguments it is applied
+/// to, and binders around the application never capture the template's free variables, which
+/// still refer to what they did where the macro was defined.
+pub fn expand_macros(expr: Expression, macros: &MacroEnv, names: &mut FreshNames) -> Result<Expression, ExpansionError> {
+    expand(expr, macros, names, 0)
+}
+
+/// a fresh name for the binder `id` if a template in `macros` has a free variable of the same
+/// name, which the binder would otherwise capture in an expansion
+fn protect(id: &Identifier, macros: &MacroEnv, names: &mut FreshNames) -> Option<Identifier> {
+    let free: HashSet<_> = macros.values().flat_map(Macro::free_variables).collect();
+    free.contains(id).then(|| fresh_avoiding(id, &free, names))
+}
+
+fn expand(mut expr: Expression, macros: &MacroEnv, names: &mut FreshNames, depth: usize) -> Result<Expression, ExpansionError> {
+    match &mut expr {
+        Expression::Application { function, arguments } => match &**function {
+            Expression::Variable(name) if macros.contains_key(name) => {
+                let Macro { parameters, template } = &macros[name];
+                if parameters.len() != arguments.len() {
+                    return Err(ExpansionError::ArityMismatch { name: name.clone(),
+                                                               expected: parameters.len(),
+                                                               found: arguments.len() })
+                }
+                if depth == MAX_EXPANSION_DEPTH {
+                    return Err(ExpansionError::TooDeep { name: name.clone(), limit: MAX_EXPANSION_DEPTH })
+                }
+
+                let arguments = mem::take(arguments).into_iter()
+                                                    .map(|argument| expand(argument, macros, names, depth))
+                                                    .collect::<Result<Vec<_>, _>>()?;
+                let replacements = parameters.iter().cloned().zip(arguments).collect();
+                let expansion = substitute(template.clone(), &replacements, names);
+
+                // the expansion may itself use macros
+                expand(expansion, macros, names, depth + 1)
+            }
+            _ => {
+                let function = Box::new(expand(function.take(), macros, names, depth)?);
+                let arguments = mem::take(arguments).into_iter()
+                                                    .map(|argument| expand(argument, macros, names, depth))
+                                                    .collect::<Result<_, _>>()?;
+                Ok(Expression::Application { function, arguments })
+            }
+        }
+
+        // a parameter with the same name as a macro shadows it
+        Expression::Function { parameters, body } => {
+            let mut inner = macros.clone();
+            for Binding { id, .. } in parameters.iter() {
+                inner.remove(id);
+            }
+            let mut renaming = HashMap::new();
+            for Binding { id, .. } in parameters.iter_mut() {
+                if let Some(fresh) = protect(id, &inner, names) {
+                    renaming.insert(mem::replace(id, fresh.clone()), Expression::Variable(fresh));
+                }
+            }
+            let body = substitute(body.take(), &renaming, names);
+            let body = Box::new(expand(body, &inner, names, depth)?);
+            Ok(Expression::Function { parameters: mem::take(parameters), body })
+        }
+
+        // and so does a variable bound by a pattern
+        Expression::Match { scrutinee, arms } => {
+            let scrutinee = Box::new(expand(scrutinee.take(), macros, names, depth)?);
+            let arms = mem::take(arms).into_iter().map(|Arm { pattern, guard, body }| {
+                let mut inner = macros.clone();
+                for id in pattern.variables() {
+                    inner.remove(&id);
+                }
+                let renamed: HashMap<_, _> = pattern.variables()
+                                                    .into_iter()
+                                                    .filter_map(|id| Some((id.clone(), protect(&id, &inner, names)?)))
+                                                    .collect();
+                let renaming = renamed.iter()
+                                      .map(|(id, fresh)| (id.clone(), Expression::Variable(fresh.clone())))
+                                      .collect();
+                let pattern = pattern.rename(&renamed);
+                let guard = guard.map(|guard| expand(substitute(guard, &renaming, names), &inner, names, depth)).transpose()?;
+                Ok(Arm { guard, body: expand(substitute(body, &renaming, names), &inner, names, depth)?, pattern })
+            }).collect::<Result<_, _>>()?;
+            Ok(Expression::Match { scrutinee, arms })
+        }
+
+        // and so does a loop variable
+        Expression::Loop { variables, body } => {
+            let mut inner = macros.clone();
+            for (Binding { id, .. }, _) in variables.iter() {
+                inner.remove(id);
+            }
+            let mut renaming = HashMap::new();
+            let variables = mem::take(variables).into_iter().map(|(mut binding, init)| {
+                if let Some(fresh) = protect(&binding.id, &inner, names) {
+                    renaming.insert(mem::replace(&mut binding.id, fresh.clone()), Expression::Variable(fresh));
+                }
+                Ok((binding, expand(init, macros, names, depth)?))
+            }).collect::<Result<_, _>>()?;
+            let body = substitute(body.take(), &renaming, names);
+            let body = Box::new(expand(body, &inner, names, depth)?);
+            Ok(Expression::Loop { variables, body })
+        }
+
+        _ => expr.try_map_children(|child| expand(child, macros, names, depth)),
+    }
+}
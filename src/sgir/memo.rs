@@ -0,0 +1,106 @@
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use super::{Binding, Environment, Expression, Identifier, Value};
+
+/// a value with nothing to it but structure, so that it can be hashed and compared cheaply. a
+/// closure is its code and the values of the variables it captures, rather than its whole
+/// environment.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Boolean(bool),
+    Number(i64),
+    /// the bits of a float, which is never NaN
+    Float(u64),
+    String(String),
+    Char(char),
+    Bytes(Vec<u8>),
+    Tuple(Vec<Key>),
+    Record(Vec<(Identifier, Key)>),
+    Variant {
+        tag: Identifier,
+        payload: Box<Key>,
+    },
+    Function {
+        parameters: Vec<Binding>,
+        body: Expression,
+        captured: Vec<(Identifier, Key)>,
+    },
+}
+
+/// the key for `value`, if it's pure: no native can be reached from it, whether inside it or
+/// captured by a closure in it. natives are the only effects in SGIR, so calling a pure function
+/// with pure arguments always has the same result, and no other consequence.
+fn key(value: &Value) -> Option<Key> {
+    Some(match value {
+        Value::Boolean(value) => Key::Boolean(*value),
+        Value::Number(value) => Key::Number(*value),
+        Value::Float(value) => Key::Float(value.to_bits()),
+        Value::String(value) => Key::String(value.clone()),
+        Value::Char(value) => Key::Char(*value),
+        Value::Bytes(value) => Key::Bytes(value.clone()),
+        Value::Tuple(values) => Key::Tuple(values.iter().map(key).collect::<Option<_>>()?),
+        Value::Record(fields) => Key::Record(fields.iter()
+                                                   .map(|(field, value)| Some((field.clone(), key(value)?)))
+                                                   .collect::<Option<_>>()?),
+        Value::Variant { tag, payload } => Key::Variant { tag: tag.clone(), payload: Box::new(key(payload)?) },
+        Value::Function { parameters, body, environment } => function_key(parameters, body, environment)?,
+        Value::Native(_) => return None,
+    })
+}
+
+fn function_key(parameters: &[Binding], body: &Expression, environment: &Environment) -> Option<Key> {
+    let mut free = body.free_variables();
+    for Binding { id, .. } in parameters {
+        free.remove(id);
+    }
+    let mut captured = free.into_iter()
+                           .map(|id| {
+                               let value = key(environment.lookup(&id)?)?;
+                               Some((id, value))
+                           })
+                           .collect::<Option<Vec<_>>>()?;
+    captured.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Some(Key::Function { parameters: parameters.to_vec(), body: body.clone(), captured })
+}
+
+/// the results of calls to pure functions, for an interpreter that memoizes them. this is
+/// opt-in: it trades memory for time, and a call that's remembered takes no steps and records
+/// no coverage.
+#[derive(Debug, Default)]
+pub struct Memo {
+    results: HashMap<(Key, Vec<Key>), Value>,
+    /// the number of calls answered from the memo
+    pub hits: usize,
+}
+
+impl Memo {
+    /// the key for a call of `function` with `arguments`, if the call is pure
+    pub(super) fn call(function: &Value, arguments: &[Value]) -> Option<CallKey> {
+        Some(CallKey((key(function)?, arguments.iter().map(key).collect::<Option<_>>()?)))
+    }
+
+    pub(super) fn lookup(&mut self, call: &CallKey) -> Option<Value> {
+        let result = self.results.get(&call.0).cloned();
+        if result.is_some() {
+            self.hits += 1;
+        }
+        result
+    }
+
+    pub(super) fn remember(&mut self, call: CallKey, result: Value) {
+        self.results.insert(call.0, result);
+    }
+
+    /// the number of calls remembered
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+/// a pure call, as the memo knows it
+pub(super) struct CallKey((Key, Vec<Key>));
@@ -1,27 +1,120 @@
-use std::collections::HashMap;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::convert::Infallible;
+use core::fmt::{self, Debug, Display, Formatter};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use crate::collections::{HashMap, HashSet};
+use crate::prelude::*;
+
+use numbers::Float;
+use patterns::{Arm, Pattern};
+use primitives::{Encoding, Primitive};
 use thiserror::Error;
 
+pub mod anf;
+pub mod arguments;
+#[cfg(feature = "std")]
+pub mod bench;
+pub mod blocks;
+pub mod binary;
+pub mod constants;
+pub mod contracts;
+pub mod coverage;
+pub mod cse;
+mod deep;
+pub mod diff;
+pub mod doc;
+pub mod dump;
+pub mod effects;
+pub mod escape;
+pub mod hash;
+pub mod interface;
+pub mod loops;
+pub mod macros;
+pub mod memo;
+pub mod multiple;
+pub mod numbers;
+pub mod operators;
+pub mod partial;
+pub mod patterns;
+pub mod pretty;
+pub mod primitives;
+pub mod program;
+pub mod resolve;
+pub mod step;
+pub mod target;
+pub mod termination;
+pub mod testing;
+pub mod typed;
+pub mod usage;
+
 #[cfg(test)]
 mod tests;
 
 type Identifier = String;
 
-#[derive(Clone, Debug, PartialEq)]
+/// a position in source text, counting lines and columns from 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// the region of source text an expression was lowered from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// a construct of the surface syntax that lowering expanded into other code, e.g.
+/// `a type ascription`, and where it was
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Expansion {
+    pub construct: String,
+    pub span: Span,
+}
+
+/// where in the source a diagnostic is about: `span`, and, when it's in code lowering
+/// generated, the constructs that code was expanded from, innermost first
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Provenance {
+    pub span: Span,
+    pub expanded_from: Vec<Expansion>,
+}
+
+impl Display for Provenance {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.span.start.line, self.span.start.column)?;
+        for Expansion { construct, span } in &self.expanded_from {
+            write!(f, ", expanded from {} at {}:{}", construct, span.start.line, span.start.column)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Kind {
     Star,
+    /// a kind variable, e.g. `k`, standing for any kind
+    Variable(Identifier),
     Arrow {
         from: Vec<Kind>,
         to: Box<Kind>,
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TypeBinding {
     pub id: Identifier,
     pub kind: Kind,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Type {
     /// a type variable, e.g. `T`
     Variable(Identifier),
@@ -44,129 +137,2043 @@ pub enum Type {
         arguments: Vec<Type>,
         result: Box<Type>,
     },
+    /// an intersection of types, e.g. `((Number) -> Number) & ((Boolean) -> Boolean)`,
+    /// used to give a single function value several overloaded arrow types
+    Intersection(Vec<Type>),
+    /// a union of types, e.g. `Number | Boolean`
+    Union(Vec<Type>),
     /// a boolean
     Boolean,
     /// a number
     Number,
+    /// a 64-bit floating point number, which is never NaN or infinite
+    Float,
+    /// a string of unicode text
+    String,
+    /// a single unicode scalar value
+    Char,
+    /// a buffer of raw bytes
+    Bytes,
+    /// a tuple, e.g. `(Number, Boolean)`. the empty tuple `()` is the unit type.
+    Tuple(Vec<Type>),
+    /// a record, e.g. `{x: Number, y: Number}`. a record with more fields is a subtype of one
+    /// with fewer.
+    Record(Vec<(Identifier, Type)>),
+    /// a variant, e.g. `variant { Some(Number), None }`, whose values are one of its cases tagged
+    /// with the case's name. a variant with fewer cases is a subtype of one with more.
+    Variant(Vec<(Identifier, Type)>),
+    /// any number of values of a type, as a tuple, e.g. `...Number`. as the type of a function's
+    /// last parameter, it makes the function variadic: the extra arguments are packed into it.
+    Rest(Box<Type>),
 }
 
 #[derive(Debug, Error, Clone, PartialEq)]
-enum TypeError {
-    #[error("kind mismatch: expected {expected:?}, found {found:?}")]
+pub enum TypeError {
+    #[error("kind mismatch: expected {expected}, found {found}")]
     KindMismatch {
         expected: Kind,
         found: Kind,
     },
 
-    #[error("kind mismatch: expected a quantifier in type {found:?}")]
+    #[error("kind mismatch: expected a quantifier in type {found}")]
     ExpectedQuantifier {
         found: Type,
     },
 
+    #[error("the type argument {parameter} of the generic function can't be inferred from its arguments; instantiate it explicitly")]
+    UninferredTypeArgument {
+        parameter: Identifier,
+    },
+
+    #[error("infinite kind: {variable} occurs in {kind}")]
+    InfiniteKind {
+        variable: Identifier,
+        kind: Kind,
+    },
+
     #[error("unbound identifier: {0}")]
     UnboundIdentifier(Identifier),
+
+    #[error("type mismatch: expected {expected}, found {found}")]
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+    },
+
+    #[error("type mismatch: expected a function, found {found}")]
+    ExpectedFunction {
+        found: Type,
+    },
+
+    #[error("type mismatch: expected a record, found {found}")]
+    ExpectedRecord {
+        found: Type,
+    },
+
+    #[error("type mismatch: expected a tuple of arguments, found {found}")]
+    ExpectedRest {
+        found: Type,
+    },
+
+    #[error("arity mismatch: expected {expected} arguments, found {found}")]
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("values of type {found} cannot be compared")]
+    NotComparable {
+        found: Type,
+    },
+
+    #[error("no overload of {found} accepts the arguments {}", Type::Tuple(arguments.clone()))]
+    NoMatchingOverload {
+        found: Type,
+        arguments: Vec<Type>,
+    },
+
+    #[error("the pattern {pattern} can't match values of type {typ}")]
+    PatternMismatch {
+        pattern: Pattern,
+        typ: Type,
+    },
+
+    /// a variable of a pattern, a parameter, a type parameter, or a record's field bound twice
+    /// by the same binder, with where each of the two is when it's known
+    #[error("{id} is bound more than once{}", twice(first, second))]
+    DuplicateBinding {
+        id: Identifier,
+        first: Option<Span>,
+        second: Option<Span>,
+    },
+
+    #[error("{0} must be bound at the same type by every alternative of an or-pattern")]
+    InconsistentAlternatives(Identifier),
+
+    #[error("continue may only appear in tail position in the body of a loop")]
+    MisplacedContinue,
+
+    #[error("return may only appear in the body of a function")]
+    MisplacedReturn,
+
+    #[error("{0} is not an iterator")]
+    NotIterable(Type),
+
+    #[error("no argument was given for the parameter {0}, which has no default")]
+    MissingArgument(Identifier),
+
+    #[error("more than one argument was given for the parameter {0}")]
+    DuplicateArgument(Identifier),
+
+    #[error("there is no parameter named {0}")]
+    UnknownArgument(Identifier),
+
+    #[error("the default for {0} refers to variables, but defaults must be closed")]
+    NonConstantDefault(Identifier),
+
+    #[error("non-exhaustive match: {missing} is not covered")]
+    NonExhaustive {
+        missing: Pattern,
+    },
+
+    #[error("a total function may not terminate: no variable of the loop over {} decreases on every continue", variables.join(", "))]
+    MayNotTerminate {
+        variables: Vec<Identifier>,
+    },
+
+    #[error("a constant can't be evaluated at compile time: {0}")]
+    Constant(Box<constants::ConstantError>),
+
+    /// an error in a program lowered from source, with where it is
+    #[error("{error} at {provenance}")]
+    Located {
+        error: Box<TypeError>,
+        provenance: Provenance,
+    },
+}
+
+/// ` at line:column and line:column` for a binding at `first` and again at `second`, or as much
+/// of that as is known
+fn twice(first: &Option<Span>, second: &Option<Span>) -> String {
+    match (first, second) {
+        (Some(first), Some(second)) => format!(" at {}:{} and {}:{}", first.start.line, first.start.column, second.start.line, second.start.column),
+        (Some(span), None) | (None, Some(span)) => format!(" at {}:{}", span.start.line, span.start.column),
+        (None, None) => String::new(),
+    }
+}
+
+/// check that no two of `bound`, each of which is bound by the same binder, have the same name
+fn distinct<'a>(bound: impl IntoIterator<Item = (&'a Identifier, Option<Span>)>) -> TC<()> {
+    let mut seen = HashMap::new();
+    for (id, span) in bound {
+        if let Some(first) = seen.insert(id, span) {
+            return Err(TypeError::DuplicateBinding { id: id.clone(), first, second: span })
+        }
+    }
+    Ok(())
+}
+
+impl TypeError {
+    /// where in the source the error is, when it's known
+    pub fn provenance(&self) -> Option<&Provenance> {
+        match self {
+            TypeError::Located { provenance, .. } => Some(provenance),
+            _ => None,
+        }
+    }
 }
 
 type TC<T> = Result<T, TypeError>;
 
 type KindEnv = HashMap<Identifier, Kind>;
 
+/// kind inference by unification. kind variables appearing in the binders of a `ForAll` that
+/// aren't fixed by the enclosing environment are generalized, so every instantiation of the
+/// quantifier may pick different kinds for them.
+#[derive(Default)]
+struct KindChecker {
+    substitution: HashMap<Identifier, Kind>,
+    supply: usize,
+}
+
+impl KindChecker {
+    fn fresh(&mut self) -> Kind {
+        self.supply += 1;
+        Kind::Variable(format!("%k{}", self.supply))
+    }
+
+    /// apply the current substitution to `kind` all the way down
+    fn resolve(&self, kind: Kind) -> Kind {
+        match kind {
+            Kind::Variable(id) => match self.substitution.get(&id) {
+                Some(kind) => self.resolve(kind.clone()),
+                None => Kind::Variable(id),
+            }
+            Kind::Arrow { from, to } => Kind::Arrow {
+                from: from.into_iter().map(|kind| self.resolve(kind)).collect(),
+                to: Box::new(self.resolve(*to)),
+            },
+            Kind::Star => Kind::Star,
+        }
+    }
+
+    fn unify(&mut self, expected: Kind, found: Kind) -> TC<()> {
+        match (self.resolve(expected), self.resolve(found)) {
+            (Kind::Star, Kind::Star) => Ok(()),
+            (Kind::Variable(left), Kind::Variable(right)) if left == right => Ok(()),
+            (Kind::Variable(variable), kind) | (kind, Kind::Variable(variable)) => {
+                if kind.variables().contains(&variable) {
+                    return Err(TypeError::InfiniteKind { variable, kind })
+                }
+                self.substitution.insert(variable, kind);
+                Ok(())
+            }
+            (Kind::Arrow { from: expected_from, to: expected_to },
+             Kind::Arrow { from: found_from, to: found_to }) if expected_from.len() == found_from.len() => {
+                for (expected, found) in expected_from.into_iter().zip(found_from) {
+                    self.unify(expected, found)?;
+                }
+                self.unify(*expected_to, *found_to)
+            }
+            (expected, found) => Err(TypeError::KindMismatch { expected, found }),
+        }
+    }
+
+    /// replace the generalized kind variables of `kind` with fresh ones
+    fn instantiate(&mut self, kenv: &KindEnv, kind: Kind) -> Kind {
+        let kind = self.resolve(kind);
+        let fixed: Vec<_> = kenv.values()
+                                .flat_map(|kind| self.resolve(kind.clone()).variables())
+                                .collect();
+        let renaming: HashMap<_, _> = kind.variables()
+                                          .into_iter()
+                                          .filter(|variable| !fixed.contains(variable))
+                                          .map(|variable| (variable, self.fresh()))
+                                          .collect();
+        kind.rename(&renaming)
+    }
+
+    fn infer(&mut self, kenv: &KindEnv, mut typ: Type) -> TC<Kind> {
+        match &mut typ {
+            Type::Variable(id) => match kenv.get(id) {
+                Some(kind) => Ok(kind.clone()),
+                None => Err(TypeError::UnboundIdentifier(id.clone())),
+            }
+
+            Type::ForAll { parameters, typ } => {
+                distinct(parameters.iter().map(|TypeBinding { id, .. }| (id, None)))?;
+                let from = parameters.iter()
+                                     .map(|TypeBinding { kind, .. }| kind.clone())
+                                     .collect();
+
+                let mut extended_kenv = kenv.clone();
+                extended_kenv.extend(core::mem::take(parameters).into_iter()
+                                     .map(|TypeBinding { id, kind }| (id, kind)));
+                let to = Box::new(self.infer(&extended_kenv, typ.take())?);
+
+                Ok(Kind::Arrow { from, to })
+            }
+
+            Type::Instantiate { typ, arguments } => {
+                let kind = self.infer(kenv, (**typ).clone())?;
+                match self.instantiate(kenv, kind) {
+                    Kind::Arrow { from, to } => {
+                        if from.len() != arguments.len() {
+                            return Err(TypeError::ArityMismatch { expected: from.len(), found: arguments.len() })
+                        }
+                        for (expected, argument) in from.into_iter().zip(core::mem::take(arguments)) {
+                            let found = self.infer(kenv, argument)?;
+                            self.unify(expected.clone(), found.clone())
+                                .map_err(|_| TypeError::KindMismatch { expected: self.resolve(expected),
+                                                                       found: self.resolve(found) })?;
+                        }
+                        Ok(*to)
+                    }
+                    // a type constructor of unknown kind is used at the kind of its arguments
+                    Kind::Variable(variable) => {
+                        let from = core::mem::take(arguments).into_iter()
+                                                             .map(|argument| self.infer(kenv, argument))
+                                                             .collect::<TC<Vec<_>>>()?;
+                        let to = self.fresh();
+                        self.unify(Kind::Variable(variable), Kind::Arrow { from, to: Box::new(to.clone()) })?;
+                        Ok(to)
+                    }
+                    Kind::Star => Err(TypeError::ExpectedQuantifier { found: typ.take() }),
+                }
+            }
+
+            Type::Intersection(types) | Type::Union(types) | Type::Tuple(types) => {
+                for typ in core::mem::take(types) {
+                    self.expect_star(kenv, typ)?;
+                }
+                Ok(Kind::Star)
+            }
+
+            Type::Record(fields) | Type::Variant(fields) => {
+                distinct(fields.iter().map(|(field, _)| (field, None)))?;
+                for (_, typ) in core::mem::take(fields) {
+                    self.expect_star(kenv, typ)?;
+                }
+                Ok(Kind::Star)
+            }
+
+            Type::Rest(typ) => {
+                self.expect_star(kenv, typ.take())?;
+                Ok(Kind::Star)
+            }
+
+            _ => Ok(Kind::Star),
+        }
+    }
+
+    fn expect_star(&mut self, kenv: &KindEnv, typ: Type) -> TC<()> {
+        let found = self.infer(kenv, typ)?;
+        self.unify(Kind::Star, found.clone())
+            .map_err(|_| TypeError::KindMismatch { expected: Kind::Star, found: self.resolve(found) })
+    }
+}
+
+impl Kind {
+    fn variables(&self) -> Vec<Identifier> {
+        match self {
+            Kind::Star => vec![],
+            Kind::Variable(id) => vec![id.clone()],
+            Kind::Arrow { from, to } => from.iter()
+                                            .chain(core::iter::once(&**to))
+                                            .flat_map(Kind::variables)
+                                            .collect(),
+        }
+    }
+
+    fn rename(self, renaming: &HashMap<Identifier, Kind>) -> Kind {
+        match self {
+            Kind::Star => Kind::Star,
+            Kind::Variable(id) => renaming.get(&id).cloned().unwrap_or(Kind::Variable(id)),
+            Kind::Arrow { from, to } => Kind::Arrow {
+                from: from.into_iter().map(|kind| kind.rename(renaming)).collect(),
+                to: Box::new(to.rename(renaming)),
+            },
+        }
+    }
+}
+
+impl Type {
+    /// the immediate component types of this type
+    pub fn children(&self) -> Vec<&Type> {
+        match self {
+            Type::Variable(_) | Type::Boolean | Type::Number | Type::Float | Type::String | Type::Char | Type::Bytes => vec![],
+            Type::ForAll { typ, .. } | Type::Rest(typ) => vec![typ],
+            Type::Instantiate { typ, arguments } => core::iter::once(&**typ).chain(arguments).collect(),
+            Type::Function { arguments, result } => arguments.iter().chain(core::iter::once(&**result)).collect(),
+            Type::Intersection(types) | Type::Union(types) | Type::Tuple(types) => types.iter().collect(),
+            Type::Record(fields) | Type::Variant(fields) => fields.iter().map(|(_, typ)| typ).collect(),
+        }
+    }
+
+    /// rebuild this type with `f` applied to each of its immediate component types
+    pub fn map_children(mut self, mut f: impl FnMut(Type) -> Type) -> Type {
+        deep::Tree::each_child_mut(&mut self, |typ| *typ = f(typ.take()));
+        self
+    }
+
+    /// the type variables referenced but not bound by a quantifier in this type
+    pub fn free_variables(&self) -> HashSet<Identifier> {
+        match self {
+            Type::Variable(id) => HashSet::from([id.clone()]),
+            Type::ForAll { parameters, typ } => {
+                let mut free = typ.free_variables();
+                for TypeBinding { id, .. } in parameters {
+                    free.remove(id);
+                }
+                free
+            }
+            typ => typ.children().into_iter().flat_map(Type::free_variables).collect(),
+        }
+    }
+
+    /// replace the free type variables in `substitution` with their types. a quantifier whose
+    /// parameter would capture a variable of one of those types has the parameter renamed.
+    pub fn substitute(mut self, substitution: &HashMap<Identifier, Type>) -> Type {
+        match &mut self {
+            Type::Variable(id) => substitution.get(id).cloned().unwrap_or(self),
+            Type::ForAll { parameters, typ } => {
+                let mut inner = substitution.clone();
+                for TypeBinding { id, .. } in parameters.iter() {
+                    inner.remove(id);
+                }
+                let mut avoid: HashSet<_> = inner.values().flat_map(Type::free_variables).collect();
+                avoid.extend(typ.free_variables());
+                let captured: HashSet<_> = inner.values().flat_map(Type::free_variables).collect();
+                let parameters = core::mem::take(parameters);
+                let parameters = parameters.into_iter()
+                                           .map(|TypeBinding { id, kind }| {
+                                               if !captured.contains(&id) {
+                                                   return TypeBinding { id, kind }
+                                               }
+                                               let mut fresh = format!("{}'", id);
+                                               while avoid.contains(&fresh) {
+                                                   fresh.push('\'');
+                                               }
+                                               avoid.insert(fresh.clone());
+                                               inner.insert(id, Type::Variable(fresh.clone()));
+                                               TypeBinding { id: fresh, kind }
+                                           })
+                                           .collect();
+                Type::ForAll { parameters, typ: Box::new(typ.take().substitute(&inner)) }
+            }
+            _ => self.map_children(|typ| typ.substitute(substitution)),
+        }
+    }
+
+    /// move this type out, leaving the unit type in its place, for taking apart a type, which
+    /// can't be moved out of by a pattern
+    pub fn take(&mut self) -> Type {
+        core::mem::take(self)
+    }
+}
+
+/// the unit type, which is left in place of a type that's been taken
+impl Default for Type {
+    fn default() -> Type {
+        Type::Tuple(vec![])
+    }
+}
+
+impl deep::Tree for Type {
+    fn each_child_mut(&mut self, mut f: impl FnMut(&mut Type)) {
+        match self {
+            Type::Variable(_) | Type::Boolean | Type::Number | Type::Float | Type::String | Type::Char | Type::Bytes => {}
+            Type::ForAll { typ, .. } | Type::Rest(typ) => f(typ),
+            Type::Instantiate { typ, arguments } => {
+                f(typ);
+                arguments.iter_mut().for_each(f);
+            }
+            Type::Function { arguments, result } => {
+                arguments.iter_mut().for_each(&mut f);
+                f(result);
+            }
+            Type::Intersection(types) | Type::Union(types) | Type::Tuple(types) => types.iter_mut().for_each(f),
+            Type::Record(fields) | Type::Variant(fields) => fields.iter_mut().for_each(|(_, typ)| f(typ)),
+        }
+    }
+
+    fn clone_with<'a>(&'a self, mut f: impl FnMut(&'a Type) -> Type) -> Type {
+        match self {
+            Type::Variable(id) => Type::Variable(id.clone()),
+            Type::ForAll { parameters, typ } => Type::ForAll { parameters: parameters.clone(), typ: Box::new(f(typ)) },
+            Type::Instantiate { typ, arguments } => Type::Instantiate {
+                typ: Box::new(f(typ)),
+                arguments: arguments.iter().map(f).collect(),
+            },
+            Type::Function { arguments, result } => Type::Function {
+                arguments: arguments.iter().map(&mut f).collect(),
+                result: Box::new(f(result)),
+            },
+            Type::Intersection(types) => Type::Intersection(types.iter().map(f).collect()),
+            Type::Union(types) => Type::Union(types.iter().map(f).collect()),
+            Type::Boolean => Type::Boolean,
+            Type::Number => Type::Number,
+            Type::Float => Type::Float,
+            Type::String => Type::String,
+            Type::Char => Type::Char,
+            Type::Bytes => Type::Bytes,
+            Type::Tuple(types) => Type::Tuple(types.iter().map(f).collect()),
+            Type::Record(fields) => Type::Record(fields.iter().map(|(field, typ)| (field.clone(), f(typ))).collect()),
+            Type::Variant(cases) => Type::Variant(cases.iter().map(|(tag, typ)| (tag.clone(), f(typ))).collect()),
+            Type::Rest(typ) => Type::Rest(Box::new(f(typ))),
+        }
+    }
+}
+
+impl Clone for Type {
+    fn clone(&self) -> Type {
+        deep::clone(self)
+    }
+}
+
+impl Drop for Type {
+    fn drop(&mut self) {
+        deep::drop_children(self);
+    }
+}
+
+#[cfg(test)]
 fn check_kinds(kenv: &KindEnv, typ: Type) -> TC<Kind> {
-    match typ {
-        Type::Variable(id) => match kenv.get(&id) {
-            Some(kind) => Ok(kind.clone()),
-            None => Err(TypeError::UnboundIdentifier(id.clone())),
+    let mut checker = KindChecker::default();
+    let kind = checker.infer(kenv, typ)?;
+    Ok(checker.resolve(kind))
+}
+
+fn expect_star(kenv: &KindEnv, typ: Type) -> TC<()> {
+    KindChecker::default().expect_star(kenv, typ)
+}
+
+/// is `sub` usable wherever a `sup` is expected?
+pub fn is_subtype(sub: &Type, sup: &Type) -> bool {
+    match (sub, sup) {
+        _ if sub == sup => true,
+
+        // A | B <: T iff A <: T and B <: T
+        (Type::Union(subs), _) => subs.iter().all(|sub| is_subtype(sub, sup)),
+        // T <: A & B iff T <: A and T <: B
+        (_, Type::Intersection(sups)) => sups.iter().all(|sup| is_subtype(sub, sup)),
+        // A & B <: T iff A <: T or B <: T
+        (Type::Intersection(subs), _) => subs.iter().any(|sub| is_subtype(sub, sup)),
+        // T <: A | B iff T <: A or T <: B
+        (_, Type::Union(sups)) => sups.iter().any(|sup| is_subtype(sub, sup)),
+
+        (Type::Function { arguments: sub_arguments, result: sub_result },
+         Type::Function { arguments: sup_arguments, result: sup_result }) => {
+            sub_arguments.len() == sup_arguments.len() &&
+                sub_arguments.iter()
+                             .zip(sup_arguments)
+                             .all(|(sub, sup)| is_subtype(sup, sub)) &&
+                is_subtype(sub_result, sup_result)
         }
 
-        Type::ForAll { parameters, typ } => {
-            let from = parameters.iter()
-                                 .map(|TypeBinding { kind, .. }| kind.clone())
-                                 .collect();
+        (Type::Tuple(subs), Type::Tuple(sups)) => {
+            subs.len() == sups.len() && subs.iter().zip(sups).all(|(sub, sup)| is_subtype(sub, sup))
+        }
+        (Type::Tuple(subs), Type::Rest(sup)) => subs.iter().all(|sub| is_subtype(sub, sup)),
+        (Type::Rest(sub), Type::Rest(sup)) => is_subtype(sub, sup),
+        // every field the supertype has must be present in the subtype
+        (Type::Record(sub_fields), Type::Record(sup_fields)) => sup_fields.iter().all(|(field, sup)| {
+            sub_fields.iter().any(|(id, sub)| id == field && is_subtype(sub, sup))
+        }),
+        // every case the subtype has must be present in the supertype
+        (Type::Variant(sub_cases), Type::Variant(sup_cases)) => sub_cases.iter().all(|(tag, sub)| {
+            sup_cases.iter().any(|(id, sup)| id == tag && is_subtype(sub, sup))
+        }),
 
-            let mut extended_kenv = kenv.clone();
-            extended_kenv.extend(parameters.into_iter()
-                                 .map(|TypeBinding { id, kind }| (id, kind)));
-            let to = Box::new(check_kinds(&extended_kenv, *typ)?);
+        _ => false,
+    }
+}
 
-            Ok(Kind::Arrow { from, to })
+/// the least upper bound of two types, falling back to their union
+fn join(left: Type, right: Type) -> Type {
+    if is_subtype(&left, &right) {
+        return right
+    }
+    if is_subtype(&right, &left) {
+        return left
+    }
+
+    // the values of two variants are those of a variant with the cases of both
+    if let (Type::Variant(left_cases), Type::Variant(right_cases)) = (&left, &right) {
+        let mut cases = left_cases.clone();
+        for (tag, payload) in right_cases {
+            match cases.iter_mut().find(|(id, _)| id == tag) {
+                Some((_, existing)) => *existing = join(existing.clone(), payload.clone()),
+                None => cases.push((tag.clone(), payload.clone())),
+            }
         }
+        return Type::Variant(cases)
+    }
 
-        Type::Instantiate { typ, arguments } => match check_kinds(&kenv, *typ.clone())? {
-            Kind::Arrow{ from, to } => {
-                for (expected, argument) in from.into_iter().zip(arguments.into_iter()) {
-                    let found = check_kinds(kenv, argument)?;
-                    if expected != found {
-                        return Err(TypeError::KindMismatch { expected, found })
-                    }
+    let mut types = vec![];
+    for mut typ in [left, right] {
+        match &mut typ {
+            Type::Union(members) => types.append(members),
+            _ => types.push(typ),
+        }
+    }
+    Type::Union(types)
+}
+
+impl TypeTag {
+    /// the tag `typeof` gives under `name`, e.g. `"number"`
+    pub fn named(name: &str) -> Option<TypeTag> {
+        Some(match name {
+            "boolean" => TypeTag::Boolean,
+            "number" => TypeTag::Number,
+            "float" => TypeTag::Float,
+            "string" => TypeTag::String,
+            "char" => TypeTag::Char,
+            "bytes" => TypeTag::Bytes,
+            "function" => TypeTag::Function,
+            "tuple" => TypeTag::Tuple,
+            "record" => TypeTag::Record,
+            "variant" => TypeTag::Variant,
+            _ => return None,
+        })
+    }
+
+    fn matches(&self, typ: &Type) -> bool {
+        matches!((self, typ), (TypeTag::Boolean, Type::Boolean) |
+                              (TypeTag::Number, Type::Number) |
+                              (TypeTag::Float, Type::Float) |
+                              (TypeTag::String, Type::String) |
+                              (TypeTag::Char, Type::Char) |
+                              (TypeTag::Bytes, Type::Bytes) |
+                              (TypeTag::Tuple, Type::Tuple(_) | Type::Rest(_)) |
+                              (TypeTag::Record, Type::Record(_)) |
+                              (TypeTag::Variant, Type::Variant(_)) |
+                              (TypeTag::Function, Type::Function { .. } | Type::Intersection(_)))
+    }
+}
+
+/// refine `typ` to the members of a union that do (or do not) pass a type test. types that
+/// aren't unions, and unions that would be refined to nothing, are left alone.
+fn narrow(mut typ: Type, tag: &TypeTag, passed: bool) -> Type {
+    match &mut typ {
+        Type::Union(members) => {
+            let members = core::mem::take(members);
+            let (mut kept, rest): (Vec<_>, Vec<_>) = members.into_iter()
+                                                            .partition(|member| tag.matches(member) == passed);
+            match kept.len() {
+                0 => Type::Union(rest),
+                1 => kept.remove(0),
+                _ => Type::Union(kept),
+            }
+        }
+        _ => typ,
+    }
+}
+
+type TypeEnv = HashMap<Identifier, Type>;
+
+/// the types of the innermost loop's variables are kept in the type environment under a name no
+/// program can write, as the arguments `continue` expects
+const LOOP_VARIABLES: &str = "%loop";
+/// and the declared result type of the innermost return point is kept as what `return` expects
+const RETURN: &str = "%return";
+/// or, if its result type is inferred, this is kept instead, and `return` adds to the types
+/// returned to it so far
+const RETURNED: &str = "%returned";
+
+/// is every `continue` in `expr` in tail position in the body of its loop, given whether `expr`
+/// itself is?
+fn continues_in_tail_position(expr: &Expression, tail: bool) -> bool {
+    match expr {
+        Expression::Continue(arguments) => tail && arguments.iter().all(|argument| continues_in_tail_position(argument, false)),
+        Expression::If { condition, consequent, alternative } => {
+            continues_in_tail_position(condition, false) &&
+                continues_in_tail_position(consequent, tail) &&
+                continues_in_tail_position(alternative, tail)
+        }
+        Expression::IfTarget { consequent, alternative, .. } => {
+            continues_in_tail_position(consequent, tail) && continues_in_tail_position(alternative, tail)
+        }
+        Expression::Match { scrutinee, arms } => {
+            continues_in_tail_position(scrutinee, false) &&
+                arms.iter().all(|Arm { guard, body, .. }| {
+                    guard.iter().all(|guard| continues_in_tail_position(guard, false)) &&
+                        continues_in_tail_position(body, tail)
+                })
+        }
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => continues_in_tail_position(expression, tail),
+        // the body of a nested loop is in tail position for its own continues
+        Expression::Loop { variables, body } => {
+            variables.iter().all(|(_, init)| continues_in_tail_position(init, false)) &&
+                continues_in_tail_position(body, true)
+        }
+        expr => expr.children().into_iter().all(|child| continues_in_tail_position(child, false)),
+    }
+}
+
+fn check_types(kenv: &KindEnv, tenv: &TypeEnv, expr: Expression) -> TC<Type> {
+    Ok(typed::elaborate_in(kenv, tenv, expr)?.typ)
+}
+
+fn check_application(mut function: Type, arguments: Vec<Type>) -> TC<Type> {
+    match &mut function {
+        Type::Function { arguments: parameters, result } => {
+            let mut arguments = arguments;
+            // a variadic function's extra arguments are packed into its rest parameter
+            if let Some(Type::Rest(_)) = parameters.last() {
+                if arguments.len() + 1 < parameters.len() {
+                    return Err(TypeError::ArityMismatch { expected: parameters.len() - 1, found: arguments.len() })
                 }
-                Ok(*to)
+                let rest = arguments.split_off(parameters.len() - 1);
+                arguments.push(Type::Tuple(rest));
             }
-            Kind::Star => Err(TypeError::ExpectedQuantifier { found: *typ }),
+            if parameters.len() != arguments.len() {
+                return Err(TypeError::ArityMismatch { expected: parameters.len(), found: arguments.len() })
+            }
+            for (expected, found) in core::mem::take(parameters).into_iter().zip(arguments) {
+                if !is_subtype(&found, &expected) {
+                    return Err(TypeError::TypeMismatch { expected, found })
+                }
+            }
+            Ok(result.take())
+        }
+
+        // overload resolution: the first arrow that accepts the arguments wins
+        Type::Intersection(overloads) => {
+            for overload in overloads.iter() {
+                if let Ok(result) = check_application(overload.clone(), arguments.clone()) {
+                    return Ok(result)
+                }
+            }
+            Err(TypeError::NoMatchingOverload { found: function, arguments })
+        }
+
+        // a generic function is instantiated at the types its arguments give its parameters
+        Type::ForAll { parameters, typ } => {
+            let Type::Function { arguments: expected, .. } = &**typ else {
+                return Err(TypeError::ExpectedFunction { found: function })
+            };
+            let variables: HashSet<_> = parameters.iter().map(|TypeBinding { id, .. }| id.clone()).collect();
+            let mut substitution = HashMap::new();
+            for (index, found) in arguments.iter().enumerate() {
+                let expected = match expected.get(index) {
+                    Some(Type::Rest(rest)) if index + 1 == expected.len() => &**rest,
+                    Some(expected) => expected,
+                    None => match expected.last() {
+                        Some(Type::Rest(rest)) => &**rest,
+                        _ => break,
+                    },
+                };
+                infer_type_arguments(expected, found, &variables, &mut substitution);
+            }
+            if let Some(TypeBinding { id, .. }) = parameters.iter().find(|TypeBinding { id, kind }| *kind != Kind::Star || !substitution.contains_key(id)) {
+                return Err(TypeError::UninferredTypeArgument { parameter: id.clone() })
+            }
+            check_application(typ.take().substitute(&substitution), arguments)
         }
 
-        _ => Ok(Kind::Star),
+        _ => Err(TypeError::ExpectedFunction { found: function }),
     }
 }
 
-#[derive(Clone, Debug)]
+/// add to `substitution` the types of `variables` that make `expected` fit `found`, joining the
+/// types a variable is given in more than one place
+fn infer_type_arguments(expected: &Type, found: &Type, variables: &HashSet<Identifier>, substitution: &mut HashMap<Identifier, Type>) {
+    let mut infer = |expected, found| infer_type_arguments(expected, found, variables, substitution);
+    match (expected, found) {
+        (Type::Variable(id), found) if variables.contains(id) => {
+            let typ = match substitution.remove(id) {
+                Some(typ) => join(typ, found.clone()),
+                None => found.clone(),
+            };
+            substitution.insert(id.clone(), typ);
+        }
+        (Type::Function { arguments: expected, result: expected_result }, Type::Function { arguments: found, result: found_result }) => {
+            expected.iter().zip(found).for_each(|(expected, found)| infer(expected, found));
+            infer(expected_result, found_result);
+        }
+        (Type::Tuple(expected), Type::Tuple(found)) => expected.iter().zip(found).for_each(|(expected, found)| infer(expected, found)),
+        (Type::Rest(expected), Type::Rest(found)) => infer(expected, found),
+        (Type::Rest(expected), Type::Tuple(found)) => found.iter().for_each(|found| infer(expected, found)),
+        (Type::Record(expected), Type::Record(found)) | (Type::Variant(expected), Type::Variant(found)) => {
+            for (label, expected) in expected {
+                if let Some((_, found)) = found.iter().find(|(other, _)| other == label) {
+                    infer(expected, found);
+                }
+            }
+        }
+        (Type::Instantiate { typ: expected, arguments: expected_arguments }, Type::Instantiate { typ: found, arguments: found_arguments }) => {
+            infer(expected, found);
+            expected_arguments.iter().zip(found_arguments).for_each(|(expected, found)| infer(expected, found));
+        }
+        _ => {}
+    }
+}
+
+/// the fields of a record, or of its type, after `{r with updates}`: each update replaces the
+/// field of the same name where it is, or is added after the others if there isn't one
+fn update_fields<T>(fields: &mut Vec<(Identifier, T)>, updates: Vec<(Identifier, T)>) {
+    for (field, value) in updates {
+        match fields.iter_mut().find(|(id, _)| *id == field) {
+            Some((_, old)) => *old = value,
+            None => fields.push((field, value)),
+        }
+    }
+}
+
+/// the types of host-provided functions and values, which have no bodies in SGIR
+pub type Declarations = HashMap<Identifier, Type>;
+
+pub fn check(expr: Expression) -> TC<Type> {
+    check_with_declarations(&Declarations::new(), expr)
+}
+
+/// check `expr` with the host-provided `declarations` in scope, after checking that each of
+/// them declares a well-formed type of kind `*`, or a polymorphic one, quantified over a type of
+/// kind `*`
+pub fn check_with_declarations(declarations: &Declarations, expr: Expression) -> TC<Type> {
+    check_with_abstract_types(&[], declarations, expr)
+}
+
+/// check `expr` like `check_with_declarations`, with the host-provided `abstract_types` in scope
+/// as well, each with its kind: types, or type constructors like `Task<T>`, that the declarations
+/// can mention, but whose representations are hidden, so that `expr` can only pass their values
+/// around, never look into them
+pub fn check_with_abstract_types(abstract_types: &[(Identifier, Kind)], declarations: &Declarations, expr: Expression) -> TC<Type> {
+    let kenv: KindEnv = abstract_types.iter().cloned().collect();
+    for typ in declarations.values() {
+        expect_value_type(&kenv, typ)?;
+    }
+    check_types(&kenv, declarations, expr)
+}
+
+/// expect `typ` to be the type of a value: of kind `*`, or quantified over one
+fn expect_value_type(kenv: &KindEnv, typ: &Type) -> TC<()> {
+    match typ {
+        Type::ForAll { parameters, typ } => {
+            let mut extended_kenv = kenv.clone();
+            extended_kenv.extend(parameters.iter().map(|TypeBinding { id, kind }| (id.clone(), kind.clone())));
+            expect_value_type(&extended_kenv, typ)
+        }
+        typ => expect_star(kenv, typ.clone()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Binding {
     pub id: Identifier,
     pub typ: Type,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Expression {
     Variable(Identifier),
 
     // Primitives
     Boolean(bool),
     Number(i64), // haha, this should be a bignum
+    Float(Float),
+    String(String),
+    Char(char),
+    Bytes(Vec<u8>),
 
     Function {
         parameters: Vec<Binding>,
         body: Box<Expression>,
     },
 
+    /// a call, e.g. `f(x, y)`. evaluation is strict and left to right, which the language
+    /// guarantees: the function is evaluated first, then each argument in turn, and the call is
+    /// only made once all of them are values. the elements of tuples and records, the operands of
+    /// primitives, and the fields of an update are evaluated left to right too, after the record
+    /// being updated. every evaluator and every pass that moves code has to keep to this order.
     Application {
         function: Box<Expression>,
         arguments: Vec<Expression>,
     },
-}
 
-#[derive(Clone, Debug)]
-pub enum Value {
-    // Primitives
-    Boolean(bool),
-    Number(i64), // haha, this should be a bignum
+    If {
+        condition: Box<Expression>,
+        consequent: Box<Expression>,
+        alternative: Box<Expression>,
+    },
+
+    /// a runtime type test, i.e. `typeof(e) == "number"`
+    TypeTest {
+        expression: Box<Expression>,
+        tag: TypeTag,
+    },
+
+    /// a conditional on the compilation target, i.e. `@if target == "wasm"`, which is resolved
+    /// away before checking by `target::resolve_targets`
+    IfTarget {
+        target: String,
+        consequent: Box<Expression>,
+        alternative: Box<Expression>,
+    },
+
+    /// an expression evaluated at compile time by `constants::evaluate_constants`, which the
+    /// checker evaluates too, so that one that can't be is an error in the program
+    Constant(Box<Expression>),
+    /// a function marked `total`, whose loops the checker has to show terminate with
+    /// `termination::check_loops`
+    Total(Box<Expression>),
+
+    /// an expression annotated with the source it came from
+    Located {
+        span: Span,
+        expression: Box<Expression>,
+    },
+    /// code lowering generated for a surface construct, e.g. the identity function an ascription
+    /// is checked with, whose diagnostics point at the construct with an "expanded from" note
+    Expanded {
+        construct: String,
+        span: Span,
+        expression: Box<Expression>,
+    },
+
+    /// a primitive operation, e.g. `a == b`
+    Primitive {
+        operator: Primitive,
+        arguments: Vec<Expression>,
+    },
+
+    /// a tuple, e.g. `(1, true)`
+    Tuple(Vec<Expression>),
+    /// a record, e.g. `{x = 1, y = 2}`
+    Record(Vec<(Identifier, Expression)>),
+    /// a copy of a record with some of its fields replaced, and any it didn't have added, e.g.
+    /// `{r with x = 1}`. the record it's copied from is left as it was.
+    Update {
+        record: Box<Expression>,
+        fields: Vec<(Identifier, Expression)>,
+    },
+    /// one case of a variant, e.g. `Some(1)`. its type has just that case, and widens to any
+    /// variant that includes it.
+    Variant {
+        tag: Identifier,
+        payload: Box<Expression>,
+    },
+    /// match a value against the pattern of each arm in turn, e.g.
+    /// `match e { case Some(x) if x > 0 -> x, case _ -> 0 }`
+    Match {
+        scrutinee: Box<Expression>,
+        arms: Vec<Arm>,
+    },
+
+    /// a loop, e.g. `loop (i: Number = 0) { if i < 10 then continue(i + 1) else i }`. the body is
+    /// evaluated with the loop variables bound, and its value is the value of the loop unless
+    /// it ends in a `continue`.
+    Loop {
+        variables: Vec<(Binding, Expression)>,
+        body: Box<Expression>,
+    },
+    /// start the next iteration of the innermost loop with new values for its variables. it
+    /// may only appear in tail position in the body of a loop.
+    Continue(Vec<Expression>),
+
+    /// the point a `return` returns to, with the declared result type, or none if it's the join
+    /// of the types of the values returned to it. every function lowered from source has one as
+    /// its body.
+    Returning {
+        result: Option<Type>,
+        body: Box<Expression>,
+    },
+    /// finish the innermost `Returning` with a value, e.g. `return e`
+    Return(Box<Expression>),
+
+    /// a type abstraction, e.g. `forall<T>. fn (x: T) -> x`, whose type is a `ForAll`. types are
+    /// erased at runtime, so it evaluates to the value of its body.
+    TypeFunction {
+        parameters: Vec<TypeBinding>,
+        body: Box<Expression>,
+    },
+    /// a type application, e.g. `id<Number>`, which instantiates the quantifier of the
+    /// function's type with the arguments
+    TypeApplication {
+        function: Box<Expression>,
+        arguments: Vec<Type>,
+    },
+}
+
+impl Expression {
+    /// where in the source this expression was lowered from, when it's known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expression::Located { span, .. } | Expression::Expanded { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// the immediate subexpressions of this expression
+    pub fn children(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Variable(_) | Expression::Boolean(_) | Expression::Number(_) | Expression::Float(_)
+            | Expression::String(_) | Expression::Char(_) | Expression::Bytes(_) => vec![],
+            Expression::Function { body, .. } => vec![body],
+            Expression::Application { function, arguments } => core::iter::once(&**function).chain(arguments).collect(),
+            Expression::If { condition, consequent, alternative } => vec![condition, consequent, alternative],
+            Expression::TypeTest { expression, .. } => vec![expression],
+            Expression::IfTarget { consequent, alternative, .. } => vec![consequent, alternative],
+            Expression::Constant(expression) | Expression::Total(expression) => vec![expression],
+            Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => vec![expression],
+            Expression::Primitive { arguments, .. } => arguments.iter().collect(),
+            Expression::Tuple(elements) => elements.iter().collect(),
+            Expression::Record(fields) => fields.iter().map(|(_, expr)| expr).collect(),
+            Expression::Update { record, fields } => core::iter::once(&**record).chain(fields.iter().map(|(_, expr)| expr)).collect(),
+            Expression::Variant { payload, .. } => vec![payload],
+            Expression::Match { scrutinee, arms } => {
+                let mut children = vec![&**scrutinee];
+                for Arm { guard, body, .. } in arms {
+                    children.extend(guard);
+                    children.push(body);
+                }
+                children
+            }
+            Expression::Loop { variables, body } => {
+                variables.iter().map(|(_, init)| init).chain(core::iter::once(&**body)).collect()
+            }
+            Expression::Continue(arguments) => arguments.iter().collect(),
+            Expression::Returning { body, .. } => vec![body],
+            Expression::Return(value) => vec![value],
+            Expression::TypeFunction { body, .. } => vec![body],
+            Expression::TypeApplication { function, .. } => vec![function],
+        }
+    }
+
+    /// the number of nodes in this expression
+    pub fn size(&self) -> usize {
+        1 + self.children().into_iter().map(Expression::size).sum::<usize>()
+    }
+
+    /// the variables referenced but not bound in this expression
+    pub fn free_variables(&self) -> HashSet<Identifier> {
+        match self {
+            Expression::Variable(id) => HashSet::from([id.clone()]),
+            Expression::Function { parameters, body } => {
+                let mut free = body.free_variables();
+                for Binding { id, .. } in parameters {
+                    free.remove(id);
+                }
+                free
+            }
+            Expression::Match { scrutinee, arms } => {
+                let mut free = scrutinee.free_variables();
+                for Arm { pattern, guard, body } in arms {
+                    let mut arm_free: HashSet<_> = guard.iter().chain([body]).flat_map(Expression::free_variables).collect();
+                    for id in pattern.variables() {
+                        arm_free.remove(&id);
+                    }
+                    free.extend(arm_free);
+                }
+                free
+            }
+            Expression::Loop { variables, body } => {
+                let mut free = body.free_variables();
+                for (Binding { id, .. }, _) in variables {
+                    free.remove(id);
+                }
+                free.extend(variables.iter().flat_map(|(_, init)| init.free_variables()));
+                free
+            }
+            expr => expr.children()
+                        .into_iter()
+                        .flat_map(Expression::free_variables)
+                        .collect(),
+        }
+    }
+
+    /// rebuild this expression with `f` applied to each of its immediate subexpressions
+    pub fn map_children(self, mut f: impl FnMut(Expression) -> Expression) -> Expression {
+        match self.try_map_children(|expr| Ok::<_, Infallible>(f(expr))) {
+            Ok(expr) => expr,
+            Err(never) => match never {},
+        }
+    }
+
+    /// like `map_children`, but stopping at the first subexpression for which `f` fails
+    pub fn try_map_children<E>(mut self, mut f: impl FnMut(Expression) -> Result<Expression, E>) -> Result<Expression, E> {
+        let mut result = Ok(());
+        deep::Tree::each_child_mut(&mut self, |expr| {
+            if result.is_ok() {
+                match f(expr.take()) {
+                    Ok(mapped) => *expr = mapped,
+                    Err(error) => result = Err(error),
+                }
+            }
+        });
+        result.map(|()| self)
+    }
+
+    /// move this expression out, leaving the unit in its place, for taking apart an expression,
+    /// which can't be moved out of by a pattern
+    pub fn take(&mut self) -> Expression {
+        core::mem::take(self)
+    }
+}
+
+/// the unit, which is left in place of an expression that's been taken
+impl Default for Expression {
+    fn default() -> Expression {
+        Expression::Tuple(vec![])
+    }
+}
+
+impl deep::Tree for Expression {
+    fn each_child_mut(&mut self, mut f: impl FnMut(&mut Expression)) {
+        match self {
+            Expression::Variable(_) | Expression::Boolean(_) | Expression::Number(_) | Expression::Float(_)
+            | Expression::String(_) | Expression::Char(_) | Expression::Bytes(_) => {}
+            Expression::Function { body, .. } => f(body),
+            Expression::Application { function, arguments } => {
+                f(function);
+                arguments.iter_mut().for_each(f);
+            }
+            Expression::If { condition, consequent, alternative } => {
+                f(condition);
+                f(consequent);
+                f(alternative);
+            }
+            Expression::TypeTest { expression, .. } => f(expression),
+            Expression::IfTarget { consequent, alternative, .. } => {
+                f(consequent);
+                f(alternative);
+            }
+            Expression::Constant(expression) | Expression::Total(expression) => f(expression),
+            Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => f(expression),
+            Expression::Primitive { arguments, .. } | Expression::Continue(arguments) => arguments.iter_mut().for_each(f),
+            Expression::Tuple(elements) => elements.iter_mut().for_each(f),
+            Expression::Record(fields) => fields.iter_mut().for_each(|(_, expr)| f(expr)),
+            Expression::Update { record, fields } => {
+                f(record);
+                fields.iter_mut().for_each(|(_, expr)| f(expr));
+            }
+            Expression::Variant { payload, .. } => f(payload),
+            Expression::Match { scrutinee, arms } => {
+                f(scrutinee);
+                for Arm { guard, body, .. } in arms {
+                    guard.iter_mut().for_each(&mut f);
+                    f(body);
+                }
+            }
+            Expression::Loop { variables, body } => {
+                variables.iter_mut().for_each(|(_, init)| f(init));
+                f(body);
+            }
+            Expression::Returning { body, .. } | Expression::TypeFunction { body, .. } => f(body),
+            Expression::Return(value) => f(value),
+            Expression::TypeApplication { function, .. } => f(function),
+        }
+    }
+
+    fn clone_with<'a>(&'a self, mut f: impl FnMut(&'a Expression) -> Expression) -> Expression {
+        match self {
+            Expression::Variable(id) => Expression::Variable(id.clone()),
+            Expression::Boolean(value) => Expression::Boolean(*value),
+            Expression::Number(value) => Expression::Number(*value),
+            Expression::Float(value) => Expression::Float(*value),
+            Expression::String(value) => Expression::String(value.clone()),
+            Expression::Char(value) => Expression::Char(*value),
+            Expression::Bytes(bytes) => Expression::Bytes(bytes.clone()),
+            Expression::Function { parameters, body } => Expression::Function { parameters: parameters.clone(), body: Box::new(f(body)) },
+            Expression::Application { function, arguments } => Expression::Application {
+                function: Box::new(f(function)),
+                arguments: arguments.iter().map(f).collect(),
+            },
+            Expression::If { condition, consequent, alternative } => Expression::If {
+                condition: Box::new(f(condition)),
+                consequent: Box::new(f(consequent)),
+                alternative: Box::new(f(alternative)),
+            },
+            Expression::TypeTest { expression, tag } => Expression::TypeTest { expression: Box::new(f(expression)), tag: tag.clone() },
+            Expression::IfTarget { target, consequent, alternative } => Expression::IfTarget {
+                target: target.clone(),
+                consequent: Box::new(f(consequent)),
+                alternative: Box::new(f(alternative)),
+            },
+            Expression::Constant(expression) => Expression::Constant(Box::new(f(expression))),
+            Expression::Total(expression) => Expression::Total(Box::new(f(expression))),
+            Expression::Located { span, expression } => Expression::Located { span: *span, expression: Box::new(f(expression)) },
+            Expression::Expanded { construct, span, expression } => Expression::Expanded {
+                construct: construct.clone(),
+                span: *span,
+                expression: Box::new(f(expression)),
+            },
+            Expression::Primitive { operator, arguments } => Expression::Primitive {
+                operator: *operator,
+                arguments: arguments.iter().map(f).collect(),
+            },
+            Expression::Tuple(elements) => Expression::Tuple(elements.iter().map(f).collect()),
+            Expression::Record(fields) => Expression::Record(fields.iter().map(|(field, expr)| (field.clone(), f(expr))).collect()),
+            Expression::Update { record, fields } => Expression::Update {
+                record: Box::new(f(record)),
+                fields: fields.iter().map(|(field, expr)| (field.clone(), f(expr))).collect(),
+            },
+            Expression::Variant { tag, payload } => Expression::Variant { tag: tag.clone(), payload: Box::new(f(payload)) },
+            Expression::Match { scrutinee, arms } => Expression::Match {
+                scrutinee: Box::new(f(scrutinee)),
+                arms: arms.iter()
+                          .map(|Arm { pattern, guard, body }| Arm {
+                              pattern: pattern.clone(),
+                              guard: guard.as_ref().map(&mut f),
+                              body: f(body),
+                          })
+                          .collect(),
+            },
+            Expression::Loop { variables, body } => Expression::Loop {
+                variables: variables.iter().map(|(binding, init)| (binding.clone(), f(init))).collect(),
+                body: Box::new(f(body)),
+            },
+            Expression::Continue(arguments) => Expression::Continue(arguments.iter().map(f).collect()),
+            Expression::Returning { result, body } => Expression::Returning { result: result.clone(), body: Box::new(f(body)) },
+            Expression::Return(value) => Expression::Return(Box::new(f(value))),
+            Expression::TypeFunction { parameters, body } => Expression::TypeFunction { parameters: parameters.clone(), body: Box::new(f(body)) },
+            Expression::TypeApplication { function, arguments } => Expression::TypeApplication {
+                function: Box::new(f(function)),
+                arguments: arguments.clone(),
+            },
+        }
+    }
+}
+
+impl Clone for Expression {
+    fn clone(&self) -> Expression {
+        deep::clone(self)
+    }
+}
+
+impl Drop for Expression {
+    fn drop(&mut self) {
+        deep::drop_children(self);
+    }
+}
+
+/// the result of `typeof` on a value
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TypeTag {
+    Boolean,
+    Number,
+    Float,
+    String,
+    Char,
+    Bytes,
+    Function,
+    Tuple,
+    Record,
+    Variant,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    // Primitives
+    Boolean(bool),
+    Number(i64), // haha, this should be a bignum
+    Float(f64),
+    String(String),
+    Char(char),
+    Bytes(Vec<u8>),
+
+    Tuple(Vec<Value>),
+    /// the fields of a record are shared by its copies, and only copied when one that's shared is
+    /// updated, so that passing a record around costs the same however many fields it has
+    Record(Arc<Vec<(Identifier, Value)>>),
+    Variant {
+        tag: Identifier,
+        payload: Box<Value>,
+    },
 
     Function {
         parameters: Vec<Binding>,
         body: Box<Expression>,
+        /// the bindings of the variables `body` refers to, captured when the closure was made, on
+        /// top of the outermost frame
+        environment: Arc<Environment>,
     },
+
+    Native(Native),
 }
 
-type Substitution = HashMap<Identifier, Value>;
+/// a function implemented by the host
+#[derive(Clone)]
+pub struct Native {
+    pub name: Identifier,
+    pub function: NativeFunction,
+}
 
-fn eval(subst: &Substitution, expr: Expression) -> Value {
-    match expr {
-        Expression::Variable(identifier) => subst[&identifier].clone(),
-        Expression::Boolean(value) => Value::Boolean(value),
-        Expression::Number(value) => Value::Number(value),
-        Expression::Function { parameters, body } => Value::Function { parameters: parameters.clone(), body: body.clone() },
-        Expression::Application { function, arguments } => match eval(subst, *function) {
-            Value::Function { parameters, body } => {
-                let mut extended_subst = subst.clone();
-                extended_subst.extend(parameters.into_iter()
-                                      .zip(arguments.into_iter())
-                                      .map(|(param, arg)| (param.id, eval(subst, arg))));
-                eval(&extended_subst, *body)
-            },
-            _ => panic!("this is not a function")
-        },
+#[derive(Clone)]
+pub enum NativeFunction {
+    Sync(Arc<dyn Fn(Vec<Value>) -> EV<Value> + Send + Sync>),
+    /// a native whose result the host produces later, usable only when running asynchronously
+    Async(Arc<dyn Fn(Vec<Value>) -> Evaluation<'static> + Send + Sync>),
+}
+
+impl Native {
+    pub fn new(name: &str, function: impl Fn(Vec<Value>) -> EV<Value> + Send + Sync + 'static) -> Native {
+        Native { name: name.to_owned(), function: NativeFunction::Sync(Arc::new(function)) }
+    }
+
+    pub fn new_async<F>(name: &str, function: impl Fn(Vec<Value>) -> F + Send + Sync + 'static) -> Native
+    where F: Future<Output = EV<Value>> + 'static {
+        let function = move |arguments| Box::pin(function(arguments)) as Evaluation<'static>;
+        Native { name: name.to_owned(), function: NativeFunction::Async(Arc::new(function)) }
+    }
+}
+
+impl Debug for Native {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Native({})", self.name)
+    }
+}
+
+/// natives are identified by name, since the host links each name to one function
+impl PartialEq for Native {
+    fn eq(&self, other: &Native) -> bool {
+        self.name == other.name
+    }
+}
+
+/// a frame of variable bindings, whose lookups fall back to the enclosing frames
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Environment {
+    bindings: HashMap<Identifier, Value>,
+    parent: Option<Arc<Environment>>,
+}
+
+impl Environment {
+    /// the outermost environment, holding the given bindings
+    pub fn global(bindings: HashMap<Identifier, Value>) -> Arc<Environment> {
+        Arc::new(Environment { bindings, parent: None })
+    }
+
+    fn extend(parent: &Arc<Environment>, bindings: HashMap<Identifier, Value>) -> Arc<Environment> {
+        Arc::new(Environment { bindings, parent: Some(parent.clone()) })
+    }
+
+    /// the environment of a closure created in `env`, whose body refers to the variables `free`:
+    /// just their bindings, on top of the outermost frame, which closures share. a closure that
+    /// captured the whole of `env` would keep alive everything it could see.
+    fn capture(env: &Arc<Environment>, free: HashSet<Identifier>) -> Arc<Environment> {
+        let mut outermost = env;
+        while let Some(parent) = &outermost.parent {
+            outermost = parent;
+        }
+        if Arc::ptr_eq(outermost, env) {
+            return env.clone()
+        }
+        let bindings = free.into_iter()
+                           .filter_map(|id| {
+                               let value = env.lookup_within(&id)?.clone();
+                               Some((id, value))
+                           })
+                           .collect();
+        Environment::extend(outermost, bindings)
+    }
+
+    /// the binding of `id` in a frame of this environment other than the outermost one
+    fn lookup_within(&self, id: &str) -> Option<&Value> {
+        let parent = self.parent.as_ref()?;
+        self.bindings.get(id).or_else(|| parent.lookup_within(id))
+    }
+
+    pub fn lookup(&self, id: &str) -> Option<&Value> {
+        match self.bindings.get(id) {
+            Some(value) => Some(value),
+            None => self.parent.as_ref().and_then(|parent| parent.lookup(id)),
+        }
+    }
+
+    /// the number of frames in this environment
+    pub fn depth(&self) -> usize {
+        1 + self.parent.as_ref().map_or(0, |parent| parent.depth())
+    }
+
+    /// every visible binding, with inner frames shadowing outer ones
+    pub fn flatten(&self) -> HashMap<Identifier, Value> {
+        let mut bindings = self.parent.as_ref().map(|parent| parent.flatten()).unwrap_or_default();
+        bindings.extend(self.bindings.clone());
+        bindings
+    }
+}
+
+/// the resources the evaluator can run out of
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resource {
+    Allocations,
+    EnvironmentDepth,
+    Length,
+}
+
+impl Display for Resource {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Resource::Allocations => write!(f, "allocations"),
+            Resource::EnvironmentDepth => write!(f, "environment depth"),
+            Resource::Length => write!(f, "length"),
+        }
+    }
+}
+
+/// bounds on the resources a program may use, for running untrusted code. `None` is unlimited.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// the number of values that may be allocated into closures and environments over the whole
+    /// run. it's a budget for allocating rather than a bound on memory: a value counts against it
+    /// once it's allocated, even after it's freed, so a long enough loop exhausts it however
+    /// little it keeps.
+    pub allocations: Option<usize>,
+    /// the maximum number of nested environment frames
+    pub environment_depth: Option<usize>,
+    /// the longest string or byte buffer, in bytes, or tuple or record, in elements, that a
+    /// primitive or a constructor may build
+    pub length: Option<usize>,
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum EvalError {
+    #[error("unbound identifier: {0}")]
+    UnboundIdentifier(Identifier),
+
+    #[error("expected a function, found {found}")]
+    ExpectedFunction {
+        found: Value,
+    },
+
+    #[error("expected a boolean, found {found}")]
+    ExpectedBoolean {
+        found: Value,
+    },
+
+    #[error("expected a number, found {found}")]
+    ExpectedNumber {
+        found: Value,
+    },
+
+    #[error("expected a string, found {found}")]
+    ExpectedString {
+        found: Value,
+    },
+
+    #[error("expected bytes, found {found}")]
+    ExpectedBytes {
+        found: Value,
+    },
+
+    #[error("expected a tuple, found {found}")]
+    ExpectedTuple {
+        found: Value,
+    },
+
+    #[error("expected a record, found {found}")]
+    ExpectedRecord {
+        found: Value,
+    },
+
+    #[error("expected a character, found {found}")]
+    ExpectedChar {
+        found: Value,
+    },
+
+    #[error("cannot compare {left} with {right}")]
+    NotComparable {
+        left: Value,
+        right: Value,
+    },
+
+    #[error("arity mismatch: expected {expected} arguments, found {found}")]
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("index {index} is out of bounds for length {length}")]
+    IndexOutOfBounds {
+        index: i64,
+        length: usize,
+    },
+
+    #[error("byte offset {offset} is not on a character boundary")]
+    NotCharBoundary {
+        offset: usize,
+    },
+
+    #[error("invalid range {start}..{end} for length {length}")]
+    InvalidRange {
+        start: i64,
+        end: i64,
+        length: usize,
+    },
+
+    #[error("cannot convert at offset {offset}: not valid {encoding}")]
+    InvalidEncoding {
+        encoding: Encoding,
+        offset: usize,
+    },
+
+    #[error("{base} is not a base from 2 to 36")]
+    InvalidBase {
+        base: i64,
+    },
+
+    #[error("floats are only written in base 10, not {base}")]
+    FloatBase {
+        base: i64,
+    },
+
+    #[error("division by zero")]
+    DivisionByZero,
+
+    #[error("arithmetic overflow in {left} {operator} {right}")]
+    Overflow {
+        operator: Primitive,
+        left: i64,
+        right: i64,
+    },
+
+    /// an operation on floats whose result would be infinite, which no float is
+    #[error("arithmetic overflow in {} {operator} {}", numbers::format_float(*left), numbers::format_float(*right))]
+    FloatOverflow {
+        operator: Primitive,
+        left: f64,
+        right: f64,
+    },
+
+    #[error("target conditional on {0} was not resolved before evaluation")]
+    UnresolvedTarget(String),
+
+    /// a construct outside the core calculus, which the small-step semantics doesn't cover
+    #[error("{0} has no small-step semantics")]
+    NoSmallStep(&'static str),
+
+    #[error("evaluation ran out of fuel")]
+    OutOfFuel,
+
+    /// the host stopped evaluation, which was in the calls at `stack`, innermost first
+    #[error("evaluation was interrupted")]
+    Interrupted {
+        stack: Vec<Span>,
+    },
+
+    #[error("resource exhausted: {0}")]
+    ResourceExhausted(Resource),
+
+    #[error("native function {0} is async and can only be called by run_async")]
+    AsyncNative(Identifier),
+
+    #[error("continue outside of the tail of a loop")]
+    MisplacedContinue,
+
+    /// the interpreter unwinds to the innermost `Returning` with this, so it only escapes a
+    /// program that returns outside of a function
+    #[error("return outside of a function")]
+    Returned {
+        value: Value,
+    },
+
+    #[error("no arm of the match accepts {value}")]
+    MatchFailure {
+        value: Value,
+    },
+
+    #[error("{contract} was violated, blaming {blame}")]
+    ContractViolation {
+        contract: String,
+        blame: String,
+    },
+
+    /// `error(value)`, at the call of `error` if it's known
+    #[error("error: {value}{}", located(at))]
+    Raised {
+        value: Value,
+        at: Option<Span>,
+    },
+
+    /// a failed `assert`, at the call of `assert` if it's known
+    #[error("assertion failed{}{}", message.as_ref().map_or(String::new(), |message| format!(": {}", message)), located(at))]
+    AssertionFailed {
+        message: Option<String>,
+        at: Option<Span>,
+    },
+
+    #[error("native function {name} failed: {message}")]
+    NativeFailure {
+        name: Identifier,
+        message: String,
+    },
+
+    /// a replayed program did something other than what its trace recorded, e.g. `a call of
+    /// io.read` or `the end of the program`
+    #[error("the program diverged from its trace: expected {expected}, found {found}")]
+    Diverged {
+        expected: String,
+        found: String,
+    },
+}
+
+/// a call of a native that a program made, with what it produced
+#[derive(Clone, Debug, PartialEq)]
+pub struct Effect {
+    pub native: Identifier,
+    pub arguments: Vec<Value>,
+    pub result: EV<Value>,
+}
+
+type EV<T> = Result<T, EvalError>;
+
+/// `error`, unwinding out of the call at `call`, if it's a call, which is added to the stack of an
+/// interruption. a failure that isn't located yet is located at the innermost call it unwinds
+/// out of, which is the call of `error` or `assert` that failed.
+fn unwind(error: EvalError, call: Option<Span>) -> EvalError {
+    match (error, call) {
+        (EvalError::Interrupted { mut stack }, Some(span)) => {
+            stack.push(span);
+            EvalError::Interrupted { stack }
+        }
+        (EvalError::Raised { value, at: None }, Some(span)) => EvalError::Raised { value, at: Some(span) },
+        (EvalError::AssertionFailed { message, at: None }, Some(span)) => EvalError::AssertionFailed { message, at: Some(span) },
+        (error, _) => error,
+    }
+}
+
+/// ` at line:column` for a failure at `span`, or nothing if where it failed isn't known
+fn located(span: &Option<Span>) -> String {
+    span.map_or(String::new(), |span| format!(" at {}:{}", span.start.line, span.start.column))
+}
+
+/// a way for the host to stop a program that runs too long: by setting `token`, e.g. from
+/// another thread, or by letting `deadline` pass. both are checked every `interval` steps, so that
+/// checking them costs little.
+#[derive(Clone, Debug)]
+pub struct Interrupt {
+    pub token: Arc<AtomicBool>,
+    pub interval: usize,
+    #[cfg(feature = "std")]
+    pub deadline: Option<std::time::Instant>,
+}
+
+impl Interrupt {
+    pub fn new(token: Arc<AtomicBool>, interval: usize) -> Interrupt {
+        Interrupt {
+            token,
+            interval: interval.max(1),
+            #[cfg(feature = "std")]
+            deadline: None,
+        }
+    }
+
+    /// whether the host has asked to stop, for those who check for it themselves, like the
+    /// checking of a document in an editor
+    pub fn requested(&self) -> bool {
+        #[cfg(feature = "std")]
+        if self.deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline) {
+            return true
+        }
+        self.token.load(Ordering::Relaxed)
+    }
+}
+
+/// the state threaded through evaluation
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    /// the number of evaluation steps left, or `None` to run without limit
+    pub fuel: Option<usize>,
+    /// the number of evaluation steps taken so far
+    pub steps: usize,
+    /// how many times each span was evaluated, when recording coverage
+    pub coverage: Option<HashMap<Span, usize>>,
+    /// the results of pure calls, when memoizing them
+    pub memo: Option<memo::Memo>,
+    pub limits: Limits,
+    /// the number of values allocated so far, freed or not, counted against `limits.allocations`
+    pub allocated: usize,
+    /// the calls of natives made so far, in order, when recording a trace of them
+    pub trace: Option<Vec<Effect>>,
+    /// what stops evaluation early at the host's request, if it can be
+    pub interrupt: Option<Interrupt>,
+    /// the calls of natives the program is expected to make, next first, when replaying a trace.
+    /// each call is answered with its recorded result rather than calling the native.
+    pub replay: Option<VecDeque<Effect>>,
+    /// whether async natives may be awaited
+    asynchronous: bool,
+}
+
+type Evaluation<'a> = Pin<Box<dyn Future<Output = EV<Value>> + 'a>>;
+
+/// how an iteration of a loop's body finished
+enum Tail {
+    Continue(Vec<Value>),
+    Done(Value),
+}
+
+impl Interpreter {
+    pub fn with_fuel(fuel: usize) -> Interpreter {
+        Interpreter { fuel: Some(fuel), ..Interpreter::default() }
+    }
+
+    fn step(&mut self) -> EV<()> {
+        self.steps += 1;
+        if let Some(interrupt) = &self.interrupt {
+            if self.steps.is_multiple_of(interrupt.interval) && interrupt.requested() {
+                return Err(EvalError::Interrupted { stack: vec![] })
+            }
+        }
+        match &mut self.fuel {
+            Some(0) => Err(EvalError::OutOfFuel),
+            Some(fuel) => {
+                *fuel -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn allocate(&mut self, values: usize) -> EV<()> {
+        self.allocated += values;
+        match self.limits.allocations {
+            Some(allocations) if self.allocated > allocations => Err(EvalError::ResourceExhausted(Resource::Allocations)),
+            _ => Ok(()),
+        }
+    }
+
+    /// evaluation is asynchronous so that it can suspend at calls to async natives. when
+    /// running synchronously, nothing ever suspends and the future is ready on its first poll.
+    fn eval<'a>(&'a mut self, env: &'a Arc<Environment>, mut expr: Expression) -> Evaluation<'a> {
+        Box::pin(async move {
+            self.step()?;
+            match &mut expr {
+                Expression::Variable(identifier) => match env.lookup(identifier) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(EvalError::UnboundIdentifier(core::mem::take(identifier))),
+                }
+                Expression::Boolean(value) => Ok(Value::Boolean(*value)),
+                Expression::Number(value) => Ok(Value::Number(*value)),
+                Expression::Float(Float(value)) => Ok(Value::Float(*value)),
+                Expression::String(value) => Ok(Value::String(core::mem::take(value))),
+                Expression::Char(value) => Ok(Value::Char(*value)),
+                Expression::Bytes(value) => Ok(Value::Bytes(core::mem::take(value))),
+                Expression::Tuple(elements) => {
+                    let mut values = vec![];
+                    for element in core::mem::take(elements) {
+                        values.push(self.eval(env, element).await?);
+                    }
+                    self.allocate(1)?;
+                    self.within_length(Value::Tuple(values))
+                }
+                Expression::Record(fields) => {
+                    let mut values = vec![];
+                    for (field, expr) in core::mem::take(fields) {
+                        values.push((field, self.eval(env, expr).await?));
+                    }
+                    self.allocate(1)?;
+                    self.within_length(Value::Record(Arc::new(values)))
+                }
+                Expression::Update { record, fields } => {
+                    let mut record = match self.eval(env, record.take()).await? {
+                        Value::Record(record) => record,
+                        found => return Err(EvalError::ExpectedRecord { found }),
+                    };
+                    let mut values = vec![];
+                    for (field, expr) in core::mem::take(fields) {
+                        values.push((field, self.eval(env, expr).await?));
+                    }
+                    // the fields are copied only if another value still shares them
+                    if Arc::get_mut(&mut record).is_none() {
+                        self.allocate(1)?;
+                    }
+                    update_fields(Arc::make_mut(&mut record), values);
+                    self.within_length(Value::Record(record))
+                }
+                Expression::Variant { tag, payload } => {
+                    let payload = Box::new(self.eval(env, payload.take()).await?);
+                    self.allocate(1)?;
+                    Ok(Value::Variant { tag: core::mem::take(tag), payload })
+                }
+                Expression::Match { scrutinee, arms } => {
+                    let value = self.eval(env, scrutinee.take()).await?;
+                    let (extended_env, body) = self.select_arm(env, value, core::mem::take(arms)).await?;
+                    self.eval(&extended_env, body).await
+                }
+                Expression::Loop { variables, body } => {
+                    let mut ids = vec![];
+                    let mut values = vec![];
+                    for (binding, init) in core::mem::take(variables) {
+                        values.push(self.eval(env, init).await?);
+                        ids.push(binding.id);
+                    }
+                    loop {
+                        let extended_env = self.bind(env, ids.iter().cloned().zip(values).collect())?;
+                        match self.eval_tail(&extended_env, (**body).clone()).await? {
+                            Tail::Continue(next) => values = next,
+                            Tail::Done(value) => return Ok(value),
+                        }
+                    }
+                }
+                Expression::Continue(_) => Err(EvalError::MisplacedContinue),
+                Expression::Returning { body, .. } => match self.eval(env, body.take()).await {
+                    Err(EvalError::Returned { value }) => Ok(value),
+                    result => result,
+                },
+                Expression::Return(value) => Err(EvalError::Returned { value: self.eval(env, value.take()).await? }),
+                Expression::Function { .. } => {
+                    self.allocate(1)?;
+                    let environment = Environment::capture(env, expr.free_variables());
+                    let Expression::Function { parameters, body } = &mut expr else { unreachable!("matched a function") };
+                    Ok(Value::Function { parameters: core::mem::take(parameters), body: core::mem::take(body), environment })
+                }
+                // the function, then the arguments in order, all before the call
+                Expression::Application { function, arguments } => match self.eval(env, function.take()).await? {
+                    function @ (Value::Function { .. } | Value::Native(_)) => {
+                        let mut values = vec![];
+                        for argument in core::mem::take(arguments) {
+                            values.push(self.eval(env, argument).await?);
+                        }
+                        self.apply(function, values).await
+                    }
+                    found => Err(EvalError::ExpectedFunction { found }),
+                },
+                Expression::If { condition, consequent, alternative } => match self.eval(env, condition.take()).await? {
+                    Value::Boolean(true) => self.eval(env, consequent.take()).await,
+                    Value::Boolean(false) => self.eval(env, alternative.take()).await,
+                    found => Err(EvalError::ExpectedBoolean { found }),
+                },
+                Expression::TypeTest { expression, tag } => {
+                    let found = match self.eval(env, expression.take()).await? {
+                        Value::Boolean(_) => TypeTag::Boolean,
+                        Value::Number(_) => TypeTag::Number,
+                        Value::Float(_) => TypeTag::Float,
+                        Value::String(_) => TypeTag::String,
+                        Value::Char(_) => TypeTag::Char,
+                        Value::Bytes(_) => TypeTag::Bytes,
+                        Value::Tuple(_) => TypeTag::Tuple,
+                        Value::Record(_) => TypeTag::Record,
+                        Value::Variant { .. } => TypeTag::Variant,
+                        Value::Function { .. } | Value::Native(_) => TypeTag::Function,
+                    };
+                    Ok(Value::Boolean(found == *tag))
+                }
+                Expression::IfTarget { target, .. } => Err(EvalError::UnresolvedTarget(core::mem::take(target))),
+                // types are erased at runtime
+                Expression::TypeFunction { body, .. } => self.eval(env, body.take()).await,
+                Expression::TypeApplication { function, .. } => self.eval(env, function.take()).await,
+                Expression::Constant(expression) | Expression::Total(expression) => self.eval(env, expression.take()).await,
+                Expression::Located { span, expression } => {
+                    let span = *span;
+                    self.hit(span);
+                    let call = matches!(**expression, Expression::Application { .. });
+                    self.eval(env, expression.take()).await.map_err(|error| unwind(error, call.then_some(span)))
+                }
+                Expression::Expanded { expression, .. } => self.eval(env, expression.take()).await,
+                Expression::Primitive { operator, arguments } => {
+                    let operator = *operator;
+                    let mut values = vec![];
+                    for argument in core::mem::take(arguments) {
+                        values.push(self.eval(env, argument).await?);
+                    }
+                    match (operator, &mut values[..]) {
+                        (Primitive::FoldChars, [string, accumulator, function]) => {
+                            let string = match string {
+                                Value::String(string) => core::mem::take(string),
+                                found => return Err(EvalError::ExpectedString { found: found.clone() }),
+                            };
+                            let mut accumulator = accumulator.clone();
+                            for c in string.chars() {
+                                accumulator = self.apply(function.clone(), vec![accumulator, Value::Char(c)]).await?;
+                            }
+                            Ok(accumulator)
+                        }
+                        (Primitive::FoldChars, _) => Err(EvalError::ArityMismatch { expected: 3, found: values.len() }),
+                        _ => self.within_length(primitives::apply_primitive(operator, values)?),
+                    }
+                }
+            }
+        })
+    }
+
+    /// evaluate the body of a loop, stopping at a `continue` in tail position
+    fn eval_tail<'a>(&'a mut self, env: &'a Arc<Environment>, mut expr: Expression) -> Pin<Box<dyn Future<Output = EV<Tail>> + 'a>> {
+        Box::pin(async move {
+            match &mut expr {
+                Expression::Continue(arguments) => {
+                    self.step()?;
+                    let mut values = vec![];
+                    for argument in core::mem::take(arguments) {
+                        values.push(self.eval(env, argument).await?);
+                    }
+                    Ok(Tail::Continue(values))
+                }
+                Expression::If { condition, consequent, alternative } => {
+                    self.step()?;
+                    match self.eval(env, condition.take()).await? {
+                        Value::Boolean(true) => self.eval_tail(env, consequent.take()).await,
+                        Value::Boolean(false) => self.eval_tail(env, alternative.take()).await,
+                        found => Err(EvalError::ExpectedBoolean { found }),
+                    }
+                }
+                Expression::Match { scrutinee, arms } => {
+                    self.step()?;
+                    let value = self.eval(env, scrutinee.take()).await?;
+                    let (extended_env, body) = self.select_arm(env, value, core::mem::take(arms)).await?;
+                    self.eval_tail(&extended_env, body).await
+                }
+                Expression::Located { span, expression } => {
+                    let span = *span;
+                    self.step()?;
+                    self.hit(span);
+                    let call = matches!(**expression, Expression::Application { .. });
+                    self.eval_tail(env, expression.take()).await.map_err(|error| unwind(error, call.then_some(span)))
+                }
+                Expression::Expanded { expression, .. } => {
+                    self.step()?;
+                    self.eval_tail(env, expression.take()).await
+                }
+                _ => Ok(Tail::Done(self.eval(env, expr).await?)),
+            }
+        })
+    }
+
+    /// the first arm that accepts `value`, along with the environment to evaluate its body in
+    async fn select_arm(&mut self, env: &Arc<Environment>, value: Value, arms: Vec<Arm>) -> EV<(Arc<Environment>, Expression)> {
+        for Arm { pattern, guard, body } in arms {
+            let mut bindings = HashMap::new();
+            if !pattern.matches(&value, &mut bindings) {
+                continue
+            }
+            let extended_env = self.bind(env, bindings)?;
+            if let Some(guard) = guard {
+                match self.eval(&extended_env, guard).await? {
+                    Value::Boolean(true) => {}
+                    Value::Boolean(false) => continue,
+                    found => return Err(EvalError::ExpectedBoolean { found }),
+                }
+            }
+            return Ok((extended_env, body))
+        }
+        Err(EvalError::MatchFailure { value })
+    }
+
+    /// count a hit on `span`, when recording coverage
+    fn hit(&mut self, span: Span) {
+        if let Some(hits) = &mut self.coverage {
+            *hits.entry(span).or_default() += 1;
+        }
+    }
+
+    /// `value`, unless it's longer than `limits.length` allows
+    fn within_length(&self, value: Value) -> EV<Value> {
+        let length = match &value {
+            Value::String(string) => string.len(),
+            Value::Bytes(bytes) => bytes.len(),
+            Value::Tuple(elements) => elements.len(),
+            Value::Record(fields) => fields.len(),
+            _ => 0,
+        };
+        match self.limits.length {
+            Some(limit) if length > limit => Err(EvalError::ResourceExhausted(Resource::Length)),
+            _ => Ok(value),
+        }
+    }
+
+    /// extend `env` with `bindings`, within the interpreter's limits
+    fn bind(&mut self, env: &Arc<Environment>, bindings: HashMap<Identifier, Value>) -> EV<Arc<Environment>> {
+        self.allocate(bindings.len())?;
+        let extended_env = Environment::extend(env, bindings);
+        if let Some(depth) = self.limits.environment_depth {
+            if extended_env.depth() > depth {
+                return Err(EvalError::ResourceExhausted(Resource::EnvironmentDepth))
+            }
+        }
+        Ok(extended_env)
+    }
+
+    /// call a function with evaluated arguments
+    fn apply(&mut self, function: Value, arguments: Vec<Value>) -> Evaluation<'_> {
+        Box::pin(async move {
+            match function {
+                Value::Function { .. } if self.memo.is_some() => {
+                    let Some(call) = memo::Memo::call(&function, &arguments) else {
+                        return self.call(function, arguments).await
+                    };
+                    if let Some(result) = self.memo.as_mut().and_then(|memo| memo.lookup(&call)) {
+                        return Ok(result)
+                    }
+                    let result = self.call(function, arguments).await?;
+                    if let Some(memo) = &mut self.memo {
+                        memo.remember(call, result.clone());
+                    }
+                    Ok(result)
+                }
+                Value::Function { .. } => self.call(function, arguments).await,
+                Value::Native(Native { name, function }) => {
+                    if let Some(replay) = &mut self.replay {
+                        return match replay.pop_front() {
+                            Some(effect) if effect.native == name && effect.arguments == arguments => effect.result,
+                            effect => Err(EvalError::Diverged {
+                                expected: effect.map_or_else(|| "the end of the program".to_owned(),
+                                                             |effect| format!("a call of {}", effect.native)),
+                                found: format!("a call of {}", name),
+                            }),
+                        }
+                    }
+                    let recorded = self.trace.is_some().then(|| arguments.clone());
+                    let result = match function {
+                        NativeFunction::Sync(function) => function(arguments),
+                        NativeFunction::Async(function) if self.asynchronous => function(arguments).await,
+                        NativeFunction::Async(_) => Err(EvalError::AsyncNative(name.clone())),
+                    };
+                    if let (Some(trace), Some(arguments)) = (&mut self.trace, recorded) {
+                        trace.push(Effect { native: name, arguments, result: result.clone() });
+                    }
+                    result
+                }
+                found => Err(EvalError::ExpectedFunction { found }),
+            }
+        })
+    }
+
+    /// call a closure with evaluated arguments, binding each to its parameter, which it has to
+    /// have exactly one of
+    fn call(&mut self, function: Value, mut arguments: Vec<Value>) -> Evaluation<'_> {
+        Box::pin(async move {
+            let Value::Function { parameters, body, environment } = function else {
+                return Err(EvalError::ExpectedFunction { found: function })
+            };
+            if let Some(Binding { typ: Type::Rest(_), .. }) = parameters.last() {
+                let rest = arguments.split_off((parameters.len() - 1).min(arguments.len()));
+                self.allocate(1)?;
+                arguments.push(Value::Tuple(rest));
+            }
+            if arguments.len() != parameters.len() {
+                return Err(EvalError::ArityMismatch { expected: parameters.len(), found: arguments.len() })
+            }
+            let bindings = parameters.into_iter().map(|param| param.id).zip(arguments).collect();
+            let extended_env = self.bind(&environment, bindings)?;
+            self.eval(&extended_env, *body).await
+        })
+    }
+
+    pub fn run(&mut self, expr: Expression) -> EV<Value> {
+        self.run_in(&Arc::new(Environment::default()), expr)
+    }
+
+    /// evaluate `expr` with the bindings of `env` in scope
+    pub fn run_in(&mut self, env: &Arc<Environment>, expr: Expression) -> EV<Value> {
+        self.asynchronous = false;
+        let mut context = Context::from_waker(Waker::noop());
+        match self.eval(env, expr).as_mut().poll(&mut context) {
+            Poll::Ready(result) => result,
+            Poll::Pending => unreachable!("synchronous evaluation never awaits a native"),
+        }
+    }
+
+    /// evaluate `expr` with the bindings of `env` in scope, suspending whenever an async
+    /// native is waiting on the host
+    pub async fn run_async(&mut self, env: &Arc<Environment>, expr: Expression) -> EV<Value> {
+        self.asynchronous = true;
+        let result = self.eval(env, expr).await;
+        self.asynchronous = false;
+        result
     }
 }
 
-pub fn run(expr: Expression) -> Value {
-    let subst = HashMap::new();
-    eval(&subst, expr)
+pub fn run(expr: Expression) -> EV<Value> {
+    Interpreter::default().run(expr)
 }
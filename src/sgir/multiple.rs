@@ -0,0 +1,82 @@
+use crate::prelude::*;
+
+use super::blocks::{block, let_in, Statement};
+use super::patterns::Pattern;
+use super::{Expression, Identifier, Type};
+
+// a function returns several values as a tuple of them, and a local declaration with several
+// names destructures it. as in Luau, values without a name are dropped, and names without a
+// value are given the unit value, since there's no `nil`.
+
+/// `return values`: one value is returned as itself, and any other number as a tuple
+pub fn returns(mut values: Vec<Expression>) -> Expression {
+    if values.len() == 1 {
+        values.remove(0)
+    } else {
+        Expression::Tuple(values)
+    }
+}
+
+/// the number of values an expression of type `typ` produces
+pub fn count(typ: &Type) -> usize {
+    match typ {
+        Type::Tuple(types) => types.len(),
+        _ => 1,
+    }
+}
+
+/// `values`, each with the number of values it produces, adjusted to `wanted` values like the
+/// values of a multiple assignment in Luau: each of them but the last gives its first value, and
+/// the last gives all of its values. extra values are dropped, though they're still evaluated,
+/// and missing ones are the unit value. it's a tuple of them, or the value itself if there's
+/// one.
+pub fn adjust(mut values: Vec<(Expression, usize)>, wanted: usize) -> Expression {
+    if let [(_, found)] = &values[..] {
+        if *found == wanted {
+            return values.remove(0).0
+        }
+    }
+    if values.len() == wanted && values.iter().all(|(_, found)| *found == 1) {
+        return Expression::Tuple(values.into_iter().map(|(value, _)| value).collect())
+    }
+    let last = values.len().saturating_sub(1);
+    let (mut statements, mut adjusted) = (vec![], vec![]);
+    for (i, (value, found)) in values.into_iter().enumerate() {
+        let id = |j| format!("%values{}.{}", i, j);
+        match found {
+            0 => {
+                statements.push(Statement::Expression(value));
+                if i < last {
+                    adjusted.push(Expression::Tuple(vec![]));
+                }
+            }
+            1 => {
+                statements.push(Statement::Local(Pattern::Variable(id(0)), value));
+                adjusted.push(Expression::Variable(id(0)));
+            }
+            found => {
+                let mut patterns: Vec<_> = (0..found).map(|j| Pattern::Variable(id(j))).collect();
+                // only the last value gives more than its first
+                if i < last {
+                    patterns[1..].fill(Pattern::Wildcard);
+                }
+                adjusted.extend(patterns.iter().filter_map(|pattern| match pattern {
+                    Pattern::Variable(id) => Some(Expression::Variable(id.clone())),
+                    _ => None,
+                }));
+                statements.push(Statement::Local(Pattern::Tuple(patterns), value));
+            }
+        }
+    }
+    adjusted.resize_with(wanted, || Expression::Tuple(vec![]));
+    let result = if wanted == 1 { adjusted.remove(0) } else { Expression::Tuple(adjusted) };
+    block(statements, result)
+}
+
+/// `local names = value` followed by `body`, where `value` has type `value_type`
+pub fn local(names: Vec<Identifier>, value: Expression, value_type: &Type, body: Expression) -> Expression {
+    let value = adjust(vec![(value, count(value_type))], names.len());
+    let mut patterns: Vec<_> = names.into_iter().map(Pattern::Variable).collect();
+    let pattern = if patterns.len() == 1 { patterns.remove(0) } else { Pattern::Tuple(patterns) };
+    let_in(pattern, value, body)
+}
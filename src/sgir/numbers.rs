@@ -0,0 +1,92 @@
+//! writing and reading numbers as text, the same way everywhere: the interpreter's `tostring`,
+//! `tonumber` and `tofloat`, the REPL, the formatter, and the serializers. none of it depends on the
+//! locale, so a number is written with `-` and the digits `0-9a-z`, never with a grouping
+//! separator or a decimal comma.
+
+use core::hash::{Hash, Hasher};
+
+use crate::prelude::*;
+
+/// the bases numbers can be written in, whose digits are `0-9` and then `a-z`
+pub const BASES: core::ops::RangeInclusive<u32> = 2..=36;
+
+/// `value` in `base`, with a `-` before it if it's negative and lowercase letters for the
+/// digits past 9, e.g. `-ff` in base 16
+pub fn format(value: i64, base: u32) -> String {
+    assert!(BASES.contains(&base), "{} is not a base numbers can be written in", base);
+    let mut magnitude = value.unsigned_abs();
+    let mut digits = vec![];
+    loop {
+        digits.push(char::from_digit((magnitude % base as u64) as u32, base).unwrap());
+        magnitude /= base as u64;
+        if magnitude == 0 {
+            break
+        }
+    }
+    if value < 0 {
+        digits.push('-');
+    }
+    digits.into_iter().rev().collect()
+}
+
+/// the number `text` writes in `base`: an optional `-` and then at least one digit, in either
+/// case, with nothing around them. it's `None` if `text` isn't one, or if the number doesn't fit.
+pub fn parse(text: &str, base: u32) -> Option<i64> {
+    assert!(BASES.contains(&base), "{} is not a base numbers can be written in", base);
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    if digits.is_empty() {
+        return None
+    }
+    // accumulated negatively, since the most negative number has no positive counterpart
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = c.to_digit(base)?;
+        value = value.checked_mul(base as i64)?.checked_sub(digit as i64)?;
+    }
+    if negative { Some(value) } else { value.checked_neg() }
+}
+
+/// the shortest decimal that reads back as exactly `value`, always with a `.` or an exponent so
+/// that it reads back as a float rather than a number, e.g. `1.0`, `0.1`, or `1e100`
+pub fn format_float(value: f64) -> String {
+    format!("{:?}", value)
+}
+
+/// the float `text` writes in decimal: an optional `-`, at least one digit, and then optionally a
+/// `.` and more digits and an exponent, e.g. `-1.5e3`, with nothing around them. it's `None` if
+/// `text` isn't one, or if the float is too large to be finite.
+pub fn parse_float(text: &str) -> Option<f64> {
+    let digits = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+    let unsigned = text.strip_prefix('-').unwrap_or(text);
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent.strip_prefix(['-', '+']).unwrap_or(exponent))),
+        None => (unsigned, None),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, "0"));
+    if !digits(whole) || !digits(fraction) || !exponent.is_none_or(digits) {
+        return None
+    }
+    text.parse::<f64>().ok().filter(|value| value.is_finite())
+}
+
+/// a float in an expression. it's never NaN, and it's compared and hashed by its bits, so that
+/// two expressions are only equal when they write exactly the same float, sign and all.
+#[derive(Clone, Copy, Debug)]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Float) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Float {}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
@@ -0,0 +1,197 @@
+use alloc::vec::IntoIter;
+use core::fmt::{self, Display, Formatter};
+use core::iter::Peekable;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use thiserror::Error;
+
+use super::blocks::let_in;
+use super::patterns::Pattern;
+use super::primitives::Primitive;
+use super::{Expression, Identifier};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum FixityError {
+    #[error("the operator {0} hasn't been declared")]
+    UnknownOperator(String),
+
+    #[error("{left} and {right} have the same precedence but don't associate, so they need parentheses")]
+    NonAssociative {
+        left: String,
+        right: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+    /// the operator can't be chained, so `a == b == c` needs parentheses
+    None,
+}
+
+impl Display for Associativity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Associativity::Left => write!(f, "left"),
+            Associativity::Right => write!(f, "right"),
+            Associativity::None => write!(f, "none"),
+        }
+    }
+}
+
+/// how tightly an infix operator binds. operators with higher precedence bind more tightly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fixity {
+    pub precedence: u8,
+    pub associativity: Associativity,
+}
+
+/// what an infix operator lowers to
+#[derive(Clone, Debug, PartialEq)]
+enum Meaning {
+    Primitive(Primitive),
+    /// a comparison that's a primitive one with its operands swapped, negated, or both: `a > b`
+    /// is `b < a`, and `a <= b` is `not (b < a)`
+    Comparison {
+        primitive: Primitive,
+        swapped: bool,
+        negated: bool,
+    },
+    /// `and`, which only evaluates its right operand if its left one is true
+    And,
+    /// `or`, which only evaluates its right operand if its left one is false
+    Or,
+    /// a user-defined operator, which is an ordinary function bound to the operator's symbol
+    Function(Identifier),
+}
+
+impl Meaning {
+    fn apply(&self, left: Expression, right: Expression) -> Expression {
+        let not = |condition| Expression::If {
+            condition: Box::new(condition),
+            consequent: Box::new(Expression::Boolean(false)),
+            alternative: Box::new(Expression::Boolean(true)),
+        };
+        match self {
+            Meaning::Primitive(operator) => Expression::Primitive { operator: *operator, arguments: vec![left, right] },
+            Meaning::Comparison { primitive, swapped, negated } => {
+                let comparison = if *swapped {
+                    // the operands are still evaluated from left to right
+                    let operand = |id: &str| (Pattern::Variable(id.to_owned()), Expression::Variable(id.to_owned()));
+                    let ((left_pattern, left_variable), (right_pattern, right_variable)) = (operand(LEFT), operand(RIGHT));
+                    let_in(Pattern::Tuple(vec![left_pattern, right_pattern]), Expression::Tuple(vec![left, right]),
+                           Expression::Primitive { operator: *primitive, arguments: vec![right_variable, left_variable] })
+                } else {
+                    Expression::Primitive { operator: *primitive, arguments: vec![left, right] }
+                };
+                if *negated { not(comparison) } else { comparison }
+            }
+            Meaning::And => Expression::If { condition: Box::new(left), consequent: Box::new(right), alternative: Box::new(Expression::Boolean(false)) },
+            Meaning::Or => Expression::If { condition: Box::new(left), consequent: Box::new(Expression::Boolean(true)), alternative: Box::new(right) },
+            Meaning::Function(id) => Expression::Application { function: Box::new(Expression::Variable(id.clone())), arguments: vec![left, right] },
+        }
+    }
+}
+
+/// the operands of a comparison that swaps them, which no program can refer to
+const LEFT: &str = "%left";
+const RIGHT: &str = "%right";
+
+/// the fixity environment the parser consults to resolve a chain of infix operators. it starts
+/// with the built-in operators, at Luau's precedences, and grows with each declaration.
+#[derive(Clone, Debug)]
+pub struct Operators {
+    operators: HashMap<String, (Fixity, Meaning)>,
+}
+
+impl Default for Operators {
+    fn default() -> Operators {
+        let builtins = [(3, Associativity::None, &[Primitive::Equal, Primitive::Less][..]),
+                        (4, Associativity::Left, &[Primitive::BitOr]),
+                        (5, Associativity::Left, &[Primitive::BitXor]),
+                        (6, Associativity::Left, &[Primitive::BitAnd]),
+                        (7, Associativity::Left, &[Primitive::ShiftLeft, Primitive::ShiftRight]),
+                        (8, Associativity::Right, &[Primitive::Append]),
+                        (9, Associativity::Left, &[Primitive::Add, Primitive::Subtract]),
+                        (10, Associativity::Left, &[Primitive::Multiply, Primitive::Quotient, Primitive::Divide, Primitive::Modulo]),
+                        // a prefix operator binds more tightly than anything but `^`, which the
+                        // parser takes care of
+                        (12, Associativity::Right, &[Primitive::Power])];
+        let mut operators: HashMap<_, _> = builtins.into_iter().flat_map(|(precedence, associativity, primitives)| {
+            primitives.iter().map(move |primitive| {
+                (primitive.to_string(), (Fixity { precedence, associativity }, Meaning::Primitive(*primitive)))
+            })
+        }).collect();
+
+        let comparison = Fixity { precedence: 3, associativity: Associativity::None };
+        let comparisons = [("~=", Primitive::Equal, false, true),
+                           (">", Primitive::Less, true, false),
+                           ("<=", Primitive::Less, true, true),
+                           (">=", Primitive::Less, false, true)];
+        for (symbol, primitive, swapped, negated) in comparisons {
+            operators.insert(symbol.to_owned(), (comparison, Meaning::Comparison { primitive, swapped, negated }));
+        }
+        operators.insert("and".to_owned(), (Fixity { precedence: 2, associativity: Associativity::Left }, Meaning::And));
+        operators.insert("or".to_owned(), (Fixity { precedence: 1, associativity: Associativity::Left }, Meaning::Or));
+        Operators { operators }
+    }
+}
+
+impl Operators {
+    /// declare the infix operator `symbol`, e.g. for `operator <+> left 6 (a, b) ... end`. the
+    /// operator applies the function bound to a variable named `symbol`, which only the
+    /// declaration can bind. a later declaration of the same symbol replaces an earlier one.
+    pub fn declare(&mut self, symbol: &str, fixity: Fixity) {
+        self.operators.insert(symbol.to_owned(), (fixity, Meaning::Function(symbol.to_owned())));
+    }
+
+    pub fn fixity(&self, symbol: &str) -> Option<Fixity> {
+        self.operators.get(symbol).map(|(fixity, _)| *fixity)
+    }
+
+    fn lookup(&self, symbol: &str) -> Result<&(Fixity, Meaning), FixityError> {
+        self.operators.get(symbol).ok_or_else(|| FixityError::UnknownOperator(symbol.to_owned()))
+    }
+
+    /// resolve the chain `first op1 e1 op2 e2 ...`, as the parser read it, into a tree of
+    /// applications
+    pub fn resolve(&self, first: Expression, rest: Vec<(String, Expression)>) -> Result<Expression, FixityError> {
+        let mut rest = rest.into_iter().peekable();
+        self.climb(first, &mut rest, 0)
+    }
+
+    /// precedence climbing: combine `left` with the operators that follow it, as long as they
+    /// have at least `minimum` precedence
+    fn climb(&self, mut left: Expression, rest: &mut Peekable<IntoIter<(String, Expression)>>,
+             minimum: u8) -> Result<Expression, FixityError> {
+        while let Some((symbol, _)) = rest.peek() {
+            let (fixity, meaning) = self.lookup(symbol)?;
+            if fixity.precedence < minimum {
+                break
+            }
+            let (symbol, mut right) = rest.next().unwrap();
+
+            // the right operand takes every operator that binds more tightly than this one
+            while let Some((next, _)) = rest.peek() {
+                let (next_fixity, _) = self.lookup(next)?;
+                if next_fixity.precedence > fixity.precedence {
+                    right = self.climb(right, rest, next_fixity.precedence)?;
+                } else if next_fixity.precedence < fixity.precedence {
+                    break
+                } else {
+                    match (fixity.associativity, next_fixity.associativity) {
+                        (Associativity::Left, Associativity::Left) => break,
+                        (Associativity::Right, Associativity::Right) => right = self.climb(right, rest, fixity.precedence)?,
+                        _ => return Err(FixityError::NonAssociative { left: symbol, right: next.clone() }),
+                    }
+                }
+            }
+
+            left = meaning.apply(left, right);
+        }
+        Ok(left)
+    }
+}
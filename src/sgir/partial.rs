@@ -0,0 +1,242 @@
+use alloc::sync::Arc;
+use core::mem;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use super::numbers::Float;
+use super::patterns::{Arm, Pattern};
+use super::primitives::{apply_primitive, Primitive};
+use super::{Binding, Expression, Identifier, Type, Value};
+
+/// the values known for variables in scope. they're all closed, so they can be substituted
+/// anywhere without being captured.
+type Known = HashMap<Identifier, Expression>;
+
+/// symbolically execute `expr`, with the variables in `inputs` known to have those values:
+/// applications of known functions are unfolded, primitives on constants are folded, and
+/// conditionals on constants are decided. at most `budget` applications are unfolded, so this
+/// terminates even when the program wouldn't. what can't be decided is left in place, and the
+/// result evaluates to the same value as `expr`.
+pub fn partial_evaluate(expr: Expression, inputs: HashMap<Identifier, Value>, budget: usize) -> Expression {
+    let known = inputs.into_iter()
+                      .map(|(id, value)| (id, value.reify()))
+                      .filter(|(_, value)| is_value(value))
+                      .collect();
+    PartialEvaluator { budget }.eval(&known, expr)
+}
+
+struct PartialEvaluator {
+    /// the number of applications that may still be unfolded
+    budget: usize,
+}
+
+impl PartialEvaluator {
+    fn eval(&mut self, known: &Known, mut expr: Expression) -> Expression {
+        match &mut expr {
+            Expression::Variable(id) => known.get(id).cloned().unwrap_or(expr),
+            Expression::Function { parameters, body } => {
+                let inner = without(known, parameters.iter().map(|Binding { id, .. }| id));
+                Expression::Function { body: Box::new(self.eval(&inner, body.take())), parameters: mem::take(parameters) }
+            }
+            Expression::Application { function, arguments } => {
+                let arguments = mem::take(arguments).into_iter().map(|argument| self.eval(known, argument)).collect();
+                // a function written in place is only evaluated once it's known whether it's unfolded
+                let function = match stripped(function) {
+                    Expression::Function { .. } => function.take(),
+                    _ => self.eval(known, function.take()),
+                };
+                match as_function(function) {
+                    Ok((parameters, body)) => self.unfold(known, parameters, body, arguments),
+                    Err(function) => Expression::Application { function: Box::new(function), arguments },
+                }
+            }
+            Expression::If { condition, consequent, alternative } => match self.eval(known, condition.take()) {
+                condition if *stripped(&condition) == Expression::Boolean(true) => self.eval(known, consequent.take()),
+                condition if *stripped(&condition) == Expression::Boolean(false) => self.eval(known, alternative.take()),
+                condition => Expression::If {
+                    condition: Box::new(condition),
+                    consequent: Box::new(self.eval(known, consequent.take())),
+                    alternative: Box::new(self.eval(known, alternative.take())),
+                },
+            },
+            Expression::Primitive { operator, arguments } => {
+                let operator = *operator;
+                let arguments = mem::take(arguments).into_iter().map(|argument| self.eval(known, argument)).collect::<Vec<_>>();
+                // a primitive that fails is left to fail at runtime, when it's reached
+                let folded = match operator {
+                    Primitive::FoldChars => None,
+                    _ => arguments.iter()
+                                  .map(literal)
+                                  .collect::<Option<Vec<_>>>()
+                                  .and_then(|values| apply_primitive(operator, values).ok()),
+                };
+                match folded {
+                    Some(value) => value.reify(),
+                    None => Expression::Primitive { operator, arguments },
+                }
+            }
+            Expression::Constant(expression) => match self.eval(known, expression.take()) {
+                expression if is_value(&expression) => expression,
+                expression => Expression::Constant(Box::new(expression)),
+            },
+            Expression::Returning { result, body } => {
+                let body = self.eval(known, body.take());
+                match stripped(&body) {
+                    Expression::Return(value) if is_value(value) => (**value).clone(),
+                    _ if is_value(&body) => body,
+                    _ => Expression::Returning { result: result.take(), body: Box::new(body) },
+                }
+            }
+            Expression::Match { scrutinee, arms } => {
+                let scrutinee = self.eval(known, scrutinee.take());
+                // a match that binds a value without testing it, like a `local`, is substituted away
+                if let Some(Arm { pattern, guard: None, body }) = arms.first() {
+                    let mut inner = without(known, pattern.variables().iter());
+                    if is_value(&scrutinee) && bind(pattern, stripped(&scrutinee), &mut inner) {
+                        return self.eval(&inner, body.clone())
+                    }
+                }
+                self.arms(known, scrutinee, mem::take(arms))
+            }
+            Expression::Loop { variables, body } => {
+                let variables = mem::take(variables).into_iter()
+                                                   .map(|(binding, init)| (binding, self.eval(known, init)))
+                                                   .collect::<Vec<_>>();
+                let inner = without(known, variables.iter().map(|(Binding { id, .. }, _)| id));
+                Expression::Loop { body: Box::new(self.eval(&inner, body.take())), variables }
+            }
+            _ => expr.map_children(|child| self.eval(known, child)),
+        }
+    }
+
+    fn arms(&mut self, known: &Known, scrutinee: Expression, arms: Vec<Arm>) -> Expression {
+        Expression::Match {
+            scrutinee: Box::new(scrutinee),
+            arms: arms.into_iter()
+                      .map(|Arm { pattern, guard, body }| {
+                          let inner = without(known, pattern.variables().iter());
+                          Arm { guard: guard.map(|guard| self.eval(&inner, guard)), body: self.eval(&inner, body), pattern }
+                      })
+                      .collect(),
+        }
+    }
+
+    /// `function(arguments)`, with the body of the function in place of the call if it's known
+    /// and there's budget left. arguments that are values are substituted into the body, and
+    /// the rest are still bound by a residual function, so they're evaluated just as before.
+    fn unfold(&mut self, known: &Known, parameters: Vec<Binding>, body: Expression, arguments: Vec<Expression>) -> Expression {
+        let variadic = matches!(parameters.last(), Some(Binding { typ: Type::Rest(_), .. }));
+        if self.budget == 0 || variadic || parameters.len() != arguments.len() {
+            let function = self.eval(known, Expression::Function { parameters, body: Box::new(body) });
+            return Expression::Application { function: Box::new(function), arguments }
+        }
+        self.budget -= 1;
+
+        let mut inner = without(known, parameters.iter().map(|Binding { id, .. }| id));
+        let (mut residual, mut passed) = (vec![], vec![]);
+        for (parameter, argument) in parameters.into_iter().zip(arguments) {
+            if is_value(&argument) {
+                inner.insert(parameter.id, strip(argument));
+            } else {
+                residual.push(parameter);
+                passed.push(argument);
+            }
+        }
+        let body = self.eval(&inner, body);
+        if residual.is_empty() {
+            return body
+        }
+        Expression::Application {
+            function: Box::new(Expression::Function { parameters: residual, body: Box::new(body) }),
+            arguments: passed,
+        }
+    }
+}
+
+/// `known` without the variables bound by a new scope
+fn without<'a>(known: &Known, bound: impl Iterator<Item = &'a Identifier>) -> Known {
+    let mut known = known.clone();
+    for id in bound {
+        known.remove(id);
+    }
+    known
+}
+
+/// `expr` without the source annotations around it
+fn strip(mut expr: Expression) -> Expression {
+    match &mut expr {
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => strip(expression.take()),
+        _ => expr,
+    }
+}
+
+/// the parameters and body of `expr` if it's a function, or `expr` as it was
+fn as_function(mut expr: Expression) -> Result<(Vec<Binding>, Expression), Expression> {
+    match &mut expr {
+        Expression::Function { parameters, body } => Ok((mem::take(parameters), body.take())),
+        Expression::Located { span, expression } => {
+            let span = *span;
+            as_function(expression.take()).map_err(|expression| Expression::Located { span, expression: Box::new(expression) })
+        }
+        _ => Err(expr),
+    }
+}
+
+/// `expr` without the source annotations around it, by reference
+fn stripped(expr: &Expression) -> &Expression {
+    match expr {
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => stripped(expression),
+        expr => expr,
+    }
+}
+
+/// bind the variables of `pattern` to the parts of the value `expr` in `known`, if the pattern
+/// is sure to match it. literal patterns are left for runtime.
+fn bind(pattern: &Pattern, expr: &Expression, known: &mut Known) -> bool {
+    match (pattern, expr) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Variable(id), expr) => {
+            known.insert(id.clone(), expr.clone());
+            true
+        }
+        (Pattern::Tuple(patterns), Expression::Tuple(elements)) if patterns.len() == elements.len() => {
+            patterns.iter().zip(elements).all(|(pattern, element)| bind(pattern, stripped(element), known))
+        }
+        _ => false,
+    }
+}
+
+/// whether `expr` is a closed value, which can be duplicated or dropped without changing what
+/// a program does
+fn is_value(expr: &Expression) -> bool {
+    match expr {
+        Expression::Boolean(_) | Expression::Number(_) | Expression::Float(_) | Expression::String(_) | Expression::Char(_)
+        | Expression::Bytes(_) => true,
+        Expression::Function { .. } => expr.free_variables().is_empty(),
+        Expression::Tuple(_) | Expression::Record(_) | Expression::Variant { .. } | Expression::Located { .. }
+        | Expression::Expanded { .. } => {
+            expr.children().into_iter().all(is_value)
+        }
+        _ => false,
+    }
+}
+
+/// the value of `expr`, if it's built from literals alone
+fn literal(expr: &Expression) -> Option<Value> {
+    Some(match expr {
+        Expression::Boolean(value) => Value::Boolean(*value),
+        Expression::Number(value) => Value::Number(*value),
+        Expression::Float(Float(value)) => Value::Float(*value),
+        Expression::String(value) => Value::String(value.clone()),
+        Expression::Char(value) => Value::Char(*value),
+        Expression::Bytes(value) => Value::Bytes(value.clone()),
+        Expression::Tuple(elements) => Value::Tuple(elements.iter().map(literal).collect::<Option<_>>()?),
+        Expression::Record(fields) => Value::Record(Arc::new(fields.iter()
+                                                                   .map(|(field, expr)| Some((field.clone(), literal(expr)?)))
+                                                                   .collect::<Option<_>>()?)),
+        Expression::Variant { tag, payload } => Value::Variant { tag: tag.clone(), payload: Box::new(literal(payload)?) },
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => literal(expression)?,
+        _ => return None,
+    })
+}
@@ -0,0 +1,330 @@
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use super::{is_subtype, join, Expression, Identifier, Type, TypeEnv, TypeError, Value};
+
+/// a pattern that a value can be matched against
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    /// matches anything, e.g. `_`
+    Wildcard,
+    /// matches anything and binds it to a variable, e.g. `x`
+    Variable(Identifier),
+
+    // Literals
+    Boolean(bool),
+    Number(i64),
+    String(String),
+    Char(char),
+
+    /// matches a tuple of the same length, e.g. `(p, q)`
+    Tuple(Vec<Pattern>),
+    /// matches a record with at least the listed fields, e.g. `{x = p}`
+    Record(Vec<(Identifier, Pattern)>),
+    /// matches one case of a variant, e.g. `Some(p)`
+    Variant {
+        tag: Identifier,
+        payload: Box<Pattern>,
+    },
+    /// matches what any of the alternatives matches, e.g. `A(x) | B(x)`. every alternative
+    /// binds the same variables.
+    Or(Vec<Pattern>),
+}
+
+/// one arm of a `match`, e.g. `case Some(x) if x > 0 -> x`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Arm {
+    pub pattern: Pattern,
+    /// the arm is only taken when the guard evaluates to true
+    pub guard: Option<Expression>,
+    pub body: Expression,
+}
+
+impl Pattern {
+    /// the immediate subpatterns of this pattern
+    fn children(&self) -> Vec<&Pattern> {
+        match self {
+            Pattern::Tuple(patterns) => patterns.iter().collect(),
+            Pattern::Record(fields) => fields.iter().map(|(_, pattern)| pattern).collect(),
+            Pattern::Variant { payload, .. } => vec![payload],
+            Pattern::Or(alternatives) => alternatives.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// the variables bound by this pattern, from left to right
+    pub fn variables(&self) -> Vec<Identifier> {
+        match self {
+            Pattern::Variable(id) => vec![id.clone()],
+            Pattern::Or(alternatives) => alternatives.first().map(Pattern::variables).unwrap_or_default(),
+            pattern => pattern.children().into_iter().flat_map(Pattern::variables).collect(),
+        }
+    }
+
+    /// rename the variables bound by this pattern according to `renaming`
+    pub fn rename(self, renaming: &HashMap<Identifier, Identifier>) -> Pattern {
+        match self {
+            Pattern::Variable(id) => Pattern::Variable(renaming.get(&id).cloned().unwrap_or(id)),
+            Pattern::Tuple(patterns) => Pattern::Tuple(patterns.into_iter().map(|pattern| pattern.rename(renaming)).collect()),
+            Pattern::Record(fields) => Pattern::Record(fields.into_iter()
+                                                             .map(|(field, pattern)| (field, pattern.rename(renaming)))
+                                                             .collect()),
+            Pattern::Variant { tag, payload } => Pattern::Variant { tag, payload: Box::new(payload.rename(renaming)) },
+            Pattern::Or(alternatives) => Pattern::Or(alternatives.into_iter().map(|pattern| pattern.rename(renaming)).collect()),
+            pattern => pattern,
+        }
+    }
+
+    /// does `value` match this pattern? the values of the variables it binds are added to
+    /// `bindings`.
+    pub fn matches(&self, value: &Value, bindings: &mut HashMap<Identifier, Value>) -> bool {
+        match (self, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Variable(id), value) => {
+                bindings.insert(id.clone(), value.clone());
+                true
+            }
+            (Pattern::Boolean(expected), Value::Boolean(found)) => expected == found,
+            (Pattern::Number(expected), Value::Number(found)) => expected == found,
+            (Pattern::String(expected), Value::String(found)) => expected == found,
+            (Pattern::Char(expected), Value::Char(found)) => expected == found,
+            (Pattern::Tuple(patterns), Value::Tuple(values)) => {
+                patterns.len() == values.len() &&
+                    patterns.iter().zip(values).all(|(pattern, value)| pattern.matches(value, bindings))
+            }
+            (Pattern::Record(patterns), Value::Record(fields)) => patterns.iter().all(|(field, pattern)| {
+                match fields.iter().find(|(id, _)| id == field) {
+                    Some((_, value)) => pattern.matches(value, bindings),
+                    None => false,
+                }
+            }),
+            (Pattern::Variant { tag: expected, payload: pattern }, Value::Variant { tag: found, payload }) => {
+                expected == found && pattern.matches(payload, bindings)
+            }
+            // an alternative that fails partway through mustn't leave its bindings behind
+            (Pattern::Or(alternatives), value) => alternatives.iter().any(|pattern| {
+                let mut alternative = bindings.clone();
+                let matched = pattern.matches(value, &mut alternative);
+                if matched {
+                    *bindings = alternative;
+                }
+                matched
+            }),
+            _ => false,
+        }
+    }
+}
+
+/// the cases of a variant type. a union of variants has the cases of all of them.
+fn variant_cases(typ: &Type) -> Option<Vec<(Identifier, Type)>> {
+    match typ {
+        Type::Variant(cases) => Some(cases.clone()),
+        Type::Union(members) if !members.is_empty() => {
+            let mut merged: Vec<(Identifier, Type)> = vec![];
+            for member in members {
+                for (tag, payload) in variant_cases(member)? {
+                    match merged.iter_mut().find(|(id, _)| *id == tag) {
+                        Some((_, existing)) => *existing = join(existing.clone(), payload),
+                        None => merged.push((tag, payload)),
+                    }
+                }
+            }
+            Some(merged)
+        }
+        _ => None,
+    }
+}
+
+/// check that `pattern` can match values of `typ`, adding the types of the variables it binds
+/// to `bindings`
+pub fn check_pattern(pattern: &Pattern, typ: &Type, bindings: &mut TypeEnv) -> Result<(), TypeError> {
+    let mismatch = || Err(TypeError::PatternMismatch { pattern: pattern.clone(), typ: typ.clone() });
+    let literal = |found: Type| if is_subtype(&found, typ) { Ok(()) } else { mismatch() };
+
+    match pattern {
+        Pattern::Wildcard => Ok(()),
+        Pattern::Variable(id) => match bindings.insert(id.clone(), typ.clone()) {
+            Some(_) => Err(TypeError::DuplicateBinding { id: id.clone(), first: None, second: None }),
+            None => Ok(()),
+        },
+        Pattern::Boolean(_) => literal(Type::Boolean),
+        Pattern::Number(_) => literal(Type::Number),
+        Pattern::String(_) => literal(Type::String),
+        Pattern::Char(_) => literal(Type::Char),
+        Pattern::Tuple(patterns) => match typ {
+            Type::Tuple(types) if types.len() == patterns.len() => {
+                for (pattern, typ) in patterns.iter().zip(types) {
+                    check_pattern(pattern, typ, bindings)?;
+                }
+                Ok(())
+            }
+            _ => mismatch(),
+        },
+        Pattern::Record(patterns) => match typ {
+            Type::Record(fields) => {
+                for (field, pattern) in patterns {
+                    match fields.iter().find(|(id, _)| id == field) {
+                        Some((_, typ)) => check_pattern(pattern, typ, bindings)?,
+                        None => return mismatch(),
+                    }
+                }
+                Ok(())
+            }
+            _ => mismatch(),
+        },
+        Pattern::Variant { tag, payload } => {
+            let cases = variant_cases(typ).unwrap_or_default();
+            match cases.iter().find(|(id, _)| id == tag) {
+                Some((_, typ)) => check_pattern(payload, typ, bindings),
+                None => mismatch(),
+            }
+        }
+        Pattern::Or(alternatives) => {
+            let mut checked = alternatives.iter().map(|pattern| {
+                let mut alternative = TypeEnv::new();
+                check_pattern(pattern, typ, &mut alternative)?;
+                Ok(alternative)
+            });
+            let Some(first) = checked.next().transpose()? else { return Ok(()) };
+            for alternative in checked {
+                let alternative = alternative?;
+                let inconsistent = first.iter()
+                                        .find(|(id, typ)| alternative.get(*id) != Some(typ))
+                                        .or_else(|| alternative.iter().find(|(id, _)| !first.contains_key(*id)));
+                if let Some((variable, _)) = inconsistent {
+                    return Err(TypeError::InconsistentAlternatives(variable.clone()))
+                }
+            }
+            for (id, typ) in first {
+                if bindings.insert(id.clone(), typ).is_some() {
+                    return Err(TypeError::DuplicateBinding { id, first: None, second: None })
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// the ways of building a value of a type whose values can be enumerated by shape
+enum Constructor {
+    Boolean(bool),
+    /// the single constructor of a tuple or record type
+    Product,
+    Variant(Identifier),
+}
+
+impl Constructor {
+    /// the constructors of `typ` along with the types of their fields, or nothing if there are
+    /// too many to enumerate, as with numbers and strings
+    fn all(typ: &Type) -> Option<Vec<(Constructor, Vec<Type>)>> {
+        match typ {
+            Type::Boolean => Some(vec![(Constructor::Boolean(true), vec![]), (Constructor::Boolean(false), vec![])]),
+            Type::Tuple(types) => Some(vec![(Constructor::Product, types.clone())]),
+            Type::Record(fields) => Some(vec![(Constructor::Product, fields.iter().map(|(_, typ)| typ.clone()).collect())]),
+            typ => variant_cases(typ).map(|cases| {
+                cases.into_iter()
+                     .map(|(tag, payload)| (Constructor::Variant(tag), vec![payload]))
+                     .collect()
+            }),
+        }
+    }
+
+    /// the subpatterns of `pattern` if it matches values built by this constructor, with
+    /// fields it doesn't mention filled in by wildcards
+    fn specialize(&self, typ: &Type, pattern: &Pattern, arity: usize) -> Option<Vec<Pattern>> {
+        match (self, pattern) {
+            (_, Pattern::Wildcard | Pattern::Variable(_)) => Some(vec![Pattern::Wildcard; arity]),
+            (Constructor::Boolean(expected), Pattern::Boolean(found)) => (expected == found).then(Vec::new),
+            (Constructor::Product, Pattern::Tuple(patterns)) => Some(patterns.clone()),
+            (Constructor::Product, Pattern::Record(patterns)) => match typ {
+                Type::Record(fields) => Some(fields.iter().map(|(field, _)| {
+                    patterns.iter()
+                            .find(|(id, _)| id == field)
+                            .map_or(Pattern::Wildcard, |(_, pattern)| pattern.clone())
+                }).collect()),
+                _ => None,
+            },
+            (Constructor::Variant(expected), Pattern::Variant { tag, payload }) => {
+                (expected == tag).then(|| vec![(**payload).clone()])
+            }
+            _ => None,
+        }
+    }
+
+    /// a pattern matching values built by this constructor from values matching `fields`
+    fn build(self, typ: &Type, fields: Vec<Pattern>) -> Pattern {
+        match (self, typ) {
+            (Constructor::Boolean(value), _) => Pattern::Boolean(value),
+            (Constructor::Product, Type::Record(types)) => {
+                Pattern::Record(types.iter().map(|(field, _)| field.clone()).zip(fields).collect())
+            }
+            (Constructor::Product, _) => Pattern::Tuple(fields),
+            (Constructor::Variant(tag), _) => Pattern::Variant { tag, payload: Box::new(fields.into_iter().next().unwrap()) },
+        }
+    }
+}
+
+/// find values of `types` matched by none of `rows`, each of which has a pattern for every
+/// column. this is the usefulness check of maranget's "warnings for pattern matching".
+fn missing(rows: Vec<Vec<Pattern>>, types: &[Type]) -> Option<Vec<Pattern>> {
+    let Some((typ, rest)) = types.split_first() else {
+        return if rows.is_empty() { Some(vec![]) } else { None }
+    };
+
+    // a row starting with an or-pattern covers what a row for each alternative would
+    let rows: Vec<_> = rows.into_iter().flat_map(expand_alternatives).collect();
+
+    match Constructor::all(typ) {
+        Some(constructors) => {
+            for (constructor, fields) in constructors {
+                let specialized = rows.iter()
+                                      .filter_map(|row| {
+                                          let mut specialized = constructor.specialize(typ, &row[0], fields.len())?;
+                                          specialized.extend(row[1..].iter().cloned());
+                                          Some(specialized)
+                                      })
+                                      .collect();
+                let columns: Vec<_> = fields.iter().chain(rest).cloned().collect();
+                if let Some(mut witness) = missing(specialized, &columns) {
+                    let rest = witness.split_off(fields.len());
+                    return Some(core::iter::once(constructor.build(typ, witness)).chain(rest).collect())
+                }
+            }
+            None
+        }
+        // only wildcards can cover a type with too many values to enumerate
+        None => {
+            let defaults = rows.into_iter()
+                               .filter(|row| matches!(row[0], Pattern::Wildcard | Pattern::Variable(_)))
+                               .map(|row| row[1..].to_vec())
+                               .collect();
+            missing(defaults, rest).map(|witness| core::iter::once(Pattern::Wildcard).chain(witness).collect())
+        }
+    }
+}
+
+fn expand_alternatives(row: Vec<Pattern>) -> Vec<Vec<Pattern>> {
+    match &row[0] {
+        Pattern::Or(alternatives) => alternatives.iter()
+                                                 .flat_map(|pattern| {
+                                                     let mut expanded = vec![pattern.clone()];
+                                                     expanded.extend(row[1..].iter().cloned());
+                                                     expand_alternatives(expanded)
+                                                 })
+                                                 .collect(),
+        _ => vec![row],
+    }
+}
+
+/// check that every value of `typ` is matched by one of `arms`. a guard might fail, so arms
+/// with guards don't count toward covering anything.
+pub fn check_exhaustive(typ: &Type, arms: &[Arm]) -> Result<(), TypeError> {
+    let rows = arms.iter()
+                   .filter(|arm| arm.guard.is_none())
+                   .map(|arm| vec![arm.pattern.clone()])
+                   .collect();
+    match missing(rows, core::slice::from_ref(typ)) {
+        Some(mut witness) => Err(TypeError::NonExhaustive { missing: witness.remove(0) }),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,218 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use super::numbers;
+use super::patterns::Pattern;
+use super::{check_types, Kind, Type, TypeBinding, TypeEnv, Value};
+
+/// write `items` separated by `separator`, each formatted by `write`
+fn write_separated<T>(f: &mut Formatter, items: &[T], separator: &str,
+                      mut write: impl FnMut(&mut Formatter, &T) -> fmt::Result) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write(f, item)?;
+    }
+    Ok(())
+}
+
+/// write `items` as a tuple, with a trailing comma when there's only one
+fn write_tuple<T>(f: &mut Formatter, items: &[T], write: impl FnMut(&mut Formatter, &T) -> fmt::Result) -> fmt::Result {
+    write!(f, "(")?;
+    write_separated(f, items, ", ", write)?;
+    if items.len() == 1 {
+        write!(f, ",")?;
+    }
+    write!(f, ")")
+}
+
+/// write a case of a variant, leaving out the payload when it's the unit tuple
+fn write_case<T: Display>(f: &mut Formatter, tag: &str, payload: &T, is_unit: bool) -> fmt::Result {
+    if is_unit {
+        write!(f, "{}", tag)
+    } else {
+        write!(f, "{}({})", tag, payload)
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Kind::Star => write!(f, "*"),
+            Kind::Variable(id) => write!(f, "{}", id),
+            Kind::Arrow { from, to } => {
+                match &from[..] {
+                    [kind @ (Kind::Star | Kind::Variable(_))] => write!(f, "{}", kind)?,
+                    _ => {
+                        write!(f, "(")?;
+                        write_separated(f, from, ", ", |f, kind| write!(f, "{}", kind))?;
+                        write!(f, ")")?;
+                    }
+                }
+                write!(f, " -> {}", to)
+            }
+        }
+    }
+}
+
+impl Display for TypeBinding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.kind {
+            Kind::Star => write!(f, "{}", self.id),
+            _ => write!(f, "{}: {}", self.id, self.kind),
+        }
+    }
+}
+
+impl Type {
+    /// does this type need parentheses when it appears as an operand?
+    fn is_compound(&self) -> bool {
+        matches!(self, Type::ForAll { .. } | Type::Function { .. } | Type::Intersection(_) | Type::Union(_))
+    }
+}
+
+/// a type in a position where compound types need parentheses
+struct Operand<'a>(&'a Type);
+
+impl Display for Operand<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.0.is_compound() {
+            write!(f, "({})", self.0)
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Type::Variable(id) => write!(f, "{}", id),
+            Type::ForAll { parameters, typ } => {
+                write!(f, "forall<")?;
+                write_separated(f, parameters, ", ", |f, parameter| write!(f, "{}", parameter))?;
+                write!(f, ">. {}", typ)
+            }
+            Type::Instantiate { typ, arguments } => {
+                write!(f, "{}<", Operand(typ))?;
+                write_separated(f, arguments, ", ", |f, argument| write!(f, "{}", argument))?;
+                write!(f, ">")
+            }
+            Type::Function { arguments, result } => {
+                write!(f, "(")?;
+                write_separated(f, arguments, ", ", |f, argument| write!(f, "{}", argument))?;
+                write!(f, ") -> {}", result)
+            }
+            Type::Intersection(types) => write_separated(f, types, " & ", |f, typ| write!(f, "{}", Operand(typ))),
+            Type::Union(types) => write_separated(f, types, " | ", |f, typ| write!(f, "{}", Operand(typ))),
+            Type::Boolean => write!(f, "Boolean"),
+            Type::Number => write!(f, "Number"),
+            Type::Float => write!(f, "Float"),
+            Type::String => write!(f, "String"),
+            Type::Char => write!(f, "Char"),
+            Type::Bytes => write!(f, "Bytes"),
+            Type::Tuple(types) => write_tuple(f, types, |f, typ| write!(f, "{}", typ)),
+            Type::Record(fields) => {
+                write!(f, "{{")?;
+                write_separated(f, fields, ", ", |f, (field, typ)| write!(f, "{}: {}", field, typ))?;
+                write!(f, "}}")
+            }
+            Type::Variant(cases) => {
+                write!(f, "variant {{ ")?;
+                write_separated(f, cases, ", ", |f, (tag, payload)| {
+                    write_case(f, tag, payload, *payload == Type::Tuple(vec![]))
+                })?;
+                write!(f, " }}")
+            }
+            Type::Rest(typ) => write!(f, "...{}", Operand(typ)),
+        }
+    }
+}
+
+impl Value {
+    /// the type of a value, if it can be recovered. closures are checked against the types of the
+    /// values they capture; natives carry no type of their own.
+    pub fn type_of(&self) -> Option<Type> {
+        match self {
+            Value::Boolean(_) => Some(Type::Boolean),
+            Value::Number(_) => Some(Type::Number),
+            Value::Float(_) => Some(Type::Float),
+            Value::String(_) => Some(Type::String),
+            Value::Char(_) => Some(Type::Char),
+            Value::Bytes(_) => Some(Type::Bytes),
+            Value::Tuple(values) => Some(Type::Tuple(values.iter().map(Value::type_of).collect::<Option<_>>()?)),
+            Value::Record(fields) => Some(Type::Record(fields.iter()
+                                                            .map(|(field, value)| Some((field.clone(), value.type_of()?)))
+                                                            .collect::<Option<_>>()?)),
+            Value::Variant { tag, payload } => Some(Type::Variant(vec![(tag.clone(), payload.type_of()?)])),
+            Value::Function { parameters, body, environment } => {
+                let mut tenv = TypeEnv::new();
+                for id in body.free_variables() {
+                    if let Some(typ) = environment.lookup(&id).and_then(Value::type_of) {
+                        tenv.insert(id, typ);
+                    }
+                }
+                for binding in parameters {
+                    tenv.insert(binding.id.clone(), binding.typ.clone());
+                }
+                let result = check_types(&HashMap::new(), &tenv, (**body).clone()).ok()?;
+                let arguments = parameters.iter().map(|binding| binding.typ.clone()).collect();
+                Some(Type::Function { arguments, result: Box::new(result) })
+            }
+            Value::Native(_) => None,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", numbers::format(*n, 10)),
+            Value::Float(n) => write!(f, "{}", numbers::format_float(*n)),
+            Value::String(string) => write!(f, "{:?}", string),
+            Value::Char(c) => write!(f, "{:?}", c),
+            Value::Bytes(bytes) => write!(f, "b\"{}\"", bytes.escape_ascii()),
+            Value::Tuple(values) => write_tuple(f, values, |f, value| write!(f, "{}", value)),
+            Value::Record(fields) => {
+                write!(f, "{{")?;
+                write_separated(f, fields, ", ", |f, (field, value)| write!(f, "{} = {}", field, value))?;
+                write!(f, "}}")
+            }
+            Value::Variant { tag, payload } => write_case(f, tag, payload, **payload == Value::Tuple(vec![])),
+            Value::Function { parameters, .. } => match self.type_of() {
+                Some(typ) => write!(f, "<fn {}>", typ),
+                None => {
+                    write!(f, "<fn (")?;
+                    write_separated(f, parameters, ", ", |f, binding| write!(f, "{}", binding.typ))?;
+                    write!(f, ")>")
+                }
+            },
+            Value::Native(native) => write!(f, "<native {}>", native.name),
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Variable(id) => write!(f, "{}", id),
+            Pattern::Boolean(value) => write!(f, "{}", value),
+            Pattern::Number(value) => write!(f, "{}", value),
+            Pattern::String(value) => write!(f, "{:?}", value),
+            Pattern::Char(value) => write!(f, "{:?}", value),
+            Pattern::Tuple(patterns) => write_tuple(f, patterns, |f, pattern| write!(f, "{}", pattern)),
+            Pattern::Record(fields) => {
+                write!(f, "{{")?;
+                write_separated(f, fields, ", ", |f, (field, pattern)| write!(f, "{} = {}", field, pattern))?;
+                write!(f, "}}")
+            }
+            Pattern::Variant { tag, payload } => write_case(f, tag, payload, **payload == Pattern::Tuple(vec![])),
+            Pattern::Or(alternatives) => write_separated(f, alternatives, " | ", |f, pattern| write!(f, "{}", pattern)),
+        }
+    }
+}
@@ -0,0 +1,725 @@
+use core::fmt::{self, Display, Formatter};
+use core::mem;
+
+use crate::prelude::*;
+
+use super::numbers::{self, BASES};
+use super::{is_subtype, join, EvalError, Type, TypeError, Value};
+
+/// the operations built into the language, as opposed to natives provided by the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Primitive {
+    /// structural equality, `a == b`
+    Equal,
+    /// numeric ordering, `a < b`
+    Less,
+    /// `a + b`
+    Add,
+    /// `a - b`
+    Subtract,
+    /// `a * b`
+    Multiply,
+    /// floor division, `a // b`, which rounds toward negative infinity
+    Divide,
+    /// `a % b`, which takes the sign of the divisor so that `(a // b) * b + a % b == a`
+    Modulo,
+    /// bitwise and, `a & b`, on the two's complement representation
+    BitAnd,
+    /// bitwise or, `a | b`
+    BitOr,
+    /// bitwise exclusive or, `a ~ b`
+    BitXor,
+    /// bitwise complement, `~a`
+    BitNot,
+    /// `a << n`. a negative `n` shifts right instead, and bits shifted past the top are discarded.
+    ShiftLeft,
+    /// arithmetic shift, `a >> n`, which is `a // 2^n`. a negative `n` shifts left instead.
+    ShiftRight,
+    /// exponentiation, `a ^ n`. a negative `n` makes a fraction, which rounds toward negative
+    /// infinity like `//` does.
+    Power,
+
+    // strings are indexed from 0. byte operations work on their utf-8 encoding and take byte
+    // offsets; character operations work on unicode scalar values and take character positions.
+    /// the length of a string in bytes
+    ByteLength,
+    /// the byte at a byte offset in a string, as a number
+    Byte,
+    /// the number of characters in a string
+    CharLength,
+    /// the character at a character position in a string
+    CharAt,
+    /// the character beginning at a byte offset in a string, which must fall on a character boundary
+    DecodeChar,
+    /// one string followed by another, `a .. b`
+    Append,
+    /// the number of bytes in the utf-8 encoding of a character
+    CharWidth,
+    /// the unicode scalar value of a character, as a number
+    CodePoint,
+    /// `fold_chars(s, init, f)` calls `f(acc, c)` for each character `c` of `s` in order,
+    /// threading the accumulator through from `init`
+    FoldChars,
+
+    /// the length of a byte buffer
+    BytesLength,
+    /// `slice(b, start, end)`, the bytes from `start` up to but not including `end`
+    Slice,
+    /// the bytes of one buffer followed by the bytes of another
+    Concat,
+    /// the utf-8 encoding of a string
+    EncodeUtf8,
+    /// the string whose utf-8 encoding is a byte buffer, which must be valid utf-8
+    DecodeUtf8,
+    /// the latin-1 encoding of a string, which must only contain characters up to U+00FF
+    EncodeLatin1,
+    /// the string whose latin-1 encoding is a byte buffer. every buffer is valid latin-1.
+    DecodeLatin1,
+
+    /// the number of values packed into a rest parameter, `select("#", ...)`
+    Count,
+    /// `select(n, ...)`, the value at position `n` of those packed into a rest parameter,
+    /// counting from 1, or back from the last at -1. unlike Luau's, it's only that value, not
+    /// all of the values from it on.
+    Select,
+
+    /// `assert(condition, contract, blame)` fails with a contract violation naming the
+    /// contract and who's to blame for it unless `condition` holds
+    Assert,
+
+    // the base of a conversion is optional, and 10 when it's left out. a conversion that can't
+    // be made exactly produces `()` rather than failing or rounding.
+    /// `tostring(n, base)`, a number written in a base from 2 to 36, or a float written in
+    /// decimal, the only base floats are written in
+    ToString,
+    /// `tonumber(s, base)`, the number a string writes in a base from 2 to 36, or `()` if it
+    /// doesn't write one
+    ToNumber,
+    /// `tointeger(x)`, the number a float is exactly, or `()` if it isn't a whole number that fits
+    /// in one. a number is already one.
+    ToInteger,
+    /// `tofloat(x)`, the float a number is exactly, or `()` if it's too large for a float to hold
+    /// exactly; or the float a string writes in decimal, or `()` if it doesn't write one
+    ToFloat,
+
+    // failures, which the interpreter locates at the call they fail in
+    /// `error(value)` fails with `value`. it never returns, so its result is of every type.
+    Error,
+    /// `check(condition, message)` fails with a failed assertion unless `condition` holds.
+    /// `message` is a rest of strings, the first of which, if any, says what was expected.
+    Check,
+
+    /// float division, `a / b`, of numbers or floats, to the nearest float. unlike in Luau,
+    /// dividing by zero fails rather than making an infinity or NaN, as does a quotient too large
+    /// to be finite.
+    Quotient,
+    /// the length operator, `#x`: the bytes in a string or a byte buffer, or the values packed
+    /// into a rest parameter or a tuple
+    Length,
+}
+
+/// the operators of the library, by the names a program calls them as: those of a module, like
+/// `string.len`, after the module's name
+pub const LIBRARY: &[(&str, Primitive)] = &[
+    ("string.len", Primitive::ByteLength),
+    ("string.byte", Primitive::Byte),
+    ("utf8.len", Primitive::CharLength),
+    ("utf8.char_at", Primitive::CharAt),
+    ("utf8.decode", Primitive::DecodeChar),
+    ("utf8.width", Primitive::CharWidth),
+    ("utf8.codepoint", Primitive::CodePoint),
+    ("utf8.fold", Primitive::FoldChars),
+    ("bytes.len", Primitive::BytesLength),
+    ("bytes.slice", Primitive::Slice),
+    ("bytes.concat", Primitive::Concat),
+    ("bytes.encode_utf8", Primitive::EncodeUtf8),
+    ("bytes.decode_utf8", Primitive::DecodeUtf8),
+    ("bytes.encode_latin1", Primitive::EncodeLatin1),
+    ("bytes.decode_latin1", Primitive::DecodeLatin1),
+    ("tostring", Primitive::ToString),
+    ("tonumber", Primitive::ToNumber),
+    ("tointeger", Primitive::ToInteger),
+    ("tofloat", Primitive::ToFloat),
+];
+
+/// a way of converting between strings and bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Encoding::Utf8 => write!(f, "utf-8"),
+            Encoding::Latin1 => write!(f, "latin-1"),
+        }
+    }
+}
+
+impl Primitive {
+    pub const ALL: &'static [Primitive] = &[Primitive::Equal, Primitive::Less, Primitive::Add, Primitive::Subtract,
+                                            Primitive::Multiply, Primitive::Divide, Primitive::Modulo, Primitive::BitAnd,
+                                            Primitive::BitOr, Primitive::BitXor, Primitive::BitNot, Primitive::ShiftLeft,
+                                            Primitive::ShiftRight, Primitive::ByteLength, Primitive::Byte,
+                                            Primitive::CharLength, Primitive::CharAt, Primitive::DecodeChar,
+                                            Primitive::CharWidth, Primitive::CodePoint, Primitive::FoldChars,
+                                            Primitive::BytesLength, Primitive::Slice, Primitive::Concat,
+                                            Primitive::EncodeUtf8, Primitive::DecodeUtf8, Primitive::EncodeLatin1,
+                                            Primitive::DecodeLatin1, Primitive::Count, Primitive::Select,
+                                            Primitive::Assert, Primitive::ToString, Primitive::ToNumber,
+                                            Primitive::ToInteger, Primitive::ToFloat, Primitive::Error,
+                                            Primitive::Check, Primitive::Power, Primitive::Append, Primitive::Quotient,
+                                            Primitive::Length];
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Primitive::FoldChars => 3,
+            Primitive::Equal | Primitive::Select => 2,
+            Primitive::Count | Primitive::Error | Primitive::Length => 1,
+            _ => self.signature().0.len(),
+        }
+    }
+
+    /// the operator of the library a program calls as `name`
+    pub fn library(name: &str) -> Option<Primitive> {
+        LIBRARY.iter().find(|(id, _)| *id == name).map(|(_, operator)| *operator)
+    }
+
+    /// the type of this operator as a function, unless it's polymorphic
+    pub fn function_type(&self) -> Option<Type> {
+        match self {
+            Primitive::Equal | Primitive::FoldChars | Primitive::Count | Primitive::Select | Primitive::Error | Primitive::Length => None,
+            _ => {
+                let (arguments, result) = self.signature();
+                Some(Type::Function { arguments, result: Box::new(result) })
+            }
+        }
+    }
+
+    /// the fewest arguments this operator can be applied to, when its last ones are optional
+    pub fn required(&self) -> usize {
+        match self {
+            Primitive::ToString | Primitive::ToNumber => 1,
+            _ => self.arity(),
+        }
+    }
+
+    /// does this operator take numbers to a number?
+    fn is_arithmetic(&self) -> bool {
+        matches!(self, Primitive::Add | Primitive::Subtract | Primitive::Multiply | Primitive::Divide
+                     | Primitive::Modulo | Primitive::BitAnd | Primitive::BitOr | Primitive::BitXor
+                     | Primitive::BitNot | Primitive::ShiftLeft | Primitive::ShiftRight | Primitive::Power)
+    }
+
+    /// the argument and result types of an operator that isn't polymorphic
+    fn signature(&self) -> (Vec<Type>, Type) {
+        match self {
+            Primitive::BitNot => (vec![Type::Number], Type::Number),
+            Primitive::Less => (vec![Type::Number, Type::Number], Type::Boolean),
+            Primitive::ByteLength | Primitive::CharLength => (vec![Type::String], Type::Number),
+            Primitive::Byte => (vec![Type::String, Type::Number], Type::Number),
+            Primitive::CharAt | Primitive::DecodeChar => (vec![Type::String, Type::Number], Type::Char),
+            Primitive::Append => (vec![Type::String, Type::String], Type::String),
+            Primitive::CharWidth | Primitive::CodePoint => (vec![Type::Char], Type::Number),
+            Primitive::BytesLength => (vec![Type::Bytes], Type::Number),
+            Primitive::Slice => (vec![Type::Bytes, Type::Number, Type::Number], Type::Bytes),
+            Primitive::Concat => (vec![Type::Bytes, Type::Bytes], Type::Bytes),
+            Primitive::EncodeUtf8 | Primitive::EncodeLatin1 => (vec![Type::String], Type::Bytes),
+            Primitive::DecodeUtf8 | Primitive::DecodeLatin1 => (vec![Type::Bytes], Type::String),
+            Primitive::Assert => (vec![Type::Boolean, Type::String, Type::String], Type::Tuple(vec![])),
+            Primitive::ToString => (vec![Type::Union(vec![Type::Number, Type::Float]), Type::Number], Type::String),
+            Primitive::ToNumber => (vec![Type::String, Type::Number], Type::Union(vec![Type::Number, Type::Tuple(vec![])])),
+            Primitive::ToInteger => (vec![Type::Union(vec![Type::Number, Type::Float])], Type::Union(vec![Type::Number, Type::Tuple(vec![])])),
+            Primitive::ToFloat => (vec![Type::Union(vec![Type::Number, Type::String])], Type::Union(vec![Type::Float, Type::Tuple(vec![])])),
+            Primitive::Check => (vec![Type::Boolean, Type::Rest(Box::new(Type::String))], Type::Tuple(vec![])),
+            Primitive::Quotient => {
+                let real = Type::Union(vec![Type::Number, Type::Float]);
+                (vec![real.clone(), real], Type::Float)
+            }
+            Primitive::Equal | Primitive::FoldChars | Primitive::Count | Primitive::Select | Primitive::Error | Primitive::Length => {
+                unreachable!("{} is polymorphic", self)
+            }
+            // the binary arithmetic operators
+            _ => (vec![Type::Number, Type::Number], Type::Number),
+        }
+    }
+}
+
+impl Display for Primitive {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Primitive::Equal => write!(f, "=="),
+            Primitive::Less => write!(f, "<"),
+            Primitive::Add => write!(f, "+"),
+            Primitive::Subtract => write!(f, "-"),
+            Primitive::Multiply => write!(f, "*"),
+            Primitive::Divide => write!(f, "//"),
+            Primitive::Modulo => write!(f, "%"),
+            Primitive::BitAnd => write!(f, "&"),
+            Primitive::BitOr => write!(f, "|"),
+            Primitive::BitXor | Primitive::BitNot => write!(f, "~"),
+            Primitive::ShiftLeft => write!(f, "<<"),
+            Primitive::ShiftRight => write!(f, ">>"),
+            Primitive::Power => write!(f, "^"),
+            Primitive::ByteLength => write!(f, "string.len"),
+            Primitive::Byte => write!(f, "string.byte"),
+            Primitive::CharLength => write!(f, "utf8.len"),
+            Primitive::CharAt => write!(f, "utf8.char_at"),
+            Primitive::DecodeChar => write!(f, "utf8.decode"),
+            Primitive::Append => write!(f, ".."),
+            Primitive::CharWidth => write!(f, "utf8.width"),
+            Primitive::CodePoint => write!(f, "utf8.codepoint"),
+            Primitive::FoldChars => write!(f, "utf8.fold"),
+            Primitive::BytesLength => write!(f, "bytes.len"),
+            Primitive::Slice => write!(f, "bytes.slice"),
+            Primitive::Concat => write!(f, "bytes.concat"),
+            Primitive::EncodeUtf8 => write!(f, "bytes.encode_utf8"),
+            Primitive::DecodeUtf8 => write!(f, "bytes.decode_utf8"),
+            Primitive::EncodeLatin1 => write!(f, "bytes.encode_latin1"),
+            Primitive::DecodeLatin1 => write!(f, "bytes.decode_latin1"),
+            Primitive::Count => write!(f, "select.count"),
+            Primitive::Select => write!(f, "select"),
+            Primitive::Assert => write!(f, "assert"),
+            Primitive::ToString => write!(f, "tostring"),
+            Primitive::ToNumber => write!(f, "tonumber"),
+            Primitive::ToInteger => write!(f, "tointeger"),
+            Primitive::ToFloat => write!(f, "tofloat"),
+            Primitive::Error => write!(f, "error"),
+            Primitive::Check => write!(f, "check"),
+            Primitive::Quotient => write!(f, "/"),
+            Primitive::Length => write!(f, "#"),
+        }
+    }
+}
+
+/// can values of `typ` be compared for equality? functions can't be, since there's no way to
+/// decide whether two of them behave the same.
+fn is_comparable(typ: &Type) -> bool {
+    match typ {
+        Type::Boolean | Type::Number | Type::Float | Type::String | Type::Char | Type::Bytes => true,
+        Type::Union(types) | Type::Tuple(types) => types.iter().all(is_comparable),
+        Type::Record(fields) | Type::Variant(fields) => fields.iter().all(|(_, typ)| is_comparable(typ)),
+        Type::Rest(typ) => is_comparable(typ),
+        _ => false,
+    }
+}
+
+/// the type of the values packed into a rest parameter of type `typ`
+fn rest_element(mut typ: Type) -> Result<Type, TypeError> {
+    match &mut typ {
+        Type::Rest(element) => Ok(element.take()),
+        // a tuple is a rest of any type its elements share
+        Type::Tuple(types) => Ok(mem::take(types).into_iter().fold(Type::Union(vec![]), join)),
+        _ => Err(TypeError::ExpectedRest { found: typ }),
+    }
+}
+
+fn expect_subtype(found: Type, expected: &Type) -> Result<(), TypeError> {
+    if is_subtype(&found, expected) {
+        Ok(())
+    } else {
+        Err(TypeError::TypeMismatch { expected: expected.clone(), found })
+    }
+}
+
+pub fn check_primitive(operator: Primitive, arguments: Vec<Type>) -> Result<Type, TypeError> {
+    if arguments.len() < operator.required() || arguments.len() > operator.arity() {
+        return Err(TypeError::ArityMismatch { expected: operator.arity(), found: arguments.len() })
+    }
+
+    match operator {
+        Primitive::Equal => {
+            for found in arguments {
+                if !is_comparable(&found) {
+                    return Err(TypeError::NotComparable { found })
+                }
+            }
+            Ok(Type::Boolean)
+        }
+        Primitive::FoldChars => {
+            let mut arguments = arguments.into_iter();
+            let (string, init, function) = (arguments.next().unwrap(), arguments.next().unwrap(), arguments.next().unwrap());
+            expect_subtype(string, &Type::String)?;
+            let mut function = function;
+            let (accumulator, result) = match &mut function {
+                Type::Function { arguments, result } if arguments.len() == 2 => {
+                    let mut arguments = mem::take(arguments).into_iter();
+                    let accumulator = arguments.next().unwrap();
+                    expect_subtype(Type::Char, &arguments.next().unwrap())?;
+                    (accumulator, result.take())
+                }
+                Type::Function { arguments, .. } => {
+                    return Err(TypeError::ArityMismatch { expected: 2, found: arguments.len() })
+                }
+                _ => return Err(TypeError::ExpectedFunction { found: function }),
+            };
+            expect_subtype(init, &accumulator)?;
+            expect_subtype(result, &accumulator)?;
+            Ok(accumulator)
+        }
+        Primitive::Count => {
+            rest_element(arguments.into_iter().next().unwrap())?;
+            Ok(Type::Number)
+        }
+        Primitive::Error => Ok(Type::Union(vec![])),
+        Primitive::Length => {
+            let found = arguments.into_iter().next().unwrap();
+            let sequence = Type::Union(vec![Type::String, Type::Bytes]);
+            if !is_subtype(&found, &sequence) {
+                rest_element(found.clone()).map_err(|_| TypeError::TypeMismatch { expected: sequence, found })?;
+            }
+            Ok(Type::Number)
+        }
+        Primitive::Select => {
+            let mut arguments = arguments.into_iter();
+            let (index, rest) = (arguments.next().unwrap(), arguments.next().unwrap());
+            expect_subtype(index, &Type::Number)?;
+            rest_element(rest)
+        }
+        _ => {
+            let (parameters, result) = operator.signature();
+            for (found, expected) in arguments.into_iter().zip(&parameters) {
+                expect_subtype(found, expected)?;
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// are all of the pairs equal? every pair is compared, so that comparing functions is an error
+/// wherever they appear.
+fn all_equal<'a>(pairs: impl Iterator<Item = (&'a Value, &'a Value)>) -> Result<bool, EvalError> {
+    let mut equal = true;
+    for (left, right) in pairs {
+        equal &= left.equals(right)?;
+    }
+    Ok(equal)
+}
+
+impl Value {
+    /// language-level equality: values of different types are unequal, and comparing
+    /// functions is an error
+    pub fn equals(&self, other: &Value) -> Result<bool, EvalError> {
+        match (self, other) {
+            (Value::Boolean(left), Value::Boolean(right)) => Ok(left == right),
+            (Value::Number(left), Value::Number(right)) => Ok(left == right),
+            (Value::Float(left), Value::Float(right)) => Ok(left == right),
+            (Value::String(left), Value::String(right)) => Ok(left == right),
+            (Value::Char(left), Value::Char(right)) => Ok(left == right),
+            (Value::Bytes(left), Value::Bytes(right)) => Ok(left == right),
+            (Value::Tuple(lefts), Value::Tuple(rights)) if lefts.len() == rights.len() => {
+                all_equal(lefts.iter().zip(rights))
+            }
+            // records are equal when they have the same fields with equal values, in any order
+            (Value::Record(lefts), Value::Record(rights)) if lefts.len() == rights.len() => {
+                let mut pairs = vec![];
+                for (field, left) in lefts.iter() {
+                    match rights.iter().find(|(id, _)| id == field) {
+                        Some((_, right)) => pairs.push((left, right)),
+                        None => return Ok(false),
+                    }
+                }
+                all_equal(pairs.into_iter())
+            }
+            (Value::Variant { tag: left_tag, payload: left }, Value::Variant { tag: right_tag, payload: right }) => {
+                Ok(left_tag == right_tag && left.equals(right)?)
+            }
+            (Value::Function { .. } | Value::Native(_), _) | (_, Value::Function { .. } | Value::Native(_)) => {
+                Err(EvalError::NotComparable { left: self.clone(), right: other.clone() })
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// language-level ordering, which only relates numbers
+    pub fn less_than(&self, other: &Value) -> Result<bool, EvalError> {
+        match (self, other) {
+            (Value::Number(left), Value::Number(right)) => Ok(left < right),
+            (Value::Number(_), found) | (found, _) => Err(EvalError::ExpectedNumber { found: found.clone() }),
+        }
+    }
+}
+
+/// shift `value` left by `amount` bits, or arithmetically right by `-amount` bits. shifting by the
+/// width of a number or more leaves only the sign (when shifting right) or nothing (when shifting left).
+fn shift_left(value: i64, amount: i64) -> i64 {
+    match u32::try_from(amount.unsigned_abs()) {
+        Ok(bits) if bits < i64::BITS => if amount >= 0 { value << bits } else { value >> bits },
+        _ => if amount >= 0 { 0 } else { value >> (i64::BITS - 1) },
+    }
+}
+
+/// `base ^ exponent`, or none if it doesn't fit in a number
+fn power(base: i64, exponent: i64) -> Option<i64> {
+    match (base, exponent) {
+        (1, _) => Some(1),
+        (-1, _) => Some(if exponent % 2 == 0 { 1 } else { -1 }),
+        (0, 1..) => Some(0),
+        (_, 0..) => base.checked_pow(u32::try_from(exponent).ok()?),
+        // the fraction is strictly between -1 and 1, and only negative for a negative base
+        // raised to an odd power
+        _ => Some(if base < 0 && exponent % 2 != 0 { -1 } else { 0 }),
+    }
+}
+
+/// integer arithmetic. numbers are 64-bit, and a sum, difference, product, quotient or power that
+/// doesn't fit is an error rather than wrapping around. shifts discard bits by definition.
+fn arithmetic(operator: Primitive, left: i64, right: i64) -> Result<i64, EvalError> {
+    if (matches!(operator, Primitive::Divide | Primitive::Modulo) && right == 0) || (operator == Primitive::Power && left == 0 && right < 0) {
+        return Err(EvalError::DivisionByZero)
+    }
+
+    let result = match operator {
+        Primitive::Add => left.checked_add(right),
+        Primitive::Subtract => left.checked_sub(right),
+        Primitive::Multiply => left.checked_mul(right),
+        Primitive::Divide => left.checked_div(right).map(|quotient| {
+            // truncating division rounds toward zero, so step down when the signs differ
+            if left % right != 0 && (left < 0) != (right < 0) { quotient - 1 } else { quotient }
+        }),
+        Primitive::Modulo => {
+            // `i64::MIN % -1` overflows in the hardware, but its remainder is exactly 0
+            let remainder = left.wrapping_rem(right);
+            Some(if remainder != 0 && (remainder < 0) != (right < 0) { remainder + right } else { remainder })
+        }
+        Primitive::BitAnd => Some(left & right),
+        Primitive::BitOr => Some(left | right),
+        Primitive::BitXor => Some(left ^ right),
+        Primitive::ShiftLeft => Some(shift_left(left, right)),
+        Primitive::ShiftRight => Some(shift_left(left, right.checked_neg().unwrap_or(i64::MAX))),
+        Primitive::Power => power(left, right),
+        _ => unreachable!("{} is not a binary arithmetic operator", operator),
+    };
+    result.ok_or(EvalError::Overflow { operator, left, right })
+}
+
+fn expect_string(value: &Value) -> Result<&str, EvalError> {
+    match value {
+        Value::String(string) => Ok(string),
+        found => Err(EvalError::ExpectedString { found: found.clone() }),
+    }
+}
+
+fn expect_char(value: &Value) -> Result<char, EvalError> {
+    match value {
+        Value::Char(c) => Ok(*c),
+        found => Err(EvalError::ExpectedChar { found: found.clone() }),
+    }
+}
+
+fn expect_bytes(value: &Value) -> Result<&[u8], EvalError> {
+    match value {
+        Value::Bytes(bytes) => Ok(bytes),
+        found => Err(EvalError::ExpectedBytes { found: found.clone() }),
+    }
+}
+
+fn expect_tuple(value: &Value) -> Result<&[Value], EvalError> {
+    match value {
+        Value::Tuple(values) => Ok(values),
+        found => Err(EvalError::ExpectedTuple { found: found.clone() }),
+    }
+}
+
+fn expect_number(value: &Value) -> Result<i64, EvalError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        found => Err(EvalError::ExpectedNumber { found: found.clone() }),
+    }
+}
+
+/// the float nearest a number, or a float itself
+fn expect_real(value: &Value) -> Result<f64, EvalError> {
+    match value {
+        Value::Number(n) => Ok(*n as f64),
+        Value::Float(float) => Ok(*float),
+        found => Err(EvalError::ExpectedNumber { found: found.clone() }),
+    }
+}
+
+/// `left / right` as a float. dividing by zero is an error, as is a quotient too large to be
+/// finite, rather than an infinity or NaN, so that a float is always finite.
+fn quotient(left: f64, right: f64) -> Result<Value, EvalError> {
+    if right == 0.0 {
+        return Err(EvalError::DivisionByZero)
+    }
+    match left / right {
+        quotient if quotient.is_finite() => Ok(Value::Float(quotient)),
+        _ => Err(EvalError::FloatOverflow { operator: Primitive::Quotient, left, right }),
+    }
+}
+
+/// 2^63, the least float too large for a number, whose negation is the most negative number
+const TWO_TO_THE_63: f64 = 9_223_372_036_854_775_808.0;
+
+/// the number `value` is exactly, if it's a whole number that fits in one
+fn exact_integer(value: f64) -> Option<i64> {
+    let integer = value as i64;
+    (-TWO_TO_THE_63..TWO_TO_THE_63).contains(&value).then_some(integer).filter(|integer| *integer as f64 == value)
+}
+
+/// the float `value` is exactly, if a float can hold it
+fn exact_float(value: i64) -> Option<f64> {
+    let float = value as f64;
+    // rounding can carry a number up to 2^63, which converts back to the largest number instead
+    Some(float).filter(|float| *float < TWO_TO_THE_63 && *float as i64 == value)
+}
+
+/// `()` for a conversion that couldn't be made exactly
+fn optional(value: Option<Value>) -> Value {
+    value.unwrap_or(Value::Tuple(vec![]))
+}
+
+/// the base a conversion is in: the one given, which must be from 2 to 36, or else 10
+fn expect_base(base: Option<&Value>) -> Result<u32, EvalError> {
+    let Some(base) = base else { return Ok(10) };
+    let base = expect_number(base)?;
+    match u32::try_from(base) {
+        Ok(base) if BASES.contains(&base) => Ok(base),
+        _ => Err(EvalError::InvalidBase { base }),
+    }
+}
+
+fn slice(bytes: &[u8], start: i64, end: i64) -> Result<Value, EvalError> {
+    match (usize::try_from(start), usize::try_from(end)) {
+        (Ok(from), Ok(to)) if from <= to && to <= bytes.len() => Ok(Value::Bytes(bytes[from..to].to_vec())),
+        _ => Err(EvalError::InvalidRange { start, end, length: bytes.len() }),
+    }
+}
+
+fn encode_latin1(string: &str) -> Result<Value, EvalError> {
+    let mut bytes = vec![];
+    for (offset, c) in string.char_indices() {
+        match u8::try_from(c) {
+            Ok(byte) => bytes.push(byte),
+            Err(_) => return Err(EvalError::InvalidEncoding { encoding: Encoding::Latin1, offset }),
+        }
+    }
+    Ok(Value::Bytes(bytes))
+}
+
+fn expect_index(value: &Value, length: usize) -> Result<usize, EvalError> {
+    match value {
+        Value::Number(index) => match usize::try_from(*index) {
+            Ok(i) if i < length => Ok(i),
+            _ => Err(EvalError::IndexOutOfBounds { index: *index, length }),
+        },
+        found => Err(EvalError::ExpectedNumber { found: found.clone() }),
+    }
+}
+
+/// the index of position `value` in a sequence of `length`, where 1 is the first and -1 the last
+fn expect_position(value: &Value, length: usize) -> Result<usize, EvalError> {
+    let Value::Number(position) = value else {
+        return Err(EvalError::ExpectedNumber { found: value.clone() })
+    };
+    let index = match *position {
+        position if position > 0 => usize::try_from(position - 1).ok(),
+        position => length.checked_sub(position.unsigned_abs() as usize).filter(|_| position < 0),
+    };
+    index.filter(|index| *index < length).ok_or(EvalError::IndexOutOfBounds { index: *position, length })
+}
+
+/// string operations, which never fail on account of the encoding: byte offsets reach every
+/// byte, character positions reach every character, and decoding in the middle of a character
+/// is an error rather than producing garbage.
+fn string_operation(operator: Primitive, string: &str, arguments: &[Value]) -> Result<Value, EvalError> {
+    match (operator, arguments) {
+        (Primitive::ByteLength, []) => Ok(Value::Number(string.len() as i64)),
+        (Primitive::CharLength, []) => Ok(Value::Number(string.chars().count() as i64)),
+        (Primitive::Byte, [index]) => {
+            let offset = expect_index(index, string.len())?;
+            Ok(Value::Number(string.as_bytes()[offset] as i64))
+        }
+        (Primitive::CharAt, [index]) => {
+            let position = expect_index(index, string.chars().count())?;
+            Ok(Value::Char(string.chars().nth(position).unwrap()))
+        }
+        (Primitive::DecodeChar, [index]) => {
+            let offset = expect_index(index, string.len())?;
+            match string.get(offset..).and_then(|rest| rest.chars().next()) {
+                Some(c) => Ok(Value::Char(c)),
+                None => Err(EvalError::NotCharBoundary { offset }),
+            }
+        }
+        _ => Err(EvalError::ArityMismatch { expected: operator.arity(), found: arguments.len() + 1 }),
+    }
+}
+
+/// apply an operator to evaluated arguments. `fold_chars` calls back into the interpreter, so it's
+/// applied there instead.
+pub fn apply_primitive(operator: Primitive, arguments: Vec<Value>) -> Result<Value, EvalError> {
+    match (operator, &arguments[..]) {
+        (Primitive::ByteLength | Primitive::Byte | Primitive::CharLength | Primitive::CharAt | Primitive::DecodeChar,
+         [string, rest @ ..]) => string_operation(operator, expect_string(string)?, rest),
+        (Primitive::Append, [left, right]) => Ok(Value::String([expect_string(left)?, expect_string(right)?].concat())),
+        (Primitive::Quotient, [left, right]) => quotient(expect_real(left)?, expect_real(right)?),
+        (Primitive::CharWidth, [c]) => Ok(Value::Number(expect_char(c)?.len_utf8() as i64)),
+        (Primitive::CodePoint, [c]) => Ok(Value::Number(u32::from(expect_char(c)?).into())),
+        (Primitive::FoldChars, _) => unreachable!("fold_chars is applied by the interpreter"),
+        (Primitive::BytesLength, [bytes]) => Ok(Value::Number(expect_bytes(bytes)?.len() as i64)),
+        (Primitive::Slice, [bytes, start, end]) => slice(expect_bytes(bytes)?, expect_number(start)?, expect_number(end)?),
+        (Primitive::Concat, [left, right]) => Ok(Value::Bytes([expect_bytes(left)?, expect_bytes(right)?].concat())),
+        (Primitive::EncodeUtf8, [string]) => Ok(Value::Bytes(expect_string(string)?.as_bytes().to_vec())),
+        (Primitive::DecodeUtf8, [bytes]) => match core::str::from_utf8(expect_bytes(bytes)?) {
+            Ok(string) => Ok(Value::String(string.to_owned())),
+            Err(error) => Err(EvalError::InvalidEncoding { encoding: Encoding::Utf8, offset: error.valid_up_to() }),
+        },
+        (Primitive::EncodeLatin1, [string]) => encode_latin1(expect_string(string)?),
+        (Primitive::DecodeLatin1, [bytes]) => Ok(Value::String(expect_bytes(bytes)?.iter().map(|byte| char::from(*byte)).collect())),
+        (Primitive::Count, [rest]) => Ok(Value::Number(expect_tuple(rest)?.len() as i64)),
+        (Primitive::Length, [Value::String(string)]) => Ok(Value::Number(string.len() as i64)),
+        (Primitive::Length, [Value::Bytes(bytes)]) => Ok(Value::Number(bytes.len() as i64)),
+        (Primitive::Length, [values]) => Ok(Value::Number(expect_tuple(values)?.len() as i64)),
+        (Primitive::Select, [index, rest]) => {
+            let values = expect_tuple(rest)?;
+            Ok(values[expect_position(index, values.len())?].clone())
+        }
+        (Primitive::Assert, [condition, contract, blame]) => match condition {
+            Value::Boolean(true) => Ok(Value::Tuple(vec![])),
+            Value::Boolean(false) => Err(EvalError::ContractViolation {
+                contract: expect_string(contract)?.to_owned(),
+                blame: expect_string(blame)?.to_owned(),
+            }),
+            found => Err(EvalError::ExpectedBoolean { found: found.clone() }),
+        },
+        (Primitive::Error, [value]) => Err(EvalError::Raised { value: value.clone(), at: None }),
+        (Primitive::Check, [condition, message]) => match condition {
+            Value::Boolean(true) => Ok(Value::Tuple(vec![])),
+            Value::Boolean(false) => Err(EvalError::AssertionFailed {
+                message: expect_tuple(message)?.first().map(expect_string).transpose()?.map(str::to_owned),
+                at: None,
+            }),
+            found => Err(EvalError::ExpectedBoolean { found: found.clone() }),
+        },
+        (Primitive::ToString, [Value::Float(float), base @ ..]) if base.len() <= 1 => match expect_base(base.first())? {
+            10 => Ok(Value::String(numbers::format_float(*float))),
+            base => Err(EvalError::FloatBase { base: base.into() }),
+        },
+        (Primitive::ToString, [number, base @ ..]) if base.len() <= 1 => {
+            Ok(Value::String(numbers::format(expect_number(number)?, expect_base(base.first())?)))
+        }
+        (Primitive::ToNumber, [string, base @ ..]) if base.len() <= 1 => {
+            let base = expect_base(base.first())?;
+            Ok(optional(numbers::parse(expect_string(string)?, base).map(Value::Number)))
+        }
+        (Primitive::ToInteger, [Value::Float(float)]) => Ok(optional(exact_integer(*float).map(Value::Number))),
+        (Primitive::ToInteger, [number]) => Ok(Value::Number(expect_number(number)?)),
+        (Primitive::ToFloat, [Value::Number(number)]) => Ok(optional(exact_float(*number).map(Value::Float))),
+        (Primitive::ToFloat, [string]) => Ok(optional(numbers::parse_float(expect_string(string)?).map(Value::Float))),
+        (Primitive::Equal, [left, right]) => Ok(Value::Boolean(left.equals(right)?)),
+        (Primitive::Less, [left, right]) => Ok(Value::Boolean(left.less_than(right)?)),
+        (Primitive::BitNot, [Value::Number(value)]) => Ok(Value::Number(!value)),
+        (Primitive::BitNot, [found]) => Err(EvalError::ExpectedNumber { found: found.clone() }),
+        (_, [Value::Number(left), Value::Number(right)]) if operator.is_arithmetic() => {
+            Ok(Value::Number(arithmetic(operator, *left, *right)?))
+        }
+        (_, [left, right]) if operator.is_arithmetic() => {
+            let found = if matches!(left, Value::Number(_)) { right } else { left };
+            Err(EvalError::ExpectedNumber { found: found.clone() })
+        }
+        _ => Err(EvalError::ArityMismatch { expected: operator.arity(), found: arguments.len() }),
+    }
+}
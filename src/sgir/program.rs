@@ -0,0 +1,182 @@
+use core::fmt::{self, Display, Formatter};
+use core::mem;
+
+use crate::collections::{BTreeSet, HashSet};
+use crate::prelude::*;
+
+use super::blocks::let_in;
+use super::patterns::{Arm, Pattern};
+use super::{Binding, EvalError, Expression, Identifier, Value};
+
+/// a function declared at the top level of a program, which can only refer to the globals and
+/// to the functions declared before it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declaration {
+    pub name: Identifier,
+    pub parameters: Vec<Binding>,
+    pub body: Expression,
+}
+
+/// a whole program as a backend sees it: top-level functions, which need no environment, and
+/// the expression that runs it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub declarations: Vec<Declaration>,
+    pub main: Expression,
+}
+
+impl Program {
+    /// the program as a single expression, with its declarations bound in order around `main`
+    pub fn into_expression(self) -> Expression {
+        self.declarations.into_iter().rev().fold(self.main, |body, Declaration { name, parameters, body: function }| {
+            let_in(Pattern::Variable(name), Expression::Function { parameters, body: Box::new(function) }, body)
+        })
+    }
+
+    pub fn run(self) -> Result<Value, EvalError> {
+        super::run(self.into_expression())
+    }
+
+    /// which declarations `main` and each declaration refer to
+    pub fn call_graph(&self) -> CallGraph {
+        let declared = self.declarations.iter().map(|declaration| declaration.name.clone()).collect::<HashSet<_>>();
+        let refers_to = |expr: &Expression, parameters: &[Binding]| {
+            let mut free = expr.free_variables();
+            for Binding { id, .. } in parameters {
+                free.remove(id);
+            }
+            free.into_iter().filter(|id| declared.contains(id)).collect()
+        };
+        CallGraph {
+            main: refers_to(&self.main, &[]),
+            declarations: self.declarations
+                              .iter()
+                              .map(|Declaration { name, parameters, body }| (name.clone(), refers_to(body, parameters)))
+                              .collect(),
+        }
+    }
+
+    /// the program without the declarations that can't be reached from `main` or from one of
+    /// `exports`
+    pub fn without_unreachable(self, exports: &[Identifier]) -> Program {
+        let reachable = self.call_graph().reachable(exports);
+        Program {
+            declarations: self.declarations.into_iter().filter(|declaration| reachable.contains(&declaration.name)).collect(),
+            main: self.main,
+        }
+    }
+}
+
+/// the references between the declarations of a program. a reference that isn't a call still
+/// counts, since the function it refers to might be called wherever it ends up.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CallGraph {
+    /// the declarations `main` refers to
+    pub main: BTreeSet<Identifier>,
+    /// each declaration, in order, with the declarations it refers to
+    pub declarations: Vec<(Identifier, BTreeSet<Identifier>)>,
+}
+
+impl CallGraph {
+    /// the declarations reachable from `main`, or from one of `exports`
+    pub fn reachable(&self, exports: &[Identifier]) -> HashSet<Identifier> {
+        let mut reachable = HashSet::new();
+        let mut pending = self.main.iter().chain(exports).cloned().collect::<Vec<_>>();
+        while let Some(name) = pending.pop() {
+            if !reachable.insert(name.clone()) {
+                continue
+            }
+            for (_, callees) in self.declarations.iter().filter(|(declaration, _)| *declaration == name) {
+                pending.extend(callees.iter().cloned());
+            }
+        }
+        reachable
+    }
+}
+
+/// lift every function in `expr` that doesn't refer to a variable bound around it to a
+/// declaration of its own, innermost first. the functions nested in it have been lifted by then,
+/// so a function that only captures those is lifted too. functions that capture a local are
+/// left where they are, and so are functions inside a type abstraction, whose types may refer to
+/// its parameters.
+pub fn lift_lambdas(expr: Expression) -> Program {
+    let mut lifter = Lifter { declarations: vec![] };
+    let main = lifter.lift(expr, &HashSet::new(), false);
+    Program { declarations: lifter.declarations, main }
+}
+
+struct Lifter {
+    declarations: Vec<Declaration>,
+}
+
+impl Lifter {
+    /// lift the functions in `expr`, where the variables in `bound` are locals
+    fn lift(&mut self, mut expr: Expression, bound: &HashSet<Identifier>, polymorphic: bool) -> Expression {
+        let within = |ids: Vec<Identifier>| bound.iter().cloned().chain(ids).collect::<HashSet<_>>();
+        match &mut expr {
+            Expression::Function { parameters, body } => {
+                let parameters = mem::take(parameters);
+                let inner = within(parameters.iter().map(|Binding { id, .. }| id.clone()).collect());
+                let body = self.lift(body.take(), &inner, polymorphic);
+                let mut free = body.free_variables();
+                for Binding { id, .. } in &parameters {
+                    free.remove(id);
+                }
+                if polymorphic || !free.is_disjoint(bound) {
+                    return Expression::Function { parameters, body: Box::new(body) }
+                }
+                let name = format!("%lambda{}", self.declarations.len());
+                self.declarations.push(Declaration { name: name.clone(), parameters, body });
+                Expression::Variable(name)
+            }
+            Expression::Match { scrutinee, arms } => Expression::Match {
+                scrutinee: Box::new(self.lift(scrutinee.take(), bound, polymorphic)),
+                arms: mem::take(arms).into_iter()
+                                    .map(|Arm { pattern, guard, body }| {
+                                        let inner = within(pattern.variables());
+                                        Arm {
+                                            guard: guard.map(|guard| self.lift(guard, &inner, polymorphic)),
+                                            body: self.lift(body, &inner, polymorphic),
+                                            pattern,
+                                        }
+                                    })
+                                    .collect(),
+            },
+            Expression::Loop { variables, body } => {
+                let variables = mem::take(variables).into_iter()
+                                                    .map(|(binding, init)| (binding, self.lift(init, bound, polymorphic)))
+                                                    .collect::<Vec<_>>();
+                let inner = within(variables.iter().map(|(Binding { id, .. }, _)| id.clone()).collect());
+                Expression::Loop { body: Box::new(self.lift(body.take(), &inner, polymorphic)), variables }
+            }
+            Expression::TypeFunction { parameters, body } => {
+                Expression::TypeFunction { body: Box::new(self.lift(body.take(), bound, true)), parameters: mem::take(parameters) }
+            }
+            _ => expr.map_children(|child| self.lift(child, bound, polymorphic)),
+        }
+    }
+}
+
+/// the call graph in the DOT language, with the declarations that can't be reached from `main`
+/// drawn dashed
+impl Display for CallGraph {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let reachable = self.reachable(&[]);
+        writeln!(f, "digraph calls {{")?;
+        writeln!(f, "    main;")?;
+        for callee in &self.main {
+            writeln!(f, "    main -> {:?};", callee)?;
+        }
+        for (name, callees) in &self.declarations {
+            if reachable.contains(name) {
+                writeln!(f, "    {:?};", name)?;
+            } else {
+                writeln!(f, "    {:?} [style=dashed];", name)?;
+            }
+            for callee in callees {
+                writeln!(f, "    {:?} -> {:?};", name, callee)?;
+            }
+        }
+        write!(f, "}}")
+    }
+}
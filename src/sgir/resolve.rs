@@ -0,0 +1,199 @@
+//! resolving names: every binder in a program, of a variable or of a type, gets a name of its
+//! own, `x%n`, and every reference to it is rewritten to that name. nothing shadows anything
+//! afterwards, so each name is the id of exactly one binder, and the passes after this one can
+//! tell variables apart by name alone, without tracking scopes again. a variable that neither
+//! the program nor the host's declarations bind is reported before anything else happens.
+//!
+//! the host's declarations, including the exports of other modules, are always referred to by
+//! their whole names, e.g. `module.name`, so they can't be ambiguous. they're left as they are.
+//! a reference to a variable another module keeps private is reported as such, with how to export
+//! it, rather than as unbound.
+
+use core::mem;
+
+use crate::prelude::*;
+
+use thiserror::Error;
+
+use super::deep::Tree;
+use super::macros::FreshNames;
+use super::patterns::{Arm, Pattern};
+use super::{Binding, Declarations, Expression, Identifier, Span, Type, TypeBinding};
+use crate::collections::HashSet;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ResolveError {
+    #[error("unbound identifier: {0}")]
+    UnboundIdentifier(Identifier),
+
+    #[error("{id} is private to the module {module}, so it can't be used outside of it; declare it `pub` in {module} to export it")]
+    Private {
+        id: Identifier,
+        module: String,
+    },
+
+    #[error("{error} at {}:{}", span.start.line, span.start.column)]
+    Located {
+        error: Box<ResolveError>,
+        span: Span,
+    },
+}
+
+type RS<T> = Result<T, ResolveError>;
+
+struct Resolver<'a> {
+    declarations: &'a Declarations,
+    /// the variables of other modules that they keep private, as `module.name`
+    private: &'a HashSet<Identifier>,
+    names: FreshNames,
+    /// the variables in scope, innermost last, each with the name of its binder
+    variables: Vec<(Identifier, Identifier)>,
+    /// and the type variables
+    types: Vec<(Identifier, Identifier)>,
+}
+
+/// the name of the binder of `id` in `scope`, if it's bound there
+fn lookup<'a>(scope: &'a [(Identifier, Identifier)], id: &str) -> Option<&'a Identifier> {
+    scope.iter().rev().find(|(name, _)| name == id).map(|(_, binder)| binder)
+}
+
+impl Resolver<'_> {
+    /// bring a variable named `id` into scope, producing the name of its binder
+    fn bind(&mut self, id: &str) -> Identifier {
+        let binder = self.names.fresh(id);
+        self.variables.push((id.to_owned(), binder.clone()));
+        binder
+    }
+
+    fn binding(&mut self, Binding { id, typ }: &mut Binding) {
+        self.typ(typ);
+        *id = self.bind(id);
+    }
+
+    fn type_bindings(&mut self, parameters: &mut [TypeBinding]) {
+        for TypeBinding { id, .. } in parameters {
+            let binder = self.names.fresh(id);
+            self.types.push((mem::take(id), binder.clone()));
+            *id = binder;
+        }
+    }
+
+    /// rename the type variables of `typ`. those that aren't bound are left alone, for the kind
+    /// checker to report or for the host to declare, like its abstract types.
+    fn typ(&mut self, typ: &mut Type) {
+        match typ {
+            Type::Variable(id) => {
+                if let Some(binder) = lookup(&self.types, id) {
+                    *id = binder.clone();
+                }
+            }
+            Type::ForAll { parameters, typ } => {
+                let scope = self.types.len();
+                self.type_bindings(parameters);
+                self.typ(typ);
+                self.types.truncate(scope);
+            }
+            _ => typ.each_child_mut(|typ| self.typ(typ)),
+        }
+    }
+
+    fn arm(&mut self, Arm { pattern, guard, body }: &mut Arm) -> RS<()> {
+        let scope = self.variables.len();
+        // the alternatives of an or-pattern bind the same variables, to the same binders
+        let renaming = pattern.variables().into_iter().map(|id| {
+            let binder = self.bind(&id);
+            (id, binder)
+        }).collect();
+        *pattern = mem::replace(pattern, Pattern::Wildcard).rename(&renaming);
+        if let Some(guard) = guard {
+            self.expression(guard)?;
+        }
+        self.expression(body)?;
+        self.variables.truncate(scope);
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &mut Expression) -> RS<()> {
+        match expr {
+            Expression::Variable(id) => match lookup(&self.variables, id) {
+                Some(binder) => *id = binder.clone(),
+                None if self.declarations.contains_key(id) => {}
+                None if self.private.contains(id) => {
+                    let (module, _) = id.split_once('.').unwrap_or((id, ""));
+                    return Err(ResolveError::Private { id: id.clone(), module: module.to_owned() })
+                }
+                None => return Err(ResolveError::UnboundIdentifier(id.clone())),
+            },
+            Expression::Function { parameters, body } => {
+                let scope = self.variables.len();
+                parameters.iter_mut().for_each(|binding| self.binding(binding));
+                self.expression(body)?;
+                self.variables.truncate(scope);
+            }
+            Expression::Match { scrutinee, arms } => {
+                self.expression(scrutinee)?;
+                for arm in arms {
+                    self.arm(arm)?;
+                }
+            }
+            // the initial values of a loop's variables are outside it
+            Expression::Loop { variables, body } => {
+                for (_, init) in variables.iter_mut() {
+                    self.expression(init)?;
+                }
+                let scope = self.variables.len();
+                variables.iter_mut().for_each(|(binding, _)| self.binding(binding));
+                self.expression(body)?;
+                self.variables.truncate(scope);
+            }
+            Expression::Returning { result, body } => {
+                if let Some(result) = result {
+                    self.typ(result);
+                }
+                self.expression(body)?;
+            }
+            Expression::TypeFunction { parameters, body } => {
+                let scope = self.types.len();
+                self.type_bindings(parameters);
+                self.expression(body)?;
+                self.types.truncate(scope);
+            }
+            Expression::TypeApplication { function, arguments } => {
+                self.expression(function)?;
+                arguments.iter_mut().for_each(|typ| self.typ(typ));
+            }
+            Expression::Located { span, expression } => {
+                let span = *span;
+                self.expression(expression).map_err(|error| match error {
+                    ResolveError::Located { .. } => error,
+                    error => ResolveError::Located { error: Box::new(error), span },
+                })?;
+            }
+            _ => {
+                let mut result = Ok(());
+                expr.each_child_mut(|child| {
+                    if result.is_ok() {
+                        result = self.expression(child);
+                    }
+                });
+                result?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `expr` with each of its binders given a name of its own, and each of its variables renamed to
+/// the binder it refers to, or an error about the first variable that neither it nor
+/// `declarations` binds
+pub fn resolve(expr: Expression, declarations: &Declarations) -> RS<Expression> {
+    resolve_module(expr, declarations, &HashSet::new())
+}
+
+/// `resolve` a module that depends on other modules, which keep the variables in `private` to
+/// themselves
+pub fn resolve_module(mut expr: Expression, declarations: &Declarations, private: &HashSet<Identifier>) -> RS<Expression> {
+    let mut resolver = Resolver { declarations, private, names: FreshNames::default(), variables: vec![], types: vec![] };
+    resolver.expression(&mut expr)?;
+    Ok(expr)
+}
@@ -0,0 +1,213 @@
+//! a small-step semantics for the core calculus, alongside the interpreter's `eval`. a term
+//! steps one redex at a time, left to right, by substituting values for variables rather than
+//! keeping an environment, so that every term on the way from a program to its value is itself a
+//! closed term that can be checked. that's what the progress and preservation tests rely on.
+//!
+//! the primitives are applied by the interpreter, so that the two semantics can't disagree
+//! about them. loops, returns, and type functions aren't part of the core calculus, and don't
+//! step.
+
+use core::iter;
+use core::mem;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use super::macros::{substitute, FreshNames};
+use super::patterns::Arm;
+use super::primitives::Primitive;
+use super::{run, update_fields, Binding, EvalError, Expression, Type, EV};
+
+/// what a term does when it's stepped
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    /// it's a value, which takes no more steps
+    Value(Expression),
+    /// it takes a step to this term
+    Next(Expression),
+}
+
+/// is `expr` a value: a literal, a function, or a tuple, record, or variant of values?
+pub fn is_value(expr: &Expression) -> bool {
+    match expr {
+        Expression::Boolean(_) | Expression::Number(_) | Expression::Float(_) | Expression::String(_) | Expression::Char(_)
+        | Expression::Bytes(_) | Expression::Function { .. } => true,
+        Expression::Tuple(elements) => elements.iter().all(is_value),
+        Expression::Record(fields) => fields.iter().all(|(_, value)| is_value(value)),
+        Expression::Variant { payload, .. } => is_value(payload),
+        _ => false,
+    }
+}
+
+/// the subterm of the closed term `expr` that its next step contracts, or `None` if it's a value
+pub fn redex(expr: &Expression) -> Option<&Expression> {
+    if is_value(expr) {
+        return None
+    }
+    let operands: Vec<&Expression> = match expr {
+        Expression::Tuple(elements) => elements.iter().collect(),
+        Expression::Record(fields) => fields.iter().map(|(_, value)| value).collect(),
+        Expression::Update { record, fields } => iter::once(&**record).chain(fields.iter().map(|(_, value)| value)).collect(),
+        Expression::Application { function, arguments } => iter::once(&**function).chain(arguments).collect(),
+        Expression::Primitive { arguments, .. } => arguments.iter().collect(),
+        Expression::Variant { payload: operand, .. } | Expression::If { condition: operand, .. }
+        | Expression::Match { scrutinee: operand, .. } | Expression::TypeTest { expression: operand, .. }
+        | Expression::Located { expression: operand, .. } | Expression::Expanded { expression: operand, .. }
+        | Expression::Constant(operand) | Expression::Total(operand) => vec![&**operand],
+        _ => vec![],
+    };
+    // the first operand that isn't a value steps, the same order `reduce` steps them in
+    Some(operands.into_iter().find_map(redex).unwrap_or(expr))
+}
+
+/// take one step of evaluating the closed term `expr`
+pub fn step(expr: Expression) -> EV<Step> {
+    if is_value(&expr) {
+        Ok(Step::Value(expr))
+    } else {
+        reduce(expr).map(Step::Next)
+    }
+}
+
+/// step the first of `expressions` that isn't a value in place, or report that they all are
+fn step_first<'a>(expressions: impl IntoIterator<Item = &'a mut Expression>) -> EV<bool> {
+    for expr in expressions {
+        if !is_value(expr) {
+            *expr = reduce(expr.take())?;
+            return Ok(true)
+        }
+    }
+    Ok(false)
+}
+
+/// the term `expr`, which isn't a value, steps to
+fn reduce(mut expr: Expression) -> EV<Expression> {
+    match &mut expr {
+        Expression::Variable(id) => Err(EvalError::UnboundIdentifier(mem::take(id))),
+        Expression::Tuple(elements) => {
+            step_first(elements)?;
+            Ok(expr)
+        }
+        Expression::Record(fields) => {
+            step_first(fields.iter_mut().map(|(_, value)| value))?;
+            Ok(expr)
+        }
+        Expression::Variant { payload, .. } => {
+            step_first([&mut **payload])?;
+            Ok(expr)
+        }
+        Expression::Update { record, fields } => {
+            if step_first(iter::once(&mut **record).chain(fields.iter_mut().map(|(_, value)| value)))? {
+                return Ok(expr)
+            }
+            match &mut **record {
+                Expression::Record(values) => {
+                    update_fields(values, mem::take(fields));
+                    Ok(record.take())
+                }
+                _ => Err(EvalError::ExpectedRecord { found: run(record.take())? }),
+            }
+        }
+        Expression::Application { function, arguments } => {
+            if step_first(iter::once(&mut **function).chain(arguments.iter_mut()))? {
+                return Ok(expr)
+            }
+            call(function.take(), mem::take(arguments))
+        }
+        Expression::If { condition, consequent, alternative } => {
+            if step_first([&mut **condition])? {
+                return Ok(expr)
+            }
+            match **condition {
+                Expression::Boolean(true) => Ok(consequent.take()),
+                Expression::Boolean(false) => Ok(alternative.take()),
+                _ => Err(EvalError::ExpectedBoolean { found: run(condition.take())? }),
+            }
+        }
+        Expression::Match { scrutinee, arms } => {
+            if step_first([&mut **scrutinee])? {
+                return Ok(expr)
+            }
+            let value = run((**scrutinee).clone())?;
+            if arms.is_empty() {
+                return Err(EvalError::MatchFailure { value })
+            }
+            let Arm { pattern, guard, body } = arms.remove(0);
+            let mut bindings = HashMap::new();
+            if !pattern.matches(&value, &mut bindings) {
+                return Ok(expr)
+            }
+            let replacements = bindings.into_iter().map(|(id, value)| (id, value.reify())).collect();
+            let names = &mut FreshNames::default();
+            let body = substitute(body, &replacements, names);
+            // a guard that fails falls through to the arms after it
+            Ok(match guard {
+                Some(guard) => Expression::If {
+                    condition: Box::new(substitute(guard, &replacements, names)),
+                    consequent: Box::new(body),
+                    alternative: Box::new(expr),
+                },
+                None => body,
+            })
+        }
+        Expression::Primitive { operator: Primitive::FoldChars, arguments } => {
+            if step_first(arguments.iter_mut())? {
+                return Ok(expr)
+            }
+            match &mut arguments[..] {
+                [Expression::String(string), init, function] => {
+                    Ok(string.chars().fold(init.take(), |accumulator, c| Expression::Application {
+                        function: Box::new(function.clone()),
+                        arguments: vec![accumulator, Expression::Char(c)],
+                    }))
+                }
+                [string, _, _] => Err(EvalError::ExpectedString { found: run(string.take())? }),
+                _ => Err(EvalError::ArityMismatch { expected: 3, found: arguments.len() }),
+            }
+        }
+        // once its arguments are values, a primitive takes the interpreter a single step
+        Expression::Primitive { arguments, .. } => {
+            if step_first(arguments.iter_mut())? {
+                return Ok(expr)
+            }
+            Ok(run(expr)?.reify())
+        }
+        Expression::TypeTest { expression, .. } => {
+            if step_first([&mut **expression])? {
+                return Ok(expr)
+            }
+            Ok(run(expr)?.reify())
+        }
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } | Expression::Constant(expression)
+        | Expression::Total(expression) => {
+            if step_first([&mut **expression])? {
+                return Ok(expr)
+            }
+            Ok(expression.take())
+        }
+        Expression::IfTarget { target, .. } => Err(EvalError::UnresolvedTarget(mem::take(target))),
+        Expression::Loop { .. } => Err(EvalError::NoSmallStep("a loop")),
+        Expression::Continue(_) => Err(EvalError::NoSmallStep("a continue")),
+        Expression::Returning { .. } | Expression::Return(_) => Err(EvalError::NoSmallStep("a return")),
+        Expression::TypeFunction { .. } | Expression::TypeApplication { .. } => Err(EvalError::NoSmallStep("a type function")),
+        Expression::Boolean(_) | Expression::Number(_) | Expression::Float(_) | Expression::String(_) | Expression::Char(_)
+        | Expression::Bytes(_) | Expression::Function { .. } => unreachable!("a value takes no steps"),
+    }
+}
+
+/// call the value `function` with the values `arguments`, by substituting them for its
+/// parameters in its body
+fn call(mut function: Expression, mut arguments: Vec<Expression>) -> EV<Expression> {
+    let Expression::Function { parameters, body } = &mut function else {
+        return Err(EvalError::ExpectedFunction { found: run(function)? })
+    };
+    if let Some(Binding { typ: Type::Rest(_), .. }) = parameters.last() {
+        let rest = arguments.split_off((parameters.len() - 1).min(arguments.len()));
+        arguments.push(Expression::Tuple(rest));
+    }
+    if arguments.len() != parameters.len() {
+        return Err(EvalError::ArityMismatch { expected: parameters.len(), found: arguments.len() })
+    }
+    let replacements = parameters.iter().map(|Binding { id, .. }| id.clone()).zip(arguments).collect();
+    Ok(substitute(body.take(), &replacements, &mut FreshNames::default()))
+}
@@ -0,0 +1,20 @@
+use super::Expression;
+
+/// the target a program runs on unless it's compiled for another, such as `"wasm"` for the
+/// playground
+pub const NATIVE: &str = "native";
+
+/// select the branch of every target conditional in `expr` that applies to `target`, so that
+/// no trace of the other targets remains at runtime
+pub fn resolve_targets(mut expr: Expression, target: &str) -> Expression {
+    match &mut expr {
+        Expression::IfTarget { target: expected, consequent, alternative } => {
+            if expected == target {
+                resolve_targets(consequent.take(), target)
+            } else {
+                resolve_targets(alternative.take(), target)
+            }
+        }
+        _ => expr.map_children(|child| resolve_targets(child, target)),
+    }
+}
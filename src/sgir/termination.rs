@@ -0,0 +1,179 @@
+use crate::collections::HashSet;
+use crate::prelude::*;
+
+use super::patterns::{Arm, Pattern};
+use super::primitives::Primitive;
+use super::{Binding, Expression, Identifier, TypeError};
+
+// without recursive bindings or recursive types, the only way an SGIR program can run forever is
+// a loop that never stops continuing. so a function terminates when each of its loops does, and
+// calls to the functions it's passed do.
+
+/// what's known to hold where a `continue` is reached
+#[derive(Clone, Debug, Default)]
+struct Context {
+    /// comparisons that hold, each `(left, right)` for `left < right`
+    less: Vec<(Expression, Expression)>,
+    /// comparisons that don't, each `(left, right)` for `right <= left`
+    not_less: Vec<(Expression, Expression)>,
+    /// variables bound to a part of another variable's value by a pattern, each `(part, whole)`
+    parts: Vec<(Identifier, Identifier)>,
+    /// the variables bound inside the body of the loop, whose values may change from one
+    /// iteration to the next, and which may shadow its variables
+    local: HashSet<Identifier>,
+}
+
+impl Context {
+    /// what's still known inside a scope that binds `bound`
+    fn binding(&self, bound: &[Identifier]) -> Context {
+        let mentions = |expr: &Expression| expr.free_variables().iter().any(|id| bound.contains(id));
+        Context {
+            less: self.less.iter().filter(|(left, right)| !mentions(left) && !mentions(right)).cloned().collect(),
+            not_less: self.not_less.iter().filter(|(left, right)| !mentions(left) && !mentions(right)).cloned().collect(),
+            parts: self.parts.iter().filter(|(part, whole)| !bound.contains(part) && !bound.contains(whole)).cloned().collect(),
+            local: self.local.iter().chain(bound).cloned().collect(),
+        }
+    }
+
+    /// whether `part` is bound to a part of the value of `whole`, or a part of one of its parts
+    fn is_part(&self, part: &str, whole: &str) -> bool {
+        self.parts.iter().any(|(found, of)| found == part && (of == whole || self.is_part(of, whole)))
+    }
+}
+
+/// check that every loop in `expr` terminates, as they must in a function marked `total`. a loop
+/// terminates when one of its variables gets smaller on every `continue`, with no end to how
+/// many times that can happen: either it's continued with a part of its value taken apart by
+/// a pattern, or it's a number continued with itself minus a constant while above some bound,
+/// or plus a constant while below one. a bound is a literal, a variable bound outside the loop,
+/// or a loop variable continued with itself.
+pub fn check_loops(expr: &Expression) -> Result<(), TypeError> {
+    if let Expression::Loop { variables, body } = expr {
+        let mut continues = vec![];
+        collect_continues(body, &Context::default(), &mut continues);
+        let decreasing = (0..variables.len()).any(|i| {
+            continues.iter().all(|(arguments, context)| decreases(variables, i, arguments, context))
+        });
+        if !decreasing {
+            return Err(TypeError::MayNotTerminate {
+                variables: variables.iter().map(|(Binding { id, .. }, _)| id.clone()).collect(),
+            })
+        }
+    }
+    expr.children().into_iter().try_for_each(check_loops)
+}
+
+/// the arguments of every `continue` of the loop with `body`, with what's known where each is
+/// reached. those inside nested loops and functions belong to them.
+fn collect_continues<'a>(expr: &'a Expression, context: &Context, found: &mut Vec<(&'a [Expression], Context)>) {
+    match expr {
+        Expression::Continue(arguments) => found.push((arguments, context.clone())),
+        Expression::Loop { .. } | Expression::Function { .. } => {}
+        Expression::If { condition, consequent, alternative } => {
+            let (mut holds, mut fails) = (context.clone(), context.clone());
+            if let Expression::Primitive { operator: Primitive::Less, arguments } = stripped(condition) {
+                if let [left, right] = &arguments[..] {
+                    holds.less.push((unlocated(left), unlocated(right)));
+                    fails.not_less.push((unlocated(left), unlocated(right)));
+                }
+            }
+            collect_continues(consequent, &holds, found);
+            collect_continues(alternative, &fails, found);
+        }
+        Expression::Match { scrutinee, arms } => {
+            let whole = match stripped(scrutinee) {
+                Expression::Variable(whole) if !context.local.contains(whole) => Some(whole),
+                _ => None,
+            };
+            for Arm { pattern, guard, body } in arms {
+                let mut inner = context.binding(&pattern.variables());
+                if let Some(whole) = whole {
+                    inner.parts.extend(parts(pattern).into_iter().map(|part| (part, whole.clone())));
+                }
+                if let Some(guard) = guard {
+                    collect_continues(guard, &inner, found);
+                }
+                collect_continues(body, &inner, found);
+            }
+        }
+        expr => {
+            for child in expr.children() {
+                collect_continues(child, context, found);
+            }
+        }
+    }
+}
+
+/// whether the `i`th of `variables` gets smaller when the loop is continued with `arguments`
+fn decreases(variables: &[(Binding, Expression)], i: usize, arguments: &[Expression], context: &Context) -> bool {
+    let id = &variables[i].0.id;
+    let Some(argument) = arguments.get(i) else { return false };
+    if context.local.contains(id) {
+        return false
+    }
+    let is_id = |expr: &Expression| matches!(stripped(expr), Expression::Variable(variable) if variable == id);
+    // a bound stays the same from one iteration to the next
+    let is_bound = |expr: &Expression| expr.free_variables().iter().all(|variable| {
+        match variables.iter().position(|(Binding { id, .. }, _)| id == variable) {
+            Some(j) => !context.local.contains(variable)
+                && matches!(arguments.get(j).map(stripped), Some(Expression::Variable(argument)) if argument == variable),
+            None => !context.local.contains(variable),
+        }
+    });
+    let positive = |expr: &Expression| matches!(stripped(expr), Expression::Number(step) if *step > 0);
+    match stripped(argument) {
+        Expression::Variable(part) => context.is_part(part, id),
+        // x - k, where b < x or x >= b
+        Expression::Primitive { operator: Primitive::Subtract, arguments } => match &arguments[..] {
+            [left, step] if is_id(left) && positive(step) => {
+                context.less.iter().any(|(bound, right)| is_id(right) && is_bound(bound))
+                    || context.not_less.iter().any(|(left, bound)| is_id(left) && is_bound(bound))
+            }
+            _ => false,
+        },
+        // x + k, where x < b or b >= x
+        Expression::Primitive { operator: Primitive::Add, arguments } => match &arguments[..] {
+            [left, right] if (is_id(left) && positive(right)) || (positive(left) && is_id(right)) => {
+                context.less.iter().any(|(left, bound)| is_id(left) && is_bound(bound))
+                    || context.not_less.iter().any(|(bound, right)| is_id(right) && is_bound(bound))
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// the variables `pattern` binds to a part of the value it matches, rather than the whole of it
+fn parts(pattern: &Pattern) -> Vec<Identifier> {
+    match pattern {
+        Pattern::Tuple(_) | Pattern::Record(_) | Pattern::Variant { .. } => pattern.variables(),
+        // a part in every alternative
+        Pattern::Or(alternatives) => {
+            let mut alternatives = alternatives.iter().map(parts);
+            let first = alternatives.next().unwrap_or_default();
+            alternatives.fold(first, |parts, alternative| parts.into_iter().filter(|part| alternative.contains(part)).collect())
+        }
+        _ => vec![],
+    }
+}
+
+/// whether every loop in `expr` is shown to terminate by `check_loops`
+pub fn terminates(expr: &Expression) -> bool {
+    check_loops(expr).is_ok()
+}
+
+fn stripped(expr: &Expression) -> &Expression {
+    match expr {
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => stripped(expression),
+        expr => expr,
+    }
+}
+
+/// `expr` without any of its source annotations, so comparisons can be matched up with the
+/// variables they test
+fn unlocated(expr: &Expression) -> Expression {
+    match expr {
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => unlocated(expression),
+        expr => expr.clone().map_children(|child| unlocated(&child)),
+    }
+}
@@ -0,0 +1,67 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::prelude::*;
+
+use super::{EvalError, Expression, Interpreter, Value};
+
+/// a named test, which passes when its body evaluates to `true`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestCase {
+    pub name: String,
+    pub body: Expression,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// the body evaluated to something other than `true`
+    Failed(Value),
+    /// the body failed to evaluate
+    Errored(EvalError),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TestReport {
+    pub results: Vec<(String, Outcome)>,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|(_, outcome)| *outcome == Outcome::Passed)
+    }
+}
+
+/// run each test in a fresh interpreter, so no state leaks between them. `fuel` bounds the
+/// steps each test may take, so a diverging test fails rather than hanging the run.
+pub fn run_tests(tests: Vec<TestCase>, fuel: Option<usize>) -> TestReport {
+    run_tests_with(tests, |body| (Interpreter { fuel, ..Interpreter::default() }).run(body))
+}
+
+/// run each test with `run`, which is called once for each, in order
+pub fn run_tests_with(tests: Vec<TestCase>, mut run: impl FnMut(Expression) -> Result<Value, EvalError>) -> TestReport {
+    let results = tests.into_iter()
+                       .map(|TestCase { name, body }| {
+                           let outcome = match run(body) {
+                               Ok(Value::Boolean(true)) => Outcome::Passed,
+                               Ok(value) => Outcome::Failed(value),
+                               Err(error) => Outcome::Errored(error),
+                           };
+                           (name, outcome)
+                       })
+                       .collect();
+    TestReport { results }
+}
+
+impl Display for TestReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (name, outcome) in &self.results {
+            match outcome {
+                Outcome::Passed => writeln!(f, "test {} ... ok", name)?,
+                Outcome::Failed(value) => writeln!(f, "test {} ... FAILED: evaluated to {}", name, value)?,
+                Outcome::Errored(error) => writeln!(f, "test {} ... FAILED: {}", name, error)?,
+            }
+        }
+        let passed = self.results.iter().filter(|(_, outcome)| *outcome == Outcome::Passed).count();
+        write!(f, "{} passed; {} failed", passed, self.results.len() - passed)
+    }
+}
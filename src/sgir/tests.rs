@@ -53,3 +53,2417 @@ fn test_kind_checking_instantiated_polymorphic_function_with_type_constructor()
     let kind = check_kinds(&HashMap::new(), typ);
     assert_eq!(kind, Err(TypeError::KindMismatch { expected: Kind::Star, found: Kind::Arrow { from: vec![Kind::Star], to: Box::new(Kind::Star) }}));
 }
+
+#[test]
+fn test_kind_checking_intersection_of_type_constructor() {
+    let constructor = Type::ForAll { parameters: vec![TypeBinding { id: "a".to_owned(), kind: Kind::Star }],
+                                     typ: Box::new(Type::Variable("a".to_owned())) };
+    let typ = Type::Intersection(vec![Type::Number, constructor]);
+    let kind = check_kinds(&HashMap::new(), typ);
+    assert_eq!(kind, Err(TypeError::KindMismatch { expected: Kind::Star, found: Kind::Arrow { from: vec![Kind::Star], to: Box::new(Kind::Star) }}));
+}
+
+fn overloaded_tenv() -> TypeEnv {
+    let overloads = Type::Intersection(vec![Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) },
+                                            Type::Function { arguments: vec![Type::Boolean], result: Box::new(Type::Boolean) }]);
+    HashMap::from([("f".to_owned(), overloads)])
+}
+
+#[test]
+fn test_type_checking_overload_selects_matching_arrow() {
+    let expr = Expression::Application { function: Box::new(Expression::Variable("f".to_owned())),
+                                         arguments: vec![Expression::Boolean(true)] };
+    let typ = check_types(&HashMap::new(), &overloaded_tenv(), expr);
+    assert_eq!(typ, Ok(Type::Boolean));
+}
+
+#[test]
+fn test_type_checking_overload_without_matching_arrow() {
+    let expr = Expression::Application { function: Box::new(Expression::Variable("f".to_owned())),
+                                         arguments: vec![Expression::Number(1), Expression::Number(2)] };
+    let typ = check_types(&HashMap::new(), &overloaded_tenv(), expr);
+    assert_eq!(typ, Err(TypeError::NoMatchingOverload { found: overloaded_tenv()["f"].clone(),
+                                                        arguments: vec![Type::Number, Type::Number] }));
+}
+
+#[test]
+fn test_type_checking_overloaded_function_as_argument() {
+    // an overloaded function can be passed wherever one of its arrows is expected
+    let expr = Expression::Application { function: Box::new(Expression::Function { parameters: vec![Binding { id: "g".to_owned(),
+                                                                                                              typ: Type::Function { arguments: vec![Type::Number],
+                                                                                                                                    result: Box::new(Type::Number) } }],
+                                                                                     body: Box::new(Expression::Variable("g".to_owned())) }),
+                                         arguments: vec![Expression::Variable("f".to_owned())] };
+    let typ = check_types(&HashMap::new(), &overloaded_tenv(), expr);
+    assert_eq!(typ, Ok(Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) }));
+}
+
+fn number_or_boolean_tenv() -> TypeEnv {
+    HashMap::from([("x".to_owned(), Type::Union(vec![Type::Number, Type::Boolean]))])
+}
+
+#[test]
+fn test_type_checking_narrowing_by_type_test() {
+    // if typeof(x) == "number" then x else 0
+    let expr = Expression::If { condition: Box::new(Expression::TypeTest { expression: Box::new(Expression::Variable("x".to_owned())),
+                                                                           tag: TypeTag::Number }),
+                                consequent: Box::new(Expression::Variable("x".to_owned())),
+                                alternative: Box::new(Expression::Number(0)) };
+    let typ = check_types(&HashMap::new(), &number_or_boolean_tenv(), expr);
+    assert_eq!(typ, Ok(Type::Number));
+}
+
+#[test]
+fn test_type_checking_narrowing_in_alternative() {
+    // if typeof(x) == "number" then false else x
+    let expr = Expression::If { condition: Box::new(Expression::TypeTest { expression: Box::new(Expression::Variable("x".to_owned())),
+                                                                           tag: TypeTag::Number }),
+                                consequent: Box::new(Expression::Boolean(false)),
+                                alternative: Box::new(Expression::Variable("x".to_owned())) };
+    let typ = check_types(&HashMap::new(), &number_or_boolean_tenv(), expr);
+    assert_eq!(typ, Ok(Type::Boolean));
+}
+
+#[test]
+fn test_type_checking_narrowing_by_match() {
+    use patterns::{Arm, Pattern};
+
+    // match v case Some(_) then unwrap(v) case None(_) then 0 end, where unwrap takes only a `Some`
+    let some = Type::Variant(vec![("Some".to_owned(), Type::Number)]);
+    let none = Type::Variant(vec![("None".to_owned(), Type::Tuple(vec![]))]);
+    let option = Type::Variant(vec![("Some".to_owned(), Type::Number), ("None".to_owned(), Type::Tuple(vec![]))]);
+    let unwrap = Expression::Function { parameters: vec![Binding { id: "o".to_owned(), typ: some.clone() }], body: Box::new(Expression::Number(1)) };
+    let arm = |tag: &str, body| Arm { pattern: Pattern::Variant { tag: tag.to_owned(), payload: Box::new(Pattern::Wildcard) }, guard: None, body };
+    let at = Span { start: Position { line: 1, column: 7 }, end: Position { line: 1, column: 8 } };
+    let variable = Expression::Located { span: at, expression: Box::new(Expression::Variable("v".to_owned())) };
+    let expr = Expression::Match {
+        scrutinee: Box::new(variable.clone()),
+        arms: vec![arm("Some", Expression::Application { function: Box::new(unwrap.clone()), arguments: vec![variable.clone()] }),
+                   arm("None", Expression::Number(0))],
+    };
+    let tenv = HashMap::from([("v".to_owned(), option)]);
+    assert_eq!(check_types(&HashMap::new(), &tenv, expr), Ok(Type::Number));
+
+    // and in the other arm, it's only the other case
+    let expr = Expression::Match {
+        scrutinee: Box::new(variable.clone()),
+        arms: vec![arm("None", Expression::Application { function: Box::new(unwrap), arguments: vec![variable] }),
+                   arm("Some", Expression::Number(0))],
+    };
+    assert_eq!(check_types(&HashMap::new(), &tenv, expr), Err(TypeError::TypeMismatch { expected: some, found: none }));
+}
+
+#[test]
+fn test_type_checking_union_without_narrowing() {
+    // passing an unrefined `Number | Boolean` where a `Number` is expected is rejected
+    let expr = Expression::Application { function: Box::new(Expression::Function { parameters: vec![Binding { id: "n".to_owned(), typ: Type::Number }],
+                                                                                     body: Box::new(Expression::Variable("n".to_owned())) }),
+                                         arguments: vec![Expression::Variable("x".to_owned())] };
+    let typ = check_types(&HashMap::new(), &number_or_boolean_tenv(), expr);
+    assert_eq!(typ, Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::Union(vec![Type::Number, Type::Boolean]) }));
+}
+
+#[test]
+fn test_eval_type_test() {
+    let expr = Expression::If { condition: Box::new(Expression::TypeTest { expression: Box::new(Expression::Boolean(true)),
+                                                                           tag: TypeTag::Number }),
+                                consequent: Box::new(Expression::Number(1)),
+                                alternative: Box::new(Expression::Number(2)) };
+    assert_eq!(run(expr), Ok(Value::Number(2)));
+}
+
+fn kind_polymorphic_application() -> Type {
+    // forall<f: k -> *, a: k>. f<a>
+    Type::ForAll { parameters: vec![TypeBinding { id: "f".to_owned(),
+                                                  kind: Kind::Arrow { from: vec![Kind::Variable("k".to_owned())], to: Box::new(Kind::Star) } },
+                                    TypeBinding { id: "a".to_owned(), kind: Kind::Variable("k".to_owned()) }],
+                   typ: Box::new(Type::Instantiate { typ: Box::new(Type::Variable("f".to_owned())),
+                                                     arguments: vec![Type::Variable("a".to_owned())] }) }
+}
+
+fn identity_constructor(kind: Kind) -> Type {
+    // forall<b: kind>. Number
+    Type::ForAll { parameters: vec![TypeBinding { id: "b".to_owned(), kind }],
+                   typ: Box::new(Type::Number) }
+}
+
+#[test]
+fn test_kind_checking_kind_polymorphic_type() {
+    let kind = check_kinds(&HashMap::new(), kind_polymorphic_application());
+    assert_eq!(kind, Ok(Kind::Arrow { from: vec![Kind::Arrow { from: vec![Kind::Variable("k".to_owned())], to: Box::new(Kind::Star) },
+                                                 Kind::Variable("k".to_owned())],
+                                      to: Box::new(Kind::Star) }));
+}
+
+#[test]
+fn test_kind_checking_kind_polymorphic_instantiations() {
+    // the same kind-polymorphic type can be instantiated at `*` and at `* -> *`
+    let star_to_star = Kind::Arrow { from: vec![Kind::Star], to: Box::new(Kind::Star) };
+    for (argument_kind, argument) in [(Kind::Star, Type::Number),
+                                      (star_to_star.clone(), identity_constructor(Kind::Star))] {
+        let typ = Type::Instantiate { typ: Box::new(kind_polymorphic_application()),
+                                      arguments: vec![identity_constructor(argument_kind), argument] };
+        let kind = check_kinds(&HashMap::new(), typ);
+        assert_eq!(kind, Ok(Kind::Star));
+    }
+}
+
+#[test]
+fn test_kind_checking_kind_polymorphic_instantiation_mismatch() {
+    // f: * -> * forces k = *, so a type constructor can't be passed for a
+    let typ = Type::Instantiate { typ: Box::new(kind_polymorphic_application()),
+                                  arguments: vec![identity_constructor(Kind::Star), identity_constructor(Kind::Star)] };
+    let kind = check_kinds(&HashMap::new(), typ);
+    assert_eq!(kind, Err(TypeError::KindMismatch { expected: Kind::Star, found: Kind::Arrow { from: vec![Kind::Star], to: Box::new(Kind::Star) } }));
+}
+
+#[test]
+fn test_kind_checking_infers_kind_of_applied_variable() {
+    // forall<f: k>. f<Number>, where k must be inferred as * -> %k
+    let typ = Type::ForAll { parameters: vec![TypeBinding { id: "f".to_owned(), kind: Kind::Variable("k".to_owned()) }],
+                             typ: Box::new(Type::Instantiate { typ: Box::new(Type::Variable("f".to_owned())),
+                                                               arguments: vec![Type::Number] }) };
+    match check_kinds(&HashMap::new(), typ) {
+        Ok(Kind::Arrow { from, to }) => assert_eq!(from, vec![Kind::Arrow { from: vec![Kind::Star], to: to.clone() }]),
+        kind => panic!("unexpected kind {:?}", kind),
+    }
+}
+
+#[test]
+fn test_kind_checking_instantiation_arity() {
+    let pair = Type::ForAll { parameters: vec![TypeBinding { id: "a".to_owned(), kind: Kind::Star },
+                                               TypeBinding { id: "b".to_owned(), kind: Kind::Star }],
+                              typ: Box::new(Type::Tuple(vec![Type::Variable("a".to_owned()), Type::Variable("b".to_owned())])) };
+    for arguments in [vec![Type::Number], vec![Type::Number, Type::String, Type::Boolean]] {
+        let found = arguments.len();
+        let typ = Type::Instantiate { typ: Box::new(pair.clone()), arguments };
+        assert_eq!(check_kinds(&HashMap::new(), typ), Err(TypeError::ArityMismatch { expected: 2, found }));
+    }
+}
+
+#[test]
+fn test_kind_checking_type_parameter_used_at_one_kind() {
+    // Λ(f: k). λ(x: f, y: f<Number>). x uses f at * and at * -> *
+    let f = || Type::Variable("f".to_owned());
+    let function = |parameters: Vec<Type>| Expression::TypeFunction {
+        parameters: vec![TypeBinding { id: "f".to_owned(), kind: Kind::Variable("k".to_owned()) }],
+        body: Box::new(Expression::Function {
+            parameters: parameters.into_iter().enumerate().map(|(i, typ)| Binding { id: format!("x{}", i), typ }).collect(),
+            body: Box::new(Expression::Tuple(vec![])),
+        }),
+    };
+    assert!(check(function(vec![f(), f()])).is_ok());
+    assert!(check(function(vec![Type::Instantiate { typ: Box::new(f()), arguments: vec![Type::Number] }])).is_ok());
+    assert_eq!(check(function(vec![f(), Type::Instantiate { typ: Box::new(f()), arguments: vec![Type::Number] }])),
+               Err(TypeError::ExpectedQuantifier { found: f() }));
+}
+
+#[test]
+fn test_errors_display_types_and_values() {
+    let function = Type::Function { arguments: vec![Type::String], result: Box::new(Type::Boolean) };
+    assert_eq!(TypeError::TypeMismatch { expected: Type::Number, found: function.clone() }.to_string(),
+               "type mismatch: expected Number, found (String) -> Boolean");
+    assert_eq!(TypeError::NoMatchingOverload { found: function, arguments: vec![Type::Number] }.to_string(),
+               "no overload of (String) -> Boolean accepts the arguments (Number,)");
+    assert_eq!(TypeError::KindMismatch { expected: Kind::Star, found: Kind::Arrow { from: vec![Kind::Star], to: Box::new(Kind::Star) } }.to_string(),
+               "kind mismatch: expected *, found * -> *");
+    assert_eq!(EvalError::ExpectedNumber { found: Value::String("two".to_owned()) }.to_string(), "expected a number, found \"two\"");
+}
+
+#[test]
+fn test_type_checking_application_infers_type_arguments() {
+    // apply : forall<t, u>. ((t) -> u, t) -> u, applied to a (Number) -> Boolean and a Number
+    let variable = |id: &str| Type::Variable(id.to_owned());
+    let function = |arguments, result| Type::Function { arguments, result: Box::new(result) };
+    let apply = Type::ForAll {
+        parameters: ["t", "u"].map(|id| TypeBinding { id: id.to_owned(), kind: Kind::Star }).to_vec(),
+        typ: Box::new(function(vec![function(vec![variable("t")], variable("u")), variable("t")], variable("u"))),
+    };
+    let tenv = HashMap::from([("apply".to_owned(), apply), ("even".to_owned(), function(vec![Type::Number], Type::Boolean))]);
+    let call = |arguments| Expression::Application { function: Box::new(Expression::Variable("apply".to_owned())), arguments };
+    let even = || Expression::Variable("even".to_owned());
+    assert_eq!(check_types(&HashMap::new(), &tenv, call(vec![even(), Expression::Number(2)])), Ok(Type::Boolean));
+    assert_eq!(check_types(&HashMap::new(), &tenv, call(vec![even(), Expression::Boolean(true)])),
+               Err(TypeError::TypeMismatch { expected: function(vec![Type::Union(vec![Type::Number, Type::Boolean])], Type::Boolean),
+                                             found: function(vec![Type::Number], Type::Boolean) }));
+}
+
+#[test]
+fn test_type_checking_call_into_declared_host_function() {
+    let declarations = Declarations::from([("print".to_owned(), Type::Function { arguments: vec![Type::Number],
+                                                                                 result: Box::new(Type::Boolean) })]);
+    let call = |argument| Expression::Application { function: Box::new(Expression::Variable("print".to_owned())),
+                                                    arguments: vec![argument] };
+    assert_eq!(check_with_declarations(&declarations, call(Expression::Number(5))), Ok(Type::Boolean));
+    assert_eq!(check_with_declarations(&declarations, call(Expression::Boolean(true))),
+               Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::Boolean }));
+}
+
+#[test]
+fn test_type_checking_ill_formed_declaration() {
+    let declarations = Declarations::from([("box".to_owned(), Type::Variable("T".to_owned()))]);
+    let typ = check_with_declarations(&declarations, Expression::Number(5));
+    assert_eq!(typ, Err(TypeError::UnboundIdentifier("T".to_owned())));
+}
+
+fn per_target_number() -> Expression {
+    Expression::Function { parameters: vec![],
+                           body: Box::new(Expression::IfTarget { target: "wasm".to_owned(),
+                                                                 consequent: Box::new(Expression::Number(32)),
+                                                                 alternative: Box::new(Expression::Number(64)) }) }
+}
+
+#[test]
+fn test_resolve_targets_selects_matching_branch() {
+    let expr = Expression::Application { function: Box::new(target::resolve_targets(per_target_number(), "wasm")),
+                                         arguments: vec![] };
+    assert_eq!(run(expr), Ok(Value::Number(32)));
+}
+
+#[test]
+fn test_resolve_targets_selects_alternative() {
+    let expr = Expression::Application { function: Box::new(target::resolve_targets(per_target_number(), "native")),
+                                         arguments: vec![] };
+    assert_eq!(run(expr), Ok(Value::Number(64)));
+}
+
+#[test]
+fn test_type_checking_unresolved_target_conditional() {
+    let expr = Expression::IfTarget { target: "wasm".to_owned(),
+                                      consequent: Box::new(Expression::Number(32)),
+                                      alternative: Box::new(Expression::Boolean(false)) };
+    assert_eq!(check(expr), Ok(Type::Union(vec![Type::Number, Type::Boolean])));
+}
+
+fn variable(id: &str) -> Expression {
+    Expression::Variable(id.to_owned())
+}
+
+fn constant_function() -> macros::Macro {
+    // const!(e) = fn(x: Number) -> e
+    macros::Macro { parameters: vec!["e".to_owned()],
+                    template: Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                                     body: Box::new(variable("e")) } }
+}
+
+#[test]
+fn test_macro_expansion_is_hygienic() {
+    // const!(x) must not refer to the template's own `x`
+    let macros = macros::MacroEnv::from([("const".to_owned(), constant_function())]);
+    let expr = Expression::Application { function: Box::new(variable("const")), arguments: vec![variable("x")] };
+    let expansion = macros::expand_macros(expr, &macros, &mut macros::FreshNames::default()).unwrap();
+    assert_eq!(expansion.free_variables(), HashSet::from(["x".to_owned()]));
+}
+
+#[test]
+fn test_macro_expansion_result_runs() {
+    let macros = macros::MacroEnv::from([("const".to_owned(), constant_function())]);
+    let expr = Expression::Application { function: Box::new(Expression::Application { function: Box::new(variable("const")),
+                                                                                      arguments: vec![Expression::Boolean(true)] }),
+                                         arguments: vec![Expression::Number(5)] };
+    let expansion = macros::expand_macros(expr, &macros, &mut macros::FreshNames::default()).unwrap();
+    assert_eq!(run(expansion), Ok(Value::Boolean(true)));
+}
+
+#[test]
+fn test_macro_shadowed_by_parameter() {
+    let macros = macros::MacroEnv::from([("const".to_owned(), constant_function())]);
+    let expr = Expression::Function { parameters: vec![Binding { id: "const".to_owned(), typ: Type::Number }],
+                                      body: Box::new(Expression::Application { function: Box::new(variable("const")),
+                                                                               arguments: vec![] }) };
+    let expansion = macros::expand_macros(expr, &macros, &mut macros::FreshNames::default());
+    assert!(matches!(expansion, Ok(Expression::Function { .. })));
+}
+
+#[test]
+fn test_macro_arity_mismatch() {
+    let macros = macros::MacroEnv::from([("const".to_owned(), constant_function())]);
+    let expr = Expression::Application { function: Box::new(variable("const")), arguments: vec![] };
+    let expansion = macros::expand_macros(expr, &macros, &mut macros::FreshNames::default());
+    assert_eq!(expansion.unwrap_err(), macros::ExpansionError::ArityMismatch { name: "const".to_owned(), expected: 1, found: 0 });
+}
+
+#[test]
+fn test_macro_free_variables_resolve_where_defined() {
+    // there!() = y, which refers to the `y` in scope where the macro is defined
+    let there = macros::Macro { parameters: vec![], template: variable("y") };
+    let macros = macros::MacroEnv::from([("there".to_owned(), there)]);
+    let expr = Expression::Function { parameters: vec![Binding { id: "y".to_owned(), typ: Type::Number }],
+                                      body: Box::new(Expression::Application { function: Box::new(variable("there")),
+                                                                               arguments: vec![] }) };
+    let expansion = macros::expand_macros(expr, &macros, &mut macros::FreshNames::default()).unwrap();
+    assert_eq!(expansion.free_variables(), HashSet::from(["y".to_owned()]));
+    assert!(matches!(&expansion, Expression::Function { parameters, .. } if parameters[0].id == "y%1"));
+}
+
+#[test]
+fn test_macro_expansion_depth_is_limited() {
+    // forever!(e) = forever!(e)
+    let forever = macros::Macro { parameters: vec!["e".to_owned()],
+                                  template: Expression::Application { function: Box::new(variable("forever")),
+                                                                      arguments: vec![variable("e")] } };
+    let macros = macros::MacroEnv::from([("forever".to_owned(), forever)]);
+    let expr = Expression::Application { function: Box::new(variable("forever")), arguments: vec![Expression::Number(1)] };
+    let expansion = macros::expand_macros(expr, &macros, &mut macros::FreshNames::default());
+    assert_eq!(expansion.unwrap_err(), macros::ExpansionError::TooDeep { name: "forever".to_owned(),
+                                                                         limit: macros::MAX_EXPANSION_DEPTH });
+}
+
+fn omega() -> Expression {
+    // (fn(x) -> x(x))(fn(x) -> x(x)), which never terminates
+    let self_application = Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                                  body: Box::new(Expression::Application { function: Box::new(variable("x")),
+                                                                                           arguments: vec![variable("x")] }) };
+    Expression::Application { function: Box::new(self_application.clone()), arguments: vec![self_application] }
+}
+
+#[test]
+fn test_eval_out_of_fuel() {
+    assert_eq!(Interpreter::with_fuel(100).run(omega()), Err(EvalError::OutOfFuel));
+}
+
+/// fn(fib, n) -> if n < 2 then n else fib(fib, n - 1) + fib(fib, n - 2), which recurs by being
+/// passed itself
+fn naive_fib() -> Expression {
+    use primitives::Primitive::*;
+    let recur = |offset| apply(variable("fib"), vec![variable("fib"), primitive(Subtract, vec![variable("n"), Expression::Number(offset)])]);
+    Expression::Function {
+        parameters: vec![Binding { id: "fib".to_owned(), typ: Type::Number }, number_binding("n")],
+        body: Box::new(Expression::If { condition: Box::new(primitive(Less, vec![variable("n"), Expression::Number(2)])),
+                                        consequent: Box::new(variable("n")),
+                                        alternative: Box::new(primitive(Add, vec![recur(1), recur(2)])) }),
+    }
+}
+
+#[test]
+fn test_memoize_pure_calls() {
+    let fib = |n| apply(naive_fib(), vec![naive_fib(), Expression::Number(n)]);
+    let mut naive = Interpreter::default();
+    assert_eq!(naive.run(fib(20)), Ok(Value::Number(6765)));
+
+    let mut memoizing = Interpreter { memo: Some(memo::Memo::default()), ..Interpreter::default() };
+    assert_eq!(memoizing.run(fib(20)), Ok(Value::Number(6765)));
+    assert!(memoizing.steps * 50 < naive.steps, "{} steps memoized, {} naively", memoizing.steps, naive.steps);
+    let memo = memoizing.memo.as_ref().unwrap();
+    assert_eq!((memo.len(), memo.hits), (21, 18));
+    assert_eq!(memoizing.run(fib(40)), Ok(Value::Number(102334155)));
+}
+
+#[test]
+fn test_memoize_skips_natives() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    // fn(tick) -> tick(), called twice with the same native, has to call it twice
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let counter = ticks.clone();
+    let tick = Native::new("tick", move |_| Ok(Value::Number(counter.fetch_add(1, Ordering::SeqCst) as i64)));
+    let env = Environment::global(HashMap::from([("tick".to_owned(), Value::Native(tick))]));
+    let call = || apply(Expression::Function { parameters: vec![Binding { id: "f".to_owned(), typ: Type::Number }],
+                                               body: Box::new(apply(variable("f"), vec![])) },
+                        vec![variable("tick")]);
+
+    let mut interpreter = Interpreter { memo: Some(memo::Memo::default()), ..Interpreter::default() };
+    assert_eq!(interpreter.run_in(&env, Expression::Tuple(vec![call(), call()])),
+               Ok(Value::Tuple(vec![Value::Number(0), Value::Number(1)])));
+    assert!(interpreter.memo.unwrap().is_empty());
+}
+
+#[test]
+fn test_partial_evaluation_unfolds_known_calls() {
+    let fib = |n| apply(naive_fib(), vec![naive_fib(), Expression::Number(n)]);
+    assert_eq!(partial::partial_evaluate(fib(10), HashMap::new(), 1000), Expression::Number(55));
+    // out of budget, what's left still evaluates the same
+    let residual = partial::partial_evaluate(fib(10), HashMap::new(), 20);
+    assert!(matches!(residual, Expression::Primitive { .. }));
+    assert_eq!(run(residual), Ok(Value::Number(55)));
+    // unfolding never ends for omega, so the budget is what stops it
+    assert!(matches!(partial::partial_evaluate(omega(), HashMap::new(), 100), Expression::Application { .. }));
+}
+
+#[test]
+fn test_partial_evaluation_specializes_on_inputs() {
+    use primitives::Primitive::*;
+    // fn(x: Number) -> if flag then x * scale else x, with flag and scale known
+    let scale = Expression::Function {
+        parameters: vec![number_binding("x")],
+        body: Box::new(Expression::If { condition: Box::new(variable("flag")),
+                                        consequent: Box::new(primitive(Multiply, vec![variable("x"), variable("scale")])),
+                                        alternative: Box::new(variable("x")) }),
+    };
+    let inputs = HashMap::from([("flag".to_owned(), Value::Boolean(true)), ("scale".to_owned(), Value::Number(3))]);
+    assert_eq!(partial::partial_evaluate(scale, inputs, 10),
+               Expression::Function { parameters: vec![number_binding("x")],
+                                      body: Box::new(primitive(Multiply, vec![variable("x"), Expression::Number(3)])) });
+
+    // (fn(a: Number, b: Number) -> a + b)(y, 2) only substitutes the argument it knows
+    let add = Expression::Function { parameters: vec![number_binding("a"), number_binding("b")],
+                                     body: Box::new(primitive(Add, vec![variable("a"), variable("b")])) };
+    assert_eq!(partial::partial_evaluate(apply(add, vec![variable("y"), Expression::Number(2)]), HashMap::new(), 10),
+               apply(Expression::Function { parameters: vec![number_binding("a")],
+                                            body: Box::new(primitive(Add, vec![variable("a"), Expression::Number(2)])) },
+                     vec![variable("y")]));
+}
+
+#[test]
+fn test_anf_binds_operations_in_order() {
+    use primitives::Primitive::*;
+    // f(a + 1, g(b)) binds the sum before the call to g
+    let expr = apply(variable("f"), vec![primitive(Add, vec![variable("a"), Expression::Number(1)]),
+                                         apply(variable("g"), vec![variable("b")])]);
+    let temporary = |i: usize| format!("%anf{}", i);
+    assert_eq!(anf::normalize(expr),
+               blocks::let_in(patterns::Pattern::Variable(temporary(0)), primitive(Add, vec![variable("a"), Expression::Number(1)]),
+                              blocks::let_in(patterns::Pattern::Variable(temporary(1)), apply(variable("g"), vec![variable("b")]),
+                                             apply(variable("f"), vec![variable(&temporary(0)), variable(&temporary(1))]))));
+}
+
+#[test]
+fn test_cse_shares_pure_operations() {
+    use primitives::Primitive::*;
+    // fn(a: Number, b: Number) -> (a * b + 1) * (a * b + 1)
+    let square = || primitive(Add, vec![primitive(Multiply, vec![variable("a"), variable("b")]), Expression::Number(1)]);
+    let function = Expression::Function { parameters: vec![number_binding("a"), number_binding("b")],
+                                          body: Box::new(primitive(Multiply, vec![square(), square()])) };
+    let normalized = anf::normalize(function.clone());
+    let eliminated = cse::eliminate_common_subexpressions(function);
+    assert_eq!(eliminated.size(), normalized.size() - 4);
+    assert_eq!(check(eliminated.clone()), check(normalized));
+    assert_eq!(run(apply(eliminated, vec![Expression::Number(2), Expression::Number(3)])), Ok(Value::Number(49)));
+}
+
+#[test]
+fn test_cse_keeps_calls_and_rebound_variables() {
+    use primitives::Primitive::*;
+    // (f(1), f(1)) calls f twice, since f might have effects
+    let calls = Expression::Tuple(vec![apply(variable("f"), vec![Expression::Number(1)]),
+                                       apply(variable("f"), vec![Expression::Number(1)])]);
+    assert_eq!(cse::eliminate_common_subexpressions(calls.clone()), anf::normalize(calls));
+
+    // (a + 1, fn(a: Number) -> a + 1): the a inside the function is another variable
+    let shadowed = Expression::Tuple(vec![primitive(Add, vec![variable("a"), Expression::Number(1)]),
+                                          Expression::Function { parameters: vec![number_binding("a")],
+                                                                 body: Box::new(primitive(Add, vec![variable("a"), Expression::Number(1)])) }]);
+    assert_eq!(cse::eliminate_common_subexpressions(shadowed.clone()), anf::normalize(shadowed));
+}
+
+#[test]
+fn test_lift_closed_lambdas() {
+    use primitives::Primitive::*;
+    // (fn(x: Number) -> (fn(y: Number) -> y)(x))(1) lifts both functions, the inner one first
+    let expr = apply(Expression::Function { parameters: vec![number_binding("x")],
+                                            body: Box::new(apply(identity("y"), vec![variable("x")])) },
+                     vec![Expression::Number(1)]);
+    let lifted = program::lift_lambdas(expr);
+    assert_eq!(lifted.declarations.iter().map(|declaration| declaration.name.as_str()).collect::<Vec<_>>(), ["%lambda0", "%lambda1"]);
+    assert_eq!(lifted.declarations[1].body, apply(variable("%lambda0"), vec![variable("x")]));
+    assert_eq!(lifted.main, apply(variable("%lambda1"), vec![Expression::Number(1)]));
+    assert_eq!(check(lifted.clone().into_expression()), Ok(Type::Number));
+    assert_eq!(lifted.run(), Ok(Value::Number(1)));
+
+    // fn(x: Number) -> fn(y: Number) -> x + y: the inner function captures x, so it stays
+    let add_x = Expression::Function { parameters: vec![number_binding("y")],
+                                       body: Box::new(primitive(Add, vec![variable("x"), variable("y")])) };
+    let adder = Expression::Function { parameters: vec![number_binding("x")], body: Box::new(add_x.clone()) };
+    let lifted = program::lift_lambdas(apply(apply(adder, vec![Expression::Number(2)]), vec![Expression::Number(3)]));
+    assert_eq!(lifted.declarations.len(), 1);
+    assert_eq!(lifted.declarations[0].body, add_x);
+    assert_eq!(lifted.run(), Ok(Value::Number(5)));
+}
+
+#[test]
+fn test_call_graph_reachability() {
+    let declaration = |name: &str, body| program::Declaration { name: name.to_owned(), parameters: vec![number_binding("x")], body };
+    // g calls f, h is never called, and main calls g
+    let lifted = program::Program {
+        declarations: vec![declaration("f", variable("x")),
+                           declaration("g", apply(variable("f"), vec![variable("x")])),
+                           declaration("h", apply(variable("f"), vec![variable("x")]))],
+        main: apply(variable("g"), vec![Expression::Number(1)]),
+    };
+    let graph = lifted.call_graph();
+    assert_eq!(graph.reachable(&[]), ["f", "g"].into_iter().map(str::to_owned).collect());
+    assert_eq!(graph.reachable(&["h".to_owned()]).len(), 3);
+    assert_eq!(graph.to_string(),
+               "digraph calls {\n    main;\n    main -> \"g\";\n    \"f\";\n    \"g\";\n    \"g\" -> \"f\";\n    \"h\" [style=dashed];\n    \"h\" -> \"f\";\n}");
+
+    let pruned = lifted.clone().without_unreachable(&[]);
+    assert_eq!(pruned.declarations.iter().map(|declaration| declaration.name.as_str()).collect::<Vec<_>>(), ["f", "g"]);
+    assert_eq!(pruned.run(), lifted.run());
+}
+
+#[test]
+fn test_escape_analysis() {
+    use patterns::Pattern;
+    use primitives::Primitive::*;
+    let local = |name: &str, value, body| blocks::let_in(Pattern::Variable(name.to_owned()), value, body);
+    let pair = || Expression::Tuple(vec![variable("a"), variable("b")]);
+    let kept = |expr: &Expression| escape::analyze(expr).allocations.iter().map(|allocation| allocation.escapes).collect::<Vec<_>>();
+
+    // let p = (a, b) in match p { case (x, y) -> x + y } only takes p apart
+    let taken_apart = local("p", pair(), blocks::let_in(Pattern::Tuple(vec![Pattern::Variable("x".to_owned()), Pattern::Variable("y".to_owned())]),
+                                                        variable("p"),
+                                                        primitive(Add, vec![variable("x"), variable("y")])));
+    assert_eq!(kept(&taken_apart), [false]);
+    // let f = fn(x: Number) -> x in f(1) only calls f
+    assert_eq!(kept(&local("f", identity("x"), apply(variable("f"), vec![Expression::Number(1)]))), [false]);
+
+    // a value escapes as the value of its scope, as an argument, inside another value, captured
+    // by a closure, or by another name, though not by a parameter that shadows it
+    assert_eq!(kept(&local("p", pair(), variable("p"))), [true]);
+    assert_eq!(kept(&local("p", pair(), apply(variable("g"), vec![variable("p")]))), [true]);
+    assert_eq!(kept(&local("p", pair(), Expression::Tuple(vec![variable("p")]))), [true]);
+    assert_eq!(kept(&local("p", pair(), Expression::Function { parameters: vec![number_binding("x")], body: Box::new(variable("p")) })),
+               [true]);
+    assert_eq!(kept(&local("p", pair(), identity("p"))), [false]);
+    assert_eq!(kept(&local("p", pair(), local("q", variable("p"), Expression::Number(1)))), [true]);
+
+    let report = escape::analyze(&local("f", identity("x"), local("p", pair(), variable("p"))));
+    assert_eq!(report.to_string(), "closure f: frame\ntuple p: escapes\n1 of 2 allocations can live in their frame");
+}
+
+#[test]
+fn test_parameter_usage() {
+    use patterns::Pattern;
+    use primitives::Primitive::*;
+    use usage::Usage::*;
+    let parameters = vec![number_binding("a"), number_binding("b"), number_binding("c"), number_binding("d")];
+    // fn(a, b, c, d) -> if 0 < a then b else (fn(x) -> d)(b)
+    let body = Expression::If {
+        condition: Box::new(primitive(Less, vec![Expression::Number(0), variable("a")])),
+        consequent: Box::new(variable("b")),
+        alternative: Box::new(apply(Expression::Function { parameters: vec![number_binding("x")], body: Box::new(variable("d")) },
+                                    vec![variable("b")])),
+    };
+    assert_eq!(usage::parameter_usage(&parameters, &body), [Always, Always, Never, Conditionally]);
+
+    // a use after an early return might never be reached
+    let returning = Expression::Returning {
+        result: Some(Type::Number),
+        body: Box::new(blocks::let_in(Pattern::Wildcard,
+                                      Expression::If { condition: Box::new(variable("a")),
+                                                       consequent: Box::new(Expression::Return(Box::new(Expression::Number(0)))),
+                                                       alternative: Box::new(Expression::Tuple(vec![])) },
+                                      variable("b"))),
+    };
+    assert_eq!(usage::parameter_usage(&parameters[..2], &returning), [Always, Conditionally]);
+
+    // let f = fn(a, b, c, d) -> ... in f(1, 2, 3, 4) + f(5, 6, 7, 8) drops c from f and its calls
+    let call = |arguments: [i64; 4]| apply(variable("f"), arguments.into_iter().map(Expression::Number).collect());
+    let program = blocks::let_in(Pattern::Variable("f".to_owned()),
+                                 Expression::Function { parameters: parameters.clone(), body: Box::new(body.clone()) },
+                                 primitive(Add, vec![call([1, 2, 3, 4]), call([-5, 6, 7, 8])]));
+    let eliminated = usage::eliminate_dead_parameters(program.clone());
+    let expected = blocks::let_in(Pattern::Variable("f".to_owned()),
+                                  Expression::Function { parameters: vec![number_binding("a"), number_binding("b"), number_binding("d")],
+                                                         body: Box::new(body) },
+                                  primitive(Add, vec![apply(variable("f"), vec![Expression::Number(1), Expression::Number(2), Expression::Number(4)]),
+                                                      apply(variable("f"), vec![Expression::Number(-5), Expression::Number(6), Expression::Number(8)])]));
+    assert_eq!(eliminated, expected);
+    assert_eq!(run(eliminated), run(program.clone()));
+
+    // f escapes when it's used as anything but a call, so it keeps its parameters
+    let escaping = blocks::let_in(Pattern::Variable("f".to_owned()),
+                                  Expression::Function { parameters: parameters.clone(), body: Box::new(variable("a")) },
+                                  Expression::Tuple(vec![variable("f")]));
+    assert_eq!(usage::eliminate_dead_parameters(escaping.clone()), escaping);
+}
+
+#[test]
+fn test_evaluate_constants_folds_constant() {
+    // fn(y: Boolean) -> const { (fn(x: Number) -> x)(7) }
+    let folded = Expression::Application { function: Box::new(Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                                                                       body: Box::new(variable("x")) }),
+                                           arguments: vec![Expression::Number(7)] };
+    let expr = Expression::Function { parameters: vec![Binding { id: "y".to_owned(), typ: Type::Boolean }],
+                                      body: Box::new(Expression::Constant(Box::new(folded))) };
+    assert_eq!(constants::evaluate_constants(expr, 100),
+               Ok(Expression::Function { parameters: vec![Binding { id: "y".to_owned(), typ: Type::Boolean }],
+                                         body: Box::new(Expression::Number(7)) }));
+}
+
+#[test]
+fn test_evaluate_constants_rejects_runtime_variables() {
+    let expr = Expression::Function { parameters: vec![Binding { id: "y".to_owned(), typ: Type::Boolean }],
+                                      body: Box::new(Expression::Constant(Box::new(variable("y")))) };
+    assert_eq!(constants::evaluate_constants(expr, 100), Err(constants::ConstantError::NotConstant("y".to_owned())));
+}
+
+#[test]
+fn test_evaluate_constants_out_of_fuel() {
+    // a loop that never ends, which isn't shown to terminate
+    let forever = Expression::Loop { variables: vec![(Binding { id: "n".to_owned(), typ: Type::Number }, Expression::Number(0))],
+                                     body: Box::new(Expression::Continue(vec![variable("n")])) };
+    let expr = Expression::Constant(Box::new(forever));
+    assert_eq!(constants::evaluate_constants(expr.clone(), 100), Err(constants::ConstantError::Eval(EvalError::OutOfFuel)));
+    assert_eq!(check(expr), Err(TypeError::Constant(Box::new(constants::ConstantError::Eval(EvalError::OutOfFuel)))));
+}
+
+#[test]
+fn test_evaluate_constants_checks_before_evaluating() {
+    // omega isn't well typed, so it's rejected rather than run until it's out of fuel
+    let expr = Expression::Constant(Box::new(omega()));
+    let error = TypeError::ExpectedFunction { found: Type::Number };
+    assert_eq!(constants::evaluate_constants(expr.clone(), 100), Err(constants::ConstantError::Type(error.clone())));
+    assert_eq!(check(expr), Err(error));
+
+    let division = Expression::Constant(Box::new(primitive(Primitive::Divide, vec![Expression::Number(1), Expression::Number(0)])));
+    assert!(matches!(check(division), Err(TypeError::Constant(error)) if matches!(*error, constants::ConstantError::Eval(_))));
+}
+
+#[test]
+fn test_diff_shows_what_a_pass_changed() {
+    // (1, const { 2 }, {x = 3}) against (1, 2, {x = 4, y = 5})
+    let before = Expression::Tuple(vec![Expression::Number(1),
+                                        Expression::Constant(Box::new(Expression::Number(2))),
+                                        Expression::Record(vec![("x".to_owned(), Expression::Number(3))])]);
+    let after = constants::evaluate_constants(before.clone(), 100).unwrap();
+    let folded = diff::diff(&before, &after);
+    assert_eq!(folded.changes, vec![diff::Change::Changed { path: "$.elements[1]".to_owned(),
+                                                            before: Expression::Constant(Box::new(Expression::Number(2))),
+                                                            after: Expression::Number(2) }]);
+    assert_eq!(folded.to_string(), "~ $.elements[1]: const(2) => 2");
+
+    let edited = Expression::Tuple(vec![Expression::Number(1),
+                                        Expression::Number(2),
+                                        Expression::Record(vec![("x".to_owned(), Expression::Number(4)),
+                                                                ("y".to_owned(), Expression::Number(5))])]);
+    assert_eq!(diff::diff(&after, &edited).paths(), vec!["$.elements[2].x", "$.elements[2].y"]);
+    assert_eq!(diff::diff(&edited, &after).changes[1],
+               diff::Change::Removed { path: "$.elements[2].y".to_owned(), expression: Expression::Number(5) });
+    assert!(diff::diff(&after, &after).is_empty());
+
+    // a change is written as SGIR, without where it came from, and a function takes lines of its own
+    let at = Span { start: Position { line: 1, column: 1 }, end: Position { line: 1, column: 2 } };
+    let located = |expression| Expression::Located { span: at, expression: Box::new(expression) };
+    let function = Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                          body: Box::new(located(Expression::Variable("x".to_owned()))) };
+    let replaced = diff::diff(&Expression::Tuple(vec![located(Expression::Number(1))]), &Expression::Tuple(vec![function]));
+    assert_eq!(replaced.to_string(), "~ $.elements[0]: 1\n  =>\n    fn (x: Number) ->\n        x");
+}
+
+#[test]
+fn test_pretty_printing_types() {
+    let identity = Type::ForAll { parameters: vec![TypeBinding { id: "a".to_owned(), kind: Kind::Star },
+                                                   TypeBinding { id: "f".to_owned(),
+                                                                 kind: Kind::Arrow { from: vec![Kind::Star], to: Box::new(Kind::Star) } }],
+                                  typ: Box::new(Type::Function { arguments: vec![Type::Variable("a".to_owned())],
+                                                                 result: Box::new(Type::Variable("a".to_owned())) }) };
+    assert_eq!(identity.to_string(), "forall<a, f: * -> *>. (a) -> a");
+
+    let overloads = Type::Intersection(vec![Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) },
+                                            Type::Union(vec![Type::Number, Type::Boolean])]);
+    assert_eq!(overloads.to_string(), "((Number) -> Number) & (Number | Boolean)");
+}
+
+#[test]
+fn test_pretty_printing_kinds() {
+    let kind = Kind::Arrow { from: vec![Kind::Arrow { from: vec![Kind::Star], to: Box::new(Kind::Star) }, Kind::Variable("k".to_owned())],
+                             to: Box::new(Kind::Star) };
+    assert_eq!(kind.to_string(), "(* -> *, k) -> *");
+}
+
+#[test]
+fn test_doc_render_markdown() {
+    let items = vec![doc::DocItem { name: "print".to_owned(),
+                                    typ: Some(Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Boolean) }),
+                                    doc: " Prints a number.\n".to_owned() }];
+    assert_eq!(doc::render_markdown("host", &items),
+               "# host\n\n## `print`\n\n```\nprint: (Number) -> Boolean\n```\n\nPrints a number.\n");
+}
+
+#[test]
+fn test_run_tests_reports_each_outcome() {
+    let tests = vec![testing::TestCase { name: "truth".to_owned(), body: Expression::Boolean(true) },
+                     testing::TestCase { name: "number".to_owned(), body: Expression::Number(5) },
+                     testing::TestCase { name: "diverges".to_owned(), body: omega() }];
+    let report = testing::run_tests(tests, Some(100));
+    assert!(!report.passed());
+    assert_eq!(report.to_string(),
+               "test truth ... ok\n\
+                test number ... FAILED: evaluated to 5\n\
+                test diverges ... FAILED: evaluation ran out of fuel\n\
+                1 passed; 2 failed");
+}
+
+#[test]
+fn test_bench_statistics() {
+    let statistics = bench::Statistics::of(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+    assert_eq!(statistics, bench::Statistics { mean: 5.0, median: 4.5, stddev: 2.0 });
+}
+
+#[test]
+fn test_bench_measure_counts_steps() {
+    let expr = Expression::Application { function: Box::new(Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                                                                     body: Box::new(variable("x")) }),
+                                         arguments: vec![Expression::Number(1)] };
+    let measurement = bench::measure("identity", &expr, 3).unwrap();
+    // the application, the function, the argument, and the body
+    assert_eq!(measurement.steps, bench::Statistics { mean: 4.0, median: 4.0, stddev: 0.0 });
+}
+
+#[test]
+fn test_bench_baseline_round_trips_and_compares() {
+    let before = bench::Measurement { name: "loop (interpreter)".to_owned(),
+                                      steps: bench::Statistics { mean: 100.0, median: 100.0, stddev: 0.0 },
+                                      time: bench::Statistics { mean: 0.5, median: 0.25, stddev: 0.125 } };
+    assert_eq!(bench::load(&bench::save(std::slice::from_ref(&before))), Ok(vec![before.clone()]));
+    assert_eq!(bench::load("loop\t1\t2"), Err(bench::BaselineError::Malformed(1)));
+
+    let after = bench::Measurement { steps: bench::Statistics { mean: 90.0, median: 90.0, stddev: 0.0 }, ..before.clone() };
+    assert_eq!(after.delta(&before), bench::Delta { steps: -10.0, time: 0.0 });
+    assert_eq!(after.delta(&before).to_string(), "-10.0% steps and +0.0% time against the baseline");
+}
+
+#[test]
+fn test_bench_suite_agrees_across_evaluators() {
+    // the interpreter recurses for each call the suite makes, which takes more than a test
+    // thread's stack in a debug build
+    let suite = std::thread::Builder::new().stack_size(64 << 20).spawn(|| {
+        let point = Value::Record(Arc::new(vec![("x".to_owned(), Value::Number(250)), ("y".to_owned(), Value::Number(250))]));
+        let expected = [("recursion", Value::Number(610)), ("closures", Value::Number(125250)), ("records", point)];
+        for ((name, program), (expected_name, value)) in bench::suite().into_iter().zip(expected) {
+            assert_eq!(name, expected_name);
+            assert_eq!(run(program.clone()), Ok(value.clone()), "{}", name);
+            let mut expr = program.clone();
+            let stepped = loop {
+                match step::step(expr).unwrap() {
+                    step::Step::Next(next) => expr = next,
+                    step::Step::Value(value) => break value,
+                }
+            };
+            assert_eq!(stepped, value.reify(), "{}", name);
+            for evaluator in bench::Evaluator::ALL {
+                assert_eq!(bench::Evaluator::named(evaluator.name()), Some(evaluator));
+                assert!(bench::measure_with(evaluator, name, &program, 1).is_ok(), "{} with the {}", name, evaluator.name());
+            }
+        }
+    });
+    suite.unwrap().join().unwrap();
+}
+
+fn located(line: usize, expression: Expression) -> Expression {
+    Expression::Located { span: Span { start: Position { line, column: 1 }, end: Position { line, column: 10 } },
+                          expression: Box::new(expression) }
+}
+
+#[test]
+fn test_elaborate_types_nodes_and_resolves_shadowed_names() {
+    use typed::{Binder, Node};
+    // fn(x: Number) -> (x, fn(x: Boolean) -> x, g), with g declared
+    let inner = Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Boolean }], body: Box::new(variable("x")) };
+    let expr = Expression::Function {
+        parameters: vec![number_binding("x")],
+        body: Box::new(Expression::Tuple(vec![variable("x"), inner, variable("g")])),
+    };
+    let declarations = Declarations::from([("g".to_owned(), Type::String)]);
+    let typed = typed::elaborate(&declarations, expr.clone()).unwrap();
+    assert_eq!(Ok(typed.typ.clone()), check_with_declarations(&declarations, expr.clone()));
+
+    let Node::Function { parameters, body } = &typed.node else { panic!("expected a function, found {:?}", typed.node) };
+    assert_eq!(parameters.iter().map(|(binder, _)| *binder).collect::<Vec<_>>(), vec![0]);
+    let Node::Tuple(elements) = &body.node else { panic!("expected a tuple, found {:?}", body.node) };
+    assert_eq!(elements[0].node, Node::Variable { id: "x".to_owned(), binder: Binder::Bound(0) });
+    assert_eq!(elements[1].typ, Type::Function { arguments: vec![Type::Boolean], result: Box::new(Type::Boolean) });
+    let Node::Function { body: inner_body, .. } = &elements[1].node else { panic!("expected a function, found {:?}", elements[1].node) };
+    assert_eq!(inner_body.node, Node::Variable { id: "x".to_owned(), binder: Binder::Bound(1) });
+    assert_eq!(elements[2], typed::TypedExpression { typ: Type::String, node: Node::Variable { id: "g".to_owned(), binder: Binder::Declared } });
+
+    assert_eq!(typed.erase(), expr);
+}
+
+#[test]
+fn test_elaborate_polymorphic_declarations() {
+    // id : forall T. (T) -> T, which is the type of a value although it isn't of kind *
+    let identity = Type::ForAll {
+        parameters: vec![TypeBinding { id: "T".to_owned(), kind: Kind::Star }],
+        typ: Box::new(Type::Function { arguments: vec![Type::Variable("T".to_owned())], result: Box::new(Type::Variable("T".to_owned())) }),
+    };
+    let declarations = Declarations::from([("id".to_owned(), identity.clone())]);
+    let typed = typed::elaborate(&declarations, variable("id")).unwrap();
+    assert_eq!(typed.typ, identity);
+    assert_eq!(Ok(typed.typ), check_with_declarations(&declarations, variable("id")));
+}
+
+#[test]
+fn test_eval_interrupted_with_its_stack() {
+    // (fn() -> omega)(), with both calls located
+    let expr = located(1, apply(Expression::Function { parameters: vec![], body: Box::new(located(2, omega())) }, vec![]));
+    let token = Arc::new(core::sync::atomic::AtomicBool::new(false));
+    let mut interpreter = Interpreter { interrupt: Some(Interrupt::new(token.clone(), 10)), ..Interpreter::with_fuel(1000) };
+    token.store(true, Ordering::Relaxed);
+    let stack = [2, 1].map(|line| Span { start: Position { line, column: 1 }, end: Position { line, column: 10 } });
+    assert_eq!(interpreter.run(expr), Err(EvalError::Interrupted { stack: stack.to_vec() }));
+    // checked on the tenth step
+    assert_eq!(interpreter.steps, 10);
+}
+
+#[test]
+fn test_eval_failures_located_at_innermost_call() {
+    use primitives::Primitive::{Check, Error};
+    let raise = Expression::Function { parameters: vec![number_binding("x")], body: Box::new(primitive(Error, vec![variable("x")])) };
+    // (fn() -> (fn(x) -> error(x))(5))(), with both calls located
+    let expr = located(1, apply(Expression::Function { parameters: vec![], body: Box::new(located(2, apply(raise, vec![Expression::Number(5)]))) }, vec![]));
+    let at = Span { start: Position { line: 2, column: 1 }, end: Position { line: 2, column: 10 } };
+    assert_eq!(run(expr), Err(EvalError::Raised { value: Value::Number(5), at: Some(at) }));
+
+    // outside of any call, where it failed isn't known
+    let check = |condition, message: Vec<Expression>| run(primitive(Check, vec![Expression::Boolean(condition), Expression::Tuple(message)]));
+    assert_eq!(check(true, vec![]), Ok(Value::Tuple(vec![])));
+    assert_eq!(check(false, vec![string("expected")]), Err(EvalError::AssertionFailed { message: Some("expected".to_owned()), at: None }));
+    assert_eq!(EvalError::AssertionFailed { message: None, at: Some(at) }.to_string(), "assertion failed at 2:1");
+}
+
+#[test]
+fn test_eval_is_strict_and_left_to_right() {
+    use std::sync::Mutex;
+    use patterns::Pattern;
+    use primitives::Primitive::{Add, Error};
+
+    // log(n) records that it was called with n, and produces n, so what it records is the order
+    // things were evaluated in
+    let logged = Arc::new(Mutex::new(vec![]));
+    let log = logged.clone();
+    let native = Native::new("log", move |arguments| match &arguments[..] {
+        [Value::Number(n)] => {
+            log.lock().unwrap().push(*n);
+            Ok(Value::Number(*n))
+        }
+        _ => unreachable!("log is called with a number"),
+    });
+    let env = Environment::global(HashMap::from([("log".to_owned(), Value::Native(native))]));
+    let run_logged = |expr| {
+        let result = Interpreter::default().run_in(&env, expr);
+        (result, core::mem::take(&mut *logged.lock().unwrap()))
+    };
+    let log = |n| apply(variable("log"), vec![Expression::Number(n)]);
+    let after = |n, expr| blocks::let_in(Pattern::Wildcard, log(n), expr);
+    // fn(a, b, c) -> log(a + b + c)
+    let sum = Expression::Function {
+        parameters: vec![number_binding("a"), number_binding("b"), number_binding("c")],
+        body: Box::new(apply(variable("log"), vec![primitive(Add, vec![primitive(Add, vec![variable("a"), variable("b")]), variable("c")])])),
+    };
+
+    // the function, then its arguments, then the call; operands, fields, and the record updated
+    let program = Expression::Tuple(vec![
+        apply(after(0, sum.clone()), vec![log(1), log(2), log(3)]),
+        primitive(Add, vec![log(7), log(8)]),
+        Expression::Record(vec![("x".to_owned(), log(9)), ("y".to_owned(), log(10))]),
+        Expression::Update { record: Box::new(after(11, Expression::Record(vec![("x".to_owned(), Expression::Number(0))]))),
+                             fields: vec![("x".to_owned(), log(12))] },
+    ]);
+    let (result, order) = run_logged(program);
+    assert!(result.is_ok());
+    assert_eq!(order, [0, 1, 2, 3, 6, 7, 8, 9, 10, 11, 12]);
+
+    // an argument that fails stops the ones after it, and the call
+    let (result, order) = run_logged(apply(sum.clone(), vec![log(1), primitive(Error, vec![string("stop")]), log(2)]));
+    assert_eq!(result, Err(EvalError::Raised { value: Value::String("stop".to_owned()), at: None }));
+    assert_eq!(order, [1]);
+    // every argument is evaluated before the call finds there isn't a parameter for each
+    let (result, order) = run_logged(apply(sum, vec![log(1), log(2)]));
+    assert_eq!(result, Err(EvalError::ArityMismatch { expected: 3, found: 2 }));
+    assert_eq!(order, [1, 2]);
+
+    // and the small-step semantics steps the same way
+    let raised = EvalError::Raised { value: Value::String("first".to_owned()), at: None };
+    let both = Expression::Tuple(vec![primitive(Error, vec![string("first")]), primitive(Error, vec![string("second")])]);
+    assert_eq!(run(both.clone()), Err(raised.clone()));
+    assert_eq!(step::step(both), Err(raised));
+}
+
+#[test]
+fn test_structural_hash_is_alpha_invariant() {
+    use patterns::{Arm, Pattern};
+    // fn(x) -> fn(y) -> (x, y, z) against fn(a) -> fn(b) -> (a, b, z), located differently
+    let curried = |x: &str, y: &str| Expression::Function {
+        parameters: vec![number_binding(x)],
+        body: Box::new(located(2, Expression::Function {
+            parameters: vec![number_binding(y)],
+            body: Box::new(Expression::Tuple(vec![variable(x), variable(y), variable("z")])),
+        })),
+    };
+    assert_eq!(hash::hash_expression(&curried("x", "y")), hash::hash_expression(&located(7, curried("a", "b"))));
+    // which variable is used matters, and so do the names of free ones
+    let constant = |body: &str| Expression::Function {
+        parameters: vec![number_binding("x")],
+        body: Box::new(Expression::Function { parameters: vec![number_binding("y")], body: Box::new(variable(body)) }),
+    };
+    assert_ne!(hash::hash_expression(&constant("x")), hash::hash_expression(&constant("y")));
+    assert_ne!(hash::hash_expression(&identity("x")), hash::hash_expression(&Expression::Function {
+        parameters: vec![number_binding("x")],
+        body: Box::new(variable("z")),
+    }));
+    let arm = |id: &str| Arm { pattern: Pattern::Variant { tag: "Some".to_owned(), payload: Box::new(Pattern::Variable(id.to_owned())) },
+                               guard: None,
+                               body: variable(id) };
+    let matching = |id| Expression::Match { scrutinee: Box::new(variable("o")), arms: vec![arm(id)] };
+    assert_eq!(hash::hash_expression(&matching("v")), hash::hash_expression(&matching("w")));
+
+    let forall = |id: &str| Type::ForAll { parameters: vec![TypeBinding { id: id.to_owned(), kind: Kind::Star }],
+                                           typ: Box::new(Type::Tuple(vec![Type::Variable(id.to_owned()), Type::Variable("U".to_owned())])) };
+    assert_eq!(hash::hash_type(&forall("T")), hash::hash_type(&forall("V")));
+    assert_ne!(hash::hash_type(&forall("T")), hash::hash_type(&forall("U")));
+    // stable across runs and platforms
+    assert_eq!(hash::hash_type(&Type::Number).to_string().len(), 16);
+    assert_eq!(hash::hash_type(&Type::Number), hash::hash_type(&Type::Number));
+}
+
+fn partially_covered() -> Expression {
+    Expression::If { condition: Box::new(located(1, Expression::Boolean(true))),
+                     consequent: Box::new(located(2, Expression::Number(1))),
+                     alternative: Box::new(located(3, Expression::Number(2))) }
+}
+
+#[test]
+fn test_coverage_text_report() {
+    let (result, report) = coverage::run_with_coverage(partially_covered());
+    assert_eq!(result, Ok(Value::Number(1)));
+    assert_eq!(report.to_text("main.sang"), "main.sang:3:1-3:10: not executed\n2 of 3 expressions executed");
+}
+
+#[test]
+fn test_coverage_lcov_report() {
+    let (_, report) = coverage::run_with_coverage(partially_covered());
+    assert_eq!(report.to_lcov("main.sang"), "SF:main.sang\nDA:1,1\nDA:2,1\nDA:3,0\nLH:2\nLF:3\nend_of_record\n");
+}
+
+fn identity(id: &str) -> Expression {
+    Expression::Function { parameters: vec![Binding { id: id.to_owned(), typ: Type::Number }],
+                           body: Box::new(variable(id)) }
+}
+
+fn apply(function: Expression, arguments: Vec<Expression>) -> Expression {
+    Expression::Application { function: Box::new(function), arguments }
+}
+
+#[test]
+fn test_eval_closures_are_lexically_scoped() {
+    // (fn(x) -> (fn(f) -> (fn(x) -> f(0))(2))(fn(y) -> x))(1) sees the `x` where `fn(y) -> x` was defined
+    let constant_x = Expression::Function { parameters: vec![Binding { id: "y".to_owned(), typ: Type::Number }],
+                                            body: Box::new(variable("x")) };
+    let rebind_x = Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                          body: Box::new(apply(variable("f"), vec![Expression::Number(0)])) };
+    let call_f = Expression::Function { parameters: vec![Binding { id: "f".to_owned(), typ: Type::Number }],
+                                        body: Box::new(apply(rebind_x, vec![Expression::Number(2)])) };
+    let expr = apply(Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                            body: Box::new(apply(call_f, vec![constant_x])) },
+                     vec![Expression::Number(1)]);
+    assert_eq!(run(expr), Ok(Value::Number(1)));
+}
+
+#[test]
+fn test_eval_allocation_limit() {
+    use crate::syntax::lower::lower_block;
+    use crate::syntax::parser::parse;
+    use operators::Operators;
+
+    let limited = |allocations| Interpreter { limits: Limits { allocations: Some(allocations), ..Limits::default() }, ..Interpreter::default() };
+    let expr = apply(identity("x"), vec![identity("y")]);
+    assert_eq!(limited(2).run(expr), Err(EvalError::ResourceExhausted(Resource::Allocations)));
+
+    // the loop only keeps one pair at a time, but every pair it allocates counts against the
+    // budget, so it runs within one as large as all of them and no smaller
+    let source = "local i = 0\nwhile i < 100 do\n  local pair = (i, i)\n  i = i + 1\nend\ni";
+    let program = lower_block(&parse(source).program, &Operators::default()).unwrap();
+    let mut unlimited = Interpreter::default();
+    assert_eq!(unlimited.run(program.clone()), Ok(Value::Number(100)));
+    assert!(unlimited.allocated > 100);
+    assert_eq!(limited(unlimited.allocated).run(program.clone()), Ok(Value::Number(100)));
+    assert_eq!(limited(unlimited.allocated - 1).run(program), Err(EvalError::ResourceExhausted(Resource::Allocations)));
+}
+
+#[test]
+fn test_eval_length_limit() {
+    let limited = |expr| Interpreter { limits: Limits { length: Some(3), ..Limits::default() }, ..Interpreter::default() }.run(expr);
+    let concat = |left: &[u8], right: &[u8]| Expression::Primitive { operator: Primitive::Concat,
+                                                                     arguments: vec![Expression::Bytes(left.to_vec()), Expression::Bytes(right.to_vec())] };
+    assert_eq!(limited(concat(b"ab", b"c")), Ok(Value::Bytes(b"abc".to_vec())));
+    assert_eq!(limited(concat(b"ab", b"cd")), Err(EvalError::ResourceExhausted(Resource::Length)));
+    let decode = |bytes: &[u8]| Expression::Primitive { operator: Primitive::DecodeLatin1, arguments: vec![Expression::Bytes(bytes.to_vec())] };
+    assert_eq!(limited(decode(b"abcd")), Err(EvalError::ResourceExhausted(Resource::Length)));
+
+    let numbers = |count| (0..count).map(Expression::Number).collect::<Vec<_>>();
+    assert_eq!(limited(Expression::Tuple(numbers(3))), Ok(Value::Tuple((0..3).map(Value::Number).collect())));
+    assert_eq!(limited(Expression::Tuple(numbers(4))), Err(EvalError::ResourceExhausted(Resource::Length)));
+    let fields = |count| numbers(count).into_iter().enumerate().map(|(i, number)| (format!("x{}", i), number)).collect::<Vec<_>>();
+    assert_eq!(limited(Expression::Record(fields(4))), Err(EvalError::ResourceExhausted(Resource::Length)));
+    let update = Expression::Update { record: Box::new(Expression::Record(fields(3))), fields: vec![("y".to_owned(), Expression::Number(0))] };
+    assert_eq!(limited(update), Err(EvalError::ResourceExhausted(Resource::Length)));
+}
+
+#[test]
+fn test_eval_environment_depth_limit() {
+    use patterns::{Arm, Pattern};
+    let bind = |id: &str, value, body| Expression::Match { scrutinee: Box::new(value),
+                                                         arms: vec![Arm { pattern: Pattern::Variable(id.to_owned()), guard: None, body }] };
+    // let a = 1 in let b = a in let c = b in c nests three frames inside the global one
+    let expr = bind("a", Expression::Number(1), bind("b", variable("a"), bind("c", variable("b"), variable("c"))));
+    let limited = |environment_depth| Interpreter { limits: Limits { environment_depth: Some(environment_depth), ..Limits::default() },
+                                                    ..Interpreter::default() };
+    assert_eq!(limited(4).run(expr.clone()), Ok(Value::Number(1)));
+    assert_eq!(limited(3).run(expr), Err(EvalError::ResourceExhausted(Resource::EnvironmentDepth)));
+
+    // (fn(a) -> (fn(b) -> (fn(c) -> c)(b))(a))(1) nests no deeper than two frames, since each
+    // closure only captures what its body refers to, in a frame of its own
+    let expr = apply(Expression::Function { parameters: vec![Binding { id: "a".to_owned(), typ: Type::Number }],
+                                            body: Box::new(apply(Expression::Function { parameters: vec![Binding { id: "b".to_owned(), typ: Type::Number }],
+                                                                                        body: Box::new(apply(identity("c"), vec![variable("b")])) },
+                                                                 vec![variable("a")])) },
+                     vec![Expression::Number(1)]);
+    assert_eq!(limited(3).run(expr.clone()), Ok(Value::Number(1)));
+    assert_eq!(limited(2).run(expr), Err(EvalError::ResourceExhausted(Resource::EnvironmentDepth)));
+}
+
+#[test]
+fn test_eval_closures_capture_only_their_free_variables() {
+    // let unused = "big" in let x = 1 in fn(y) -> x + y keeps `x`, but not `unused`
+    use patterns::{Arm, Pattern};
+    use primitives::Primitive::Add;
+    let bind = |id: &str, value, body| Expression::Match { scrutinee: Box::new(value),
+                                                         arms: vec![Arm { pattern: Pattern::Variable(id.to_owned()), guard: None, body }] };
+    let closure = Expression::Function { parameters: vec![number_binding("y")],
+                                         body: Box::new(primitive(Add, vec![variable("x"), variable("y")])) };
+    let expr = bind("unused", string("big"), bind("x", Expression::Number(1), closure));
+    let Ok(Value::Function { environment, .. }) = run(expr) else { panic!("a function evaluates to a closure") };
+    assert_eq!(environment.lookup("x"), Some(&Value::Number(1)));
+    assert_eq!(environment.lookup("unused"), None);
+    assert_eq!(environment.depth(), 2);
+}
+
+#[test]
+fn test_evaluate_constants_reifies_closure_environment() {
+    // const { (fn(x) -> fn(y) -> x)(7) } is folded to fn(y) -> 7
+    let constant = Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                          body: Box::new(Expression::Function { parameters: vec![Binding { id: "y".to_owned(), typ: Type::Number }],
+                                                                                body: Box::new(variable("x")) }) };
+    let expr = Expression::Constant(Box::new(apply(constant, vec![Expression::Number(7)])));
+    assert_eq!(constants::evaluate_constants(expr, 100),
+               Ok(Expression::Function { parameters: vec![Binding { id: "y".to_owned(), typ: Type::Number }],
+                                         body: Box::new(Expression::Number(7)) }));
+}
+
+
+fn primitive(operator: primitives::Primitive, arguments: Vec<Expression>) -> Expression {
+    Expression::Primitive { operator, arguments }
+}
+
+#[test]
+fn test_eval_equality() {
+    use primitives::Primitive::Equal;
+    assert_eq!(run(primitive(Equal, vec![Expression::Number(1), Expression::Number(1)])), Ok(Value::Boolean(true)));
+    assert_eq!(run(primitive(Equal, vec![Expression::Number(1), Expression::Boolean(true)])), Ok(Value::Boolean(false)));
+    assert!(matches!(run(primitive(Equal, vec![identity("x"), identity("x")])), Err(EvalError::NotComparable { .. })));
+}
+
+#[test]
+fn test_eval_ordering() {
+    use primitives::Primitive::Less;
+    assert_eq!(run(primitive(Less, vec![Expression::Number(-1), Expression::Number(1)])), Ok(Value::Boolean(true)));
+    assert_eq!(run(primitive(Less, vec![Expression::Number(1), Expression::Boolean(true)])),
+               Err(EvalError::ExpectedNumber { found: Value::Boolean(true) }));
+}
+
+#[test]
+fn test_type_checking_comparisons() {
+    use primitives::Primitive::{Equal, Less};
+    assert_eq!(check_types(&HashMap::new(), &number_or_boolean_tenv(), primitive(Equal, vec![variable("x"), Expression::Number(1)])),
+               Ok(Type::Boolean));
+    assert_eq!(check(primitive(Equal, vec![identity("x"), identity("x")])),
+               Err(TypeError::NotComparable { found: Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) } }));
+    assert_eq!(check(primitive(Less, vec![Expression::Boolean(false), Expression::Boolean(true)])),
+               Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::Boolean }));
+    assert_eq!(check(primitive(Less, vec![Expression::Number(1)])),
+               Err(TypeError::ArityMismatch { expected: 2, found: 1 }));
+}
+
+#[test]
+fn test_display_values() {
+    assert_eq!(Value::Number(-3).to_string(), "-3");
+    assert_eq!(Value::Boolean(true).to_string(), "true");
+    assert_eq!(run(identity("x")).unwrap().to_string(), "<fn (Number) -> Number>");
+    // fn(b: Boolean) -> fn(x: Number) -> b captures a boolean, so its result type is recovered
+    let captures = Expression::Function { parameters: vec![Binding { id: "b".to_owned(), typ: Type::Boolean }],
+                                          body: Box::new(Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                                                                body: Box::new(variable("b")) }) };
+    assert_eq!(run(apply(captures, vec![Expression::Boolean(false)])).unwrap().to_string(), "<fn (Number) -> Boolean>");
+    let native = Value::Native(Native::new("io.print", |_| Ok(Value::Boolean(true))));
+    assert_eq!(native.to_string(), "<native io.print>");
+}
+
+#[test]
+fn test_eval_arithmetic_spec() {
+    use primitives::Primitive::*;
+    let overflow = |operator, left, right| Err(EvalError::Overflow { operator, left, right });
+    let spec = [
+        (Add, 2, 3, Ok(5)),
+        (Add, i64::MAX, 1, overflow(Add, i64::MAX, 1)),
+        (Add, i64::MIN, -1, overflow(Add, i64::MIN, -1)),
+        (Subtract, i64::MIN, 1, overflow(Subtract, i64::MIN, 1)),
+        (Subtract, 0, i64::MIN, overflow(Subtract, 0, i64::MIN)),
+        (Multiply, -4, 5, Ok(-20)),
+        (Multiply, i64::MAX, 2, overflow(Multiply, i64::MAX, 2)),
+        (Multiply, i64::MIN, -1, overflow(Multiply, i64::MIN, -1)),
+        // floor division rounds toward negative infinity
+        (Divide, 7, 2, Ok(3)),
+        (Divide, -7, 2, Ok(-4)),
+        (Divide, 7, -2, Ok(-4)),
+        (Divide, -7, -2, Ok(3)),
+        (Divide, -8, 2, Ok(-4)),
+        (Divide, i64::MIN, -1, overflow(Divide, i64::MIN, -1)),
+        (Divide, 1, 0, Err(EvalError::DivisionByZero)),
+        // the remainder takes the sign of the divisor
+        (Modulo, 7, 3, Ok(1)),
+        (Modulo, -7, 3, Ok(2)),
+        (Modulo, 7, -3, Ok(-2)),
+        (Modulo, -7, -3, Ok(-1)),
+        (Modulo, -6, 3, Ok(0)),
+        (Modulo, i64::MIN, -1, Ok(0)),
+        (Modulo, i64::MIN, i64::MAX, Ok(i64::MAX - 1)),
+        (Modulo, 1, 0, Err(EvalError::DivisionByZero)),
+    ];
+    for (operator, left, right, expected) in spec {
+        let result = run(primitive(operator, vec![Expression::Number(left), Expression::Number(right)]));
+        assert_eq!(result, expected.map(Value::Number), "{} {} {}", left, operator, right);
+    }
+}
+
+#[test]
+fn test_floor_division_and_modulo_agree() {
+    use primitives::Primitive::{Divide, Modulo};
+    for left in -10..=10 {
+        for right in [-3, -2, -1, 1, 2, 3] {
+            let [quotient, remainder] = [Divide, Modulo].map(|operator| {
+                run(primitive(operator, vec![Expression::Number(left), Expression::Number(right)]))
+            });
+            match (quotient, remainder) {
+                (Ok(Value::Number(q)), Ok(Value::Number(r))) => assert_eq!(q * right + r, left),
+                other => panic!("unexpected results {:?}", other),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_arithmetic_requires_numbers() {
+    use primitives::Primitive::Add;
+    assert_eq!(run(primitive(Add, vec![Expression::Number(1), Expression::Boolean(true)])),
+               Err(EvalError::ExpectedNumber { found: Value::Boolean(true) }));
+    assert_eq!(check(primitive(Add, vec![Expression::Number(1), Expression::Number(2)])), Ok(Type::Number));
+    assert_eq!(check(primitive(Add, vec![Expression::Boolean(true), Expression::Number(2)])),
+               Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::Boolean }));
+}
+
+#[test]
+fn test_eval_bitwise_spec() {
+    use primitives::Primitive::*;
+    let spec = [
+        (BitAnd, 0b1100, 0b1010, 0b1000),
+        (BitOr, 0b1100, 0b1010, 0b1110),
+        (BitXor, 0b1100, 0b1010, 0b0110),
+        // negative numbers are operated on in two's complement
+        (BitAnd, -1, 0xff, 0xff),
+        (BitXor, -1, 0, -1),
+        (ShiftLeft, 1, 4, 16),
+        (ShiftLeft, 1, 63, i64::MIN),
+        (ShiftLeft, 3, 63, i64::MIN),
+        (ShiftLeft, 1, 64, 0),
+        (ShiftLeft, 16, -4, 1),
+        (ShiftLeft, 1, i64::MAX, 0),
+        // right shifts are arithmetic, agreeing with floor division
+        (ShiftRight, 16, 4, 1),
+        (ShiftRight, -7, 1, -4),
+        (ShiftRight, -1, 100, -1),
+        (ShiftRight, i64::MAX, 64, 0),
+        (ShiftRight, 1, -4, 16),
+        (ShiftRight, 1, i64::MIN, 0),
+    ];
+    for (operator, left, right, expected) in spec {
+        let result = run(primitive(operator, vec![Expression::Number(left), Expression::Number(right)]));
+        assert_eq!(result, Ok(Value::Number(expected)), "{} {} {}", left, operator, right);
+    }
+    assert_eq!(run(primitive(BitNot, vec![Expression::Number(0)])), Ok(Value::Number(-1)));
+
+    // a power with a negative exponent rounds toward negative infinity, like `//`
+    let powers = [(2, 10, 1024), (-3, 3, -27), (7, 0, 1), (0, 0, 1), (0, 5, 0), (1, i64::MAX, 1), (-1, i64::MAX, -1),
+                  (2, -1, 0), (-2, -1, -1), (-2, -2, 0), (-1, -3, -1)];
+    for (base, exponent, expected) in powers {
+        assert_eq!(run(primitive(Power, vec![Expression::Number(base), Expression::Number(exponent)])), Ok(Value::Number(expected)),
+                   "{} ^ {}", base, exponent);
+    }
+    assert_eq!(run(primitive(Power, vec![Expression::Number(2), Expression::Number(63)])),
+               Err(EvalError::Overflow { operator: Power, left: 2, right: 63 }));
+    assert_eq!(run(primitive(Power, vec![Expression::Number(0), Expression::Number(-1)])), Err(EvalError::DivisionByZero));
+    assert_eq!(run(primitive(BitNot, vec![Expression::Boolean(false)])),
+               Err(EvalError::ExpectedNumber { found: Value::Boolean(false) }));
+}
+
+#[test]
+fn test_type_checking_bitwise() {
+    use primitives::Primitive::{BitNot, BitOr};
+    assert_eq!(check(primitive(BitNot, vec![Expression::Number(1)])), Ok(Type::Number));
+    assert_eq!(check(primitive(BitNot, vec![Expression::Number(1), Expression::Number(2)])),
+               Err(TypeError::ArityMismatch { expected: 1, found: 2 }));
+    assert_eq!(check(primitive(BitOr, vec![Expression::Number(1), Expression::Boolean(true)])),
+               Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::Boolean }));
+}
+
+fn string(value: &str) -> Expression {
+    Expression::String(value.to_owned())
+}
+
+#[test]
+fn test_eval_string_indexing() {
+    use primitives::Primitive::*;
+    // "né😀" is 1 + 2 + 4 bytes, but only three characters
+    let text = || string("né😀");
+    assert_eq!(run(primitive(ByteLength, vec![text()])), Ok(Value::Number(7)));
+    assert_eq!(run(primitive(CharLength, vec![text()])), Ok(Value::Number(3)));
+    assert_eq!(run(primitive(Byte, vec![text(), Expression::Number(1)])), Ok(Value::Number(0xc3)));
+    assert_eq!(run(primitive(CharAt, vec![text(), Expression::Number(2)])), Ok(Value::Char('😀')));
+    assert_eq!(run(primitive(CharAt, vec![text(), Expression::Number(3)])),
+               Err(EvalError::IndexOutOfBounds { index: 3, length: 3 }));
+    assert_eq!(run(primitive(Byte, vec![text(), Expression::Number(-1)])),
+               Err(EvalError::IndexOutOfBounds { index: -1, length: 7 }));
+    assert_eq!(run(primitive(DecodeChar, vec![text(), Expression::Number(3)])), Ok(Value::Char('😀')));
+    assert_eq!(run(primitive(DecodeChar, vec![text(), Expression::Number(2)])),
+               Err(EvalError::NotCharBoundary { offset: 2 }));
+    assert_eq!(run(primitive(CharWidth, vec![Expression::Char('é')])), Ok(Value::Number(2)));
+    assert_eq!(run(primitive(CodePoint, vec![Expression::Char('😀')])), Ok(Value::Number(0x1f600)));
+}
+
+fn count_chars(target: char) -> Expression {
+    // fn(n: Number, c: Char) -> if c == target then n + 1 else n
+    let n = || variable("n");
+    Expression::Function { parameters: vec![Binding { id: "n".to_owned(), typ: Type::Number },
+                                            Binding { id: "c".to_owned(), typ: Type::Char }],
+                           body: Box::new(Expression::If { condition: Box::new(primitive(primitives::Primitive::Equal,
+                                                                                         vec![variable("c"), Expression::Char(target)])),
+                                                           consequent: Box::new(primitive(primitives::Primitive::Add,
+                                                                                          vec![n(), Expression::Number(1)])),
+                                                           alternative: Box::new(n()) }) }
+}
+
+#[test]
+fn test_fold_chars() {
+    use primitives::Primitive::FoldChars;
+    let fold = primitive(FoldChars, vec![string("añaña"), Expression::Number(0), count_chars('ñ')]);
+    assert_eq!(check(fold.clone()), Ok(Type::Number));
+    assert_eq!(run(fold), Ok(Value::Number(2)));
+
+    // the accumulator can't be a character when the function expects a number
+    let mismatched = primitive(FoldChars, vec![string("a"), Expression::Char('a'), count_chars('a')]);
+    assert_eq!(check(mismatched), Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::Char }));
+}
+
+#[test]
+fn test_type_checking_strings() {
+    use primitives::Primitive::{CharAt, CodePoint, Equal};
+    assert_eq!(check(primitive(CharAt, vec![string("abc"), Expression::Number(0)])), Ok(Type::Char));
+    assert_eq!(check(primitive(CodePoint, vec![string("a")])),
+               Err(TypeError::TypeMismatch { expected: Type::Char, found: Type::String }));
+    assert_eq!(check(primitive(Equal, vec![string("a"), string("a")])), Ok(Type::Boolean));
+    assert_eq!(run(primitive(Equal, vec![string("a"), Expression::Char('a')])), Ok(Value::Boolean(false)));
+    assert_eq!(run(string("a\"b")).unwrap().to_string(), "\"a\\\"b\"");
+}
+
+fn bytes(value: &[u8]) -> Expression {
+    Expression::Bytes(value.to_vec())
+}
+
+#[test]
+fn test_eval_bytes() {
+    use primitives::Primitive::*;
+    assert_eq!(run(primitive(BytesLength, vec![bytes(b"abc")])), Ok(Value::Number(3)));
+    assert_eq!(run(primitive(Slice, vec![bytes(b"abcdef"), Expression::Number(1), Expression::Number(4)])),
+               Ok(Value::Bytes(b"bcd".to_vec())));
+    assert_eq!(run(primitive(Slice, vec![bytes(b"abc"), Expression::Number(2), Expression::Number(1)])),
+               Err(EvalError::InvalidRange { start: 2, end: 1, length: 3 }));
+    assert_eq!(run(primitive(Slice, vec![bytes(b"abc"), Expression::Number(0), Expression::Number(4)])),
+               Err(EvalError::InvalidRange { start: 0, end: 4, length: 3 }));
+    assert_eq!(run(primitive(Concat, vec![bytes(b"ab"), bytes(b"\0")])), Ok(Value::Bytes(b"ab\0".to_vec())));
+    assert_eq!(run(primitive(Concat, vec![bytes(b"ab"), string("c")])),
+               Err(EvalError::ExpectedBytes { found: Value::String("c".to_owned()) }));
+    assert_eq!(Value::Bytes(b"a\"\xff".to_vec()).to_string(), "b\"a\\\"\\xff\"");
+}
+
+#[test]
+fn test_eval_bytes_encodings() {
+    use primitives::Primitive::*;
+    use primitives::Encoding;
+    assert_eq!(run(primitive(EncodeUtf8, vec![string("é")])), Ok(Value::Bytes(vec![0xc3, 0xa9])));
+    assert_eq!(run(primitive(EncodeLatin1, vec![string("é")])), Ok(Value::Bytes(vec![0xe9])));
+    assert_eq!(run(primitive(EncodeLatin1, vec![string("a€")])),
+               Err(EvalError::InvalidEncoding { encoding: Encoding::Latin1, offset: 1 }));
+    assert_eq!(run(primitive(DecodeUtf8, vec![bytes(&[0x61, 0xc3, 0xa9])])), Ok(Value::String("aé".to_owned())));
+    assert_eq!(run(primitive(DecodeUtf8, vec![bytes(&[0x61, 0xe9])])),
+               Err(EvalError::InvalidEncoding { encoding: Encoding::Utf8, offset: 1 }));
+    assert_eq!(run(primitive(DecodeLatin1, vec![bytes(&[0x61, 0xe9])])), Ok(Value::String("aé".to_owned())));
+    assert_eq!(check(primitive(DecodeUtf8, vec![string("a")])),
+               Err(TypeError::TypeMismatch { expected: Type::Bytes, found: Type::String }));
+}
+
+#[test]
+fn test_eval_number_conversions() {
+    use primitives::Primitive::{ToNumber, ToString};
+    let number = Expression::Number;
+    assert_eq!(run(primitive(ToString, vec![number(-255)])), Ok(Value::String("-255".to_owned())));
+    assert_eq!(run(primitive(ToString, vec![number(-255), number(16)])), Ok(Value::String("-ff".to_owned())));
+    assert_eq!(run(primitive(ToNumber, vec![string("-FF"), number(16)])), Ok(Value::Number(-255)));
+    assert_eq!(run(primitive(ToNumber, vec![string("1_000")])), Ok(Value::Tuple(vec![])));
+    assert_eq!(run(primitive(ToString, vec![number(1), number(37)])), Err(EvalError::InvalidBase { base: 37 }));
+    assert_eq!(check(primitive(ToNumber, vec![string("12")])), Ok(Type::Union(vec![Type::Number, Type::Tuple(vec![])])));
+    assert_eq!(check(primitive(ToString, vec![])), Err(TypeError::ArityMismatch { expected: 2, found: 0 }));
+    assert_eq!(check(primitive(ToString, vec![number(1), string("2")])),
+               Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::String }));
+
+    for base in numbers::BASES {
+        for value in [0, 1, -1, 35, -36, 1 << 40, i64::MAX, i64::MIN] {
+            assert_eq!(numbers::parse(&numbers::format(value, base), base), Some(value), "{} in base {}", value, base);
+        }
+    }
+    assert_eq!(numbers::format(i64::MIN, 2), format!("-1{}", "0".repeat(63)));
+    for text in ["", "-", "+1", " 1", "1.0", "9223372036854775808", "12a"] {
+        assert_eq!(numbers::parse(text, 10), None, "{:?}", text);
+    }
+    for (value, text) in [(1.0, "1.0"), (0.1, "0.1"), (1e100, "1e100"), (-2.5e-8, "-2.5e-8"), (123456.75, "123456.75")] {
+        assert_eq!(numbers::format_float(value), text);
+        assert_eq!(text.parse::<f64>(), Ok(value));
+    }
+}
+
+#[test]
+fn test_eval_checked_conversions() {
+    use primitives::Primitive::{ToFloat, ToInteger, ToNumber, ToString};
+    let (number, float) = (Expression::Number, |value| Expression::Float(numbers::Float(value)));
+    let none = Value::Tuple(vec![]);
+
+    // each conversion of each kind of input, and what it produces: the converted value, or `()`
+    // when it can't be made exactly
+    let matrix = [
+        (ToInteger, vec![number(-7)], Value::Number(-7)),
+        (ToInteger, vec![float(-7.0)], Value::Number(-7)),
+        (ToInteger, vec![float(-0.0)], Value::Number(0)),
+        (ToInteger, vec![float(2.5)], none.clone()),
+        (ToInteger, vec![float(i64::MIN as f64)], Value::Number(i64::MIN)),
+        (ToInteger, vec![float(-(i64::MIN as f64))], none.clone()),
+        (ToInteger, vec![float(1e300)], none.clone()),
+        (ToFloat, vec![number(3)], Value::Float(3.0)),
+        (ToFloat, vec![number(1 << 53)], Value::Float(9007199254740992.0)),
+        (ToFloat, vec![number((1 << 53) + 1)], none.clone()),
+        (ToFloat, vec![number(i64::MIN)], Value::Float(i64::MIN as f64)),
+        (ToFloat, vec![number(i64::MAX)], none.clone()),
+        (ToFloat, vec![string("-1.5e3")], Value::Float(-1500.0)),
+        (ToFloat, vec![string("0.1")], Value::Float(0.1)),
+        (ToFloat, vec![string("7")], Value::Float(7.0)),
+        (ToFloat, vec![string("1e400")], none.clone()),
+        (ToFloat, vec![string(".5")], none.clone()),
+        (ToFloat, vec![string("1.")], none.clone()),
+        (ToFloat, vec![string("inf")], none.clone()),
+        (ToFloat, vec![string("NaN")], none.clone()),
+        (ToFloat, vec![string(" 1.0")], none.clone()),
+        (ToString, vec![float(0.1)], Value::String("0.1".to_owned())),
+        (ToString, vec![float(-3.0), number(10)], Value::String("-3.0".to_owned())),
+        (ToString, vec![float(1e100)], Value::String("1e100".to_owned())),
+        (ToString, vec![number(255), number(2)], Value::String("11111111".to_owned())),
+        (ToNumber, vec![string("1.0")], none.clone()),
+        (ToNumber, vec![string("-9223372036854775808")], Value::Number(i64::MIN)),
+    ];
+    for (operator, arguments, expected) in matrix {
+        let expr = primitive(operator, arguments);
+        assert_eq!(run(expr.clone()), Ok(expected), "{:?}", expr);
+    }
+    assert_eq!(run(primitive(ToString, vec![float(1.5), number(16)])), Err(EvalError::FloatBase { base: 16 }));
+
+    let optional = |typ| Type::Union(vec![typ, Type::Tuple(vec![])]);
+    assert_eq!(check(primitive(ToInteger, vec![float(1.0)])), Ok(optional(Type::Number)));
+    assert_eq!(check(primitive(ToFloat, vec![string("1.0")])), Ok(optional(Type::Float)));
+    assert_eq!(check(primitive(ToString, vec![float(1.0)])), Ok(Type::String));
+    assert_eq!(check(primitive(ToFloat, vec![float(1.0)])),
+               Err(TypeError::TypeMismatch { expected: Type::Union(vec![Type::Number, Type::String]), found: Type::Float }));
+    assert_eq!(check(primitive(ToInteger, vec![string("1")])),
+               Err(TypeError::TypeMismatch { expected: Type::Union(vec![Type::Number, Type::Float]), found: Type::String }));
+}
+
+#[test]
+fn test_binary_round_trip_literals() {
+    let expr = primitive(primitives::Primitive::Concat, vec![bytes(b"\x00\xff"), string("né"), Expression::Char('😀'),
+                                                             Expression::Float(numbers::Float(-0.5))]);
+    let mut encoder = binary::Encoder::new();
+    encoder.encode(&expr);
+    let encoded = encoder.finish();
+    let mut decoder = binary::Decoder::new(&encoded, &|_| None);
+    assert_eq!(decoder.decode::<Expression>(), Ok(expr));
+    assert!(decoder.is_finished());
+}
+
+/// a format whose version 1 files held a number as a `u32`, and whose version 2 files hold it as
+/// an `i64`, in version 3 with a string after it
+const NUMBERS: binary::Format = binary::Format {
+    magic: b"TEST",
+    version: binary::Version { major: 3, minor: 1 },
+    migrations: &[
+        |contents| {
+            let mut encoder = binary::Encoder::new();
+            encoder.encode(&(binary::Decoder::new(contents, &|_| None).u32()? as i64));
+            Ok(encoder.finish())
+        },
+        |contents| Ok([contents, &0u64.to_le_bytes()].concat()),
+    ],
+};
+
+#[test]
+fn test_binary_versions_and_migrations() {
+    use binary::{DecodeError, Version};
+    let file = |major, minor, contents: &[u8]| {
+        let mut encoder = binary::Encoder::new();
+        encoder.bytes(b"TEST");
+        encoder.u16(major);
+        encoder.u16(minor);
+        encoder.bytes(contents);
+        encoder.finish()
+    };
+    let mut encoder = binary::Encoder::new();
+    encoder.header(&NUMBERS);
+    encoder.encode(&5i64);
+    encoder.encode("");
+    let current = encoder.finish();
+    assert_eq!(binary::Decoder::new(&current, &|_| None).header(&NUMBERS), Ok(Version { major: 3, minor: 1 }));
+
+    // an earlier minor version is read as it is, and an earlier major version is migrated
+    let earlier_minor = file(3, 0, &current[8..]);
+    assert_eq!(binary::Decoder::new(&earlier_minor, &|_| None).header(&NUMBERS), Ok(Version { major: 3, minor: 0 }));
+    assert_eq!(NUMBERS.migrate(&earlier_minor), Ok(earlier_minor.clone()));
+    assert_eq!(NUMBERS.migrate(&file(1, 4, &5u32.to_le_bytes())), Ok(current.clone()));
+
+    let unsupported = |major, minor| DecodeError::UnsupportedVersion { found: Version { major, minor }, supported: NUMBERS.version };
+    let old = file(1, 0, &5u32.to_le_bytes());
+    assert_eq!(binary::Decoder::new(&old, &|_| None).header(&NUMBERS), Err(unsupported(1, 0)));
+    assert_eq!(NUMBERS.migrate(&file(0, 9, &[])), Err(unsupported(0, 9)));
+    assert_eq!(NUMBERS.migrate(&file(3, 2, &[])), Err(unsupported(3, 2)));
+    assert_eq!(NUMBERS.migrate(&file(4, 0, &[])), Err(unsupported(4, 0)));
+    assert_eq!(NUMBERS.migrate(b"nope"), Err(DecodeError::BadMagic { expected: "TEST".to_owned() }));
+}
+
+fn option_type() -> Type {
+    Type::Variant(vec![("Some".to_owned(), Type::Number), ("None".to_owned(), Type::Tuple(vec![]))])
+}
+
+fn some(payload: Expression) -> Expression {
+    Expression::Variant { tag: "Some".to_owned(), payload: Box::new(payload) }
+}
+
+fn none() -> Expression {
+    Expression::Variant { tag: "None".to_owned(), payload: Box::new(Expression::Tuple(vec![])) }
+}
+
+fn some_pattern(payload: Pattern) -> Pattern {
+    Pattern::Variant { tag: "Some".to_owned(), payload: Box::new(payload) }
+}
+
+fn none_pattern() -> Pattern {
+    Pattern::Variant { tag: "None".to_owned(), payload: Box::new(Pattern::Tuple(vec![])) }
+}
+
+fn arm(pattern: Pattern, guard: Option<Expression>, body: Expression) -> Arm {
+    Arm { pattern, guard, body }
+}
+
+fn matching(scrutinee: Expression, arms: Vec<Arm>) -> Expression {
+    Expression::Match { scrutinee: Box::new(scrutinee), arms }
+}
+
+fn sign_of_option(scrutinee: Expression) -> Expression {
+    // match scrutinee { case Some(x) if 0 < x -> 1, case Some(_) -> -1, case None -> 0 }
+    use primitives::Primitive::Less;
+    matching(scrutinee, vec![
+        arm(some_pattern(Pattern::Variable("x".to_owned())),
+            Some(primitive(Less, vec![Expression::Number(0), variable("x")])),
+            Expression::Number(1)),
+        arm(some_pattern(Pattern::Wildcard), None, Expression::Number(-1)),
+        arm(none_pattern(), None, Expression::Number(0)),
+    ])
+}
+
+#[test]
+fn test_eval_match_with_guards() {
+    assert_eq!(run(sign_of_option(some(Expression::Number(5)))), Ok(Value::Number(1)));
+    assert_eq!(run(sign_of_option(some(Expression::Number(-5)))), Ok(Value::Number(-1)));
+    assert_eq!(run(sign_of_option(none())), Ok(Value::Number(0)));
+    let tenv = HashMap::from([("o".to_owned(), option_type())]);
+    assert_eq!(check_types(&HashMap::new(), &tenv, sign_of_option(variable("o"))), Ok(Type::Number));
+}
+
+#[test]
+fn test_eval_match_nested_patterns() {
+    // match ({pair = (Some(1), Some(2)), flag = true}) { case {pair = (Some(a), Some(b))} -> a + b, case _ -> 0 }
+    use primitives::Primitive::Add;
+    let scrutinee = Expression::Record(vec![("pair".to_owned(), Expression::Tuple(vec![some(Expression::Number(1)),
+                                                                                       some(Expression::Number(2))])),
+                                            ("flag".to_owned(), Expression::Boolean(true))]);
+    let pattern = Pattern::Record(vec![("pair".to_owned(),
+                                        Pattern::Tuple(vec![some_pattern(Pattern::Variable("a".to_owned())),
+                                                            some_pattern(Pattern::Variable("b".to_owned()))]))]);
+    let expr = matching(scrutinee, vec![arm(pattern, None, primitive(Add, vec![variable("a"), variable("b")])),
+                                        arm(Pattern::Wildcard, None, Expression::Number(0))]);
+    assert_eq!(check(expr.clone()), Ok(Type::Number));
+    assert_eq!(run(expr), Ok(Value::Number(3)));
+}
+
+#[test]
+fn test_type_checking_match_exhaustiveness() {
+    let tenv = HashMap::from([("o".to_owned(), option_type()),
+                              ("p".to_owned(), Type::Tuple(vec![Type::Boolean, Type::Boolean]))]);
+    let check_in_tenv = |expr| check_types(&HashMap::new(), &tenv, expr);
+
+    let missing_none = matching(variable("o"), vec![arm(some_pattern(Pattern::Wildcard), None, Expression::Number(0))]);
+    assert_eq!(check_in_tenv(missing_none), Err(TypeError::NonExhaustive { missing: none_pattern() }));
+
+    // a guard may fail, so a guarded arm covers nothing
+    let guarded = matching(variable("o"), vec![arm(some_pattern(Pattern::Wildcard), Some(Expression::Boolean(true)), Expression::Number(0)),
+                                               arm(none_pattern(), None, Expression::Number(0))]);
+    assert_eq!(check_in_tenv(guarded), Err(TypeError::NonExhaustive { missing: some_pattern(Pattern::Wildcard) }));
+
+    let pairs = matching(variable("p"), vec![arm(Pattern::Tuple(vec![Pattern::Boolean(true), Pattern::Wildcard]), None, Expression::Number(0)),
+                                             arm(Pattern::Tuple(vec![Pattern::Wildcard, Pattern::Boolean(true)]), None, Expression::Number(1))]);
+    let missing = Pattern::Tuple(vec![Pattern::Boolean(false), Pattern::Boolean(false)]);
+    assert_eq!(check_in_tenv(pairs), Err(TypeError::NonExhaustive { missing: missing.clone() }));
+    assert_eq!(missing.to_string(), "(false, false)");
+
+    let numbers = matching(Expression::Number(1), vec![arm(Pattern::Number(1), None, Expression::Number(0))]);
+    assert_eq!(check(numbers), Err(TypeError::NonExhaustive { missing: Pattern::Wildcard }));
+}
+
+#[test]
+fn test_type_checking_match_patterns() {
+    let mismatched = matching(Expression::Number(1), vec![arm(some_pattern(Pattern::Wildcard), None, Expression::Number(0))]);
+    assert_eq!(check(mismatched), Err(TypeError::PatternMismatch { pattern: some_pattern(Pattern::Wildcard), typ: Type::Number }));
+
+    let pair = Expression::Tuple(vec![Expression::Number(1), Expression::Number(2)]);
+    let duplicate = Pattern::Tuple(vec![Pattern::Variable("x".to_owned()), Pattern::Variable("x".to_owned())]);
+    assert_eq!(check(matching(pair, vec![arm(duplicate, None, variable("x"))])),
+               Err(TypeError::DuplicateBinding { id: "x".to_owned(), first: None, second: None }));
+
+    // the arms of an if join to a variant with both cases
+    let either = Expression::If { condition: Box::new(Expression::Boolean(true)), consequent: Box::new(some(Expression::Number(1))),
+                                  alternative: Box::new(none()) };
+    assert_eq!(check(either.clone()), Ok(option_type()));
+    assert_eq!(check(sign_of_option(either)), Ok(Type::Number));
+    assert_eq!(option_type().to_string(), "variant { Some(Number), None }");
+}
+
+#[test]
+fn test_type_checking_duplicate_binders() {
+    let duplicate = |id: &str| TypeError::DuplicateBinding { id: id.to_owned(), first: None, second: None };
+
+    let parameters = Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number },
+                                                             Binding { id: "x".to_owned(), typ: Type::Boolean }],
+                                            body: Box::new(variable("x")) };
+    assert_eq!(check(parameters).unwrap_err(), duplicate("x"));
+
+    let binders = vec![TypeBinding { id: "a".to_owned(), kind: Kind::Star }, TypeBinding { id: "a".to_owned(), kind: Kind::Star }];
+    let type_function = Expression::TypeFunction { parameters: binders.clone(), body: Box::new(Expression::Number(1)) };
+    assert_eq!(check(type_function).unwrap_err(), duplicate("a"));
+    let quantified = Type::ForAll { parameters: binders, typ: Box::new(Type::Variable("a".to_owned())) };
+    assert_eq!(check_kinds(&HashMap::new(), quantified).unwrap_err(), duplicate("a"));
+
+    let labels = Type::Record(vec![("x".to_owned(), Type::Number), ("x".to_owned(), Type::Boolean)]);
+    assert_eq!(check_kinds(&HashMap::new(), labels).unwrap_err(), duplicate("x"));
+    let tags = Type::Variant(vec![("Some".to_owned(), Type::Number), ("Some".to_owned(), Type::Boolean)]);
+    assert_eq!(check_kinds(&HashMap::new(), tags.clone()).unwrap_err(), duplicate("Some"));
+    let annotated = Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: tags }], body: Box::new(variable("x")) };
+    assert_eq!(check(annotated).unwrap_err(), duplicate("Some"));
+
+    // the fields of a record literal are located by their values
+    let fields = Expression::Record(vec![("x".to_owned(), located(1, Expression::Number(1))), ("x".to_owned(), located(2, Expression::Number(2)))]);
+    let error = check(fields).unwrap_err();
+    assert_eq!(error.to_string(), "x is bound more than once at 1:1 and 2:1");
+}
+
+#[test]
+fn test_match_binds_its_pattern_variables() {
+    let expr = matching(variable("o"), vec![arm(some_pattern(Pattern::Variable("x".to_owned())), None, variable("x")),
+                                            arm(none_pattern(), None, variable("y"))]);
+    assert_eq!(expr.free_variables(), HashSet::from(["o".to_owned(), "y".to_owned()]));
+
+    // substituting a term mentioning `x` into the match renames the pattern's `x`
+    let replacements = HashMap::from([("y".to_owned(), variable("x"))]);
+    let substituted = macros::substitute(expr, &replacements, &mut macros::FreshNames::default());
+    assert_eq!(substituted.free_variables(), HashSet::from(["o".to_owned(), "x".to_owned()]));
+    assert_eq!(run(apply(Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Number }],
+                                                body: Box::new(matching(some(Expression::Number(1)), vec![
+                                                    arm(some_pattern(Pattern::Variable("y".to_owned())), None, variable("x")),
+                                                    arm(none_pattern(), None, variable("x")),
+                                                ])) },
+                         vec![Expression::Number(7)])),
+               Ok(Value::Number(7)));
+}
+
+#[test]
+fn test_display_compound_values() {
+    let value = run(Expression::Tuple(vec![some(Expression::Number(1)), none(),
+                                           Expression::Record(vec![("x".to_owned(), Expression::Boolean(true))])])).unwrap();
+    assert_eq!(value.to_string(), "(Some(1), None, {x = true})");
+    assert_eq!(run(Expression::Tuple(vec![Expression::Number(1)])).unwrap().to_string(), "(1,)");
+    assert_eq!(run(primitive(primitives::Primitive::Equal, vec![some(Expression::Number(1)), some(Expression::Number(1))])),
+               Ok(Value::Boolean(true)));
+}
+
+#[test]
+fn test_binary_round_trip_match() {
+    let expr = sign_of_option(some(Expression::Number(5)));
+    let mut encoder = binary::Encoder::new();
+    encoder.encode(&expr);
+    let encoded = encoder.finish();
+    assert_eq!(binary::Decoder::new(&encoded, &|_| None).decode::<Expression>(), Ok(expr));
+}
+
+fn shape_type() -> Type {
+    Type::Variant(vec![("Circle".to_owned(), Type::Number), ("Square".to_owned(), Type::Number),
+                       ("Point".to_owned(), Type::Tuple(vec![]))])
+}
+
+fn case(tag: &str, payload: Pattern) -> Pattern {
+    Pattern::Variant { tag: tag.to_owned(), payload: Box::new(payload) }
+}
+
+#[test]
+fn test_or_patterns() {
+    // match s { case Circle(n) | Square(n) -> n, case Point -> 0 }
+    let size = |scrutinee| matching(scrutinee, vec![
+        arm(Pattern::Or(vec![case("Circle", Pattern::Variable("n".to_owned())), case("Square", Pattern::Variable("n".to_owned()))]),
+            None, variable("n")),
+        arm(case("Point", Pattern::Tuple(vec![])), None, Expression::Number(0)),
+    ]);
+    let tenv = HashMap::from([("s".to_owned(), shape_type())]);
+    assert_eq!(check_types(&HashMap::new(), &tenv, size(variable("s"))), Ok(Type::Number));
+    let square = Expression::Variant { tag: "Square".to_owned(), payload: Box::new(Expression::Number(4)) };
+    assert_eq!(run(size(square)), Ok(Value::Number(4)));
+
+    let pattern = Pattern::Or(vec![case("Circle", Pattern::Variable("n".to_owned())), case("Square", Pattern::Variable("n".to_owned()))]);
+    assert_eq!(pattern.to_string(), "Circle(n) | Square(n)");
+    let partial = matching(variable("s"), vec![arm(pattern, None, variable("n"))]);
+    assert_eq!(check_types(&HashMap::new(), &tenv, partial),
+               Err(TypeError::NonExhaustive { missing: case("Point", Pattern::Tuple(vec![])) }));
+}
+
+#[test]
+fn test_or_patterns_bind_consistently() {
+    let tenv = HashMap::from([("s".to_owned(), shape_type()),
+                              ("p".to_owned(), Type::Tuple(vec![Type::Number, Type::Boolean]))]);
+    let check_in_tenv = |expr| check_types(&HashMap::new(), &tenv, expr);
+
+    // Circle(n) | Point binds n on only one side
+    let one_sided = Pattern::Or(vec![case("Circle", Pattern::Variable("n".to_owned())), case("Point", Pattern::Wildcard)]);
+    assert_eq!(check_in_tenv(matching(variable("s"), vec![arm(one_sided, None, Expression::Number(0)),
+                                                          arm(Pattern::Wildcard, None, Expression::Number(0))])),
+               Err(TypeError::InconsistentAlternatives("n".to_owned())));
+
+    // (x, _) | (_, x) binds x at both Number and Boolean
+    let mixed = Pattern::Or(vec![Pattern::Tuple(vec![Pattern::Variable("x".to_owned()), Pattern::Wildcard]),
+                                 Pattern::Tuple(vec![Pattern::Wildcard, Pattern::Variable("x".to_owned())])]);
+    assert_eq!(check_in_tenv(matching(variable("p"), vec![arm(mixed, None, Expression::Number(0))])),
+               Err(TypeError::InconsistentAlternatives("x".to_owned())));
+}
+
+#[test]
+fn test_or_pattern_alternatives_do_not_leak_bindings() {
+    // match (1, 2) { case (x, 3) | (_, x) -> x }: the first alternative binds x before failing
+    let pattern = Pattern::Or(vec![Pattern::Tuple(vec![Pattern::Variable("x".to_owned()), Pattern::Number(3)]),
+                                   Pattern::Tuple(vec![Pattern::Wildcard, Pattern::Variable("x".to_owned())])]);
+    let pair = Expression::Tuple(vec![Expression::Number(1), Expression::Number(2)]);
+    let expr = matching(pair, vec![arm(pattern, None, variable("x"))]);
+    assert_eq!(check(expr.clone()), Ok(Type::Number));
+    assert_eq!(run(expr), Ok(Value::Number(2)));
+}
+
+fn number_binding(id: &str) -> Binding {
+    Binding { id: id.to_owned(), typ: Type::Number }
+}
+
+/// while i < limit do total = total + i; i = i + 1 end, then total
+fn sum_below(limit: i64) -> Expression {
+    use primitives::Primitive::{Add, Less};
+    loops::while_loop(vec![(number_binding("i"), Expression::Number(0)), (number_binding("total"), Expression::Number(0))],
+                      primitive(Less, vec![variable("i"), Expression::Number(limit)]),
+                      vec![primitive(Add, vec![variable("i"), Expression::Number(1)]),
+                           primitive(Add, vec![variable("total"), variable("i")])],
+                      variable("total"))
+}
+
+#[test]
+fn test_while_loop() {
+    assert_eq!(check(sum_below(10)), Ok(Type::Number));
+    assert_eq!(run(sum_below(10)), Ok(Value::Number(45)));
+    // iterations don't nest, so long loops don't exhaust the stack
+    assert_eq!(run(sum_below(100_000)), Ok(Value::Number(4_999_950_000)));
+}
+
+#[test]
+fn test_repeat_until_runs_its_body_first() {
+    use primitives::Primitive::{Add, Less};
+    // repeat i = i + 1 until not (i < 0), starting from 5
+    let expr = loops::repeat_until(vec![(number_binding("i"), Expression::Number(5))],
+                                   vec![primitive(Add, vec![variable("i"), Expression::Number(1)])],
+                                   Expression::If { condition: Box::new(primitive(Less, vec![variable("i"), Expression::Number(0)])),
+                                                    consequent: Box::new(Expression::Boolean(false)),
+                                                    alternative: Box::new(Expression::Boolean(true)) },
+                                   variable("i"));
+    assert_eq!(check(expr.clone()), Ok(Type::Number));
+    assert_eq!(run(expr), Ok(Value::Number(6)));
+}
+
+#[test]
+fn test_type_checking_continue() {
+    use primitives::Primitive::Add;
+    let looping = |body| Expression::Loop { variables: vec![(number_binding("i"), Expression::Number(0))], body: Box::new(body) };
+
+    assert_eq!(check(Expression::Continue(vec![])), Err(TypeError::MisplacedContinue));
+    // continue isn't in tail position when its value is used
+    assert_eq!(check(looping(primitive(Add, vec![Expression::Continue(vec![Expression::Number(1)]), Expression::Number(1)]))),
+               Err(TypeError::MisplacedContinue));
+    // nor can it escape a function to continue the loop around it
+    let escaping = Expression::Function { parameters: vec![], body: Box::new(Expression::Continue(vec![Expression::Number(1)])) };
+    assert_eq!(check(looping(escaping)), Err(TypeError::MisplacedContinue));
+    assert_eq!(check(looping(Expression::Continue(vec![Expression::Boolean(true)]))),
+               Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::Boolean }));
+    assert_eq!(check(looping(Expression::Continue(vec![]))), Err(TypeError::ArityMismatch { expected: 1, found: 0 }));
+}
+
+#[test]
+fn test_infinite_loop_runs_out_of_fuel() {
+    let expr = Expression::Loop { variables: vec![], body: Box::new(Expression::Continue(vec![])) };
+    assert_eq!(check(expr.clone()), Ok(Type::Union(vec![])));
+    assert_eq!(Interpreter::with_fuel(1000).run(expr), Err(EvalError::OutOfFuel));
+}
+
+#[test]
+fn test_binary_round_trip_loop() {
+    let expr = sum_below(10);
+    let mut encoder = binary::Encoder::new();
+    encoder.encode(&expr);
+    let encoded = encoder.finish();
+    assert_eq!(binary::Decoder::new(&encoded, &|_| None).decode::<Expression>(), Ok(expr));
+}
+
+#[test]
+fn test_numeric_for() {
+    use primitives::Primitive::Add;
+    // for i = 1, 10 do total = total + i end
+    let sum = |start, stop| loops::for_range("i", Expression::Number(start), Expression::Number(stop),
+                                             vec![(number_binding("total"), Expression::Number(0))],
+                                             vec![primitive(Add, vec![variable("total"), variable("i")])],
+                                             variable("total"));
+    assert_eq!(check(sum(1, 10)), Ok(Type::Number));
+    assert_eq!(run(sum(1, 10)), Ok(Value::Number(55)));
+    assert_eq!(run(sum(10, 1)), Ok(Value::Number(0)));
+}
+
+#[test]
+fn test_total_functions_terminate() {
+    use patterns::{Arm, Pattern};
+    use primitives::Primitive::{Add, Equal, Less, Subtract};
+    let function = |body| Expression::Function { parameters: vec![number_binding("n")], body: Box::new(body) };
+    let total = |body| Expression::Total(Box::new(function(body)));
+    let may_not_terminate = |variables: &[&str]| TypeError::MayNotTerminate {
+        variables: variables.iter().map(|&variable| variable.to_owned()).collect(),
+    };
+
+    // counting up below a bound, and a numeric for, which counts up to its bound
+    assert_eq!(check(total(sum_below(10))), Ok(Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) }));
+    let for_range = loops::for_range("i", Expression::Number(1), variable("n"), vec![(number_binding("total"), Expression::Number(0))],
+                                     vec![primitive(Add, vec![variable("total"), variable("i")])], variable("total"));
+    assert!(check(total(for_range)).is_ok());
+
+    // while i < n do i = i + 1; n = n + 1 end chases a bound that moves
+    let chasing = loops::while_loop(vec![(number_binding("i"), Expression::Number(0)), (number_binding("m"), variable("n"))],
+                                    primitive(Less, vec![variable("i"), variable("m")]),
+                                    vec![primitive(Add, vec![variable("i"), Expression::Number(1)]),
+                                         primitive(Add, vec![variable("m"), Expression::Number(1)])],
+                                    variable("i"));
+    assert_eq!(check(total(chasing.clone())), Err(may_not_terminate(&["i", "m"])));
+    assert!(check(function(chasing)).is_ok());
+
+    // counting down to zero does terminate, but nothing bounds it below
+    let countdown = |start| Expression::Loop {
+        variables: vec![(number_binding("i"), start)],
+        body: Box::new(Expression::If { condition: Box::new(primitive(Equal, vec![variable("i"), Expression::Number(0)])),
+                                        consequent: Box::new(variable("i")),
+                                        alternative: Box::new(Expression::Continue(vec![primitive(Subtract, vec![variable("i"), Expression::Number(1)])])) }),
+    };
+    assert_eq!(check(total(countdown(variable("n")))), Err(may_not_terminate(&["i"])));
+
+    // continuing with a part of a loop variable's value takes it apart
+    let unwrap = |case| Expression::Loop {
+        variables: vec![(Binding { id: "x".to_owned(), typ: Type::Union(vec![Type::Tuple(vec![Type::Number]), Type::Number]) },
+                         Expression::Tuple(vec![Expression::Number(1)]))],
+        body: Box::new(Expression::Match {
+            scrutinee: Box::new(variable("x")),
+            arms: vec![Arm { pattern: case, guard: None, body: Expression::Continue(vec![variable("y")]) },
+                       Arm { pattern: Pattern::Wildcard, guard: None, body: Expression::Number(0) }],
+        }),
+    };
+    assert_eq!(termination::check_loops(&unwrap(Pattern::Tuple(vec![Pattern::Variable("y".to_owned())]))), Ok(()));
+    assert_eq!(termination::check_loops(&unwrap(Pattern::Variable("y".to_owned()))), Err(may_not_terminate(&["x"])));
+
+    // the compile-time evaluator trusts constants that are shown to terminate with any amount of fuel
+    assert_eq!(constants::evaluate_constants(Expression::Constant(Box::new(sum_below(100))), 10), Ok(Expression::Number(4950)));
+    assert_eq!(constants::evaluate_constants(Expression::Constant(Box::new(countdown(Expression::Number(100)))), 10),
+               Err(constants::ConstantError::Eval(EvalError::OutOfFuel)));
+}
+
+/// an iterator over the numbers from `start` up to 3
+fn up_to_three(start: i64) -> Expression {
+    use primitives::Primitive::{Add, Less};
+    let next = Expression::If {
+        condition: Box::new(primitive(Less, vec![Expression::Number(3), variable("n")])),
+        consequent: Box::new(Expression::Variant { tag: "Done".to_owned(), payload: Box::new(Expression::Tuple(vec![])) }),
+        alternative: Box::new(Expression::Variant {
+            tag: "Next".to_owned(),
+            payload: Box::new(Expression::Tuple(vec![variable("n"), primitive(Add, vec![variable("n"), Expression::Number(1)])])),
+        }),
+    };
+    Expression::Record(vec![("next".to_owned(), Expression::Function { parameters: vec![number_binding("n")], body: Box::new(next) }),
+                            ("state".to_owned(), Expression::Number(start))])
+}
+
+#[test]
+fn test_generic_for() {
+    use primitives::Primitive::Add;
+    let iterable = up_to_three(1);
+    let iterable_type = check(iterable.clone()).unwrap();
+    assert_eq!(loops::iterator_item(&iterable_type), Some((Type::Number, Type::Number)));
+    assert!(is_subtype(&iterable_type, &loops::iterator_type(Type::Number, Type::Number)));
+
+    // for x in iterable do total = total + x end
+    let expr = loops::for_in("x", iterable, &iterable_type, vec![(number_binding("total"), Expression::Number(0))],
+                             vec![primitive(Add, vec![variable("total"), variable("x")])], variable("total")).unwrap();
+    assert_eq!(check(expr.clone()), Ok(Type::Number));
+    assert_eq!(run(expr), Ok(Value::Number(6)));
+
+    assert_eq!(loops::for_in("x", Expression::Number(1), &Type::Number, vec![], vec![], Expression::Tuple(vec![])),
+               Err(TypeError::NotIterable(Type::Number)));
+}
+
+#[test]
+fn test_multiple_return_values() {
+    use primitives::Primitive::{Divide, Modulo, Subtract};
+    // function divmod(a, b) return a // b, a % b end
+    let divmod = Expression::Function {
+        parameters: vec![number_binding("a"), number_binding("b")],
+        body: Box::new(multiple::returns(vec![primitive(Divide, vec![variable("a"), variable("b")]),
+                                              primitive(Modulo, vec![variable("a"), variable("b")])])),
+    };
+    let call = Expression::Application { function: Box::new(divmod), arguments: vec![Expression::Number(7), Expression::Number(2)] };
+    let call_type = check(call.clone()).unwrap();
+    assert_eq!(call_type, Type::Tuple(vec![Type::Number, Type::Number]));
+
+    // local q, r = divmod(7, 2); q - r
+    let local = |names: &[&str], body| multiple::local(names.iter().map(|name| name.to_string()).collect(),
+                                                       call.clone(), &call_type, body);
+    let both = local(&["q", "r"], primitive(Subtract, vec![variable("q"), variable("r")]));
+    assert_eq!(run(both), Ok(Value::Number(2)));
+    // extra values are dropped, and missing ones are the unit value
+    assert_eq!(run(local(&["q"], variable("q"))), Ok(Value::Number(3)));
+    let padded = local(&["q", "r", "s"], Expression::Tuple(vec![variable("q"), variable("s")]));
+    assert_eq!(check(padded.clone()), Ok(Type::Tuple(vec![Type::Number, Type::Tuple(vec![])])));
+    assert_eq!(run(padded), Ok(Value::Tuple(vec![Value::Number(3), Value::Tuple(vec![])])));
+
+    assert_eq!(multiple::returns(vec![Expression::Number(1)]), Expression::Number(1));
+    let single = multiple::local(vec!["x".to_owned()], Expression::Number(1), &Type::Number, variable("x"));
+    assert_eq!(run(single), Ok(Value::Number(1)));
+
+    // each value but the last gives one value, and the last all of its values
+    let adjusted = multiple::adjust(vec![(call.clone(), 2), (Expression::Number(5), 1), (call.clone(), 2)], 5);
+    let (number, unit) = (Value::Number, Value::Tuple(vec![]));
+    assert_eq!(run(adjusted), Ok(Value::Tuple(vec![number(3), number(5), number(3), number(1), unit])));
+}
+
+#[test]
+fn test_named_and_default_arguments() {
+    use arguments::Parameter;
+    use primitives::Primitive::Subtract;
+    // function subtract(a, b = 1) return a - b end
+    let parameters = vec![Parameter { binding: number_binding("a"), default: None },
+                          Parameter { binding: number_binding("b"), default: Some(Expression::Number(1)) }];
+    let subtract = arguments::function(&parameters, primitive(Subtract, vec![variable("a"), variable("b")])).unwrap();
+    let call = |positional: Vec<i64>, named: Vec<(&str, i64)>| {
+        arguments::call(subtract.clone(), &parameters, positional.into_iter().map(Expression::Number).collect(),
+                        named.into_iter().map(|(name, n)| (name.to_owned(), Expression::Number(n))).collect())
+    };
+
+    assert_eq!(run(call(vec![10], vec![]).unwrap()), Ok(Value::Number(9)));
+    assert_eq!(run(call(vec![10, 3], vec![]).unwrap()), Ok(Value::Number(7)));
+    let reordered = call(vec![], vec![("b", 3), ("a", 10)]).unwrap();
+    assert_eq!(check(reordered.clone()), Ok(Type::Number));
+    assert_eq!(run(reordered), Ok(Value::Number(7)));
+
+    assert_eq!(call(vec![], vec![("b", 3)]), Err(TypeError::MissingArgument("a".to_owned())));
+    assert_eq!(call(vec![10], vec![("a", 3)]), Err(TypeError::DuplicateArgument("a".to_owned())));
+    assert_eq!(call(vec![10], vec![("c", 3)]), Err(TypeError::UnknownArgument("c".to_owned())));
+    assert_eq!(call(vec![1, 2, 3], vec![]), Err(TypeError::ArityMismatch { expected: 2, found: 3 }));
+
+    let open = vec![Parameter { binding: number_binding("a"), default: Some(variable("x")) }];
+    assert_eq!(arguments::function(&open, variable("a")), Err(TypeError::NonConstantDefault("a".to_owned())));
+}
+
+/// function(first, ...) return first + the sum of ... end
+fn variadic_sum() -> Expression {
+    use primitives::Primitive::{Add, Count, Select};
+    let rest = Binding { id: "rest".to_owned(), typ: Type::Rest(Box::new(Type::Number)) };
+    let last = primitive(Count, vec![variable("rest")]);
+    let body = loops::for_range("i", Expression::Number(1), last, vec![(number_binding("total"), variable("first"))],
+                                vec![primitive(Add, vec![variable("total"), primitive(Select, vec![variable("i"), variable("rest")])])],
+                                variable("total"));
+    Expression::Function { parameters: vec![number_binding("first"), rest], body: Box::new(body) }
+}
+
+#[test]
+fn test_varargs() {
+    let sum = |arguments: Vec<i64>| Expression::Application { function: Box::new(variadic_sum()),
+                                                             arguments: arguments.into_iter().map(Expression::Number).collect() };
+    let function_type = check(variadic_sum()).unwrap();
+    assert_eq!(function_type.to_string(), "(Number, ...Number) -> Number");
+
+    assert_eq!(check(sum(vec![1, 2, 3])), Ok(Type::Number));
+    assert_eq!(run(sum(vec![1, 2, 3])), Ok(Value::Number(6)));
+    assert_eq!(run(sum(vec![1])), Ok(Value::Number(1)));
+    assert_eq!(check(sum(vec![])), Err(TypeError::ArityMismatch { expected: 1, found: 0 }));
+    let mixed = Expression::Application { function: Box::new(variadic_sum()),
+                                          arguments: vec![Expression::Number(1), Expression::Boolean(true)] };
+    assert_eq!(check(mixed), Err(TypeError::TypeMismatch { expected: Type::Rest(Box::new(Type::Number)),
+                                                           found: Type::Tuple(vec![Type::Boolean]) }));
+}
+
+#[test]
+fn test_select() {
+    use primitives::Primitive::{Count, Select};
+    let values = Expression::Tuple(vec![Expression::Number(1), Expression::Boolean(true)]);
+    assert_eq!(check(primitive(Select, vec![Expression::Number(1), values.clone()])),
+               Ok(Type::Union(vec![Type::Number, Type::Boolean])));
+    // positions count from 1, and negative ones back from the last
+    assert_eq!(run(primitive(Select, vec![Expression::Number(1), values.clone()])), Ok(Value::Number(1)));
+    assert_eq!(run(primitive(Select, vec![Expression::Number(2), values.clone()])), Ok(Value::Boolean(true)));
+    assert_eq!(run(primitive(Select, vec![Expression::Number(-1), values.clone()])), Ok(Value::Boolean(true)));
+    assert_eq!(run(primitive(Select, vec![Expression::Number(-2), values.clone()])), Ok(Value::Number(1)));
+    assert_eq!(run(primitive(Count, vec![values.clone()])), Ok(Value::Number(2)));
+    for index in [0, 3, -3] {
+        assert_eq!(run(primitive(Select, vec![Expression::Number(index), values.clone()])),
+                   Err(EvalError::IndexOutOfBounds { index, length: 2 }));
+    }
+    assert_eq!(check(primitive(Count, vec![Expression::Number(1)])), Err(TypeError::ExpectedRest { found: Type::Number }));
+}
+
+#[test]
+fn test_partial_application() {
+    use primitives::Primitive::Subtract;
+    let subtract = Expression::Function { parameters: vec![number_binding("a"), number_binding("b")],
+                                          body: Box::new(primitive(Subtract, vec![variable("a"), variable("b")])) };
+    let subtract_type = check(subtract.clone()).unwrap();
+
+    // subtract(_, 2) is a function of the first argument
+    let minus_two = arguments::partial(subtract.clone(), &subtract_type, vec![None, Some(Expression::Number(2))]).unwrap();
+    assert_eq!(check(minus_two.clone()), Ok(Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) }));
+    let applied = Expression::Application { function: Box::new(minus_two), arguments: vec![Expression::Number(10)] };
+    assert_eq!(run(applied), Ok(Value::Number(8)));
+
+    let from_ten = arguments::partial(subtract.clone(), &subtract_type, vec![Some(Expression::Number(10)), None]).unwrap();
+    let applied = Expression::Application { function: Box::new(from_ten), arguments: vec![Expression::Number(3)] };
+    assert_eq!(run(applied), Ok(Value::Number(7)));
+
+    assert_eq!(arguments::partial(subtract.clone(), &subtract_type, vec![None]), Err(TypeError::ArityMismatch { expected: 2, found: 1 }));
+    assert_eq!(arguments::partial(Expression::Number(1), &Type::Number, vec![None]),
+               Err(TypeError::ExpectedFunction { found: Type::Number }));
+}
+
+#[test]
+fn test_resolve_operator_precedence() {
+    use operators::{Associativity, Fixity, FixityError, Operators};
+    let chain = |first: i64, rest: &[(&str, i64)]| (Expression::Number(first),
+                                                     rest.iter().map(|(op, n)| (op.to_string(), Expression::Number(*n))).collect());
+    let mut operators = Operators::default();
+    let eval = |operators: &Operators, (first, rest)| operators.resolve(first, rest).map(run);
+
+    assert_eq!(eval(&operators, chain(1, &[("+", 2), ("*", 3)])), Ok(Ok(Value::Number(7))));
+    assert_eq!(eval(&operators, chain(10, &[("-", 3), ("-", 2)])), Ok(Ok(Value::Number(5))));
+    assert_eq!(eval(&operators, chain(1, &[("==", 1), ("==", 1)])),
+               Err(FixityError::NonAssociative { left: "==".to_owned(), right: "==".to_owned() }));
+    assert_eq!(eval(&operators, chain(1, &[("<+>", 2)])), Err(FixityError::UnknownOperator("<+>".to_owned())));
+    // `^` is right associative and binds more tightly than `*`
+    assert_eq!(eval(&operators, chain(3, &[("*", 2), ("^", 3), ("^", 2)])), Ok(Ok(Value::Number(1536))));
+    for (symbol, expected) in [("<", [true, false, false]), (">", [false, false, true]), ("<=", [true, true, false]),
+                               (">=", [false, true, true]), ("==", [false, true, false]), ("~=", [true, false, true])] {
+        let compared = [1, 2, 3].map(|n| eval(&operators, chain(n, &[(symbol, 2)])));
+        assert_eq!(compared, expected.map(|expected| Ok(Ok(Value::Boolean(expected)))), "{}", symbol);
+    }
+    // `and` binds more tightly than `or`, and neither evaluates its right operand unless it has to
+    let boolean = |value| Expression::Boolean(value);
+    let fails = || primitive(primitives::Primitive::Error, vec![string("evaluated")]);
+    let connect = |first, rest: &[(&str, Expression)]| operators.resolve(first, rest.iter().map(|(op, e)| (op.to_string(), e.clone())).collect()).map(run);
+    assert_eq!(connect(boolean(false), &[("and", boolean(false)), ("or", boolean(true))]), Ok(Ok(Value::Boolean(true))));
+    assert_eq!(connect(boolean(true), &[("or", fails())]), Ok(Ok(Value::Boolean(true))));
+    assert_eq!(connect(boolean(false), &[("and", fails())]), Ok(Ok(Value::Boolean(false))));
+    assert_eq!(connect(string("a"), &[("..", string("b")), ("..", string("c"))]), Ok(Ok(Value::String("abc".to_owned()))));
+
+    // operator <+> right 9 (a, b) return a - b end
+    operators.declare("<+>", Fixity { precedence: 9, associativity: Associativity::Right });
+    let (first, rest) = chain(1, &[("<+>", 2), ("<+>", 3), ("*", 2)]);
+    let resolved = operators.resolve(first, rest).unwrap();
+    let declaration = Expression::Function {
+        parameters: vec![Binding { id: "<+>".to_owned(),
+                                   typ: Type::Function { arguments: vec![Type::Number, Type::Number], result: Box::new(Type::Number) } }],
+        body: Box::new(resolved),
+    };
+    let subtract = Expression::Function { parameters: vec![number_binding("a"), number_binding("b")],
+                                          body: Box::new(primitive(primitives::Primitive::Subtract, vec![variable("a"), variable("b")])) };
+    // 1 <+> (2 <+> (3 * 2))
+    assert_eq!(run(Expression::Application { function: Box::new(declaration), arguments: vec![subtract] }), Ok(Value::Number(5)));
+    assert_eq!(eval(&operators, chain(1, &[("<+>", 2), ("+", 3)])),
+               Err(FixityError::NonAssociative { left: "<+>".to_owned(), right: "+".to_owned() }));
+}
+
+#[test]
+fn test_block_expressions() {
+    use blocks::Statement;
+    use primitives::Primitive::{Add, Multiply};
+    // do local x = 2; local (y, z) = (x + 1, x * 5); x + y + z end
+    let expr = blocks::block(vec![Statement::Local(Pattern::Variable("x".to_owned()), Expression::Number(2)),
+                                  Statement::Local(Pattern::Tuple(vec![Pattern::Variable("y".to_owned()), Pattern::Variable("z".to_owned())]),
+                                                   Expression::Tuple(vec![primitive(Add, vec![variable("x"), Expression::Number(1)]),
+                                                                          primitive(Multiply, vec![variable("x"), Expression::Number(5)])])),
+                                  Statement::Expression(Expression::Boolean(true))],
+                             primitive(Add, vec![primitive(Add, vec![variable("x"), variable("y")]), variable("z")]));
+    assert_eq!(check(expr.clone()), Ok(Type::Number));
+    assert_eq!(run(expr), Ok(Value::Number(15)));
+
+    // a local can't refute its value
+    let refutable = blocks::block(vec![Statement::Local(Pattern::Number(1), Expression::Number(2))], Expression::Number(0));
+    assert!(matches!(check(refutable), Err(TypeError::NonExhaustive { .. })));
+}
+
+/// function(x: Number): Number if x < 0 then return 0 - x end; x end
+fn absolute() -> Expression {
+    use blocks::Statement;
+    use primitives::Primitive::{Less, Subtract};
+    let negative = Expression::If {
+        condition: Box::new(primitive(Less, vec![variable("x"), Expression::Number(0)])),
+        consequent: Box::new(Expression::Return(Box::new(primitive(Subtract, vec![Expression::Number(0), variable("x")])))),
+        alternative: Box::new(Expression::Tuple(vec![])),
+    };
+    Expression::Function {
+        parameters: vec![number_binding("x")],
+        body: Box::new(Expression::Returning { result: Some(Type::Number),
+                                               body: Box::new(blocks::block(vec![Statement::Expression(negative)], variable("x"))) }),
+    }
+}
+
+#[test]
+fn test_early_return() {
+    let call = |n| Expression::Application { function: Box::new(absolute()), arguments: vec![Expression::Number(n)] };
+    assert_eq!(check(absolute()), Ok(Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) }));
+    assert_eq!(run(call(-3)), Ok(Value::Number(3)));
+    assert_eq!(run(call(4)), Ok(Value::Number(4)));
+
+    // returning out of a loop
+    let find = Expression::Returning {
+        result: Some(Type::Number),
+        body: Box::new(loops::while_loop(vec![(number_binding("i"), Expression::Number(0))], Expression::Boolean(true),
+                                         vec![Expression::Return(Box::new(variable("i")))], Expression::Number(-1))),
+    };
+    assert_eq!(run(find), Ok(Value::Number(0)));
+}
+
+#[test]
+fn test_type_checking_return() {
+    let returning = |body| Expression::Returning { result: Some(Type::Number), body: Box::new(body) };
+    assert_eq!(check(Expression::Return(Box::new(Expression::Number(1)))), Err(TypeError::MisplacedReturn));
+    assert_eq!(check(returning(Expression::Return(Box::new(Expression::Boolean(true))))),
+               Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::Boolean }));
+    assert_eq!(check(returning(Expression::Boolean(true))),
+               Err(TypeError::TypeMismatch { expected: Type::Number, found: Type::Boolean }));
+    // a return can't leave the function it's in for the return point around it
+    let inner = Expression::Function { parameters: vec![], body: Box::new(Expression::Return(Box::new(Expression::Number(1)))) };
+    assert_eq!(check(returning(Expression::Application { function: Box::new(inner), arguments: vec![] })),
+               Err(TypeError::MisplacedReturn));
+}
+
+/// forall<T>. fn (x: T) -> x
+fn polymorphic_identity() -> Expression {
+    Expression::TypeFunction {
+        parameters: vec![TypeBinding { id: "T".to_owned(), kind: Kind::Star }],
+        body: Box::new(Expression::Function { parameters: vec![Binding { id: "x".to_owned(), typ: Type::Variable("T".to_owned()) }],
+                                              body: Box::new(variable("x")) }),
+    }
+}
+
+#[test]
+fn test_type_application() {
+    let t = || Type::Variable("T".to_owned());
+    assert_eq!(check(polymorphic_identity()),
+               Ok(Type::ForAll { parameters: vec![TypeBinding { id: "T".to_owned(), kind: Kind::Star }],
+                                 typ: Box::new(Type::Function { arguments: vec![t()], result: Box::new(t()) }) }));
+
+    let instantiate = |arguments| Expression::TypeApplication { function: Box::new(polymorphic_identity()), arguments };
+    let call = Expression::Application { function: Box::new(instantiate(vec![Type::Number])), arguments: vec![Expression::Number(5)] };
+    assert_eq!(check(call.clone()), Ok(Type::Number));
+    assert_eq!(run(call), Ok(Value::Number(5)));
+    let mut encoder = binary::Encoder::new();
+    encoder.encode(&instantiate(vec![Type::Boolean]));
+    let encoded = encoder.finish();
+    assert_eq!(binary::Decoder::new(&encoded, &|_| None).decode::<Expression>(), Ok(instantiate(vec![Type::Boolean])));
+
+    let call = Expression::Application { function: Box::new(instantiate(vec![Type::Boolean])), arguments: vec![Expression::Number(5)] };
+    assert_eq!(check(call), Err(TypeError::TypeMismatch { expected: Type::Boolean, found: Type::Number }));
+    assert_eq!(check(instantiate(vec![])), Err(TypeError::ArityMismatch { expected: 1, found: 0 }));
+    let constructor = Type::ForAll { parameters: vec![TypeBinding { id: "a".to_owned(), kind: Kind::Star }], typ: Box::new(Type::Number) };
+    assert!(matches!(check(instantiate(vec![constructor])), Err(TypeError::KindMismatch { .. })));
+    assert_eq!(check(Expression::TypeApplication { function: Box::new(Expression::Number(1)), arguments: vec![Type::Number] }),
+               Err(TypeError::ExpectedQuantifier { found: Type::Number }));
+}
+
+#[test]
+fn test_type_substitution_avoids_capture() {
+    // (forall<b>. (a, b) -> a)[a := b] renames the quantified b out of the way
+    let variable = |id: &str| Type::Variable(id.to_owned());
+    let typ = Type::ForAll { parameters: vec![TypeBinding { id: "b".to_owned(), kind: Kind::Star }],
+                             typ: Box::new(Type::Function { arguments: vec![variable("a"), variable("b")], result: Box::new(variable("a")) }) };
+    let substituted = typ.substitute(&HashMap::from([("a".to_owned(), variable("b"))]));
+    assert_eq!(substituted.to_string(), "forall<b'>. (b, b') -> b");
+    assert_eq!(substituted.free_variables(), HashSet::from(["b".to_owned()]));
+}
+
+#[test]
+fn test_deep_terms_clone_and_drop() {
+    // far deeper than the stack would allow recursing once for each level
+    const DEPTH: usize = 1_000_000;
+    let mut expr = Expression::Number(0);
+    let mut typ = Type::Number;
+    for _ in 0..DEPTH {
+        expr = Expression::Function { parameters: vec![], body: Box::new(Expression::Tuple(vec![expr, Expression::Boolean(true)])) };
+        typ = Type::Function { arguments: vec![Type::Rest(Box::new(typ))], result: Box::new(Type::String) };
+    }
+
+    let copy = expr.clone();
+    let mut depth = 0;
+    let mut node = &copy;
+    while let Expression::Function { body, .. } = node {
+        let Expression::Tuple(elements) = &**body else { panic!("expected a tuple, found {:?}", body) };
+        assert_eq!(elements[1], Expression::Boolean(true));
+        node = &elements[0];
+        depth += 1;
+    }
+    assert_eq!((depth, node), (DEPTH, &Expression::Number(0)));
+
+    let copy = typ.clone();
+    let mut depth = 0;
+    let mut node = &copy;
+    while let Type::Function { arguments, result } = node {
+        assert_eq!(**result, Type::String);
+        let [Type::Rest(element)] = &arguments[..] else { panic!("expected a rest, found {:?}", arguments) };
+        node = element;
+        depth += 1;
+    }
+    assert_eq!((depth, node), (DEPTH, &Type::Number));
+}
+
+/// a random closed term of type `typ`, at most `depth` deep, that may refer to the variables in
+/// `scope`, which is left as it was
+fn generate(random: &mut crate::engine::random::Random, typ: &Type, scope: &mut Vec<(Identifier, Type)>, depth: usize) -> Expression {
+    use primitives::Primitive::*;
+    let number = |random: &mut crate::engine::random::Random| Expression::Number(random.range(-3, 3));
+    let choice = if depth == 0 { 0 } else { random.range(0, 6) };
+    let candidates = scope.iter().filter(|(_, bound)| bound == typ).map(|(id, _)| id.clone()).collect::<Vec<_>>();
+    match choice {
+        1 if !candidates.is_empty() => variable(&candidates[random.range(0, candidates.len() as i64 - 1) as usize]),
+        2 => Expression::If { condition: Box::new(generate(random, &Type::Boolean, scope, depth - 1)),
+                              consequent: Box::new(generate(random, typ, scope, depth - 1)),
+                              alternative: Box::new(generate(random, typ, scope, depth - 1)) },
+        3 => {
+            let argument = generated_type(random, 1);
+            let function = Type::Function { arguments: vec![argument.clone()], result: Box::new(typ.clone()) };
+            apply(generate(random, &function, scope, depth - 1), vec![generate(random, &argument, scope, depth - 1)])
+        }
+        4 => {
+            let bound = generated_type(random, 1);
+            let value = generate(random, &bound, scope, depth - 1);
+            let id = format!("v{}", scope.len());
+            scope.push((id.clone(), bound));
+            let body = generate(random, typ, scope, depth - 1);
+            scope.pop();
+            blocks::let_in(patterns::Pattern::Variable(id), value, body)
+        }
+        5 => {
+            let position = Position { line: depth, column: 1 };
+            Expression::Located { span: Span { start: position, end: position }, expression: Box::new(generate(random, typ, scope, depth - 1)) }
+        }
+        _ => match typ {
+            Type::Number if depth > 0 && random.range(0, 1) == 0 => {
+                let operator = [Add, Subtract, Multiply, Divide, Modulo][random.range(0, 4) as usize];
+                primitive(operator, vec![generate(random, typ, scope, depth - 1), generate(random, typ, scope, depth - 1)])
+            }
+            Type::Number => number(random),
+            Type::Boolean if depth > 0 && random.range(0, 1) == 0 => {
+                let operator = [Equal, Less][random.range(0, 1) as usize];
+                primitive(operator, vec![generate(random, &Type::Number, scope, depth - 1), generate(random, &Type::Number, scope, depth - 1)])
+            }
+            Type::Boolean => Expression::Boolean(random.range(0, 1) == 0),
+            Type::String if depth > 0 && random.range(0, 1) == 0 => primitive(ToString, vec![generate(random, &Type::Number, scope, depth - 1)]),
+            Type::String => string(["", "a", "sanguinello"][random.range(0, 2) as usize]),
+            Type::Tuple(types) => Expression::Tuple(types.iter().map(|typ| generate(random, typ, scope, depth.saturating_sub(1))).collect()),
+            Type::Function { arguments, result } => {
+                let parameters = arguments.iter()
+                                          .enumerate()
+                                          .map(|(i, typ)| Binding { id: format!("p{}_{}", scope.len(), i), typ: typ.clone() })
+                                          .collect::<Vec<_>>();
+                let depth_before = scope.len();
+                scope.extend(parameters.iter().map(|Binding { id, typ }| (id.clone(), typ.clone())));
+                let body = generate(random, result, scope, depth.saturating_sub(1));
+                scope.truncate(depth_before);
+                Expression::Function { parameters, body: Box::new(body) }
+            }
+            _ => unreachable!("{} isn't a type terms are generated at", typ),
+        },
+    }
+}
+
+/// a random type of numbers, booleans, strings, tuples, and functions, at most `depth` deep
+fn generated_type(random: &mut crate::engine::random::Random, depth: usize) -> Type {
+    match if depth == 0 { random.range(0, 2) } else { random.range(0, 4) } {
+        0 => Type::Number,
+        1 => Type::Boolean,
+        2 => Type::String,
+        3 => Type::Tuple(vec![generated_type(random, depth - 1), generated_type(random, depth - 1)]),
+        _ => Type::Function { arguments: vec![generated_type(random, depth - 1)], result: Box::new(generated_type(random, depth - 1)) },
+    }
+}
+
+fn has_functions(typ: &Type) -> bool {
+    match typ {
+        Type::Function { .. } => true,
+        Type::Tuple(types) => types.iter().any(has_functions),
+        _ => false,
+    }
+}
+
+#[test]
+fn test_progress_and_preservation() {
+    use step::Step;
+    let mut random = crate::engine::random::Random::new(952);
+    for _ in 0..500 {
+        let typ = generated_type(&mut random, 2);
+        let program = generate(&mut random, &typ, &mut vec![], 5);
+        let mut expr = program.clone();
+        let mut steps = 0;
+        let result = loop {
+            // preservation: every step stays at the type of the program, or one more precise
+            match check(expr.clone()) {
+                Ok(found) => assert!(is_subtype(&found, &typ), "{:?} has type {}, not {}", expr, found, typ),
+                Err(error) => panic!("{:?} stepped to {:?}, which is ill-typed: {}", program, expr, error),
+            }
+            // progress: a well-typed term is a value or steps, unless it fails in a way types don't rule out
+            match step::step(expr) {
+                Ok(Step::Value(value)) => break Ok(value),
+                Ok(Step::Next(next)) => expr = next,
+                Err(error @ (EvalError::DivisionByZero | EvalError::Overflow { .. })) => break Err(error),
+                Err(error) => panic!("{:?} got stuck: {}", program, error),
+            }
+            steps += 1;
+            assert!(steps < 10_000, "{:?} took too many steps", program);
+        };
+        // and the two semantics agree, wherever values can be compared
+        if !has_functions(&typ) {
+            assert_eq!(run(program.clone()).map(Value::reify), result, "{:?}", program);
+        }
+    }
+}
+
+#[test]
+fn test_step_redex_is_highlighted() {
+    use primitives::Primitive::Add;
+    // id(1 + 2) + 4 steps the addition in the argument, then the call, then the outer addition
+    let mut expr = primitive(Add, vec![apply(identity("x"), vec![primitive(Add, vec![Expression::Number(1), Expression::Number(2)])]),
+                                       Expression::Number(4)]);
+    let mut traced = vec![];
+    while let Some(redex) = step::redex(&expr) {
+        traced.push(dump::dump_redex(&expr, redex));
+        let Ok(step::Step::Next(next)) = step::step(expr) else { panic!("a term with a redex didn't step") };
+        expr = next;
+    }
+    assert_eq!(traced, ["+((fn (x: Number) ->\n    x)(⟦+(1, 2)⟧), 4)\n",
+                        "+(⟦(fn (x: Number) ->\n    x)(3)⟧, 4)\n",
+                        "⟦+(3, 4)⟧\n"]);
+    assert_eq!(expr, Expression::Number(7));
+}
+
+#[test]
+fn test_resolve_gives_each_binder_its_own_name() {
+    // match 1 { case x -> (x, (fn(x: Number) -> x)(2), match (3, 4) { case (x, 5) | (_, x) -> x }) }
+    let alternatives = |x: &str| Pattern::Or(vec![Pattern::Tuple(vec![Pattern::Variable(x.to_owned()), Pattern::Number(5)]),
+                                                  Pattern::Tuple(vec![Pattern::Wildcard, Pattern::Variable(x.to_owned())])]);
+    let program = |x: [&str; 3]| matching(Expression::Number(1), vec![arm(Pattern::Variable(x[0].to_owned()), None, Expression::Tuple(vec![
+        variable(x[0]),
+        apply(identity(x[1]), vec![Expression::Number(2)]),
+        matching(Expression::Tuple(vec![Expression::Number(3), Expression::Number(4)]), vec![arm(alternatives(x[2]), None, variable(x[2]))]),
+    ]))]);
+    let resolved = resolve::resolve(program(["x"; 3]), &Declarations::new()).unwrap();
+    assert_eq!(resolved, program(["x%1", "x%2", "x%3"]));
+    assert_eq!(run(resolved), run(program(["x"; 3])));
+
+    // forall<T>. fn(x: T) -> x, whose type variable is renamed too
+    let polymorphic = |t: &str, x: &str| Expression::TypeFunction {
+        parameters: vec![TypeBinding { id: t.to_owned(), kind: Kind::Star }],
+        body: Box::new(Expression::Function { parameters: vec![Binding { id: x.to_owned(), typ: Type::Variable(t.to_owned()) }],
+                                              body: Box::new(variable(x)) }),
+    };
+    assert_eq!(resolve::resolve(polymorphic("T", "x"), &Declarations::new()), Ok(polymorphic("T%1", "x%2")));
+
+    // declared names are left as they are, and anything else is reported where it is
+    let declarations = Declarations::from([("m.g".to_owned(), Type::Number)]);
+    assert_eq!(resolve::resolve(variable("m.g"), &declarations), Ok(variable("m.g")));
+    let unbound = resolve::resolve(apply(identity("x"), vec![located(3, variable("y"))]), &declarations).unwrap_err();
+    assert_eq!(unbound.to_string(), "unbound identifier: y at 3:1");
+}
+
+fn update(record: Expression, fields: Vec<(&str, Expression)>) -> Expression {
+    Expression::Update { record: Box::new(record), fields: fields.into_iter().map(|(field, value)| (field.to_owned(), value)).collect() }
+}
+
+#[test]
+fn test_record_update_replaces_and_adds_fields() {
+    // {{x = 1, y = true} with y = "y", z = 3}, whose y changes type
+    let point = Expression::Record(vec![("x".to_owned(), Expression::Number(1)), ("y".to_owned(), Expression::Boolean(true))]);
+    let expr = update(point, vec![("y", Expression::String("y".to_owned())), ("z", Expression::Number(3))]);
+    fn fields<T>(x: T, y: T, z: T) -> Vec<(Identifier, T)> {
+        vec![("x".to_owned(), x), ("y".to_owned(), y), ("z".to_owned(), z)]
+    }
+    assert_eq!(check(expr.clone()), Ok(Type::Record(fields(Type::Number, Type::String, Type::Number))));
+    let expected = Value::Record(Arc::new(fields(Value::Number(1), Value::String("y".to_owned()), Value::Number(3))));
+    assert_eq!(run(expr.clone()), Ok(expected.clone()));
+
+    let mut stepped = expr;
+    while let Ok(step::Step::Next(next)) = step::step(stepped.clone()) {
+        stepped = next;
+    }
+    assert_eq!(run(stepped), Ok(expected));
+
+    assert_eq!(check(update(Expression::Number(1), vec![("x", Expression::Number(2))])),
+               Err(TypeError::ExpectedRecord { found: Type::Number }));
+}
+
+#[test]
+fn test_record_update_copies_only_shared_fields() {
+    let point = || Expression::Record(vec![("x".to_owned(), Expression::Number(1))]);
+    // nothing else has the record, so its fields are updated where they are
+    let mut interpreter = Interpreter::with_fuel(1000);
+    assert!(interpreter.run(update(point(), vec![("x", Expression::Number(2))])).is_ok());
+    assert_eq!(interpreter.allocated, 1);
+
+    // let r = {x = 1} in (r, {r with x = 2}), where r is still there to be copied
+    let shared = matching(point(), vec![arm(Pattern::Variable("r".to_owned()), None, Expression::Tuple(vec![
+        variable("r"),
+        update(variable("r"), vec![("x", Expression::Number(2))]),
+    ]))]);
+    let mut interpreter = Interpreter::with_fuel(1000);
+    let record = |x| Value::Record(Arc::new(vec![("x".to_owned(), Value::Number(x))]));
+    assert_eq!(interpreter.run(shared), Ok(Value::Tuple(vec![record(1), record(2)])));
+    // the record, r, its copy, and the tuple
+    assert_eq!(interpreter.allocated, 4);
+}
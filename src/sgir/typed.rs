@@ -0,0 +1,582 @@
+use core::mem;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+use super::constants;
+use super::numbers::Float;
+use super::patterns::{self, Arm, Pattern};
+use super::primitives::{self, Primitive};
+use super::{check_application, continues_in_tail_position, distinct, expect_value_type, is_subtype, join, narrow, termination, update_fields};
+use super::{Binding, Declarations, Expansion, Expression, Identifier, KindChecker, KindEnv, Provenance, Span, Type,
+            TypeBinding, TypeEnv, TypeError, TypeTag, LOOP_VARIABLES, RETURN, RETURNED, TC};
+
+/// where a variable was bound: by the host, in the declarations the program was checked with,
+/// or at a binding in the program, each of which has an id of its own, so that shadowed names
+/// can be told apart without tracking scopes again
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Binder {
+    Declared,
+    Bound(usize),
+}
+
+/// a checked expression, with its type
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypedExpression {
+    pub typ: Type,
+    pub node: Node,
+}
+
+/// an arm of a checked `match`, with the variables its pattern binds, in order of name
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypedArm {
+    pub pattern: Pattern,
+    pub bindings: Vec<(usize, Binding)>,
+    pub guard: Option<TypedExpression>,
+    pub body: TypedExpression,
+}
+
+/// the expressions of `Expression`, after checking: each binding has the id of its binder, and
+/// each variable refers to the binder it resolved to
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Variable {
+        id: Identifier,
+        binder: Binder,
+    },
+    Boolean(bool),
+    Number(i64),
+    Float(f64),
+    String(String),
+    Char(char),
+    Bytes(Vec<u8>),
+    Function {
+        parameters: Vec<(usize, Binding)>,
+        body: Box<TypedExpression>,
+    },
+    Application {
+        function: Box<TypedExpression>,
+        arguments: Vec<TypedExpression>,
+    },
+    If {
+        condition: Box<TypedExpression>,
+        consequent: Box<TypedExpression>,
+        alternative: Box<TypedExpression>,
+    },
+    TypeTest {
+        expression: Box<TypedExpression>,
+        tag: TypeTag,
+    },
+    IfTarget {
+        target: String,
+        consequent: Box<TypedExpression>,
+        alternative: Box<TypedExpression>,
+    },
+    Constant(Box<TypedExpression>),
+    Total(Box<TypedExpression>),
+    Located {
+        span: Span,
+        expression: Box<TypedExpression>,
+    },
+    Expanded {
+        construct: String,
+        span: Span,
+        expression: Box<TypedExpression>,
+    },
+    Primitive {
+        operator: Primitive,
+        arguments: Vec<TypedExpression>,
+    },
+    Tuple(Vec<TypedExpression>),
+    Record(Vec<(Identifier, TypedExpression)>),
+    Update {
+        record: Box<TypedExpression>,
+        fields: Vec<(Identifier, TypedExpression)>,
+    },
+    Variant {
+        tag: Identifier,
+        payload: Box<TypedExpression>,
+    },
+    Match {
+        scrutinee: Box<TypedExpression>,
+        arms: Vec<TypedArm>,
+    },
+    Loop {
+        variables: Vec<(usize, Binding, TypedExpression)>,
+        body: Box<TypedExpression>,
+    },
+    Continue(Vec<TypedExpression>),
+    Returning {
+        result: Type,
+        body: Box<TypedExpression>,
+    },
+    Return(Box<TypedExpression>),
+    TypeFunction {
+        parameters: Vec<TypeBinding>,
+        body: Box<TypedExpression>,
+    },
+    TypeApplication {
+        function: Box<TypedExpression>,
+        arguments: Vec<Type>,
+    },
+}
+
+impl TypedExpression {
+    /// the expression this was checked from
+    pub fn erase(self) -> Expression {
+        let erase = |expression: Box<TypedExpression>| Box::new(expression.erase());
+        let erase_all = |expressions: Vec<TypedExpression>| expressions.into_iter().map(TypedExpression::erase).collect();
+        match self.node {
+            Node::Variable { id, .. } => Expression::Variable(id),
+            Node::Boolean(value) => Expression::Boolean(value),
+            Node::Number(value) => Expression::Number(value),
+            Node::Float(value) => Expression::Float(Float(value)),
+            Node::String(value) => Expression::String(value),
+            Node::Char(value) => Expression::Char(value),
+            Node::Bytes(value) => Expression::Bytes(value),
+            Node::Function { parameters, body } => Expression::Function {
+                parameters: parameters.into_iter().map(|(_, binding)| binding).collect(),
+                body: erase(body),
+            },
+            Node::Application { function, arguments } => Expression::Application { function: erase(function), arguments: erase_all(arguments) },
+            Node::If { condition, consequent, alternative } => Expression::If {
+                condition: erase(condition),
+                consequent: erase(consequent),
+                alternative: erase(alternative),
+            },
+            Node::TypeTest { expression, tag } => Expression::TypeTest { expression: erase(expression), tag },
+            Node::IfTarget { target, consequent, alternative } => Expression::IfTarget {
+                target,
+                consequent: erase(consequent),
+                alternative: erase(alternative),
+            },
+            Node::Constant(expression) => Expression::Constant(erase(expression)),
+            Node::Total(expression) => Expression::Total(erase(expression)),
+            Node::Located { span, expression } => Expression::Located { span, expression: erase(expression) },
+            Node::Expanded { construct, span, expression } => Expression::Expanded { construct, span, expression: erase(expression) },
+            Node::Primitive { operator, arguments } => Expression::Primitive { operator, arguments: erase_all(arguments) },
+            Node::Tuple(elements) => Expression::Tuple(erase_all(elements)),
+            Node::Record(fields) => Expression::Record(fields.into_iter().map(|(field, value)| (field, value.erase())).collect()),
+            Node::Update { record, fields } => Expression::Update {
+                record: erase(record),
+                fields: fields.into_iter().map(|(field, value)| (field, value.erase())).collect(),
+            },
+            Node::Variant { tag, payload } => Expression::Variant { tag, payload: erase(payload) },
+            Node::Match { scrutinee, arms } => Expression::Match {
+                scrutinee: erase(scrutinee),
+                arms: arms.into_iter()
+                          .map(|TypedArm { pattern, guard, body, .. }| Arm { pattern, guard: guard.map(TypedExpression::erase), body: body.erase() })
+                          .collect(),
+            },
+            Node::Loop { variables, body } => Expression::Loop {
+                variables: variables.into_iter().map(|(_, binding, init)| (binding, init.erase())).collect(),
+                body: erase(body),
+            },
+            Node::Continue(arguments) => Expression::Continue(erase_all(arguments)),
+            Node::Returning { result, body } => Expression::Returning { result: Some(result), body: erase(body) },
+            Node::Return(value) => Expression::Return(erase(value)),
+            Node::TypeFunction { parameters, body } => Expression::TypeFunction { parameters, body: erase(body) },
+            Node::TypeApplication { function, arguments } => Expression::TypeApplication { function: erase(function), arguments },
+        }
+    }
+}
+
+/// the names in scope, with their types and the binders they resolve to
+type Scope = HashMap<Identifier, (Type, Binder)>;
+
+/// the checker, which numbers binders in the order it meets them
+#[derive(Default)]
+struct Elaborator {
+    binders: usize,
+    /// the kinds of the annotations checked so far, so that a type parameter of unknown kind is
+    /// used at the same kind throughout its type function
+    kinds: KindChecker,
+    /// whether the error being unwound has passed a `Located` since it was found, after which the
+    /// expansions it passes were of the code around it, not of its own
+    sealed: bool,
+    /// the join of the types returned so far to each return point being checked whose result
+    /// type is inferred, innermost last
+    returned: Vec<Type>,
+}
+
+impl Elaborator {
+    /// bind `id` at `typ` in `scope`, producing the id of its binder
+    fn bind(&mut self, scope: &mut Scope, id: Identifier, typ: Type) -> usize {
+        let binder = self.binders;
+        self.binders += 1;
+        scope.insert(id, (typ, Binder::Bound(binder)));
+        binder
+    }
+
+    fn expect(found: &TypedExpression, expected: &Type) -> TC<()> {
+        if is_subtype(&found.typ, expected) {
+            Ok(())
+        } else {
+            Err(TypeError::TypeMismatch { expected: expected.clone(), found: found.typ.clone() })
+        }
+    }
+
+    fn all(&mut self, kenv: &KindEnv, scope: &Scope, expressions: Vec<Expression>) -> TC<Vec<TypedExpression>> {
+        expressions.into_iter().map(|expr| self.elaborate(kenv, scope, expr)).collect()
+    }
+
+    fn elaborate(&mut self, kenv: &KindEnv, scope: &Scope, mut expr: Expression) -> TC<TypedExpression> {
+        let typed = |typ, node| Ok(TypedExpression { typ, node });
+        match &mut expr {
+            Expression::Variable(id) => match scope.get(id.as_str()) {
+                Some((typ, binder)) => typed(typ.clone(), Node::Variable { binder: *binder, id: mem::take(id) }),
+                None => Err(TypeError::UnboundIdentifier(mem::take(id))),
+            }
+
+            Expression::Boolean(value) => typed(Type::Boolean, Node::Boolean(*value)),
+            Expression::Number(value) => typed(Type::Number, Node::Number(*value)),
+            Expression::Float(Float(value)) => typed(Type::Float, Node::Float(*value)),
+            Expression::String(value) => typed(Type::String, Node::String(mem::take(value))),
+            Expression::Char(value) => typed(Type::Char, Node::Char(*value)),
+            Expression::Bytes(value) => typed(Type::Bytes, Node::Bytes(mem::take(value))),
+
+            Expression::Tuple(elements) => {
+                let elements = self.all(kenv, scope, mem::take(elements))?;
+                typed(Type::Tuple(elements.iter().map(|element| element.typ.clone()).collect()), Node::Tuple(elements))
+            }
+            Expression::Record(fields) => {
+                distinct(fields.iter().map(|(field, value)| (field, value.span())))?;
+                let fields = mem::take(fields).into_iter()
+                                              .map(|(field, expr)| Ok((field, self.elaborate(kenv, scope, expr)?)))
+                                              .collect::<TC<Vec<_>>>()?;
+                typed(Type::Record(fields.iter().map(|(field, value)| (field.clone(), value.typ.clone())).collect()), Node::Record(fields))
+            }
+            // a field that's replaced can change type, since the record it's copied from is kept
+            Expression::Update { record, fields } => {
+                let record = self.elaborate(kenv, scope, record.take())?;
+                let mut typ = match &record.typ {
+                    Type::Record(types) => types.clone(),
+                    found => return Err(TypeError::ExpectedRecord { found: found.clone() }),
+                };
+                let fields = mem::take(fields).into_iter()
+                                              .map(|(field, expr)| Ok((field, self.elaborate(kenv, scope, expr)?)))
+                                              .collect::<TC<Vec<_>>>()?;
+                update_fields(&mut typ, fields.iter().map(|(field, value)| (field.clone(), value.typ.clone())).collect());
+                typed(Type::Record(typ), Node::Update { record: Box::new(record), fields })
+            }
+            Expression::Variant { tag, payload } => {
+                let payload = self.elaborate(kenv, scope, payload.take())?;
+                typed(Type::Variant(vec![(tag.clone(), payload.typ.clone())]), Node::Variant { tag: mem::take(tag), payload: Box::new(payload) })
+            }
+
+            Expression::Match { scrutinee, arms } => {
+                let variable = match stripped(scrutinee) {
+                    Expression::Variable(id) => scope.get(id).map(|(_, binder)| (id.clone(), *binder)),
+                    _ => None,
+                };
+                let scrutinee = self.elaborate(kenv, scope, scrutinee.take())?;
+                let arms = mem::take(arms);
+                let mut bound = vec![];
+                for Arm { pattern, .. } in &arms {
+                    let mut bindings = TypeEnv::new();
+                    patterns::check_pattern(pattern, &scrutinee.typ, &mut bindings)?;
+                    bound.push(bindings);
+                }
+                patterns::check_exhaustive(&scrutinee.typ, &arms)?;
+
+                // a match with no arms can only be on a type with no values, so it never produces one
+                let mut result = Type::Union(vec![]);
+                let mut typed_arms = vec![];
+                for (Arm { pattern, guard, body }, bindings) in arms.into_iter().zip(bound) {
+                    let mut extended_scope = scope.clone();
+                    // a variable matched against the cases of a variant is only one of them in the arm
+                    if let (Some((id, binder)), Type::Variant(cases)) = (&variable, &scrutinee.typ) {
+                        let tags = case_tags(&pattern);
+                        if !tags.is_empty() {
+                            let cases = cases.iter().filter(|(tag, _)| tags.contains(&tag)).cloned().collect();
+                            extended_scope.insert(id.clone(), (Type::Variant(cases), *binder));
+                        }
+                    }
+                    let mut bindings: Vec<_> = bindings.into_iter().collect();
+                    bindings.sort_by(|(left, _), (right, _)| left.cmp(right));
+                    let bindings = bindings.into_iter()
+                                           .map(|(id, typ)| (self.bind(&mut extended_scope, id.clone(), typ.clone()), Binding { id, typ }))
+                                           .collect();
+                    let guard = match guard {
+                        Some(guard) => {
+                            let guard = self.elaborate(kenv, &extended_scope, guard)?;
+                            Self::expect(&guard, &Type::Boolean)?;
+                            Some(guard)
+                        }
+                        None => None,
+                    };
+                    let body = self.elaborate(kenv, &extended_scope, body)?;
+                    result = join(result, body.typ.clone());
+                    typed_arms.push(TypedArm { pattern, bindings, guard, body });
+                }
+                typed(result, Node::Match { scrutinee: Box::new(scrutinee), arms: typed_arms })
+            }
+
+            Expression::Loop { variables, body } => {
+                if !continues_in_tail_position(body, true) {
+                    return Err(TypeError::MisplacedContinue)
+                }
+                let mut extended_scope = scope.clone();
+                let mut types = vec![];
+                let mut typed_variables = vec![];
+                for (Binding { id, typ }, init) in mem::take(variables) {
+                    self.kinds.expect_star(kenv, typ.clone())?;
+                    let init = self.elaborate(kenv, scope, init)?;
+                    Self::expect(&init, &typ)?;
+                    let binder = self.bind(&mut extended_scope, id.clone(), typ.clone());
+                    types.push(typ.clone());
+                    typed_variables.push((binder, Binding { id, typ }, init));
+                }
+                extended_scope.insert(LOOP_VARIABLES.to_owned(), (Type::Tuple(types), Binder::Declared));
+                let body = self.elaborate(kenv, &extended_scope, body.take())?;
+                typed(body.typ.clone(), Node::Loop { variables: typed_variables, body: Box::new(body) })
+            }
+
+            Expression::Returning { result: Some(result), body } => {
+                self.kinds.expect_star(kenv, result.clone())?;
+                let mut extended_scope = scope.clone();
+                extended_scope.remove(RETURNED);
+                extended_scope.insert(RETURN.to_owned(), (result.clone(), Binder::Declared));
+                let body = self.elaborate(kenv, &extended_scope, body.take())?;
+                Self::expect(&body, result)?;
+                typed(result.clone(), Node::Returning { result: result.take(), body: Box::new(body) })
+            }
+
+            // without a declared result type, a return point produces whatever its body or any
+            // return to it does
+            Expression::Returning { result: None, body } => {
+                let mut extended_scope = scope.clone();
+                extended_scope.remove(RETURN);
+                extended_scope.insert(RETURNED.to_owned(), (Type::Union(vec![]), Binder::Declared));
+                self.returned.push(Type::Union(vec![]));
+                let body = self.elaborate(kenv, &extended_scope, body.take());
+                let returned = self.returned.pop().unwrap();
+                let body = body?;
+                let result = join(returned, body.typ.clone());
+                typed(result.clone(), Node::Returning { result, body: Box::new(body) })
+            }
+
+            // like a continue, a return never produces a value where it appears
+            Expression::Return(value) => match (scope.get(RETURN), scope.contains_key(RETURNED)) {
+                (Some((expected, _)), _) => {
+                    let value = self.elaborate(kenv, scope, value.take())?;
+                    Self::expect(&value, expected)?;
+                    typed(Type::Union(vec![]), Node::Return(Box::new(value)))
+                }
+                (None, true) => {
+                    let value = self.elaborate(kenv, scope, value.take())?;
+                    let returned = self.returned.last_mut().unwrap();
+                    *returned = join(mem::take(returned), value.typ.clone());
+                    typed(Type::Union(vec![]), Node::Return(Box::new(value)))
+                }
+                (None, false) => Err(TypeError::MisplacedReturn),
+            },
+
+            // a continue never produces a value where it appears
+            Expression::Continue(arguments) => match scope.get(LOOP_VARIABLES) {
+                Some((Type::Tuple(types), _)) => {
+                    if arguments.len() != types.len() {
+                        return Err(TypeError::ArityMismatch { expected: types.len(), found: arguments.len() })
+                    }
+                    let mut typed_arguments = vec![];
+                    for (argument, expected) in mem::take(arguments).into_iter().zip(types) {
+                        let argument = self.elaborate(kenv, scope, argument)?;
+                        Self::expect(&argument, expected)?;
+                        typed_arguments.push(argument);
+                    }
+                    typed(Type::Union(vec![]), Node::Continue(typed_arguments))
+                }
+                _ => Err(TypeError::MisplacedContinue),
+            },
+
+            Expression::Function { parameters, body } => {
+                let mut extended_scope = scope.clone();
+                extended_scope.remove(LOOP_VARIABLES);
+                extended_scope.remove(RETURN);
+                extended_scope.remove(RETURNED);
+                distinct(parameters.iter().map(|Binding { id, .. }| (id, None)))?;
+                let mut arguments = vec![];
+                let mut typed_parameters = vec![];
+                for Binding { id, typ } in mem::take(parameters) {
+                    self.kinds.expect_star(kenv, typ.clone())?;
+                    let binder = self.bind(&mut extended_scope, id.clone(), typ.clone());
+                    arguments.push(typ.clone());
+                    typed_parameters.push((binder, Binding { id, typ }));
+                }
+                let body = self.elaborate(kenv, &extended_scope, body.take())?;
+                typed(Type::Function { arguments, result: Box::new(body.typ.clone()) },
+                      Node::Function { parameters: typed_parameters, body: Box::new(body) })
+            }
+
+            Expression::TypeFunction { parameters, body } => {
+                distinct(parameters.iter().map(|TypeBinding { id, .. }| (id, None)))?;
+                let mut extended_kenv = kenv.clone();
+                extended_kenv.extend(parameters.iter().map(|TypeBinding { id, kind }| (id.clone(), kind.clone())));
+                let body = self.elaborate(&extended_kenv, scope, body.take())?;
+                typed(Type::ForAll { parameters: parameters.clone(), typ: Box::new(body.typ.clone()) },
+                      Node::TypeFunction { parameters: mem::take(parameters), body: Box::new(body) })
+            }
+
+            Expression::TypeApplication { function, arguments } => {
+                let function = self.elaborate(kenv, scope, function.take())?;
+                match &function.typ {
+                    Type::ForAll { parameters, typ } => {
+                        if parameters.len() != arguments.len() {
+                            return Err(TypeError::ArityMismatch { expected: parameters.len(), found: arguments.len() })
+                        }
+                        let mut checker = KindChecker::default();
+                        let mut substitution = HashMap::new();
+                        for (TypeBinding { id, kind }, argument) in parameters.iter().zip(arguments.iter()) {
+                            let found = checker.infer(kenv, argument.clone())?;
+                            checker.unify(kind.clone(), found.clone())
+                                   .map_err(|_| TypeError::KindMismatch { expected: kind.clone(), found: checker.resolve(found) })?;
+                            substitution.insert(id.clone(), argument.clone());
+                        }
+                        let typ = (**typ).clone().substitute(&substitution);
+                        typed(typ, Node::TypeApplication { function: Box::new(function), arguments: mem::take(arguments) })
+                    }
+                    found => Err(TypeError::ExpectedQuantifier { found: found.clone() }),
+                }
+            }
+
+            Expression::Application { function, arguments } => {
+                let function = self.elaborate(kenv, scope, function.take())?;
+                let arguments = self.all(kenv, scope, mem::take(arguments))?;
+                let result = check_application(function.typ.clone(), arguments.iter().map(|argument| argument.typ.clone()).collect())?;
+                typed(result, Node::Application { function: Box::new(function), arguments })
+            }
+
+            Expression::If { condition, consequent, alternative } => {
+                // a type test on a variable narrows its type in each branch
+                let (consequent_scope, alternative_scope) = match stripped(condition) {
+                    Expression::TypeTest { expression, tag } => match stripped(expression) {
+                        Expression::Variable(id) if scope.contains_key(id) => {
+                            let (typ, binder) = scope[id].clone();
+                            let mut consequent_scope = scope.clone();
+                            consequent_scope.insert(id.clone(), (narrow(typ.clone(), tag, true), binder));
+                            let mut alternative_scope = scope.clone();
+                            alternative_scope.insert(id.clone(), (narrow(typ, tag, false), binder));
+                            (consequent_scope, alternative_scope)
+                        }
+                        _ => (scope.clone(), scope.clone()),
+                    }
+                    _ => (scope.clone(), scope.clone()),
+                };
+
+                let condition = self.elaborate(kenv, scope, condition.take())?;
+                Self::expect(&condition, &Type::Boolean)?;
+                let consequent = self.elaborate(kenv, &consequent_scope, consequent.take())?;
+                let alternative = self.elaborate(kenv, &alternative_scope, alternative.take())?;
+                typed(join(consequent.typ.clone(), alternative.typ.clone()), Node::If {
+                    condition: Box::new(condition),
+                    consequent: Box::new(consequent),
+                    alternative: Box::new(alternative),
+                })
+            }
+
+            Expression::TypeTest { expression, tag } => {
+                let expression = self.elaborate(kenv, scope, expression.take())?;
+                typed(Type::Boolean, Node::TypeTest { expression: Box::new(expression), tag: tag.clone() })
+            }
+
+            // an unresolved target conditional has to make sense on every target
+            Expression::IfTarget { target, consequent, alternative } => {
+                let consequent = self.elaborate(kenv, scope, consequent.take())?;
+                let alternative = self.elaborate(kenv, scope, alternative.take())?;
+                typed(join(consequent.typ.clone(), alternative.typ.clone()), Node::IfTarget {
+                    target: mem::take(target),
+                    consequent: Box::new(consequent),
+                    alternative: Box::new(alternative),
+                })
+            }
+
+            Expression::Constant(expression) => {
+                let expression = self.elaborate(kenv, scope, expression.take())?;
+                constants::evaluate_constant(expression.clone().erase(), constants::FUEL).map_err(|error| TypeError::Constant(Box::new(error)))?;
+                typed(expression.typ.clone(), Node::Constant(Box::new(expression)))
+            }
+
+            Expression::Total(expression) => {
+                termination::check_loops(expression)?;
+                let expression = self.elaborate(kenv, scope, expression.take())?;
+                typed(expression.typ.clone(), Node::Total(Box::new(expression)))
+            }
+
+            Expression::Located { span, expression } => {
+                let span = *span;
+                let expression = self.elaborate(kenv, scope, expression.take()).map_err(|error| {
+                    self.sealed = true;
+                    match error {
+                        TypeError::Located { .. } => error,
+                        error => TypeError::Located { error: Box::new(error), provenance: Provenance { span, expanded_from: vec![] } },
+                    }
+                })?;
+                typed(expression.typ.clone(), Node::Located { span, expression: Box::new(expression) })
+            }
+
+            // an error in generated code is located at the construct it was expanded from
+            Expression::Expanded { construct, span, expression } => {
+                let span = *span;
+                let expression = self.elaborate(kenv, scope, expression.take()).map_err(|error| {
+                    let expansion = Expansion { construct: construct.clone(), span };
+                    match error {
+                        TypeError::Located { error, mut provenance } if !self.sealed => {
+                            provenance.expanded_from.push(expansion);
+                            TypeError::Located { error, provenance }
+                        }
+                        TypeError::Located { .. } => error,
+                        error => {
+                            self.sealed = false;
+                            TypeError::Located { error: Box::new(error), provenance: Provenance { span, expanded_from: vec![expansion] } }
+                        }
+                    }
+                })?;
+                typed(expression.typ.clone(), Node::Expanded { construct: mem::take(construct), span, expression: Box::new(expression) })
+            }
+
+            Expression::Primitive { operator, arguments } => {
+                let arguments = self.all(kenv, scope, mem::take(arguments))?;
+                let result = primitives::check_primitive(*operator, arguments.iter().map(|argument| argument.typ.clone()).collect())?;
+                typed(result, Node::Primitive { operator: *operator, arguments })
+            }
+        }
+    }
+}
+
+/// check `expr` with `tenv` in scope, each of which resolves to `Binder::Declared`
+pub(super) fn elaborate_in(kenv: &KindEnv, tenv: &TypeEnv, expr: Expression) -> TC<TypedExpression> {
+    let scope = tenv.iter().map(|(id, typ)| (id.clone(), (typ.clone(), Binder::Declared))).collect();
+    Elaborator::default().elaborate(kenv, &scope, expr)
+}
+
+fn stripped(expr: &Expression) -> &Expression {
+    match expr {
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => stripped(expression),
+        expr => expr,
+    }
+}
+
+/// the cases of a variant `pattern` matches, or none if it isn't only variant cases
+fn case_tags(pattern: &Pattern) -> Vec<&Identifier> {
+    match pattern {
+        Pattern::Variant { tag, .. } => vec![tag],
+        Pattern::Or(alternatives) => {
+            let tags: Vec<_> = alternatives.iter().map(case_tags).collect();
+            match tags.iter().all(|tags| !tags.is_empty()) {
+                true => tags.into_iter().flatten().collect(),
+                false => vec![],
+            }
+        }
+        _ => vec![],
+    }
+}
+
+/// check `expr` like `check_with_declarations`, producing it with the type of each of its
+/// subexpressions and the binder of each of its variables
+pub fn elaborate(declarations: &Declarations, expr: Expression) -> TC<TypedExpression> {
+    let kenv = HashMap::new();
+    for typ in declarations.values() {
+        expect_value_type(&kenv, typ)?;
+    }
+    elaborate_in(&kenv, declarations, expr)
+}
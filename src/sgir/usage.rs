@@ -0,0 +1,225 @@
+use core::mem;
+
+use crate::prelude::*;
+
+use super::effects::is_pure;
+use super::patterns::{Arm, Pattern};
+use super::{Binding, Expression, Type};
+
+/// how evaluating an expression uses a variable
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Usage {
+    /// its value is never needed
+    Never,
+    /// its value is needed on some paths, or by a closure that might never be called
+    Conditionally,
+    /// its value is needed on every path that finishes normally, so an argument for it could be
+    /// evaluated eagerly without changing what terminates
+    Always,
+}
+
+impl Usage {
+    /// the usage of evaluating one expression and then another. if the first might leave early
+    /// with `return` or `continue`, the second might not be evaluated at all.
+    fn then(self, next: Usage, may_exit: bool) -> Usage {
+        match (self, next) {
+            (Usage::Always, _) => Usage::Always,
+            (_, Usage::Always) if !may_exit => Usage::Always,
+            (Usage::Never, Usage::Never) => Usage::Never,
+            _ => Usage::Conditionally,
+        }
+    }
+
+    /// the usage of evaluating one expression or another
+    fn either(self, other: Usage) -> Usage {
+        if self == other { self } else { Usage::Conditionally }
+    }
+}
+
+/// how each of `parameters` is used by a function with `body`
+pub fn parameter_usage(parameters: &[Binding], body: &Expression) -> Vec<Usage> {
+    parameters.iter().map(|Binding { id, .. }| usage(id, body)).collect()
+}
+
+/// how evaluating `expr` uses the variable `id`
+pub fn usage(id: &str, expr: &Expression) -> Usage {
+    let sequence = |exprs: &mut dyn Iterator<Item = &Expression>| {
+        let (mut total, mut may_exit) = (Usage::Never, false);
+        for expr in exprs {
+            total = total.then(usage(id, expr), may_exit);
+            may_exit |= exits(expr);
+        }
+        total
+    };
+    match expr {
+        Expression::Variable(variable) => if variable == id { Usage::Always } else { Usage::Never },
+        // a closure only uses what it captures when it's called, if it ever is
+        Expression::Function { parameters, body } => {
+            if parameters.iter().any(|Binding { id: parameter, .. }| parameter == id) || usage(id, body) == Usage::Never {
+                Usage::Never
+            } else {
+                Usage::Conditionally
+            }
+        }
+        Expression::If { condition, consequent, alternative } => {
+            usage(id, condition).then(usage(id, consequent).either(usage(id, alternative)), exits(condition))
+        }
+        Expression::IfTarget { consequent, alternative, .. } => usage(id, consequent).either(usage(id, alternative)),
+        Expression::Match { scrutinee, arms } => {
+            let arm = |Arm { pattern, guard, body }: &Arm| {
+                if pattern.variables().iter().any(|variable| variable == id) {
+                    return Usage::Never
+                }
+                sequence(&mut guard.iter().chain([body]))
+            };
+            let arms = arms.iter().map(arm).reduce(Usage::either).unwrap_or(Usage::Never);
+            usage(id, scrutinee).then(arms, exits(scrutinee))
+        }
+        Expression::Loop { variables, body } => {
+            let body = if variables.iter().any(|(Binding { id: variable, .. }, _)| variable == id) {
+                Usage::Never
+            } else {
+                usage(id, body)
+            };
+            let inits = sequence(&mut variables.iter().map(|(_, init)| init));
+            inits.then(body, variables.iter().any(|(_, init)| exits(init)))
+        }
+        expr => sequence(&mut expr.children().into_iter()),
+    }
+}
+
+/// whether evaluating `expr` might leave it early, with a `return` or a `continue` that isn't
+/// caught inside it
+fn exits(expr: &Expression) -> bool {
+    match expr {
+        Expression::Return(_) | Expression::Continue(_) => true,
+        Expression::Function { .. } => false,
+        Expression::Returning { body, .. } => exits_continuing(body),
+        Expression::Loop { variables, body } => variables.iter().any(|(_, init)| exits(init)) || exits_returning(body),
+        expr => expr.children().into_iter().any(exits),
+    }
+}
+
+/// whether `expr` might `continue` out of it
+fn exits_continuing(expr: &Expression) -> bool {
+    match expr {
+        Expression::Continue(_) => true,
+        Expression::Function { .. } | Expression::Loop { .. } => false,
+        expr => expr.children().into_iter().any(exits_continuing),
+    }
+}
+
+/// whether `expr` might `return` out of it
+fn exits_returning(expr: &Expression) -> bool {
+    match expr {
+        Expression::Return(_) => true,
+        Expression::Function { .. } | Expression::Returning { .. } => false,
+        expr => expr.children().into_iter().any(exits_returning),
+    }
+}
+
+/// remove the parameters a function never uses, along with the arguments passed for them. this
+/// applies to functions bound by a `let` whose every use is a call, where every argument being
+/// dropped is pure, so dropping it changes nothing but the work done.
+pub fn eliminate_dead_parameters(expr: Expression) -> Expression {
+    let mut expr = expr.map_children(eliminate_dead_parameters);
+    match &mut expr {
+        Expression::Match { scrutinee, arms } if arms.len() == 1 && arms[0].guard.is_none() => {
+            let Arm { pattern, body, .. } = arms.pop().unwrap();
+            let (scrutinee, body) = match (&pattern, stripped(scrutinee)) {
+                (Pattern::Variable(name), Expression::Function { parameters, body: function })
+                if !matches!(parameters.last(), Some(Binding { typ: Type::Rest(_), .. })) => {
+                    let dead = parameter_usage(parameters, function).into_iter()
+                                                                    .enumerate()
+                                                                    .filter(|(_, usage)| *usage == Usage::Never)
+                                                                    .map(|(i, _)| i)
+                                                                    .collect::<Vec<_>>();
+                    if !dead.is_empty() && only_called(name, &body, parameters.len(), &dead) {
+                        let parameters = parameters.iter()
+                                                   .enumerate()
+                                                   .filter(|(i, _)| !dead.contains(i))
+                                                   .map(|(_, parameter)| parameter.clone())
+                                                   .collect();
+                        (Expression::Function { parameters, body: function.clone() }, drop_arguments(name, body, &dead))
+                    } else {
+                        (scrutinee.take(), body)
+                    }
+                }
+                _ => (scrutinee.take(), body),
+            };
+            Expression::Match { scrutinee: Box::new(scrutinee), arms: vec![Arm { pattern, guard: None, body }] }
+        }
+        _ => expr,
+    }
+}
+
+/// whether every use of `name` in `expr` is a call with `arity` arguments, where the arguments
+/// at the positions in `dead` are pure
+fn only_called(name: &str, expr: &Expression, arity: usize, dead: &[usize]) -> bool {
+    match expr {
+        Expression::Variable(variable) => variable != name,
+        Expression::Application { function, arguments } if is_variable(function, name) => {
+            arguments.len() == arity
+                && dead.iter().all(|&i| is_pure(&arguments[i]))
+                && arguments.iter().all(|argument| only_called(name, argument, arity, dead))
+        }
+        expr if shadows(expr, name) => true,
+        Expression::Match { scrutinee, arms } => {
+            only_called(name, scrutinee, arity, dead) && arms.iter().all(|Arm { pattern, guard, body }| {
+                pattern.variables().iter().any(|variable| variable == name)
+                    || guard.iter().chain([body]).all(|expr| only_called(name, expr, arity, dead))
+            })
+        }
+        expr => expr.children().into_iter().all(|child| only_called(name, child, arity, dead)),
+    }
+}
+
+/// `expr` with the arguments at the positions in `dead` dropped from every call of `name`
+fn drop_arguments(name: &str, mut expr: Expression, dead: &[usize]) -> Expression {
+    if shadows(&expr, name) {
+        return expr
+    }
+    match &mut expr {
+        Expression::Application { function, arguments } if is_variable(function, name) => Expression::Application {
+            function: mem::take(function),
+            arguments: mem::take(arguments).into_iter()
+                                           .enumerate()
+                                           .filter(|(i, _)| !dead.contains(i))
+                                           .map(|(_, argument)| drop_arguments(name, argument, dead))
+                                           .collect(),
+        },
+        Expression::Match { scrutinee, arms } => Expression::Match {
+            scrutinee: Box::new(drop_arguments(name, scrutinee.take(), dead)),
+            arms: mem::take(arms).into_iter()
+                                 .map(|Arm { pattern, guard, body }| {
+                                     if pattern.variables().iter().any(|variable| variable == name) {
+                                         return Arm { pattern, guard, body }
+                                     }
+                                     Arm { guard: guard.map(|guard| drop_arguments(name, guard, dead)), body: drop_arguments(name, body, dead), pattern }
+                                 })
+                                 .collect(),
+        },
+        _ => expr.map_children(|child| drop_arguments(name, child, dead)),
+    }
+}
+
+/// whether `expr` is a function or loop that binds `name` again, so that it refers to
+/// something else inside
+fn shadows(expr: &Expression, name: &str) -> bool {
+    match expr {
+        Expression::Function { parameters, .. } => parameters.iter().any(|Binding { id, .. }| id == name),
+        Expression::Loop { variables, .. } => variables.iter().any(|(Binding { id, .. }, _)| id == name),
+        _ => false,
+    }
+}
+
+fn is_variable(expr: &Expression, name: &str) -> bool {
+    matches!(stripped(expr), Expression::Variable(variable) if variable == name)
+}
+
+fn stripped(expr: &Expression) -> &Expression {
+    match expr {
+        Expression::Located { expression, .. } | Expression::Expanded { expression, .. } => stripped(expression),
+        expr => expr,
+    }
+}
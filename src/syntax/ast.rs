@@ -0,0 +1,411 @@
+use crate::sgir::operators::Fixity;
+use crate::sgir::{Span, Type};
+
+use super::lexer::Comment;
+
+/// a node of the surface syntax tree, along with the source it was parsed from
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub ast: Ast,
+    pub span: Span,
+    /// the comments on the lines before a statement, but for its doc comment, which its
+    /// declaration keeps instead
+    pub leading: Vec<Comment>,
+    /// the comments after a statement on its last line, followed, for the last statement of a
+    /// block, by those on the lines between it and what closes the block
+    pub trailing: Vec<Comment>,
+}
+
+/// a sequence of statements, whose value is the value of the last one
+pub type Block = Vec<Node>;
+
+/// what a function parameter or a local binds, `pattern` or `pattern: T`, where the annotation
+/// is the type of the whole value. a parameter can have a default, `pattern: T = e`, for a call
+/// that leaves it out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binder {
+    pub pattern: Pattern,
+    pub annotation: Option<Type>,
+    pub default: Option<Node>,
+}
+
+/// a pattern a binder takes its value apart with, e.g. the `{x, y}` of `local {x, y} = point`,
+/// or that a case of a `match` tries a value against. a binder can only be written with the
+/// patterns that can't fail to match a value of the right type: names, tuples and records.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Name(String),
+    /// `(a, b)`, or `(a,)` with one element
+    Tuple(Vec<Pattern>),
+    /// `{x = (a, b), y}`, where a field on its own binds a name like it
+    Record(Vec<(String, Pattern)>),
+    /// `_`, which matches anything and binds nothing
+    Wildcard,
+    Boolean(bool),
+    Number(i64),
+    String(String),
+    /// `Tag(p)`, which matches the case `Tag` of a variant if its payload matches `p`. `Tag()`
+    /// matches a case without a payload, and `Tag(p, q)` one whose payload is a tuple.
+    Variant {
+        tag: String,
+        payload: Box<Pattern>,
+    },
+    /// `p | q`, which matches what either of them matches. they bind the same names.
+    Or(Vec<Pattern>),
+}
+
+/// a case of a `match`, `case pattern then ...`, or `case pattern if guard then ...` to only be
+/// taken when the guard holds
+#[derive(Clone, Debug, PartialEq)]
+pub struct Case {
+    pub pattern: Pattern,
+    pub guard: Option<Node>,
+    pub body: Block,
+}
+
+impl Pattern {
+    /// the names this binds, from left to right
+    pub fn names(&self) -> Vec<&String> {
+        match self {
+            Pattern::Name(name) => vec![name],
+            Pattern::Tuple(patterns) => patterns.iter().flat_map(Pattern::names).collect(),
+            Pattern::Record(fields) => fields.iter().flat_map(|(_, pattern)| pattern.names()).collect(),
+            Pattern::Variant { payload, .. } => payload.names(),
+            Pattern::Or(alternatives) => alternatives.first().map(Pattern::names).unwrap_or_default(),
+            Pattern::Wildcard | Pattern::Boolean(_) | Pattern::Number(_) | Pattern::String(_) => vec![],
+        }
+    }
+
+    /// the names this binds, each with its type if it's known from `typ`, the type of the value
+    /// being taken apart
+    pub fn bindings(&self, typ: Option<&Type>) -> Vec<(String, Option<Type>)> {
+        match (self, typ) {
+            (Pattern::Name(name), typ) => vec![(name.clone(), typ.cloned())],
+            (Pattern::Tuple(patterns), Some(Type::Tuple(types))) if patterns.len() == types.len() => {
+                patterns.iter().zip(types).flat_map(|(pattern, typ)| pattern.bindings(Some(typ))).collect()
+            }
+            (Pattern::Record(fields), Some(Type::Record(types))) => fields.iter().flat_map(|(field, pattern)| {
+                pattern.bindings(types.iter().find(|(id, _)| id == field).map(|(_, typ)| typ))
+            }).collect(),
+            (Pattern::Variant { tag, payload }, Some(Type::Variant(cases))) => {
+                payload.bindings(cases.iter().find(|(id, _)| id == tag).map(|(_, typ)| typ))
+            }
+            (Pattern::Or(alternatives), typ) => alternatives.first().map(|pattern| pattern.bindings(typ)).unwrap_or_default(),
+            (pattern, _) => pattern.names().into_iter().map(|name| (name.clone(), None)).collect(),
+        }
+    }
+}
+
+/// the name `...` binds in a variadic function
+pub const VARARGS: &str = "...";
+
+impl Binder {
+    /// is this the `...` parameter of a variadic function?
+    pub fn is_varargs(&self) -> bool {
+        self.pattern == Pattern::Name(VARARGS.to_owned())
+    }
+
+    /// a binder of just `name`
+    pub fn name(name: &str, annotation: Option<Type>) -> Binder {
+        Binder { pattern: Pattern::Name(name.to_owned()), annotation, default: None }
+    }
+
+    /// the names this binds, each with its type if its annotation says what it is
+    pub fn bindings(&self) -> Vec<(String, Option<Type>)> {
+        self.pattern.bindings(self.annotation.as_ref())
+    }
+}
+
+/// an attribute, e.g. `@allow(unused_variable)`, written before a declaration to apply to it, or
+/// as `@!allow(unused_variable)` at the top level to apply to the whole file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub arguments: Vec<String>,
+    pub span: Span,
+}
+
+/// the surface language, as it was written. it's expression-oriented: statements are nodes too.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Ast {
+    Name(String),
+    Boolean(bool),
+    Number(i64),
+    Float(f64),
+    String(String),
+    /// a bytes literal, e.g. `b"\x00\xff"`
+    Bytes(Vec<u8>),
+    /// a tuple, e.g. `(1, "a")`, `(1,)`, or the unit value `()`
+    Tuple(Vec<Node>),
+    /// a record, e.g. `{x = 1, y = 2}`
+    Record(Vec<(String, Node)>),
+    /// a copy of a record with some of its fields replaced or added, e.g. `{r with x = 1}`
+    Update {
+        record: Box<Node>,
+        fields: Vec<(String, Node)>,
+    },
+
+    Call {
+        function: Box<Node>,
+        arguments: Vec<Node>,
+    },
+    /// an argument of a call given by the name of its parameter, `name = e`
+    Named {
+        name: String,
+        value: Box<Node>,
+    },
+    /// `_` for an argument of a call, which makes it a function of the arguments it leaves out,
+    /// e.g. `f(_, 2)`
+    Placeholder,
+    Field {
+        record: Box<Node>,
+        field: String,
+    },
+    Unary {
+        operator: String,
+        operand: Box<Node>,
+    },
+    /// a chain of infix operators, `first op1 e1 op2 e2 ...`. it's left flat by the parser and
+    /// resolved against the fixities in scope when it's lowered.
+    Operators {
+        first: Box<Node>,
+        rest: Vec<(String, Node)>,
+    },
+    /// `function<T...>(parameters): R ... end`, where the type parameters and annotations are
+    /// optional
+    Function {
+        type_parameters: Vec<String>,
+        parameters: Vec<Binder>,
+        result: Option<Type>,
+        body: Block,
+    },
+    /// a type ascription, `e :: T`
+    Ascription {
+        expression: Box<Node>,
+        typ: Type,
+    },
+    /// explicit instantiation of a generic function, e.g. the `id<Number>` of `id<Number>(5)`
+    Instantiate {
+        function: Box<Node>,
+        arguments: Vec<Type>,
+    },
+    /// `if c then a elseif d then b else e end`, with `elseif`s nested in the alternative
+    If {
+        condition: Box<Node>,
+        consequent: Block,
+        alternative: Option<Block>,
+    },
+    /// `do ... end`
+    Do(Block),
+    /// `@if target == "wasm" then ... else ... end`, which is lowered to just the block for the
+    /// target the program is compiled for, so that the other leaves no trace at runtime
+    IfTarget {
+        target: String,
+        consequent: Block,
+        alternative: Option<Block>,
+    },
+    /// `match e case p then ... case q if c then ... end`, which takes the first case whose
+    /// pattern matches the value of `e` and whose guard, if it has one, holds. every value of
+    /// `e` has to be matched by one of them.
+    Match {
+        scrutinee: Box<Node>,
+        cases: Vec<Case>,
+    },
+
+    /// `local a, b: T = e, f`, or `pub local ...` to export what it binds from the module it's at
+    /// the top level of
+    Local {
+        attributes: Vec<Attribute>,
+        public: bool,
+        names: Vec<Binder>,
+        values: Vec<Node>,
+        /// the text of the `---` comments right before it
+        doc: Option<String>,
+    },
+    /// `a, b = e, f`, which gives locals new values. the targets are all names: records and
+    /// tuples can't be changed in place.
+    Assign {
+        targets: Vec<Node>,
+        values: Vec<Node>,
+    },
+    /// `type Name<T...> = U`, an alias for a type in the rest of the block
+    TypeAlias {
+        name: String,
+        parameters: Vec<String>,
+        typ: Type,
+        doc: Option<String>,
+    },
+    /// `macro name(a, b) ... end`, which stands for its block with the expressions it's called
+    /// with in place of its parameters, unevaluated, in the rest of the block. the names it uses
+    /// besides its parameters refer to what they do where it's declared, and the locals it
+    /// declares are its own.
+    MacroDeclaration {
+        name: String,
+        parameters: Vec<String>,
+        body: Block,
+    },
+    /// `function name<T...>(parameters): R ... end`, or `fn name ...`, optionally marked `total`.
+    /// its contract, `requires p` and `ensures q` clauses between its signature and its body, is
+    /// checked at runtime. an `ensures` clause refers to the function's value as `result`. like a
+    /// local, it's exported from its module if it's marked `pub`.
+    ///
+    /// `operator <+> left 6 (a, b) ... end` declares the function of an infix operator, named by
+    /// its symbol, along with the fixity it has in the rest of the block.
+    FunctionDeclaration {
+        attributes: Vec<Attribute>,
+        public: bool,
+        total: bool,
+        operator: Option<Fixity>,
+        name: String,
+        type_parameters: Vec<String>,
+        parameters: Vec<Binder>,
+        result: Option<Type>,
+        requires: Vec<Node>,
+        ensures: Vec<Node>,
+        body: Block,
+        doc: Option<String>,
+    },
+    Return(Vec<Node>),
+    While {
+        condition: Box<Node>,
+        body: Block,
+    },
+    Repeat {
+        body: Block,
+        condition: Box<Node>,
+    },
+    /// `for variable = start, stop do ... end`, or `for variable = start, stop, step do ... end`
+    NumericFor {
+        variable: String,
+        start: Box<Node>,
+        stop: Box<Node>,
+        step: Option<Box<Node>>,
+        body: Block,
+    },
+    /// `for variable in iterable do ... end`
+    GenericFor {
+        variable: String,
+        iterable: Box<Node>,
+        body: Block,
+    },
+    /// `pub use module.name`, or `pub use module.name as alias`, which exports an export of another
+    /// module from this one, as its name or as `alias`
+    Reexport {
+        module: String,
+        name: String,
+        alias: Option<String>,
+    },
+    Break,
+    Continue,
+    /// `@!name(arguments)`, an attribute of the file it's at the top level of
+    FileAttribute(Attribute),
+    /// `test "name" ... end`, a test of the declarations before it at the top level of a module,
+    /// which passes if its block runs to the end without failing. only `sanguinello test` runs it.
+    Test {
+        name: String,
+        body: Block,
+    },
+
+    /// source that couldn't be parsed, which the parser skipped over to carry on after a syntax
+    /// error
+    Error,
+}
+
+impl Node {
+    /// the nodes directly within this one, in source order
+    pub fn children(&self) -> Vec<&Node> {
+        match &self.ast {
+            Ast::Name(_) | Ast::Boolean(_) | Ast::Number(_) | Ast::Float(_) | Ast::String(_) | Ast::Bytes(_) | Ast::Break | Ast::Continue | Ast::Error
+            | Ast::Placeholder | Ast::TypeAlias { .. } | Ast::Reexport { .. } | Ast::FileAttribute(_) => vec![],
+            Ast::Tuple(elements) => elements.iter().collect(),
+            Ast::Record(fields) => fields.iter().map(|(_, node)| node).collect(),
+            Ast::Update { record, fields } => std::iter::once(&**record).chain(fields.iter().map(|(_, node)| node)).collect(),
+            Ast::Call { function, arguments } => std::iter::once(&**function).chain(arguments).collect(),
+            Ast::Field { record, .. } | Ast::Named { value: record, .. } => vec![record],
+            Ast::Instantiate { function, .. } => vec![function],
+            Ast::Ascription { expression, .. } => vec![expression],
+            Ast::Unary { operand, .. } => vec![operand],
+            Ast::Operators { first, rest } => std::iter::once(&**first).chain(rest.iter().map(|(_, node)| node)).collect(),
+            Ast::Function { parameters, body, .. } => parameters.iter().filter_map(|binder| binder.default.as_ref()).chain(body).collect(),
+            Ast::Do(body) | Ast::MacroDeclaration { body, .. } | Ast::Test { body, .. } => body.iter().collect(),
+            Ast::FunctionDeclaration { parameters, requires, ensures, body, .. } => {
+                parameters.iter().filter_map(|binder| binder.default.as_ref()).chain(requires).chain(ensures).chain(body).collect()
+            }
+            Ast::If { condition, consequent, alternative } => {
+                std::iter::once(&**condition).chain(consequent).chain(alternative.iter().flatten()).collect()
+            }
+            Ast::IfTarget { consequent, alternative, .. } => consequent.iter().chain(alternative.iter().flatten()).collect(),
+            Ast::Match { scrutinee, cases } => {
+                std::iter::once(&**scrutinee).chain(cases.iter().flat_map(|Case { guard, body, .. }| guard.iter().chain(body))).collect()
+            }
+            Ast::Local { values, .. } => values.iter().collect(),
+            Ast::Assign { targets, values } => targets.iter().chain(values).collect(),
+            Ast::Return(values) => values.iter().collect(),
+            Ast::While { condition, body } => std::iter::once(&**condition).chain(body).collect(),
+            Ast::Repeat { body, condition } => body.iter().chain(std::iter::once(&**condition)).collect(),
+            Ast::NumericFor { start, stop, step, body, .. } => [&**start, &**stop].into_iter().chain(step.as_deref()).chain(body).collect(),
+            Ast::GenericFor { iterable, body, .. } => std::iter::once(&**iterable).chain(body).collect(),
+        }
+    }
+
+    /// the nodes directly within this one, in source order, mutably
+    pub fn children_mut(&mut self) -> Vec<&mut Node> {
+        match &mut self.ast {
+            Ast::Name(_) | Ast::Boolean(_) | Ast::Number(_) | Ast::Float(_) | Ast::String(_) | Ast::Bytes(_) | Ast::Break | Ast::Continue | Ast::Error
+            | Ast::Placeholder | Ast::TypeAlias { .. } | Ast::Reexport { .. } | Ast::FileAttribute(_) => vec![],
+            Ast::Tuple(elements) => elements.iter_mut().collect(),
+            Ast::Record(fields) => fields.iter_mut().map(|(_, node)| node).collect(),
+            Ast::Update { record, fields } => std::iter::once(&mut **record).chain(fields.iter_mut().map(|(_, node)| node)).collect(),
+            Ast::Call { function, arguments } => std::iter::once(&mut **function).chain(arguments).collect(),
+            Ast::Field { record, .. } | Ast::Named { value: record, .. } => vec![record],
+            Ast::Instantiate { function, .. } => vec![function],
+            Ast::Ascription { expression, .. } => vec![expression],
+            Ast::Unary { operand, .. } => vec![operand],
+            Ast::Operators { first, rest } => std::iter::once(&mut **first).chain(rest.iter_mut().map(|(_, node)| node)).collect(),
+            Ast::Function { parameters, body, .. } => {
+                parameters.iter_mut().filter_map(|binder| binder.default.as_mut()).chain(body).collect()
+            }
+            Ast::Do(body) | Ast::MacroDeclaration { body, .. } | Ast::Test { body, .. } => body.iter_mut().collect(),
+            Ast::FunctionDeclaration { parameters, requires, ensures, body, .. } => {
+                parameters.iter_mut().filter_map(|binder| binder.default.as_mut()).chain(requires).chain(ensures).chain(body).collect()
+            }
+            Ast::If { condition, consequent, alternative } => {
+                std::iter::once(&mut **condition).chain(consequent).chain(alternative.iter_mut().flatten()).collect()
+            }
+            Ast::IfTarget { consequent, alternative, .. } => consequent.iter_mut().chain(alternative.iter_mut().flatten()).collect(),
+            Ast::Match { scrutinee, cases } => {
+                std::iter::once(&mut **scrutinee).chain(cases.iter_mut().flat_map(|Case { guard, body, .. }| guard.iter_mut().chain(body))).collect()
+            }
+            Ast::Local { values, .. } => values.iter_mut().collect(),
+            Ast::Assign { targets, values } => targets.iter_mut().chain(values).collect(),
+            Ast::Return(values) => values.iter_mut().collect(),
+            Ast::While { condition, body } => std::iter::once(&mut **condition).chain(body).collect(),
+            Ast::Repeat { body, condition } => body.iter_mut().chain(std::iter::once(&mut **condition)).collect(),
+            Ast::NumericFor { start, stop, step, body, .. } => {
+                [&mut **start, &mut **stop].into_iter().chain(step.as_deref_mut()).chain(body).collect()
+            }
+            Ast::GenericFor { iterable, body, .. } => std::iter::once(&mut **iterable).chain(body).collect(),
+        }
+    }
+
+    /// apply `f` to the span of this node and every node within it
+    pub fn for_each_span(&mut self, f: &mut impl FnMut(&mut Span)) {
+        f(&mut self.span);
+        for child in self.children_mut() {
+            child.for_each_span(f);
+        }
+    }
+}
+
+/// the names the top level of the module `program` exports: those its `pub` declarations bind,
+/// and its re-exports
+pub fn exports(program: &Block) -> Vec<String> {
+    program.iter()
+           .flat_map(|node| match &node.ast {
+               Ast::Local { public: true, names, .. } => names.iter().flat_map(|binder| binder.pattern.names()).cloned().collect(),
+               Ast::FunctionDeclaration { public: true, name, .. } => vec![name.clone()],
+               Ast::Reexport { name, alias, .. } => vec![alias.as_ref().unwrap_or(name).clone()],
+               _ => vec![],
+           })
+           .collect()
+}
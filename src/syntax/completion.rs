@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use crate::sgir::operators::Operators;
+use crate::sgir::patterns::{Arm, Pattern};
+use crate::sgir::primitives::LIBRARY;
+use crate::sgir::{self, Declarations, Expression, Position, Type};
+
+use super::ast::{Ast, Binder, Node};
+use super::identifiers::{is_identifier_continue, is_identifier_start, parts, Part};
+use super::lexer::{lex, offset, TokenKind};
+use super::lower::lower_block;
+use super::parser::parse;
+
+/// what a completion names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionKind {
+    Variable,
+    Function,
+    Field,
+    Module,
+}
+
+/// a name that could be written at the cursor
+#[derive(Clone, Debug, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// its type, when it's annotated or the checker can tell what it is
+    pub typ: Option<Type>,
+}
+
+impl Completion {
+    fn new(label: &str, typ: Option<Type>) -> Completion {
+        let kind = match &typ {
+            Some(Type::Function { .. } | Type::ForAll { .. }) => CompletionKind::Function,
+            _ => CompletionKind::Variable,
+        };
+        Completion { label: label.to_owned(), kind, typ }
+    }
+}
+
+/// what's expected at a cursor
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Expected {
+    /// the type of the expression being written there, when it's known
+    pub typ: Option<Type>,
+    /// what could be written to make one
+    pub completions: Vec<Completion>,
+}
+
+/// the names in scope at a cursor, found by following the syntax tree down to it, along with the
+/// type expected of the node it's in
+struct Scope<'a> {
+    cursor: Position,
+    /// the names in scope, innermost last
+    names: Vec<Completion>,
+    /// the type aliases in scope, which the declarations after them are lowered with
+    aliases: Vec<&'a Node>,
+    /// the type expected of the innermost node the cursor is in that's been reached so far
+    expected: Option<Type>,
+    /// the result type of the innermost function the cursor is in, when it's annotated
+    result: Option<Type>,
+    /// the fields a record the cursor is in has been given, when it's between them
+    fields: Option<Vec<String>>,
+}
+
+impl<'a> Scope<'a> {
+    fn at(cursor: Position) -> Scope<'a> {
+        Scope { cursor, names: vec![], aliases: vec![], expected: None, result: None, fields: None }
+    }
+
+    fn contains(&self, node: &Node) -> bool {
+        node.span.start < self.cursor && self.cursor <= node.span.end
+    }
+
+    /// a node the cursor is in
+    fn node(&mut self, node: &'a Node) {
+        if let Ast::Function { result, .. } | Ast::FunctionDeclaration { result, .. } = &node.ast {
+            self.result = result.clone();
+        }
+        self.parts(Some(node), parts(node));
+    }
+
+    /// the parts of the node the cursor is in, or of the program, up to the one the cursor is in.
+    /// everything after the cursor was left off, so when it's past the end of the parts, it's in
+    /// the last one, like the body of a loop it hasn't written yet.
+    fn parts(&mut self, node: Option<&'a Node>, parts: Vec<Part<'a>>) {
+        let last = parts.len().saturating_sub(1);
+        for (i, part) in parts.into_iter().enumerate() {
+            match part {
+                Part::Node(child) | Part::Operator(_, child) if self.contains(child) => {
+                    self.expected = node.and_then(|node| {
+                        let position = node.children().iter().position(|other| std::ptr::eq(*other, child))?;
+                        self.expectation(node, position)
+                    });
+                    return self.node(child)
+                }
+                Part::Node(child) if child.span.end < self.cursor => self.declare(child),
+                Part::Scope(opening, parts) if i == last || self.reaches(&parts) => {
+                    for (name, typ) in opening.bindings() {
+                        self.names.push(Completion::new(&name, typ));
+                    }
+                    return self.parts(node, parts)
+                }
+                _ => {}
+            }
+        }
+        if let Some(Node { ast: Ast::Record(fields), .. }) = node {
+            self.fields = Some(fields.iter().map(|(field, _)| field.clone()).collect());
+        }
+    }
+
+    /// whether the cursor is in any of `parts`
+    fn reaches(&self, parts: &[Part]) -> bool {
+        parts.iter().any(|part| match part {
+            Part::Node(node) | Part::Operator(_, node) => self.contains(node),
+            Part::Scope(_, parts) => self.reaches(parts),
+        })
+    }
+
+    /// the type expected of the `i`th child of `node`, the node the cursor is in
+    fn expectation(&self, node: &Node, i: usize) -> Option<Type> {
+        let expected = self.expected.clone().map(|typ| self.expand(typ));
+        match (&node.ast, &expected) {
+            (Ast::Local { names, .. }, _) => match &names[..] {
+                [binder] => binder.annotation.clone(),
+                _ => None,
+            },
+            (Ast::Return(values), _) => {
+                let result = self.result.clone().map(|typ| self.expand(typ));
+                match (&values[..], &result) {
+                    ([_], _) => result.clone(),
+                    (_, Some(Type::Tuple(types))) if types.len() == values.len() => Some(types[i].clone()),
+                    _ => None,
+                }
+            }
+            (Ast::Call { function, .. }, _) if i > 0 => {
+                let Ast::Name(name) = &function.ast else { return None };
+                let binding = self.names.iter().rev().find(|completion| completion.label == *name)?;
+                match &binding.typ.clone().map(|typ| self.expand(typ)) {
+                    Some(Type::Function { arguments, .. }) => match arguments.last() {
+                        Some(Type::Rest(typ)) if i >= arguments.len() => Some((**typ).clone()),
+                        _ => arguments.get(i - 1).cloned(),
+                    },
+                    _ => None,
+                }
+            }
+            (Ast::Record(fields), Some(Type::Record(types))) => {
+                types.iter().find(|(field, _)| *field == fields[i].0).map(|(_, typ)| typ.clone())
+            }
+            (Ast::Tuple(_), Some(Type::Tuple(types))) => types.get(i).cloned(),
+            (Ast::Ascription { typ, .. }, _) => Some(typ.clone()),
+            _ => None,
+        }
+    }
+
+    /// `typ` with the type aliases in scope at its head expanded, so that it shows what sort of
+    /// type it is
+    fn expand(&self, mut typ: Type) -> Type {
+        for _ in 0..=self.aliases.len() {
+            let (name, arguments) = match &typ {
+                Type::Variable(name) => (name.clone(), vec![]),
+                Type::Instantiate { typ: head, arguments } => match &**head {
+                    Type::Variable(name) => (name.clone(), arguments.clone()),
+                    _ => return typ,
+                },
+                _ => return typ,
+            };
+            let alias = self.aliases.iter().rev().find_map(|node| match &node.ast {
+                Ast::TypeAlias { name: alias, parameters, typ, .. } if *alias == name && parameters.len() == arguments.len() => Some((parameters, typ)),
+                _ => None,
+            });
+            let Some((parameters, body)) = alias else { return typ };
+            typ = body.clone().substitute(&parameters.iter().cloned().zip(arguments).collect());
+        }
+        typ
+    }
+
+    /// a statement before the cursor, which brings the names it declares into scope
+    fn declare(&mut self, node: &'a Node) {
+        match &node.ast {
+            Ast::Local { names, .. } => {
+                let mut types = self.declared(node);
+                for (name, annotation) in names.iter().flat_map(Binder::bindings) {
+                    let typ = types.remove(&name).or(annotation);
+                    self.names.push(Completion::new(&name, typ));
+                }
+            }
+            Ast::FunctionDeclaration { name, .. } => {
+                let typ = self.declared(node).remove(name);
+                self.names.push(Completion { label: name.clone(), kind: CompletionKind::Function, typ });
+            }
+            Ast::Reexport { name, alias, .. } => self.names.push(Completion::new(alias.as_ref().unwrap_or(name), None)),
+            Ast::TypeAlias { .. } => self.aliases.push(node),
+            _ => {}
+        }
+    }
+
+    fn declared(&self, declaration: &Node) -> HashMap<String, Type> {
+        declared(&self.aliases, self.names.iter().map(|Completion { label, typ, .. }| (label, typ)), declaration)
+    }
+}
+
+/// the types of the names `declaration` binds, as far as the checker can tell from the types of
+/// the names in scope, when it's lowered after the type aliases in scope
+pub(crate) fn declared<'a>(aliases: &[&Node], names: impl IntoIterator<Item = (&'a String, &'a Option<Type>)>,
+                           declaration: &Node) -> HashMap<String, Type> {
+    let block = aliases.iter().copied().chain([declaration]).cloned().collect::<Vec<_>>();
+    let Ok(mut lowered) = lower_block(&block, &Operators::default()) else { return HashMap::new() };
+    let Expression::Match { scrutinee, arms } = &mut lowered else { return HashMap::new() };
+    // the annotations of the names in scope may mention type parameters or aliases, which the
+    // checker can't see from here
+    let declarations = names.into_iter()
+                            .filter_map(|(name, typ)| Some((name.clone(), typ.clone()?)))
+                            .filter(|(_, typ)| typ.free_variables().is_empty())
+                            .collect::<Declarations>();
+    let mut types = HashMap::new();
+    if let (Ok(typ), [Arm { pattern, .. }]) = (sgir::check_with_declarations(&declarations, scrutinee.take()), &arms[..]) {
+        bind(pattern, typ, &mut types);
+    }
+    types
+}
+
+/// the types of the variables of `pattern`, when it matches a value of type `typ`
+fn bind(pattern: &Pattern, mut typ: Type, types: &mut HashMap<String, Type>) {
+    match (pattern, &mut typ) {
+        (Pattern::Variable(id), _) => {
+            types.insert(id.clone(), typ);
+        }
+        (Pattern::Tuple(patterns), Type::Tuple(parts)) => {
+            for (pattern, typ) in patterns.iter().zip(std::mem::take(parts)) {
+                bind(pattern, typ, types);
+            }
+        }
+        (Pattern::Record(fields), Type::Record(parts)) => {
+            for (field, pattern) in fields {
+                if let Some((_, typ)) = parts.iter_mut().find(|(id, _)| id == field) {
+                    bind(pattern, std::mem::take(typ), types);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// what closes every block and bracket still open at the end of `source`, so that it can be
+/// parsed without what comes after it
+fn closers(source: &str) -> Option<String> {
+    let mut open = vec![];
+    for token in lex(source).ok()? {
+        match token.kind {
+            TokenKind::Identifier(word) => match word.as_str() {
+                "function" | "fn" | "if" => open.push(" end"),
+                // the header of a loop, waiting for its `do`
+                "while" | "for" => open.push(" do end"),
+                "do" => match open.last_mut() {
+                    Some(closer) if *closer == " do end" => *closer = " end",
+                    _ => open.push(" end"),
+                },
+                "repeat" => open.push(" until true"),
+                "end" | "until" => {
+                    open.pop();
+                }
+                _ => {}
+            },
+            TokenKind::Symbol("(") => open.push(")"),
+            TokenKind::Symbol("{") => open.push("}"),
+            TokenKind::Symbol("[") => open.push("]"),
+            TokenKind::Symbol(")" | "}" | "]") => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    Some(open.into_iter().rev().collect())
+}
+
+/// the names in `scope`, innermost first, without those that other names shadow
+fn visible(scope: Scope) -> Vec<Completion> {
+    let mut names = vec![];
+    for completion in scope.names.into_iter().rev() {
+        if !completion.label.starts_with('%') && !names.iter().any(|found: &Completion| found.label == completion.label) {
+            names.push(completion);
+        }
+    }
+    names
+}
+
+/// `completions` that start with `prefix`, those that start with it as written first, then those
+/// that start with it in another case
+fn matching(mut completions: Vec<Completion>, prefix: &str) -> Vec<Completion> {
+    completions.retain(|completion| completion.label.to_lowercase().starts_with(&prefix.to_lowercase()));
+    completions.sort_by_key(|completion| !completion.label.starts_with(prefix));
+    completions
+}
+
+/// the names that could be written at `position` in `source`, which may be only partly written:
+/// the fields of a record after a `.`, or the operators of a module of the library, or else the
+/// names in scope there and then the library's modules, each with its type when it's known.
+/// those that start with the name under the cursor as written come first, then those that start
+/// with it in another case, innermost first.
+pub fn complete(source: &str, position: Position) -> Vec<Completion> {
+    let cursor = offset(source, position);
+    let before = &source[..cursor];
+    let prefix = &before[before.trim_end_matches(is_identifier_continue).len()..];
+    let Some(closers) = closers(before) else { return vec![] };
+    let program = parse(&format!("{}{}", before, closers)).program;
+
+    let mut scope = Scope::at(position);
+    scope.parts(None, program.iter().map(Part::Node).collect());
+    let names = visible(scope);
+
+    let target = &before[..before.len() - prefix.len()];
+    let completions = if target.ends_with('.') && !target.ends_with("..") {
+        let Some(path) = receiver(target) else { return vec![] };
+        let binding = names.iter().find(|completion| completion.label == path[0]);
+        if let ([module], None) = (&path[..], binding) {
+            return matching(exports(module), prefix)
+        }
+        let mut typ = binding.and_then(|completion| completion.typ.clone());
+        for field in &path[1..] {
+            typ = match &mut typ {
+                Some(Type::Record(fields)) => std::mem::take(fields).into_iter().find(|(name, _)| name == field).map(|(_, typ)| typ),
+                _ => None,
+            };
+        }
+        match &mut typ {
+            Some(Type::Record(fields)) => std::mem::take(fields).into_iter()
+                                                              .map(|(label, typ)| Completion { label, kind: CompletionKind::Field, typ: Some(typ) })
+                                                              .collect(),
+            _ => vec![],
+        }
+    } else {
+        let modules = modules().into_iter().filter(|module| !names.iter().any(|completion| completion.label == *module)).collect::<Vec<_>>();
+        names.into_iter()
+             .chain(modules.into_iter().map(|module| Completion { label: module.to_owned(), kind: CompletionKind::Module, typ: None }))
+             .collect()
+    };
+    matching(completions, prefix)
+}
+
+/// what's expected at `position` in `source`, which may be only partly written: the type of the
+/// expression being written there, when the annotations around it say what it is, e.g. of a
+/// local, a parameter, or a function's result. where a field of a record of that type is being
+/// named, what could be written there is the fields it hasn't been given yet; anywhere else,
+/// it's the functions in scope that make a value of the type. either is matched against the
+/// name under the cursor, like `complete`'s.
+pub fn expect(source: &str, position: Position) -> Expected {
+    let cursor = offset(source, position);
+    let before = &source[..cursor];
+    let prefix = &before[before.trim_end_matches(is_identifier_continue).len()..];
+    let target = &before[..before.len() - prefix.len()];
+    let Some(closers) = closers(target) else { return Expected::default() };
+    // what's under the cursor is left off, so that the source parses wherever it is. outside of a
+    // record's fields, a name stands in for the expression being written.
+    let column = position.column - prefix.chars().count();
+    let between_fields = closers.starts_with('}') && target.trim_end().ends_with(['{', ',']);
+    let (source, column) = if between_fields { (format!("{}{}", target, closers), column) } else { (format!("{}_{}", target, closers), column + 1) };
+    let program = parse(&source).program;
+
+    let mut scope = Scope::at(Position { line: position.line, column });
+    scope.parts(None, program.iter().map(Part::Node).collect());
+    let typ = scope.expected.clone().map(|typ| scope.expand(typ));
+    let completions = match (&typ, &scope.fields) {
+        (Some(Type::Record(types)), Some(given)) if between_fields => {
+            types.iter()
+                 .filter(|(field, _)| !given.contains(field))
+                 .map(|(field, typ)| Completion { label: field.clone(), kind: CompletionKind::Field, typ: Some(typ.clone()) })
+                 .collect()
+        }
+        (Some(expected), _) if !between_fields => {
+            visible(scope).into_iter()
+                          .filter(|completion| matches!(&completion.typ, Some(Type::Function { result, .. }) if sgir::is_subtype(result, expected)))
+                          .collect()
+        }
+        _ => vec![],
+    };
+    Expected { typ, completions: matching(completions, prefix) }
+}
+
+/// the modules of the library, in the order their operators are listed
+fn modules() -> Vec<&'static str> {
+    let mut modules = vec![];
+    for (name, _) in LIBRARY {
+        if let Some((module, _)) = name.split_once('.') {
+            if !modules.contains(&module) {
+                modules.push(module);
+            }
+        }
+    }
+    modules
+}
+
+/// the operators the library module `module` exports, with their types unless they're
+/// polymorphic
+fn exports(module: &str) -> Vec<Completion> {
+    LIBRARY.iter()
+           .filter_map(|(name, operator)| {
+               let label = name.strip_prefix(module)?.strip_prefix('.')?;
+               Some(Completion { label: label.to_owned(), kind: CompletionKind::Function, typ: operator.function_type() })
+           })
+           .collect()
+}
+
+/// the chain of names `a.b.c` before the `.` that `source` ends with, if it's one
+fn receiver(source: &str) -> Option<Vec<&str>> {
+    let source = &source[..source.len() - 1];
+    let chain = &source[source.trim_end_matches(|c| c == '.' || is_identifier_continue(c)).len()..];
+    let path = chain.split('.').collect::<Vec<_>>();
+    let well_formed = path.iter().all(|name| name.starts_with(is_identifier_start));
+    if well_formed { Some(path) } else { None }
+}
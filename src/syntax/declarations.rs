@@ -0,0 +1,33 @@
+//! declaration files, `.sangd`, which declare the types of the functions and values a host or a
+//! foreign module provides, without their bodies, so that programs that use them can be checked.
+//! a declaration file for a module is named after it, like the module's own file would be, and
+//! declares what it exports by the names in it: `function read(path: String): String` is
+//! `fs.read` in `fs.sangd`.
+
+use thiserror::Error;
+
+use crate::sgir::Declarations;
+
+use super::lower::{lower_declarations, LowerError};
+use super::parser::{parse_declarations, SyntaxError};
+
+/// the extension of a declaration file
+pub const EXTENSION: &str = "sangd";
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum DeclarationError {
+    #[error(transparent)]
+    Syntax(#[from] SyntaxError),
+
+    #[error(transparent)]
+    Lower(#[from] LowerError),
+}
+
+/// the types the declaration file `source` declares, by the names they're declared by in it
+pub fn declarations(source: &str) -> Result<Declarations, DeclarationError> {
+    let parsed = parse_declarations(source);
+    if let Some(error) = parsed.errors.into_iter().next() {
+        return Err(error.into())
+    }
+    Ok(lower_declarations(&parsed.program)?)
+}
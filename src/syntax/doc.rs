@@ -0,0 +1,62 @@
+//! the API listing of a program: its declarations at the top level, with their types and the
+//! `---` doc comments attached to them
+
+use crate::sgir::doc::DocItem;
+use crate::sgir::Type;
+
+use super::ast::{Ast, Binder, Block, Node};
+use super::completion::declared;
+use super::lower::lower_declarations;
+
+/// the functions and locals declared at the top level of `program`, in order, or only those it
+/// exports if it's a module that exports any. each has its type as far as the checker can tell
+/// from the `globals` it's run with, or from its annotations if it can't.
+pub fn items(program: &Block, globals: &[(String, Option<Type>)]) -> Vec<DocItem> {
+    let exports = super::ast::exports(program);
+    let mut aliases: Vec<&Node> = vec![];
+    let mut names = globals.to_vec();
+    let mut items = vec![];
+    for node in program {
+        let (bindings, public, doc) = match &node.ast {
+            Ast::Local { names: binders, public, doc, .. } => {
+                let mut types = declared(&aliases, names.iter().map(|(name, typ)| (name, typ)), node);
+                let bindings = binders.iter()
+                                      .flat_map(Binder::bindings)
+                                      .map(|(name, annotation)| {
+                                          let typ = types.remove(&name).or(annotation);
+                                          (name, typ)
+                                      })
+                                      .collect::<Vec<_>>();
+                (bindings, *public, doc)
+            }
+            Ast::FunctionDeclaration { name, public, doc, .. } => {
+                let typ = declared(&aliases, names.iter().map(|(name, typ)| (name, typ)), node).remove(name)
+                                                                                               .or_else(|| signature(&aliases, node));
+                (vec![(name.clone(), typ)], *public, doc)
+            }
+            Ast::TypeAlias { .. } => {
+                aliases.push(node);
+                continue
+            }
+            _ => continue,
+        };
+        if exports.is_empty() || public {
+            items.extend(bindings.iter().map(|(name, typ)| DocItem { name: name.clone(), typ: typ.clone(), doc: doc.clone().unwrap_or_default() }));
+        }
+        names.extend(bindings);
+    }
+    items
+}
+
+/// the type the signature of the function declared by `declaration` gives it, after the type
+/// aliases in scope, if it gives its result type
+fn signature(aliases: &[&Node], declaration: &Node) -> Option<Type> {
+    let Ast::FunctionDeclaration { name, result: Some(_), .. } = &declaration.ast else { return None };
+    let mut signature = declaration.clone();
+    let Ast::FunctionDeclaration { body, requires, ensures, .. } = &mut signature.ast else { unreachable!() };
+    body.clear();
+    requires.clear();
+    ensures.clear();
+    let block = aliases.iter().copied().cloned().chain([signature]).collect::<Vec<_>>();
+    lower_declarations(&block).ok()?.remove(name)
+}
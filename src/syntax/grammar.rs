@@ -0,0 +1,214 @@
+//! a machine-readable grammar of the surface syntax, in `sanguinello.grammar`, and the
+//! sentences of it that the parser's tests check against the parser and the printer, so that a
+//! change to one that isn't made to the other is caught.
+
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+/// the grammar of the surface syntax
+pub const GRAMMAR: &str = include_str!("sanguinello.grammar");
+
+/// the token each kind of token in the grammar is written as in its sentences
+const SAMPLES: &[(&str, &str)] = &[("NAME", "x"), ("NUMBER", "1"), ("FLOAT", "1.5"), ("STRING", "\"s\""), ("BYTES", "b\"s\""), ("OPERATOR", "<+>")];
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum GrammarError {
+    #[error("expected {expected} at line {line}")]
+    Syntax {
+        expected: &'static str,
+        line: usize,
+    },
+
+    #[error("the rule {0} is used, but never defined")]
+    Undefined(String),
+
+    #[error("the rule {0} is defined twice")]
+    Redefined(String),
+
+    #[error("the rule {0} has no sentences without itself")]
+    Unproductive(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Symbol {
+    /// a token, written as it is in a sentence
+    Token(String),
+    /// the index of a rule
+    Rule(usize),
+}
+
+/// a rule: its name, and the sequences of symbols it can be
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub alternatives: Vec<Vec<Symbol>>,
+}
+
+/// a grammar, whose first rule is its start
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grammar {
+    pub rules: Vec<Rule>,
+    /// the fewest tokens each rule can be, which every rule has
+    shortest: Vec<Vec<String>>,
+}
+
+/// the words of a grammar, each with the line it's on
+fn words(source: &str) -> Result<Vec<(String, usize)>, GrammarError> {
+    let mut words = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let mut rest = line.trim_start();
+        while !rest.is_empty() && !rest.starts_with('#') {
+            let length = if let Some(quoted) = rest.strip_prefix('"') {
+                let close = quoted.find('"').ok_or(GrammarError::Syntax { expected: "a closing \"", line: i + 1 })?;
+                close + 2
+            } else if rest.starts_with(['=', '|', ';']) {
+                1
+            } else {
+                rest.find(|c: char| c.is_whitespace() || "=|;\"".contains(c)).unwrap_or(rest.len())
+            };
+            words.push((rest[..length].to_owned(), i + 1));
+            rest = rest[length..].trim_start();
+        }
+    }
+    Ok(words)
+}
+
+/// read a grammar in the notation of `sanguinello.grammar`
+pub fn parse(source: &str) -> Result<Grammar, GrammarError> {
+    let words = words(source)?;
+    let mut names: HashMap<&str, usize> = HashMap::new();
+    let mut definitions = vec![];
+    let mut words = words.iter();
+    while let Some((name, line)) = words.next() {
+        if !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+            return Err(GrammarError::Syntax { expected: "the name of a rule", line: *line })
+        }
+        if names.insert(name, definitions.len()).is_some() {
+            return Err(GrammarError::Redefined(name.clone()))
+        }
+        if !matches!(words.next(), Some((equals, _)) if equals == "=") {
+            return Err(GrammarError::Syntax { expected: "=", line: *line })
+        }
+        let mut alternatives = vec![vec![]];
+        loop {
+            match words.next() {
+                Some((word, _)) if word == ";" => break,
+                Some((word, _)) if word == "|" => alternatives.push(vec![]),
+                Some(word) => alternatives.last_mut().unwrap().push(word),
+                None => return Err(GrammarError::Syntax { expected: ";", line: *line }),
+            }
+        }
+        definitions.push((name.clone(), alternatives));
+    }
+
+    let mut rules = vec![];
+    for (name, alternatives) in definitions {
+        let mut symbols = vec![];
+        for alternative in alternatives {
+            let alternative = alternative.into_iter().map(|(word, _)| {
+                if let Some(token) = word.strip_prefix('"') {
+                    return Ok(Symbol::Token(token[..token.len() - 1].to_owned()))
+                }
+                if let Some((_, sample)) = SAMPLES.iter().find(|(kind, _)| kind == word) {
+                    return Ok(Symbol::Token(sample.to_string()))
+                }
+                names.get(word.as_str()).map(|&rule| Symbol::Rule(rule)).ok_or_else(|| GrammarError::Undefined(word.clone()))
+            });
+            symbols.push(alternative.collect::<Result<_, _>>()?);
+        }
+        rules.push(Rule { name, alternatives: symbols });
+    }
+    let shortest = shortest(&rules)?;
+    Ok(Grammar { rules, shortest })
+}
+
+/// the shortest sentence of each rule, found by expanding them until none gets shorter
+fn shortest(rules: &[Rule]) -> Result<Vec<Vec<String>>, GrammarError> {
+    let mut shortest: Vec<Option<Vec<String>>> = vec![None; rules.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, rule) in rules.iter().enumerate() {
+            for alternative in &rule.alternatives {
+                let sentence = alternative.iter()
+                                          .map(|symbol| match symbol {
+                                              Symbol::Token(token) => Some(vec![token.clone()]),
+                                              Symbol::Rule(rule) => shortest[*rule].clone(),
+                                          })
+                                          .collect::<Option<Vec<_>>>();
+                let Some(sentence) = sentence.map(|parts| parts.concat()) else { continue };
+                if shortest[i].as_ref().is_none_or(|tokens| sentence.len() < tokens.len()) {
+                    shortest[i] = Some(sentence);
+                    changed = true;
+                }
+            }
+        }
+    }
+    shortest.into_iter()
+            .zip(rules)
+            .map(|(tokens, rule)| tokens.ok_or_else(|| GrammarError::Unproductive(rule.name.clone())))
+            .collect()
+}
+
+impl Grammar {
+    /// the grammar of the surface syntax
+    pub fn surface() -> Grammar {
+        parse(GRAMMAR).expect("the grammar of the surface syntax is well-formed")
+    }
+
+    /// the tokens in the grammar, other than the ones standing in for kinds of tokens
+    pub fn tokens(&self) -> HashSet<&str> {
+        self.rules.iter()
+                  .flat_map(|rule| rule.alternatives.iter().flatten())
+                  .filter_map(|symbol| match symbol {
+                      Symbol::Token(token) if !SAMPLES.iter().any(|(_, sample)| sample == token) => Some(token.as_str()),
+                      _ => None,
+                  })
+                  .collect()
+    }
+
+    /// the sentences of the first rule that are its shortest sentence but for one rule, at
+    /// some depth up to `depth`, which is expanded another way: every alternative of every rule
+    /// within reach is taken in at least one of them. the tokens are separated by spaces.
+    pub fn sentences(&self, depth: usize) -> Vec<String> {
+        let mut memo = HashMap::new();
+        self.variants(0, depth, &mut memo).iter().map(|tokens| tokens.join(" ")).collect()
+    }
+
+    fn variants(&self, rule: usize, depth: usize, memo: &mut HashMap<(usize, usize), Vec<Vec<String>>>) -> Vec<Vec<String>> {
+        if depth == 0 {
+            return vec![self.shortest[rule].clone()]
+        }
+        if let Some(variants) = memo.get(&(rule, depth)) {
+            return variants.clone()
+        }
+        let mut variants = vec![];
+        let mut seen = HashSet::new();
+        for alternative in &self.rules[rule].alternatives {
+            let parts: Vec<Vec<String>> = alternative.iter()
+                                                     .map(|symbol| match symbol {
+                                                         Symbol::Token(token) => vec![token.clone()],
+                                                         Symbol::Rule(rule) => self.shortest[*rule].clone(),
+                                                     })
+                                                     .collect();
+            let mut candidates = vec![parts.concat()];
+            for (i, symbol) in alternative.iter().enumerate() {
+                if let Symbol::Rule(inner) = symbol {
+                    for variant in self.variants(*inner, depth - 1, memo) {
+                        let mut parts = parts.clone();
+                        parts[i] = variant;
+                        candidates.push(parts.concat());
+                    }
+                }
+            }
+            for candidate in candidates {
+                if seen.insert(candidate.clone()) {
+                    variants.push(candidate);
+                }
+            }
+        }
+        memo.insert((rule, depth), variants.clone());
+        variants
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use crate::sgir::{Position, Type};
+
+use super::ast::{Ast, Binder, Node, VARARGS};
+use super::completion::declared;
+use super::identifiers::{walk, Opening, Scoping};
+use super::lexer::{lex, Token};
+use super::parser::parse;
+use super::rename::binder_spans;
+
+/// what an inlay hint shows
+#[derive(Clone, Debug, PartialEq)]
+pub enum HintKind {
+    /// the type of a binding that isn't annotated, shown after its name
+    Type(Type),
+    /// the name of the parameter an argument is passed to, shown before it
+    Parameter(String),
+}
+
+/// a hint to show inline at a position in the source, which isn't part of the program
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hint {
+    pub position: Position,
+    pub kind: HintKind,
+}
+
+impl Display for Hint {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match &self.kind {
+            HintKind::Type(typ) => write!(f, ": {}", typ),
+            HintKind::Parameter(name) => write!(f, "{}:", name),
+        }
+    }
+}
+
+/// a name in scope, with its type when it's known, and the names of its parameters when it's
+/// bound to a function
+struct Binding {
+    name: String,
+    typ: Option<Type>,
+    parameters: Option<Vec<String>>,
+}
+
+struct Hinter<'a> {
+    tokens: &'a [Token],
+    /// the names in scope, innermost last
+    scopes: Vec<Vec<Binding>>,
+    /// the type aliases in scope, which the declarations after them are lowered with
+    aliases: Vec<&'a Node>,
+    /// how many type aliases were in scope as each scope opened
+    opened: Vec<usize>,
+    hints: Vec<Hint>,
+}
+
+impl<'a> Hinter<'a> {
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().flatten().rev().find(|binding| binding.name == name)
+    }
+
+    fn declared(&self, declaration: &Node) -> HashMap<String, Type> {
+        declared(&self.aliases, self.scopes.iter().flatten().map(|Binding { name, typ, .. }| (name, typ)), declaration)
+    }
+
+    fn bind(&mut self, name: &str, typ: Option<Type>, parameters: Option<Vec<String>>) {
+        self.scopes.last_mut().unwrap().push(Binding { name: name.to_owned(), typ, parameters });
+    }
+}
+
+impl<'a> Scoping<'a> for Hinter<'a> {
+    // an argument that's already named after its parameter doesn't need a hint, and neither does
+    // one that's given by its name, or one of those packed into a `...`
+    fn enter(&mut self, node: &'a Node) {
+        if let Ast::Call { function, arguments } = &node.ast {
+            if let Ast::Name(name) = &function.ast {
+                let parameters = self.lookup(name).and_then(|binding| binding.parameters.clone()).unwrap_or_default();
+                let positional = arguments.iter().take_while(|argument| !matches!(argument.ast, Ast::Named { .. }));
+                for (argument, parameter) in positional.zip(parameters).filter(|(_, parameter)| parameter != VARARGS) {
+                    if !matches!(&argument.ast, Ast::Name(name) if *name == parameter) {
+                        self.hints.push(Hint { position: argument.span.start, kind: HintKind::Parameter(parameter) });
+                    }
+                }
+            }
+        }
+    }
+
+    fn open(&mut self, _: &'a Node, opening: &Opening<'a>) {
+        self.opened.push(self.aliases.len());
+        self.scopes.push(opening.bindings().into_iter().map(|(name, typ)| Binding { name, typ, parameters: None }).collect());
+    }
+
+    fn close(&mut self) {
+        self.scopes.pop();
+        self.aliases.truncate(self.opened.pop().unwrap());
+    }
+
+    fn declare(&mut self, node: &'a Node) {
+        match &node.ast {
+            Ast::Local { names, values, .. } => {
+                let mut types = self.declared(node);
+                let start = self.tokens.partition_point(|token| token.span.start < node.span.start) + 1;
+                let spans = binder_spans(self.tokens, start, "=");
+                let parameters = match (&names[..], &values[..]) {
+                    ([_], [Node { ast: Ast::Function { parameters, .. }, .. }]) => Some(parameters.iter().map(|binder| binder.pattern.to_string()).collect()),
+                    _ => None,
+                };
+                for (i, (name, annotation)) in names.iter().flat_map(Binder::bindings).enumerate() {
+                    let typ = types.remove(&name).or_else(|| annotation.clone());
+                    if let (None, Some(typ), Some(span)) = (annotation, &typ, spans.get(i)) {
+                        self.hints.push(Hint { position: span.end, kind: HintKind::Type(typ.clone()) });
+                    }
+                    self.bind(&name, typ, parameters.clone());
+                }
+            }
+            Ast::FunctionDeclaration { name, parameters, .. } => {
+                let typ = self.declared(node).remove(name);
+                self.bind(name, typ, Some(parameters.iter().map(|binder| binder.pattern.to_string()).collect()));
+            }
+            Ast::MacroDeclaration { name, .. } => self.bind(name, None, None),
+            Ast::Reexport { name, alias, .. } => self.bind(alias.as_ref().unwrap_or(name), None, None),
+            Ast::TypeAlias { .. } => self.aliases.push(node),
+            _ => {}
+        }
+    }
+}
+
+/// the inlay hints for `source`, in the order they appear: the type of each local that isn't
+/// annotated, when the checker can tell what it is from the types of the names in scope, and
+/// the name of the parameter each argument is passed to, in calls of functions the program
+/// declares
+pub fn hints(source: &str) -> Vec<Hint> {
+    let Ok(tokens) = lex(source) else { return vec![] };
+    let program = parse(source).program;
+    let mut hinter = Hinter { tokens: &tokens, scopes: vec![vec![]], aliases: vec![], opened: vec![], hints: vec![] };
+    program.iter().for_each(|node| walk(&mut hinter, node));
+    hinter.hints.sort_by_key(|hint| hint.position);
+    hinter.hints
+}
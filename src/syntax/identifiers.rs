@@ -0,0 +1,266 @@
+use std::fmt::{self, Display, Formatter};
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::sgir::{Span, Type};
+
+use super::ast::{Ast, Binder, Block, Case, Node, Pattern};
+
+/// the characters besides UAX #31's that can continue a name, like the `!` in `alex!`
+const EXTRA_CONTINUE: &[char] = &['!'];
+
+/// whether a name can start with `c`: the XID_Start characters of UAX #31, plus `_`
+pub fn is_identifier_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
+
+/// whether `c` can be part of a name after its first character: the XID_Continue characters of
+/// UAX #31, plus the crate's extra symbols
+pub fn is_identifier_continue(c: char) -> bool {
+    unicode_ident::is_xid_continue(c) || EXTRA_CONTINUE.contains(&c)
+}
+
+/// the NFC normal form of a name, so that names that render the same are the same name however
+/// they were typed
+pub fn normalize(name: &str) -> String {
+    if name.is_ascii() { name.to_owned() } else { name.nfc().collect() }
+}
+
+/// the confusable skeleton of a name, per UTS #39: names with the same skeleton look alike
+fn skeleton(name: &str) -> String {
+    unicode_security::skeleton(name).collect()
+}
+
+/// a use or binding of `name` while a different name that looks just like it is in scope
+#[derive(Clone, Debug, PartialEq)]
+pub struct Confusable {
+    pub name: String,
+    pub similar: String,
+    pub span: Span,
+}
+
+impl Display for Confusable {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "`{}` at {}:{} looks like `{}`, which is also in scope",
+               self.name, self.span.start.line, self.span.start.column, self.similar)
+    }
+}
+
+/// what a scope binds when it opens, before anything in it
+pub(crate) enum Opening<'a> {
+    /// nothing, like a block
+    Nothing,
+    /// the parameters of a function
+    Parameters(&'a [Binder]),
+    /// the `result` of a function's `ensures` clauses, of its result type when it's annotated
+    Result(&'a Option<Type>),
+    /// the parameters of a macro
+    Macro(&'a [String]),
+    /// what the pattern of a case binds
+    Case(&'a Pattern),
+    /// the variable of a `for` loop, of the type it's known to have
+    Variable(&'a str, Option<Type>),
+}
+
+impl Opening<'_> {
+    /// the names this binds, from left to right
+    pub(crate) fn names(&self) -> Vec<String> {
+        match self {
+            Opening::Nothing => vec![],
+            Opening::Parameters(parameters) => parameters.iter().flat_map(|binder| binder.pattern.names()).cloned().collect(),
+            Opening::Result(_) => vec!["result".to_owned()],
+            Opening::Macro(parameters) => parameters.to_vec(),
+            Opening::Case(pattern) => pattern.names().into_iter().cloned().collect(),
+            Opening::Variable(variable, _) => vec![(*variable).to_owned()],
+        }
+    }
+
+    /// the names this binds, each with its type when it's known
+    pub(crate) fn bindings(&self) -> Vec<(String, Option<Type>)> {
+        match self {
+            Opening::Parameters(parameters) => parameters.iter().flat_map(Binder::bindings).collect(),
+            Opening::Result(result) => vec![("result".to_owned(), (*result).clone())],
+            Opening::Variable(variable, typ) => vec![((*variable).to_owned(), typ.clone())],
+            _ => self.names().into_iter().map(|name| (name, None)).collect(),
+        }
+    }
+}
+
+/// a part of a node, in the order its names are resolved
+pub(crate) enum Part<'a> {
+    /// a node in the scope around it
+    Node(&'a Node),
+    /// a use of a user-defined operator, written just before its operand, then the operand
+    Operator(&'a str, &'a Node),
+    /// a scope of its own, with what it binds to start with, and the parts in it
+    Scope(Opening<'a>, Vec<Part<'a>>),
+}
+
+/// the parts of `node`, which are where the scoping rules of the language are kept. a node that
+/// declares names binds them in the scope around it after all of its parts.
+pub(crate) fn parts<'a>(node: &'a Node) -> Vec<Part<'a>> {
+    let nodes = |block: &'a [Node]| block.iter().map(Part::Node).collect::<Vec<_>>();
+    let defaults = |parameters: &'a [Binder]| parameters.iter().filter_map(|binder| binder.default.as_ref()).map(Part::Node).collect::<Vec<_>>();
+    match &node.ast {
+        // a function can't refer to itself, so its name is bound after it
+        Ast::FunctionDeclaration { parameters, result, requires, ensures, body, .. } => {
+            let clauses = nodes(requires).into_iter()
+                                         .chain([Part::Scope(Opening::Result(result), nodes(ensures)), Part::Scope(Opening::Nothing, nodes(body))])
+                                         .collect();
+            defaults(parameters).into_iter().chain([Part::Scope(Opening::Parameters(parameters), clauses)]).collect()
+        }
+        Ast::Function { parameters, body, .. } => {
+            defaults(parameters).into_iter().chain([Part::Scope(Opening::Parameters(parameters), nodes(body))]).collect()
+        }
+        Ast::MacroDeclaration { parameters, body, .. } => vec![Part::Scope(Opening::Macro(parameters), nodes(body))],
+        Ast::If { condition, consequent, alternative } => {
+            let alternative = alternative.as_deref().map(|alternative| Part::Scope(Opening::Nothing, nodes(alternative)));
+            [Part::Node(condition), Part::Scope(Opening::Nothing, nodes(consequent))].into_iter().chain(alternative).collect()
+        }
+        Ast::Do(body) | Ast::Test { body, .. } => vec![Part::Scope(Opening::Nothing, nodes(body))],
+        Ast::IfTarget { consequent, alternative, .. } => {
+            std::iter::once(consequent).chain(alternative).map(|block| Part::Scope(Opening::Nothing, nodes(block))).collect()
+        }
+        // the guard of a case can see what its pattern binds, like its body
+        Ast::Match { scrutinee, cases } => {
+            let cases = cases.iter().map(|Case { pattern, guard, body }| Part::Scope(Opening::Case(pattern), guard.iter().chain(body).map(Part::Node).collect()));
+            std::iter::once(Part::Node(scrutinee)).chain(cases).collect()
+        }
+        Ast::While { condition, body } => vec![Part::Node(condition), Part::Scope(Opening::Nothing, nodes(body))],
+        // the condition of a `repeat` can see the locals of its body
+        Ast::Repeat { body, condition } => vec![Part::Scope(Opening::Nothing, body.iter().chain([&**condition]).map(Part::Node).collect())],
+        Ast::NumericFor { variable, start, stop, step, body } => {
+            [&**start, &**stop].into_iter()
+                               .chain(step.as_deref())
+                               .map(Part::Node)
+                               .chain([Part::Scope(Opening::Variable(variable, Some(Type::Number)), nodes(body))])
+                               .collect()
+        }
+        Ast::GenericFor { variable, iterable, body } => vec![Part::Node(iterable), Part::Scope(Opening::Variable(variable, None), nodes(body))],
+        Ast::Operators { first, rest } => {
+            std::iter::once(Part::Node(first)).chain(rest.iter().map(|(symbol, operand)| Part::Operator(symbol, operand))).collect()
+        }
+        _ => node.children().into_iter().map(Part::Node).collect(),
+    }
+}
+
+/// whether `node` binds names in the scope around it
+fn declares(node: &Node) -> bool {
+    matches!(node.ast, Ast::Local { .. } | Ast::FunctionDeclaration { .. } | Ast::MacroDeclaration { .. } | Ast::Reexport { .. } | Ast::TypeAlias { .. })
+}
+
+/// what a walk of the scopes of a program does as it meets the names in it
+pub(crate) trait Scoping<'a> {
+    /// a node, before its parts are walked
+    fn enter(&mut self, _node: &'a Node) {}
+
+    /// a use of the name `name`, which is `node`
+    fn name(&mut self, _node: &'a Node, _name: &'a str) {}
+
+    /// a use of the operator `symbol`, written just before `operand`
+    fn operator(&mut self, _symbol: &'a str, _operand: &'a Node) {}
+
+    /// a scope of `node` opening, with what `opening` binds in it
+    fn open(&mut self, node: &'a Node, opening: &Opening<'a>);
+
+    /// the innermost scope closing
+    fn close(&mut self);
+
+    /// `node` binding the names it declares in the innermost scope
+    fn declare(&mut self, _node: &'a Node) {}
+}
+
+/// walk `node` with `scoping`, opening and closing its scopes as the language does
+pub(crate) fn walk<'a>(scoping: &mut impl Scoping<'a>, node: &'a Node) {
+    scoping.enter(node);
+    if let Ast::Name(name) = &node.ast {
+        scoping.name(node, name);
+    }
+    walk_parts(scoping, node, parts(node));
+    if declares(node) {
+        scoping.declare(node);
+    }
+}
+
+fn walk_parts<'a>(scoping: &mut impl Scoping<'a>, node: &'a Node, parts: Vec<Part<'a>>) {
+    for part in parts {
+        match part {
+            Part::Node(child) => walk(scoping, child),
+            Part::Operator(symbol, operand) => {
+                scoping.operator(symbol, operand);
+                walk(scoping, operand);
+            }
+            Part::Scope(opening, parts) => {
+                scoping.open(node, &opening);
+                walk_parts(scoping, node, parts);
+                scoping.close();
+            }
+        }
+    }
+}
+
+/// the names in scope, innermost scope last, each with its skeleton
+struct Scopes {
+    scopes: Vec<Vec<(String, String)>>,
+    found: Vec<Confusable>,
+}
+
+impl Scopes {
+    /// note `name` if a different name that looks just like it is in scope
+    fn check(&mut self, name: &str, span: Span) {
+        let skeleton = skeleton(name);
+        let similar = self.scopes.iter().flatten().find(|(bound, other)| bound != name && *other == skeleton);
+        if let Some((similar, _)) = similar {
+            self.found.push(Confusable { name: name.to_owned(), similar: similar.clone(), span });
+        }
+    }
+
+    fn bind(&mut self, name: &str, span: Span) {
+        self.check(name, span);
+        let skeleton = skeleton(name);
+        self.scopes.last_mut().unwrap().push((name.to_owned(), skeleton));
+    }
+}
+
+impl<'a> Scoping<'a> for Scopes {
+    /// a use of `name`, which is only confusing if `name` itself isn't in scope: the binding of
+    /// the lookalike was already noted otherwise
+    fn name(&mut self, node: &'a Node, name: &'a str) {
+        if !self.scopes.iter().flatten().any(|(bound, _)| bound == name) {
+            self.check(name, node.span);
+        }
+    }
+
+    fn open(&mut self, node: &'a Node, opening: &Opening<'a>) {
+        self.scopes.push(vec![]);
+        for name in opening.names() {
+            self.bind(&name, node.span);
+        }
+    }
+
+    fn close(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, node: &'a Node) {
+        let names = match &node.ast {
+            Ast::Local { names, .. } => names.iter().flat_map(|binder| binder.pattern.names()).cloned().collect(),
+            Ast::FunctionDeclaration { name, .. } | Ast::MacroDeclaration { name, .. } => vec![name.clone()],
+            Ast::Reexport { name, alias, .. } => vec![alias.as_ref().unwrap_or(name).clone()],
+            _ => vec![],
+        };
+        for name in names {
+            self.bind(&name, node.span);
+        }
+    }
+}
+
+/// the names in `program` that look like a different name in the same scope, e.g. `scope` and
+/// `ѕcope` with a Cyrillic `ѕ`. these are warnings, which a caller can opt into.
+pub fn confusables(program: &Block) -> Vec<Confusable> {
+    let mut scopes = Scopes { scopes: vec![vec![]], found: vec![] };
+    for node in program {
+        walk(&mut scopes, node);
+    }
+    scopes.found
+}
@@ -0,0 +1,333 @@
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use crate::sgir::bench::Statistics;
+use crate::sgir::{Interrupt, Position, Span, Type};
+
+use super::ast::{Ast, Binder, Node};
+use super::completion::declared;
+use super::lexer::LexError;
+use super::parser::{parse_items, Parse, SyntaxError};
+
+/// a top-level statement of a document, with the syntax errors found in it
+#[derive(Clone, Debug, PartialEq)]
+struct Item {
+    node: Node,
+    errors: Vec<SyntaxError>,
+    /// the byte offset where the node ends. an item owns the source from where the one before
+    /// it ended up to here, and the last one owns the rest of the source too.
+    end: usize,
+}
+
+/// a change to a document: the bytes in `range` are replaced with `text`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// how much of a document was reparsed after an edit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reparse {
+    /// only the top-level statements around the edit were reparsed, `bytes` of source in all
+    Incremental { bytes: usize },
+    /// the edit couldn't be confined to the statements around it, so the whole document was
+    /// reparsed
+    Full,
+}
+
+/// the names a top-level declaration binds, in order, each with its type if the checker can tell
+/// what it is
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declared {
+    pub span: Span,
+    pub types: Vec<(String, Option<Type>)>,
+}
+
+/// what checking a document found, as far as it got
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checked {
+    /// the top-level declarations that were checked, in order
+    pub declarations: Vec<Declared>,
+    /// whether all of them were, rather than checking being interrupted before it finished
+    pub complete: bool,
+}
+
+/// a source file kept parsed as it's edited, for the language server and watch mode. an edit
+/// reparses the top-level statements it touches and their neighbours, and keeps the rest.
+#[derive(Clone, Debug)]
+pub struct Document {
+    source: String,
+    /// the byte offset each line starts at
+    lines: Vec<usize>,
+    items: Vec<Item>,
+    /// the error the source failed to lex with, if it did
+    failed: Option<LexError>,
+    /// the top-level statements checked so far, as they were when they were checked, with what
+    /// the declarations among them bind
+    checked: Vec<(Node, Option<Declared>)>,
+}
+
+impl Document {
+    pub fn new(source: &str) -> Document {
+        let mut document = Document { source: source.to_owned(), lines: vec![], items: vec![], failed: None, checked: vec![] };
+        document.reparse();
+        document
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// the document as a whole, as `parser::parse` would find it
+    pub fn parse(&self) -> Parse {
+        match &self.failed {
+            Some(error) => Parse { program: vec![], errors: vec![error.clone().into()] },
+            None => Parse { program: self.items.iter().map(|item| item.node.clone()).collect(),
+                            errors: self.items.iter().flat_map(|item| item.errors.clone()).collect() },
+        }
+    }
+
+    /// check the top-level declarations in order, for the types of the names they bind, stopping
+    /// before any of them if `interrupt` asks to, e.g. because the document was edited again or
+    /// its deadline passed. a check remembers the statements it got through, and the next one
+    /// picks up after those of them that haven't changed since, so an interrupted check isn't
+    /// wasted.
+    pub fn check(&mut self, interrupt: &Interrupt) -> Checked {
+        let unchanged = self.checked.iter()
+                                    .zip(&self.items)
+                                    .take_while(|((node, _), item)| *node == item.node)
+                                    .count();
+        self.checked.truncate(unchanged);
+        let mut complete = true;
+        for item in &self.items[unchanged..] {
+            if interrupt.requested() {
+                complete = false;
+                break
+            }
+            let declared = declare(&self.checked, &item.node);
+            self.checked.push((item.node.clone(), declared));
+        }
+        Checked { declarations: self.checked.iter().filter_map(|(_, declared)| declared.clone()).collect(), complete }
+    }
+
+    /// apply `edit`, reparsing as little as possible. the range has to lie on character
+    /// boundaries within the source.
+    pub fn edit(&mut self, edit: &Edit) -> Reparse {
+        let Edit { range, text } = edit;
+        let start = self.position(range.start);
+        let old_end = self.position(range.end);
+        self.source.replace_range(range.clone(), text);
+        self.lines = line_starts(&self.source);
+        let new_end = self.position(range.start + text.len());
+
+        match self.reparse_around(range, text.len(), start, old_end, new_end) {
+            Some(bytes) => Reparse::Incremental { bytes },
+            None => {
+                self.reparse();
+                Reparse::Full
+            }
+        }
+    }
+
+    fn reparse(&mut self) {
+        self.lines = line_starts(&self.source);
+        match parse_items(&self.source, Position { line: 1, column: 1 }) {
+            Ok(items) => {
+                self.items = self.located(items);
+                self.failed = None;
+            }
+            Err(error) => {
+                self.items = vec![];
+                self.failed = Some(error);
+            }
+        }
+    }
+
+    /// reparse the items an edit of `range` touched, now that it's been replaced by `length`
+    /// bytes, along with the item on either side of them. those neighbours have to come out as
+    /// they were, or the edit might have changed where the items around it start and end, and
+    /// `None` is returned for the whole document to be reparsed instead.
+    fn reparse_around(&mut self, range: &Range<usize>, length: usize, start: Position, old_end: Position,
+                      new_end: Position) -> Option<usize> {
+        if self.failed.is_some() || self.items.is_empty() {
+            return None
+        }
+        let last = self.items.len() - 1;
+        let first = self.items.iter().position(|item| item.end >= range.start).unwrap_or(last);
+        let touched = (first..=last).rev()
+                                    .find(|&i| i == 0 || self.items[i - 1].end < range.end)
+                                    .unwrap_or(first);
+        let (before, after) = (first.saturating_sub(1), (touched + 1).min(last));
+
+        let (chunk_start, position) = match before {
+            0 => (0, Position { line: 1, column: 1 }),
+            _ => (self.items[before - 1].end, self.items[before - 1].node.span.end),
+        };
+        let shift = |offset: usize| offset + length - range.len();
+        let chunk_end = if after == last { self.source.len() } else { shift(self.items[after].end) };
+        let mut reparsed = parse_items(&self.source[chunk_start..chunk_end], position).ok()?;
+
+        let map = |position: &mut Position| *position = map_position(*position, start, old_end, new_end);
+        if before < first && reparsed.first()?.0 != self.items[before].node {
+            return None
+        }
+        let mut next = None;
+        if after > touched {
+            let mut item = self.items[after].clone();
+            shift_item(&mut item, &map, shift);
+            if reparsed.len() < 1 + (before < first) as usize || reparsed.pop()?.0 != item.node {
+                return None
+            }
+            next = Some(item);
+        }
+
+        let items = self.located(reparsed);
+        let rest = self.items.split_off(after + 1);
+        self.items.truncate(before);
+        self.items.extend(items);
+        self.items.extend(next);
+        for mut item in rest {
+            shift_item(&mut item, &map, shift);
+            self.items.push(item);
+        }
+        Some(chunk_end - chunk_start)
+    }
+
+    /// items for statements parsed from the source as it is now
+    fn located(&self, parsed: Vec<(Node, Vec<SyntaxError>)>) -> Vec<Item> {
+        parsed.into_iter()
+              .map(|(node, errors)| {
+                  let end = self.offset(node.span.end);
+                  Item { node, errors, end }
+              })
+              .collect()
+    }
+
+    /// the position of the character at `offset`
+    fn position(&self, offset: usize) -> Position {
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        let column = self.source[self.lines[line]..offset].chars().count() + 1;
+        Position { line: line + 1, column }
+    }
+
+    /// the byte offset of the character at `position`
+    fn offset(&self, position: Position) -> usize {
+        let start = self.lines[position.line - 1];
+        self.source[start..].char_indices()
+                            .nth(position.column - 1)
+                            .map_or(self.source.len(), |(offset, _)| start + offset)
+    }
+}
+
+/// what `node` binds, if it's a declaration, checked after the statements in `checked`
+fn declare(checked: &[(Node, Option<Declared>)], node: &Node) -> Option<Declared> {
+    let aliases = checked.iter()
+                         .map(|(node, _)| node)
+                         .filter(|node| matches!(node.ast, Ast::TypeAlias { .. }))
+                         .collect::<Vec<_>>();
+    let names = checked.iter()
+                       .filter_map(|(_, declared)| declared.as_ref())
+                       .flat_map(|declared| &declared.types)
+                       .map(|(name, typ)| (name, typ));
+    let types = match &node.ast {
+        Ast::Local { names: binders, .. } => {
+            let mut types = declared(&aliases, names, node);
+            binders.iter()
+                   .flat_map(Binder::bindings)
+                   .map(|(name, annotation)| {
+                       let typ = types.remove(&name).or(annotation);
+                       (name, typ)
+                   })
+                   .collect()
+        }
+        Ast::FunctionDeclaration { name, .. } => vec![(name.clone(), declared(&aliases, names, node).remove(name))],
+        _ => return None,
+    };
+    Some(Declared { span: node.span, types })
+}
+
+/// the byte offset each line of `source` starts at
+fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0).chain(source.match_indices('\n').map(|(offset, _)| offset + 1)).collect()
+}
+
+/// where `position`, which came after an edit from `start` to `old_end`, is once the edit
+/// ends at `new_end` instead
+fn map_position(position: Position, start: Position, old_end: Position, new_end: Position) -> Position {
+    if position < start {
+        position
+    } else if position.line == old_end.line {
+        Position { line: new_end.line, column: position.column - old_end.column + new_end.column }
+    } else {
+        Position { line: position.line + new_end.line - old_end.line, ..position }
+    }
+}
+
+/// move an item that came after an edit to where it is now
+fn shift_item(item: &mut Item, map: &impl Fn(&mut Position), shift: impl Fn(usize) -> usize) {
+    let mut span = |span: &mut Span| {
+        map(&mut span.start);
+        map(&mut span.end)
+    };
+    item.node.for_each_span(&mut span);
+    for error in &mut item.errors {
+        if let SyntaxError::Expected { span: error_span, .. } = error {
+            span(error_span);
+        }
+    }
+    item.end = shift(item.end);
+}
+
+/// a comparison of reparsing a document in full after every edit against reparsing it
+/// incrementally
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    /// wall time to apply all the edits, in seconds
+    pub full: Statistics,
+    pub incremental: Statistics,
+    /// how many bytes were reparsed incrementally, against how many were reparsed in full
+    pub reparsed: usize,
+    pub total: usize,
+}
+
+/// apply `edits` to `source` in order `iterations` times, both ways
+pub fn measure(source: &str, edits: &[Edit], iterations: usize) -> Comparison {
+    assert!(iterations > 0, "a benchmark needs at least one iteration");
+
+    let (mut full, mut incremental) = (vec![], vec![]);
+    let (mut reparsed, mut total) = (0, 0);
+    for _ in 0..iterations {
+        let mut text = source.to_owned();
+        let start = Instant::now();
+        for Edit { range, text: replacement } in edits {
+            text.replace_range(range.clone(), replacement);
+            total += text.len();
+            super::parser::parse(&text);
+        }
+        full.push(start.elapsed().as_secs_f64());
+
+        let mut document = Document::new(source);
+        let start = Instant::now();
+        for edit in edits {
+            reparsed += match document.edit(edit) {
+                Reparse::Incremental { bytes } => bytes,
+                Reparse::Full => document.source().len(),
+            };
+        }
+        incremental.push(start.elapsed().as_secs_f64());
+    }
+
+    Comparison { full: Statistics::of(&full), incremental: Statistics::of(&incremental), reparsed, total }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "full: median {:?}, incremental: median {:?}, {} of {} bytes reparsed",
+               Duration::from_secs_f64(self.full.median),
+               Duration::from_secs_f64(self.incremental.median),
+               self.reparsed,
+               self.total)
+    }
+}
@@ -0,0 +1,513 @@
+use std::fmt::{self, Display, Formatter, Write};
+
+use thiserror::Error;
+
+use crate::sgir::operators::Fixity;
+use crate::sgir::{numbers, Position, Span, Type};
+
+use super::ast::{Ast, Attribute, Binder, Case, Node, Pattern};
+use super::lexer::{lex, Comment};
+use super::parser::parse;
+
+/// the version of the format `dump` produces, which changes whenever its shape does
+pub const AST_JSON_VERSION: i64 = 18;
+
+/// a JSON value, whose `Display` is its compact text. objects keep their fields in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Boolean(bool),
+    Number(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Boolean(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", numbers::format(*value, 10)),
+            Json::Float(value) => write!(f, "{}", numbers::format_float(*value)),
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, field)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// the most arrays and objects `parse` reads inside one another, so that deeply nested text can't
+/// overflow the stack
+const MAX_DEPTH: usize = 256;
+
+/// why a JSON text couldn't be read, at the offset of the byte where it went wrong
+#[derive(Debug, Error, Clone, PartialEq)]
+#[error("{message} at byte {offset}")]
+pub struct JsonError {
+    pub message: String,
+    pub offset: usize,
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    offset: usize,
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, JsonError> {
+        Err(JsonError { message: message.into(), offset: self.offset })
+    }
+
+    /// an error about what's at the offset, which isn't `expected`
+    fn unexpected<T>(&self, expected: &str) -> Result<T, JsonError> {
+        match self.peek() {
+            Some(c) => self.error(format!("expected {}, found `{}`", expected, c)),
+            None => self.error(format!("expected {}, found the end", expected)),
+        }
+    }
+
+    fn whitespace(&mut self) {
+        let rest = &self.text[self.offset..];
+        self.offset += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    /// skip `token`, if it's next
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.text[self.offset..].starts_with(token);
+        if found {
+            self.offset += token.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.whitespace();
+        match self.peek() {
+            Some('{') => self.nested(Reader::object),
+            Some('[') => self.nested(Reader::array),
+            Some('"') => self.string().map(Json::String),
+            Some('-' | '0'..='9') => self.number(),
+            _ if self.eat("null") => Ok(Json::Null),
+            _ if self.eat("true") => Ok(Json::Boolean(true)),
+            _ if self.eat("false") => Ok(Json::Boolean(false)),
+            _ => self.unexpected("a value"),
+        }
+    }
+
+    fn nested(&mut self, read: fn(&mut Self) -> Result<Json, JsonError>) -> Result<Json, JsonError> {
+        if self.depth == MAX_DEPTH {
+            return self.error(format!("arrays and objects nested more than {} deep", MAX_DEPTH))
+        }
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    /// the elements of `[...]`, each read by `element`, once the `open` bracket is next
+    fn elements<T>(&mut self, open: &str, close: &str, mut element: impl FnMut(&mut Self) -> Result<T, JsonError>) -> Result<Vec<T>, JsonError> {
+        self.eat(open);
+        self.whitespace();
+        let mut elements = vec![];
+        if self.eat(close) {
+            return Ok(elements)
+        }
+        loop {
+            elements.push(element(self)?);
+            self.whitespace();
+            if self.eat(close) {
+                return Ok(elements)
+            }
+            if !self.eat(",") {
+                return self.unexpected(&format!("`,` or `{}`", close))
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.elements("[", "]", Reader::value).map(Json::Array)
+    }
+
+    /// an object, whose fields must have names of their own
+    fn object(&mut self) -> Result<Json, JsonError> {
+        let mut fields = vec![];
+        self.elements("{", "}", |reader| {
+            reader.whitespace();
+            if reader.peek() != Some('"') {
+                return reader.unexpected("a field name")
+            }
+            let start = reader.offset;
+            let field = reader.string()?;
+            if fields.contains(&field) {
+                return Err(JsonError { message: format!("the field {} appears more than once", field), offset: start })
+            }
+            fields.push(field.clone());
+            reader.whitespace();
+            if !reader.eat(":") {
+                return reader.unexpected("`:`")
+            }
+            Ok((field, reader.value()?))
+        }).map(Json::Object)
+    }
+
+    /// four hex digits of a `\u` escape
+    fn code_unit(&mut self) -> Result<u32, JsonError> {
+        match self.text.get(self.offset..self.offset + 4).filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit())) {
+            Some(digits) => {
+                self.offset += 4;
+                Ok(u32::from_str_radix(digits, 16).unwrap())
+            }
+            None => self.error("expected four hex digits"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.eat("\"");
+        let mut value = String::new();
+        loop {
+            let start = self.offset;
+            let Some(c) = self.peek() else { return self.error("unterminated string") };
+            self.offset += c.len_utf8();
+            match c {
+                '"' => return Ok(value),
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            self.offset += 1;
+                            let unit = self.code_unit()?;
+                            // a character outside of the basic multilingual plane is a surrogate pair
+                            let code = if (0xd800..0xdc00).contains(&unit) && self.eat("\\u") {
+                                let low = self.code_unit()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(JsonError { message: "unpaired surrogate".to_owned(), offset: start })
+                                }
+                                0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                            } else {
+                                unit
+                            };
+                            match char::from_u32(code) {
+                                Some(c) => value.push(c),
+                                None => return Err(JsonError { message: "unpaired surrogate".to_owned(), offset: start }),
+                            }
+                            continue
+                        }
+                        _ => return self.unexpected("an escape"),
+                    };
+                    self.offset += 1;
+                    value.push(escaped);
+                }
+                c if c < ' ' => return Err(JsonError { message: "control character in a string".to_owned(), offset: start }),
+                c => value.push(c),
+            }
+        }
+    }
+
+    /// a number, which is a `Json::Number` if it's an integer that fits in one, and otherwise a
+    /// `Json::Float`
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.offset;
+        let digits = |reader: &mut Self| {
+            let rest = &reader.text[reader.offset..];
+            let length = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            reader.offset += length;
+            length
+        };
+        self.eat("-");
+        let whole = self.offset;
+        if digits(self) == 0 {
+            return self.unexpected("a digit")
+        }
+        if self.text[whole..].starts_with('0') && self.offset - whole > 1 {
+            return Err(JsonError { message: "a number with a leading zero".to_owned(), offset: whole })
+        }
+        let mut integer = true;
+        if self.eat(".") {
+            integer = false;
+            if digits(self) == 0 {
+                return self.unexpected("a digit")
+            }
+        }
+        if self.eat("e") || self.eat("E") {
+            integer = false;
+            let _ = self.eat("+") || self.eat("-");
+            if digits(self) == 0 {
+                return self.unexpected("a digit")
+            }
+        }
+        let text = &self.text[start..self.offset];
+        match numbers::parse(text, 10).filter(|_| integer) {
+            Some(value) => Ok(Json::Number(value)),
+            None => match numbers::parse_float(text) {
+                Some(value) => Ok(Json::Float(value)),
+                None => Err(JsonError { message: "a number too large for a float".to_owned(), offset: start }),
+            },
+        }
+    }
+}
+
+/// the JSON value `text` writes, with nothing but whitespace around it
+pub fn parse_json(text: &str) -> Result<Json, JsonError> {
+    let mut reader = Reader { text, offset: 0, depth: 0 };
+    let value = reader.value()?;
+    reader.whitespace();
+    match reader.peek() {
+        None => Ok(value),
+        Some(_) => reader.unexpected("the end"),
+    }
+}
+
+fn write_string(f: &mut Formatter, value: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(field, value)| (field.to_owned(), value)).collect())
+}
+
+fn string(value: &str) -> Json {
+    Json::String(value.to_owned())
+}
+
+fn position(position: Position) -> Json {
+    object(vec![("line", Json::Number(position.line as i64)), ("column", Json::Number(position.column as i64))])
+}
+
+pub(crate) fn span(span: Span) -> Json {
+    object(vec![("start", position(span.start)), ("end", position(span.end))])
+}
+
+/// a type as it would be written, which `parse_type` reads back
+fn typ(typ: &Type) -> Json {
+    Json::String(typ.to_string())
+}
+
+fn optional_type(annotation: &Option<Type>) -> Json {
+    annotation.as_ref().map_or(Json::Null, typ)
+}
+
+fn nodes(nodes: &[Node]) -> Json {
+    Json::Array(nodes.iter().map(node).collect())
+}
+
+fn optional_string(value: &Option<String>) -> Json {
+    value.as_ref().map_or(Json::Null, |value| string(value))
+}
+
+fn strings(names: &[String]) -> Json {
+    Json::Array(names.iter().map(|name| string(name)).collect())
+}
+
+/// a name as a string, or a pattern that takes a value apart as an object of its kind and parts
+fn pattern(pattern: &Pattern) -> Json {
+    match pattern {
+        Pattern::Name(name) => string(name),
+        Pattern::Tuple(patterns) => {
+            object(vec![("kind", string("Tuple")), ("elements", Json::Array(patterns.iter().map(self::pattern).collect()))])
+        }
+        Pattern::Record(fields) => {
+            let fields = fields.iter().map(|(field, pattern)| object(vec![("name", string(field)), ("pattern", self::pattern(pattern))]));
+            object(vec![("kind", string("Record")), ("fields", Json::Array(fields.collect()))])
+        }
+        Pattern::Wildcard => object(vec![("kind", string("Wildcard"))]),
+        Pattern::Boolean(value) => object(vec![("kind", string("Boolean")), ("value", Json::Boolean(*value))]),
+        Pattern::Number(value) => object(vec![("kind", string("Number")), ("value", Json::Number(*value))]),
+        Pattern::String(value) => object(vec![("kind", string("String")), ("value", string(value))]),
+        Pattern::Variant { tag, payload } => {
+            object(vec![("kind", string("Variant")), ("tag", string(tag)), ("payload", self::pattern(payload))])
+        }
+        Pattern::Or(alternatives) => {
+            object(vec![("kind", string("Or")), ("alternatives", Json::Array(alternatives.iter().map(self::pattern).collect()))])
+        }
+    }
+}
+
+/// a binder of a name is written with the name as it always was, and any other with its pattern
+fn binders(binders: &[Binder]) -> Json {
+    Json::Array(binders.iter()
+                       .map(|Binder { pattern: binder, annotation, default }| {
+                           let bound = match binder {
+                               Pattern::Name(name) => ("name", string(name)),
+                               binder => ("pattern", pattern(binder)),
+                           };
+                           object(vec![bound, ("annotation", optional_type(annotation)), ("default", default.as_ref().map_or(Json::Null, node))])
+                       })
+                       .collect())
+}
+
+fn attribute(Attribute { name, arguments, span: at }: &Attribute) -> Json {
+    object(vec![("name", string(name)), ("arguments", strings(arguments)), ("span", span(*at))])
+}
+
+fn attributes(attributes: &[Attribute]) -> Json {
+    Json::Array(attributes.iter().map(attribute).collect())
+}
+
+fn comment(Comment { text, block, span: at }: &Comment) -> Json {
+    object(vec![("text", string(text)), ("block", Json::Boolean(*block)), ("span", span(*at))])
+}
+
+/// a node as an object with its `kind`, the name of its variant, its `span`, and a field for each
+/// of its parts
+pub fn node(node: &Node) -> Json {
+    let child = |child: &Node| self::node(child);
+    let (kind, fields) = match &node.ast {
+        Ast::Name(name) => ("Name", vec![("name", string(name))]),
+        Ast::Boolean(value) => ("Boolean", vec![("value", Json::Boolean(*value))]),
+        Ast::Number(value) => ("Number", vec![("value", Json::Number(*value))]),
+        Ast::Float(value) => ("Float", vec![("value", Json::Float(*value))]),
+        Ast::String(value) => ("String", vec![("value", string(value))]),
+        Ast::Bytes(value) => ("Bytes", vec![("value", Json::Array(value.iter().map(|byte| Json::Number((*byte).into())).collect()))]),
+        Ast::Tuple(elements) => ("Tuple", vec![("elements", nodes(elements))]),
+        Ast::Record(fields) => {
+            let fields = fields.iter()
+                               .map(|(field, value)| object(vec![("field", string(field)), ("value", child(value))]))
+                               .collect();
+            ("Record", vec![("fields", Json::Array(fields))])
+        }
+        Ast::Update { record, fields } => {
+            let fields = fields.iter()
+                               .map(|(field, value)| object(vec![("field", string(field)), ("value", child(value))]))
+                               .collect();
+            ("Update", vec![("record", child(record)), ("fields", Json::Array(fields))])
+        }
+        Ast::Call { function, arguments } => ("Call", vec![("function", child(function)), ("arguments", nodes(arguments))]),
+        Ast::Named { name, value } => ("Named", vec![("name", string(name)), ("value", child(value))]),
+        Ast::Placeholder => ("Placeholder", vec![]),
+        Ast::Field { record, field } => ("Field", vec![("record", child(record)), ("field", string(field))]),
+        Ast::Unary { operator, operand } => ("Unary", vec![("operator", string(operator)), ("operand", child(operand))]),
+        Ast::Operators { first, rest } => {
+            let rest = rest.iter()
+                           .map(|(operator, operand)| object(vec![("operator", string(operator)), ("operand", child(operand))]))
+                           .collect();
+            ("Operators", vec![("first", child(first)), ("rest", Json::Array(rest))])
+        }
+        Ast::Function { type_parameters, parameters, result, body } => {
+            ("Function", vec![("type_parameters", strings(type_parameters)), ("parameters", binders(parameters)),
+                              ("result", optional_type(result)), ("body", nodes(body))])
+        }
+        Ast::Ascription { expression, typ: ascribed } => ("Ascription", vec![("expression", child(expression)), ("type", typ(ascribed))]),
+        Ast::Instantiate { function, arguments } => {
+            ("Instantiate", vec![("function", child(function)), ("arguments", Json::Array(arguments.iter().map(typ).collect()))])
+        }
+        Ast::If { condition, consequent, alternative } => {
+            ("If", vec![("condition", child(condition)), ("consequent", nodes(consequent)),
+                        ("alternative", alternative.as_ref().map_or(Json::Null, |alternative| nodes(alternative)))])
+        }
+        Ast::Do(body) => ("Do", vec![("body", nodes(body))]),
+        Ast::IfTarget { target, consequent, alternative } => {
+            ("IfTarget", vec![("target", Json::String(target.clone())), ("consequent", nodes(consequent)),
+                              ("alternative", alternative.as_ref().map_or(Json::Null, |alternative| nodes(alternative)))])
+        }
+        Ast::Match { scrutinee, cases } => {
+            let cases = cases.iter()
+                             .map(|Case { pattern, guard, body }| {
+                                 object(vec![("pattern", self::pattern(pattern)), ("guard", guard.as_ref().map_or(Json::Null, child)),
+                                             ("body", nodes(body))])
+                             })
+                             .collect();
+            ("Match", vec![("scrutinee", child(scrutinee)), ("cases", Json::Array(cases))])
+        }
+        Ast::Local { attributes: attributed, public, names, values, doc } => {
+            ("Local", vec![("attributes", attributes(attributed)), ("public", Json::Boolean(*public)), ("names", binders(names)),
+                           ("values", nodes(values)), ("doc", optional_string(doc))])
+        }
+        Ast::Assign { targets, values } => ("Assign", vec![("targets", nodes(targets)), ("values", nodes(values))]),
+        Ast::TypeAlias { name, parameters, typ: aliased, doc } => {
+            ("TypeAlias", vec![("name", string(name)), ("parameters", strings(parameters)), ("type", typ(aliased)),
+                               ("doc", optional_string(doc))])
+        }
+        Ast::MacroDeclaration { name, parameters, body } => {
+            ("MacroDeclaration", vec![("name", string(name)), ("parameters", strings(parameters)), ("body", nodes(body))])
+        }
+        Ast::FunctionDeclaration { attributes: attributed, public, total, operator, name, type_parameters, parameters, result, requires, ensures, body, doc } => {
+            let operator = operator.map_or(Json::Null, |Fixity { precedence, associativity }| {
+                object(vec![("precedence", Json::Number(precedence.into())), ("associativity", string(&associativity.to_string()))])
+            });
+            ("FunctionDeclaration", vec![("attributes", attributes(attributed)), ("public", Json::Boolean(*public)),
+                                         ("total", Json::Boolean(*total)), ("operator", operator), ("name", string(name)),
+                                         ("type_parameters", strings(type_parameters)), ("parameters", binders(parameters)),
+                                         ("result", optional_type(result)), ("requires", nodes(requires)),
+                                         ("ensures", nodes(ensures)), ("body", nodes(body)), ("doc", optional_string(doc))])
+        }
+        Ast::Return(values) => ("Return", vec![("values", nodes(values))]),
+        Ast::While { condition, body } => ("While", vec![("condition", child(condition)), ("body", nodes(body))]),
+        Ast::Repeat { body, condition } => ("Repeat", vec![("body", nodes(body)), ("condition", child(condition))]),
+        Ast::NumericFor { variable, start, stop, step, body } => {
+            ("NumericFor", vec![("variable", string(variable)), ("start", child(start)), ("stop", child(stop)),
+                                ("step", step.as_deref().map_or(Json::Null, child)), ("body", nodes(body))])
+        }
+        Ast::GenericFor { variable, iterable, body } => {
+            ("GenericFor", vec![("variable", string(variable)), ("iterable", child(iterable)), ("body", nodes(body))])
+        }
+        Ast::Reexport { module, name, alias } => {
+            ("Reexport", vec![("module", string(module)), ("name", string(name)),
+                              ("alias", optional_string(alias))])
+        }
+        Ast::Break => ("Break", vec![]),
+        Ast::Continue => ("Continue", vec![]),
+        Ast::Error => ("Error", vec![]),
+        Ast::FileAttribute(attribute) => ("FileAttribute", vec![("attribute", self::attribute(attribute))]),
+        Ast::Test { name, body } => ("Test", vec![("name", string(name)), ("body", nodes(body))]),
+    };
+    object([("kind", string(kind)), ("span", span(node.span))].into_iter().chain(fields).collect())
+}
+
+/// the surface syntax of `source` as JSON, for tools that don't link the crate: an object with
+/// the `version` of the format, the `program` as parsed, with `Error` nodes where it couldn't be,
+/// every `comment` with its span, and the message of each syntax error in `errors`
+pub fn dump(source: &str) -> Json {
+    let parsed = parse(source);
+    let comments = lex(source).map(|tokens| {
+        tokens.iter().flat_map(|token| token.leading.iter().chain(&token.trailing)).map(comment).collect()
+    });
+    object(vec![("version", Json::Number(AST_JSON_VERSION)),
+                ("program", nodes(&parsed.program)),
+                ("comments", Json::Array(comments.unwrap_or_default())),
+                ("errors", Json::Array(parsed.errors.iter().map(|error| Json::String(error.to_string())).collect()))])
+}
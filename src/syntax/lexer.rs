@@ -0,0 +1,523 @@
+use std::fmt::{self, Display, Formatter};
+
+use thiserror::Error;
+
+use crate::sgir::{numbers, Position, Span};
+
+use super::identifiers::{is_identifier_continue, is_identifier_start, normalize};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum LexError {
+    #[error("unexpected character {found:?} at {}:{}", position.line, position.column)]
+    UnexpectedCharacter {
+        found: char,
+        position: Position,
+    },
+
+    #[error("unterminated comment starting at {}:{}", .0.line, .0.column)]
+    UnterminatedComment(Position),
+
+    #[error("unterminated string starting at {}:{}", .0.line, .0.column)]
+    UnterminatedString(Position),
+
+    #[error("unknown escape sequence \\{found} at {}:{}", position.line, position.column)]
+    UnknownEscape {
+        found: char,
+        position: Position,
+    },
+
+    #[error("{found:?} at {}:{} isn't ASCII, so it can't be in a bytes literal, but an escape like \\xff can", position.line, position.column)]
+    NonAsciiByte {
+        found: char,
+        position: Position,
+    },
+
+    #[error("the number literal at {}:{} is too large", .0.line, .0.column)]
+    NumberTooLarge(Position),
+
+    #[error("the number literal at {}:{} is too small to be told apart from 0", .0.line, .0.column)]
+    NumberTooSmall(Position),
+
+    #[error("malformed number literal `{text}` at {}:{}", position.line, position.column)]
+    MalformedNumber {
+        text: String,
+        position: Position,
+    },
+}
+
+impl LexError {
+    /// where in the source the error is
+    pub fn position(&self) -> Position {
+        match self {
+            LexError::UnexpectedCharacter { position, .. } |
+            LexError::UnknownEscape { position, .. } |
+            LexError::NonAsciiByte { position, .. } |
+            LexError::MalformedNumber { position, .. } |
+            LexError::UnterminatedComment(position) |
+            LexError::UnterminatedString(position) |
+            LexError::NumberTooLarge(position) |
+            LexError::NumberTooSmall(position) => *position,
+        }
+    }
+}
+
+/// the symbols of the language, longest first so that they're matched greedily
+const SYMBOLS: &[&str] = &["...", "==", "~=", "<=", ">=", "<<", ">>", "//", "->", "::", "..", "@!", "+", "-", "*", "/", "%", "^", "#",
+                           "&", "~", "|", "<", ">", "=", "(", ")", "{", "}", "[", "]", ";", ":", ",", ".", "?", "@"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind {
+    /// a name, in NFC, including keywords, which the parser tells apart
+    Identifier(String),
+    /// a number literal whose value is a whole number, however it's written: `1e3` and `1.5e3`
+    /// are numbers too
+    Number(i64),
+    /// a number literal whose value isn't a whole number, to the nearest double
+    Float(f64),
+    String(String),
+    /// a bytes literal, `b"..."` or `b'...'`
+    Bytes(Vec<u8>),
+    Symbol(&'static str),
+    /// the end of the source, which carries any comments after the last token
+    End,
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Identifier(name) => write!(f, "{}", name),
+            TokenKind::Number(value) => write!(f, "{}", numbers::format(*value, 10)),
+            // the shortest literal that reads back as the same value
+            TokenKind::Float(value) => write!(f, "{}", numbers::format_float(*value)),
+            TokenKind::String(value) => write!(f, "{}", string_literal(value)),
+            TokenKind::Bytes(value) => write!(f, "{}", bytes_literal(value)),
+            TokenKind::Symbol(symbol) => write!(f, "`{}`", symbol),
+            TokenKind::End => write!(f, "the end of the input"),
+        }
+    }
+}
+
+/// a comment, kept so that the formatter and doc generator can reproduce it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    /// the text of the comment, without the `--` and brackets around it
+    pub text: String,
+    /// whether it was a `--[[ ]]` block comment rather than a line comment
+    pub block: bool,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+    /// the comments between the previous token's line and this token
+    pub leading: Vec<Comment>,
+    /// the comments after this token on the same line
+    pub trailing: Vec<Comment>,
+}
+
+impl Token {
+    /// the doc comment for a declaration starting with this token: the text of the `---` line
+    /// comments right before it, one per line, or none if there aren't any
+    pub fn doc(&self) -> Option<String> {
+        let lines = self.doc_comments()
+                        .iter()
+                        .map(|comment| comment.text[1..].trim())
+                        .collect::<Vec<_>>();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    /// the leading comments its doc comment is made of, which are the last of them
+    pub fn doc_comments(&self) -> &[Comment] {
+        let count = self.leading.iter().rev().take_while(|comment| comment.text.starts_with('-') && !comment.block).count();
+        &self.leading[self.leading.len() - count..]
+    }
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    offset: usize,
+    position: Position,
+}
+
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.position = Position { line: self.position.line + 1, column: 1 };
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    /// consume `expected` if the rest of the source starts with it
+    fn eat(&mut self, expected: &str) -> bool {
+        if !self.rest().starts_with(expected) {
+            return false
+        }
+        for _ in expected.chars() {
+            self.bump();
+        }
+        true
+    }
+
+    fn span_from(&self, start: Position) -> Span {
+        Span { start, end: self.position }
+    }
+
+    /// the level of the long bracket `[==[` the rest of the source starts with, if any
+    fn long_bracket(&self) -> Option<usize> {
+        let rest = self.rest().strip_prefix('[')?;
+        let level = rest.len() - rest.trim_start_matches('=').len();
+        rest[level..].starts_with('[').then_some(level)
+    }
+
+    /// the contents of a long bracket of `level` the lexer is at, e.g. `[[ ... ]]`, or `None`
+    /// if it doesn't close
+    fn long_bracket_contents(&mut self, level: usize) -> Option<String> {
+        let close = format!("]{}]", "=".repeat(level));
+        self.eat(&format!("[{}[", "=".repeat(level)));
+        let length = self.rest().find(&close)?;
+        let contents = self.rest()[..length].to_owned();
+        for _ in contents.chars() {
+            self.bump();
+        }
+        self.eat(&close);
+        Some(contents)
+    }
+
+    /// the comment the lexer is at, which starts with `--`
+    fn comment(&mut self) -> Result<Comment, LexError> {
+        let start = self.position;
+        self.eat("--");
+        if let Some(level) = self.long_bracket() {
+            let text = self.long_bracket_contents(level).ok_or(LexError::UnterminatedComment(start))?;
+            return Ok(Comment { text, block: true, span: self.span_from(start) })
+        }
+        let length = self.rest().find('\n').unwrap_or(self.rest().len());
+        let text = self.rest()[..length].to_owned();
+        for _ in text.chars() {
+            self.bump();
+        }
+        Ok(Comment { text, block: false, span: self.span_from(start) })
+    }
+
+    /// skip whitespace, collecting comments into `comments`. when `same_line` is set, stop at
+    /// the end of the line.
+    fn trivia(&mut self, comments: &mut Vec<Comment>, same_line: bool) -> Result<(), LexError> {
+        loop {
+            match self.peek() {
+                Some('\n') if same_line => return Ok(()),
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('-') if self.rest().starts_with("--") => comments.push(self.comment()?),
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// the number literal the lexer is at. it runs as far as anything that could be part of one,
+    /// so that `0b12` or `1e` is one malformed literal rather than a number and a name.
+    fn number(&mut self) -> Result<TokenKind, LexError> {
+        let position = self.position;
+        let decimal = !self.rest().starts_with("0x") && !self.rest().starts_with("0X");
+        let mut chars = self.rest().char_indices().peekable();
+        let (mut length, mut previous, mut point) = (0, '0', false);
+        while let Some((offset, c)) = chars.next() {
+            let next = chars.peek().map(|(_, c)| *c);
+            let part = match c {
+                '.' => decimal && !point && next.is_some_and(|next| next.is_ascii_digit()),
+                '+' | '-' => decimal && matches!(previous, 'e' | 'E'),
+                _ => is_identifier_continue(c),
+            };
+            if !part {
+                break
+            }
+            point |= c == '.';
+            (length, previous) = (offset + c.len_utf8(), c);
+        }
+        let text = self.rest()[..length].to_owned();
+        for _ in text.chars() {
+            self.bump();
+        }
+        number_value(&text).map_err(|error| match error {
+            NumberError::TooLarge => LexError::NumberTooLarge(position),
+            NumberError::TooSmall => LexError::NumberTooSmall(position),
+            NumberError::Malformed => LexError::MalformedNumber { text, position },
+        })
+    }
+
+    fn string(&mut self, quote: char) -> Result<TokenKind, LexError> {
+        let start = self.position;
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => return Ok(TokenKind::String(value)),
+                Some('\\') => {
+                    let position = self.position;
+                    value.push(match self.bump() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(c @ ('\\' | '"' | '\'')) => c,
+                        Some(found) => return Err(LexError::UnknownEscape { found, position }),
+                        None => return Err(LexError::UnterminatedString(start)),
+                    });
+                }
+                Some('\n') | None => return Err(LexError::UnterminatedString(start)),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    /// a long string, `[[ ... ]]` or `[==[ ... ]==]`, which can span lines and has no escapes.
+    /// like Lua, a newline straight after the opening bracket isn't part of it.
+    fn long_string(&mut self) -> Result<TokenKind, LexError> {
+        let start = self.position;
+        let level = self.long_bracket().unwrap_or_default();
+        let contents = self.long_bracket_contents(level).ok_or(LexError::UnterminatedString(start))?;
+        let contents = contents.strip_prefix("\r\n").or_else(|| contents.strip_prefix('\n')).unwrap_or(&contents);
+        Ok(TokenKind::String(contents.to_owned()))
+    }
+
+    /// a raw string, `r"..."` or `r'...'`, which has no escapes and stays on one line
+    fn raw_string(&mut self) -> Result<TokenKind, LexError> {
+        let start = self.position;
+        self.bump();
+        let quote = self.bump().unwrap_or_default();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => return Ok(TokenKind::String(value)),
+                Some('\n') | None => return Err(LexError::UnterminatedString(start)),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    /// a bytes literal, `b"..."` or `b'...'`, which has the escapes of a string, and `\xff` for
+    /// any byte, but only ASCII characters otherwise
+    fn bytes(&mut self) -> Result<TokenKind, LexError> {
+        let start = self.position;
+        self.bump();
+        let quote = self.bump().unwrap_or_default();
+        let mut value = vec![];
+        loop {
+            let position = self.position;
+            match self.bump() {
+                Some(c) if c == quote => return Ok(TokenKind::Bytes(value)),
+                Some('\\') => {
+                    let position = self.position;
+                    value.push(match self.bump() {
+                        Some('n') => b'\n',
+                        Some('t') => b'\t',
+                        Some('r') => b'\r',
+                        Some('0') => b'\0',
+                        Some(c @ ('\\' | '"' | '\'')) => c as u8,
+                        Some('x') => {
+                            let digits = self.rest().get(..2).and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                            let Some(byte) = digits.filter(|_| self.rest().chars().take(2).all(|c| c.is_ascii_hexdigit())) else {
+                                return Err(LexError::UnknownEscape { found: 'x', position })
+                            };
+                            self.bump();
+                            self.bump();
+                            byte
+                        }
+                        Some(found) => return Err(LexError::UnknownEscape { found, position }),
+                        None => return Err(LexError::UnterminatedString(start)),
+                    });
+                }
+                Some('\n') | None => return Err(LexError::UnterminatedString(start)),
+                Some(c) if c.is_ascii() => value.push(c as u8),
+                Some(found) => return Err(LexError::NonAsciiByte { found, position }),
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<TokenKind, LexError> {
+        let position = self.position;
+        match self.peek() {
+            None => Ok(TokenKind::End),
+            Some(c) if c.is_ascii_digit() => self.number(),
+            Some('[') if self.long_bracket().is_some() => self.long_string(),
+            Some('r') if self.rest().starts_with("r\"") || self.rest().starts_with("r'") => self.raw_string(),
+            Some('b') if self.rest().starts_with("b\"") || self.rest().starts_with("b'") => self.bytes(),
+            Some(c) if is_identifier_start(c) => {
+                let length = self.rest()
+                                 .char_indices()
+                                 .skip(1)
+                                 .find(|(_, c)| !is_identifier_continue(*c))
+                                 .map_or(self.rest().len(), |(length, _)| length);
+                let name = normalize(&self.rest()[..length]);
+                for _ in self.rest()[..length].chars() {
+                    self.bump();
+                }
+                Ok(TokenKind::Identifier(name))
+            }
+            Some(quote @ ('"' | '\'')) => self.string(quote),
+            Some(found) => match SYMBOLS.iter().find(|symbol| self.rest().starts_with(**symbol)) {
+                Some(symbol) => {
+                    self.eat(symbol);
+                    Ok(TokenKind::Symbol(symbol))
+                }
+                None => Err(LexError::UnexpectedCharacter { found, position }),
+            },
+        }
+    }
+}
+
+/// how a string is written back out: quoted with escapes, unless it spans lines or has
+/// characters that can't be escaped, when it's a long string with a level high enough not to be
+/// closed by what's in it, so that its lines are kept as they are
+pub fn string_literal(value: &str) -> String {
+    let quoted = format!("{:?}", value);
+    if !value.contains('\n') && !quoted.contains("\\u{") {
+        return quoted
+    }
+    let level = (0..).find(|level| !format!("{}]", value).contains(&format!("]{}]", "=".repeat(*level)))).unwrap_or_default();
+    let equals = "=".repeat(level);
+    // the newline after the opening bracket is dropped when it's read back, keeping any the
+    // string itself starts with
+    format!("[{}[\n{}]{}]", equals, value, equals)
+}
+
+/// how bytes are written back out: quoted, with the printable ASCII characters as they are and
+/// escapes for the rest
+pub fn bytes_literal(value: &[u8]) -> String {
+    let mut literal = String::from("b\"");
+    for byte in value {
+        match byte {
+            b'\n' => literal.push_str("\\n"),
+            b'\t' => literal.push_str("\\t"),
+            b'\r' => literal.push_str("\\r"),
+            b'\\' | b'"' => {
+                literal.push('\\');
+                literal.push(*byte as char);
+            }
+            b' '..=b'~' => literal.push(*byte as char),
+            _ => literal.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+enum NumberError {
+    TooLarge,
+    TooSmall,
+    Malformed,
+}
+
+/// the digits of `text` in `radix` without the `_`s between them, if that's all it is
+fn digits(text: &str, radix: u32) -> Result<String, NumberError> {
+    let well_formed = text.starts_with(|c: char| c.is_digit(radix))
+                      && !text.ends_with('_')
+                      && text.chars().all(|c| c == '_' || c.is_digit(radix));
+    if well_formed { Ok(text.replace('_', "")) } else { Err(NumberError::Malformed) }
+}
+
+/// the value of a number literal: `0xFF`, `0b1010`, `1_000_000`, `1e9`, or `1.5e-3`
+fn number_value(text: &str) -> Result<TokenKind, NumberError> {
+    let integer = |digits: &str, radix| numbers::parse(digits, radix).ok_or(NumberError::TooLarge);
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return Ok(TokenKind::Number(integer(&digits(hex, 16)?, 16)?))
+    }
+    if let Some(binary) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        return Ok(TokenKind::Number(integer(&digits(binary, 2)?, 2)?))
+    }
+
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => {
+            let (negative, magnitude) = match exponent.strip_prefix('-') {
+                Some(magnitude) => (true, magnitude),
+                None => (false, exponent.strip_prefix('+').unwrap_or(exponent)),
+            };
+            let magnitude = digits(magnitude, 10)?;
+            let magnitude = integer(&magnitude, 10).map_err(|error| if negative { NumberError::TooSmall } else { error })?;
+            (mantissa, if negative { -magnitude } else { magnitude })
+        }
+        None => (text, 0),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (digits(whole, 10)?, digits(fraction, 10)?),
+        None => (digits(mantissa, 10)?, String::new()),
+    };
+
+    // the value is `significand * 10^scale`, exactly
+    let significand = format!("{}{}", whole, fraction);
+    let scale = exponent.checked_sub(fraction.len() as i64).ok_or(NumberError::TooSmall)?;
+    let kept = significand.trim_end_matches('0');
+    let zeros = (significand.len() - kept.len()) as i64;
+    let shift = scale.checked_add(zeros).ok_or(NumberError::TooLarge)?;
+    if shift >= 0 {
+        let digits = kept.trim_start_matches('0');
+        let mut value = if digits.is_empty() { 0 } else { integer(digits, 10)? };
+        for _ in 0..shift {
+            if value == 0 {
+                break
+            }
+            value = value.checked_mul(10).ok_or(NumberError::TooLarge)?;
+        }
+        return Ok(TokenKind::Number(value))
+    }
+    let value = format!("{}e{}", significand, scale).parse::<f64>().map_err(|_| NumberError::Malformed)?;
+    if !value.is_finite() {
+        Err(NumberError::TooLarge)
+    } else if value == 0.0 && !kept.is_empty() {
+        // a literal that isn't 0 shouldn't silently become it
+        Err(NumberError::TooSmall)
+    } else {
+        Ok(TokenKind::Float(value))
+    }
+}
+
+/// split `source` into tokens, ending with an `End` token. comments are kept as trivia on the
+/// tokens around them.
+pub fn lex(source: &str) -> Result<Vec<Token>, LexError> {
+    lex_from(source, Position { line: 1, column: 1 })
+}
+
+/// the byte offset of the character at `position` in `source`, or its length if `position` is past
+/// the end of it
+pub fn offset(source: &str, position: Position) -> usize {
+    let start = source.split_inclusive('\n').take(position.line - 1).map(str::len).sum::<usize>();
+    source[start..].char_indices()
+                   .nth(position.column - 1)
+                   .map_or(source.len(), |(offset, _)| start + offset)
+}
+
+/// lex `source` as though it started at `start` in a larger file
+pub(crate) fn lex_from(source: &str, start: Position) -> Result<Vec<Token>, LexError> {
+    let mut lexer = Lexer { source, offset: 0, position: start };
+    let mut tokens = vec![];
+    loop {
+        let mut leading = vec![];
+        lexer.trivia(&mut leading, false)?;
+        let start = lexer.position;
+        let kind = lexer.token()?;
+        let span = lexer.span_from(start);
+        let mut trailing = vec![];
+        let end = kind == TokenKind::End;
+        if !end {
+            lexer.trivia(&mut trailing, true)?;
+        }
+        tokens.push(Token { kind, span, leading, trailing });
+        if end {
+            return Ok(tokens)
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use thiserror::Error;
+
+use crate::sgir::Span;
+
+use super::ast::{Ast, Attribute, Block, Node};
+use super::identifiers::confusables;
+use super::lexer::lex;
+use super::parser::SyntaxError;
+use super::rename::{resolve_program, Resolution};
+
+/// how much what a lint finds matters: not at all, enough to warn about, or enough to stop the
+/// program from being compiled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    /// the level named by an attribute or a manifest, e.g. `allow`
+    pub fn named(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// every lint, with what it finds
+pub const LINTS: &[(&str, &str)] = &[
+    ("unused_variable", "a local, parameter, or loop variable that's never used, unless its name starts with `_`"),
+    ("confusable_identifier", "a name that looks just like a different name in the same scope"),
+    ("dynamic_capture", "a name in a function that a call of it would have seen bound differently under dynamic scoping"),
+];
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum LintError {
+    #[error(transparent)]
+    Syntax(#[from] SyntaxError),
+
+    #[error("unknown attribute {name} at {}:{}, expected allow, warn, or deny", span.start.line, span.start.column)]
+    UnknownAttribute {
+        name: String,
+        span: Span,
+    },
+
+    #[error("unknown lint {name} at {}:{}", span.start.line, span.start.column)]
+    UnknownLint {
+        name: String,
+        span: Span,
+    },
+}
+
+/// the level of each lint before the attributes in a program have their say, e.g. from the
+/// `[lints]` table of a manifest. a lint that isn't given one warns.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Levels(HashMap<String, Level>);
+
+impl Levels {
+    pub fn set(&mut self, lint: &str, level: Level) {
+        self.0.insert(lint.to_owned(), level);
+    }
+
+    pub fn get(&self, lint: &str) -> Level {
+        self.0.get(lint).copied().unwrap_or(Level::Warn)
+    }
+}
+
+/// something a lint found, at a level other than `Allow`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub lint: &'static str,
+    pub level: Level,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let level = if self.level == Level::Deny { "error" } else { "warning" };
+        write!(f, "{}: {} [{}]", level, self.message, self.lint)
+    }
+}
+
+/// apply `attribute` to `levels`, if it's one the lints know
+fn apply(attribute: &Attribute, levels: &mut Levels) -> Result<(), LintError> {
+    let Some(level) = Level::named(&attribute.name) else {
+        return Err(LintError::UnknownAttribute { name: attribute.name.clone(), span: attribute.span })
+    };
+    for lint in &attribute.arguments {
+        if !LINTS.iter().any(|(name, _)| name == lint) {
+            return Err(LintError::UnknownLint { name: lint.clone(), span: attribute.span })
+        }
+        levels.set(lint, level);
+    }
+    Ok(())
+}
+
+/// the declarations in `node` with attributes, outermost first
+fn attributed<'a>(node: &'a Node, found: &mut Vec<(Span, &'a [Attribute])>) {
+    if let Ast::Local { attributes, .. } | Ast::FunctionDeclaration { attributes, .. } = &node.ast {
+        if !attributes.is_empty() {
+            found.push((node.span, attributes));
+        }
+    }
+    node.children().into_iter().for_each(|child| attributed(child, found));
+}
+
+/// the binding `binder` refers to, as a warning describes it
+fn describe(resolution: &Resolution, name: &str, binder: Option<usize>) -> String {
+    match binder.and_then(|binder| resolution.binders[binder].1) {
+        Some(span) => format!("the `{}` bound at {}:{}", name, span.start.line, span.start.column),
+        None if binder.is_some() => format!("the implicit `{}`", name),
+        None => format!("the global `{}`", name),
+    }
+}
+
+/// the names in functions that refer to one binding where the function is written, but would
+/// have referred to another at a call of it when a function saw the variables of whoever called
+/// it. only calls of a function by the name it's bound to are checked.
+fn dynamic_captures(resolution: &Resolution) -> Vec<(Span, String)> {
+    let mut found = vec![];
+    for (call, scope) in &resolution.calls {
+        let (_, call_span, Some(callee)) = &resolution.uses[*call] else { continue };
+        let Some(free) = resolution.functions.get(callee) else { continue };
+        for &used in free {
+            let (name, span, lexical) = &resolution.uses[used];
+            // a function calling itself is an error lowering reports
+            if *name == resolution.binders[*callee].0 {
+                continue
+            }
+            let dynamic = scope.iter().rev().find(|(bound, _)| bound == name).map(|(_, binder)| *binder);
+            if dynamic != *lexical {
+                found.push((*span, format!("`{}` at {}:{} refers to {}, but the call at {}:{} would have seen {} under dynamic scoping",
+                                           name, span.start.line, span.start.column, describe(resolution, name, *lexical),
+                                           call_span.start.line, call_span.start.column, describe(resolution, name, dynamic))));
+            }
+        }
+    }
+    found
+}
+
+/// run the lints over `program`, parsed from `source`, at `levels`. the `@!` attributes at its top
+/// level change the levels for the whole program, and the attributes of a declaration change
+/// them within it, e.g. `@allow(unused_variable) function f(x) ... end` for its parameters.
+pub fn lint(source: &str, program: &Block, levels: &Levels) -> Result<Vec<Warning>, LintError> {
+    let mut file = levels.clone();
+    let mut declarations = vec![];
+    for node in program {
+        if let Ast::FileAttribute(attribute) = &node.ast {
+            apply(attribute, &mut file)?;
+        }
+        attributed(node, &mut declarations);
+    }
+    for attribute in declarations.iter().flat_map(|(_, attributes)| attributes.iter()) {
+        apply(attribute, &mut Levels::default())?;
+    }
+    // the innermost declaration around `span` to set the lint has the last say
+    let level = |lint: &str, span: Span| {
+        declarations.iter()
+                    .rev()
+                    .filter(|(declaration, _)| declaration.start <= span.start && span.end <= declaration.end)
+                    .flat_map(|(_, attributes)| attributes.iter().rev())
+                    .filter(|attribute| attribute.arguments.iter().any(|argument| argument == lint))
+                    .find_map(|attribute| Level::named(&attribute.name))
+                    .unwrap_or_else(|| file.get(lint))
+    };
+
+    let mut found = vec![];
+    let tokens = lex(source).map_err(SyntaxError::from)?;
+    let resolution = resolve_program(program, &tokens);
+    for (binder, (name, span)) in resolution.binders.iter().enumerate() {
+        let Some(span) = span else { continue };
+        let used = resolution.exported.contains(&binder) || resolution.uses.iter().any(|(_, _, used)| *used == Some(binder));
+        if !name.starts_with('_') && !used {
+            found.push(("unused_variable", *span, format!("`{}` at {}:{} is never used", name, span.start.line, span.start.column)));
+        }
+    }
+    for confusable in confusables(program) {
+        found.push(("confusable_identifier", confusable.span, confusable.to_string()));
+    }
+    for (span, message) in dynamic_captures(&resolution) {
+        found.push(("dynamic_capture", span, message));
+    }
+    found.sort_by_key(|(_, span, _)| span.start);
+    Ok(found.into_iter()
+            .map(|(lint, span, message)| Warning { lint, level: level(lint, span), message, span })
+            .filter(|warning| warning.level != Level::Allow)
+            .collect())
+}
@@ -0,0 +1,56 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::sgir::{numbers, Value};
+
+use super::lexer::{bytes_literal, string_literal};
+
+/// a value written as source that evaluates back to it, for output that can be pasted into a
+/// program. booleans, numbers, strings, bytes, tuples, and records are written as literals. the values
+/// that have none, like functions, are written as placeholders in angle brackets, e.g.
+/// `<fn (Number) -> Number>`, which don't parse, so that pasting one in is a syntax error rather
+/// than a different value.
+pub struct Literal<'a>(pub &'a Value);
+
+impl Display for Literal<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Value::Boolean(value) => write!(f, "{}", value),
+            // a minus sign is an operator, and the most negative number is one more than any
+            // literal can be negated
+            Value::Number(i64::MIN) => write!(f, "({} - 1)", numbers::format(i64::MIN + 1, 10)),
+            Value::Number(value) => write!(f, "{}", numbers::format(*value, 10)),
+            Value::String(value) => write!(f, "{}", string_literal(value)),
+            Value::Bytes(value) => write!(f, "{}", bytes_literal(value)),
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", Literal(element))?;
+                }
+                if elements.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Record(fields) => {
+                write!(f, "{{")?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} = {}", field, Literal(value))?;
+                }
+                write!(f, "}}")
+            }
+            Value::Float(value) if value.is_finite() && value.fract() != 0.0 => write!(f, "{}", numbers::format_float(*value)),
+            // a float literal that's a whole number reads back as a number
+            Value::Float(_) => write!(f, "<float {}>", self.0),
+            Value::Char(_) => write!(f, "<char {}>", self.0),
+            Value::Variant { .. } => write!(f, "<variant {}>", self.0),
+            // already written as `<fn ...>` and `<native ...>`
+            Value::Function { .. } | Value::Native(_) => write!(f, "{}", self.0),
+        }
+    }
+}
@@ -0,0 +1,1350 @@
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::sgir::arguments::{self, Parameter};
+use crate::sgir::blocks::{self, Statement};
+use crate::sgir::macros::{self, ExpansionError, FreshNames, Macro, MacroEnv};
+use crate::sgir::numbers::Float;
+use crate::sgir::operators::{FixityError, Operators};
+use crate::sgir::patterns::{check_pattern, Arm, Pattern};
+use crate::sgir::primitives::Primitive;
+use crate::sgir::testing::TestCase;
+use crate::sgir::{check_with_abstract_types, loops, multiple, target};
+use crate::sgir::{Binding, Declarations, Expression, Kind, Span, Type, TypeBinding, TypeError, TypeTag};
+
+use super::ast::{self, Ast, Binder, Case, Node, VARARGS};
+use super::identifiers::{parts, Part};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum LowerError {
+    #[error(transparent)]
+    Fixity(#[from] FixityError),
+
+    #[error("the parameter {name} of the function at {}:{} needs a type annotation", span.start.line, span.start.column)]
+    MissingAnnotation {
+        name: String,
+        span: Span,
+    },
+
+    #[error("{construct} at {}:{} can't be lowered to SGIR yet", span.start.line, span.start.column)]
+    Unsupported {
+        construct: &'static str,
+        span: Span,
+    },
+
+    #[error("the operator {symbol} at {}:{} takes two operands, but its function has {found} parameters", span.start.line, span.start.column)]
+    OperatorParameters {
+        symbol: String,
+        found: usize,
+        span: Span,
+    },
+
+    #[error("only the top level of a module exports anything, but the declaration at {}:{} is marked `pub`", span.start.line, span.start.column)]
+    NestedExport {
+        span: Span,
+    },
+
+    #[error("only the top level of a module has tests, but the test at {}:{} is in a block", span.start.line, span.start.column)]
+    NestedTest {
+        span: Span,
+    },
+
+    #[error("{name} at {}:{} isn't a local, so it can't be assigned to", span.start.line, span.start.column)]
+    NotALocal {
+        name: String,
+        span: Span,
+    },
+
+    #[error("the loop at {}:{} assigns {name}, whose type isn't known where it's lowered; annotate its declaration", span.start.line, span.start.column)]
+    UnknownType {
+        name: String,
+        span: Span,
+    },
+
+    #[error("the type of what the loop at {}:{} iterates over isn't known where it's lowered; annotate it", span.start.line, span.start.column)]
+    UnknownIterable {
+        span: Span,
+    },
+
+    #[error("the loop at {}:{} iterates over a value of type {typ}, which isn't an iterator", span.start.line, span.start.column)]
+    NotIterable {
+        typ: Type,
+        span: Span,
+    },
+
+    #[error("{construct} at {}:{} isn't in a loop", span.start.line, span.start.column)]
+    OutsideLoop {
+        construct: &'static str,
+        span: Span,
+    },
+
+    #[error("`...` at {}:{} isn't in a variadic function", span.start.line, span.start.column)]
+    NotVariadic {
+        span: Span,
+    },
+
+    #[error("the type of the function applied partially at {}:{} isn't known where it's lowered; annotate it", span.start.line, span.start.column)]
+    UnknownFunction {
+        span: Span,
+    },
+
+    #[error("{error} at {}:{}", span.start.line, span.start.column)]
+    Arguments {
+        error: Box<TypeError>,
+        span: Span,
+    },
+
+    #[error("{error} at {}:{}", span.start.line, span.start.column)]
+    Expansion {
+        error: ExpansionError,
+        span: Span,
+    },
+
+    #[error("{name} at {}:{} calls the function it's in, but functions can't be recursive; use a loop instead", span.start.line, span.start.column)]
+    Recursive {
+        name: String,
+        span: Span,
+    },
+
+    #[error("the type alias {name} takes {expected} type arguments, but was given {found}")]
+    AliasArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl LowerError {
+    /// where in the source the error is, when it's known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            LowerError::MissingAnnotation { span, .. } | LowerError::Unsupported { span, .. } | LowerError::OperatorParameters { span, .. }
+            | LowerError::NestedExport { span } | LowerError::NestedTest { span } | LowerError::NotALocal { span, .. } | LowerError::UnknownType { span, .. }
+            | LowerError::UnknownIterable { span } | LowerError::NotIterable { span, .. } | LowerError::OutsideLoop { span, .. }
+            | LowerError::NotVariadic { span } | LowerError::UnknownFunction { span } | LowerError::Arguments { span, .. }
+            | LowerError::Expansion { span, .. } | LowerError::Recursive { span, .. } => Some(*span),
+            LowerError::Fixity(_) | LowerError::AliasArity { .. } => None,
+        }
+    }
+}
+
+type LR<T> = Result<T, LowerError>;
+
+/// what lowering keeps track of as it goes: the operators in scope to resolve infix chains against, the
+/// type aliases in scope, each with its parameters and the type it stands for, the functions in
+/// scope with a precondition, each with its parameters, the functions declared in scope, each
+/// with its parameters and their defaults, the macros in scope, the locals in scope, the types of
+/// those whose types are known, the locals of the functions around the one being lowered, which
+/// it can't assign, the type parameters in scope, how many blocks deep it is, whether it's in a
+/// loop of the function being lowered, and the supply of names for the locals it introduces
+struct Lower {
+    operators: Operators,
+    aliases: HashMap<String, (Vec<String>, Type)>,
+    contracts: HashMap<String, Vec<Binding>>,
+    signatures: HashMap<String, Vec<Parameter>>,
+    macros: MacroEnv,
+    locals: HashSet<String>,
+    types: Declarations,
+    captured: HashSet<String>,
+    type_parameters: Vec<(String, Kind)>,
+    depth: usize,
+    looping: bool,
+    names: FreshNames,
+}
+
+/// the state lowering restores at the end of a block
+type Scope = (Operators, HashMap<String, (Vec<String>, Type)>, HashMap<String, Vec<Binding>>, HashMap<String, Vec<Parameter>>, MacroEnv,
+              HashSet<String>, Declarations, HashSet<String>);
+
+/// where a block in the body of a loop is: at the top of it, where it produces whether to stop
+/// the loop, after testing the condition of a `repeat` at its end if it has one, or nested in an
+/// `if`, a `do` or a `match` there, where it produces how it ended
+#[derive(Clone, Copy)]
+enum Level<'a> {
+    Body(Option<&'a Node>),
+    Nested,
+}
+
+/// how a block nested in the body of a loop ended: by running to its end, by breaking out of the
+/// loop, or by continuing it
+const FINISHED: i64 = 0;
+const BROKE: i64 = 1;
+const CONTINUED: i64 = 2;
+/// how the block nested in the body of a loop just before the rest of it ended
+const SIGNAL: &str = "%signal";
+/// the value of a block that's followed by the values of the locals it assigns
+const VALUE: &str = "%value";
+
+/// lower a block of the surface syntax to SGIR, resolving chains of infix operators against
+/// `operators`. every node is lowered to a `Located` expression with its span.
+pub fn lower_block(block: &[Node], operators: &Operators) -> LR<Expression> {
+    lower_block_for(block, operators, target::NATIVE)
+}
+
+/// lower a block of the surface syntax to SGIR for `target`, keeping only the branch of each
+/// target conditional that applies to it
+pub fn lower_block_for(block: &[Node], operators: &Operators, target: &str) -> LR<Expression> {
+    let mut block = block.to_vec();
+    for node in &mut block {
+        for_target(node, target);
+    }
+    Lower::new(operators).block(&block)
+}
+
+/// lower a node of the surface syntax to SGIR
+pub fn lower(node: &Node, operators: &Operators) -> LR<Expression> {
+    let mut node = node.clone();
+    for_target(&mut node, target::NATIVE);
+    Lower::new(operators).node(&node)
+}
+
+/// replace every target conditional in `node` with a `do` block of its branch for `target`, or
+/// an empty one if it has none
+fn for_target(node: &mut Node, target: &str) {
+    if let Ast::IfTarget { target: expected, consequent, alternative } = &mut node.ast {
+        let chosen = if expected == target { std::mem::take(consequent) } else { alternative.take().unwrap_or_default() };
+        node.ast = Ast::Do(chosen);
+    }
+    for child in node.children_mut() {
+        for_target(child, target);
+    }
+}
+
+/// the types a declaration file declares, from its declarations as parsed, with its aliases
+/// expanded. a function's type is that of the function its signature gives, and a function
+/// without a result annotation returns the unit.
+pub fn lower_declarations(block: &[Node]) -> LR<Declarations> {
+    let mut lower = Lower::new(&Operators::default());
+    let mut declarations = Declarations::new();
+    for node in block {
+        match &node.ast {
+            Ast::TypeAlias { .. } => {
+                lower.statement(node)?;
+            }
+            Ast::Local { names, values, .. } if values.is_empty() => {
+                for Binder { pattern, annotation, .. } in names {
+                    let Some(typ) = annotation else {
+                        return Err(LowerError::Unsupported { construct: "a declaration without a type", span: node.span })
+                    };
+                    for name in pattern.names() {
+                        declarations.insert(name.clone(), lower.resolve(typ)?);
+                    }
+                }
+            }
+            Ast::FunctionDeclaration { name, type_parameters, parameters, result, body, .. } if body.is_empty() => {
+                let typ = lower.shadowed(type_parameters, |lower| {
+                    let arguments = parameters.iter().map(|binder| {
+                        let Some(typ) = &binder.annotation else {
+                            return Err(LowerError::MissingAnnotation { name: binder.pattern.to_string(), span: node.span })
+                        };
+                        if binder.default.is_some() {
+                            return Err(LowerError::Unsupported { construct: "a default for a parameter of a declared function", span: node.span })
+                        }
+                        let typ = lower.resolve(typ)?;
+                        Ok(if binder.is_varargs() { Type::Rest(Box::new(typ)) } else { typ })
+                    }).collect::<LR<Vec<_>>>()?;
+                    let result = result.as_ref().map_or(Ok(Type::Tuple(vec![])), |result| lower.resolve(result))?;
+                    Ok(Type::Function { arguments, result: Box::new(result) })
+                })?;
+                let typ = match &type_parameters[..] {
+                    [] => typ,
+                    parameters => Type::ForAll { parameters: parameters.iter().map(|id| TypeBinding { id: id.clone(), kind: Kind::Star }).collect(),
+                                                 typ: Box::new(typ) },
+                };
+                declarations.insert(name.clone(), typ);
+            }
+            _ => return Err(LowerError::Unsupported { construct: "a definition in a declaration file", span: node.span }),
+        }
+    }
+    Ok(declarations)
+}
+
+/// the tests at the top level of `block`, each with where it's declared. each is lowered on its
+/// own, as the statements before it followed by its block and then `true`, so that it passes if
+/// its block runs to the end.
+pub fn lower_tests(block: &[Node], operators: &Operators) -> LR<Vec<(Span, TestCase)>> {
+    let mut tests = vec![];
+    for (i, node) in block.iter().enumerate() {
+        let Ast::Test { name, body } = &node.ast else { continue };
+        let statement = |ast| Node { ast, span: node.span, leading: vec![], trailing: vec![] };
+        let program = block[..i].iter()
+                                .cloned()
+                                .chain([statement(Ast::Do(body.clone())), statement(Ast::Boolean(true))])
+                                .collect::<Vec<_>>();
+        tests.push((node.span, TestCase { name: name.clone(), body: lower_block(&program, operators)? }));
+    }
+    Ok(tests)
+}
+
+impl Lower {
+    fn new(operators: &Operators) -> Lower {
+        // the prelude's aliases: `Iterator<T, S>` is the iterator over `T`s whose state is an `S`
+        // that a generic for iterates over
+        let iterator = loops::iterator_type(Type::Variable("T".to_owned()), Type::Variable("S".to_owned()));
+        Lower {
+            operators: operators.clone(),
+            aliases: HashMap::from([("Iterator".to_owned(), (vec!["T".to_owned(), "S".to_owned()], iterator))]),
+            contracts: HashMap::new(),
+            signatures: HashMap::new(),
+            macros: MacroEnv::new(),
+            locals: HashSet::new(),
+            types: Declarations::new(),
+            captured: HashSet::new(),
+            type_parameters: vec![],
+            depth: 0,
+            looping: false,
+            names: FreshNames::default(),
+        }
+    }
+
+    /// the operators, aliases, functions, macros and locals declared in a block go out of scope at its end
+    fn block(&mut self, block: &[Node]) -> LR<Expression> {
+        self.scoped(|lower| lower.statements(block, &[], true))
+    }
+
+    /// run `f` one block deeper
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> LR<T>) -> LR<T> {
+        let scope: Scope = (self.operators.clone(), self.aliases.clone(), self.contracts.clone(), self.signatures.clone(), self.macros.clone(),
+                            self.locals.clone(), self.types.clone(), self.captured.clone());
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        (self.operators, self.aliases, self.contracts, self.signatures, self.macros, self.locals, self.types, self.captured) = scope;
+        result
+    }
+
+    /// a block whose value, if `value` is set, is followed by the values of the locals from
+    /// outside it named `outputs`, which it assigns. the locals a block assigns are rebound to
+    /// their new values after it, since SGIR has no assignment of its own.
+    fn statements(&mut self, block: &[Node], outputs: &[String], value: bool) -> LR<Expression> {
+        let mut saved = HashMap::new();
+        // a block that ends in a declaration has no value
+        let (last, statements) = match block.split_last() {
+            Some((last, statements)) if value && !is_declaration(last) => (Some(last), statements),
+            _ => (None, block),
+        };
+        let mut lowered = vec![];
+        for statement in statements {
+            lowered.extend(self.threaded(statement, outputs, &mut saved)?);
+        }
+        let result = match last {
+            Some(last) => {
+                let assigned = self.assigned(last);
+                if assigned.is_empty() {
+                    Some(self.node(last)?)
+                } else {
+                    let pattern = Pattern::Tuple([VALUE.to_owned()].into_iter().chain(assigned.iter().cloned()).map(Pattern::Variable).collect());
+                    lowered.push(Statement::Local(pattern, self.compound(last, &assigned, true)?));
+                    Some(Expression::Variable(VALUE.to_owned()))
+                }
+            }
+            None => value.then(|| Expression::Tuple(vec![])),
+        };
+        Ok(blocks::block(lowered, yielded(result, outputs, &saved)))
+    }
+
+    /// the statements `statement` lowers to in a block that assigns the locals from outside it
+    /// named `outputs`, where the locals in `saved` have already been shadowed. an `if`, a `do`,
+    /// a `match` or a loop that assigns locals is followed by rebinding them to their new values.
+    fn threaded(&mut self, statement: &Node, outputs: &[String], saved: &mut HashMap<String, String>) -> LR<Vec<Statement>> {
+        let mut lowered = vec![];
+        // a local that shadows one the block assigns ends the block's assignments to it, so its
+        // value so far is kept under a name of its own
+        for name in declared(statement) {
+            if outputs.contains(&name) && !saved.contains_key(&name) {
+                let id = format!("%saved.{}", name);
+                lowered.push(Statement::Local(Pattern::Variable(id.clone()), Expression::Variable(name.clone())));
+                saved.insert(name, id);
+            }
+        }
+        let assigned = self.assigned(statement);
+        if assigned.is_empty() {
+            lowered.extend(self.statement(statement)?);
+        } else {
+            let pattern = Pattern::Tuple(assigned.iter().cloned().map(Pattern::Variable).collect());
+            lowered.push(Statement::Local(pattern, self.compound(statement, &assigned, false)?));
+            for name in &assigned {
+                self.contracts.remove(name);
+                self.signatures.remove(name);
+            }
+        }
+        Ok(lowered)
+    }
+
+    /// the locals from outside the `if`, `do`, `match` or loop `node` that it assigns, or none if
+    /// it's anything else
+    fn assigned(&self, node: &Node) -> Vec<String> {
+        if !is_compound(node) {
+            return vec![]
+        }
+        let mut found = vec![];
+        assignments(node, &mut vec![], &mut found);
+        found
+    }
+
+    /// the `if`, `do`, `match` or loop `node`, whose value, if `value` is set, is followed by the
+    /// values of the locals named `outputs` that it assigns
+    fn compound(&mut self, node: &Node, outputs: &[String], value: bool) -> LR<Expression> {
+        let expression = match &node.ast {
+            Ast::If { condition, consequent, alternative } => Expression::If {
+                condition: Box::new(self.node(condition)?),
+                consequent: Box::new(self.scoped(|lower| lower.statements(consequent, outputs, value))?),
+                alternative: Box::new(self.scoped(|lower| lower.statements(alternative.as_deref().unwrap_or_default(), outputs, value))?),
+            },
+            Ast::Do(body) => self.scoped(|lower| lower.statements(body, outputs, value))?,
+            Ast::Match { scrutinee, cases } => self.matched(scrutinee, cases, |lower, body| lower.statements(body, outputs, value))?,
+            _ => self.looped(node, outputs, value)?,
+        };
+        Ok(Expression::Located { span: node.span, expression: Box::new(expression) })
+    }
+
+    /// a match of `scrutinee` against `cases`, whose bodies are lowered by `body`
+    fn matched(&mut self, scrutinee: &Node, cases: &[Case], mut body: impl FnMut(&mut Self, &[Node]) -> LR<Expression>) -> LR<Expression> {
+        let scrutinee = self.node(scrutinee)?;
+        let typ = self.infer(&scrutinee);
+        let arms = cases.iter()
+                        .map(|Case { pattern, guard, body: block }| {
+                            let names = pattern.names().into_iter().cloned().collect::<Vec<_>>();
+                            let pattern = lower_pattern(pattern);
+                            self.bound(&names, |lower| {
+                                lower.typed(&pattern, typ.clone());
+                                let guard = guard.as_ref().map(|guard| lower.node(guard)).transpose()?;
+                                Ok(Arm { pattern, guard, body: lower.scoped(|lower| body(lower, block))? })
+                            })
+                        })
+                        .collect::<LR<_>>()?;
+        Ok(Expression::Match { scrutinee: Box::new(scrutinee), arms })
+    }
+
+    /// the loop `node`, whose value, if `value` is set, is followed by the values of the locals
+    /// named `outputs` that it assigns. those locals are its state, so their types have to be
+    /// known.
+    fn looped(&mut self, node: &Node, outputs: &[String], value: bool) -> LR<Expression> {
+        let state = outputs.iter()
+                           .map(|name| match self.types.get(name) {
+                               Some(typ) => Ok((Binding { id: name.clone(), typ: typ.clone() }, Expression::Variable(name.clone()))),
+                               None => Err(LowerError::UnknownType { name: name.clone(), span: node.span }),
+                           })
+                           .collect::<LR<Vec<_>>>()?;
+        let result = yielded(value.then(|| Expression::Tuple(vec![])), outputs, &HashMap::new());
+        let looping = std::mem::replace(&mut self.looping, true);
+        let lowered = (|| match &node.ast {
+            Ast::While { condition, body } => {
+                let condition = self.node(condition)?;
+                let body = self.scoped(|lower| lower.escaping(body, outputs, Level::Body(None), &mut HashMap::new()))?;
+                Ok(loops::while_body(state, condition, body, result))
+            }
+            Ast::Repeat { body, condition } => {
+                let body = self.scoped(|lower| lower.escaping(body, outputs, Level::Body(Some(condition)), &mut HashMap::new()))?;
+                Ok(loops::repeat_body(state, body, result))
+            }
+            Ast::NumericFor { variable, start, stop, step, body } => {
+                let (start, stop) = (self.node(start)?, self.node(stop)?);
+                let step = step.as_deref().map(|step| self.node(step)).transpose()?;
+                let body = self.scoped(|lower| {
+                    lower.declare([variable]);
+                    lower.types.insert(variable.clone(), Type::Number);
+                    lower.escaping(body, outputs, Level::Body(None), &mut HashMap::new())
+                })?;
+                Ok(loops::for_range_body(variable, start, stop, step, state, body, result))
+            }
+            // the loop variable has the type of the iterator's items
+            Ast::GenericFor { variable, iterable, body } => {
+                let iterable = self.node(iterable)?;
+                let typ = self.infer(&iterable).ok_or(LowerError::UnknownIterable { span: node.span })?;
+                let (item, _) = loops::iterator_item(&typ).ok_or_else(|| LowerError::NotIterable { typ: typ.clone(), span: node.span })?;
+                let body = self.scoped(|lower| {
+                    lower.declare([variable]);
+                    lower.types.insert(variable.clone(), item);
+                    lower.escaping(body, outputs, Level::Body(None), &mut HashMap::new())
+                })?;
+                loops::for_in_body(variable, iterable, &typ, state, body, result)
+                    .map_err(|_| LowerError::NotIterable { typ: typ.clone(), span: node.span })
+            }
+            _ => unreachable!("only a loop is lowered as one"),
+        })();
+        self.looping = looping;
+        lowered
+    }
+
+    /// a block in the body of a loop at `level`, which produces a tuple of how it ended, followed
+    /// by the values of the locals named `outputs` that it assigns. a `break` or a `continue`
+    /// ends the block there, and an `if`, a `do` or a `match` with one inside is followed by the
+    /// rest of the block only if it ran to its end.
+    fn escaping(&mut self, block: &[Node], outputs: &[String], level: Level, saved: &mut HashMap<String, String>) -> LR<Expression> {
+        let mut lowered = vec![];
+        for (i, statement) in block.iter().enumerate() {
+            let ended = match (&statement.ast, level) {
+                (Ast::Break, _) => BROKE,
+                (Ast::Continue, Level::Body(Some(_))) => {
+                    return Err(LowerError::Unsupported { construct: "continue in a repeat loop", span: statement.span })
+                }
+                (Ast::Continue, _) => CONTINUED,
+                _ if escapes(statement) => {
+                    if !matches!(statement.ast, Ast::If { .. } | Ast::Do(_) | Ast::Match { .. }) {
+                        return Err(LowerError::Unsupported { construct: "a break or a continue inside an expression", span: statement.span })
+                    }
+                    let mut inner = outputs.to_vec();
+                    inner.extend(self.assigned(statement).into_iter().filter(|name| !outputs.contains(name)));
+                    let pattern = Pattern::Tuple([SIGNAL.to_owned()].into_iter().chain(inner.iter().cloned()).map(Pattern::Variable).collect());
+                    let nested = self.escaping_compound(statement, &inner)?;
+                    lowered.push(Statement::Local(pattern, nested));
+                    let signal = Expression::Variable(SIGNAL.to_owned());
+                    let finished = Expression::Primitive { operator: Primitive::Equal, arguments: vec![signal.clone(), Expression::Number(FINISHED)] };
+                    let stopped = signalled([converted(signal, level)], outputs, saved);
+                    let rest = self.escaping(&block[i + 1..], outputs, level, saved)?;
+                    let rest = Expression::If { condition: Box::new(finished), consequent: Box::new(rest), alternative: Box::new(stopped) };
+                    return Ok(blocks::block(lowered, rest))
+                }
+                _ => {
+                    lowered.extend(self.threaded(statement, outputs, saved)?);
+                    continue
+                }
+            };
+            return Ok(blocks::block(lowered, signalled([converted(Expression::Number(ended), level)], outputs, saved)))
+        }
+        let end = match level {
+            Level::Body(Some(condition)) => self.node(condition)?,
+            Level::Body(None) => Expression::Boolean(false),
+            Level::Nested => Expression::Number(FINISHED),
+        };
+        Ok(blocks::block(lowered, signalled([end], outputs, saved)))
+    }
+
+    /// the `if`, `do` or `match` `node` with a `break` or a `continue` inside, which produces a
+    /// tuple of how it ended, followed by the values of the locals named `outputs`
+    fn escaping_compound(&mut self, node: &Node, outputs: &[String]) -> LR<Expression> {
+        let nested = |lower: &mut Self, block: &[Node]| lower.escaping(block, outputs, Level::Nested, &mut HashMap::new());
+        let expression = match &node.ast {
+            Ast::If { condition, consequent, alternative } => Expression::If {
+                condition: Box::new(self.node(condition)?),
+                consequent: Box::new(self.scoped(|lower| nested(lower, consequent))?),
+                alternative: Box::new(self.scoped(|lower| nested(lower, alternative.as_deref().unwrap_or_default()))?),
+            },
+            Ast::Do(body) => self.scoped(|lower| nested(lower, body))?,
+            Ast::Match { scrutinee, cases } => self.matched(scrutinee, cases, nested)?,
+            _ => unreachable!("only an if, a do or a match is lowered with what escapes it"),
+        };
+        Ok(Expression::Located { span: node.span, expression: Box::new(expression) })
+    }
+
+    /// a statement of a block, or none for a type alias, which only changes how the statements
+    /// after it are lowered
+    fn statement(&mut self, node: &Node) -> LR<Option<Statement>> {
+        if self.depth > 1 && matches!(node.ast, Ast::Local { public: true, .. } | Ast::FunctionDeclaration { public: true, .. } | Ast::Reexport { .. }) {
+            return Err(LowerError::NestedExport { span: node.span })
+        }
+        Ok(Some(match &node.ast {
+            Ast::Local { names, values, .. } => {
+                let mut value = match (&names[..], &values[..]) {
+                    // a pattern destructures the value itself, rather than its first value
+                    ([Binder { pattern, .. }], [value]) if !matches!(pattern, ast::Pattern::Name(_)) => self.node(value)?,
+                    _ => self.values(values, names.len())?,
+                };
+                let pattern = match &names[..] {
+                    [Binder { pattern, annotation, .. }] => {
+                        if let Some(typ) = annotation {
+                            value = expanded("a type annotation", node.span, ascribe(value, self.resolve(typ)?));
+                        }
+                        lower_pattern(pattern)
+                    }
+                    names if names.iter().any(|binder| binder.annotation.is_some()) => {
+                        return Err(LowerError::Unsupported { construct: "an annotated multiple assignment", span: node.span })
+                    }
+                    names => Pattern::Tuple(names.iter().map(|binder| lower_pattern(&binder.pattern)).collect()),
+                };
+                let typ = self.infer(&value);
+                self.declare(names.iter().flat_map(|binder| binder.pattern.names()));
+                self.typed(&pattern, typ);
+                Statement::Local(pattern, value)
+            }
+            Ast::Assign { targets, values } => {
+                let mut names = vec![];
+                for target in targets {
+                    let Ast::Name(name) = &target.ast else {
+                        return Err(LowerError::Unsupported { construct: "assignment to anything but a name", span: target.span })
+                    };
+                    if self.captured.contains(name) {
+                        return Err(LowerError::Unsupported { construct: "an assignment to a local of another function", span: target.span })
+                    }
+                    if !self.locals.contains(name) {
+                        return Err(LowerError::NotALocal { name: name.clone(), span: target.span })
+                    }
+                    self.contracts.remove(name);
+                    self.signatures.remove(name);
+                    names.push(name.clone());
+                }
+                let mut value = self.values(values, names.len())?;
+                let pattern = match &names[..] {
+                    [name] => Pattern::Variable(name.clone()),
+                    names => Pattern::Tuple(names.iter().cloned().map(Pattern::Variable).collect()),
+                };
+                // a local keeps its type when it's assigned, so a loop can carry it
+                match names.iter().map(|name| self.types.get(name).cloned()).collect::<Option<Vec<_>>>() {
+                    Some(mut types) => {
+                        let typ = if types.len() == 1 { types.remove(0) } else { Type::Tuple(types) };
+                        value = expanded("an assignment", node.span, ascribe(value, typ));
+                    }
+                    None => {
+                        let typ = self.infer(&value);
+                        self.declare(&names);
+                        self.typed(&pattern, typ);
+                    }
+                }
+                Statement::Local(pattern, value)
+            }
+            Ast::FunctionDeclaration { total, operator, name, type_parameters, parameters, result, requires, ensures, body, .. } => {
+                if operator.is_some() && parameters.len() != 2 {
+                    return Err(LowerError::OperatorParameters { symbol: name.clone(), found: parameters.len(), span: node.span })
+                }
+                // an operator applies its function directly, so nothing would check its precondition
+                if operator.is_some() && !requires.is_empty() {
+                    return Err(LowerError::Unsupported { construct: "a precondition on an operator", span: node.span })
+                }
+                let (mut function, signature) = self.function(node.span, type_parameters, parameters, result, body)?;
+                // SGIR has no recursive bindings, so the name in its own body would be whatever it was before
+                if function.free_variables().contains(name) {
+                    return Err(LowerError::Recursive { name: name.clone(), span: occurrence(&function, name).unwrap_or(node.span) })
+                }
+                let (mut checker, mut contract) = (None, None);
+                if !requires.is_empty() || !ensures.is_empty() {
+                    if !parameters.iter().all(|binder| matches!(binder.pattern, ast::Pattern::Name(_))) {
+                        return Err(LowerError::Unsupported { construct: "a contract on a function with destructured parameters", span: node.span })
+                    }
+                    let Expression::Function { parameters, body } = &mut function else {
+                        return Err(LowerError::Unsupported { construct: "a contract on a generic function", span: node.span })
+                    };
+                    let (parameters, body) = (std::mem::take(parameters), body.take());
+                    if matches!(parameters.last(), Some(Binding { typ: Type::Rest(_), .. })) {
+                        return Err(LowerError::Unsupported { construct: "a contract on a variadic function", span: node.span })
+                    }
+                    checker = self.precondition(name, &parameters, requires)?
+                                  .map(|checker| expanded(&format!("the precondition of {}", name), node.span, checker));
+                    let body = self.postcondition(name, node.span, &parameters, body, ensures)?;
+                    contract = checker.is_some().then(|| parameters.clone());
+                    function = Expression::Function { parameters, body: Box::new(body) };
+                }
+                let typ = self.infer(&function);
+                self.declare([name]);
+                if let Some(typ) = typ {
+                    self.types.insert(name.clone(), typ);
+                }
+                if let Some(parameters) = contract {
+                    self.contracts.insert(name.clone(), parameters);
+                }
+                // a variadic function's calls are never elaborated
+                if !matches!(signature.last(), Some(Parameter { binding: Binding { typ: Type::Rest(_), .. }, .. })) {
+                    self.signatures.insert(name.clone(), signature);
+                }
+                if let Some(fixity) = operator {
+                    self.operators.declare(name, *fixity);
+                }
+                if *total {
+                    function = Expression::Total(Box::new(function));
+                }
+                let function = Expression::Located { span: node.span, expression: Box::new(function) };
+                match checker {
+                    Some(checker) => Statement::Local(Pattern::Tuple(vec![Pattern::Variable(requires_name(name)),
+                                                                          Pattern::Variable(name.clone())]),
+                                                      Expression::Tuple(vec![checker, function])),
+                    None => Statement::Local(Pattern::Variable(name.clone()), function),
+                }
+            }
+            Ast::TypeAlias { name, parameters, typ, .. } => {
+                let typ = self.shadowed(parameters, |lower| lower.resolve(typ))?;
+                self.aliases.insert(name.clone(), (parameters.clone(), typ));
+                return Ok(None)
+            }
+            Ast::MacroDeclaration { name, parameters, body } => return self.macro_declaration(name, parameters, body),
+            Ast::Reexport { module, name, alias } => {
+                let alias = alias.as_ref().unwrap_or(name);
+                self.declare([alias]);
+                let export = Expression::Located { span: node.span, expression: Box::new(Expression::Variable(format!("{}.{}", module, name))) };
+                Statement::Local(Pattern::Variable(alias.clone()), export)
+            }
+            // attributes are for the tools that read the source, like lints
+            Ast::FileAttribute(_) => return Ok(None),
+            // tests are only run by `sanguinello test`, which lowers each of them on its own
+            Ast::Test { .. } if self.depth > 1 => return Err(LowerError::NestedTest { span: node.span }),
+            Ast::Test { .. } => return Ok(None),
+            _ => Statement::Expression(self.node(node)?),
+        }))
+    }
+
+    /// a function, which is wrapped in a type abstraction if it has type parameters, along with
+    /// its parameters and their defaults. its body is what `return` returns to, with the result
+    /// type it's annotated with, or else the one its returns and its last value have in common.
+    fn function(&mut self, span: Span, type_parameters: &[String], parameters: &[Binder], result: &Option<Type>,
+                body: &[Node]) -> LR<(Expression, Vec<Parameter>)> {
+        let defaults = parameters.iter()
+                                 .map(|binder| binder.default.as_ref().map(|default| self.node(default)).transpose())
+                                 .collect::<LR<Vec<_>>>()?;
+        // a function can't assign the locals around it, and a loop around it isn't its own
+        let (captured, looping) = (self.captured.clone(), std::mem::replace(&mut self.looping, false));
+        self.captured.extend(self.locals.iter().cloned());
+        let function = self.shadowed(type_parameters, |lower| {
+            // a parameter that's taken apart is bound to a name of its own, which the body takes
+            // apart before anything else
+            let mut destructured = vec![];
+            let names = parameters.iter().flat_map(|binder| binder.pattern.names()).cloned().collect::<Vec<_>>();
+            let parameters = parameters.iter()
+                                       .enumerate()
+                                       .map(|(i, binder @ Binder { pattern, annotation, .. })| {
+                                           let Some(typ) = annotation else {
+                                               return Err(LowerError::MissingAnnotation { name: pattern.to_string(), span })
+                                           };
+                                           // `...: T` is annotated with the type of each argument
+                                           if binder.is_varargs() {
+                                               return Ok(Binding { id: VARARGS.to_owned(), typ: Type::Rest(Box::new(lower.resolve(typ)?)) })
+                                           }
+                                           let id = match pattern {
+                                               ast::Pattern::Name(name) => name.clone(),
+                                               pattern => {
+                                                   let id = format!("%parameter{}", i);
+                                                   destructured.push((lower_pattern(pattern), id.clone()));
+                                                   id
+                                               }
+                                           };
+                                           Ok(Binding { id, typ: lower.resolve(typ)? })
+                                       })
+                                       .collect::<LR<Vec<_>>>()?;
+            let mut body = lower.bound(&names, |lower| {
+                for Binding { id, typ } in &parameters {
+                    if !matches!(typ, Type::Rest(_)) {
+                        lower.types.insert(id.clone(), typ.clone());
+                    }
+                }
+                for (pattern, id) in &destructured {
+                    let typ = lower.types.get(id).cloned();
+                    lower.typed(pattern, typ);
+                }
+                lower.block(body)
+            })?;
+            for (pattern, id) in destructured.into_iter().rev() {
+                body = expanded("a destructured parameter", span, blocks::let_in(pattern, Expression::Variable(id), body));
+            }
+            let result = result.as_ref().map(|result| lower.resolve(result)).transpose()?;
+            body = Expression::Returning { result, body: Box::new(body) };
+            let signature = parameters.into_iter()
+                                      .zip(defaults)
+                                      .map(|(binding, default)| Parameter { binding, default })
+                                      .collect::<Vec<_>>();
+            let function = arguments::function(&signature, body).map_err(|error| LowerError::Arguments { error: Box::new(error), span })?;
+            Ok((function, signature))
+        });
+        (self.captured, self.looping) = (captured, looping);
+        let (function, signature) = function?;
+        if type_parameters.is_empty() {
+            return Ok((function, signature))
+        }
+        let function = Expression::TypeFunction {
+            parameters: type_parameters.iter().map(|id| TypeBinding { id: id.clone(), kind: Kind::Star }).collect(),
+            body: Box::new(function),
+        };
+        Ok((function, signature))
+    }
+
+    /// the function that checks the precondition of the function `name` with `parameters`, which
+    /// takes its arguments and who to blame if they don't satisfy it, or none if it has none
+    fn precondition(&mut self, name: &str, parameters: &[Binding], requires: &[Node]) -> LR<Option<Expression>> {
+        if requires.is_empty() {
+            return Ok(None)
+        }
+        let names = parameters.iter().map(|Binding { id, .. }| id.clone()).collect::<Vec<_>>();
+        let checks = self.bound(&names, |lower| {
+            requires.iter()
+                    .map(|clause| Ok(Statement::Expression(assert(lower.node(clause)?, contract_label("precondition", name, clause.span),
+                                                                  Expression::Variable(BLAME.to_owned())))))
+                    .collect::<LR<Vec<_>>>()
+        })?;
+        let blame = Binding { id: BLAME.to_owned(), typ: Type::String };
+        Ok(Some(Expression::Function {
+            parameters: parameters.iter().cloned().chain([blame]).collect(),
+            body: Box::new(blocks::block(checks, Expression::Tuple(vec![]))),
+        }))
+    }
+
+    /// `body`, checking that its value satisfies the postcondition of the function `name` with
+    /// `parameters`, which is to blame if it doesn't
+    fn postcondition(&mut self, name: &str, span: Span, parameters: &[Binding], body: Expression, ensures: &[Node]) -> LR<Expression> {
+        if ensures.is_empty() {
+            return Ok(body)
+        }
+        let names = parameters.iter().map(|Binding { id, .. }| id.clone()).chain([RESULT.to_owned()]).collect::<Vec<_>>();
+        let blame = format!("{} at {}:{}", name, span.start.line, span.start.column);
+        let checks = self.bound(&names, |lower| {
+            ensures.iter()
+                   .map(|clause| Ok(Statement::Expression(assert(lower.node(clause)?, contract_label("postcondition", name, clause.span),
+                                                                 Expression::String(blame.clone())))))
+                   .collect::<LR<Vec<_>>>()
+        })?;
+        let checked = blocks::let_in(Pattern::Variable(RESULT.to_owned()), body, blocks::block(checks, Expression::Variable(RESULT.to_owned())));
+        Ok(expanded(&format!("the postcondition of {}", name), span, checked))
+    }
+
+    /// declare the macro `name`, whose template is `body` lowered like the body of a function of
+    /// `parameters`. the names the template uses besides its parameters are bound to fresh locals
+    /// here, which it refers to instead, so that wherever it's expanded they refer to what they do
+    /// where it's declared, even under a binding of the same name.
+    fn macro_declaration(&mut self, name: &str, parameters: &[String], body: &[Node]) -> LR<Option<Statement>> {
+        let (captured, looping) = (self.captured.clone(), std::mem::replace(&mut self.looping, false));
+        self.captured.extend(self.locals.iter().cloned());
+        let template = self.bound(parameters, |lower| lower.block(body));
+        (self.captured, self.looping) = (captured, looping);
+        let template = template?;
+
+        let mut free = Macro { parameters: parameters.to_vec(), template: template.clone() }.free_variables()
+                                                                                           .into_iter()
+                                                                                           .collect::<Vec<_>>();
+        free.sort();
+        let aliases = free.iter().map(|id| self.names.fresh(id)).collect::<Vec<_>>();
+        let replacements = free.iter().cloned().zip(aliases.iter().cloned().map(Expression::Variable)).collect();
+        let template = macros::substitute(template, &replacements, &mut self.names);
+        for (id, alias) in free.iter().zip(&aliases) {
+            let typ = self.types.get(id).cloned();
+            self.declare([alias]);
+            if let Some(typ) = typ {
+                self.types.insert(alias.clone(), typ);
+            }
+        }
+        self.macros.insert(name.to_owned(), Macro { parameters: parameters.to_vec(), template });
+
+        Ok(match (&free[..], &aliases[..]) {
+            ([], []) => None,
+            ([id], [alias]) => Some(Statement::Local(Pattern::Variable(alias.clone()), Expression::Variable(id.clone()))),
+            _ => Some(Statement::Local(Pattern::Tuple(aliases.into_iter().map(Pattern::Variable).collect()),
+                                       Expression::Tuple(free.into_iter().map(Expression::Variable).collect()))),
+        })
+    }
+
+    /// run `f` with the values named like `names` bound as locals, so the functions they shadow
+    /// aren't checked against their contracts, and the macros they shadow aren't expanded
+    fn bound<T>(&mut self, names: &[String], f: impl FnOnce(&mut Self) -> LR<T>) -> LR<T> {
+        let (contracts, signatures, macros, locals, types, captured) = (self.contracts.clone(), self.signatures.clone(), self.macros.clone(),
+                                                                        self.locals.clone(), self.types.clone(), self.captured.clone());
+        self.declare(names);
+        let result = f(self);
+        (self.contracts, self.signatures, self.macros, self.locals, self.types, self.captured) = (contracts, signatures, macros, locals, types,
+                                                                                                  captured);
+        result
+    }
+
+    /// bring the locals named `names` into scope, whose types aren't known yet
+    fn declare<'a>(&mut self, names: impl IntoIterator<Item = &'a String>) {
+        for name in names {
+            self.contracts.remove(name);
+            self.signatures.remove(name);
+            self.macros.remove(name);
+            self.types.remove(name);
+            self.captured.remove(name);
+            self.locals.insert(name.clone());
+        }
+    }
+
+    /// note the types of the locals `pattern` binds, if it takes apart a value of the known type `typ`
+    fn typed(&mut self, pattern: &Pattern, typ: Option<Type>) {
+        let mut types = Declarations::new();
+        if typ.is_some_and(|typ| check_pattern(pattern, &typ, &mut types).is_ok()) {
+            self.types.extend(types);
+        }
+    }
+
+    /// the type of `expr`, if the types of the locals it refers to are known
+    fn infer(&self, expr: &Expression) -> Option<Type> {
+        check_with_abstract_types(&self.type_parameters, &self.types, expr.clone()).ok()
+    }
+
+    /// `values`, lowered and adjusted to `wanted` values. a call gives as many values as its
+    /// result type has, and so does a value that's the only one given for several names, since
+    /// it's taken apart like a tuple. anything else gives one value. if the type isn't known
+    /// here, the last value gives as many as are left to fill.
+    fn values(&mut self, values: &[Node], wanted: usize) -> LR<Expression> {
+        let mut adjusted = vec![];
+        for (i, value) in values.iter().enumerate() {
+            let lowered = self.node(value)?;
+            let spread = matches!(value.ast, Ast::Call { .. }) || (values.len() == 1 && wanted > 1);
+            let count = match self.infer(&lowered) {
+                _ if !spread => 1,
+                Some(typ) => multiple::count(&typ),
+                None if i + 1 == values.len() => wanted.saturating_sub(i).max(1),
+                None => 1,
+            };
+            adjusted.push((lowered, count));
+        }
+        Ok(multiple::adjust(adjusted, wanted))
+    }
+
+    /// run `f` with the aliases named like `parameters` out of scope, and them in scope as type
+    /// parameters instead
+    fn shadowed<T>(&mut self, parameters: &[String], f: impl FnOnce(&mut Self) -> LR<T>) -> LR<T> {
+        let aliases = self.aliases.clone();
+        for parameter in parameters {
+            self.aliases.remove(parameter);
+        }
+        let count = self.type_parameters.len();
+        self.type_parameters.extend(parameters.iter().map(|parameter| (parameter.clone(), Kind::Star)));
+        let result = f(self);
+        self.aliases = aliases;
+        self.type_parameters.truncate(count);
+        result
+    }
+
+    /// `typ` with the aliases in it expanded. the aliases in scope were expanded themselves when
+    /// they were declared, so one pass is enough.
+    fn resolve(&self, typ: &Type) -> LR<Type> {
+        let (name, arguments) = match typ {
+            Type::Variable(name) => (name, &[][..]),
+            Type::Instantiate { typ: function, arguments } => match &**function {
+                Type::Variable(name) => (name, &arguments[..]),
+                _ => return self.resolve_children(typ),
+            },
+            Type::ForAll { parameters, typ: body } => {
+                let mut inner = Lower { aliases: self.aliases.clone(), ..Lower::new(&self.operators) };
+                for binding in parameters {
+                    inner.aliases.remove(&binding.id);
+                }
+                return Ok(Type::ForAll { parameters: parameters.clone(), typ: Box::new(inner.resolve(body)?) })
+            }
+            _ => return self.resolve_children(typ),
+        };
+        let Some((parameters, alias)) = self.aliases.get(name) else {
+            return self.resolve_children(typ)
+        };
+        if parameters.len() != arguments.len() {
+            return Err(LowerError::AliasArity { name: name.clone(), expected: parameters.len(), found: arguments.len() })
+        }
+        let substitution = parameters.iter()
+                                     .cloned()
+                                     .zip(arguments.iter().map(|argument| self.resolve(argument)))
+                                     .map(|(parameter, argument)| Ok((parameter, argument?)))
+                                     .collect::<LR<HashMap<_, _>>>()?;
+        Ok(alias.clone().substitute(&substitution))
+    }
+
+    fn resolve_children(&self, typ: &Type) -> LR<Type> {
+        let mut error = None;
+        let resolved = typ.clone().map_children(|child| match self.resolve(&child) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                error.get_or_insert(e);
+                child
+            }
+        });
+        match error {
+            Some(error) => Err(error),
+            None => Ok(resolved),
+        }
+    }
+
+    fn node(&mut self, node: &Node) -> LR<Expression> {
+        let unsupported = |construct| Err(LowerError::Unsupported { construct, span: node.span });
+        let expression = match &node.ast {
+            // a function can't refer to the `...` of the one around it
+            Ast::Name(name) if name == VARARGS && (!self.locals.contains(name) || self.captured.contains(name)) => {
+                return Err(LowerError::NotVariadic { span: node.span })
+            }
+            // a function with a precondition that's passed around is checked when it's called
+            Ast::Name(name) => match self.contracts.get(name) {
+                Some(parameters) => {
+                    let checked = checked(name, parameters, format!("the call through the reference at {}:{}",
+                                                                    node.span.start.line, node.span.start.column));
+                    expanded(&format!("a reference to {}, which checks its precondition", name), node.span, checked)
+                }
+                None => Expression::Variable(name.clone()),
+            },
+            Ast::Boolean(value) => Expression::Boolean(*value),
+            Ast::Number(value) => Expression::Number(*value),
+            Ast::Float(value) => Expression::Float(Float(*value)),
+            Ast::String(value) => Expression::String(value.clone()),
+            Ast::Bytes(value) => Expression::Bytes(value.clone()),
+            Ast::Tuple(elements) => Expression::Tuple(self.all(elements)?),
+            Ast::Record(fields) => {
+                Expression::Record(fields.iter().map(|(field, value)| Ok((field.clone(), self.node(value)?))).collect::<LR<_>>()?)
+            }
+            Ast::Update { record, fields } => Expression::Update {
+                record: Box::new(self.node(record)?),
+                fields: fields.iter().map(|(field, value)| Ok((field.clone(), self.node(value)?))).collect::<LR<_>>()?,
+            },
+            // a macro's arguments are lowered where it's called, and put in place of its parameters
+            // unevaluated
+            Ast::Call { function, arguments } if matches!(&function.ast, Ast::Name(name) if self.macros.contains_key(name)) => {
+                if arguments.iter().any(|argument| matches!(argument.ast, Ast::Named { .. })) {
+                    return unsupported("a named argument of a macro")
+                }
+                let Ast::Name(name) = &function.ast else { unreachable!("a macro is called by its name") };
+                let call = Expression::Application { function: Box::new(Expression::Variable(name.clone())), arguments: self.all(arguments)? };
+                let expansion = macros::expand_macros(call, &self.macros, &mut self.names)
+                                       .map_err(|error| LowerError::Expansion { error, span: node.span })?;
+                expanded(&format!("the macro {}", name), node.span, expansion)
+            }
+            // `select(n, ...)` is the `n`th of the values packed into a `...`, counting from 1 as in
+            // Luau, and `select("#", ...)` is how many of them there are, unless there's a local
+            // `select`
+            Ast::Call { function, arguments } if matches!(&function.ast, Ast::Name(name) if name == "select" && !self.locals.contains(name)) => {
+                let [index, values] = &arguments[..] else {
+                    let error = TypeError::ArityMismatch { expected: 2, found: arguments.len() };
+                    return Err(LowerError::Arguments { error: Box::new(error), span: node.span })
+                };
+                match &index.ast {
+                    Ast::String(count) if count == "#" => Expression::Primitive { operator: Primitive::Count, arguments: vec![self.node(values)?] },
+                    _ => Expression::Primitive { operator: Primitive::Select, arguments: vec![self.node(index)?, self.node(values)?] },
+                }
+            }
+            // a call of the library, like `tostring(n, 16)`, is its operator, unless a local shadows it
+            Ast::Call { function, arguments } if self.library(function).is_some() => {
+                let operator = self.library(function).expect("checked by the guard");
+                if arguments.iter().any(|argument| matches!(argument.ast, Ast::Named { .. } | Ast::Placeholder)) {
+                    return unsupported("a named argument or a placeholder in a call of the library")
+                }
+                if !(operator.required()..=operator.arity()).contains(&arguments.len()) {
+                    let error = TypeError::ArityMismatch { expected: operator.arity(), found: arguments.len() };
+                    return Err(LowerError::Arguments { error: Box::new(error), span: node.span })
+                }
+                Expression::Primitive { operator, arguments: self.all(arguments)? }
+            }
+            Ast::Call { function, arguments } => {
+                // a call that names its arguments or leaves some out is elaborated against the
+                // signature of the function it calls, and one with placeholders against its type.
+                // `_` is only a placeholder if there's no local named `_`.
+                let named = arguments.iter().any(|argument| matches!(argument.ast, Ast::Named { .. }));
+                let partial = !self.locals.contains("_") && arguments.iter().any(|argument| matches!(argument.ast, Ast::Placeholder));
+                if named && partial {
+                    return unsupported("a placeholder in a call with named arguments")
+                }
+                let (signature, typ) = match &function.ast {
+                    Ast::Name(name) if !partial => {
+                        (self.signatures.get(name).filter(|parameters| named || parameters.len() > arguments.len()).cloned(), None)
+                    }
+                    Ast::Name(name) => (None, self.types.get(name).cloned()),
+                    _ => (None, None),
+                };
+                if named && signature.is_none() {
+                    return unsupported("a named argument of a function that isn't declared where it's called")
+                }
+                let count = signature.as_ref().map_or(arguments.len(), Vec::len);
+                let function = match &function.ast {
+                    Ast::Name(name) if self.contracts.get(name).is_some_and(|parameters| parameters.len() == count) => {
+                        let blame = format!("the call at {}:{}", node.span.start.line, node.span.start.column);
+                        expanded(&format!("a call of {}, which checks its precondition", name), function.span,
+                                 checked(name, &self.contracts[name], blame))
+                    }
+                    _ => self.node(function)?,
+                };
+                match signature {
+                    None if partial => {
+                        let typ = typ.or_else(|| self.infer(&function)).ok_or(LowerError::UnknownFunction { span: node.span })?;
+                        let arguments = arguments.iter()
+                                                 .map(|argument| match argument.ast {
+                                                     Ast::Placeholder => Ok(None),
+                                                     _ => self.node(argument).map(Some),
+                                                 })
+                                                 .collect::<LR<_>>()?;
+                        let applied = arguments::partial(function, &typ, arguments)
+                            .map_err(|error| LowerError::Arguments { error: Box::new(error), span: node.span })?;
+                        expanded("a partial application", node.span, applied)
+                    }
+                    Some(parameters) => {
+                        let (mut positional, mut named) = (vec![], vec![]);
+                        for argument in arguments {
+                            match &argument.ast {
+                                Ast::Named { name, value } => named.push((name.clone(), self.node(value)?)),
+                                _ => positional.push(self.node(argument)?),
+                            }
+                        }
+                        arguments::call(function, &parameters, positional, named)
+                            .map_err(|error| LowerError::Arguments { error: Box::new(error), span: node.span })?
+                    }
+                    None => Expression::Application { function: Box::new(function), arguments: self.all(arguments)? },
+                }
+            }
+            Ast::Named { .. } => return unsupported("a named argument outside of a call"),
+            Ast::Placeholder if self.locals.contains("_") => {
+                return self.node(&Node { ast: Ast::Name("_".to_owned()), span: node.span, leading: vec![], trailing: vec![] })
+            }
+            Ast::Placeholder => return unsupported("a placeholder outside of a call"),
+            Ast::Instantiate { function, arguments } => Expression::TypeApplication {
+                function: Box::new(self.node(function)?),
+                arguments: arguments.iter().map(|argument| self.resolve(argument)).collect::<LR<_>>()?,
+            },
+            Ast::Ascription { expression, typ } => {
+                expanded("a type ascription", node.span, ascribe(self.node(expression)?, self.resolve(typ)?))
+            }
+            Ast::Unary { operator, operand } => {
+                let operand = self.node(operand)?;
+                match operator.as_str() {
+                    "-" => expanded("a negation", node.span,
+                                    Expression::Primitive { operator: Primitive::Subtract, arguments: vec![Expression::Number(0), operand] }),
+                    "~" => Expression::Primitive { operator: Primitive::BitNot, arguments: vec![operand] },
+                    "#" => Expression::Primitive { operator: Primitive::Length, arguments: vec![operand] },
+                    "not" => expanded("a not", node.span, Expression::If { condition: Box::new(operand),
+                                                                          consequent: Box::new(Expression::Boolean(false)),
+                                                                          alternative: Box::new(Expression::Boolean(true)) }),
+                    _ => unreachable!("the parser only reads the prefix operators -, #, ~ and not"),
+                }
+            }
+            // `typeof(e) == "number"` tests the type of `e`, unless there's a local `typeof`
+            Ast::Operators { first, rest } if self.type_test(first, rest).is_some() => {
+                let (expression, tag) = self.type_test(first, rest).expect("checked by the guard");
+                Expression::TypeTest { expression: Box::new(self.node(expression)?), tag }
+            }
+            Ast::Operators { first, rest } => {
+                let rest = rest.iter()
+                               .map(|(operator, operand)| Ok((operator.clone(), self.node(operand)?)))
+                               .collect::<LR<_>>()?;
+                let first = self.node(first)?;
+                self.operators.resolve(first, rest)?
+            }
+            Ast::Function { type_parameters, parameters, result, body } => {
+                let (function, signature) = self.function(node.span, type_parameters, parameters, result, body)?;
+                // only a declared function's calls are elaborated against its signature
+                if signature.iter().any(|parameter| parameter.default.is_some()) {
+                    return unsupported("a default for a parameter of an anonymous function")
+                }
+                function
+            }
+            // what's assigned inside an expression couldn't be rebound after it
+            Ast::If { .. } | Ast::Do(_) | Ast::Match { .. } | Ast::While { .. } | Ast::Repeat { .. } | Ast::NumericFor { .. }
+            | Ast::GenericFor { .. } => match self.assigned(node)[..] {
+                [] => return self.compound(node, &[], true),
+                _ => return unsupported("an assignment inside an expression"),
+            },
+            // every one is replaced by the block for the target before lowering
+            Ast::IfTarget { .. } => return unsupported("a target conditional"),
+            Ast::Local { .. } | Ast::FunctionDeclaration { .. } | Ast::TypeAlias { .. } | Ast::MacroDeclaration { .. } | Ast::Reexport { .. }
+            | Ast::FileAttribute(_) | Ast::Test { .. } => {
+                self.block(std::slice::from_ref(node))?
+            }
+            Ast::Assign { .. } => return unsupported("an assignment inside an expression"),
+            Ast::Return(values) => Expression::Return(Box::new(multiple::returns(self.all(values)?))),
+            // `module.name` names a global, like a native or another module's export, unless a
+            // local is named `module`. any other field access projects a record.
+            Ast::Field { record, field } => match &record.ast {
+                Ast::Name(module) if !self.locals.contains(module) => Expression::Variable(format!("{}.{}", module, field)),
+                _ => {
+                    let projection = blocks::let_in(Pattern::Record(vec![(field.clone(), Pattern::Variable(field.clone()))]),
+                                                    self.node(record)?, Expression::Variable(field.clone()));
+                    expanded("a field access", node.span, projection)
+                }
+            },
+            // the body of a loop lowers the `break`s and `continue`s among its statements itself
+            Ast::Break | Ast::Continue if self.looping => return unsupported("a break or a continue inside an expression"),
+            Ast::Break => return Err(LowerError::OutsideLoop { construct: "break", span: node.span }),
+            Ast::Continue => return Err(LowerError::OutsideLoop { construct: "continue", span: node.span }),
+            Ast::Error => return unsupported("a syntax error"),
+        };
+        Ok(Expression::Located { span: node.span, expression: Box::new(expression) })
+    }
+
+    fn all(&mut self, nodes: &[Node]) -> LR<Vec<Expression>> {
+        nodes.iter().map(|node| self.node(node)).collect()
+    }
+
+    /// the operator of the library `function` names, as `name` or `module.name`, unless a local
+    /// is named `name` or `module`
+    /// what the chain `first rest` tests the type of, and the tag it tests for, if it's
+    /// `typeof(e) == "tag"`
+    fn type_test<'a>(&self, first: &'a Node, rest: &[(String, Node)]) -> Option<(&'a Node, TypeTag)> {
+        let (Ast::Call { function, arguments }, [(operator, Node { ast: Ast::String(tag), .. })]) = (&first.ast, rest) else {
+            return None
+        };
+        match (&function.ast, &arguments[..]) {
+            (Ast::Name(name), [argument]) if name == "typeof" && operator == "==" && !self.locals.contains(name)
+                                             && !matches!(argument.ast, Ast::Named { .. } | Ast::Placeholder) => {
+                Some((argument, TypeTag::named(tag)?))
+            }
+            _ => None,
+        }
+    }
+
+    fn library(&self, function: &Node) -> Option<Primitive> {
+        match &function.ast {
+            Ast::Name(name) if !self.locals.contains(name) => Primitive::library(name),
+            Ast::Field { record, field } => match &record.ast {
+                Ast::Name(module) if !self.locals.contains(module) => Primitive::library(&format!("{}.{}", module, field)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// whether `node` declares something, so that a block ending in it has no value
+fn is_declaration(node: &Node) -> bool {
+    matches!(node.ast, Ast::Local { .. } | Ast::Assign { .. } | Ast::FunctionDeclaration { .. } | Ast::TypeAlias { .. }
+                       | Ast::MacroDeclaration { .. } | Ast::Reexport { .. } | Ast::FileAttribute(_) | Ast::Test { .. })
+}
+
+/// whether `node` has blocks of statements that can assign the locals around it
+fn is_compound(node: &Node) -> bool {
+    matches!(node.ast, Ast::If { .. } | Ast::Do(_) | Ast::Match { .. } | Ast::While { .. } | Ast::Repeat { .. } | Ast::NumericFor { .. }
+                       | Ast::GenericFor { .. })
+}
+
+/// the locals the statement `node` declares
+fn declared(node: &Node) -> Vec<String> {
+    match &node.ast {
+        Ast::Local { names, .. } => names.iter().flat_map(|binder| binder.pattern.names()).cloned().collect(),
+        Ast::FunctionDeclaration { name, .. } => vec![name.clone()],
+        Ast::Reexport { name, alias, .. } => vec![alias.as_ref().unwrap_or(name).clone()],
+        _ => vec![],
+    }
+}
+
+/// add the names `node` assigns to `found`, in the order they're first assigned, except for
+/// those in `inner`, the locals declared inside what's being searched. a function assigns its
+/// own locals, and so does the template of a macro, and a test, which is lowered on its own.
+fn assignments(node: &Node, inner: &mut Vec<String>, found: &mut Vec<String>) {
+    match &node.ast {
+        Ast::Assign { targets, values } => {
+            for value in values {
+                assignments(value, inner, found);
+            }
+            for target in targets {
+                if let Ast::Name(name) = &target.ast {
+                    if !inner.contains(name) && !found.contains(name) {
+                        found.push(name.clone());
+                    }
+                }
+            }
+        }
+        Ast::Function { .. } | Ast::FunctionDeclaration { .. } | Ast::MacroDeclaration { .. } | Ast::Test { .. } => {}
+        _ => part_assignments(parts(node), inner, found),
+    }
+}
+
+/// add the names `parts` assign to `found`, like `assignments`
+fn part_assignments(parts: Vec<Part>, inner: &mut Vec<String>, found: &mut Vec<String>) {
+    for part in parts {
+        match part {
+            Part::Node(node) | Part::Operator(_, node) => {
+                assignments(node, inner, found);
+                inner.extend(declared(node));
+            }
+            Part::Scope(opening, parts) => {
+                let count = inner.len();
+                inner.extend(opening.names());
+                part_assignments(parts, inner, found);
+                inner.truncate(count);
+            }
+        }
+    }
+}
+
+/// whether `node` has a `break` or a `continue` of the loop it's in. those in a nested loop, a
+/// function or the template of a macro are theirs.
+fn escapes(node: &Node) -> bool {
+    match &node.ast {
+        Ast::Break | Ast::Continue => true,
+        Ast::While { .. } | Ast::Repeat { .. } | Ast::NumericFor { .. } | Ast::GenericFor { .. } | Ast::Function { .. }
+        | Ast::FunctionDeclaration { .. } | Ast::MacroDeclaration { .. } => false,
+        _ => node.children().into_iter().any(escapes),
+    }
+}
+
+/// `value`, if there is one, followed by the values of the locals named `outputs`, which are kept
+/// under the names in `saved` for those that were shadowed
+fn yielded(value: Option<Expression>, outputs: &[String], saved: &HashMap<String, String>) -> Expression {
+    if outputs.is_empty() {
+        return value.unwrap_or(Expression::Tuple(vec![]))
+    }
+    signalled(value, outputs, saved)
+}
+
+/// how a block in the body of a loop ended, `signal`, followed by the values of the locals named
+/// `outputs` like `yielded`, always as a tuple
+fn signalled(signal: impl IntoIterator<Item = Expression>, outputs: &[String], saved: &HashMap<String, String>) -> Expression {
+    let outputs = outputs.iter().map(|name| Expression::Variable(saved.get(name).unwrap_or(name).clone()));
+    Expression::Tuple(signal.into_iter().chain(outputs).collect())
+}
+
+/// how a nested block ended, `signal`, as a block at `level` ends: whether it stops the loop at
+/// the top of its body, and as it is anywhere else
+fn converted(signal: Expression, level: Level) -> Expression {
+    match level {
+        Level::Body(_) => Expression::Primitive { operator: Primitive::Equal, arguments: vec![signal, Expression::Number(BROKE)] },
+        Level::Nested => signal,
+    }
+}
+
+/// the name the function checking the precondition of `name` is bound to
+fn requires_name(name: &str) -> String {
+    format!("%requires.{}", name)
+}
+
+/// the parameter of a precondition for who to blame when it fails
+const BLAME: &str = "%blame";
+/// the name of a function's value in its postcondition
+const RESULT: &str = "result";
+
+fn contract_label(kind: &str, name: &str, span: Span) -> Expression {
+    Expression::String(format!("the {} of {} at {}:{}", kind, name, span.start.line, span.start.column))
+}
+
+fn assert(condition: Expression, contract: Expression, blame: Expression) -> Expression {
+    Expression::Primitive { operator: Primitive::Assert, arguments: vec![condition, contract, blame] }
+}
+
+/// a function that checks the precondition of the function `name` with `parameters`, blaming
+/// `blame` if it fails, then calls it
+fn checked(name: &str, parameters: &[Binding], blame: String) -> Expression {
+    let parameters = parameters.iter()
+                               .enumerate()
+                               .map(|(i, Binding { typ, .. })| Binding { id: format!("%argument{}", i), typ: typ.clone() })
+                               .collect::<Vec<_>>();
+    let arguments = || parameters.iter().map(|Binding { id, .. }| Expression::Variable(id.clone()));
+    let check = Expression::Application {
+        function: Box::new(Expression::Variable(requires_name(name))),
+        arguments: arguments().chain([Expression::String(blame)]).collect(),
+    };
+    let call = Expression::Application { function: Box::new(Expression::Variable(name.to_owned())), arguments: arguments().collect() };
+    Expression::Function { body: Box::new(blocks::block(vec![Statement::Expression(check)], call)), parameters }
+}
+
+/// the pattern a binder's or a case's pattern is matched with, which binds the same names
+fn lower_pattern(pattern: &ast::Pattern) -> Pattern {
+    match pattern {
+        ast::Pattern::Name(name) => Pattern::Variable(name.clone()),
+        ast::Pattern::Tuple(patterns) => Pattern::Tuple(patterns.iter().map(lower_pattern).collect()),
+        ast::Pattern::Record(fields) => {
+            Pattern::Record(fields.iter().map(|(field, pattern)| (field.clone(), lower_pattern(pattern))).collect())
+        }
+        ast::Pattern::Wildcard => Pattern::Wildcard,
+        ast::Pattern::Boolean(value) => Pattern::Boolean(*value),
+        ast::Pattern::Number(value) => Pattern::Number(*value),
+        ast::Pattern::String(value) => Pattern::String(value.clone()),
+        ast::Pattern::Variant { tag, payload } => Pattern::Variant { tag: tag.clone(), payload: Box::new(lower_pattern(payload)) },
+        ast::Pattern::Or(alternatives) => Pattern::Or(alternatives.iter().map(lower_pattern).collect()),
+    }
+}
+
+/// where the first free use of `name` in `expression` is
+fn occurrence(expression: &Expression, name: &str) -> Option<Span> {
+    if let Expression::Located { span, expression } = expression {
+        if matches!(&**expression, Expression::Variable(id) if id == name) {
+            return Some(*span)
+        }
+    }
+    expression.children()
+              .into_iter()
+              .filter(|child| child.free_variables().contains(name))
+              .find_map(|child| occurrence(child, name))
+}
+
+/// `expression`, the code lowering generated for `construct` at `span`
+fn expanded(construct: &str, span: Span, expression: Expression) -> Expression {
+    Expression::Expanded { construct: construct.to_owned(), span, expression: Box::new(expression) }
+}
+
+/// `expression`, checked against `typ`. SGIR has no ascription of its own, so this is the
+/// application of an identity function on `typ`.
+fn ascribe(expression: Expression, typ: Type) -> Expression {
+    let id = "%ascribed".to_owned();
+    Expression::Application {
+        function: Box::new(Expression::Function {
+            parameters: vec![Binding { id: id.clone(), typ }],
+            body: Box::new(Expression::Variable(id)),
+        }),
+        arguments: vec![expression],
+    }
+}
@@ -0,0 +1,68 @@
+use thiserror::Error;
+
+use super::lint::{Level, Levels, LINTS};
+
+/// the name of the manifest of a project, which is found in the directory of a program or the
+/// closest directory above it that has one
+pub const MANIFEST_NAME: &str = "sanguinello.toml";
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ManifestError {
+    #[error("expected a [table] or a key = \"value\" at line {0}")]
+    Syntax(usize),
+
+    #[error("unknown lint {name} at line {line}")]
+    UnknownLint {
+        name: String,
+        line: usize,
+    },
+
+    #[error("unknown lint level {level} at line {line}, expected allow, warn, or deny")]
+    UnknownLevel {
+        level: String,
+        line: usize,
+    },
+}
+
+/// the settings of a project, e.g.
+///
+/// ```toml
+/// [lints]
+/// unused_variable = "allow"
+/// confusable_identifier = "deny"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub lints: Levels,
+}
+
+/// read a manifest. it's TOML, but only tables of keys with string values, which is all the
+/// settings need, and tables other than `[lints]` are skipped so that older versions can read
+/// the manifests of newer ones.
+pub fn parse(source: &str) -> Result<Manifest, ManifestError> {
+    let mut manifest = Manifest::default();
+    let mut table = String::new();
+    for (i, line) in source.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+        if line.is_empty() {
+            continue
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            table = name.trim().to_owned();
+            continue
+        }
+        let Some((key, value)) = line.split_once('=') else { return Err(ManifestError::Syntax(i + 1)) };
+        let (key, value) = (key.trim(), value.trim());
+        let Some(value) = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) else {
+            return Err(ManifestError::Syntax(i + 1))
+        };
+        if table == "lints" {
+            if !LINTS.iter().any(|(name, _)| *name == key) {
+                return Err(ManifestError::UnknownLint { name: key.to_owned(), line: i + 1 })
+            }
+            let level = Level::named(value).ok_or_else(|| ManifestError::UnknownLevel { level: value.to_owned(), line: i + 1 })?;
+            manifest.lints.set(key, level);
+        }
+    }
+    Ok(manifest)
+}
@@ -0,0 +1,21 @@
+pub mod ast;
+pub mod completion;
+pub mod declarations;
+pub mod doc;
+pub mod grammar;
+pub mod hints;
+pub mod identifiers;
+pub mod incremental;
+pub mod json;
+pub mod lexer;
+pub mod lint;
+pub mod literal;
+pub mod lower;
+pub mod manifest;
+pub mod parser;
+pub mod print;
+pub mod reduce;
+pub mod rename;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,1229 @@
+use thiserror::Error;
+
+use crate::sgir::operators::{Associativity, Fixity};
+use crate::sgir::{Kind, Position, Span, Type, TypeBinding};
+
+use super::ast::{Ast, Attribute, Binder, Block, Case, Node, Pattern, VARARGS};
+use super::lexer::{lex, lex_from, LexError, Token, TokenKind};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum SyntaxError {
+    #[error(transparent)]
+    Lex(#[from] LexError),
+
+    #[error("expected {expected}, found {found} at {}:{}", span.start.line, span.start.column)]
+    Expected {
+        expected: &'static str,
+        found: String,
+        span: Span,
+    },
+
+    /// a construct of a compat dialect that has no counterpart to be translated to
+    #[error("{construct} at {}:{} is not supported in sanguinello", span.start.line, span.start.column)]
+    Unsupported {
+        construct: &'static str,
+        span: Span,
+    },
+}
+
+impl SyntaxError {
+    /// where in the source the error is, which is empty for an error in a single character
+    pub fn span(&self) -> Span {
+        match self {
+            SyntaxError::Lex(error) => Span { start: error.position(), end: error.position() },
+            SyntaxError::Expected { span, .. } | SyntaxError::Unsupported { span, .. } => *span,
+        }
+    }
+}
+
+/// the result of parsing a program: as much of it as could be parsed, with `Ast::Error` nodes
+/// where it couldn't, and every syntax error found along the way
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parse {
+    pub program: Block,
+    pub errors: Vec<SyntaxError>,
+}
+
+/// a language the parser can read programs in besides sanguinello, translating what it can, for
+/// porting code written in it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compat {
+    /// Luau, whose `local function f() ... end` declares a function, whose primitive types are
+    /// `number`, `boolean`, and `string`, and whose files are statements, with their value
+    /// returned at the end
+    Luau,
+}
+
+pub(crate) const KEYWORDS: &[&str] = &["and", "break", "continue", "do", "else", "elseif", "end", "false", "fn", "for", "function", "if", "in",
+                                       "local", "not", "or", "repeat", "return", "then", "true", "until", "while"];
+
+/// the keywords a statement can start with, where the parser picks up again after an error
+const STATEMENT_KEYWORDS: &[&str] = &["break", "continue", "do", "fn", "for", "function", "if", "local", "repeat", "return", "while"];
+
+/// the keywords that end a block
+const BLOCK_ENDS: &[&str] = &["else", "elseif", "end", "until"];
+
+const BINARY_OPERATORS: &[&str] = &["==", "~=", "<", "<=", ">", ">=", "|", "~", "&", "<<", ">>", "..", "+", "-", "*", "/",
+                                    "//", "%", "^"];
+
+/// the characters of the symbols a user-defined operator can be written with
+const OPERATOR_CHARACTERS: &str = "+-*/%^&|<>=~";
+
+type PR<T> = Result<T, SyntaxError>;
+
+/// everything about a function but its name: `<T...>(parameters): R requires p ensures q ... end`
+struct FunctionParts {
+    type_parameters: Vec<String>,
+    parameters: Vec<Binder>,
+    result: Option<Type>,
+    requires: Vec<Node>,
+    ensures: Vec<Node>,
+    body: Block,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+    /// where the last token consumed ended
+    end: Position,
+    errors: Vec<SyntaxError>,
+    /// the `>>` tokens split in two to close nested type arguments, as they were, so that they
+    /// can be put back when the parser backtracks
+    splits: Vec<(usize, Token)>,
+    /// whether this is in the body of a case of a `match`, which ends where the next `case`
+    /// starts
+    in_case: bool,
+    compat: Option<Compat>,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>, start: Position) -> Parser {
+        Parser { tokens, index: 0, end: start, errors: vec![], splits: vec![], in_case: false, compat: None }
+    }
+
+    fn peek(&self) -> &TokenKind {
+        &self.tokens[self.index].kind
+    }
+
+    fn lookahead(&self, n: usize) -> &TokenKind {
+        &self.tokens[(self.index + n).min(self.tokens.len() - 1)].kind
+    }
+
+    fn next(&mut self) {
+        if self.index + 1 < self.tokens.len() {
+            self.end = self.tokens[self.index].span.end;
+            self.index += 1;
+        }
+    }
+
+    fn start(&self) -> Position {
+        self.tokens[self.index].span.start
+    }
+
+    fn finish(&self, start: Position, ast: Ast) -> Node {
+        Node { ast, span: Span { start, end: self.end.max(start) }, leading: vec![], trailing: vec![] }
+    }
+
+    fn at_end(&self) -> bool {
+        *self.peek() == TokenKind::End
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), TokenKind::Identifier(name) if name == keyword)
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), TokenKind::Symbol(found) if *found == symbol)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = self.is_symbol(symbol);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn unexpected(&self, expected: &'static str) -> SyntaxError {
+        let token = &self.tokens[self.index];
+        SyntaxError::Expected { expected, found: token.kind.to_string(), span: token.span }
+    }
+
+    fn expect_keyword(&mut self, keyword: &'static str) -> PR<()> {
+        if self.eat_keyword(keyword) { Ok(()) } else { Err(self.unexpected(keyword)) }
+    }
+
+    fn expect_symbol(&mut self, symbol: &'static str) -> PR<()> {
+        if self.eat_symbol(symbol) { Ok(()) } else { Err(self.unexpected(symbol)) }
+    }
+
+    fn name(&mut self) -> PR<String> {
+        match self.peek() {
+            TokenKind::Identifier(name) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.next();
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    fn at_block_end(&self) -> bool {
+        self.at_end() || BLOCK_ENDS.iter().any(|keyword| self.is_keyword(keyword)) || (self.in_case && self.is_keyword("case"))
+    }
+
+    fn at_statement_start(&self) -> bool {
+        STATEMENT_KEYWORDS.iter().any(|keyword| self.is_keyword(keyword)) || self.is_symbol("@") || self.is_symbol("@!")
+    }
+
+    /// skip to the next statement after a syntax error, returning the node standing in for
+    /// what was skipped. something is always skipped, so that parsing makes progress.
+    fn recover(&mut self, error: SyntaxError, start_index: usize, start: Position,
+               boundary: impl Fn(&Parser) -> bool) -> Node {
+        self.errors.push(error);
+        if self.index == start_index && !self.at_block_end() && !boundary(self) {
+            self.next();
+        }
+        while !(self.at_block_end() || boundary(self)) {
+            self.next();
+        }
+        self.finish(start, Ast::Error)
+    }
+
+    /// the statements up to the end of the block. the comments after what opens it on the same
+    /// line go before the first of them, and those before what closes it after the last.
+    fn block(&mut self) -> Block {
+        let mut block: Block = vec![];
+        let mut opening = self.index.checked_sub(1).map_or(vec![], |before| self.tokens[before].trailing.clone());
+        loop {
+            while self.eat_symbol(";") {}
+            if self.at_block_end() {
+                if let Some(last) = block.last_mut() {
+                    last.trailing.extend(self.tokens[self.index].leading.iter().cloned());
+                }
+                return block
+            }
+            let (start_index, start) = (self.index, self.start());
+            let statement = match self.statement() {
+                Ok(statement) => statement,
+                Err(error) => self.recover(error, start_index, start,
+                                           |parser| parser.at_statement_start() || parser.is_symbol(";")),
+            };
+            let mut statement = self.commented(statement, start_index);
+            statement.leading.splice(0..0, std::mem::take(&mut opening));
+            block.push(statement);
+        }
+    }
+
+    /// `node`, the statement that starts at the token at `first` and ends just before this one,
+    /// with the comments around it: those on the lines before it, but for the doc comment its
+    /// declaration keeps, and those after it on its last line, including after the `;`s that
+    /// follow it, which this skips
+    fn commented(&mut self, mut node: Node, first: usize) -> Node {
+        let token = &self.tokens[first];
+        let documented = matches!(&node.ast, Ast::Local { doc: Some(_), .. } | Ast::TypeAlias { doc: Some(_), .. }
+                                             | Ast::FunctionDeclaration { doc: Some(_), .. });
+        let undocumented = token.leading.len() - if documented { token.doc_comments().len() } else { 0 };
+        node.leading = token.leading[..undocumented].to_vec();
+        if self.index > first {
+            node.trailing = self.tokens[self.index - 1].trailing.clone();
+        }
+        while self.is_symbol(";") {
+            let token = &self.tokens[self.index];
+            node.trailing.extend(token.leading.iter().chain(&token.trailing).cloned());
+            self.next();
+        }
+        node
+    }
+
+    /// `@name(arguments)` or, with `marker` `@!`, `@!name(arguments)`, where the arguments are
+    /// names and can be left out along with their parentheses
+    fn attribute(&mut self, marker: &'static str) -> PR<Attribute> {
+        let start = self.start();
+        self.expect_symbol(marker)?;
+        let name = self.name()?;
+        let mut arguments = vec![];
+        if self.eat_symbol("(") {
+            arguments.push(self.name()?);
+            while self.eat_symbol(",") {
+                arguments.push(self.name()?);
+            }
+            self.expect_symbol(")")?;
+        }
+        Ok(Attribute { name, arguments, span: self.finish(start, Ast::Error).span })
+    }
+
+    fn statement(&mut self) -> PR<Node> {
+        let doc = self.tokens[self.index].doc();
+        let mut attributes = vec![];
+        while self.is_symbol("@") && !self.is_target_conditional() {
+            attributes.push(self.attribute("@")?);
+        }
+        let start = self.start();
+        let public = self.is_visibility() && self.eat_keyword("pub");
+        if public && attributes.is_empty() && self.eat_keyword("use") {
+            let module = self.name()?;
+            self.expect_symbol(".")?;
+            let name = self.name()?;
+            let alias = if self.eat_keyword("as") { Some(self.name()?) } else { None };
+            return Ok(self.finish(start, Ast::Reexport { module, name, alias }))
+        }
+        if (public || !attributes.is_empty()) && !self.is_keyword("local") && !self.is_function_declaration() && !self.is_operator_declaration() {
+            return Err(self.unexpected(if attributes.is_empty() { "a declaration after `pub`" } else { "a declaration after its attributes" }))
+        }
+        // luau's `local function f` is a function declaration, which is local to its block anyway
+        if self.compat == Some(Compat::Luau)
+            && self.is_keyword("local")
+            && matches!(self.lookahead(1), TokenKind::Identifier(keyword) if keyword == "function") {
+            self.next();
+        }
+        let ast = if self.eat_keyword("local") {
+            let mut names = vec![self.binder()?];
+            while self.eat_symbol(",") {
+                names.push(self.binder()?);
+            }
+            self.expect_symbol("=")?;
+            Ast::Local { attributes, public, names, values: self.expressions()?, doc }
+        } else if self.is_type_alias() {
+            self.next();
+            let name = self.name()?;
+            let mut parameters = vec![];
+            if self.eat_symbol("<") {
+                parameters.push(self.name()?);
+                while self.eat_symbol(",") {
+                    parameters.push(self.name()?);
+                }
+                self.expect_closing_angle()?;
+            }
+            self.expect_symbol("=")?;
+            Ast::TypeAlias { name, parameters, typ: self.typ()?, doc }
+        } else if self.is_macro_declaration() {
+            self.next();
+            let name = self.name()?;
+            self.expect_symbol("(")?;
+            let mut parameters = vec![];
+            if !self.is_symbol(")") {
+                parameters.push(self.name()?);
+                while self.eat_symbol(",") {
+                    parameters.push(self.name()?);
+                }
+            }
+            self.expect_symbol(")")?;
+            let body = self.block();
+            self.expect_keyword("end")?;
+            Ast::MacroDeclaration { name, parameters, body }
+        } else if self.is_test() {
+            self.next();
+            let TokenKind::String(name) = self.peek().clone() else { unreachable!("checked by is_test") };
+            self.next();
+            let body = self.block();
+            self.expect_keyword("end")?;
+            Ast::Test { name, body }
+        } else if self.is_function_declaration() {
+            let total = self.eat_keyword("total");
+            self.next();
+            let name = self.name()?;
+            let FunctionParts { type_parameters, parameters, result, requires, ensures, body } = self.function_body(true)?;
+            Ast::FunctionDeclaration { attributes, public, total, operator: None, name, type_parameters, parameters, result, requires, ensures, body, doc }
+        } else if self.is_operator_declaration() {
+            self.next();
+            let (name, length) = self.operator_at(self.index).ok_or_else(|| self.unexpected("an operator"))?;
+            for _ in 0..length {
+                self.next();
+            }
+            let associativity = match self.peek() {
+                TokenKind::Identifier(word) if word == "left" => Associativity::Left,
+                TokenKind::Identifier(word) if word == "right" => Associativity::Right,
+                TokenKind::Identifier(word) if word == "none" => Associativity::None,
+                _ => return Err(self.unexpected("`left`, `right` or `none`")),
+            };
+            self.next();
+            let precedence = match self.peek() {
+                TokenKind::Number(precedence) => u8::try_from(*precedence).map_err(|_| self.unexpected("a precedence from 0 to 255"))?,
+                _ => return Err(self.unexpected("a precedence")),
+            };
+            self.next();
+            let FunctionParts { type_parameters, parameters, result, requires, ensures, body } = self.function_body(true)?;
+            let operator = Some(Fixity { precedence, associativity });
+            Ast::FunctionDeclaration { attributes, public, total: false, operator, name, type_parameters, parameters, result, requires, ensures, body, doc }
+        } else if self.eat_keyword("return") {
+            let mut values = vec![];
+            if !self.at_block_end() && !self.is_symbol(";") {
+                values = self.expressions()?;
+            }
+            Ast::Return(values)
+        } else if self.eat_keyword("while") {
+            let condition = Box::new(self.expression()?);
+            self.expect_keyword("do")?;
+            let body = self.block();
+            self.expect_keyword("end")?;
+            Ast::While { condition, body }
+        } else if self.eat_keyword("repeat") {
+            let body = self.block();
+            self.expect_keyword("until")?;
+            Ast::Repeat { body, condition: Box::new(self.expression()?) }
+        } else if self.eat_keyword("for") {
+            let variable = self.name()?;
+            if self.eat_symbol("=") {
+                let start = Box::new(self.expression()?);
+                self.expect_symbol(",")?;
+                let stop = Box::new(self.expression()?);
+                let step = if self.eat_symbol(",") { Some(Box::new(self.expression()?)) } else { None };
+                let body = self.loop_body()?;
+                Ast::NumericFor { variable, start, stop, step, body }
+            } else {
+                self.expect_keyword("in")?;
+                let iterable = Box::new(self.expression()?);
+                let body = self.loop_body()?;
+                Ast::GenericFor { variable, iterable, body }
+            }
+        } else if self.eat_keyword("break") {
+            Ast::Break
+        } else if self.eat_keyword("continue") {
+            Ast::Continue
+        } else {
+            let expression = self.expression()?;
+            if !self.is_symbol("=") && !self.is_symbol(",") {
+                return Ok(expression)
+            }
+            let mut targets = vec![expression];
+            while self.eat_symbol(",") {
+                targets.push(self.expression()?);
+            }
+            self.expect_symbol("=")?;
+            let values = self.expressions()?;
+            if let Some(target) = targets.iter().find(|target| !matches!(target.ast, Ast::Name(_))) {
+                let construct = match target.ast {
+                    Ast::Field { .. } => "assignment to a field",
+                    _ => "assignment to anything but a name",
+                };
+                return Err(SyntaxError::Unsupported { construct, span: self.finish(start, Ast::Error).span })
+            }
+            Ast::Assign { targets, values }
+        };
+        Ok(self.finish(start, ast))
+    }
+
+    /// `do ... end`
+    fn loop_body(&mut self) -> PR<Block> {
+        self.expect_keyword("do")?;
+        let body = self.block();
+        self.expect_keyword("end")?;
+        Ok(body)
+    }
+
+    /// `type Name = ...` or `type Name<T> = ...`. `type` isn't a keyword, so it can still be used
+    /// as a name anywhere else.
+    fn is_type_alias(&self) -> bool {
+        self.is_keyword("type")
+            && matches!(self.lookahead(1), TokenKind::Identifier(_))
+            && matches!(self.lookahead(2), TokenKind::Symbol("=" | "<"))
+    }
+
+    /// `macro name(`, which starts a macro declaration. like `type`, `macro` isn't a keyword.
+    fn is_macro_declaration(&self) -> bool {
+        self.is_keyword("macro")
+            && matches!(self.lookahead(1), TokenKind::Identifier(_))
+            && matches!(self.lookahead(2), TokenKind::Symbol("("))
+    }
+
+    /// `@if`, which starts a target conditional rather than an attribute
+    fn is_target_conditional(&self) -> bool {
+        self.is_symbol("@") && matches!(self.lookahead(1), TokenKind::Identifier(keyword) if keyword == "if")
+    }
+
+    /// `test "name"`, which starts a test. `test` isn't a keyword either.
+    fn is_test(&self) -> bool {
+        self.is_keyword("test") && matches!(self.lookahead(1), TokenKind::String(_))
+    }
+
+    /// `pub` before a declaration or a re-export, `pub use ...`. `pub` isn't a keyword, and
+    /// neither is `use`.
+    fn is_visibility(&self) -> bool {
+        self.is_keyword("pub")
+            && matches!(self.lookahead(1), TokenKind::Identifier(keyword) if ["local", "function", "fn", "total", "use"].contains(&keyword.as_str()))
+    }
+
+    /// `total function name ...`. `total` isn't a keyword either.
+    fn is_total_function(&self) -> bool {
+        self.is_keyword("total")
+            && matches!(self.lookahead(1), TokenKind::Identifier(keyword) if keyword == "function" || keyword == "fn")
+            && matches!(self.lookahead(2), TokenKind::Identifier(_))
+    }
+
+    /// `function name ...`, or `fn name ...` for short, possibly `total`, or luau's
+    /// `local function name ...`
+    fn is_function_declaration(&self) -> bool {
+        let local = usize::from(self.compat == Some(Compat::Luau) && self.is_keyword("local"));
+        self.is_total_function()
+            || (matches!(self.lookahead(local), TokenKind::Identifier(keyword) if keyword == "function" || keyword == "fn")
+                && matches!(self.lookahead(local + 1), TokenKind::Identifier(_)))
+    }
+
+    /// `operator <+> left 6 (parameters) ... end`, which declares an infix operator and its
+    /// fixity. `operator` isn't a keyword, and nor are the associativities.
+    fn is_operator_declaration(&self) -> bool {
+        let Some((_, length)) = self.is_keyword("operator").then(|| self.operator_at(self.index + 1)).flatten() else {
+            return false
+        };
+        matches!(self.lookahead(1 + length), TokenKind::Identifier(word) if ["left", "right", "none"].contains(&word.as_str()))
+            && matches!(self.lookahead(2 + length), TokenKind::Number(_))
+    }
+
+    /// `<T...>(parameters): R ... end`, with the `requires` and `ensures` clauses of a contract
+    /// before the body if `contracts` is set. `requires` and `ensures` are only keywords there.
+    fn function_body(&mut self, contracts: bool) -> PR<FunctionParts> {
+        let (type_parameters, parameters, result) = self.signature()?;
+        let (mut requires, mut ensures) = (vec![], vec![]);
+        while contracts && (self.is_keyword("requires") || self.is_keyword("ensures")) {
+            let clauses = if self.is_keyword("requires") { &mut requires } else { &mut ensures };
+            self.next();
+            clauses.push(self.expression()?);
+        }
+        let body = self.block();
+        self.expect_keyword("end")?;
+        Ok(FunctionParts { type_parameters, parameters, result, requires, ensures, body })
+    }
+
+    /// `<T...>(parameters): R`, the type parameters, parameters and result of a function
+    fn signature(&mut self) -> PR<(Vec<String>, Vec<Binder>, Option<Type>)> {
+        let mut type_parameters = vec![];
+        if self.eat_symbol("<") {
+            type_parameters.push(self.name()?);
+            while self.eat_symbol(",") {
+                type_parameters.push(self.name()?);
+            }
+            self.expect_symbol(">")?;
+        }
+        self.expect_symbol("(")?;
+        let mut parameters = vec![];
+        if !self.is_symbol(")") {
+            parameters.push(self.parameter()?);
+            // `...` is the last parameter
+            while !parameters.last().is_some_and(Binder::is_varargs) && self.eat_symbol(",") {
+                parameters.push(self.parameter()?);
+            }
+        }
+        self.expect_symbol(")")?;
+        let result = if self.eat_symbol(":") { Some(self.typ()?) } else { None };
+        Ok((type_parameters, parameters, result))
+    }
+
+    /// a declaration of a declaration file: `function name<T...>(parameters): R`, without a body,
+    /// `local name: T`, or a type alias. what's declared is public, since the host or the module
+    /// that provides it does so for the program to use.
+    fn declaration(&mut self) -> PR<Node> {
+        if self.is_type_alias() {
+            return self.statement()
+        }
+        let (start, doc) = (self.start(), self.tokens[self.index].doc());
+        let ast = if self.eat_keyword("function") || self.eat_keyword("fn") {
+            let name = self.name()?;
+            let (type_parameters, parameters, result) = self.signature()?;
+            Ast::FunctionDeclaration { attributes: vec![], public: true, total: false, operator: None, name, type_parameters, parameters,
+                                       result, requires: vec![], ensures: vec![], body: vec![], doc }
+        } else if self.eat_keyword("local") {
+            let name = self.name()?;
+            self.expect_symbol(":")?;
+            let names = vec![Binder::name(&name, Some(self.typ()?))];
+            Ast::Local { attributes: vec![], public: true, names, values: vec![], doc }
+        } else {
+            return Err(self.unexpected("a declaration"))
+        };
+        Ok(self.finish(start, ast))
+    }
+
+    /// `pattern` or `pattern: T`
+    fn binder(&mut self) -> PR<Binder> {
+        let pattern = self.pattern(false)?;
+        let annotation = if self.eat_symbol(":") { Some(self.typ()?) } else { None };
+        Ok(Binder { pattern, annotation, default: None })
+    }
+
+    /// a binder, with a default after it if it's `binder = e`, or `...: T`, which takes any number
+    /// of arguments of type `T`
+    fn parameter(&mut self) -> PR<Binder> {
+        let start = self.start();
+        let binder = if self.eat_symbol("...") {
+            let annotation = if self.eat_symbol(":") { Some(self.typ()?) } else { None };
+            Binder { annotation, ..Binder::name(VARARGS, None) }
+        } else {
+            let binder = self.binder()?;
+            let default = if self.eat_symbol("=") { Some(self.expression()?) } else { None };
+            Binder { default, ..binder }
+        };
+        // Luau infers the types of parameters, but sanguinello needs them written down. the rest
+        // of the function parses all the same, so it's only noted.
+        if self.compat == Some(Compat::Luau) && binder.annotation.is_none() {
+            let span = self.finish(start, Ast::Error).span;
+            self.errors.push(SyntaxError::Unsupported { construct: "a parameter without a type annotation", span });
+        }
+        Ok(binder)
+    }
+
+    /// a name, or a tuple or record of patterns. like an expression, `(p)` is only parenthesized,
+    /// and `(p,)` is a tuple. a `refutable` pattern, of a case of a `match`, can also be `_`, a
+    /// literal, a case of a variant, `Tag(p)`, or alternatives, `p | q`.
+    fn pattern(&mut self, refutable: bool) -> PR<Pattern> {
+        let pattern = self.primary_pattern(refutable)?;
+        if !(refutable && self.is_symbol("|")) {
+            return Ok(pattern)
+        }
+        let mut alternatives = vec![pattern];
+        while self.eat_symbol("|") {
+            alternatives.push(self.primary_pattern(true)?);
+        }
+        Ok(Pattern::Or(alternatives))
+    }
+
+    fn primary_pattern(&mut self, refutable: bool) -> PR<Pattern> {
+        if self.eat_symbol("(") {
+            if self.eat_symbol(")") {
+                return Ok(Pattern::Tuple(vec![]))
+            }
+            let pattern = self.pattern(refutable)?;
+            if !self.eat_symbol(",") {
+                self.expect_symbol(")")?;
+                return Ok(pattern)
+            }
+            let mut patterns = vec![pattern];
+            while !self.eat_symbol(")") {
+                patterns.push(self.pattern(refutable)?);
+                if !self.eat_symbol(",") {
+                    self.expect_symbol(")")?;
+                    break
+                }
+            }
+            Ok(Pattern::Tuple(patterns))
+        } else if self.eat_symbol("{") {
+            let mut fields = vec![];
+            while !self.eat_symbol("}") {
+                let field = self.name()?;
+                let pattern = if self.eat_symbol("=") { self.pattern(refutable)? } else { Pattern::Name(field.clone()) };
+                fields.push((field, pattern));
+                if !self.eat_symbol(",") {
+                    self.expect_symbol("}")?;
+                    break
+                }
+            }
+            Ok(Pattern::Record(fields))
+        } else if !refutable {
+            Ok(Pattern::Name(self.name()?))
+        } else {
+            let pattern = match self.peek().clone() {
+                TokenKind::Identifier(name) if name == "_" => Pattern::Wildcard,
+                TokenKind::Identifier(name) if name == "true" || name == "false" => Pattern::Boolean(name == "true"),
+                TokenKind::Number(value) => Pattern::Number(value),
+                TokenKind::String(value) => Pattern::String(value),
+                TokenKind::Symbol("-") => match *self.lookahead(1) {
+                    TokenKind::Number(value) => {
+                        self.next();
+                        Pattern::Number(-value)
+                    }
+                    _ => return Err(self.unexpected("a pattern")),
+                },
+                _ => {
+                    let name = self.name().map_err(|_| self.unexpected("a pattern"))?;
+                    if !self.is_symbol("(") {
+                        return Ok(Pattern::Name(name))
+                    }
+                    return Ok(Pattern::Variant { tag: name, payload: Box::new(self.primary_pattern(true)?) })
+                }
+            };
+            self.next();
+            Ok(pattern)
+        }
+    }
+
+    /// a type: a union of intersections, e.g. `(Number) -> Number | {x: T}?`
+    fn typ(&mut self) -> PR<Type> {
+        let mut members = vec![self.intersection_type()?];
+        while self.eat_symbol("|") {
+            members.push(self.intersection_type()?);
+        }
+        Ok(if members.len() == 1 { members.remove(0) } else { Type::Union(members) })
+    }
+
+    fn intersection_type(&mut self) -> PR<Type> {
+        let mut members = vec![self.optional_type()?];
+        while self.eat_symbol("&") {
+            members.push(self.optional_type()?);
+        }
+        Ok(if members.len() == 1 { members.remove(0) } else { Type::Intersection(members) })
+    }
+
+    /// `T?`, which is `T | ()`: there's no nil, so the unit stands in for an absent value
+    fn optional_type(&mut self) -> PR<Type> {
+        let mut typ = self.primary_type()?;
+        while self.eat_symbol("?") {
+            typ = Type::Union(vec![typ, Type::Tuple(vec![])]);
+        }
+        Ok(typ)
+    }
+
+    fn primary_type(&mut self) -> PR<Type> {
+        // a parenthesized type, a tuple, or the parameters of a function type
+        if self.eat_symbol("(") {
+            let mut types = vec![];
+            if !self.is_symbol(")") {
+                types.push(self.typ()?);
+                while self.eat_symbol(",") {
+                    types.push(self.typ()?);
+                }
+            }
+            self.expect_symbol(")")?;
+            if self.eat_symbol("->") {
+                return Ok(Type::Function { arguments: types, result: Box::new(self.typ()?) })
+            }
+            return Ok(if types.len() == 1 { types.remove(0) } else { Type::Tuple(types) })
+        }
+        if self.eat_symbol("{") {
+            let mut fields = vec![];
+            while !self.is_symbol("}") {
+                let field = self.name()?;
+                self.expect_symbol(":")?;
+                fields.push((field, self.typ()?));
+                if !self.eat_symbol(",") {
+                    break
+                }
+            }
+            self.expect_symbol("}")?;
+            return Ok(Type::Record(fields))
+        }
+        if self.eat_symbol("...") {
+            return Ok(Type::Rest(Box::new(self.optional_type()?)))
+        }
+        if self.is_keyword("forall") && *self.lookahead(1) == TokenKind::Symbol("<") {
+            self.next();
+            self.next();
+            let mut parameters = vec![self.name()?];
+            while self.eat_symbol(",") {
+                parameters.push(self.name()?);
+            }
+            self.expect_closing_angle()?;
+            self.expect_symbol(".")?;
+            let parameters = parameters.into_iter().map(|id| TypeBinding { id, kind: Kind::Star }).collect();
+            return Ok(Type::ForAll { parameters, typ: Box::new(self.typ()?) })
+        }
+
+        let name = self.name().map_err(|_| self.unexpected("a type"))?;
+        let typ = match name.as_str() {
+            "Number" => Type::Number,
+            "Boolean" => Type::Boolean,
+            "String" => Type::String,
+            "number" if self.compat == Some(Compat::Luau) => Type::Number,
+            "boolean" if self.compat == Some(Compat::Luau) => Type::Boolean,
+            "string" if self.compat == Some(Compat::Luau) => Type::String,
+            "Float" => Type::Float,
+            "Char" => Type::Char,
+            "Bytes" => Type::Bytes,
+            _ => Type::Variable(name),
+        };
+        if !self.eat_symbol("<") {
+            return Ok(typ)
+        }
+        let mut arguments = vec![self.typ()?];
+        while self.eat_symbol(",") {
+            arguments.push(self.typ()?);
+        }
+        self.expect_closing_angle()?;
+        Ok(Type::Instantiate { typ: Box::new(typ), arguments })
+    }
+
+    /// consume the `>` closing a list of type parameters or arguments. the lexer reads the `>>`
+    /// that closes two nested lists as a shift, so that's split in two.
+    fn expect_closing_angle(&mut self) -> PR<()> {
+        if !self.is_symbol(">>") {
+            return self.expect_symbol(">")
+        }
+        let token = self.tokens[self.index].clone();
+        let middle = Position { column: token.span.start.column + 1, ..token.span.start };
+        self.splits.push((self.index, token));
+        let token = &mut self.tokens[self.index];
+        token.kind = TokenKind::Symbol(">");
+        token.span.start = middle;
+        self.end = middle;
+        Ok(())
+    }
+
+    /// the type arguments of an explicit instantiation, `<T...>`, which is only taken to be one
+    /// if it's followed by the `(` of a call: otherwise the `<` is a comparison, and nothing is
+    /// consumed
+    fn type_arguments(&mut self) -> Option<Vec<Type>> {
+        self.attempt(|parser| {
+            parser.expect_symbol("<")?;
+            let mut arguments = vec![parser.typ()?];
+            while parser.eat_symbol(",") {
+                arguments.push(parser.typ()?);
+            }
+            parser.expect_closing_angle()?;
+            if parser.is_symbol("(") { Ok(arguments) } else { Err(parser.unexpected("(")) }
+        })
+    }
+
+    /// what `parse` parses from here, or nothing if it fails, when the parser backtracks to
+    /// where it started as if it had never been tried
+    fn attempt<T>(&mut self, parse: impl FnOnce(&mut Parser) -> PR<T>) -> Option<T> {
+        let (index, end, errors, splits) = (self.index, self.end, self.errors.len(), self.splits.len());
+        let parsed = parse(self);
+        if parsed.is_err() {
+            (self.index, self.end) = (index, end);
+            self.errors.truncate(errors);
+            for (index, token) in self.splits.drain(splits..).rev() {
+                self.tokens[index] = token;
+            }
+        }
+        parsed.ok()
+    }
+
+    /// `e, f, ...`, one or more expressions separated by commas
+    fn expressions(&mut self) -> PR<Vec<Node>> {
+        let mut values = vec![self.expression()?];
+        while self.eat_symbol(",") {
+            values.push(self.expression()?);
+        }
+        Ok(values)
+    }
+
+    fn expression(&mut self) -> PR<Node> {
+        let start = self.start();
+        let first = self.unary()?;
+        let rest = self.operators(|_| true)?;
+        if rest.is_empty() {
+            Ok(first)
+        } else {
+            Ok(self.finish(start, Ast::Operators { first: Box::new(first), rest }))
+        }
+    }
+
+    /// the infix operators and their right operands from here on, as long as the operators
+    /// satisfy `f`
+    fn operators(&mut self, f: impl Fn(&str) -> bool) -> PR<Vec<(String, Node)>> {
+        let mut rest = vec![];
+        while let Some((operator, length)) = self.binary_operator().filter(|(operator, _)| f(operator)) {
+            for _ in 0..length {
+                self.next();
+            }
+            rest.push((operator, self.unary()?));
+        }
+        Ok(rest)
+    }
+
+    /// the infix operator here, if there is one, and how many tokens it's written with
+    fn binary_operator(&self) -> Option<(String, usize)> {
+        match self.peek() {
+            TokenKind::Identifier(name) if name == "and" || name == "or" => Some((name.clone(), 1)),
+            _ => self.operator_at(self.index),
+        }
+    }
+
+    /// the infix operator the symbols from the token at `index` on are written as: a built-in
+    /// one, or a run of symbols with nothing in between, like `<+>`. the run leaves out a `-` or
+    /// `~` at its end, which is a prefix operator of the operand after it, so `a==-1` is still
+    /// `a == -1`.
+    fn operator_at(&self, index: usize) -> Option<(String, usize)> {
+        let symbol = |i: usize| match self.tokens.get(i)?.kind {
+            TokenKind::Symbol(symbol) => Some(symbol),
+            _ => None,
+        };
+        let is_operator = |symbol: &str| symbol.chars().all(|c| OPERATOR_CHARACTERS.contains(c));
+        let first = symbol(index)?;
+        let mut run = vec![first];
+        while let Some(next) = symbol(index + run.len()) {
+            let i = index + run.len();
+            if !(is_operator(first) && is_operator(next) && self.tokens[i].span.start == self.tokens[i - 1].span.end) {
+                break
+            }
+            run.push(next);
+        }
+        while run.len() > 1 && matches!(run.last(), Some(&("-" | "~"))) {
+            run.pop();
+        }
+        Some((run.concat(), run.len())).filter(|(operator, length)| *length > 1 || BINARY_OPERATORS.contains(&operator.as_str()))
+    }
+
+    fn unary(&mut self) -> PR<Node> {
+        let start = self.start();
+        let operator = match self.peek() {
+            TokenKind::Symbol(symbol @ ("-" | "#" | "~")) => symbol.to_string(),
+            TokenKind::Identifier(name) if name == "not" => name.clone(),
+            _ => return self.postfix(),
+        };
+        self.next();
+        let mut operand = self.unary()?;
+        // `^` binds more tightly than a prefix operator, so `-x ^ 2` is `-(x ^ 2)`
+        let rest = self.operators(|operator| operator == "^")?;
+        if !rest.is_empty() {
+            operand = self.finish(operand.span.start, Ast::Operators { first: Box::new(operand), rest });
+        }
+        Ok(self.finish(start, Ast::Unary { operator, operand: Box::new(operand) }))
+    }
+
+    fn postfix(&mut self) -> PR<Node> {
+        let start = self.start();
+        let mut node = self.primary()?;
+        loop {
+            if self.eat_symbol("(") {
+                let arguments = self.arguments()?;
+                node = self.finish(start, Ast::Call { function: Box::new(node), arguments });
+            } else if self.eat_symbol(".") {
+                let field = self.name()?;
+                node = self.finish(start, Ast::Field { record: Box::new(node), field });
+            } else if let Some(arguments) = self.is_symbol("<").then(|| self.type_arguments()).flatten() {
+                node = self.finish(start, Ast::Instantiate { function: Box::new(node), arguments });
+            } else if self.eat_symbol("::") {
+                let typ = self.typ()?;
+                node = self.finish(start, Ast::Ascription { expression: Box::new(node), typ });
+            } else if self.compat == Some(Compat::Luau) && self.eat_symbol("[") {
+                self.expression()?;
+                self.expect_symbol("]")?;
+                return Err(SyntaxError::Unsupported { construct: "indexing with `[]`", span: self.finish(start, Ast::Error).span })
+            } else {
+                return Ok(node)
+            }
+        }
+    }
+
+    /// the arguments of a call, after its `(`. a bad argument is skipped up to the next one.
+    fn arguments(&mut self) -> PR<Vec<Node>> {
+        let mut arguments = vec![];
+        if self.eat_symbol(")") {
+            return Ok(arguments)
+        }
+        loop {
+            // the arguments given by name come after all of the others
+            if matches!(arguments.last(), Some(Node { ast: Ast::Named { .. }, .. })) && !self.is_named_argument() {
+                return Err(self.unexpected("a named argument"))
+            }
+            let (start_index, start) = (self.index, self.start());
+            arguments.push(match self.argument() {
+                Ok(argument) => argument,
+                Err(error) => self.recover(error, start_index, start,
+                                           |parser| parser.is_symbol(",") || parser.is_symbol(")") || parser.at_statement_start()),
+            });
+            if !self.eat_symbol(",") {
+                self.expect_symbol(")")?;
+                return Ok(arguments)
+            }
+        }
+    }
+
+    /// `name = e`, an argument given by the name of its parameter
+    fn is_named_argument(&self) -> bool {
+        matches!(self.peek(), TokenKind::Identifier(_)) && *self.lookahead(1) == TokenKind::Symbol("=")
+    }
+
+    /// an argument of a call
+    fn argument(&mut self) -> PR<Node> {
+        let start = self.start();
+        if self.is_keyword("_") && matches!(self.lookahead(1), TokenKind::Symbol("," | ")")) {
+            self.next();
+            return Ok(self.finish(start, Ast::Placeholder))
+        }
+        if !self.is_named_argument() {
+            return self.expression()
+        }
+        let name = self.name()?;
+        self.expect_symbol("=")?;
+        let value = Box::new(self.expression()?);
+        Ok(self.finish(start, Ast::Named { name, value }))
+    }
+
+    fn primary(&mut self) -> PR<Node> {
+        let start = self.start();
+        // `match` isn't a keyword: it only starts a match if the expression after it is followed
+        // by a `case`
+        if self.is_keyword("match") {
+            let scrutinee = self.attempt(|parser| {
+                parser.next();
+                let scrutinee = parser.expression()?;
+                if parser.is_keyword("case") { Ok(scrutinee) } else { Err(parser.unexpected("case")) }
+            });
+            if let Some(scrutinee) = scrutinee {
+                return self.match_rest(start, scrutinee)
+            }
+        }
+        let ast = match self.peek().clone() {
+            TokenKind::Identifier(name) => match name.as_str() {
+                "true" | "false" => {
+                    self.next();
+                    Ast::Boolean(name == "true")
+                }
+                "nil" if self.compat == Some(Compat::Luau) => {
+                    return Err(SyntaxError::Unsupported { construct: "nil", span: self.tokens[self.index].span })
+                }
+                "function" | "fn" => {
+                    self.next();
+                    let FunctionParts { type_parameters, parameters, result, body, .. } = self.function_body(false)?;
+                    Ast::Function { type_parameters, parameters, result, body }
+                }
+                "if" => {
+                    self.next();
+                    return self.if_rest(start)
+                }
+                "do" => {
+                    self.next();
+                    let body = self.block();
+                    self.expect_keyword("end")?;
+                    Ast::Do(body)
+                }
+                _ => Ast::Name(self.name().map_err(|_| self.unexpected("an expression"))?),
+            },
+            TokenKind::Number(value) => {
+                self.next();
+                Ast::Number(value)
+            }
+            TokenKind::Float(value) => {
+                self.next();
+                Ast::Float(value)
+            }
+            TokenKind::String(value) => {
+                self.next();
+                Ast::String(value)
+            }
+            TokenKind::Bytes(value) => {
+                self.next();
+                Ast::Bytes(value)
+            }
+            // `target` isn't a keyword, and only the name of a target can be compared with it
+            TokenKind::Symbol("@") if self.is_target_conditional() => {
+                self.next();
+                self.next();
+                self.expect_keyword("target")?;
+                self.expect_symbol("==")?;
+                let TokenKind::String(target) = self.peek().clone() else { return Err(self.unexpected("the name of a target")) };
+                self.next();
+                self.expect_keyword("then")?;
+                let consequent = self.block();
+                let alternative = if self.eat_keyword("else") { Some(self.block()) } else { None };
+                self.expect_keyword("end")?;
+                Ast::IfTarget { target, consequent, alternative }
+            }
+            // the arguments of a variadic function packed into a tuple, which is bound like a name
+            TokenKind::Symbol("...") => {
+                self.next();
+                Ast::Name(VARARGS.to_owned())
+            }
+            // a parenthesized expression, unless there's a comma, when it's a tuple
+            TokenKind::Symbol("(") => {
+                self.next();
+                if self.eat_symbol(")") {
+                    return Ok(self.finish(start, Ast::Tuple(vec![])))
+                }
+                let expression = self.expression()?;
+                if !self.eat_symbol(",") {
+                    self.expect_symbol(")")?;
+                    return Ok(expression)
+                }
+                let mut elements = vec![expression];
+                while !self.eat_symbol(")") {
+                    elements.push(self.expression()?);
+                    if !self.eat_symbol(",") {
+                        self.expect_symbol(")")?;
+                        break
+                    }
+                }
+                Ast::Tuple(elements)
+            }
+            // a record literal starts with `field =`, and anything else is the record an update
+            // copies. `with` isn't a keyword, since it can't follow an expression otherwise.
+            TokenKind::Symbol("{") => {
+                self.next();
+                if self.is_symbol("}") || matches!(self.lookahead(1), TokenKind::Symbol("=")) {
+                    Ast::Record(self.fields()?)
+                } else {
+                    let record = Box::new(self.expression()?);
+                    self.expect_keyword("with")?;
+                    Ast::Update { record, fields: self.fields()? }
+                }
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        Ok(self.finish(start, ast))
+    }
+
+    /// the fields of a record, `x = 1, y = 2`, up to and including its `}`
+    fn fields(&mut self) -> PR<Vec<(String, Node)>> {
+        let mut fields = vec![];
+        while !self.eat_symbol("}") {
+            let field = self.name()?;
+            self.expect_symbol("=")?;
+            fields.push((field, self.expression()?));
+            if !self.eat_symbol(",") {
+                self.expect_symbol("}")?;
+                break
+            }
+        }
+        Ok(fields)
+    }
+
+    /// the cases of a `match` after its scrutinee, up to and including its `end`. within them,
+    /// `case` can't start a statement, since it starts the next case.
+    fn match_rest(&mut self, start: Position, scrutinee: Node) -> PR<Node> {
+        let in_case = std::mem::replace(&mut self.in_case, true);
+        let cases = (|| -> PR<Vec<Case>> {
+            let mut cases = vec![];
+            while self.eat_keyword("case") {
+                let pattern = self.pattern(true)?;
+                let guard = if self.eat_keyword("if") { Some(self.expression()?) } else { None };
+                self.expect_keyword("then")?;
+                cases.push(Case { pattern, guard, body: self.block() });
+            }
+            Ok(cases)
+        })();
+        self.in_case = in_case;
+        let cases = cases?;
+        self.expect_keyword("end")?;
+        Ok(self.finish(start, Ast::Match { scrutinee: Box::new(scrutinee), cases }))
+    }
+
+    /// the rest of an `if` after the keyword. an `elseif` is parsed as a nested `if`, which
+    /// consumes the one `end` they share.
+    fn if_rest(&mut self, start: Position) -> PR<Node> {
+        let condition = Box::new(self.expression()?);
+        self.expect_keyword("then")?;
+        let consequent = self.block();
+        let alternative = if self.is_keyword("elseif") {
+            let start = self.start();
+            self.next();
+            Some(vec![self.if_rest(start)?])
+        } else if self.eat_keyword("else") {
+            let alternative = self.block();
+            self.expect_keyword("end")?;
+            Some(alternative)
+        } else {
+            self.expect_keyword("end")?;
+            None
+        };
+        Ok(self.finish(start, Ast::If { condition, consequent, alternative }))
+    }
+}
+
+/// parse `source`, carrying on past syntax errors to find as many as possible
+pub fn parse(source: &str) -> Parse {
+    match parse_items(source, Position { line: 1, column: 1 }) {
+        Ok(items) => {
+            let (program, errors): (Vec<_>, Vec<_>) = items.into_iter().unzip();
+            Parse { program, errors: errors.into_iter().flatten().collect() }
+        }
+        Err(error) => Parse { program: vec![], errors: vec![error.into()] },
+    }
+}
+
+/// parse `source` as though it started at `start` in a larger file, into its top-level
+/// statements, each with the syntax errors found in it
+pub(crate) fn parse_items(source: &str, start: Position) -> Result<Vec<(Node, Vec<SyntaxError>)>, LexError> {
+    parse_items_in(source, start, None)
+}
+
+fn parse_items_in(source: &str, start: Position, compat: Option<Compat>) -> Result<Vec<(Node, Vec<SyntaxError>)>, LexError> {
+    let tokens = lex_from(source, start)?;
+    let mut parser = Parser { compat, ..Parser::new(tokens, start) };
+
+    let mut items: Vec<(Node, Vec<SyntaxError>)> = vec![];
+    loop {
+        while parser.eat_symbol(";") {}
+        if parser.at_end() {
+            // the comments at the end of the file follow its last statement
+            if let Some((last, _)) = items.last_mut() {
+                last.trailing.extend(parser.tokens[parser.index].leading.iter().cloned());
+            }
+            return Ok(items)
+        }
+        let (start_index, start) = (parser.index, parser.start());
+        let node = if parser.is_symbol("@!") {
+            match parser.attribute("@!") {
+                Ok(attribute) => parser.finish(start, Ast::FileAttribute(attribute)),
+                Err(error) => parser.recover(error, start_index, start,
+                                             |parser| parser.at_statement_start() || parser.is_symbol(";")),
+            }
+        } else if parser.at_block_end() {
+            // a stray `end` or the like, which no block is waiting for
+            let error = parser.unexpected("a statement");
+            parser.errors.push(error);
+            parser.next();
+            parser.finish(start, Ast::Error)
+        } else {
+            match parser.statement() {
+                Ok(statement) => statement,
+                Err(error) => parser.recover(error, start_index, start,
+                                             |parser| parser.at_statement_start() || parser.is_symbol(";")),
+            }
+        };
+        let node = parser.commented(node, start_index);
+        items.push((node, std::mem::take(&mut parser.errors)));
+    }
+}
+
+/// parse `source`, a program written in the `compat` language, into sanguinello's syntax. the
+/// constructs that have no counterpart are syntax errors.
+pub fn parse_compat(source: &str, compat: Compat) -> Parse {
+    let items = match parse_items_in(source, Position { line: 1, column: 1 }, Some(compat)) {
+        Ok(items) => items,
+        Err(error) => return Parse { program: vec![], errors: vec![error.into()] },
+    };
+    let (mut program, errors): (Vec<_>, Vec<_>) = items.into_iter().unzip();
+    // the value of a statement-oriented file is what it returns at its end
+    if let Some(last) = program.last_mut() {
+        if let Ast::Return(values) = &mut last.ast {
+            last.ast = match values.len() {
+                1 => values.remove(0).ast,
+                _ => Ast::Tuple(std::mem::take(values)),
+            };
+        }
+    }
+    Parse { program, errors: errors.into_iter().flatten().collect() }
+}
+
+/// parse `source` as a declaration file, which declares the types of what a host or a foreign
+/// module provides without defining it. a function is declared as a function declaration with
+/// no body, and a value as a local with no value.
+pub fn parse_declarations(source: &str) -> Parse {
+    let tokens = match lex(source) {
+        Ok(tokens) => tokens,
+        Err(error) => return Parse { program: vec![], errors: vec![error.into()] },
+    };
+    let mut parser = Parser::new(tokens, Position { line: 1, column: 1 });
+    let mut program = vec![];
+    loop {
+        while parser.eat_symbol(";") {}
+        if parser.at_end() {
+            return Parse { program, errors: parser.errors }
+        }
+        let (start_index, start) = (parser.index, parser.start());
+        // a stray `end` or the like, which `recover` wouldn't skip
+        if parser.at_block_end() {
+            let error = parser.unexpected("a declaration");
+            parser.errors.push(error);
+            parser.next();
+            program.push(parser.finish(start, Ast::Error));
+            continue
+        }
+        program.push(match parser.declaration() {
+            Ok(declaration) => declaration,
+            // skipping at least what the declaration started with, which may begin a statement
+            // but never a declaration, like the `return` of a body
+            Err(error) => parser.recover(error, start_index, start, |parser| {
+                parser.index > start_index && ["function", "fn", "local", "type"].iter().any(|keyword| parser.is_keyword(keyword))
+            }),
+        });
+    }
+}
+
+/// parse `source` as a type, e.g. `(Number, T?) -> {x: T}`
+pub fn parse_type(source: &str) -> Result<Type, SyntaxError> {
+    let mut parser = Parser::new(lex(source)?, Position { line: 1, column: 1 });
+    let typ = parser.typ()?;
+    if !parser.at_end() {
+        return Err(parser.unexpected("the end of the type"))
+    }
+    Ok(typ)
+}
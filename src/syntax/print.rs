@@ -0,0 +1,471 @@
+use std::fmt::{self, Display, Formatter, Write};
+
+use crate::sgir::operators::Fixity;
+use crate::sgir::{numbers, Type};
+
+use super::ast::{Ast, Attribute, Binder, Block, Case, Node, Pattern};
+use super::lexer::{bytes_literal, string_literal, Comment};
+
+const INDENTATION: &str = "  ";
+
+/// a pattern as it's written, with a field that binds a name like it on its own, and alternatives
+/// within alternatives parenthesized
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let separated = |f: &mut Formatter, patterns: Vec<String>| write!(f, "{}", patterns.join(", "));
+        match self {
+            Pattern::Name(name) => write!(f, "{}", name),
+            Pattern::Tuple(patterns) => {
+                write!(f, "(")?;
+                separated(f, patterns.iter().map(Pattern::to_string).collect())?;
+                write!(f, "{})", if patterns.len() == 1 { "," } else { "" })
+            }
+            Pattern::Record(fields) => {
+                write!(f, "{{")?;
+                separated(f, fields.iter().map(|(field, pattern)| match pattern {
+                    Pattern::Name(name) if name == field => field.clone(),
+                    pattern => format!("{} = {}", field, pattern),
+                }).collect())?;
+                write!(f, "}}")
+            }
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Boolean(value) => write!(f, "{}", value),
+            Pattern::Number(value) => write!(f, "{}", value),
+            Pattern::String(value) => write!(f, "{}", string_literal(value)),
+            Pattern::Variant { tag, payload } => match &**payload {
+                Pattern::Tuple(patterns) if patterns.len() != 1 => write!(f, "{}{}", tag, payload),
+                payload => write!(f, "{}({})", tag, payload),
+            },
+            Pattern::Or(alternatives) => write!(f, "{}", alternatives.iter().map(|pattern| match pattern {
+                Pattern::Or(_) => format!("({})", pattern),
+                pattern => pattern.to_string(),
+            }).collect::<Vec<_>>().join(" | ")),
+        }
+    }
+}
+
+/// writes the source of a syntax tree, one statement to a line
+struct Printer {
+    output: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        self.output.push('\n');
+        self.output.push_str(&INDENTATION.repeat(self.indent));
+    }
+
+    fn separated<T>(&mut self, items: &[T], mut write: impl FnMut(&mut Self, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            write(self, item);
+        }
+    }
+
+    /// the statements of `block` on the lines after this one, indented one level further,
+    /// followed by a line for what closes it
+    fn block(&mut self, block: &[Node]) {
+        self.block_after(block, false);
+    }
+
+    /// `block`, which follows an expression if `after_expression` is set, e.g. the body of a
+    /// function after its contract
+    fn block_after(&mut self, block: &[Node], after_expression: bool) {
+        self.indent += 1;
+        for (i, node) in block.iter().enumerate() {
+            self.newline();
+            self.statement(node, after_expression || i > 0);
+        }
+        self.indent -= 1;
+        self.newline();
+    }
+
+    /// a statement, after a `;` if it would otherwise be read as continuing the expression
+    /// before it, e.g. `(a, b)` as the arguments of a call to the `f` before it, or `-x` as a
+    /// subtraction from it
+    fn statement(&mut self, node: &Node, after_expression: bool) {
+        for comment in &node.leading {
+            self.comment(comment);
+            self.newline();
+        }
+        let start = self.output.len();
+        self.node(node);
+        // a declaration can start with its doc comment, which isn't a subtraction
+        let printed = &self.output[start..];
+        if after_expression && printed.starts_with(['(', '-', '~']) && !printed.starts_with("--") {
+            self.output.insert(start, ';');
+        }
+        for comment in &node.trailing {
+            if comment.span.start.line == node.span.end.line {
+                self.write(" ");
+            } else {
+                self.newline();
+            }
+            self.comment(comment);
+        }
+    }
+
+    /// a comment, with brackets long enough that its text can't close them if it's a block
+    /// comment
+    fn comment(&mut self, Comment { text, block, .. }: &Comment) {
+        if !block {
+            write!(self.output, "--{}", text).unwrap();
+            return
+        }
+        let closing = |level| format!("]{}]", "=".repeat(level));
+        let level = (0..).find(|&level| format!("{}{}", text, closing(level)).find(&closing(level)) == Some(text.len())).unwrap();
+        write!(self.output, "--[{0}[{1}]{0}]", "=".repeat(level), text).unwrap();
+    }
+
+    fn attributes(&mut self, attributes: &[Attribute]) {
+        for attribute in attributes {
+            self.attribute("@", attribute);
+            self.write(" ");
+        }
+    }
+
+    /// a doc comment, a `---` comment for each of its lines, on the lines before its declaration
+    fn doc(&mut self, doc: &Option<String>) {
+        for line in doc.iter().flat_map(|doc| doc.split('\n')) {
+            self.write("---");
+            if !line.is_empty() {
+                write!(self.output, " {}", line).unwrap();
+            }
+            self.newline();
+        }
+    }
+
+    fn visibility(&mut self, public: bool) {
+        if public {
+            self.write("pub ");
+        }
+    }
+
+    fn attribute(&mut self, marker: &str, Attribute { name, arguments, .. }: &Attribute) {
+        write!(self.output, "{}{}", marker, name).unwrap();
+        if !arguments.is_empty() {
+            write!(self.output, "({})", arguments.join(", ")).unwrap();
+        }
+    }
+
+    fn binders(&mut self, binders: &[Binder]) {
+        self.separated(binders, |printer, Binder { pattern, annotation, default }| {
+            write!(printer.output, "{}", pattern).unwrap();
+            if let Some(typ) = annotation {
+                write!(printer.output, ": {}", typ).unwrap();
+            }
+            if let Some(default) = default {
+                printer.write(" = ");
+                printer.node(default);
+            }
+        });
+    }
+
+    /// `<T...>(parameters): R`
+    fn signature(&mut self, type_parameters: &[String], parameters: &[Binder], result: &Option<Type>) {
+        if !type_parameters.is_empty() {
+            write!(self.output, "<{}>", type_parameters.join(", ")).unwrap();
+        }
+        self.write("(");
+        self.binders(parameters);
+        self.write(")");
+        if let Some(result) = result {
+            write!(self.output, ": {}", result).unwrap();
+        }
+    }
+
+    /// `node` where a postfix operator will follow it, parenthesized unless it's an operand that
+    /// ends where it looks like it does
+    fn postfix_operand(&mut self, node: &Node) {
+        match &node.ast {
+            Ast::Name(_) | Ast::Boolean(_) | Ast::String(_) | Ast::Bytes(_) | Ast::Tuple(_) | Ast::Record(_) | Ast::Update { .. } | Ast::Call { .. } | Ast::Field { .. }
+            | Ast::Instantiate { .. } => self.node(node),
+            _ => self.parenthesized(node),
+        }
+    }
+
+    /// `node` as an operand of an infix or prefix operator. the parser leaves parentheses out
+    /// of the tree, so a chain of operators within another was parenthesized.
+    fn operand(&mut self, node: &Node) {
+        match &node.ast {
+            Ast::Operators { .. } | Ast::Ascription { .. } => self.parenthesized(node),
+            _ => self.node(node),
+        }
+    }
+
+    fn parenthesized(&mut self, node: &Node) {
+        self.write("(");
+        self.node(node);
+        self.write(")");
+    }
+
+    fn node(&mut self, node: &Node) {
+        match &node.ast {
+            Ast::Name(name) => self.write(name),
+            Ast::Boolean(value) => write!(self.output, "{}", value).unwrap(),
+            Ast::Number(value) => self.write(&numbers::format(*value, 10)),
+            Ast::Float(value) => self.write(&numbers::format_float(*value)),
+            Ast::String(value) => self.write(&string_literal(value)),
+            Ast::Bytes(value) => self.write(&bytes_literal(value)),
+            Ast::Tuple(elements) => {
+                self.write("(");
+                self.separated(elements, Self::node);
+                if elements.len() == 1 {
+                    self.write(",");
+                }
+                self.write(")");
+            }
+            Ast::Record(fields) => {
+                self.write("{");
+                self.separated(fields, |printer, (field, value)| {
+                    write!(printer.output, "{} = ", field).unwrap();
+                    printer.node(value);
+                });
+                self.write("}");
+            }
+            Ast::Update { record, fields } => {
+                self.write("{");
+                self.node(record);
+                self.write(" with ");
+                self.separated(fields, |printer, (field, value)| {
+                    write!(printer.output, "{} = ", field).unwrap();
+                    printer.node(value);
+                });
+                self.write("}");
+            }
+            Ast::Call { function, arguments } => {
+                self.postfix_operand(function);
+                self.write("(");
+                self.separated(arguments, Self::node);
+                self.write(")");
+            }
+            Ast::Named { name, value } => {
+                write!(self.output, "{} = ", name).unwrap();
+                self.node(value);
+            }
+            Ast::Placeholder => self.write("_"),
+            Ast::Field { record, field } => {
+                self.postfix_operand(record);
+                write!(self.output, ".{}", field).unwrap();
+            }
+            Ast::Unary { operator, operand } => {
+                self.write(operator);
+                if operator == "not" {
+                    self.write(" ");
+                }
+                // `- -x` would be a comment without the parentheses
+                match &operand.ast {
+                    Ast::Unary { .. } => self.parenthesized(operand),
+                    _ => self.operand(operand),
+                }
+            }
+            Ast::Operators { first, rest } => {
+                // `^` binds more tightly than a prefix operator, so `(-x) ^ 2` keeps its parentheses
+                let mut operand = &**first;
+                for (operator, next) in rest {
+                    match &operand.ast {
+                        Ast::Unary { .. } if operator == "^" => self.parenthesized(operand),
+                        _ => self.operand(operand),
+                    }
+                    write!(self.output, " {} ", operator).unwrap();
+                    operand = next;
+                }
+                self.operand(operand);
+            }
+            Ast::Function { type_parameters, parameters, result, body } => {
+                self.write("function");
+                self.signature(type_parameters, parameters, result);
+                self.block(body);
+                self.write("end");
+            }
+            Ast::Ascription { expression, typ } => {
+                self.postfix_operand(expression);
+                write!(self.output, " :: {}", typ).unwrap();
+            }
+            Ast::Instantiate { function, arguments } => {
+                self.postfix_operand(function);
+                self.write("<");
+                self.separated(arguments, |printer, typ| write!(printer.output, "{}", typ).unwrap());
+                self.write(">");
+            }
+            Ast::If { condition, consequent, alternative } => {
+                self.write("if ");
+                self.node(condition);
+                self.write(" then");
+                self.block(consequent);
+                match alternative.as_deref() {
+                    // an `elseif` is an `if` alone in the alternative, sharing its `end`
+                    Some([nested @ Node { ast: Ast::If { .. }, .. }]) => {
+                        self.write("else");
+                        self.node(nested);
+                    }
+                    Some(alternative) => {
+                        self.write("else");
+                        self.block(alternative);
+                        self.write("end");
+                    }
+                    None => self.write("end"),
+                }
+            }
+            Ast::Do(body) => {
+                self.write("do");
+                self.block(body);
+                self.write("end");
+            }
+            Ast::IfTarget { target, consequent, alternative } => {
+                self.write(&format!("@if target == {} then", string_literal(target)));
+                self.block(consequent);
+                if let Some(alternative) = alternative {
+                    self.write("else");
+                    self.block(alternative);
+                }
+                self.write("end");
+            }
+            Ast::Match { scrutinee, cases } => {
+                self.write("match ");
+                self.node(scrutinee);
+                self.newline();
+                for Case { pattern, guard, body } in cases {
+                    write!(self.output, "case {}", pattern).unwrap();
+                    if let Some(guard) = guard {
+                        self.write(" if ");
+                        self.node(guard);
+                    }
+                    self.write(" then");
+                    self.block(body);
+                }
+                self.write("end");
+            }
+            Ast::Local { attributes, public, names, values, doc } => {
+                self.doc(doc);
+                self.attributes(attributes);
+                self.visibility(*public);
+                self.write("local ");
+                self.binders(names);
+                // a local of a declaration file has no value
+                if !values.is_empty() {
+                    self.write(" = ");
+                    self.separated(values, Self::node);
+                }
+            }
+            Ast::Assign { targets, values } => {
+                self.separated(targets, Self::node);
+                self.write(" = ");
+                self.separated(values, Self::node);
+            }
+            Ast::TypeAlias { name, parameters, typ, doc } => {
+                self.doc(doc);
+                write!(self.output, "type {}", name).unwrap();
+                if !parameters.is_empty() {
+                    write!(self.output, "<{}>", parameters.join(", ")).unwrap();
+                }
+                write!(self.output, " = {}", typ).unwrap();
+            }
+            Ast::MacroDeclaration { name, parameters, body } => {
+                write!(self.output, "macro {}({})", name, parameters.join(", ")).unwrap();
+                self.block(body);
+                self.write("end");
+            }
+            Ast::Test { name, body } => {
+                write!(self.output, "test {}", string_literal(name)).unwrap();
+                self.block(body);
+                self.write("end");
+            }
+            Ast::FunctionDeclaration { attributes, public, total, operator, name, type_parameters, parameters, result, requires, ensures, body, doc } => {
+                self.doc(doc);
+                self.attributes(attributes);
+                self.visibility(*public);
+                if *total {
+                    self.write("total ");
+                }
+                match operator {
+                    Some(Fixity { precedence, associativity }) => write!(self.output, "operator {} {} {} ", name, associativity, precedence).unwrap(),
+                    None => write!(self.output, "function {}", name).unwrap(),
+                }
+                self.signature(type_parameters, parameters, result);
+                self.indent += 1;
+                for (keyword, clause) in requires.iter().map(|clause| ("requires", clause)).chain(ensures.iter().map(|clause| ("ensures", clause))) {
+                    self.newline();
+                    write!(self.output, "{} ", keyword).unwrap();
+                    self.node(clause);
+                }
+                self.indent -= 1;
+                self.block_after(body, !requires.is_empty() || !ensures.is_empty());
+                self.write("end");
+            }
+            Ast::Reexport { module, name, alias } => {
+                write!(self.output, "pub use {}.{}", module, name).unwrap();
+                if let Some(alias) = alias {
+                    write!(self.output, " as {}", alias).unwrap();
+                }
+            }
+            Ast::Return(values) => {
+                self.write("return");
+                if !values.is_empty() {
+                    self.write(" ");
+                    self.separated(values, Self::node);
+                }
+            }
+            Ast::While { condition, body } => {
+                self.write("while ");
+                self.node(condition);
+                self.write(" do");
+                self.block(body);
+                self.write("end");
+            }
+            Ast::Repeat { body, condition } => {
+                self.write("repeat");
+                self.block(body);
+                self.write("until ");
+                self.node(condition);
+            }
+            Ast::NumericFor { variable, start, stop, step, body } => {
+                write!(self.output, "for {} = ", variable).unwrap();
+                self.node(start);
+                self.write(", ");
+                self.node(stop);
+                if let Some(step) = step {
+                    self.write(", ");
+                    self.node(step);
+                }
+                self.write(" do");
+                self.block(body);
+                self.write("end");
+            }
+            Ast::GenericFor { variable, iterable, body } => {
+                write!(self.output, "for {} in ", variable).unwrap();
+                self.node(iterable);
+                self.write(" do");
+                self.block(body);
+                self.write("end");
+            }
+            Ast::Break => self.write("break"),
+            Ast::Continue => self.write("continue"),
+            Ast::FileAttribute(attribute) => self.attribute("@!", attribute),
+            // what couldn't be parsed is gone, so there's nothing to write back
+            Ast::Error => self.write("--[[ error ]]"),
+        }
+    }
+}
+
+/// the source of `program`, which parses back to the same tree, but for spans: redundant
+/// parentheses are gone, and it's laid out one statement to a line, indented by block, with the
+/// comments around each statement kept around it
+pub fn print(program: &Block) -> String {
+    let mut printer = Printer { output: String::new(), indent: 0 };
+    for (i, node) in program.iter().enumerate() {
+        if i > 0 {
+            printer.newline();
+        }
+        printer.statement(node, i > 0);
+    }
+    printer.output.push('\n');
+    printer.output
+}
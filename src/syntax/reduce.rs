@@ -0,0 +1,101 @@
+use crate::sgir::Span;
+
+use super::ast::{Ast, Block, Node};
+use super::lexer::offset;
+use super::parser::parse;
+
+/// a change to try on the source: replace the text at `span` with `replacement`
+struct Edit {
+    span: Span,
+    replacement: String,
+}
+
+/// the blocks of statements directly within `node`
+fn blocks(node: &Node) -> Vec<&Block> {
+    match &node.ast {
+        Ast::Function { body, .. } | Ast::Do(body) | Ast::FunctionDeclaration { body, .. } | Ast::Test { body, .. } | Ast::While { body, .. }
+        | Ast::Repeat { body, .. } | Ast::NumericFor { body, .. } | Ast::GenericFor { body, .. } => vec![body],
+        Ast::If { consequent, alternative, .. } | Ast::IfTarget { consequent, alternative, .. } => std::iter::once(consequent).chain(alternative).collect(),
+        Ast::Match { cases, .. } => cases.iter().map(|case| &case.body).collect(),
+        _ => vec![],
+    }
+}
+
+/// the edits that delete runs of the statements in `block`, halving their length down to single
+/// statements, the way delta debugging splits its input
+fn deletions(block: &Block, edits: &mut Vec<Edit>) {
+    let mut length = block.len();
+    while length > 0 {
+        for run in block.chunks(length) {
+            let span = Span { start: run[0].span.start, end: run[run.len() - 1].span.end };
+            edits.push(Edit { span, replacement: String::new() });
+        }
+        length /= 2;
+    }
+}
+
+/// the edits that shrink `node` or the nodes within it, in the order they're worth trying: the
+/// statements deleted before each expression is replaced by one of its parts
+fn edits(source: &str, program: &Block) -> Vec<Edit> {
+    let mut deleted = vec![];
+    let mut hoisted = vec![];
+    deletions(program, &mut deleted);
+    let mut nodes: Vec<&Node> = program.iter().collect();
+    while let Some(node) = nodes.pop() {
+        for block in blocks(node) {
+            deletions(block, &mut deleted);
+        }
+        // each operator in a chain can go with its right operand
+        if let Ast::Operators { first, rest } = &node.ast {
+            let mut previous = first.span.end;
+            for (_, operand) in rest {
+                hoisted.push(Edit { span: Span { start: previous, end: operand.span.end }, replacement: String::new() });
+                previous = operand.span.end;
+            }
+        }
+        for child in node.children() {
+            let text = &source[offset(source, child.span.start)..offset(source, child.span.end)];
+            hoisted.push(Edit { span: node.span, replacement: text.to_owned() });
+            nodes.push(child);
+        }
+    }
+    deleted.extend(hoisted);
+    deleted
+}
+
+fn apply(source: &str, Edit { span, replacement }: &Edit) -> String {
+    let (mut start, mut end) = (offset(source, span.start), offset(source, span.end));
+    // the span of a parenthesized expression leaves out its parentheses, so a deletion that
+    // starts before an opening one takes the closing ones with it
+    let mut open = source[start..end].matches('(').count().saturating_sub(source[start..end].matches(')').count());
+    while open > 0 && source[end..].trim_start().starts_with(')') {
+        end = source.len() - source[end..].trim_start().len() + 1;
+        open -= 1;
+    }
+    // a deletion that empties its lines takes them with it
+    let line_start = source[..start].rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = source[end..].find('\n').map_or(source.len(), |newline| end + newline + 1);
+    if replacement.is_empty() && source[line_start..start].trim().is_empty() && source[end..line_end].trim().is_empty() {
+        (start, end) = (line_start, line_end);
+    }
+    format!("{}{}{}", &source[..start], replacement, &source[end..])
+}
+
+/// shrink `source`, which `interesting` must hold of, to a smaller program it still holds of, e.g.
+/// one that still crashes the checker, for a bug report. statements are deleted and expressions
+/// replaced by their parts until no single edit keeps it interesting, so the result is minimal
+/// in that sense, not the smallest possible.
+pub fn reduce(source: &str, mut interesting: impl FnMut(&str) -> bool) -> String {
+    let mut source = source.to_owned();
+    'shrink: loop {
+        let program = parse(&source).program;
+        for edit in edits(&source, &program) {
+            let candidate = apply(&source, &edit);
+            if candidate.len() < source.len() && interesting(&candidate) {
+                source = candidate;
+                continue 'shrink
+            }
+        }
+        return source
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::sgir::{Position, Span};
+
+use super::ast::{Ast, Binder, Block, Node};
+use super::identifiers::{is_identifier_continue, is_identifier_start, normalize, walk, Opening, Scoping};
+use super::lexer::{lex, offset, Token, TokenKind};
+use super::parser::{parse, SyntaxError, KEYWORDS};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum RenameError {
+    #[error(transparent)]
+    Syntax(#[from] SyntaxError),
+
+    #[error("there's no name to rename at {}:{}", .0.line, .0.column)]
+    NoName(Position),
+
+    #[error("{name} at {}:{} isn't bound by the program, so it can't be renamed", span.start.line, span.start.column)]
+    NotBound {
+        name: String,
+        span: Span,
+    },
+
+    #[error("{0} isn't a name")]
+    InvalidName(String),
+
+    #[error("renaming to {name} would change what the name at {}:{} refers to", span.start.line, span.start.column)]
+    Capture {
+        name: String,
+        span: Span,
+    },
+}
+
+/// a binding renamed throughout a program
+#[derive(Clone, Debug, PartialEq)]
+pub struct Renamed {
+    pub old: String,
+    /// where the binding and each of its uses were, in source order
+    pub spans: Vec<Span>,
+    /// the program with all of them renamed
+    pub source: String,
+}
+
+/// the binding each name in a program refers to
+#[derive(Default)]
+pub(crate) struct Resolution {
+    /// each binding, in the order they're bound: its name, and where it's written, unless it's
+    /// bound implicitly, like the `result` of an `ensures` clause
+    pub(crate) binders: Vec<(String, Option<Span>)>,
+    /// each use of a name, with the index of the binding it refers to, or none for a global
+    pub(crate) uses: Vec<(String, Span, Option<usize>)>,
+    /// the index of each binding of a function, with the indices of the uses in the function
+    /// of names bound outside it
+    pub(crate) functions: HashMap<usize, Vec<usize>>,
+    /// each call of a name, by the index of its use, with the bindings in scope where it's
+    /// called, innermost last
+    pub(crate) calls: Vec<(usize, Vec<(String, usize)>)>,
+    /// the indices of the bindings a `pub` declaration or a re-export exports, which the modules
+    /// that depend on it may use
+    pub(crate) exported: Vec<usize>,
+}
+
+struct Resolver<'a> {
+    /// the tokens of the program, where the names its binders are written can be found
+    tokens: &'a [Token],
+    /// the bindings in scope, innermost last
+    scopes: Vec<Vec<(String, usize)>>,
+    /// how many bindings and uses there were as each function being resolved began
+    functions: Vec<(usize, usize)>,
+    /// whether the next name is the function of a call
+    callee: bool,
+    resolution: Resolution,
+}
+
+impl Resolver<'_> {
+    /// the index of the first token at or after `position`
+    fn token(&self, position: Position) -> usize {
+        self.tokens.partition_point(|token| token.span.start < position)
+    }
+
+    /// the parameters of the function whose `function`, `fn` or `operator` keyword is at or after
+    /// the start of `span`, each with where it's written, and where the function's name would be.
+    /// an operator's name is the symbols after its keyword.
+    fn parameters(&self, span: Span, parameters: &[Binder]) -> (Option<Span>, Vec<(String, Option<Span>)>) {
+        let start = self.token(span.start);
+        let is_keyword = |token: &Token| matches!(&token.kind, TokenKind::Identifier(word) if ["function", "fn", "operator"].contains(&word.as_str()));
+        let keyword = start + self.tokens[start..].iter().position(is_keyword).unwrap_or(0);
+        let open = keyword + self.tokens[keyword..].iter().position(|token| token.kind == TokenKind::Symbol("(")).unwrap_or(0);
+        let spans = binder_spans(self.tokens, open + 1, ")");
+        let parameters = parameters.iter()
+                                   .flat_map(|binder| binder.pattern.names())
+                                   .enumerate()
+                                   .map(|(i, name)| (name.clone(), spans.get(i).copied()))
+                                   .collect();
+        let name = self.tokens.get(keyword + 1).map(|token| match &token.kind {
+            TokenKind::Symbol(_) => {
+                let symbols = self.tokens[keyword + 1..].iter().take_while(|token| matches!(token.kind, TokenKind::Symbol(_)));
+                Span { start: token.span.start, end: symbols.last().map_or(token.span.end, |last| last.span.end) }
+            }
+            _ => token.span,
+        });
+        (name, parameters)
+    }
+
+    fn bind(&mut self, name: &str, span: Option<Span>) {
+        let binder = self.resolution.binders.len();
+        self.resolution.binders.push((name.to_owned(), span));
+        self.scopes.last_mut().unwrap().push((name.to_owned(), binder));
+    }
+
+    /// mark the binding just made as exported, if it is
+    fn export(&mut self, exported: bool) {
+        if exported {
+            self.resolution.exported.push(self.resolution.binders.len() - 1);
+        }
+    }
+
+    /// the binding of the function just resolved is next: record the uses in it of names bound
+    /// outside it
+    fn function(&mut self) {
+        let (binders, uses) = self.functions.pop().unwrap();
+        let free = (uses..self.resolution.uses.len()).filter(|&i| self.resolution.uses[i].2.is_none_or(|binder| binder < binders))
+                                                     .collect();
+        self.resolution.functions.insert(self.resolution.binders.len(), free);
+    }
+}
+
+/// whether `node` binds a function next to a name
+fn is_function(node: &Node) -> bool {
+    match &node.ast {
+        Ast::Local { names, values, .. } => names.len() == 1 && matches!(&values[..], [Node { ast: Ast::Function { .. }, .. }]),
+        Ast::FunctionDeclaration { .. } | Ast::MacroDeclaration { .. } => true,
+        _ => false,
+    }
+}
+
+impl<'n> Scoping<'n> for Resolver<'_> {
+    fn enter(&mut self, node: &'n Node) {
+        if is_function(node) {
+            self.functions.push((self.resolution.binders.len(), self.resolution.uses.len()));
+        }
+        if let Ast::Call { function, .. } = &node.ast {
+            self.callee = matches!(function.ast, Ast::Name(_));
+        }
+    }
+
+    fn name(&mut self, node: &'n Node, name: &'n str) {
+        let binder = self.scopes.iter().flatten().rev().find(|(bound, _)| bound == name).map(|(_, binder)| *binder);
+        self.resolution.uses.push((name.to_owned(), node.span, binder));
+        if std::mem::take(&mut self.callee) {
+            let scope = self.scopes.concat();
+            self.resolution.calls.push((self.resolution.uses.len() - 1, scope));
+        }
+    }
+
+    /// a use of the operator `symbol` written just before `operand`, if a declaration in scope
+    /// binds it. the built-in operators aren't bound by the program, so they aren't uses.
+    fn operator(&mut self, symbol: &'n str, operand: &'n Node) {
+        let Some(binder) = self.scopes.iter().flatten().rev().find(|(bound, _)| bound == symbol).map(|(_, binder)| *binder) else { return };
+        // the symbols of the operator end before any parentheses around its operand
+        let mut end = self.token(operand.span.start);
+        while end > 0 && self.tokens[end - 1].kind == TokenKind::Symbol("(") {
+            end -= 1;
+        }
+        let (mut start, mut length) = (end, 0);
+        while length < symbol.len() && start > 0 {
+            let TokenKind::Symbol(part) = self.tokens[start - 1].kind else { break };
+            (start, length) = (start - 1, length + part.len());
+        }
+        let span = match (self.tokens.get(start), end.checked_sub(1).and_then(|last| self.tokens.get(last))) {
+            (Some(first), Some(last)) if start < end => Span { start: first.span.start, end: last.span.end },
+            _ => operand.span,
+        };
+        self.resolution.uses.push((symbol.to_owned(), span, Some(binder)));
+    }
+
+    fn open(&mut self, node: &'n Node, opening: &Opening<'n>) {
+        self.scopes.push(vec![]);
+        let names = match opening {
+            Opening::Parameters(parameters) => self.parameters(node.span, parameters).1,
+            // a macro's parameters are bound in its block, after `macro name (`
+            Opening::Macro(parameters) => {
+                let spans = binder_spans(self.tokens, self.token(node.span.start) + 3, ")");
+                parameters.iter().enumerate().map(|(i, parameter)| (parameter.clone(), spans.get(i).copied())).collect()
+            }
+            Opening::Variable(variable, _) => {
+                let span = self.tokens.get(self.token(node.span.start) + 1).map(|token| token.span);
+                vec![((*variable).to_owned(), span)]
+            }
+            // the names a case's pattern binds aren't found in the source, so they can't be
+            // renamed, but they still shadow the names outside it
+            opening => opening.names().into_iter().map(|name| (name, None)).collect(),
+        };
+        for (name, span) in names {
+            self.bind(&name, span);
+        }
+    }
+
+    fn close(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, node: &'n Node) {
+        if is_function(node) {
+            self.function();
+        }
+        match &node.ast {
+            Ast::Local { public, names, .. } => {
+                // past `local`, and `pub` before it
+                let spans = binder_spans(self.tokens, self.token(node.span.start) + 1 + usize::from(*public), "=");
+                for (i, name) in names.iter().flat_map(|binder| binder.pattern.names()).enumerate() {
+                    self.bind(name, spans.get(i).copied());
+                    self.export(*public);
+                }
+            }
+            Ast::FunctionDeclaration { public, name, parameters, .. } => {
+                let (span, _) = self.parameters(node.span, parameters);
+                self.bind(name, span);
+                self.export(*public);
+            }
+            Ast::MacroDeclaration { name, .. } => self.bind(name, self.tokens.get(self.token(node.span.start) + 1).map(|token| token.span)),
+            // a re-export without an alias binds the name it exports, which can't be renamed
+            // without exporting something else
+            Ast::Reexport { name, alias, .. } => {
+                let span = alias.as_ref().and_then(|_| self.tokens.get(self.token(node.span.end) - 1)).map(|token| token.span);
+                self.bind(alias.as_ref().unwrap_or(name), span);
+                self.export(true);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// the spans of the names bound by a list of binders `a: T, {x, y = b}, ...`, in order, from the
+/// token at `start` to the `end` symbol outside any brackets. a name in an annotation isn't bound,
+/// and neither is a field followed by the pattern of its value.
+pub(crate) fn binder_spans(tokens: &[Token], start: usize, end: &str) -> Vec<Span> {
+    let (mut spans, mut annotation, mut depth) = (vec![], false, 0);
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match &token.kind {
+            TokenKind::Symbol(symbol) if depth == 0 && *symbol == end => break,
+            TokenKind::Symbol(",") if depth == 0 => annotation = false,
+            // a default is skipped like an annotation
+            TokenKind::Symbol(":" | "=") if depth == 0 => annotation = true,
+            TokenKind::Symbol("(" | "{" | "[" | "<") => depth += 1,
+            TokenKind::Symbol(")" | "}" | "]" | ">") => depth -= 1,
+            TokenKind::Symbol(">>") => depth -= 2,
+            TokenKind::Identifier(_) if !annotation => {
+                let field = depth > 0 && tokens.get(i + 1).is_some_and(|next| next.kind == TokenKind::Symbol("="));
+                if !field {
+                    spans.push(token.span);
+                }
+            }
+            TokenKind::End => break,
+            _ => {}
+        }
+    }
+    spans
+}
+
+/// resolve every name in `source` to the binding it refers to
+fn resolve(source: &str) -> Result<Resolution, RenameError> {
+    let parsed = parse(source);
+    if let Some(error) = parsed.errors.into_iter().next() {
+        return Err(error.into())
+    }
+    let tokens = lex(source).map_err(SyntaxError::from)?;
+    Ok(resolve_program(&parsed.program, &tokens))
+}
+
+/// resolve every name in `program`, whose `tokens` are where its binders are written
+pub(crate) fn resolve_program(program: &Block, tokens: &[Token]) -> Resolution {
+    let mut resolver = Resolver { tokens, scopes: vec![vec![]], functions: vec![], callee: false, resolution: Resolution::default() };
+    program.iter().for_each(|node| walk(&mut resolver, node));
+    resolver.resolution
+}
+
+/// rename the binding of the name at `position` in `source`, along with every use of it, to
+/// `name`. a rename that would change what any name refers to, with the binding shadowing a
+/// name of the same name or being shadowed by one, is refused.
+pub fn rename(source: &str, position: Position, name: &str) -> Result<Renamed, RenameError> {
+    let name = normalize(name);
+    let mut chars = name.chars();
+    let is_name = chars.next().is_some_and(is_identifier_start) && chars.all(is_identifier_continue);
+    if !is_name || KEYWORDS.contains(&name.as_str()) {
+        return Err(RenameError::InvalidName(name))
+    }
+
+    let before = resolve(source)?;
+    let at = |span: &Span| span.start <= position && position <= span.end;
+    let binder = match before.binders.iter().position(|(_, span)| span.as_ref().is_some_and(at)) {
+        Some(binder) => binder,
+        None => match before.uses.iter().find(|(_, span, _)| at(span)) {
+            Some((_, _, Some(binder))) if before.binders[*binder].1.is_some() => *binder,
+            Some((old, span, _)) => return Err(RenameError::NotBound { name: old.clone(), span: *span }),
+            None => return Err(RenameError::NoName(position)),
+        },
+    };
+    let (old, span) = &before.binders[binder];
+    let mut spans = before.uses.iter()
+                               .filter(|(_, _, found)| *found == Some(binder))
+                               .map(|(_, span, _)| *span)
+                               .chain(*span)
+                               .collect::<Vec<_>>();
+    spans.sort_by_key(|span| span.start);
+
+    let mut renamed = source.to_owned();
+    for span in spans.iter().rev() {
+        renamed.replace_range(offset(source, span.start)..offset(source, span.end), &name);
+    }
+    // every name still refers to the binding it did
+    let after = resolve(&renamed)?;
+    let captured = before.uses.iter().zip(&after.uses).find(|((_, _, before), (_, _, after))| before != after);
+    if let Some(((_, span, _), _)) = captured {
+        return Err(RenameError::Capture { name, span: *span })
+    }
+    Ok(Renamed { old: old.clone(), spans, source: renamed })
+}
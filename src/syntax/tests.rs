@@ -0,0 +1,1837 @@
+use super::lexer::*;
+
+fn kinds(source: &str) -> Vec<TokenKind> {
+    lex(source).unwrap().into_iter().map(|token| token.kind).collect()
+}
+
+#[test]
+fn test_lex_tokens() {
+    assert_eq!(kinds("local x = f(1, 'a\\n') // 2"),
+               vec![TokenKind::Identifier("local".to_owned()), TokenKind::Identifier("x".to_owned()), TokenKind::Symbol("="),
+                    TokenKind::Identifier("f".to_owned()), TokenKind::Symbol("("), TokenKind::Number(1), TokenKind::Symbol(","),
+                    TokenKind::String("a\n".to_owned()), TokenKind::Symbol(")"), TokenKind::Symbol("//"), TokenKind::Number(2),
+                    TokenKind::End]);
+}
+
+#[test]
+fn test_lex_keeps_comments_as_trivia() {
+    let source = "-- the answer\n--[[ to everything ]]\nx = 42 -- trailing\n--[==[ a ]] b ]==]";
+    let tokens = lex(source).unwrap();
+    assert_eq!(tokens.len(), 4);
+
+    let texts = |comments: &[Comment]| comments.iter().map(|comment| (comment.text.clone(), comment.block)).collect::<Vec<_>>();
+    assert_eq!(texts(&tokens[0].leading), vec![(" the answer".to_owned(), false), (" to everything ".to_owned(), true)]);
+    // only the `---` comments right before a token are its doc comment
+    assert_eq!(tokens[0].doc(), None);
+    let documented = lex("--- the answer\n-- aside\n--- to everything\n---\n--- of course\nx = 42").unwrap();
+    assert_eq!(documented[0].doc().as_deref(), Some("to everything\n\nof course"));
+    assert_eq!(texts(&tokens[2].trailing), vec![(" trailing".to_owned(), false)]);
+    // comments after the last token are kept on the end
+    assert_eq!(texts(&tokens[3].leading), vec![(" a ]] b ".to_owned(), true)]);
+
+    let x = &tokens[0];
+    assert_eq!((x.span.start.line, x.span.start.column, x.span.end.column), (3, 1, 2));
+}
+
+#[test]
+fn test_lex_errors() {
+    use crate::sgir::Position;
+    assert_eq!(lex("--[[ open"), Err(LexError::UnterminatedComment(Position { line: 1, column: 1 })));
+    assert_eq!(lex("x = \"open"), Err(LexError::UnterminatedString(Position { line: 1, column: 5 })));
+    assert_eq!(lex("x $"), Err(LexError::UnexpectedCharacter { found: '$', position: Position { line: 1, column: 3 } }));
+    assert_eq!(lex("99999999999999999999"), Err(LexError::NumberTooLarge(Position { line: 1, column: 1 })));
+}
+
+#[test]
+fn test_lex_number_literals() {
+    let number = |source: &str| kinds(source).remove(0);
+    assert_eq!(number("0xFF"), TokenKind::Number(255));
+    assert_eq!(number("0b1010"), TokenKind::Number(10));
+    assert_eq!(number("1_000_000"), TokenKind::Number(1_000_000));
+    assert_eq!(number("0x7fff_ffff_ffff_ffff"), TokenKind::Number(i64::MAX));
+    assert_eq!(number("1e9"), TokenKind::Number(1_000_000_000));
+    assert_eq!(number("1.5e3"), TokenKind::Number(1500));
+    assert_eq!(number("2.50"), TokenKind::Float(2.5));
+    assert_eq!(number("1.5e-3"), TokenKind::Float(0.0015));
+    assert_eq!(number("0e999"), TokenKind::Number(0));
+    // `..` after a number is concatenation, not a decimal point
+    assert_eq!(kinds("1..2"), vec![TokenKind::Number(1), TokenKind::Symbol(".."), TokenKind::Number(2), TokenKind::End]);
+
+    use crate::sgir::Position;
+    let malformed = |text: &str| Err(LexError::MalformedNumber { text: text.to_owned(), position: Position { line: 1, column: 1 } });
+    for text in ["0x", "0b102", "1_", "1e", "1e+", "12abc", "1.5e-x"] {
+        assert_eq!(lex(text), malformed(text));
+    }
+    assert_eq!(lex("0x8000_0000_0000_0000"), Err(LexError::NumberTooLarge(Position { line: 1, column: 1 })));
+    assert_eq!(lex("1e19"), Err(LexError::NumberTooLarge(Position { line: 1, column: 1 })));
+    assert_eq!(lex("10e9223372036854775807"), Err(LexError::NumberTooLarge(Position { line: 1, column: 1 })));
+    for text in ["1.55e-9223372036854775807", "1e-99999999999999999999", "1e-400"] {
+        assert_eq!(lex(text), Err(LexError::NumberTooSmall(Position { line: 1, column: 1 })));
+    }
+    assert_eq!(number("0.0e-400"), TokenKind::Float(0.0));
+
+    // numbers print as literals that lex back to the same value
+    for source in ["0xFF", "1e9", "1.5e-3", "0.1", "123.456e-300", "2.5"] {
+        let token = number(source);
+        assert_eq!(number(&token.to_string()), token);
+    }
+}
+
+#[test]
+fn test_lex_long_strings() {
+    let source = "x = [[\nfirst\n  second \\n]] .. [==[a ]] b]==] .. r\"C:\\dir\" .. r'\"'";
+    let tokens = lex(source).unwrap();
+    let strings = tokens.iter()
+                        .filter_map(|token| match &token.kind {
+                            TokenKind::String(value) => Some((value.as_str(), token.span.start.line, token.span.end.line)),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+    assert_eq!(strings, vec![("first\n  second \\n", 1, 3), ("a ]] b", 3, 3), ("C:\\dir", 3, 3), ("\"", 3, 3)]);
+    // the lines a long string spans count towards the positions of what comes after it
+    assert_eq!((tokens[3].span.start.line, tokens[3].span.start.column), (3, 15));
+
+    use crate::sgir::Position;
+    assert_eq!(lex("[==[ open ]]"), Err(LexError::UnterminatedString(Position { line: 1, column: 1 })));
+    assert_eq!(lex("r\"open\n\""), Err(LexError::UnterminatedString(Position { line: 1, column: 1 })));
+
+    // strings that span lines are written back as long strings, verbatim
+    assert_eq!(string_literal("one line\t"), "\"one line\\t\"");
+    for value in ["a\nb", "\nleading", "]]\n", "x]=]\n]", "ends with ]=\n]="] {
+        let literal = string_literal(value);
+        assert!(literal.starts_with('['));
+        assert_eq!(kinds(&literal), vec![TokenKind::String(value.to_owned()), TokenKind::End]);
+    }
+}
+
+#[test]
+fn test_lex_bytes_literals() {
+    use crate::sgir::Position;
+
+    assert_eq!(kinds("b\"a\\x00\\xFf\\n\" b'\"'"), vec![TokenKind::Bytes(vec![b'a', 0, 0xff, b'\n']), TokenKind::Bytes(vec![b'"']), TokenKind::End]);
+    // `b` alone is still a name
+    assert_eq!(kinds("b .. \"s\""), vec![TokenKind::Identifier("b".to_owned()), TokenKind::Symbol(".."), TokenKind::String("s".to_owned()), TokenKind::End]);
+    assert_eq!(lex("b\"é\""), Err(LexError::NonAsciiByte { found: 'é', position: Position { line: 1, column: 3 } }));
+    assert_eq!(lex("b\"\\xg0\""), Err(LexError::UnknownEscape { found: 'x', position: Position { line: 1, column: 4 } }));
+    let all = (0..=255).collect::<Vec<u8>>();
+    assert_eq!(kinds(&bytes_literal(&all)), vec![TokenKind::Bytes(all), TokenKind::End]);
+    assert_eq!(bytes_literal(b"say \"hi\"\x7f"), "b\"say \\\"hi\\\"\\x7f\"");
+}
+
+#[test]
+fn test_lex_unicode_identifiers() {
+    // `e` followed by a combining acute accent is the same name as the precomposed `é`
+    assert_eq!(kinds("cafe\u{301} λ alex! _x1"),
+               vec![TokenKind::Identifier("café".to_owned()), TokenKind::Identifier("λ".to_owned()),
+                    TokenKind::Identifier("alex!".to_owned()), TokenKind::Identifier("_x1".to_owned()), TokenKind::End]);
+    let tokens = lex("cafe\u{301} = 1").unwrap();
+    assert_eq!(tokens[1].span.start.column, 7);
+
+    use crate::sgir::Position;
+    assert_eq!(lex("x €"), Err(LexError::UnexpectedCharacter { found: '€', position: Position { line: 1, column: 3 } }));
+    assert_eq!(lex("!x"), Err(LexError::UnexpectedCharacter { found: '!', position: Position { line: 1, column: 1 } }));
+}
+
+#[test]
+fn test_confusable_identifiers() {
+    use super::identifiers::confusables;
+    use super::parser::parse;
+
+    // the second `scope` starts with a Cyrillic `ѕ`
+    let found = confusables(&parse("local scope = 1\nlocal \u{455}cope = 2\nprint(scope)").program);
+    assert_eq!(found.iter().map(|found| (found.name.as_str(), found.similar.as_str(), found.span.start.line)).collect::<Vec<_>>(),
+               vec![("\u{455}cope", "scope", 2)]);
+
+    // a lookalike that was never bound is only noticed where it's used
+    let found = confusables(&parse("local scope = 1\nprint(\u{455}cope)").program);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].to_string(), "`\u{455}cope` at 2:7 looks like `scope`, which is also in scope");
+
+    // names in different scopes can't be confused for one another, and shadowing is fine
+    assert!(confusables(&parse("function f(scope) return scope end\nfunction g(\u{455}cope) return \u{455}cope end\n\
+                                local x = 1 local x = x").program).is_empty());
+}
+
+fn asts(block: &[super::ast::Node]) -> Vec<&super::ast::Ast> {
+    block.iter().map(|node| &node.ast).collect()
+}
+
+#[test]
+fn test_parse_program() {
+    use super::ast::Ast;
+    use super::parser::parse;
+    let source = "local total = 0\n\
+                  for i = 1, 10 do\n  if i % 2 == 0 then continue elseif i > 7 then break end\nend\n\
+                  function add(a, b) return a + b * 2 end\n\
+                  print(add(1, -total), f.x)";
+    let parsed = parse(source);
+    assert_eq!(parsed.errors, vec![]);
+    assert_eq!(parsed.program.len(), 4);
+
+    let Ast::NumericFor { body, .. } = &parsed.program[1].ast else { panic!("expected a for loop") };
+    let Ast::If { alternative: Some(alternative), .. } = &body[0].ast else { panic!("expected an if") };
+    assert!(matches!(alternative[..], [super::ast::Node { ast: Ast::If { alternative: None, .. }, .. }]));
+
+    let Ast::FunctionDeclaration { parameters, body, .. } = &parsed.program[2].ast else { panic!("expected a function") };
+    assert_eq!(parameters.iter().map(|parameter| parameter.pattern.to_string()).collect::<Vec<_>>(), vec!["a", "b"]);
+    let Ast::Return(values) = &body[0].ast else { panic!("expected a return") };
+    let Ast::Operators { rest, .. } = &values[0].ast else { panic!("expected an operator chain") };
+    assert_eq!(rest.iter().map(|(operator, _)| operator.as_str()).collect::<Vec<_>>(), vec!["+", "*"]);
+
+    let call = &parsed.program[3];
+    assert_eq!((call.span.start.line, call.span.start.column, call.span.end.line, call.span.end.column), (6, 1, 6, 27));
+}
+
+#[test]
+fn test_parse_recovers_from_errors() {
+    use super::ast::{Ast, Binder};
+    use super::parser::{parse, SyntaxError};
+    let parsed = parse("local x = ; local y = 2\nf(1, +, 3)\nwhile do end\nlocal z = 3 end");
+    assert_eq!(parsed.errors.len(), 4);
+    assert!(matches!(&parsed.errors[0], SyntaxError::Expected { expected: "an expression", found, .. } if found == "`;`"));
+    assert_eq!(parsed.program[0].ast, Ast::Error);
+    assert!(matches!(&parsed.program[1].ast, Ast::Local { names, .. } if names.len() == 1 && names[0] == Binder::name("y", None)));
+
+    // a bad argument doesn't lose the rest of the call
+    let Ast::Call { arguments, .. } = &parsed.program[2].ast else { panic!("expected a call") };
+    assert_eq!(asts(arguments), vec![&Ast::Number(1), &Ast::Error, &Ast::Number(3)]);
+
+    assert_eq!(parsed.program[3].ast, Ast::Error);
+    assert!(matches!(parsed.program[4].ast, Ast::Local { .. }));
+    // the stray end
+    assert_eq!(parsed.program[5].ast, Ast::Error);
+
+    assert!(matches!(parse("x = \"open").errors[..], [SyntaxError::Lex(LexError::UnterminatedString(_))]));
+}
+
+const EDITED: &str = "local x = 1\n\nfunction f(a, b)\n  return a + b -- sum\nend\n\nwhile x < 10 do x = f(x, 1) end\nprint(f(1, 2))\nlocal y = (x\n";
+
+#[test]
+fn test_incremental_edits_match_full_parse() {
+    use super::incremental::{Document, Edit};
+    use super::parser::parse;
+
+    let insertions = ["", " ", "x", "\n", "end", "(", ")", "--", "--[[", "]]", "\"", "function g() ", "é"];
+    for (offset, _) in EDITED.char_indices() {
+        for text in insertions {
+            for deleted in [0, 1, 3] {
+                let end = EDITED[offset..].char_indices().nth(deleted).map_or(EDITED.len(), |(length, _)| offset + length);
+                let edit = Edit { range: offset..end, text: text.to_owned() };
+                let mut document = Document::new(EDITED);
+                document.edit(&edit);
+
+                let mut source = EDITED.to_owned();
+                source.replace_range(offset..end, text);
+                assert_eq!(document.source(), source);
+                assert_eq!(document.parse(), parse(&source), "after replacing {:?} with {:?}", &EDITED[offset..end], text);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_incremental_edits_reparse_locally() {
+    use super::incremental::{Document, Edit, Reparse};
+    use super::parser::parse;
+
+    let mut document = Document::new(EDITED);
+    let offset = EDITED.find("a + b").unwrap();
+    assert!(matches!(document.edit(&Edit { range: offset..offset + 1, text: "b".to_owned() }),
+                     Reparse::Incremental { bytes } if bytes < EDITED.len()));
+
+    // an unclosed comment swallows the rest of the document, so no statement around it is safe
+    let offset = document.source().find("while").unwrap();
+    assert_eq!(document.edit(&Edit { range: offset..offset, text: "--[[\n".to_owned() }), Reparse::Full);
+    assert_eq!(document.parse(), parse(document.source()));
+
+    let offset = document.source().find("--[[").unwrap();
+    document.edit(&Edit { range: offset..offset + 5, text: String::new() });
+    assert_eq!(document.source(), EDITED.replace("a + b", "b + b"));
+    assert_eq!(document.parse(), parse(document.source()));
+}
+
+#[test]
+fn test_incremental_check_can_be_interrupted() {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use crate::sgir::{Interrupt, Type};
+
+    use super::incremental::{Document, Edit};
+
+    let source = "local x = 1\nfn f(a: Number): Number\n  return a + x\nend\nprint(f(x))\nlocal y, z = (f(1), true)\n";
+    let mut document = Document::new(source);
+    let interrupted = Interrupt::new(Arc::new(AtomicBool::new(true)), 1);
+    let checked = document.check(&interrupted);
+    assert!(!checked.complete);
+    assert!(checked.declarations.is_empty());
+
+    let checked = document.check(&Interrupt::new(Arc::default(), 1));
+    assert!(checked.complete);
+    let types = checked.declarations.iter().flat_map(|declared| declared.types.clone()).collect::<Vec<_>>();
+    let function = Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) };
+    assert_eq!(types, [("x".to_owned(), Some(Type::Number)), ("f".to_owned(), Some(function)),
+                       ("y".to_owned(), Some(Type::Number)), ("z".to_owned(), Some(Type::Boolean))]);
+
+    // the declarations before an edit were checked already, so an interrupted check still has them
+    let offset = source.find("true").unwrap();
+    document.edit(&Edit { range: offset..offset + 4, text: "\"z\"".to_owned() });
+    let past = Interrupt { deadline: Some(Instant::now()), ..Interrupt::new(Arc::default(), 1) };
+    let checked = document.check(&past);
+    assert!(!checked.complete);
+    assert_eq!(checked.declarations.len(), 2);
+    let checked = document.check(&Interrupt::new(Arc::default(), 1));
+    assert_eq!(checked.declarations[2].types[1], ("z".to_owned(), Some(Type::String)));
+}
+
+#[test]
+fn test_incremental_benchmark() {
+    use super::incremental::{measure, Edit};
+
+    let source = (0..500).map(|i| format!("function f{i}(a)\n  local b = a * {i}\n  return b + 1\nend\n")).collect::<String>();
+    let edits = (0..20).map(|i| {
+                           let offset = source.find(&format!("a * {}\n", i * 25)).unwrap();
+                           Edit { range: offset..offset + 1, text: "b".to_owned() }
+                       })
+                       .collect::<Vec<_>>();
+    let comparison = measure(&source, &edits, 2);
+    assert!(comparison.reparsed * 100 < comparison.total, "{}", comparison);
+}
+
+#[test]
+fn test_parse_generic_functions() {
+    use super::ast::{Ast, Binder};
+    use super::parser::parse;
+    use crate::sgir::Type;
+
+    let parsed = parse("fn id<T>(x: T): T return x end\nprint(id<Number>(5), a < b, c > d)");
+    assert_eq!(parsed.errors, vec![]);
+    let Ast::FunctionDeclaration { name, type_parameters, parameters, result, .. } = &parsed.program[0].ast else {
+        panic!("expected a function")
+    };
+    assert_eq!((name.as_str(), &type_parameters[..]), ("id", &["T".to_owned()][..]));
+    assert_eq!(parameters, &[Binder::name("x", Some(Type::Variable("T".to_owned())))]);
+    assert_eq!(result, &Some(Type::Variable("T".to_owned())));
+
+    // `<` is only the start of type arguments when they're followed by a call
+    let Ast::Call { arguments, .. } = &parsed.program[1].ast else { panic!("expected a call") };
+    let Ast::Call { function, .. } = &arguments[0].ast else { panic!("expected a call") };
+    assert!(matches!(&function.ast, Ast::Instantiate { arguments, .. } if arguments == &[Type::Number]));
+    assert!(matches!(&arguments[1].ast, Ast::Operators { rest, .. } if rest.len() == 1));
+}
+
+#[test]
+fn test_lower_type_tests() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Expression, Type, TypeError, Value};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default()).unwrap();
+
+    // `#x` only checks where `x` is narrowed to a string
+    let program = lowered("function size(x: Number | String): Number\n  if typeof(x) == \"number\" then\n    return x\n  else\n    return #x\n  end\nend\n\
+                           size(1) + size(\"abc\")");
+    assert_eq!(check(program.clone()), Ok(Type::Number));
+    assert_eq!(run(program), Ok(Value::Number(4)));
+    let Err(TypeError::Located { error, .. }) = check(lowered("function size(x: Number | String): Number\n  if typeof(x) == \"string\" then\n    return x\n  end\n  return 0\nend")) else {
+        panic!("expected a type error")
+    };
+    assert!(matches!(*error, TypeError::TypeMismatch { .. }));
+
+    // only a tag `typeof` gives is a type test, and a local `typeof` is called like any other function
+    fn tests(expression: &Expression) -> bool {
+        matches!(expression, Expression::TypeTest { .. }) || expression.children().into_iter().any(tests)
+    }
+    let is_test = |source: &str| tests(&lowered(source));
+    assert!(is_test("typeof(1) == \"number\""));
+    assert!(!is_test("typeof(1) == \"int\""));
+    assert!(!is_test("local typeof = fn(x: Number) \"number\" end\ntypeof(1) == \"number\""));
+}
+
+#[test]
+fn test_lower_rejects_recursion() {
+    use super::lower::{lower_block, LowerError};
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+
+    let recursive = |source: &str| match lowered(source) {
+        Err(error @ LowerError::Recursive { .. }) => error.to_string(),
+        other => panic!("expected a recursive function, found {:?}", other),
+    };
+    assert_eq!(recursive("function fact(k: Number): Number\n  if k == 0 then return 1 end\n  return k * fact(k - 1)\nend\nfact(5)"),
+               "fact at 3:14 calls the function it's in, but functions can't be recursive; use a loop instead");
+    // even with a local of the same name around it, which the body would otherwise refer to
+    assert_eq!(recursive("local even = fn(k: Number) true end\nfunction even(k: Number): Boolean\n  return k == 0 or not even(k - 1)\nend"),
+               "even at 3:24 calls the function it's in, but functions can't be recursive; use a loop instead");
+    // a function may shadow another of the same name it doesn't refer to
+    assert!(lowered("function f(): Number return 1 end\nfunction f(): Number return 2 end\nf()").is_ok());
+}
+
+#[test]
+fn test_lower_generic_functions() {
+    use super::lower::{lower_block, LowerError};
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, TypeError, Value};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+
+    let program = lowered("fn id<T>(x: T): T\n  return x\nend\n\
+                           local twice = function(n: Number): Number return n * 2 end\n\
+                           twice(id<Number>(5))").unwrap();
+    assert_eq!(check(program.clone()), Ok(Type::Number));
+    assert_eq!(run(program), Ok(Value::Number(10)));
+
+    // a call without type arguments instantiates the function at the types of its arguments
+    let program = lowered("fn pick<T>(first: Boolean, x: T, y: T): T\n  if first then return x else return y end\nend\n\
+                           pick(true, 1, \"one\")").unwrap();
+    assert_eq!(check(program.clone()), Ok(Type::Union(vec![Type::Number, Type::String])));
+    assert_eq!(run(program), Ok(Value::Number(1)));
+    let Err(TypeError::Located { error, .. }) = check(lowered("fn default<T>(): Number\n  return 0\nend\ndefault()").unwrap()) else {
+        panic!("expected a type error")
+    };
+    assert_eq!(error.to_string(), "the type argument T of the generic function can't be inferred from its arguments; instantiate it explicitly");
+
+    let Err(LowerError::MissingAnnotation { name, .. }) = lowered("function f(x) return x end") else {
+        panic!("expected a missing annotation")
+    };
+    assert_eq!(name, "x");
+
+    // a field of a name is the global of a module, like a native
+    assert_eq!(lowered("io.print").unwrap().free_variables().into_iter().collect::<Vec<_>>(), vec!["io.print".to_owned()]);
+
+    // unless the name is a local, whose field is projected like any other record's
+    let program = lowered("function x(p: {x: Number, y: Number}): Number return p.x - p.y end\n\
+                           local io = {print = 7}\n\
+                           x({x = io.print, y = 2})").unwrap();
+    assert!(!program.free_variables().contains("io.print"));
+    assert_eq!(check(program.clone()), Ok(Type::Number));
+    assert_eq!(run(program), Ok(Value::Number(5)));
+    assert_eq!(run(lowered("({x = 1, y = (2, 3)}).y").unwrap()), Ok(Value::Tuple(vec![Value::Number(2), Value::Number(3)])));
+    assert_eq!(check(lowered("({x = 1}).y").unwrap()).map_err(|error| error.to_string()),
+               Err("the pattern {y = y} can't match values of type {x: Number} at 1:1, expanded from a field access at 1:1".to_owned()));
+}
+
+#[test]
+fn test_lower_functions_without_result_annotations() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default()).unwrap();
+
+    let program = lowered("function f(a: Number) return a end\nf(1)");
+    assert_eq!(check(program.clone()), Ok(Type::Number));
+    assert_eq!(run(program), Ok(Value::Number(1)));
+    let program = lowered("local f = function(a: Number) return a * 2 end\nf(4)");
+    assert_eq!(check(program.clone()), Ok(Type::Number));
+    assert_eq!(run(program), Ok(Value::Number(8)));
+
+    // the result is what every return and the last value have in common
+    let program = lowered("function sign(n: Number)\n  if n < 0 then return false end\n  return n\nend\nsign(-1)");
+    assert_eq!(check(program.clone()), Ok(Type::Union(vec![Type::Boolean, Type::Number])));
+    assert_eq!(run(program), Ok(Value::Boolean(false)));
+
+    // a return in a function inside another returns from the inner one
+    let program = lowered("function outer(n: Number)\n  local inner = function(m: Number) return m > 0 end\n  return inner(n)\nend\nouter(2)");
+    assert_eq!(check(program.clone()), Ok(Type::Boolean));
+    assert_eq!(run(program), Ok(Value::Boolean(true)));
+}
+
+#[test]
+fn test_lower_operator_declarations() {
+    use super::ast::Ast;
+    use super::lower::{lower_block, LowerError};
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::{Associativity, Fixity, FixityError, Operators};
+    use crate::sgir::{run, Value};
+
+    let parsed = parse("operator <+> right 9 (a: Number, b: Number): Number\n  return a - b\nend\n1 <+> 2 <+> 3 * 2");
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    let Ast::FunctionDeclaration { operator, name, .. } = &parsed.program[0].ast else { panic!("expected a declaration") };
+    assert_eq!((operator, name.as_str()), (&Some(Fixity { precedence: 9, associativity: Associativity::Right }), "<+>"));
+    assert!(print(&parsed.program).starts_with("operator <+> right 9 (a: Number, b: Number): Number\n"));
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+    // 1 <+> (2 <+> (3 * 2))
+    assert_eq!(run(lower_block(&parsed.program, &Operators::default()).unwrap()), Ok(Value::Number(5)));
+
+    // an operator is only declared for the rest of its block
+    let program = "do\n  operator |> left 1 (x: Number, f: (Number) -> Number): Number return f(x) end\n  2 |> fn(n: Number): Number return n * n end\nend\n";
+    assert_eq!(run(lowered(program).unwrap()), Ok(Value::Number(4)));
+    assert_eq!(lowered(&format!("{}1 |> 2", program)), Err(LowerError::Fixity(FixityError::UnknownOperator("|>".to_owned()))));
+    assert!(matches!(lowered("operator <+> left 6 (a: Number): Number return a end"),
+                     Err(LowerError::OperatorParameters { found: 1, .. })));
+
+    // a `-` or `~` after an operator is a prefix of the operand after it, and `^` binds more
+    // tightly than a prefix operator
+    let evaluated = |source: &str| run(lowered(source).unwrap());
+    assert_eq!(evaluated("2==-2"), Ok(Value::Boolean(false)));
+    assert_eq!(evaluated("3>-2"), Ok(Value::Boolean(true)));
+    assert_eq!(evaluated("-2 ^ 2"), Ok(Value::Number(-4)));
+    assert_eq!(evaluated("(-2) ^ 2"), Ok(Value::Number(4)));
+    assert_eq!(print(&parse("(-2) ^ 2").program), "(-2) ^ 2\n");
+    assert_eq!(evaluated("2 ^ -1 + 2 ^ 3 ^ 2"), Ok(Value::Number(512)));
+    assert_eq!(evaluated("1 <= 1 and 2 >= 3 or \"a\" .. \"b\" ~= \"ab\""), Ok(Value::Boolean(false)));
+}
+
+#[test]
+fn test_lower_record_updates() {
+    use super::ast::Ast;
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+    use std::sync::Arc;
+
+    // y changes type, and z is added
+    let parsed = parse("local p = {x = 1, y = 2}\nlocal q = {p with y = \"two\", z = 3}\n{q with x = 0}");
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    let Ast::Update { record, fields } = &parsed.program[2].ast else { panic!("expected an update") };
+    assert_eq!((&record.ast, fields.len()), (&Ast::Name("q".to_owned()), 1));
+    assert!(matches!(&parse("{}").program[0].ast, Ast::Record(fields) if fields.is_empty()));
+
+    let program = lower_block(&parsed.program, &Operators::default()).unwrap();
+    let fields = |x, y, z| vec![("x".to_owned(), x), ("y".to_owned(), y), ("z".to_owned(), z)];
+    assert_eq!(check(program.clone()), Ok(Type::Record(fields(Type::Number, Type::String, Type::Number))));
+    assert_eq!(run(program), Ok(Value::Record(Arc::new(vec![("x".to_owned(), Value::Number(0)),
+                                                            ("y".to_owned(), Value::String("two".to_owned())),
+                                                            ("z".to_owned(), Value::Number(3))]))));
+}
+
+#[test]
+fn test_lower_destructuring() {
+    use super::ast::{Ast, Pattern};
+    use super::lower::{lower_block, LowerError};
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+
+    let source = "function norm({x, y}: {x: Number, y: Number}): Number return x * x + y * y end\n\
+                  local {x, y = (a, b)} = {x = 1, y = (2, 3)}\n\
+                  local (c,), d = ((4,), 5)\n\
+                  norm({x = x + a, y = b + c}) + d";
+    let parsed = parse(source);
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    let Ast::Local { names, .. } = &parsed.program[1].ast else { panic!("expected a local") };
+    let ab = Pattern::Tuple(vec![Pattern::Name("a".to_owned()), Pattern::Name("b".to_owned())]);
+    assert_eq!(names[0].pattern, Pattern::Record(vec![("x".to_owned(), Pattern::Name("x".to_owned())), ("y".to_owned(), ab)]));
+    assert!(print(&parsed.program).contains("local {x, y = (a, b)} = "));
+
+    let program = lower_block(&parsed.program, &Operators::default()).unwrap();
+    assert_eq!(check(program.clone()), Ok(Type::Number));
+    assert_eq!(run(program), Ok(Value::Number(3 * 3 + 7 * 7 + 5)));
+
+    // only a value the pattern fits can be taken apart by it
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+    assert!(check(lowered("local (a, b) = (1, 2, 3)\na").unwrap()).is_err());
+    assert!(check(lowered("local {z} = {x = 1}\nz").unwrap()).is_err());
+    let contract = "function f((a, b): (Number, Number)): Number requires a > b return a end";
+    assert!(matches!(lowered(contract), Err(LowerError::Unsupported { .. })));
+}
+
+#[test]
+fn test_contracts_blame_the_violating_party() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, contracts, run, EvalError, Type, Value};
+
+    let half = "function half(n: Number): Number\n\
+                \x20 requires n % 2 == 0\n\
+                \x20 ensures result * 2 == n\n\
+                \x20 return n // 2\n\
+                end\n\
+                function twice(f: (Number) -> Number, x: Number): Number return f(f(x)) end\n";
+    let lowered = |program: &str| {
+        let parsed = parse(&format!("{}{}", half, program));
+        assert_eq!(parsed.errors, vec![]);
+        lower_block(&parsed.program, &Operators::default()).unwrap()
+    };
+    let violation = |contract: &str, blame: &str| Err(EvalError::ContractViolation { contract: contract.to_owned(), blame: blame.to_owned() });
+
+    assert_eq!(check(lowered("half(8) + twice(half, 8)")), Ok(Type::Number));
+    assert_eq!(run(lowered("half(8) + twice(half, 8)")), Ok(Value::Number(6)));
+    // a bad argument blames the call, even when it's made through a reference
+    assert_eq!(run(lowered("half(3)")), violation("the precondition of half at 2:12", "the call at 7:1"));
+    assert_eq!(run(lowered("twice(half, 6)")), violation("the precondition of half at 2:12", "the call through the reference at 7:7"));
+    // a name that shadows a function with a contract isn't checked against it
+    assert_eq!(run(lowered("local half = function(n: Number): Number return n end\nhalf(3)")), Ok(Value::Number(3)));
+    assert_eq!(run(lowered("(function(half: Number): Number return half end)(3)")), Ok(Value::Number(3)));
+
+    // a bad result blames the function
+    let parsed = parse("function off(n: Number): Number ensures result == n return n + 1 end\noff(1)");
+    let off = lower_block(&parsed.program, &Operators::default()).unwrap();
+    assert_eq!(run(off.clone()), violation("the postcondition of off at 1:41", "off at 1:1"));
+
+    // a release build leaves the checks out
+    assert_eq!(run(contracts::erase(off)), Ok(Value::Number(2)));
+    assert_eq!(run(contracts::erase(lowered("half(3)"))), Ok(Value::Number(1)));
+}
+
+#[test]
+fn test_total_function_declarations() {
+    use super::ast::Ast;
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{run, Expression, Value};
+
+    let parsed = parse("total function double(n: Number): Number return n * 2 end\nlocal total = double(4)\ntotal");
+    assert_eq!(parsed.errors, vec![]);
+    assert!(matches!(&parsed.program[0].ast, Ast::FunctionDeclaration { total: true, name, .. } if name == "double"));
+
+    // the function is checked before it's bound, and `total` is still an ordinary name
+    let lowered = lower_block(&parsed.program, &Operators::default()).unwrap();
+    let Expression::Match { scrutinee, .. } = &lowered else { panic!("expected a local") };
+    let Expression::Located { expression, .. } = &**scrutinee else { panic!("expected a declaration") };
+    assert!(matches!(**expression, Expression::Total(_)));
+    assert_eq!(run(lowered), Ok(Value::Number(8)));
+}
+
+#[test]
+fn test_parse_types() {
+    use super::parser::parse_type;
+    use crate::sgir::{Kind, Type, TypeBinding};
+
+    let variable = |name: &str| Type::Variable(name.to_owned());
+    let list = |typ| Type::Instantiate { typ: Box::new(variable("List")), arguments: vec![typ] };
+
+    assert_eq!(parse_type("(Number, T?) -> {x: T, y: ...String}"), Ok(Type::Function {
+        arguments: vec![Type::Number, Type::Union(vec![variable("T"), Type::Tuple(vec![])])],
+        result: Box::new(Type::Record(vec![("x".to_owned(), variable("T")),
+                                           ("y".to_owned(), Type::Rest(Box::new(Type::String)))])),
+    }));
+    // `&` binds tighter than `|`, and a function type's result takes in everything after it
+    assert_eq!(parse_type("(A) -> B | C & D"), Ok(Type::Function {
+        arguments: vec![variable("A")],
+        result: Box::new(Type::Union(vec![variable("B"), Type::Intersection(vec![variable("C"), variable("D")])])),
+    }));
+    assert_eq!(parse_type("(Number)"), Ok(Type::Number));
+    assert_eq!(parse_type("(Number, Boolean)"), Ok(Type::Tuple(vec![Type::Number, Type::Boolean])));
+    assert_eq!(parse_type("forall<T>. (T) -> T"), Ok(Type::ForAll {
+        parameters: vec![TypeBinding { id: "T".to_owned(), kind: Kind::Star }],
+        typ: Box::new(Type::Function { arguments: vec![variable("T")], result: Box::new(variable("T")) }),
+    }));
+    // the `>>` closing nested arguments is two closing angles
+    assert_eq!(parse_type("List<List<Number>>"), Ok(list(list(Type::Number))));
+    assert!(parse_type("List<Number").is_err());
+    assert!(parse_type("Number Number").is_err());
+}
+
+#[test]
+fn test_lower_type_annotations() {
+    use super::ast::{Ast, Binder};
+    use super::lower::{lower_block, LowerError};
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+
+    let parsed = parse("type Pair<T> = (T, T)\nlocal type = 1\nlocal p: Pair<Number> = f<Pair<Number>>(x)");
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert!(matches!(&parsed.program[0].ast, Ast::TypeAlias { name, parameters, .. } if name == "Pair" && parameters.len() == 1));
+    // `type` is only a keyword in front of an alias
+    assert!(matches!(&parsed.program[1].ast, Ast::Local { names, .. } if names[0] == Binder::name("type", None)));
+
+    let program = lowered("type N = Number\n\
+                           type Unary<T> = (T) -> T\n\
+                           local double: Unary<N> = function(n: N): N return n * 2 end\n\
+                           double(4 :: N)").unwrap();
+    assert_eq!(check(program.clone()), Ok(Type::Number));
+    assert_eq!(run(program), Ok(Value::Number(8)));
+
+    // an ascription that doesn't hold is a type error, and aliases are scoped to their block
+    assert!(check(lowered("true :: Number").unwrap()).is_err());
+    assert!(check(lowered("do type N = Number end\nlocal n: N = 1").unwrap()).is_err());
+    // a type parameter shadows an alias of the same name
+    assert!(check(lowered("type T = Number\nfn id<T>(x: T): T return x end\nid<Boolean>(true)").unwrap()).is_ok());
+    assert!(matches!(lowered("type Pair<T> = (T, T)\nlocal p: Pair = 1"),
+                     Err(LowerError::AliasArity { expected: 1, found: 0, .. })));
+}
+
+#[test]
+fn test_lower_match() {
+    use super::ast::{Ast, Case, Pattern};
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+
+    let source = "function classify(p: (Number, {x: Number, y: Boolean})): String\n\
+                  \x20 return match p\n\
+                  \x20 case (0, _) then\n    \"zero\"\n\
+                  \x20 case (n, {x}) if n == x then\n    \"same\"\n\
+                  \x20 case (-1, {y = true}) then\n    \"negative\"\n\
+                  \x20 case (n, _) if n < 0 then\n    local s = \"negative\"\n    s .. \"!\"\n\
+                  \x20 case _ then\n    \"other\"\n\
+                  \x20 end\n\
+                  end\n";
+    let parsed = parse(source);
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert_eq!(print(&parsed.program), source);
+    let Ast::FunctionDeclaration { body, .. } = &parsed.program[0].ast else { panic!("expected a declaration") };
+    let Ast::Return(values) = &body[0].ast else { panic!("expected a return") };
+    let Ast::Match { cases, .. } = &values[0].ast else { panic!("expected a match") };
+    let Case { pattern, guard, .. } = &cases[2];
+    let y = Pattern::Record(vec![("y".to_owned(), Pattern::Boolean(true))]);
+    assert_eq!((pattern, guard), (&Pattern::Tuple(vec![Pattern::Number(-1), y]), &None));
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+    let classify = |argument: &str| run(lowered(&format!("{}classify({})", source, argument)).unwrap());
+    let string = |value: &str| Ok(Value::String(value.to_owned()));
+    assert_eq!(classify("(0, {x = 0, y = true})"), string("zero"));
+    assert_eq!(classify("(2, {x = 2, y = true})"), string("same"));
+    assert_eq!(classify("(-1, {x = 0, y = true})"), string("negative"));
+    // a case whose guard fails falls through to the cases after it
+    assert_eq!(classify("(-1, {x = 0, y = false})"), string("negative!"));
+    assert_eq!(classify("(3, {x = 0, y = false})"), string("other"));
+
+    // a case binds its names in its guard and its body, shadowing the locals outside it
+    assert_eq!(run(lowered("local x = 1\nlocal y = match 2 case x if x > 1 then x * 10 case _ then x end\ny + x").unwrap()),
+               Ok(Value::Number(21)));
+    // `match` isn't a keyword, so it's a name unless a `case` follows the expression after it
+    assert_eq!(run(lowered("local match = 1\nmatch + 1").unwrap()), Ok(Value::Number(2)));
+
+    // every value has to be matched, and a case with a guard might not match anything
+    let error = |source: &str| check(lowered(source).unwrap()).unwrap_err().to_string();
+    assert_eq!(error("match true case true then 1 end"), "non-exhaustive match: false is not covered at 1:1");
+    assert_eq!(error("match (1, true) case (n, false) then n case (n, b) if b then n end"),
+               "non-exhaustive match: (_, true) is not covered at 1:1");
+    assert_eq!(check(lowered("match (1, true) case (n, false) then n case (n, b) if b then n case (n, _) then 0 - n end").unwrap()),
+               Ok(Type::Number));
+}
+
+#[test]
+fn test_lower_or_patterns() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Value};
+
+    let source = "match (2, \"b\")\ncase (1, s) | (2, s) then\n  s\ncase (3 | 4 | -5, _) then\n  \"few\"\ncase _ then\n  \"other\"\nend\n";
+    let parsed = parse(source);
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert_eq!(print(&parsed.program), source);
+    assert_eq!(print(&parse("match 1 case 1 | (2 | 3) then 0 case _ then 1 end").program),
+               "match 1\ncase 1 | (2 | 3) then\n  0\ncase _ then\n  1\nend\n");
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+    let evaluated = |scrutinee: &str| run(lowered(&source.replace("(2, \"b\")", scrutinee)).unwrap());
+    assert_eq!(evaluated("(2, \"b\")"), Ok(Value::String("b".to_owned())));
+    assert_eq!(evaluated("(-5, \"b\")"), Ok(Value::String("few".to_owned())));
+    assert_eq!(evaluated("(5, \"b\")"), Ok(Value::String("other".to_owned())));
+
+    // the alternatives cover what each of them does, and have to bind the same names at the same types
+    let error = |source: &str| check(lowered(source).unwrap()).unwrap_err().to_string();
+    assert!(check(lowered("match (true, 1) case (true, n) | (false, n) then n end").unwrap()).is_ok());
+    assert_eq!(error("match (1, 2) case (x, 1) | (1, y) then 0 case _ then 1 end"),
+               "x must be bound at the same type by every alternative of an or-pattern at 1:1");
+    assert_eq!(error("match (1, true) case (x, true) | (_, false) then 0 end"),
+               "x must be bound at the same type by every alternative of an or-pattern at 1:1");
+}
+
+#[test]
+fn test_lower_loops() {
+    use super::ast::Ast;
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Value};
+
+    let source = "local total = 0\n\
+                  local i = 0\n\
+                  while i < 10 do\n\
+                  \x20 i = i + 1\n\
+                  \x20 if i % 2 == 0 then\n    continue\n  end\n\
+                  \x20 if i > 7 then\n    break\n  end\n\
+                  \x20 total = total + i\n\
+                  end\n\
+                  local count = 0\n\
+                  repeat\n\
+                  \x20 local next = count + 1\n\
+                  \x20 count = next\n\
+                  until next >= 3\n\
+                  local pairs = 0\n\
+                  local a = 0\n\
+                  while a < 3 do\n\
+                  \x20 a = a + 1\n\
+                  \x20 local b = 0\n\
+                  \x20 while true do\n\
+                  \x20   b = b + 1\n\
+                  \x20   if b > a then\n      break\n    end\n\
+                  \x20   pairs = pairs + 1\n\
+                  \x20 end\n\
+                  end\n\
+                  local result = (total, i, count, pairs)\n\
+                  result\n";
+    let parsed = parse(source);
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert_eq!(print(&parsed.program), source);
+    let Ast::While { body, .. } = &parsed.program[2].ast else { panic!("expected a while loop") };
+    assert!(matches!(&body[0].ast, Ast::Assign { targets, .. } if targets.len() == 1));
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+    let program = lowered(source).unwrap();
+    assert!(check(program.clone()).is_ok(), "{:?}", check(program));
+    let numbers = |numbers: &[i64]| Ok(Value::Tuple(numbers.iter().map(|n| Value::Number(*n)).collect()));
+    assert_eq!(run(lowered(source).unwrap()), numbers(&[16, 9, 3, 6]));
+
+    // an `if` or a `do` that assigns a local rebinds it after, and a local declared after an
+    // assignment in the same block shadows it from there on
+    assert_eq!(run(lowered("local x = 1\nif x > 0 then\n  x = 2\nend\nx").unwrap()), Ok(Value::Number(2)));
+    assert_eq!(run(lowered("local x = 1\nlocal y = 0\ndo\n  x = 2\n  local x = 10\n  x = 11\n  y = x\nend\nlocal z = (x, y)\nz").unwrap()),
+               numbers(&[2, 11]));
+    assert_eq!(run(lowered("local x = 1\nlocal y = if true then\n  2\nelse\n  3\nend\nif y > 2 then\n  x = 5\n  y\nelse\n  x = 7\n  y\nend").unwrap()),
+               Ok(Value::Number(2)));
+    // the value of a function that ends in a loop is the unit value
+    assert_eq!(run(lowered("function f(n: Number): () while n > 0 do n = n - 1 end end\nf(3)").unwrap()), Ok(Value::Tuple(vec![])));
+
+    let error = |source: &str| lowered(source).unwrap_err().to_string();
+    assert_eq!(error("break"), "break at 1:1 isn't in a loop");
+    assert_eq!(error("while true do\n  local f = function() continue end\nend"), "continue at 2:24 isn't in a loop");
+    assert_eq!(error("x = 1"), "x at 1:1 isn't a local, so it can't be assigned to");
+    assert_eq!(error("local n = 0\nlocal f = function() n = 1 end"),
+               "an assignment to a local of another function at 2:22 can't be lowered to SGIR yet");
+    assert_eq!(error("local n = 0\nlocal m = if true then\n  n = 1\n  n\nelse\n  n\nend"),
+               "an assignment inside an expression at 2:11 can't be lowered to SGIR yet");
+    assert_eq!(error("local n = 0\nrepeat\n  n = n + 1\n  continue\nuntil n > 2"), "continue in a repeat loop at 4:3 can't be lowered to SGIR yet");
+    assert_eq!(error("local p = io.print\nwhile false do\n  p = p\nend"),
+               "the loop at 2:1 assigns p, whose type isn't known where it's lowered; annotate its declaration");
+    // an assignment keeps the type of what it assigns
+    assert_eq!(check(lowered("local n = 0\nn = \"one\"").unwrap()).unwrap_err().to_string(),
+               "type mismatch: expected Number, found String at 2:1, expanded from an assignment at 2:1");
+}
+
+#[test]
+fn test_lower_for_loops() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::primitives::Primitive;
+    use crate::sgir::{check, run, Binding, Expression, Type, Value};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+    let source = "local sum = 0\n\
+                  for a = 1, 3 do\n\
+                  \x20 for b = 1, a do\n\
+                  \x20   if b == 2 then\n      continue\n    end\n\
+                  \x20   sum = sum + b\n\
+                  \x20 end\n\
+                  end\n\
+                  sum";
+    assert_eq!(check(lowered(source).unwrap()), Ok(Type::Number));
+    assert_eq!(run(lowered(source).unwrap()), Ok(Value::Number(6)));
+    // assigning the loop variable doesn't change where the loop is up to
+    assert_eq!(run(lowered("local n = 0\nfor i = 1, 3 do\n  i = i * 10\n  n = n + i\nend\nn").unwrap()), Ok(Value::Number(60)));
+    // a step counts by something other than one, and a negative one counts down
+    let digits = |range: &str| run(lowered(&format!("local n = 0\nfor i = {} do\n  n = n * 10 + i\nend\nn", range)).unwrap());
+    assert_eq!(digits("1, 9, 3"), Ok(Value::Number(147)));
+    assert_eq!(digits("9, 1, -2"), Ok(Value::Number(97531)));
+    assert_eq!(digits("1, 3, -1"), Ok(Value::Number(0)));
+    assert_eq!(digits("1, 3, 0"), Ok(Value::Number(0)));
+    assert_eq!(print(&parse("for i = 10, 1, -1 do\n  f(i)\nend").program), "for i = 10, 1, -1 do\n  f(i)\nend\n");
+
+    // an iterator counting up from its state to 3
+    let variable = |name: &str| Expression::Variable(name.to_owned());
+    let primitive = |operator, arguments| Expression::Primitive { operator, arguments };
+    let next = Expression::If {
+        condition: Box::new(primitive(Primitive::Less, vec![Expression::Number(3), variable("n")])),
+        consequent: Box::new(Expression::Variant { tag: "Done".to_owned(), payload: Box::new(Expression::Tuple(vec![])) }),
+        alternative: Box::new(Expression::Variant {
+            tag: "Next".to_owned(),
+            payload: Box::new(Expression::Tuple(vec![variable("n"), primitive(Primitive::Add, vec![variable("n"), Expression::Number(1)])])),
+        }),
+    };
+    let next = Expression::Function { parameters: vec![Binding { id: "n".to_owned(), typ: Type::Number }], body: Box::new(next) };
+    let iterator = Expression::Record(vec![("next".to_owned(), next), ("state".to_owned(), Expression::Number(0))]);
+
+    // the loop variable has the type of the iterator's items
+    let sum = "function sum(items: Iterator<Number, Number>): Number\n\
+               \x20 local total = 0\n\
+               \x20 for item in items do\n\
+               \x20   if item > 2 then\n      break\n    end\n\
+               \x20   total = total + item\n\
+               \x20 end\n\
+               \x20 return total\n\
+               end\n\
+               sum";
+    let call = Expression::Application { function: Box::new(lowered(sum).unwrap()), arguments: vec![iterator] };
+    assert_eq!(check(call.clone()), Ok(Type::Number));
+    assert_eq!(run(call), Ok(Value::Number(3)));
+    let strings = "function count(items: Iterator<String, Number>): Number\n  local n = 0\n  for item in items do\n    n = n + item\n  end\n  return n\nend";
+    assert!(check(lowered(strings).unwrap()).is_err());
+
+    let error = |source: &str| lowered(source).unwrap_err().to_string();
+    assert_eq!(error("for x in 5 do\nend"), "the loop at 1:1 iterates over a value of type Number, which isn't an iterator");
+    assert_eq!(error("for x in os.args() do\nend"),
+               "the type of what the loop at 1:1 iterates over isn't known where it's lowered; annotate it");
+}
+
+#[test]
+fn test_lower_multiple_values() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+
+    let run = |source: &str| {
+        let parsed = parse(&format!("function two(): (Number, Number) return 1, 2 end
+{}", source));
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let program = lower_block(&parsed.program, &Operators::default()).unwrap();
+        assert!(check(program.clone()).is_ok(), "{:?}", check(program.clone()));
+        run(program)
+    };
+    let numbers = |numbers: &[u64]| Ok(Value::Tuple(numbers.iter().map(|n| Value::Number(*n as _)).collect()));
+
+    // a call gives its first value to one name, and missing values are the unit value
+    assert_eq!(run("local a = two()
+a"), Ok(Value::Number(1)));
+    assert_eq!(run("local a, b, c = two()
+local result = (a, b, c)
+result"),
+               Ok(Value::Tuple(vec![Value::Number(1), Value::Number(2), Value::Tuple(vec![])])));
+    // only the last value gives all of its values
+    assert_eq!(run("local a, b, c = two(), two()
+local result = (a, b, c)
+result"), numbers(&[1, 1, 2]));
+    assert_eq!(run("local a, b = two(), 3, 4
+local result = (a, b)
+result"), numbers(&[1, 3]));
+    assert_eq!(run("local a, b = 1, 2
+a, b = b, a
+local result = (a, b)
+result"), numbers(&[2, 1]));
+    // a tuple is a value, and it's only taken apart for several names
+    assert_eq!(run("local p = (1, 2)
+p"), numbers(&[1, 2]));
+    assert_eq!(run("local a, b = (1, 2)
+local result = (b, a)
+result"), numbers(&[2, 1]));
+
+    let parsed = parse("local a, b = 1, two()
+a, b = b, a");
+    assert_eq!(print(&parsed.program), "local a, b = 1, two()\na, b = b, a\n");
+    assert_eq!(check(lower_block(&parse("local a: Number = 1, true\na").program, &Operators::default()).unwrap()), Ok(Type::Number));
+}
+
+#[test]
+fn test_lower_float_literals() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default()).unwrap();
+    let program = lowered("local x: Float = 1.5\nx");
+    assert_eq!(check(program.clone()), Ok(Type::Float));
+    assert_eq!(run(program), Ok(Value::Float(1.5)));
+    // a literal that's a whole number is a number, however it's written
+    assert_eq!(check(lowered("1.5e3")), Ok(Type::Number));
+    assert_eq!(run(lowered("local result = (tostring(2.5e-3), tointeger(0.5), tofloat(\"0x1p4\"))\nresult")),
+               Ok(Value::Tuple(vec![Value::String("0.0025".to_owned()), Value::Tuple(vec![]), Value::Tuple(vec![])])));
+    assert!(check(lowered("local x: Number = 0.5\nx")).is_err());
+
+    // a float is written back as a literal unless it would read back as a number
+    use super::literal::Literal;
+    assert_eq!(Literal(&Value::Float(2.5e-3)).to_string(), "0.0025");
+    assert!(Literal(&Value::Float(2.0)).to_string().starts_with("<float"));
+}
+
+#[test]
+fn test_lower_float_division() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::primitives::Primitive;
+    use crate::sgir::{check, run, EvalError, Type, Value};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default()).unwrap();
+    // `/` divides numbers or floats to a float, and `//` is still floor division of numbers
+    assert_eq!(check(lowered("7 / 2")), Ok(Type::Float));
+    assert_eq!(run(lowered("local result = (7 / 2, 7 // 2, -7 / 2, 1.5 / 0.5, 2 * 3 / 4)\nresult")),
+               Ok(Value::Tuple(vec![Value::Float(3.5), Value::Number(3), Value::Float(-3.5), Value::Float(3.0), Value::Float(1.5)])));
+    // a float is never infinite or NaN, so dividing by zero is an error, unlike in Luau, as is a
+    // quotient too large to be finite
+    assert_eq!(run(lowered("1 / 0")), Err(EvalError::DivisionByZero));
+    assert_eq!(run(lowered("0 / 0")), Err(EvalError::DivisionByZero));
+    assert_eq!(run(lowered("1e9 / 1e-300")),
+               Err(EvalError::FloatOverflow { operator: Primitive::Quotient, left: 1e9, right: 1e-300 }));
+    assert!(check(lowered("local x: Number = 4 / 2\nx")).is_err());
+}
+
+#[test]
+fn test_lower_target_conditionals() {
+    use super::ast::Ast;
+    use super::lower::{lower_block, lower_block_for};
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{run, Value};
+
+    let source = "local x = @if target == \"wasm\" then\n  1\nelse\n  2\nend\n@if target == \"native\" then\n  x = x + 10\nend\nx";
+    let parsed = parse(source);
+    assert!(parsed.errors.is_empty());
+    assert!(matches!(&parsed.program[1].ast, Ast::IfTarget { target, alternative: None, .. } if target == "native"));
+    assert_eq!(print(&parsed.program), format!("{}\n", source));
+    // only the branch for the target is lowered, so the other leaves nothing behind
+    let lowered = lower_block(&parsed.program, &Operators::default()).unwrap();
+    assert_eq!(run(lowered), Ok(Value::Number(12)));
+    let lowered = lower_block_for(&parsed.program, &Operators::default(), "wasm").unwrap();
+    assert_eq!(run(lowered), Ok(Value::Number(1)));
+    // an attribute that isn't `@if` still attaches to the declaration after it
+    assert!(parse("@inline\nfunction f() 1 end").errors.is_empty());
+    assert!(!parse("@if target == wasm then 1 end").errors.is_empty());
+}
+
+#[test]
+fn test_lower_length_operator() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, TypeError, Value};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default()).unwrap();
+    let run = |source: &str| {
+        let program = lowered(source);
+        assert!(check(program.clone()).is_ok(), "{:?}", check(program.clone()));
+        run(program)
+    };
+    // a string's length counts its bytes, like `string.len`
+    assert_eq!(run("local result = (#\"héllo\", #b\"\\x00\\x01\", #(1, \"a\", true), -#\"ab\")\nresult"),
+               Ok(Value::Tuple([6, 2, 3, -2].into_iter().map(Value::Number).collect())));
+    assert_eq!(run("function count(...: Number): Number return #... end\ncount(1, 2, 3)"), Ok(Value::Number(3)));
+    let Err(TypeError::Located { error, .. }) = check(lowered("#5")) else { panic!("expected a located type error") };
+    assert_eq!(*error, TypeError::TypeMismatch { expected: Type::Union(vec![Type::String, Type::Bytes]), found: Type::Number });
+}
+
+#[test]
+fn test_lower_conversions() {
+    use super::lower::{lower_block, LowerError};
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default());
+    let run = |source: &str| {
+        let program = lowered(source).unwrap();
+        assert!(check(program.clone()).is_ok(), "{:?}", check(program.clone()));
+        run(program)
+    };
+    let string = |s: &str| Value::String(s.to_owned());
+    assert_eq!(run("local result = (tostring(255, 16), tostring(255), tostring(-5, 2))\nresult"),
+               Ok(Value::Tuple(vec![string("ff"), string("255"), string("-101")])));
+    assert_eq!(run("local result = (tonumber(\"ff\", 16), tonumber(\"12\"), tonumber(\"twelve\"))\nresult"),
+               Ok(Value::Tuple(vec![Value::Number(255), Value::Number(12), Value::Tuple(vec![])])));
+    assert_eq!(run("local result = (tofloat(\"2.5\"), tofloat(3), tointeger(7))\nresult"),
+               Ok(Value::Tuple(vec![Value::Float(2.5), Value::Float(3.0), Value::Number(7)])));
+    assert_eq!(check(lowered("tonumber(\"7\")").unwrap()), Ok(Type::Union(vec![Type::Number, Type::Tuple(vec![])])));
+    // a local of the same name shadows the library
+    assert_eq!(run("local tostring = function(n: Number): Number return n + 1 end\ntostring(1)"), Ok(Value::Number(2)));
+    assert!(matches!(lowered("tostring(1, 2, 3)"), Err(LowerError::Arguments { .. })));
+    assert!(run("tostring(1, 37)").is_err());
+}
+
+#[test]
+fn test_lower_string_library() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Value};
+
+    let run = |source: &str| {
+        let program = lower_block(&parse(source).program, &Operators::default()).unwrap();
+        assert!(check(program.clone()).is_ok(), "{:?}", check(program.clone()));
+        run(program)
+    };
+    // `string` counts bytes and `utf8` counts characters
+    assert_eq!(run("local s = \"héllo\"\nlocal result = (string.len(s), utf8.len(s), string.byte(s, 1), utf8.codepoint(utf8.char_at(s, 1)))\nresult"),
+               Ok(Value::Tuple([6, 5, 0xc3, 0xe9].into_iter().map(Value::Number).collect())));
+    assert_eq!(run("utf8.char_at(\"日本\", 1)"), Ok(Value::Char('本')));
+    assert_eq!(run("utf8.decode(\"日本\", 3)"), Ok(Value::Char('本')));
+    assert_eq!(run("utf8.width(utf8.char_at(\"日本\", 0))"), Ok(Value::Number(3)));
+    assert_eq!(run("utf8.fold(\"abc\", 0, function(n: Number, c: Char): Number return n + utf8.codepoint(c) end)"),
+               Ok(Value::Number(0x61 + 0x62 + 0x63)));
+    assert!(run("string.byte(\"a\", 1)").is_err());
+}
+
+#[test]
+fn test_lower_bytes_library() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+
+    let run = |source: &str| {
+        let program = lower_block(&parse(source).program, &Operators::default()).unwrap();
+        assert!(check(program.clone()).is_ok(), "{:?}", check(program.clone()));
+        run(program)
+    };
+    assert_eq!(run("bytes.concat(b\"\\x00ab\", bytes.slice(b\"xyz\", 1, 3))"), Ok(Value::Bytes(b"\x00abyz".to_vec())));
+    assert_eq!(run("bytes.len(bytes.encode_utf8(\"é\"))"), Ok(Value::Number(2)));
+    assert_eq!(run("bytes.encode_latin1(\"é\")"), Ok(Value::Bytes(vec![0xe9])));
+    assert_eq!(run("bytes.decode_utf8(b\"\\xc3\\xa9\")"), Ok(Value::String("é".to_owned())));
+    assert_eq!(run("bytes.decode_latin1(b\"\\xe9\")"), Ok(Value::String("é".to_owned())));
+    assert!(run("bytes.decode_utf8(b\"\\xff\")").is_err());
+    assert_eq!(check(lower_block(&parse("b\"\"").program, &Operators::default()).unwrap()), Ok(Type::Bytes));
+    assert_eq!(print(&parse("local b = b\"\\x00\\\"\"\n").program), "local b = b\"\\x00\\\"\"\n");
+}
+
+#[test]
+fn test_lower_named_and_default_arguments() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Value};
+
+    let declarations = "function sub(a: Number, b: Number = 1): Number\n  return a - b\nend\n\
+                        function positive(x: Number = 1): Number\n  requires x > 0\n  return x\nend\n";
+    let lowered = |source: &str| lower_block(&parse(&format!("{}{}", declarations, source)).program, &Operators::default());
+    let program = lowered("local result = (sub(5), sub(5, 2), sub(b = 2, a = 10), sub(10, b = 3), positive(), positive(x = 4))\nresult").unwrap();
+    assert!(check(program.clone()).is_ok(), "{:?}", check(program.clone()));
+    assert_eq!(run(program), Ok(Value::Tuple([4, 3, 8, 7, 1, 4].into_iter().map(Value::Number).collect())));
+    // the precondition is checked against the default too
+    assert!(run(lowered("function negative(x: Number = -1): Number\n  requires x > 0\n  return x\nend\nnegative()").unwrap()).is_err());
+
+    let parsed = parse(declarations);
+    assert!(print(&parsed.program).starts_with("function sub(a: Number, b: Number = 1): Number\n"));
+    assert_eq!(print(&parse("sub(10, b = 3)").program), "sub(10, b = 3)\n");
+    assert_eq!(parse("sub(a = 1, 2)").errors[0].to_string(), "expected a named argument, found 2 at 1:12");
+
+    let error = |source: &str| lowered(source).unwrap_err().to_string();
+    assert_eq!(error("sub(c = 1)"), "there is no parameter named c at 8:1");
+    assert_eq!(error("sub(1, a = 2)"), "more than one argument was given for the parameter a at 8:1");
+    assert_eq!(error("sub(b = 2)"), "no argument was given for the parameter a, which has no default at 8:1");
+    assert_eq!(error("local y = 1\nfunction f(x: Number = y): Number\n  return x\nend"),
+               "the default for x refers to variables, but defaults must be closed at 9:1");
+    assert_eq!(error("local f = function(x: Number = 1): Number\n  return x\nend"),
+               "a default for a parameter of an anonymous function at 8:11 can't be lowered to SGIR yet");
+    assert_eq!(error("local f = sub\nf(a = 1)"),
+               "a named argument of a function that isn't declared where it's called at 9:1 can't be lowered to SGIR yet");
+}
+
+#[test]
+fn test_lower_partial_application() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Type, Value};
+
+    let lowered = |source: &str| {
+        lower_block(&parse(&format!("function sub(a: Number, b: Number): Number\n  return a - b\nend\n{}", source)).program,
+                    &Operators::default())
+    };
+    let program = lowered("local minus_two = sub(_, 2)\nlocal from_ten = sub(10, _)\n\
+                           local result = (minus_two(5), from_ten(3), sub(_, _)(7, 1))\nresult").unwrap();
+    assert!(check(program.clone()).is_ok(), "{:?}", check(program.clone()));
+    assert_eq!(run(program), Ok(Value::Tuple(vec![Value::Number(3), Value::Number(7), Value::Number(6)])));
+    let function = Type::Function { arguments: vec![Type::Number], result: Box::new(Type::Number) };
+    assert_eq!(check(lowered("sub(_, 2)").unwrap()), Ok(function));
+    assert_eq!(print(&parse("sub(_, 2)").program), "sub(_, 2)\n");
+    // a local named `_` isn't a placeholder
+    assert_eq!(run(lowered("local _ = 5\nsub(_, 2)").unwrap()), Ok(Value::Number(3)));
+
+    let error = |source: &str| lowered(source).unwrap_err().to_string();
+    assert_eq!(error("sub(_)"), "arity mismatch: expected 2 arguments, found 1 at 4:1");
+    assert_eq!(error("local f = os.args\nf(_)"),
+               "the type of the function applied partially at 5:1 isn't known where it's lowered; annotate it");
+    assert_eq!(error("sub(_, b = 2)"), "a placeholder in a call with named arguments at 4:1 can't be lowered to SGIR yet");
+}
+
+#[test]
+fn test_lower_varargs() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Value};
+
+    let source = "function sum(...: Number): Number\n\
+                  \x20 local total = 0\n\
+                  \x20 for i = 1, select(\"#\", ...) do\n\
+                  \x20   total = total + select(i, ...)\n\
+                  \x20 end\n\
+                  \x20 return total\n\
+                  end\n\
+                  function rest(first: String, ...: Number): ...Number\n  return ...\nend\n\
+                  local result = (sum(1, 2, 3), sum(), rest(\"a\", 4, 5))\n\
+                  result";
+    let parsed = parse(source);
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert!(print(&parsed.program).starts_with("function sum(...: Number): Number\n"));
+    let program = lower_block(&parsed.program, &Operators::default()).unwrap();
+    assert!(check(program.clone()).is_ok(), "{:?}", check(program.clone()));
+    assert_eq!(run(program),
+               Ok(Value::Tuple(vec![Value::Number(6), Value::Number(0), Value::Tuple(vec![Value::Number(4), Value::Number(5)])])));
+
+    assert_eq!(parse("function f(...: Number, x: Number)\nend").errors[0].to_string(), "expected ), found `,` at 1:23");
+    let error = |source: &str| lower_block(&parse(source).program, &Operators::default()).unwrap_err().to_string();
+    assert_eq!(error("function f(...)\nend"), "the parameter ... of the function at 1:1 needs a type annotation");
+    assert_eq!(error("local x = ..."), "`...` at 1:11 isn't in a variadic function");
+    assert_eq!(error("function f(...: Number): Number\n  local g = function(): Number\n    return select(1, ...)\n  end\n  return g()\nend"),
+               "`...` at 3:22 isn't in a variadic function");
+    assert_eq!(error("function f(...: Number): Number\n  return select(...)\nend"), "arity mismatch: expected 2 arguments, found 1 at 2:10");
+}
+
+#[test]
+fn test_lower_macros() {
+    use super::ast::Ast;
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{check, run, Value};
+
+    // `plus_y`'s `y` is the one where it's declared, and `twice`'s `x` is its own
+    let source = "local y = 10\n\
+                  macro plus_y(e)\n  e + y\nend\n\
+                  macro twice(e)\n  local x = e\n  x + x\nend\n\
+                  macro unless(condition, e)\n  if condition then 0 else e end\nend\n\
+                  function f(y: Number): Number\n  return plus_y(y)\nend\n\
+                  local x = 3\n\
+                  local result = (f(1), twice(x + 1), unless(true, 1 // 0))\n\
+                  result";
+    let parsed = parse(source);
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert!(matches!(&parsed.program[1].ast, Ast::MacroDeclaration { name, parameters, .. } if name == "plus_y" && parameters.len() == 1));
+    assert!(print(&parsed.program).starts_with("local y = 10\nmacro plus_y(e)\n  e + y\nend\n"));
+    let program = lower_block(&parsed.program, &Operators::default()).unwrap();
+    assert!(check(program.clone()).is_ok(), "{:?}", check(program.clone()));
+    assert_eq!(run(program), Ok(Value::Tuple(vec![Value::Number(11), Value::Number(8), Value::Number(0)])));
+
+    // a local shadows a macro, and a macro is out of scope after its block
+    let source = "macro one()\n  1\nend\n\
+                  local f = function(one: Number): Number\n  return one\nend\n\
+                  do\n  macro two()\n    2\n  end\nend\n\
+                  local two = function(): Number\n  return 3\nend\n\
+                  local result = (one(), f(4), two())\n\
+                  result";
+    let program = lower_block(&parse(source).program, &Operators::default()).unwrap();
+    assert_eq!(run(program), Ok(Value::Tuple(vec![Value::Number(1), Value::Number(4), Value::Number(3)])));
+
+    let error = |source: &str| lower_block(&parse(source).program, &Operators::default()).unwrap_err().to_string();
+    assert_eq!(error("macro m(a, b)\n  a\nend\nm(1)"), "macro m expects 2 arguments, found 1 at 4:1");
+    assert_eq!(error("local x = 1\nmacro m()\n  x = 2\nend"),
+               "an assignment to a local of another function at 3:3 can't be lowered to SGIR yet");
+}
+
+#[test]
+fn test_test_declarations() {
+    use super::ast::Ast;
+    use super::lower::{lower_block, lower_tests, LowerError};
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::operators::Operators;
+    use crate::engine::{Engine, EngineError};
+    use crate::sgir::testing::run_tests_with;
+    use crate::sgir::{Interpreter, Value};
+
+    // `test` is only a keyword before the name of a test
+    let source = "local test = 2\ntest \"doubles\"\n  assert(test * 2 == 4)\nend\ntest \"decrements\"\n  assert(test - 2 == 2)\nend\ntest\n";
+    let parsed = parse(source);
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert!(matches!(&parsed.program[1].ast, Ast::Test { name, body } if name == "doubles" && body.len() == 1));
+    assert_eq!(print(&parsed.program), source);
+
+    // a program runs without its tests, and each test runs after the statements before it
+    let operators = Operators::default();
+    assert!(Interpreter::default().run(lower_block(&parsed.program, &operators).unwrap()).is_ok_and(|value| value == Value::Number(2)));
+    let tests = lower_tests(&parsed.program, &operators).unwrap();
+    assert_eq!(tests.iter().map(|(span, test)| (span.start.line, test.name.as_str())).collect::<Vec<_>>(), [(2, "doubles"), (5, "decrements")]);
+    let engine = Engine::new();
+    let report = run_tests_with(tests.into_iter().map(|(_, test)| test).collect(), |body| match engine.run(body) {
+        Err(EngineError::Eval(error)) => Err(error),
+        result => Ok(result.unwrap()),
+    });
+    assert_eq!(report.to_string(), "test doubles ... ok\ntest decrements ... FAILED: assertion failed at 6:3\n1 passed; 1 failed");
+
+    let nested = parse("do\n  test \"inner\" end\nend\n").program;
+    assert!(matches!(lower_block(&nested, &operators), Err(LowerError::NestedTest { span }) if span.start.line == 2));
+}
+
+#[test]
+fn test_print_keeps_comments() {
+    use super::parser::parse;
+    use super::print::print;
+
+    let source = "-- the start\nlocal x = 1 -- one\n--[=[ has ]] in it ]=]\nwhile x < 10 do\n  -- step\n  x = x + 1; --[[ after ]]\n  -- before end\nend\n\
+                  --- doubles\nfunction f(a: Number)\n  return a * 2\nend\nx -- last\n-- the end\n";
+    let program = parse(source).program;
+    assert_eq!(program[0].leading.iter().map(|comment| &comment.text[..]).collect::<Vec<_>>(), [" the start"]);
+    assert_eq!(program[0].trailing.iter().map(|comment| &comment.text[..]).collect::<Vec<_>>(), [" one"]);
+    let printed = print(&program);
+    assert_eq!(printed, source.replace("x = x + 1;", "x = x + 1"));
+    assert_eq!(print(&parse(&printed).program), printed);
+    // comments after what opens a block move to the line after it
+    assert_eq!(print(&parse("do -- why\n  f()\nend").program), "do\n  -- why\n  f()\nend\n");
+}
+
+#[test]
+fn test_doc_comments_attach_to_declarations() {
+    use super::ast::Ast;
+    use super::doc::items;
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::doc::DocItem;
+    use crate::sgir::Type;
+
+    let source = "--- twice `n`\n---\n--- exactly\nfunction twice(n: Number) return n * 2 end\n\
+                  -- an aside\nlocal x = 1\n\
+                  --- says hello\n@allow(unused_variable)\nlocal greet = function(name: String) log(name) end\n";
+    let program = parse(source).program;
+    let docs = program.iter()
+                      .map(|node| match &node.ast {
+                          Ast::FunctionDeclaration { doc, .. } | Ast::Local { doc, .. } => doc.as_deref(),
+                          _ => panic!("expected a declaration"),
+                      })
+                      .collect::<Vec<_>>();
+    assert_eq!(docs, [Some("twice `n`\n\nexactly"), None, Some("says hello")]);
+    let printed = print(&program);
+    assert!(printed.starts_with("--- twice `n`\n---\n--- exactly\nfunction twice"), "{}", printed);
+    assert!(printed.contains("--- says hello\n@allow(unused_variable) local greet"), "{}", printed);
+
+    // the types come from the checker, which knows the globals the program is run with
+    let function = |arguments, result| Some(Type::Function { arguments, result: Box::new(result) });
+    let log = ("log".to_owned(), function(vec![Type::String], Type::Tuple(vec![])));
+    let item = |name: &str, typ, doc: &str| DocItem { name: name.to_owned(), typ, doc: doc.to_owned() };
+    assert_eq!(items(&program, &[log]),
+               [item("twice", function(vec![Type::Number], Type::Number), "twice `n`\n\nexactly"),
+                item("x", Some(Type::Number), ""),
+                item("greet", function(vec![Type::String], Type::Tuple(vec![])), "says hello")]);
+    assert_eq!(items(&program, &[])[2], item("greet", None, "says hello"));
+}
+
+#[test]
+fn test_declaration_files() {
+    use super::declarations::declarations;
+    use crate::sgir::{check_with_declarations, Expression, Kind, Type, TypeBinding};
+
+    let source = "-- the files under the working directory\n\
+                  type Path = String\n\
+                  function read(path: Path): String\n\
+                  fn remove(path: Path)\n\
+                  function first<T>(xs: (T, T)): T\n\
+                  function join(separator: String, ...: String): String\n\
+                  local separator: Path\n";
+    let declared = declarations(source).unwrap();
+    assert_eq!(declared["read"], Type::Function { arguments: vec![Type::String], result: Box::new(Type::String) });
+    assert_eq!(declared["remove"], Type::Function { arguments: vec![Type::String], result: Box::new(Type::Tuple(vec![])) });
+    assert_eq!(declared["first"], Type::ForAll { parameters: vec![TypeBinding { id: "T".to_owned(), kind: Kind::Star }],
+                                                 typ: Box::new(Type::Function { arguments: vec![Type::Tuple(vec![Type::Variable("T".to_owned());
+                                                                                                                  2])],
+                                                                                result: Box::new(Type::Variable("T".to_owned())) }) });
+    assert_eq!(declared["join"], Type::Function { arguments: vec![Type::String, Type::Rest(Box::new(Type::String))],
+                                                  result: Box::new(Type::String) });
+    assert_eq!(declared["separator"], Type::String);
+    assert!(!declared.contains_key("Path"));
+
+    // calls into what's declared are checked like any others
+    let call = |function: &str, argument| Expression::Application { function: Box::new(Expression::Variable(function.to_owned())),
+                                                                    arguments: vec![argument] };
+    assert_eq!(check_with_declarations(&declared, call("read", Expression::Variable("separator".to_owned()))), Ok(Type::String));
+    assert!(check_with_declarations(&declared, call("read", Expression::Number(1))).is_err());
+
+    let error = |source: &str| declarations(source).unwrap_err().to_string();
+    assert_eq!(error("function read(path: String): String\n  return path\nend"), "expected a declaration, found return at 2:3");
+    assert_eq!(error("function read(path): String"), "the parameter path of the function at 1:1 needs a type annotation");
+    assert_eq!(error("local separator = \"/\""), "expected :, found `=` at 1:17");
+}
+
+#[test]
+fn test_type_errors_in_expanded_code_point_at_their_construct() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::check;
+
+    let error = |source: &str| check(lower_block(&parse(source).program, &Operators::default()).unwrap()).unwrap_err().to_string();
+    assert_eq!(error("local x = 1\nx + true"), "type mismatch: expected Number, found Boolean at 2:1");
+    assert_eq!(error("local x = 1\nx :: Boolean"),
+               "type mismatch: expected Boolean, found Number at 2:1, expanded from a type ascription at 2:1");
+    assert_eq!(error("-true"), "type mismatch: expected Number, found Boolean at 1:1, expanded from a negation at 1:1");
+    // the operand of an ascription isn't code it expanded to
+    assert_eq!(error("(1 + true) :: Number"), "type mismatch: expected Number, found Boolean at 1:2");
+}
+
+#[test]
+fn test_interface_checks_dependents_without_the_module() {
+    use super::ast::exports;
+    use super::lower::{lower_block, LowerError};
+    use super::parser::parse;
+    use crate::sgir::interface::Interface;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::resolve::resolve_module;
+    use crate::sgir::typed::elaborate;
+    use crate::sgir::{Declarations, Span, Position, Type};
+
+    let lowered = |source: &str| lower_block(&parse(source).program, &Operators::default()).unwrap();
+    let source = "local scale = 2\npub function area(w: Number, h: Number): Number\n  return w * h * scale\nend\npub local scale = true";
+    let module = lowered(source);
+    let interface = Interface::of(&elaborate(&Declarations::new(), module).unwrap(), &exports(&parse(source).program));
+    // the second `scale` shadows the first
+    assert_eq!(interface.exports,
+               vec![("area".to_owned(), Type::Function { arguments: vec![Type::Number, Type::Number], result: Box::new(Type::Number) }),
+                    ("scale".to_owned(), Type::Boolean)]);
+    assert!(interface.private.is_empty());
+    let interface = Interface::decode(&interface.encode()).unwrap();
+
+    let declarations = interface.declarations("geometry");
+    let check = |source: &str| elaborate(&declarations, lowered(source)).map(|typed| typed.typ).map_err(|error| error.to_string());
+    assert_eq!(check("geometry.area(3, 4)"), Ok(Type::Number));
+    assert_eq!(check("geometry.area(3, geometry.scale)"),
+               Err("type mismatch: expected Number, found Boolean at 1:1".to_owned()));
+
+    // a helper that isn't `pub` stays in its module, and so does a re-export's original name
+    let source = "function square(x: Number): Number return x * x end\n\
+                  pub function cube(x: Number): Number return x * square(x) end\n\
+                  pub use geometry.area as size\n\
+                  pub use geometry.scale";
+    let module = lowered(source);
+    let interface = Interface::of(&elaborate(&declarations, module).unwrap(), &exports(&parse(source).program));
+    assert_eq!(interface.exports.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), ["cube", "size", "scale"]);
+    assert_eq!(interface.private, ["square"]);
+    let interface = Interface::decode(&interface.encode()).unwrap();
+    let resolve = |source: &str| resolve_module(lowered(source), &interface.declarations("powers"), &interface.private("powers"))
+                                     .map(|_| ())
+                                     .map_err(|error| error.to_string());
+    assert_eq!(resolve("powers.cube(2) + powers.size(1, 2)"), Ok(()));
+    assert_eq!(resolve("1 +\npowers.square(2)"),
+               Err("powers.square is private to the module powers, so it can't be used outside of it; \
+                    declare it `pub` in powers to export it at 2:1".to_owned()));
+
+    // only the top level of a module exports anything
+    let nested = lower_block(&parse("do\n  pub local x = 1\nend").program, &Operators::default());
+    let at = Span { start: Position { line: 2, column: 3 }, end: Position { line: 2, column: 18 } };
+    assert_eq!(nested, Err(LowerError::NestedExport { span: at }));
+}
+
+#[test]
+fn test_dump_sgir_with_source_lines() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::dump::{definition, dump};
+    use crate::sgir::operators::Operators;
+
+    let source = "function square(x: number): number\n  return x * x\nend\nlocal y = square(3)\ny";
+    let program = lower_block(&parse(source).program, &Operators::default()).unwrap();
+    assert_eq!(dump(&program, Some(source)),
+               "-- 1-3: function square(x: number): number\n\
+                let square = fn (x: number) ->\n\
+                \x20   returning number\n\
+                \x20       -- 2: return x * x\n\
+                \x20       return *(x, x)\n\
+                -- 4: local y = square(3)\n\
+                let y = square(3)\n\
+                -- 5: y\n\
+                y\n");
+    let square = definition(&program, "square").unwrap();
+    assert_eq!(dump(square, None).lines().next(), Some("-- 1-3"));
+    assert!(definition(&program, "cube").is_none());
+}
+
+#[test]
+fn test_lint_levels_from_attributes_and_manifest() {
+    use super::lint::{lint, Level, Levels, LintError};
+    use super::manifest::{self, ManifestError};
+    use super::parser::parse;
+
+    let found = |source: &str, levels: &Levels| {
+        let parsed = parse(source);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        lint(source, &parsed.program, levels).map(|warnings| warnings.iter().map(ToString::to_string).collect::<Vec<_>>())
+    };
+    let source = "@allow(unused_variable)\nfunction f(x: Number, y: Number)\n  local z = 1\n  x\nend\n\
+                  function g(a: Number, _b: Number) 1 end\nf(1, 2) + g(3, 4)";
+    assert_eq!(found(source, &Levels::default()), Ok(vec!["warning: `a` at 6:12 is never used [unused_variable]".to_owned()]));
+    // an inner declaration has the last say
+    assert_eq!(found("@allow(unused_variable) function f()\n  @warn(unused_variable) local z = 1\n  2\nend\nf()", &Levels::default()),
+               Ok(vec!["warning: `z` at 2:32 is never used [unused_variable]".to_owned()]));
+
+    let strict = manifest::parse("[package]\nname = \"demo\"\n\n[lints]\nunused_variable = \"deny\" # no dead code\n").unwrap();
+    assert_eq!(strict.lints.get("unused_variable"), Level::Deny);
+    assert_eq!(found("local a = 1\n2", &strict.lints), Ok(vec!["error: `a` at 1:7 is never used [unused_variable]".to_owned()]));
+    assert_eq!(found("@!allow(unused_variable)\nlocal a = 1\n2", &strict.lints), Ok(vec![]));
+    // what a module exports is used by the modules that depend on it
+    assert_eq!(found("pub local a = 1\n@deny(unused_variable) pub local b, c = (2, 3)\npub use m.d as e\n2", &strict.lints), Ok(vec![]));
+    // an operator is used by applying it, even inside parentheses, and is otherwise unused
+    let operators = "operator <+> left 6 (a: Number, b: Number): Number\n  return a + b\nend\n\
+                     operator <-> left 6 (a: Number, b: Number): Number\n  return a - b\nend\n";
+    assert_eq!(found(&format!("{}1 <+> (2 * 3)", operators), &Levels::default()),
+               Ok(vec!["warning: `<->` at 4:10 is never used [unused_variable]".to_owned()]));
+    let source = format!("{}(1 <+> 2) <+> ((3))", operators);
+    let resolution = super::rename::resolve_program(&parse(&source).program, &super::lexer::lex(&source).unwrap());
+    let spans = resolution.uses.iter()
+                               .filter(|(name, _, _)| name == "<+>")
+                               .map(|(_, span, _)| (span.start.column, span.end.column))
+                               .collect::<Vec<_>>();
+    assert_eq!(spans, [(4, 7), (11, 14)]);
+
+    assert!(matches!(found("@allow(unused) local a = 1", &Levels::default()), Err(LintError::UnknownLint { .. })));
+    assert!(matches!(found("@inline local a = 1", &Levels::default()), Err(LintError::UnknownAttribute { .. })));
+    assert!(!parse("@allow(unused_variable) f()").errors.is_empty());
+    assert_eq!(manifest::parse("[lints]\nunused = \"allow\""), Err(ManifestError::UnknownLint { name: "unused".to_owned(), line: 2 }));
+    assert_eq!(manifest::parse("[lints]\nunused_variable = allow"), Err(ManifestError::Syntax(2)));
+}
+
+#[test]
+fn test_lint_dynamic_captures() {
+    use super::lint::{lint, Levels};
+    use super::parser::parse;
+
+    let found = |source: &str| {
+        let parsed = parse(source);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        let warnings = lint(source, &parsed.program, &Levels::default()).unwrap();
+        warnings.into_iter().filter(|warning| warning.lint == "dynamic_capture").map(|warning| warning.message).collect::<Vec<_>>()
+    };
+    assert_eq!(found("local x = 1\nfunction f() x end\nlocal x = 2\nf() + x"),
+               vec!["`x` at 2:14 refers to the `x` bound at 1:7, but the call at 4:1 would have seen the `x` bound at 3:7 under dynamic scoping"]);
+    assert_eq!(found("local g = fn() y end\nfunction h(y: Number) g() + y end\nh(1)"),
+               vec!["`y` at 1:16 refers to the global `y`, but the call at 2:23 would have seen the `y` bound at 2:12 under dynamic scoping"]);
+    // the parameters of the function itself, and names bound the same way at the call, are fine
+    assert_eq!(found("local x = 1\nfunction f(x: Number) x end\nfunction g() x end\nlocal y = x\nf(y) + g()"), Vec::<String>::new());
+    // a function calling itself is an error, not a capture
+    assert_eq!(found("function f(n: Number) f(n) end\nfunction g(f: Number) f + 1 end"), Vec::<String>::new());
+    let source = "local x = 1\n@allow(dynamic_capture) function f() x end\nlocal x = 2\nf() + x";
+    assert_eq!(lint(source, &parse(source).program, &Levels::default()), Ok(vec![]));
+}
+
+#[test]
+fn test_parse_luau_compat() {
+    use super::lower::lower_block;
+    use super::parser::{parse, parse_compat, Compat, SyntaxError};
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{run, Value};
+
+    let source = "local function double(n: number): number\n  return n * 2\nend\nlocal big: boolean = double(3) == 6\nreturn double(21), big";
+    let parsed = parse_compat(source, Compat::Luau);
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    let program = lower_block(&parsed.program, &Operators::default()).unwrap();
+    assert_eq!(run(program), Ok(Value::Tuple(vec![Value::Number(42), Value::Boolean(true)])));
+    // none of it is sanguinello
+    assert!(!parse(source).errors.is_empty());
+
+    let unsupported = |source| parse_compat(source, Compat::Luau).errors.into_iter().map(|error| error.to_string()).collect::<Vec<_>>();
+    assert_eq!(unsupported("local t = {x = 1}\nt.x = 2\nlocal y = nil"),
+               vec!["assignment to a field at 2:1 is not supported in sanguinello", "nil at 3:11 is not supported in sanguinello"]);
+    assert!(matches!(&parse_compat("t.x = 1", Compat::Luau).errors[..],
+                     [SyntaxError::Unsupported { construct: "assignment to a field", span }] if span.end.column == 8));
+    // assigning a local is the same in both
+    assert!(parse_compat("local x = 1\nx = x + 1", Compat::Luau).errors.is_empty());
+
+    assert_eq!(unsupported("local function f(x, n: number)\n  return n\nend"),
+               vec!["a parameter without a type annotation at 1:18 is not supported in sanguinello"]);
+    assert_eq!(unsupported("local g = function(...) return 1 end"),
+               vec!["a parameter without a type annotation at 1:20 is not supported in sanguinello"]);
+    assert_eq!(unsupported("local t = {x = 1}\nt[1] = 2"), vec!["indexing with `[]` at 2:1 is not supported in sanguinello"]);
+    assert!(matches!(&parse_compat("local y = t[\"x\"]", Compat::Luau).errors[..],
+                     [SyntaxError::Unsupported { construct: "indexing with `[]`", span }] if (span.start.column, span.end.column) == (11, 17)));
+}
+
+#[test]
+fn test_reduce_keeps_what_the_predicate_needs() {
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use super::reduce::reduce;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{run, EvalError};
+
+    let divides_by_zero = |source: &str| {
+        let parsed = parse(source);
+        parsed.errors.is_empty()
+            && lower_block(&parsed.program, &Operators::default()).is_ok_and(|program| run(program) == Err(EvalError::DivisionByZero))
+    };
+    let source = "local a = 1 + 2\nlocal b = a * 3\nfn f(x: Number): Number\n  local y = x + 1\n  return y // 0\nend\nlocal c = f(b) + a\nc\n";
+    assert_eq!(reduce(source, divides_by_zero), "local a = 1\nlocal b = a\nfn f(x: Number): Number\n  local y = x\n  y // 0\nend\nf(b)\n");
+    assert_eq!(reduce("1 // 0 + (2 + 3)", divides_by_zero), "1 // 0");
+}
+
+#[test]
+fn test_complete_names_in_scope() {
+    use super::completion::{complete, Completion, CompletionKind};
+    use crate::sgir::{Position, Type};
+
+    // the completions at the `|` in `source`, which is taken out
+    let complete_at = |source: &str| {
+        let (before, after) = source.split_once('|').unwrap();
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap().chars().count() + 1;
+        complete(&format!("{}{}", before, after), Position { line, column })
+    };
+    let labels = |completions: Vec<Completion>| completions.into_iter().map(|completion| completion.label).collect::<Vec<_>>();
+
+    let source = "local total = 1\n\
+                  fn scale(n: Number, by: Number): Number\n  \
+                  local t = n * by\n  \
+                  if n < 0 then local tmp = 0 else\n    \
+                  return t|\n\
+                  end\n\
+                  local trailing = 2";
+    // innermost first, without the locals of other blocks or those after the cursor
+    assert_eq!(labels(complete_at(source)), ["t", "total"]);
+    assert_eq!(labels(complete_at(&source.replace("t|", "|"))), ["t", "by", "n", "total", "string", "utf8", "bytes"]);
+    let completions = complete_at("fn f(q: Number): Number return q end\nlocal z = f(1)\n|");
+    assert_eq!(completions.iter().find(|completion| completion.label == "z").unwrap().typ, Some(Type::Number));
+    assert!(matches!(completions.iter().find(|completion| completion.label == "f").unwrap(),
+                     Completion { kind: CompletionKind::Function, typ: Some(Type::Function { .. }), .. }));
+
+    // the fields of a record after a `.`, through nested records
+    let record = "fn f(p: {x: Number, inner: {yes: Boolean}}): Number\n  ";
+    let completions = complete_at(&format!("{}return g(p.|)", record));
+    assert_eq!(labels(completions.clone()), ["x", "inner"]);
+    assert!(completions.iter().all(|completion| completion.kind == CompletionKind::Field));
+    assert_eq!(complete_at(&format!("{}p.inner.y|", record))[0].typ, Some(Type::Boolean));
+    assert!(complete_at("local n = 1\nn.|").is_empty());
+
+    // a case-sensitive match ranks above a case-insensitive one, and `result` is in scope in an
+    // `ensures` clause
+    assert_eq!(labels(complete_at("local Value = 1\nlocal value = 2\nval|")), ["value", "Value"]);
+    assert_eq!(labels(complete_at("fn f(x: Number): Number\n  ensures r| > x\n  return x\nend")), ["result"]);
+
+    // the modules of the library after the names in scope, and their operators after a `.`,
+    // unless a local shadows the module
+    let completions = complete_at("local s = \"\"\nlocal n = utf8.|");
+    assert_eq!(labels(completions.clone())[..2], ["len", "char_at"]);
+    assert_eq!(completions[0].typ, Some(Type::Function { arguments: vec![Type::String], result: Box::new(Type::Number) }));
+    assert_eq!(labels(complete_at("local n = by|")), ["bytes"]);
+    assert_eq!(complete_at("local st = 1\nst|").iter().map(|completion| completion.kind).collect::<Vec<_>>(),
+               [CompletionKind::Variable, CompletionKind::Module]);
+    assert!(complete_at("local string = 1\nstring.|").is_empty());
+    assert_eq!(labels(complete_at("repeat\n  local step = 1\nuntil st|")), ["step", "string"]);
+}
+
+#[test]
+fn test_expect_fields_and_constructors() {
+    use super::completion::{expect, CompletionKind, Expected};
+    use crate::sgir::{Position, Type};
+
+    // what's expected at the `|` in `source`, which is taken out, after some declarations
+    let expect_at = |source: &str| {
+        let declarations = "type Point = {x: Number, y: Number}\n\
+                            fn origin(): Point return {x = 0, y = 0} end\n\
+                            fn flag(): Boolean return true end\n\
+                            fn norm(p: Point, rest: ...Number): Number return 0 end\n";
+        let (before, after) = source.split_once('|').unwrap();
+        let line = before.matches('\n').count() + 5;
+        let column = before.rsplit('\n').next().unwrap().chars().count() + 1;
+        expect(&format!("{}{}{}", declarations, before, after), Position { line, column })
+    };
+    let labels = |expected: Expected| expected.completions.into_iter().map(|completion| completion.label).collect::<Vec<_>>();
+    let point = Type::Record(vec![("x".to_owned(), Type::Number), ("y".to_owned(), Type::Number)]);
+
+    // the fields a record hasn't been given yet, of the type its local is annotated with
+    let expected = expect_at("local p: Point = {x = 1, |}");
+    assert_eq!(expected.typ, Some(point.clone()));
+    assert_eq!(expected.completions.iter().map(|completion| (completion.label.as_str(), completion.kind)).collect::<Vec<_>>(),
+               [("y", CompletionKind::Field)]);
+    assert_eq!(labels(expect_at("local p: Point = {|")), ["x", "y"]);
+    assert_eq!(labels(expect_at("local q: {a: Number, b: {c: Boolean}} = {b = {|}}")), ["c"]);
+
+    // the functions that make a value of the type expected of an argument, a result, or a local
+    assert_eq!(expect_at("norm(|").typ, Some(point.clone()));
+    assert_eq!(labels(expect_at("norm(or|")), ["origin"]);
+    assert_eq!(expect_at("norm(origin(), 1, |)").typ, Some(Type::Number));
+    assert_eq!(labels(expect_at("fn g(): Boolean\n  return |\nend")), ["flag"]);
+    assert_eq!(labels(expect_at("local p: Point = |")), ["origin"]);
+    assert_eq!(expect_at("local n = |"), Expected::default());
+}
+
+#[test]
+fn test_rename_refuses_capture() {
+    use super::rename::{rename, RenameError};
+    use crate::sgir::Position;
+
+    let at = |line, column| Position { line, column };
+    let source = "local x = 1\n\
+                  fn f(y: Number, p: Pair<Number, Number>): Number\n  \
+                  ensures result > 0\n  \
+                  local z = y\n  \
+                  return x + z\n\
+                  end\n\
+                  do local x = 2; print(x) end\n\
+                  f(x, pair(x, x))";
+
+    // from the binder or a use, but not a shadowing binding of the same name
+    let renamed = rename(source, at(8, 3), "count").unwrap();
+    assert_eq!(renamed.old, "x");
+    assert_eq!(renamed.spans.len(), 5);
+    assert!(renamed.source.starts_with("local count = 1") && renamed.source.contains("return count + z"));
+    assert!(renamed.source.contains("do local x = 2; print(x) end\nf(count, pair(count, count))"));
+    assert_eq!(rename(source, at(1, 7), "count"), Ok(renamed));
+    assert!(rename(source, at(2, 17), "q").unwrap().source.contains("fn f(y: Number, q: Pair<Number, Number>)"));
+
+    // a binding that would capture a use of another, or be captured by one
+    assert!(matches!(rename(source, at(4, 9), "x"), Err(RenameError::Capture { span, .. }) if span.start == at(5, 10)));
+    assert!(matches!(rename(source, at(2, 6), "x"), Err(RenameError::Capture { span, .. }) if span.start == at(5, 10)));
+    assert!(matches!(rename(source, at(1, 7), "print"), Err(RenameError::Capture { .. })));
+    assert!(rename(source, at(7, 23), "y").is_ok());
+
+    assert!(matches!(rename(source, at(7, 17), "y"), Err(RenameError::NotBound { name, .. }) if name == "print"));
+    assert!(matches!(rename(source, at(3, 11), "r"), Err(RenameError::NotBound { name, .. }) if name == "result"));
+    assert!(matches!(rename(source, at(3, 3), "r"), Err(RenameError::NoName(_))));
+    assert_eq!(rename(source, at(1, 7), "end"), Err(RenameError::InvalidName("end".to_owned())));
+
+    // the condition of a `repeat` is in the scope of its body
+    let renamed = rename("local n = 0\nrepeat\n  local done = n > 1\n  n = n + 1\nuntil done", at(3, 9), "finished").unwrap();
+    assert!(renamed.source.ends_with("until finished"));
+}
+
+#[test]
+fn test_inlay_hints() {
+    use super::hints::{hints, HintKind};
+    use crate::sgir::Position;
+
+    let source = "fn scale(n: Number, by: Number): Number return n * by end\n\
+                  local by = 3\n\
+                  local doubled, flag: Boolean = scale(2, by), true\n\
+                  local twice = function(x: Number): Number return scale(x, 2) end\n\
+                  local unknown = print(twice(by))";
+    let found = hints(source).into_iter().map(|hint| (hint.position, hint.to_string())).collect::<Vec<_>>();
+    let at = |line, column| Position { line, column };
+    // `by` is already named after its parameter, `flag` is annotated, and `print` isn't declared
+    assert_eq!(found, [(at(2, 9), ": Number".to_owned()),
+                       (at(3, 38), "n:".to_owned()),
+                       (at(4, 12), ": (Number) -> Number".to_owned()),
+                       (at(4, 56), "n:".to_owned()),
+                       (at(4, 59), "by:".to_owned()),
+                       (at(5, 29), "x:".to_owned())]);
+    assert!(hints("local p = 1 +").iter().all(|hint| matches!(hint.kind, HintKind::Type(_))));
+}
+
+#[test]
+fn test_ast_json_dump() {
+    use super::json::{dump, Json};
+
+    let dumped = dump("-- answer\nlocal x: Number = f(\"a\\\"b\", 1.5) --[[ why ]]\nend").to_string();
+    assert!(dumped.starts_with("{\"version\":18,\"program\":[{\"kind\":\"Local\",\
+                                \"span\":{\"start\":{\"line\":2,\"column\":1},\"end\":{\"line\":2,\"column\":33}},\
+                                \"attributes\":[],\"public\":false,\"names\":[{\"name\":\"x\",\"annotation\":\"Number\",\"default\":null}],\
+                                \"values\":[{\"kind\":\"Call\""), "{}", dumped);
+    assert!(dumped.contains("{\"kind\":\"String\",\"span\":{\"start\":{\"line\":2,\"column\":21},\"end\":{\"line\":2,\"column\":27}},\
+                             \"value\":\"a\\\"b\"}"), "{}", dumped);
+    assert!(dumped.contains("\"value\":1.5}"));
+    assert!(dumped.contains("\"comments\":[{\"text\":\" answer\",\"block\":false,"));
+    assert!(dumped.contains("{\"text\":\" why \",\"block\":true,"));
+    assert!(dumped.contains("{\"kind\":\"Error\","));
+    assert!(dumped.ends_with("\"errors\":[\"expected a statement, found end at 3:1\"]}"), "{}", dumped);
+
+    assert_eq!(Json::String("tab\there\u{1}".to_owned()).to_string(), "\"tab\\there\\u0001\"");
+    assert_eq!(Json::Object(vec![]).to_string(), "{}");
+}
+
+#[test]
+fn test_json_parses_values_and_locates_errors() {
+    use super::json::{parse_json, Json, JsonError};
+
+    let error = |message: &str, offset| Err(JsonError { message: message.to_owned(), offset });
+    assert_eq!(parse_json(" {\"a\": [1, -2.5e1, true, null], \"b\": {}}\n"),
+               Ok(Json::Object(vec![("a".to_owned(), Json::Array(vec![Json::Number(1), Json::Float(-25.0), Json::Boolean(true), Json::Null])),
+                                    ("b".to_owned(), Json::Object(vec![]))])));
+    assert_eq!(parse_json(r#""\"\\\/\b\t\u00e9\ud83d\ude00""#), Ok(Json::String("\"\\/\u{8}\té😀".to_owned())));
+    assert_eq!(parse_json("9223372036854775808"), Ok(Json::Float(9223372036854775808.0)));
+    let text = "{\"tab\":\"\\t\",\"list\":[1,2.5,\"\\u0001\"]}";
+    assert_eq!(parse_json(text).map(|json| json.to_string()), Ok(text.to_owned()));
+
+    assert_eq!(parse_json("[1, 2,]"), error("expected a value, found `]`", 6));
+    assert_eq!(parse_json("[1 2]"), error("expected `,` or `]`, found `2`", 3));
+    assert_eq!(parse_json("{\"a\": 1, \"a\": 2}"), error("the field a appears more than once", 9));
+    assert_eq!(parse_json("\"é\u{1}\""), error("control character in a string", 3));
+    assert_eq!(parse_json("\"\\ud800x\""), error("unpaired surrogate", 1));
+    assert_eq!(parse_json("012"), error("a number with a leading zero", 0));
+    assert_eq!(parse_json("1e999"), error("a number too large for a float", 0));
+    assert_eq!(parse_json("\"open"), error("unterminated string", 5));
+    assert_eq!(parse_json("1 1"), error("expected the end, found `1`", 2));
+    assert_eq!(parse_json(""), error("expected a value, found the end", 0));
+    assert_eq!(parse_json(&"[".repeat(1000)), error("arrays and objects nested more than 256 deep", 256));
+}
+
+#[test]
+fn test_literals_read_back_as_their_values() {
+    use super::literal::Literal;
+    use super::lower::lower_block;
+    use super::parser::parse;
+    use crate::sgir::operators::Operators;
+    use crate::sgir::{run, Value};
+    use std::sync::Arc;
+
+    let string = |value: &str| Value::String(value.to_owned());
+    let values = [Value::Number(-5),
+                  Value::Number(i64::MIN),
+                  Value::Tuple(vec![]),
+                  Value::Tuple(vec![Value::Boolean(true)]),
+                  Value::Record(Arc::new(vec![("name".to_owned(), string("say \"hi\"\n\tand \u{1b}")),
+                                              ("pair".to_owned(), Value::Tuple(vec![Value::Number(1), string("]]")]))]))];
+    for value in values {
+        let source = Literal(&value).to_string();
+        let parsed = parse(&source);
+        assert!(parsed.errors.is_empty(), "{}: {:?}", source, parsed.errors);
+        assert_eq!(run(lower_block(&parsed.program, &Operators::default()).unwrap()), Ok(value), "{}", source);
+    }
+    assert_eq!(Literal(&Value::Record(Arc::new(vec![("x".to_owned(), Value::Number(1))]))).to_string(), "{x = 1}");
+
+    let function = run(lower_block(&parse("function(n: Number): Number return n end").program, &Operators::default()).unwrap()).unwrap();
+    let placeholder = Literal(&Value::Tuple(vec![function, Value::Char('c')])).to_string();
+    assert_eq!(placeholder, "(<fn (Number) -> Number>, <char 'c'>)");
+    assert!(!parse(&placeholder).errors.is_empty());
+}
+
+#[test]
+fn test_grammar_sentences_parse_and_print_back() {
+    use super::ast::{Ast, Block, Node};
+    use super::grammar::{self, Grammar, GrammarError};
+    use super::parser::parse;
+    use super::print::print;
+    use crate::sgir::{Position, Span};
+
+    // the parser's tree, but for where it came from, which printing it changes
+    fn unplaced(mut program: Block) -> Block {
+        fn visit(node: &mut Node) {
+            let nowhere = Position { line: 0, column: 0 };
+            node.span = Span { start: nowhere, end: nowhere };
+            if let Ast::Local { attributes, .. } | Ast::FunctionDeclaration { attributes, .. } = &mut node.ast {
+                attributes.iter_mut().for_each(|attribute| attribute.span = node.span);
+            }
+            if let Ast::FileAttribute(attribute) = &mut node.ast {
+                attribute.span = node.span;
+            }
+            node.children_mut().into_iter().for_each(visit);
+        }
+        program.iter_mut().for_each(visit);
+        program
+    }
+
+    let grammar = Grammar::surface();
+    let sentences = grammar.sentences(7);
+    for sentence in &sentences {
+        let parsed = parse(sentence);
+        assert!(parsed.errors.is_empty(), "{}: {:?}", sentence, parsed.errors);
+        let printed = print(&parsed.program);
+        let reparsed = parse(&printed);
+        assert!(reparsed.errors.is_empty(), "{}\nprinted as\n{}: {:?}", sentence, printed, reparsed.errors);
+        assert_eq!(unplaced(reparsed.program), unplaced(parsed.program), "{}\nprinted as\n{}", sentence, printed);
+    }
+    let written: std::collections::HashSet<&str> = sentences.iter().flat_map(|sentence| sentence.split(' ')).collect();
+    let mut unwritten: Vec<_> = grammar.tokens().into_iter().filter(|token| !written.contains(token)).collect();
+    unwritten.sort();
+    assert!(unwritten.is_empty(), "no sentence has {:?}", unwritten);
+
+    assert_eq!(grammar::parse("a = b ;"), Err(GrammarError::Undefined("b".to_owned())));
+    assert_eq!(grammar::parse("a = \"(\" a ;"), Err(GrammarError::Unproductive("a".to_owned())));
+    assert_eq!(grammar::parse("a = \"x\" ;\nB = a ;"), Err(GrammarError::Syntax { expected: "the name of a rule", line: 2 }));
+}
@@ -0,0 +1,150 @@
+//! tests of the `sanguinello` command, run on programs written to a scratch directory
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// a fresh scratch directory for the test `name`, with `files` written into it
+fn scratch(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("sanguinello-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    for (path, contents) in files {
+        let path = directory.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    directory
+}
+
+/// run `sanguinello` with `arguments` from `directory`
+fn sanguinello(directory: &Path, arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sanguinello")).args(arguments)
+                                                   .current_dir(directory)
+                                                   .output()
+                                                   .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_cli_runs_a_bare_file_name() {
+    let directory = scratch("bare", &[("main.sg", "1 + 2\n")]);
+    for arguments in [&["main.sg"][..], &["run", "main.sg"]] {
+        let output = sanguinello(&directory, arguments);
+        assert!(output.status.success(), "{:?}: {}", arguments, stderr(&output));
+        assert_eq!(stdout(&output), "3\n");
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_cli_checks_a_program_before_running_it() {
+    let directory = scratch("check", &[("main.sg", "io.print(\"ran\")\nlocal x: Number = \"no\"\nx\n")]);
+    let output = sanguinello(&directory, &["main.sg"]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).starts_with("main.sg: type mismatch: expected Number, found String at 2:1"), "{}", stderr(&output));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_cli_run_prints_and_reads_its_arguments() {
+    let directory = scratch("run", &[("main.sg", "io.print(\"hello\")\nio.print(os.args())\n")]);
+    let output = sanguinello(&directory, &["run", "main.sg", "--", "a", "b c"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "hello\n(\"a\", \"b c\")\n()\n");
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_cli_build_keeps_private_bindings_in_their_module() {
+    let directory = scratch("visibility", &[("powers.sg", "function square(x: Number): Number return x * x end\n\
+                                                         pub function cube(x: Number): Number return x * square(x) end\n"),
+                                           ("public.sg", "powers.cube(2)\n"),
+                                           ("private.sg", "1 +\npowers.square(2)\n")]);
+    let output = sanguinello(&directory, &["build", "powers.sg"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(directory.join("powers.sangi").is_file());
+
+    let output = sanguinello(&directory, &["build", "public.sg"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = sanguinello(&directory, &["build", "private.sg"]);
+    assert!(!output.status.success());
+    assert_eq!(stderr(&output), "private.sg: powers.square is private to the module powers, so it can't be used outside of it; \
+                                 declare it `pub` in powers to export it at 2:1\n");
+    assert!(!directory.join("private.sangi").exists());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_cli_build_checks_calls_against_a_declaration_file() {
+    let directory = scratch("declarations", &[("fs.sangd", "type Path = String\nfunction read(path: Path): String\n"),
+                                             ("good.sg", "fs.read(\"notes\")\n"),
+                                             ("bad.sg", "fs.read(1)\n")]);
+    let output = sanguinello(&directory, &["build", "good.sg"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = sanguinello(&directory, &["build", "bad.sg"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).starts_with("bad.sg: type mismatch: expected String, found Number"), "{}", stderr(&output));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_cli_doc_lists_documented_declarations() {
+    let directory = scratch("doc", &[("shapes.sg", "--- a point in the plane\ntype Point = {x: Number, y: Number}\n\
+                                                   --- how far `p` is from the origin, squared\n\
+                                                   pub function norm(p: Point): Number return p.x * p.x + p.y * p.y end\n\
+                                                   -- not a doc comment\n\
+                                                   pub function show(p: Point) io.print(p.x) end\n\
+                                                   function hidden() return 1 end\n")]);
+    let output = sanguinello(&directory, &["doc", "shapes.sg"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "# shapes\n\n\
+                                 ## `norm`\n\n```\nnorm: ({x: Number, y: Number}) -> Number\n```\n\n\
+                                 how far `p` is from the origin, squared\n\n\
+                                 ## `show`\n\n```\nshow: ({x: Number, y: Number}) -> ()\n```\n");
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_cli_test_runs_the_tests_it_discovers() {
+    let directory = scratch("test", &[("math.sg", "function double(x: Number): Number return x * 2 end\n\
+                                                   test \"doubling\"\n  assert(double(2) == 4)\nend\n\
+                                                   test \"wrong\"\n  assert(double(2) == 5, \"not five\")\nend\n"),
+                                      ("nested/strings.sg", "test \"concatenation\"\n  assert(\"a\" .. \"b\" == \"ab\")\nend\n"),
+                                      ("script.sg", "io.print(\"not a test\")\n")]);
+    let output = sanguinello(&directory, &["test"]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "test math.sg:2: doubling ... ok\n\
+                                 test math.sg:5: wrong ... FAILED: assertion failed: not five at 6:3\n\
+                                 test nested/strings.sg:1: concatenation ... ok\n\
+                                 2 passed; 1 failed\n");
+    assert_eq!(stderr(&output), "1 of 3 tests failed\n");
+    let output = sanguinello(&directory, &["test", "nested"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "test nested/strings.sg:1: concatenation ... ok\n1 passed; 0 failed\n");
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_cli_coverage_reports_what_was_not_executed() {
+    let directory = scratch("coverage", &[("main.sg", "local x = 1\nif x > 1 then\n  io.print(\"big\")\nend\nx\n"),
+                                          ("sign.sg", "function sign(x: Number): Number\n  if x < 0 then\n    return -1\n  end\n  return 1\nend\n\
+                                                       test \"positive\"\n  assert(sign(2) == 1)\nend\n")]);
+    let output = sanguinello(&directory, &["run", "--coverage", "main.sg"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "1\n");
+    assert!(stderr(&output).starts_with("main.sg:3:3-3:11: not executed\nmain.sg:3:3-3:18: not executed\n"), "{}", stderr(&output));
+
+    let output = sanguinello(&directory, &["test", "--coverage=lcov.info", "sign.sg"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let lcov = std::fs::read_to_string(directory.join("lcov.info")).unwrap();
+    assert!(lcov.starts_with("SF:sign.sg\nDA:1,1\nDA:2,1\nDA:3,0\nDA:5,1\n"), "{}", lcov);
+    std::fs::remove_dir_all(&directory).unwrap();
+}